@@ -2,9 +2,13 @@
  * build.rs
  *
  * Build script for procguard.
- * Ensures libc is linked for the no_std binary.
+ * Ensures libc is linked for the no_std binary, and records the git commit
+ * for `--version --json`.
  */
 
+use std::path::Path;
+use std::process::Command;
+
 fn main() {
     let target_os = std::env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
 
@@ -16,4 +20,52 @@ fn main() {
         println!("cargo:rustc-link-lib=c");
         println!("cargo:rustc-link-lib=System");
     }
+
+    /* linux is a secondary target. libSystem carries the unwinder on
+     * darwin; glibc leaves _Unwind_Resume (still referenced by the
+     * prebuilt core, even with panic=abort) to libgcc_s */
+    if target_os == "linux" {
+        println!("cargo:rustc-link-lib=c");
+        println!("cargo:rustc-link-lib=gcc_s");
+    }
+
+    git_hash();
+}
+
+/*
+ * PROCGUARD_GIT_HASH: short commit hash of the checkout being built.
+ * packagers building from a tarball can set it themselves; with neither a
+ * repo nor the variable, --version --json reports null.
+ */
+fn git_hash() {
+    println!("cargo:rerun-if-env-changed=PROCGUARD_GIT_HASH");
+    if std::env::var_os("PROCGUARD_GIT_HASH").is_some() {
+        return;
+    }
+
+    /* rebuild when HEAD moves: HEAD itself on checkout, the branch ref on
+     * commit. naming a path that doesn't exist would rerun every build */
+    let git = Path::new(env!("CARGO_MANIFEST_DIR")).join(".git");
+    for path in [git.join("HEAD"), git.join("packed-refs")] {
+        if path.exists() {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+    if let Ok(head) = std::fs::read_to_string(git.join("HEAD"))
+        && let Some(branch) = head.trim().strip_prefix("ref: ")
+        && git.join(branch).exists()
+    {
+        println!("cargo:rerun-if-changed={}", git.join(branch).display());
+    }
+
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok());
+    if let Some(hash) = hash.as_deref().map(str::trim).filter(|h| !h.is_empty()) {
+        println!("cargo:rustc-env=PROCGUARD_GIT_HASH={hash}");
+    }
 }
@@ -16,16 +16,48 @@ _procguard_completions() {
             COMPREPLY=($(compgen -W "$signals" -- "$cur"))
             return 0
             ;;
-        -k|--kill-after|--on-timeout-limit|--wait-for-file-timeout|--retry-delay|-H|--heartbeat|-S|--stdin-timeout)
+        -t|--duration|-k|--kill-after|--on-timeout-limit|--wait-for-file-timeout|--flock-timeout|--window-wait|--every|--for|--retry-delay|-H|--heartbeat|-S|--stdin-timeout|--stdin-inject-wait|--extend-max|--debugger-grace)
             # Duration suffixes
             COMPREPLY=($(compgen -W "1s 5s 10s 30s 1m 5m" -- "$cur"))
             return 0
             ;;
+        --stdin)
+            COMPREPLY=($(compgen -W "null close file:" -- "$cur"))
+            return 0
+            ;;
+        --stdin-mode)
+            COMPREPLY=($(compgen -W "consume watch pty" -- "$cur"))
+            return 0
+            ;;
+        --pgroup)
+            COMPREPLY=($(compgen -W "inherit new session" -- "$cur"))
+            return 0
+            ;;
+        --arch)
+            COMPREPLY=($(compgen -W "arm64 x86_64" -- "$cur"))
+            return 0
+            ;;
+        --monitor-qos)
+            COMPREPLY=($(compgen -W "user-interactive user-initiated default utility background" -- "$cur"))
+            return 0
+            ;;
         -r|--retry)
             # Common retry counts
             COMPREPLY=($(compgen -W "1 2 3 5 10" -- "$cur"))
             return 0
             ;;
+        --only-between)
+            COMPREPLY=($(compgen -W "22:00-06:00 00:00-06:00 09:00-17:00" -- "$cur"))
+            return 0
+            ;;
+        --spawn-retry)
+            COMPREPLY=($(compgen -W "1 2 3 5 10" -- "$cur"))
+            return 0
+            ;;
+        --on-stopped)
+            COMPREPLY=($(compgen -W "continue kill" -- "$cur"))
+            return 0
+            ;;
         --retry-backoff)
             # Common backoff multipliers
             COMPREPLY=($(compgen -W "2x 3x 4x" -- "$cur"))
@@ -35,28 +67,56 @@ _procguard_completions() {
             COMPREPLY=($(compgen -W "124 125 126 127 0 1" -- "$cur"))
             return 0
             ;;
-        --on-timeout)
+        --on-timeout|--on-limit|--crash-hook)
             # Commands
             COMPREPLY=($(compgen -c -- "$cur"))
             return 0
             ;;
-        --wait-for-file)
+        --wait-for-file|--wait-for-file-gone|--flock|--result-file|--pidfile|--export-remaining-file|--extend-env-file|--also-kill)
             # Files
             COMPREPLY=($(compgen -f -- "$cur"))
             return 0
             ;;
         -c|--confine)
-            COMPREPLY=($(compgen -W "wall active" -- "$cur"))
+            COMPREPLY=($(compgen -W "wall active hybrid" -- "$cur"))
+            return 0
+            ;;
+        --stop-on)
+            COMPREPLY=($(compgen -W "never timeout failure" -- "$cur"))
+            return 0
+            ;;
+        --on-sleep|--on-child-stop)
+            COMPREPLY=($(compgen -W "continue pause fail" -- "$cur"))
+            return 0
+            ;;
+        --wait-pid)
+            COMPREPLY=($(compgen -W "$(ps -axo pid=)" -- "$cur"))
+            return 0
+            ;;
+        --hook-budget)
+            COMPREPLY=($(compgen -W "separate shared" -- "$cur"))
+            return 0
+            ;;
+        --launchd-mode)
+            COMPREPLY=($(compgen -W "stop relaunch" -- "$cur"))
+            return 0
+            ;;
+        --timestamps)
+            COMPREPLY=($(compgen -W "relative absolute" -- "$cur"))
+            return 0
+            ;;
+        --output-rate-policy)
+            COMPREPLY=($(compgen -W "drop block" -- "$cur"))
             return 0
             ;;
     esac
 
     # Options
     if [[ "$cur" == -* ]]; then
-        opts="-s --signal -k --kill-after -p --preserve-status -f --foreground"
-        opts="$opts -v --verbose -q --quiet -c --confine --timeout-exit-code --on-timeout"
-        opts="$opts --on-timeout-limit --wait-for-file --wait-for-file-timeout"
-        opts="$opts -r --retry --retry-delay --retry-backoff -H --heartbeat -S --stdin-timeout --json -h --help -V --version"
+        opts="-t --duration -s --signal -k --kill-after -p --preserve-status -f --foreground"
+        opts="$opts -v --verbose -q --quiet --tag --prefix --run-id --pgroup --arch --tty-foreground --restore-tty --monitor-qos -c --confine --active-min --on-sleep --on-child-stop --sleep --wait-pid --timeout-exit-code --no-warn-exit-code --strict --kill-self-on-timeout --launchd-mode --on-timeout --on-limit"
+        opts="$opts --on-timeout-limit --hook-budget --hook-async --verify-signal-delivery --on-stopped --debugger-grace --wait-for-file --wait-for-file-gone --wait-for-file-contains --wait-for-file-timeout --flock --flock-timeout --flock-queue --only-between --window-wait --window-stop --every --for --stop-on --max-runs --runs --flaky-threshold --detect-flaky --flaky-exit"
+        opts="$opts -r --retry --retry-delay --retry-backoff --spawn-retry --spawn-retry-delay -H --heartbeat -S --stdin-timeout --stdin --stdin-passthrough --stdin-mode --stdin-inject-on-timeout --stdin-inject-wait --json --json-fd --json-stderr --explain --crash-report --crash-hook --result-file --pidfile --export-remaining --export-remaining-file --extend-env-file --extend-max --file-mode --no-fsync --temp-dir --keep-temp-on-failure --print-pid --proc-tree --kill-escaped --reap-orphans --also-kill --prefix-output --timestamps --raw-output --merge-output --output-rate-limit --output-rate-policy -h --help -V --version"
         COMPREPLY=($(compgen -W "$opts" -- "$cur"))
         return 0
     fi
@@ -65,7 +125,11 @@ _procguard_completions() {
     local i cmd_start=0
     for ((i=1; i < COMP_CWORD; i++)); do
         case "${COMP_WORDS[i]}" in
-            -s|--signal|-k|--kill-after|--timeout-exit-code|--on-timeout|--on-timeout-limit)
+            -t|--duration)
+                ((i++))
+                cmd_start=$i  # the duration, so no operand is
+                ;;
+            -s|--signal|-k|--kill-after|--timeout-exit-code|--on-timeout|--on-limit|--on-timeout-limit|--hook-budget|--on-stopped|--debugger-grace|--launchd-mode|--tag|--prefix|--run-id|--spawn-retry|--spawn-retry-delay|--pgroup|--arch|--monitor-qos|--active-min|--on-sleep|--on-child-stop|--wait-pid|--result-file|--pidfile|--export-remaining|--export-remaining-file|--extend-env-file|--also-kill|--extend-max|--file-mode|--prefix-output|--timestamps|--output-rate-limit|--output-rate-policy|--json-fd|--crash-hook|--wait-for-file|--wait-for-file-gone|--wait-for-file-contains|--wait-for-file-timeout|--flock|--flock-timeout|--only-between|--window-wait|--every|--for|--stop-on|--max-runs|--runs|--flaky-threshold|--detect-flaky|--flaky-exit|--stdin|--stdin-mode|--stdin-inject-on-timeout|--stdin-inject-wait)
                 ((i++))  # skip value
                 ;;
             -*)
@@ -16,23 +16,94 @@ set -l durations 1s 5s 10s 30s 1m 5m 10m 1h
 complete -c procguard -s h -l help -d 'Show help message'
 complete -c procguard -s V -l version -d 'Show version'
 complete -c procguard -s s -l signal -d 'Signal to send on timeout' -xa "$signals"
+complete -c procguard -s t -l duration -d 'The duration, so every operand is the command' -xa "$durations"
 complete -c procguard -s k -l kill-after -d 'Send KILL after duration' -xa "$durations"
 complete -c procguard -s p -l preserve-status -d 'Exit with command status on timeout'
 complete -c procguard -s f -l foreground -d 'Run in foreground (allow TTY access)'
 complete -c procguard -s v -l verbose -d 'Diagnose signals to stderr'
-complete -c procguard -s q -l quiet -d 'Suppress diagnostic output'
-complete -c procguard -s c -l confine -d 'Time mode (wall or active)' -xa 'wall active'
+complete -c procguard -s q -l quiet -d 'Suppress warnings (-qq: errors too)'
+complete -c procguard -l tag -l prefix -d 'Prefix for stderr messages' -x
+complete -c procguard -s c -l confine -d 'Time mode (wall, active or hybrid)' -xa 'wall active hybrid'
+complete -c procguard -l active-min -d 'Awake time a hybrid timeout waits for' -x
+complete -c procguard -l on-sleep -d 'What a system sleep does to the timeout' -xa 'continue pause fail'
+complete -c procguard -l on-child-stop -d 'What the command stopping itself does to the timeout' -xa 'continue pause fail'
+complete -c procguard -l sleep -d 'No command: sleep for DURATION, counting system sleep'
+complete -c procguard -l wait-pid -d 'No command: wait up to DURATION for a process to exit' -xa '(__fish_complete_pids)'
+complete -c procguard -l pgroup -d 'Process group for command' -xa 'inherit new session'
+complete -c procguard -l arch -d 'Slice of a universal binary to run' -xa 'arm64 x86_64'
+complete -c procguard -l tty-foreground -d 'Give command the terminal'
+complete -c procguard -l restore-tty -d 'Restore terminal settings afterwards'
+complete -c procguard -l monitor-qos -d 'QoS class for timeout itself' -xa 'user-interactive user-initiated default utility background'
 complete -c procguard -l timeout-exit-code -d 'Exit code on timeout' -xa '124 125 0 1'
+complete -c procguard -l no-warn-exit-code -d 'No warning for a reserved timeout exit code'
+complete -c procguard -l strict -d 'Refuse a reserved timeout exit code'
+complete -c procguard -l kill-self-on-timeout -d 'On timeout, die of a signal (=SIG, default ALRM) instead of exiting 124'
+complete -c procguard -l launchd-mode -d 'Under launchd: exit 0 or die of SIGTERM when stopped' -xa 'stop relaunch'
 complete -c procguard -l on-timeout -d 'Command to run before signaling' -xa '(__fish_complete_command)'
+complete -c procguard -l on-limit -d 'Command to run before signaling over --mem-limit' -xa '(__fish_complete_command)'
 complete -c procguard -l on-timeout-limit -d 'Timeout for hook command' -xa "$durations"
+complete -c procguard -l hook-budget -d 'Hook time on top of the kill-after grace or out of it' -xa 'separate shared'
+complete -c procguard -l hook-async -d 'Send the signal while the hook runs, not after it'
+complete -c procguard -l verify-signal-delivery -d 'Check the command is not stopped after each signal'
+complete -c procguard -l on-stopped -d 'What to do with a stopped command' -xa 'continue kill'
+complete -c procguard -l debugger-grace -d 'Wait for an attached debugger before enforcing the timeout' -xa "$durations"
 complete -c procguard -l wait-for-file -d 'Wait for file to exist before starting' -rF
+complete -c procguard -l wait-for-file-gone -d 'Wait for file to go away before starting' -rF
+complete -c procguard -l wait-for-file-contains -d 'Wait until the file contains a string' -x
+complete -c procguard -l result-file -d 'Write JSON result to file atomically' -rF
+complete -c procguard -l pidfile -d 'Write command and procguard PIDs to file while running' -rF
+complete -c procguard -l export-remaining -d 'Set env var to the timeout in seconds' -x
+complete -c procguard -l export-remaining-file -d 'Keep file at the seconds left' -rF
+complete -c procguard -l extend-env-file -d 'Re-read TIMEOUT_EXTEND from file' -rF
+complete -c procguard -l extend-max -d 'Cap on TIMEOUT_EXTEND' -xa "$durations"
+complete -c procguard -l file-mode -d 'Exact permissions for written files (octal)' -xa '0600 0640 0644'
+complete -c procguard -l no-fsync -d 'Do not fsync written files before renaming them'
+complete -c procguard -l temp-dir -d 'Fresh TMPDIR per attempt (=DIR for its parent), removed afterwards'
+complete -c procguard -l keep-temp-on-failure -d 'Keep the --temp-dir of a failed attempt'
 complete -c procguard -l wait-for-file-timeout -d 'Timeout for wait-for-file' -xa "$durations"
+complete -c procguard -l flock -d 'Hold an exclusive lock on file, exit 75 if held' -rF
+complete -c procguard -l flock-timeout -d 'How long to wait for the --flock lock' -xa "$durations"
+complete -c procguard -l flock-queue -d 'Wait for the --flock lock in arrival order (=MAX to give up)'
+complete -c procguard -l only-between -d 'Run only in this local-time window (HH:MM-HH:MM)' -x
+complete -c procguard -l window-wait -d 'How long to wait for the --only-between window' -xa "$durations"
+complete -c procguard -l window-stop -d 'Stop the command when the --only-between window closes'
+complete -c procguard -l every -d 'Run the command again at this interval' -xa "$durations"
+complete -c procguard -l for -d 'How long to keep --every going' -xa "$durations"
+complete -c procguard -l stop-on -d 'Which run ends --every early' -xa 'never timeout failure'
+complete -c procguard -l max-runs -d 'Stop --every after this many runs' -x
+complete -c procguard -l runs -d 'Run the command this many times back to back' -x
+complete -c procguard -l flaky-threshold -d 'Percentage of runs allowed to fail' -x
+complete -c procguard -l detect-flaky -d 'Rerun a failure up to this many times' -x
+complete -c procguard -l flaky-exit -d 'Exit status of a flaky command' -xa 'pass fail'
 complete -c procguard -s r -l retry -d 'Retry command N times on timeout' -xa '1 2 3 5 10'
 complete -c procguard -l retry-delay -d 'Delay between retries' -xa "$durations"
 complete -c procguard -l retry-backoff -d 'Multiply delay by N each retry' -xa '2x 3x 4x'
+complete -c procguard -l spawn-retry -d 'Retry a start that failed with ETXTBSY or EAGAIN' -xa '1 2 3 5 10'
+complete -c procguard -l spawn-retry-delay -d 'First wait before a spawn retry' -xa '50ms 100ms 500ms 1s'
 complete -c procguard -s H -l heartbeat -d 'Print status to stderr at interval' -xa "$durations"
 complete -c procguard -s S -l stdin-timeout -d 'Kill if stdin idle for duration' -xa "$durations"
+complete -c procguard -l stdin -d "Command's stdin (null, close or file:PATH)" -xa 'null close file:'
+complete -c procguard -l stdin-mode -d 'How -S watches stdin' -xa 'consume watch pty'
+complete -c procguard -l stdin-inject-on-timeout -d 'Write TEXT to stdin on timeout, before signaling' -x
+complete -c procguard -l stdin-inject-wait -d 'How long the command gets to exit on TEXT' -xa "$durations"
 complete -c procguard -l json -d 'Output JSON for scripting'
+complete -c procguard -l json-fd -d 'Write JSON to file descriptor' -x
+complete -c procguard -l json-stderr -d 'Write JSON to stderr'
+complete -c procguard -l explain -d 'Print timeline of decisions at exit'
+complete -c procguard -l crash-report -d 'Find the crash report if the command crashes'
+complete -c procguard -l print-pid -d 'Print a started JSON line with the command PID'
+complete -c procguard -l proc-tree -d 'Record the processes the command forks and execs'
+complete -c procguard -l kill-escaped -d 'Kill daemons that escaped the command process group'
+complete -c procguard -l reap-orphans -d 'Kill and reap what the command left in its process group'
+complete -c procguard -l also-kill -d 'At a timeout or limit kill, also signal these PIDs (list or pidfile)' -rF
+complete -c procguard -l prefix-output -d 'Prefix each line of the command output' -x
+complete -c procguard -l run-id -d 'Correlation ID for every JSON object' -x
+complete -c procguard -l timestamps -d 'Timestamp each line of the command output' -xa 'relative absolute'
+complete -c procguard -l raw-output -d 'Never rewrite the command output'
+complete -c procguard -l merge-output -d 'Merge the command stderr into its stdout'
+complete -c procguard -l output-rate-limit -d 'Cap the command output at bytes/sec (64K, 1M)' -x
+complete -c procguard -l output-rate-policy -d 'Over the output cap: drop or block' -xa 'drop block'
+complete -c procguard -l crash-hook -d 'Command to run if the command crashes' -xa '(__fish_complete_command)'
 complete -c procguard -n '__fish_is_first_arg' -xa "$durations"
 complete -c procguard -n 'not __fish_is_first_arg' -xa '(__fish_complete_command)'
 
@@ -40,22 +111,93 @@ complete -c procguard -n 'not __fish_is_first_arg' -xa '(__fish_complete_command
 complete -c timeout -s h -l help -d 'Show help message'
 complete -c timeout -s V -l version -d 'Show version'
 complete -c timeout -s s -l signal -d 'Signal to send on timeout' -xa "$signals"
+complete -c timeout -s t -l duration -d 'The duration, so every operand is the command' -xa "$durations"
 complete -c timeout -s k -l kill-after -d 'Send KILL after duration' -xa "$durations"
 complete -c timeout -s p -l preserve-status -d 'Exit with command status on timeout'
 complete -c timeout -s f -l foreground -d 'Run in foreground (allow TTY access)'
 complete -c timeout -s v -l verbose -d 'Diagnose signals to stderr'
-complete -c timeout -s q -l quiet -d 'Suppress diagnostic output'
-complete -c timeout -s c -l confine -d 'Time mode (wall or active)' -xa 'wall active'
+complete -c timeout -s q -l quiet -d 'Suppress warnings (-qq: errors too)'
+complete -c timeout -l tag -l prefix -d 'Prefix for stderr messages' -x
+complete -c timeout -s c -l confine -d 'Time mode (wall, active or hybrid)' -xa 'wall active hybrid'
+complete -c timeout -l active-min -d 'Awake time a hybrid timeout waits for' -x
+complete -c timeout -l on-sleep -d 'What a system sleep does to the timeout' -xa 'continue pause fail'
+complete -c timeout -l on-child-stop -d 'What the command stopping itself does to the timeout' -xa 'continue pause fail'
+complete -c timeout -l sleep -d 'No command: sleep for DURATION, counting system sleep'
+complete -c timeout -l wait-pid -d 'No command: wait up to DURATION for a process to exit' -xa '(__fish_complete_pids)'
+complete -c timeout -l pgroup -d 'Process group for command' -xa 'inherit new session'
+complete -c timeout -l arch -d 'Slice of a universal binary to run' -xa 'arm64 x86_64'
+complete -c timeout -l tty-foreground -d 'Give command the terminal'
+complete -c timeout -l restore-tty -d 'Restore terminal settings afterwards'
+complete -c timeout -l monitor-qos -d 'QoS class for timeout itself' -xa 'user-interactive user-initiated default utility background'
 complete -c timeout -l timeout-exit-code -d 'Exit code on timeout' -xa '124 125 0 1'
+complete -c timeout -l no-warn-exit-code -d 'No warning for a reserved timeout exit code'
+complete -c timeout -l strict -d 'Refuse a reserved timeout exit code'
+complete -c timeout -l kill-self-on-timeout -d 'On timeout, die of a signal (=SIG, default ALRM) instead of exiting 124'
+complete -c timeout -l launchd-mode -d 'Under launchd: exit 0 or die of SIGTERM when stopped' -xa 'stop relaunch'
 complete -c timeout -l on-timeout -d 'Command to run before signaling' -xa '(__fish_complete_command)'
+complete -c timeout -l on-limit -d 'Command to run before signaling over --mem-limit' -xa '(__fish_complete_command)'
 complete -c timeout -l on-timeout-limit -d 'Timeout for hook command' -xa "$durations"
+complete -c timeout -l hook-budget -d 'Hook time on top of the kill-after grace or out of it' -xa 'separate shared'
+complete -c timeout -l hook-async -d 'Send the signal while the hook runs, not after it'
+complete -c timeout -l verify-signal-delivery -d 'Check the command is not stopped after each signal'
+complete -c timeout -l on-stopped -d 'What to do with a stopped command' -xa 'continue kill'
+complete -c timeout -l debugger-grace -d 'Wait for an attached debugger before enforcing the timeout' -xa "$durations"
 complete -c timeout -l wait-for-file -d 'Wait for file to exist before starting' -rF
+complete -c timeout -l wait-for-file-gone -d 'Wait for file to go away before starting' -rF
+complete -c timeout -l wait-for-file-contains -d 'Wait until the file contains a string' -x
+complete -c timeout -l result-file -d 'Write JSON result to file atomically' -rF
+complete -c timeout -l pidfile -d 'Write command and procguard PIDs to file while running' -rF
+complete -c timeout -l export-remaining -d 'Set env var to the timeout in seconds' -x
+complete -c timeout -l export-remaining-file -d 'Keep file at the seconds left' -rF
+complete -c timeout -l extend-env-file -d 'Re-read TIMEOUT_EXTEND from file' -rF
+complete -c timeout -l extend-max -d 'Cap on TIMEOUT_EXTEND' -xa "$durations"
+complete -c timeout -l file-mode -d 'Exact permissions for written files (octal)' -xa '0600 0640 0644'
+complete -c timeout -l no-fsync -d 'Do not fsync written files before renaming them'
+complete -c timeout -l temp-dir -d 'Fresh TMPDIR per attempt (=DIR for its parent), removed afterwards'
+complete -c timeout -l keep-temp-on-failure -d 'Keep the --temp-dir of a failed attempt'
 complete -c timeout -l wait-for-file-timeout -d 'Timeout for wait-for-file' -xa "$durations"
+complete -c timeout -l flock -d 'Hold an exclusive lock on file, exit 75 if held' -rF
+complete -c timeout -l flock-timeout -d 'How long to wait for the --flock lock' -xa "$durations"
+complete -c timeout -l flock-queue -d 'Wait for the --flock lock in arrival order (=MAX to give up)'
+complete -c timeout -l only-between -d 'Run only in this local-time window (HH:MM-HH:MM)' -x
+complete -c timeout -l window-wait -d 'How long to wait for the --only-between window' -xa "$durations"
+complete -c timeout -l window-stop -d 'Stop the command when the --only-between window closes'
+complete -c timeout -l every -d 'Run the command again at this interval' -xa "$durations"
+complete -c timeout -l for -d 'How long to keep --every going' -xa "$durations"
+complete -c timeout -l stop-on -d 'Which run ends --every early' -xa 'never timeout failure'
+complete -c timeout -l max-runs -d 'Stop --every after this many runs' -x
+complete -c timeout -l runs -d 'Run the command this many times back to back' -x
+complete -c timeout -l flaky-threshold -d 'Percentage of runs allowed to fail' -x
+complete -c timeout -l detect-flaky -d 'Rerun a failure up to this many times' -x
+complete -c timeout -l flaky-exit -d 'Exit status of a flaky command' -xa 'pass fail'
 complete -c timeout -s r -l retry -d 'Retry command N times on timeout' -xa '1 2 3 5 10'
 complete -c timeout -l retry-delay -d 'Delay between retries' -xa "$durations"
 complete -c timeout -l retry-backoff -d 'Multiply delay by N each retry' -xa '2x 3x 4x'
+complete -c timeout -l spawn-retry -d 'Retry a start that failed with ETXTBSY or EAGAIN' -xa '1 2 3 5 10'
+complete -c timeout -l spawn-retry-delay -d 'First wait before a spawn retry' -xa '50ms 100ms 500ms 1s'
 complete -c timeout -s H -l heartbeat -d 'Print status to stderr at interval' -xa "$durations"
 complete -c timeout -s S -l stdin-timeout -d 'Kill if stdin idle for duration' -xa "$durations"
+complete -c timeout -l stdin -d "Command's stdin (null, close or file:PATH)" -xa 'null close file:'
+complete -c timeout -l stdin-mode -d 'How -S watches stdin' -xa 'consume watch pty'
+complete -c timeout -l stdin-inject-on-timeout -d 'Write TEXT to stdin on timeout, before signaling' -x
+complete -c timeout -l stdin-inject-wait -d 'How long the command gets to exit on TEXT' -xa "$durations"
 complete -c timeout -l json -d 'Output JSON for scripting'
+complete -c timeout -l json-fd -d 'Write JSON to file descriptor' -x
+complete -c timeout -l json-stderr -d 'Write JSON to stderr'
+complete -c timeout -l explain -d 'Print timeline of decisions at exit'
+complete -c timeout -l crash-report -d 'Find the crash report if the command crashes'
+complete -c timeout -l print-pid -d 'Print a started JSON line with the command PID'
+complete -c timeout -l proc-tree -d 'Record the processes the command forks and execs'
+complete -c timeout -l kill-escaped -d 'Kill daemons that escaped the command process group'
+complete -c timeout -l reap-orphans -d 'Kill and reap what the command left in its process group'
+complete -c timeout -l also-kill -d 'At a timeout or limit kill, also signal these PIDs (list or pidfile)' -rF
+complete -c timeout -l prefix-output -d 'Prefix each line of the command output' -x
+complete -c timeout -l run-id -d 'Correlation ID for every JSON object' -x
+complete -c timeout -l timestamps -d 'Timestamp each line of the command output' -xa 'relative absolute'
+complete -c timeout -l raw-output -d 'Never rewrite the command output'
+complete -c timeout -l merge-output -d 'Merge the command stderr into its stdout'
+complete -c timeout -l output-rate-limit -d 'Cap the command output at bytes/sec (64K, 1M)' -x
+complete -c timeout -l output-rate-policy -d 'Over the output cap: drop or block' -xa 'drop block'
+complete -c timeout -l crash-hook -d 'Command to run if the command crashes' -xa '(__fish_complete_command)'
 complete -c timeout -n '__fish_is_first_arg' -xa "$durations"
 complete -c timeout -n 'not __fish_is_first_arg' -xa '(__fish_complete_command)'
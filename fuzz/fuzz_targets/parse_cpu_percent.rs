@@ -0,0 +1,19 @@
+/*
+ * fuzz_targets/parse_cpu_percent.rs
+ *
+ * fuzz target for cpu percent parsing. validates that parse_cpu_percent
+ * never panics on arbitrary input strings.
+ *
+ * edge cases: "", "0", "-1", "50%", "4294967296", "+50", " 25 "
+ */
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(s) = core::str::from_utf8(data) {
+        /* parse_cpu_percent must not panic on any valid UTF-8 string */
+        let _ = procguard::rlimit::parse_cpu_percent(s);
+    }
+});
@@ -2,7 +2,8 @@
  * fuzz_targets/parse_duration.rs
  *
  * fuzz target for duration parsing. validates that parse_duration never panics
- * on arbitrary input, only returns Ok or Err gracefully.
+ * on arbitrary input, only returns Ok or Err gracefully, and that whatever
+ * it accepts survives a trip through format_duration.
  *
  * edge cases: "", "999999999h", "-1", "1.2.3s", unicode, huge numbers
  */
@@ -15,6 +16,12 @@ fuzz_target!(|data: &[u8]| {
     /* convert to str - invalid UTF-8 should be handled gracefully */
     if let Ok(s) = core::str::from_utf8(data) {
         /* parse_duration must not panic on any valid UTF-8 string */
-        let _ = procguard::duration::parse_duration(s);
+        if let Ok(d) = procguard::duration::parse_duration(s) {
+            let formatted = procguard::duration::format_duration(d).to_string();
+            assert_eq!(
+                procguard::duration::parse_duration(&formatted).ok(),
+                Some(d)
+            );
+        }
     }
 });
@@ -0,0 +1,26 @@
+/*
+ * fuzz_targets/parse_response_file.rs
+ *
+ * fuzz target for @file response file splitting, and the TIMEOUT_OPTS
+ * word splitting next to it. validates that neither panics on arbitrary
+ * input, valid UTF-8 or not.
+ *
+ * edge cases: "\"", "'", "\"a\\", "\r\n", "\"x\" y", lone "#", NUL bytes
+ */
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    /* split must not panic; every argument it returns is a whole line or less */
+    if let Ok(args) = procguard::response_file::split(data) {
+        for arg in &args {
+            assert!(arg.len() <= data.len());
+        }
+    }
+    /* words only lose bytes: quotes, separators, escaping backslashes */
+    if let Ok(words) = procguard::response_file::split_words(data) {
+        assert!(words.iter().map(Vec::len).sum::<usize>() <= data.len());
+    }
+});
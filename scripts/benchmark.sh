@@ -9,6 +9,8 @@
 #
 # If no path is given, uses target/release/timeout
 #
+# Set BASELINE_BIN to another build to compare against it where supported.
+#
 
 set -euo pipefail
 
@@ -75,6 +77,19 @@ else
 fi
 echo ""
 
+# argv conversion and env fallbacks scale with argument count, so time a
+# long command line too. set BASELINE_BIN to an older build to compare.
+many_args="$(seq -s ' ' 1 200)"
+echo -e "${CYAN}Startup with 200 arguments:${NC}"
+if [[ -n "${BASELINE_BIN:-}" ]]; then
+    hyperfine --warmup 10 -N --runs 30 \
+        -n "baseline" "$BASELINE_BIN 1 true $many_args" \
+        -n "current" "$TIMEOUT_BIN 1 true $many_args"
+else
+    hyperfine --warmup 10 -N --runs 30 "$TIMEOUT_BIN 1 true $many_args"
+fi
+echo ""
+
 # ============================================================================
 echo -e "${YELLOW}2. Timeout Precision${NC}"
 echo "   How accurately does the timeout trigger?"
@@ -224,6 +239,37 @@ hyperfine --warmup 2 -N -i --runs 10 \
     -n "3 retries (all fail)" "$TIMEOUT_BIN --retry 2 50ms sleep 60"
 echo ""
 
+# 32 attempts of 1ms each: dominated by per-attempt spawn/monitor setup, so
+# this is where reusing one kqueue per run shows. set BASELINE_BIN to an
+# older build to compare.
+echo -e "${CYAN}Retry storm (32 attempts, 1ms timeout each):${NC}"
+if [[ -n "${BASELINE_BIN:-}" ]]; then
+    hyperfine --warmup 3 -N -i --runs 20 \
+        -n "baseline" "$BASELINE_BIN --retry 31 1ms sleep 60" \
+        -n "current" "$TIMEOUT_BIN --retry 31 1ms sleep 60"
+else
+    hyperfine --warmup 3 -N -i --runs 20 "$TIMEOUT_BIN --retry 31 1ms sleep 60"
+fi
+echo ""
+
+# ============================================================================
+echo -e "${YELLOW}10. Allocations${NC}"
+echo "   Heap allocations per run (needs a build with --features alloc-stats)"
+echo ""
+# ============================================================================
+
+# the result should be built without allocating; anything else here is a
+# regression worth a look
+if "$TIMEOUT_BIN" --json 1 true 2>&1 >/dev/null | grep -q "allocations"; then
+    echo -e "${CYAN}completed:${NC}"
+    "$TIMEOUT_BIN" --json 1 true 2>&1 >/dev/null | grep "allocations"
+    echo -e "${CYAN}timeout:${NC}"
+    "$TIMEOUT_BIN" --json 50ms sleep 60 2>&1 >/dev/null | grep "allocations" || true
+else
+    echo "  skipped: rebuild with 'cargo build --release --features alloc-stats'"
+fi
+echo ""
+
 # ============================================================================
 echo -e "${BLUE}=== Summary ===${NC}"
 echo ""
@@ -111,8 +111,9 @@ strip -x -S target/universal/procguard 2>/dev/null || true
 echo "Signing binary..."
 codesign -s - target/universal/procguard 2>/dev/null || true
 
-# Create timeout symlink for GNU compatibility
+# Create timeout symlink for GNU compatibility, gtimeout for strict GNU
 ln -sf procguard target/universal/timeout
+ln -sf procguard target/universal/gtimeout
 
 echo ""
 echo "=== Build complete ==="
@@ -135,8 +136,10 @@ echo ""
 echo "Sanity test:"
 target/universal/procguard --version
 target/universal/timeout --version
+target/universal/gtimeout --version
 echo ""
 
 echo "Universal binaries available at:"
 echo "  target/universal/procguard (primary)"
 echo "  target/universal/timeout (GNU-compatible symlink)"
+echo "  target/universal/gtimeout (strict GNU symlink)"
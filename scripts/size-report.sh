@@ -0,0 +1,49 @@
+#!/bin/bash
+#
+# Release binary size for each feature set.
+#
+# Builds the full default binary, the bare core (--no-default-features),
+# and the core plus one optional subsystem at a time, then prints what
+# each subsystem adds. Use it before adding a feature, or when the size
+# check in CI starts getting close to the 450KB budget.
+#
+# Usage:
+#   ./scripts/size-report.sh
+#
+# Builds go to target/size-report so the normal target dir is untouched.
+#
+
+set -euo pipefail
+
+SCRIPT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" && pwd)"
+PROJECT_ROOT="$(dirname "$SCRIPT_DIR")"
+TARGET_DIR="$PROJECT_ROOT/target/size-report"
+
+cd "$PROJECT_ROOT"
+
+# optional subsystems, as named in Cargo.toml [features]
+FEATURES=(rlimit throttle proc-info crash-report)
+
+# build with the given cargo feature flags, print the binary size in bytes
+build_size() {
+    cargo build --release --quiet --bin procguard --target-dir "$TARGET_DIR" "$@"
+    stat -f%z "$TARGET_DIR/release/procguard" 2>/dev/null ||
+        stat -c%s "$TARGET_DIR/release/procguard"
+}
+
+full=$(build_size)
+core=$(build_size --no-default-features)
+
+printf "%-24s %10s %10s\n" "build" "bytes" "vs core"
+printf "%-24s %10d %10s\n" "core (no features)" "$core" "-"
+for feature in "${FEATURES[@]}"; do
+    size=$(build_size --no-default-features --features "$feature")
+    printf "%-24s %10d %+10d\n" "core + $feature" "$size" "$((size - core))"
+done
+printf "%-24s %10d %+10d\n" "default (all)" "$full" "$((full - core))"
+echo ""
+echo "Budget: 460800 bytes (default build)"
+if [[ $full -gt 460800 ]]; then
+    echo "OVER BUDGET by $((full - 460800)) bytes"
+    exit 1
+fi
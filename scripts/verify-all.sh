@@ -52,7 +52,7 @@ echo ""
 echo -e "${YELLOW}[6/7] binary size check${NC}"
 cargo build --release
 SIZE=$(stat -f%z target/release/timeout 2>/dev/null || stat -c%s target/release/timeout)
-MAX_SIZE=153600  # 150KB
+MAX_SIZE=460800  # 450KB
 if [ "$SIZE" -le "$MAX_SIZE" ]; then
     echo -e "${GREEN}✓ binary size ok: ${SIZE} bytes (limit: ${MAX_SIZE})${NC}"
 else
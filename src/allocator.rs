@@ -6,9 +6,70 @@
  *
  * This is only used by the binary. Tests use std's allocator via
  * #[cfg(test)] extern crate std in main.rs.
+ *
+ * --features alloc-stats counts allocations (two relaxed atomic adds per
+ * malloc/realloc), so heap traffic creeping into a path that should have
+ * none shows up in the bench suite. with the feature on, debug builds use
+ * this allocator too, so the counts mean something there.
  */
 
 use core::alloc::{GlobalAlloc, Layout};
+#[cfg(feature = "alloc-stats")]
+use core::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(feature = "alloc-stats")]
+static COUNT: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "alloc-stats")]
+static BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Allocations made through [`SystemAlloc`] so far. reallocs count as one
+/// allocation of the new size.
+///
+/// ```
+/// use procguard::AllocStats;
+///
+/// let before = AllocStats::now();
+/// let after = AllocStats::now();
+/// assert!(after.count >= before.count && after.bytes >= before.bytes);
+/// ```
+#[cfg(feature = "alloc-stats")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AllocStats {
+    /// Allocations made.
+    pub count: u64,
+    /// Bytes asked for, all of them together.
+    pub bytes: u64,
+}
+
+#[cfg(feature = "alloc-stats")]
+impl AllocStats {
+    /// Counters now. Zero in test builds, which use std's allocator.
+    pub fn now() -> Self {
+        Self {
+            count: COUNT.load(Ordering::Relaxed),
+            bytes: BYTES.load(Ordering::Relaxed),
+        }
+    }
+
+    /// What was allocated between `earlier` and `self`.
+    #[must_use]
+    pub const fn since(self, earlier: Self) -> Self {
+        Self {
+            count: self.count.wrapping_sub(earlier.count),
+            bytes: self.bytes.wrapping_sub(earlier.bytes),
+        }
+    }
+}
+
+#[allow(dead_code)] // only used in release builds via #[global_allocator]
+#[inline(always)]
+fn count(_size: usize) {
+    #[cfg(feature = "alloc-stats")]
+    {
+        COUNT.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(_size as u64, Ordering::Relaxed);
+    }
+}
 
 /// System allocator - thin wrapper around malloc/free.
 ///
@@ -34,6 +95,7 @@ pub struct SystemAlloc;
 unsafe impl GlobalAlloc for SystemAlloc {
     #[inline]
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
         // SAFETY: malloc is safe to call with any size. Returns aligned memory for
         // alignments <= 16 bytes (guaranteed by macOS malloc). For larger alignments,
         // posix_memalign is used which guarantees the requested alignment.
@@ -86,6 +148,7 @@ unsafe impl GlobalAlloc for SystemAlloc {
             layout.align() <= 16,
             "realloc called with alignment > 16; alignment may not be preserved"
         );
+        count(new_size);
         // SAFETY: see above - realloc is safe with valid malloc'd pointer
         unsafe { libc::realloc(ptr as *mut libc::c_void, new_size) as *mut u8 }
     }
@@ -97,18 +160,20 @@ unsafe impl GlobalAlloc for SystemAlloc {
         // write_bytes is safe because ptr is valid for layout.size() bytes (just allocated).
         unsafe {
             if layout.align() > 16 {
+                /* alloc() does the counting */
                 let ptr = self.alloc(layout);
                 if !ptr.is_null() {
                     core::ptr::write_bytes(ptr, 0, layout.size());
                 }
                 ptr
             } else {
+                count(layout.size());
                 libc::calloc(1, layout.size()) as *mut u8
             }
         }
     }
 }
 
-#[cfg(not(any(debug_assertions, test)))]
+#[cfg(all(not(test), any(not(debug_assertions), feature = "alloc-stats")))]
 #[global_allocator]
 static ALLOCATOR: SystemAlloc = SystemAlloc;
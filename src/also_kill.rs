@@ -0,0 +1,194 @@
+/*
+ * also_kill.rs
+ *
+ * --also-kill PIDS|FILE: processes that aren't the command's descendants
+ * but should go down with it - an emulator or a daemon the build started
+ * and knows the pid of. at a timeout, or --mem-limit, they get what the
+ * command gets: the --signal then, SIGKILL once --kill-after runs out.
+ * one the command outlives the grace period of - it exited on the soft
+ * signal - gets SIGKILL when that grace period would have ended.
+ *
+ * PIDS is a comma-separated list, each pid once, and our own pid (a
+ * $$ in a script that runs us) left out. anything else is a file of pids,
+ * separated by newlines or spaces (a pidfile, or several cat'ed together),
+ * re-read every time a signal is due: a pid written after we started is
+ * still found, and one no longer listed is left alone. what isn't a pid
+ * is skipped, and so are 0, 1 and our own pid.
+ *
+ * the pids are trusted as given: one that exited and was reused before
+ * the signal is due gets the signal all the same.
+ */
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::errno::Errno;
+use crate::signal::Signal;
+
+/* more than any pidfile; anything bigger isn't one */
+const FILE_LIMIT: usize = 64 * 1024;
+
+/// Where `--also-kill` finds its pids.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlsoKill {
+    /// Given inline: `--also-kill 4242,4250`.
+    Pids(Vec<i32>),
+    /// A file of pids, re-read at each signal.
+    File(String),
+}
+
+impl AlsoKill {
+    /// A comma-separated list of pids, or else a path. A list naming pid
+    /// 0 or 1 is refused - those would signal a process group or init - and
+    /// so is one with pids separated by spaces rather than commas. A pid
+    /// given twice is kept once, and our own pid is left out.
+    ///
+    /// ```
+    /// use procguard::also_kill::AlsoKill;
+    ///
+    /// assert_eq!(AlsoKill::parse("4242,4250"), Ok(AlsoKill::Pids(vec![4242, 4250])));
+    /// assert_eq!(
+    ///     AlsoKill::parse("/tmp/emulator.pid"),
+    ///     Ok(AlsoKill::File("/tmp/emulator.pid".to_string()))
+    /// );
+    /// assert!(AlsoKill::parse("0").is_err());
+    /// assert!(AlsoKill::parse("4242 4250").is_err());
+    /// ```
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let is_list = !value.is_empty()
+            && value
+                .bytes()
+                .all(|b| b.is_ascii_digit() || b == b',' || b == b' ');
+        if !is_list {
+            return Ok(Self::File(String::from(value)));
+        }
+        // SAFETY: getpid has no preconditions
+        let us = unsafe { libc::getpid() };
+        let mut pids = Vec::new();
+        let mut ours = false;
+        for item in value.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            if item.contains(' ') {
+                return Err(alloc::format!("'{item}': separate process ids with commas"));
+            }
+            match item.parse::<i32>() {
+                Ok(pid) if pid == us => ours = true,
+                Ok(pid) if pid > 1 => {
+                    if !pids.contains(&pid) {
+                        pids.push(pid);
+                    }
+                }
+                _ => return Err(alloc::format!("'{item}' isn't a process id we can signal")),
+            }
+        }
+        if pids.is_empty() {
+            let why = if ours { " but procguard's own" } else { "" };
+            return Err(alloc::format!("no process ids{why} in '{value}'"));
+        }
+        Ok(Self::Pids(pids))
+    }
+
+    /// The pids as of now: the list, or what the file holds. A file that
+    /// can't be read holds none.
+    #[must_use]
+    pub fn pids(&self) -> Vec<i32> {
+        match self {
+            Self::Pids(pids) => pids.clone(),
+            Self::File(path) => crate::io::read_file(path.as_bytes(), FILE_LIMIT)
+                .map(|contents| parse_pids(&contents))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/* the pids in a file's contents, first mention only */
+fn parse_pids(contents: &[u8]) -> Vec<i32> {
+    // SAFETY: getpid has no preconditions
+    let us = unsafe { libc::getpid() };
+    let mut pids = Vec::new();
+    for word in contents.split(u8::is_ascii_whitespace) {
+        let Some(pid) = core::str::from_utf8(word)
+            .ok()
+            .and_then(|w| w.parse::<i32>().ok())
+        else {
+            continue;
+        };
+        if pid > 1 && pid != us && !pids.contains(&pid) {
+            pids.push(pid);
+        }
+    }
+    pids
+}
+
+/// What happened to one `--also-kill` process, across all attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target {
+    /// Its pid.
+    pub pid: i32,
+    /// The first signal that reached it; None if none did.
+    pub signal: Option<Signal>,
+    /// Whether SIGKILL followed.
+    pub killed: bool,
+    /// Why the last signal didn't reach it: [`Errno::NoSuchProcess`] once
+    /// it's gone, [`Errno::NotPermitted`] for another user's process.
+    pub error: Option<Errno>,
+    /* wall clock at its first signal this attempt, for its grace period */
+    pub(crate) signaled_at_ns: Option<u64>,
+}
+
+impl Target {
+    /// Nothing sent yet.
+    #[must_use]
+    pub const fn new(pid: i32) -> Self {
+        Self {
+            pid,
+            signal: None,
+            killed: false,
+            error: None,
+            signaled_at_ns: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg_attr(miri, ignore)] /* getpid is FFI */
+    fn test_parse() {
+        assert_eq!(AlsoKill::parse("42"), Ok(AlsoKill::Pids(alloc::vec![42])));
+        assert_eq!(
+            AlsoKill::parse("42, 43,"),
+            Ok(AlsoKill::Pids(alloc::vec![42, 43]))
+        );
+        assert_eq!(
+            AlsoKill::parse("run/a.pid"),
+            Ok(AlsoKill::File(String::from("run/a.pid")))
+        );
+        assert!(AlsoKill::parse("1").is_err());
+        assert!(AlsoKill::parse("42,0").is_err());
+        assert!(AlsoKill::parse(",").is_err());
+        assert!(AlsoKill::parse("99999999999").is_err());
+        /* spaces only around the commas */
+        assert!(AlsoKill::parse("42 43").unwrap_err().contains("commas"));
+        assert!(AlsoKill::parse("42,43 44").is_err());
+        /* each once, and never us */
+        // SAFETY: getpid has no preconditions
+        let us = unsafe { libc::getpid() };
+        assert_eq!(
+            AlsoKill::parse(&alloc::format!("42,{us},43,42")),
+            Ok(AlsoKill::Pids(alloc::vec![42, 43]))
+        );
+        assert!(AlsoKill::parse(&us.to_string()).is_err());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] /* getpid is FFI */
+    fn test_parse_pids() {
+        // SAFETY: getpid has no preconditions
+        let us = unsafe { libc::getpid() };
+        let contents = alloc::format!("4242\n4250 4242\n\n0 1 -7 abc {us}\r\n77\n");
+        assert_eq!(parse_pids(contents.as_bytes()), alloc::vec![4242, 4250, 77]);
+        assert!(parse_pids(b"").is_empty());
+    }
+}
@@ -12,20 +12,60 @@
  * - env var fallback is used
  * - short option cluster contains embedded value (-sTERM)
  * - --option=value syntax (borrows from slice, but may need owned for env)
+ *
+ * argv is bytes, not UTF-8. options are matched on a lossy copy; the command
+ * and its args are handed on as the original bytes (OwnedArgs::operands).
+ *
+ * startup is on the path of every wrapped command, so parse_args keeps it
+ * cheap: argv is borrowed in place (the lossy copy only allocates for
+ * arguments that aren't UTF-8), Args is converted to OwnedArgs once, and
+ * the TIMEOUT* env fallbacks are skipped after a single pass over environ
+ * finds none set - the usual case.
  */
 
+use alloc::borrow::Cow;
 use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
-use core::ffi::{CStr, c_char, c_int};
+#[cfg(target_os = "macos")]
+use core::ffi::c_int;
+use core::ffi::{CStr, c_char};
+use core::fmt::Write;
+
+use crate::also_kill::AlsoKill;
+use crate::process::{Arch, ProcessGroup};
+use crate::qos::QosClass;
+use crate::repeat::{FlakyExit, StopOn};
+use crate::sync::AtomicOnce;
+use crate::throttle::ThrottleNotify;
+use crate::window::Window;
 
 /* Darwin-specific APIs to get argc/argv and environment */
+#[cfg(target_os = "macos")]
 unsafe extern "C" {
     fn _NSGetArgc() -> *const c_int;
     fn _NSGetArgv() -> *const *const *const c_char;
+}
+
+unsafe extern "C" {
     fn getenv(name: *const c_char) -> *const c_char;
+    static environ: *const *const c_char;
+}
+
+/* (argc, argv) as main got them. linux keeps them from .init_array */
+#[cfg(target_os = "macos")]
+fn raw_argv() -> (c_int, *const *const c_char) {
+    // SAFETY: _NSGetArgc/_NSGetArgv always return valid pointers on macOS.
+    // Multiple unsafe ops allowed: both calls and derefs share that invariant.
+    #[allow(clippy::multiple_unsafe_ops_per_block)]
+    unsafe {
+        (*_NSGetArgc(), *_NSGetArgv())
+    }
 }
 
+#[cfg(target_os = "linux")]
+use crate::linux::raw_argv;
+
 /* Helper to read environment variable */
 pub fn get_env(name: &[u8]) -> Option<String> {
     // SAFETY: name must be null-terminated (caller ensures this), getenv returns
@@ -42,20 +82,54 @@ pub fn get_env(name: &[u8]) -> Option<String> {
     }
 }
 
-/* Get arguments from Darwin's _NSGetArgc/_NSGetArgv */
-fn get_args_from_darwin() -> Vec<String> {
-    // SAFETY: _NSGetArgc/_NSGetArgv always return valid pointers on macOS.
-    // argc is the valid count, argv[0..argc] are valid null-terminated C strings.
+/* any TIMEOUT* variable set? scanned once; if not, every env fallback
+ * below is a known miss and we skip its getenv() */
+static TIMEOUT_VARS: AtomicOnce<bool> = AtomicOnce::new();
+
+fn any_timeout_var() -> bool {
+    *TIMEOUT_VARS.get_or_init(|| {
+        // SAFETY: environ is the process environment: a null-terminated array
+        // of valid null-terminated C strings. We only read it.
+        // Multiple unsafe ops allowed: all share the same invariant.
+        #[allow(clippy::multiple_unsafe_ops_per_block)]
+        unsafe {
+            let mut p = environ;
+            while !p.is_null() && !(*p).is_null() {
+                if CStr::from_ptr(*p).to_bytes().starts_with(b"TIMEOUT") {
+                    return true;
+                }
+                p = p.add(1);
+            }
+            false
+        }
+    })
+}
+
+/// [`get_env`] for our own `TIMEOUT*` variables. Cheaper when none are set:
+/// one scan of the environment covers every lookup.
+pub fn get_timeout_env(name: &[u8]) -> Option<String> {
+    if any_timeout_var() {
+        get_env(name)
+    } else {
+        None
+    }
+}
+
+/* argv from Darwin's _NSGetArgc/_NSGetArgv, bytes as the kernel gave them.
+ * borrowed, not copied: the strings live as long as the process and
+ * nothing in it writes to them. owned only where an @file was expanded */
+fn get_args_from_darwin() -> Vec<Cow<'static, [u8]>> {
+    let (argc, argv) = raw_argv();
+    // SAFETY: argc is the valid count, argv[0..argc] are valid null-terminated
+    // C strings that stay put for the life of the process.
     // Multiple unsafe ops allowed: all share the same invariant (valid argv array).
     #[allow(clippy::multiple_unsafe_ops_per_block)]
     unsafe {
-        let argc = *_NSGetArgc();
-        let argv = *_NSGetArgv();
         let mut args = Vec::with_capacity(argc as usize);
         for i in 0..argc as isize {
             let arg_ptr = *argv.offset(i);
             if !arg_ptr.is_null() {
-                args.push(CStr::from_ptr(arg_ptr).to_string_lossy().into_owned());
+                args.push(Cow::Borrowed(CStr::from_ptr(arg_ptr).to_bytes()));
             }
         }
         args
@@ -65,14 +139,13 @@ fn get_args_from_darwin() -> Vec<String> {
 /// Get argv[0] (program name) from Darwin's _NSGetArgv.
 /// Used for dual-binary detection: "procguard" vs "timeout" alias.
 pub fn get_argv0() -> Option<String> {
-    // SAFETY: _NSGetArgc/_NSGetArgv always return valid pointers on macOS.
+    let (argc, argv) = raw_argv();
+    if argc < 1 {
+        return None;
+    }
+    // SAFETY: argc >= 1, so argv[0] is readable and null or a valid C string.
     #[allow(clippy::multiple_unsafe_ops_per_block)]
     unsafe {
-        let argc = *_NSGetArgc();
-        if argc < 1 {
-            return None;
-        }
-        let argv = *_NSGetArgv();
         let arg_ptr = *argv.offset(0);
         if arg_ptr.is_null() {
             return None;
@@ -81,6 +154,45 @@ pub fn get_argv0() -> Option<String> {
     }
 }
 
+/// Which program we were invoked as, from argv[0]'s basename
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Personality {
+    /// "procguard", "darwin-timeout" or anything else: every extension
+    #[default]
+    Procguard,
+    /// "timeout": every extension, defaults to `--confine active`
+    Timeout,
+    /// "gtimeout": GNU coreutils' options only, GNU's messages, no
+    /// `TIMEOUT*` variables or @files - for scripts written against it
+    Gnu,
+}
+
+impl Personality {
+    /// From argv[0] as given, e.g. "/opt/homebrew/bin/gtimeout"
+    pub fn from_argv0(argv0: &str) -> Self {
+        match argv0.rsplit('/').next().unwrap_or(argv0) {
+            "timeout" => Self::Timeout,
+            "gtimeout" => Self::Gnu,
+            _ => Self::Procguard,
+        }
+    }
+
+    /// From our own argv[0]
+    pub fn detect() -> Self {
+        get_argv0().map_or(Self::Procguard, |s| Self::from_argv0(&s))
+    }
+
+    /// The name in our stderr lines and --help
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Procguard => "procguard",
+            Self::Timeout => "timeout",
+            Self::Gnu => "gtimeout",
+        }
+    }
+}
+
 /// Parsed argument - either borrowed from argv or owned (env var / embedded value)
 #[derive(Debug, Clone)]
 pub enum ArgValue<'a> {
@@ -128,6 +240,9 @@ pub enum Confine {
     /// Active/awake time only (excludes system sleep) - uses CLOCK_MONOTONIC_RAW
     /// ~28% faster, useful for benchmarks where idle time shouldn't count
     Active,
+    /// Both clocks: the timeout fires once the wall deadline has passed AND
+    /// at least `--active-min` of awake time has gone by
+    Hybrid,
 }
 
 impl Confine {
@@ -135,102 +250,964 @@ impl Confine {
         match s.to_ascii_lowercase().as_str() {
             "wall" => Some(Self::Wall),
             "active" => Some(Self::Active),
+            "hybrid" => Some(Self::Hybrid),
+            _ => None,
+        }
+    }
+}
+
+/// `--on-sleep`: what the machine sleeping during a run means for the timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum SleepPolicy {
+    /// Nothing special: with `--confine wall` the sleep counts towards the
+    /// timeout (which may fire right after wake), with `active` it doesn't
+    #[default]
+    Continue,
+    /// The sleep never counts: the deadline moves out by however long the
+    /// machine slept
+    Pause,
+    /// Stop the command on wake, as a timeout with reason `system_sleep`
+    Fail,
+}
+
+impl SleepPolicy {
+    fn from_str(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "continue" => Some(Self::Continue),
+            "pause" => Some(Self::Pause),
+            "fail" => Some(Self::Fail),
+            _ => None,
+        }
+    }
+}
+
+/// `--on-child-stop`: what the command stopping itself (SIGSTOP, SIGTSTP,
+/// a ^Z) means for the timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum ChildStopPolicy {
+    /// The clock keeps running: the stop is only recorded
+    #[default]
+    Continue,
+    /// Time stopped doesn't count: the deadline moves out by however long
+    /// the command stayed stopped
+    Pause,
+    /// Stop the command as soon as it's seen stopped, as a timeout with
+    /// reason `child_stopped`
+    Fail,
+}
+
+impl ChildStopPolicy {
+    fn from_str(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "continue" => Some(Self::Continue),
+            "pause" => Some(Self::Pause),
+            "fail" => Some(Self::Fail),
+            _ => None,
+        }
+    }
+}
+
+/// `--on-stopped`: what `--verify-signal-delivery` does about a command it
+/// finds stopped after the signal, which it couldn't act on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum StoppedPolicy {
+    /// SIGCONT it, so the signal is delivered and the grace period means
+    /// something; SIGKILL if that doesn't wake it (a debugger holds it)
+    #[default]
+    Continue,
+    /// SIGKILL right away, skipping the grace period
+    Kill,
+}
+
+impl StoppedPolicy {
+    fn from_str(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "continue" => Some(Self::Continue),
+            "kill" => Some(Self::Kill),
+            _ => None,
+        }
+    }
+}
+
+/// `--hook-budget`: where the `--on-timeout` hook's time comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum HookBudget {
+    /// Its own `--on-timeout-limit`, run before the signal and its
+    /// `--kill-after` grace: the command can outlive the timeout by both
+    #[default]
+    Separate,
+    /// Out of the `--kill-after` grace: the hook is cut off at it, and the
+    /// signal gets what's left, so the command never outlives the timeout
+    /// by more than the grace
+    Shared,
+}
+
+impl HookBudget {
+    fn from_str(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "separate" => Some(Self::Separate),
+            "shared" => Some(Self::Shared),
+            _ => None,
+        }
+    }
+
+    /// The name `--hook-budget` takes, as the JSON output gives it.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Separate => "separate",
+            Self::Shared => "shared",
+        }
+    }
+}
+
+/// `--launchd-mode`: how a run procguard stopped (a timeout, a limit)
+/// ends, for a launchd job's `KeepAlive` to act on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LaunchdMode {
+    /// Exit 0, so `KeepAlive = { SuccessfulExit = false; }` doesn't
+    /// relaunch the job for it
+    Stop,
+    /// Die of SIGTERM once the result is out: an abnormal exit, which
+    /// launchd relaunches
+    Relaunch,
+}
+
+impl LaunchdMode {
+    fn from_str(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "stop" => Some(Self::Stop),
+            "relaunch" => Some(Self::Relaunch),
+            _ => None,
+        }
+    }
+
+    /// The name `--launchd-mode` takes, as the JSON output gives it.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Stop => "stop",
+            Self::Relaunch => "relaunch",
+        }
+    }
+}
+
+fn parse_launchd_mode(val: &str) -> Result<LaunchdMode, ParseError> {
+    LaunchdMode::from_str(val).ok_or_else(|| ParseError {
+        message: format!(
+            "invalid --launchd-mode: '{}' (use 'stop' or 'relaunch')",
+            val
+        ),
+    })
+}
+
+fn parse_hook_budget(val: &str) -> Result<HookBudget, ParseError> {
+    HookBudget::from_str(val).ok_or_else(|| ParseError {
+        message: format!(
+            "invalid --hook-budget: '{}' (use 'separate' or 'shared')",
+            val
+        ),
+    })
+}
+
+fn parse_window(val: &str) -> Result<Window, ParseError> {
+    Window::parse(val).ok_or_else(|| ParseError {
+        message: format!(
+            "invalid --only-between: '{}' (use HH:MM-HH:MM, e.g. 22:00-06:00)",
+            val
+        ),
+    })
+}
+
+fn parse_stop_on(val: &str) -> Result<StopOn, ParseError> {
+    StopOn::parse(val).ok_or_else(|| ParseError {
+        message: format!(
+            "invalid --stop-on: '{}' (use 'never', 'timeout' or 'failure')",
+            val
+        ),
+    })
+}
+
+fn parse_stopped_policy(val: &str) -> Result<StoppedPolicy, ParseError> {
+    StoppedPolicy::from_str(val).ok_or_else(|| ParseError {
+        message: format!("invalid --on-stopped: '{}' (use 'continue' or 'kill')", val),
+    })
+}
+
+fn parse_sleep_policy(val: &str) -> Result<SleepPolicy, ParseError> {
+    SleepPolicy::from_str(val).ok_or_else(|| ParseError {
+        message: format!(
+            "invalid --on-sleep: '{}' (use 'continue', 'pause' or 'fail')",
+            val
+        ),
+    })
+}
+
+fn parse_child_stop_policy(val: &str) -> Result<ChildStopPolicy, ParseError> {
+    ChildStopPolicy::from_str(val).ok_or_else(|| ParseError {
+        message: format!(
+            "invalid --on-child-stop: '{}' (use 'continue', 'pause' or 'fail')",
+            val
+        ),
+    })
+}
+
+/// `--stdin`: what the command gets as its stdin instead of ours
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum ChildStdin {
+    /// Ours, as is
+    #[default]
+    Inherit,
+    /// `/dev/null` - reads see EOF straight away
+    Null,
+    /// No fd 0 at all - reads fail with EBADF
+    Close,
+    /// Read from this file (`file:PATH`)
+    File(String),
+}
+
+fn parse_child_stdin(val: &str) -> Result<ChildStdin, ParseError> {
+    match val {
+        "null" => Ok(ChildStdin::Null),
+        "close" => Ok(ChildStdin::Close),
+        _ => match val.strip_prefix("file:") {
+            Some(path) if !path.is_empty() => Ok(ChildStdin::File(path.to_string())),
+            _ => Err(ParseError {
+                message: format!(
+                    "invalid --stdin: '{}' (use 'null', 'close' or 'file:PATH')",
+                    val
+                ),
+            }),
+        },
+    }
+}
+
+fn parse_also_kill(val: &str) -> Result<AlsoKill, ParseError> {
+    AlsoKill::parse(val).map_err(|why| ParseError {
+        message: format!("invalid --also-kill: {why}"),
+    })
+}
+
+/// `--stdin-mode`: how `--stdin-timeout` sees activity on stdin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum StdinMode {
+    /// Read stdin and throw it away - the command never gets it
+    #[default]
+    Consume,
+    /// Look without reading (`--stdin-passthrough`): pending input, and on
+    /// a terminal, when it was last read
+    Watch,
+    /// Put the command on a pseudo-terminal and type everything from stdin
+    /// into it, noting each keystroke on the way
+    Pty,
+}
+
+impl StdinMode {
+    fn from_str(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "consume" => Some(Self::Consume),
+            "watch" => Some(Self::Watch),
+            "pty" => Some(Self::Pty),
+            _ => None,
+        }
+    }
+}
+
+fn parse_stdin_mode(val: &str) -> Result<StdinMode, ParseError> {
+    StdinMode::from_str(val).ok_or_else(|| ParseError {
+        message: format!(
+            "invalid --stdin-mode: '{}' (use 'consume', 'watch' or 'pty')",
+            val
+        ),
+    })
+}
+
+/* --stdin-inject-on-timeout: C-style escapes, so a newline or a control
+ * key can be given on the command line */
+fn parse_inject(val: &str) -> Result<Vec<u8>, ParseError> {
+    let invalid = |what: &str| ParseError {
+        message: format!(
+            "invalid --stdin-inject-on-timeout: {} (escapes: \\n \\r \\t \\e \\0 \\\\ \\xHH)",
+            what
+        ),
+    };
+    if val.is_empty() {
+        return Err(invalid("nothing to write"));
+    }
+    let mut out = Vec::with_capacity(val.len());
+    let mut bytes = val.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+        let byte = match bytes.next() {
+            Some(b'n') => b'\n',
+            Some(b'r') => b'\r',
+            Some(b't') => b'\t',
+            Some(b'e') => 0x1b,
+            Some(b'0') => 0,
+            Some(b'\\') => b'\\',
+            Some(b'x') => {
+                let hex = [bytes.next(), bytes.next()];
+                let digits = hex.map(|d| d.and_then(|d| char::from(d).to_digit(16)));
+                match digits {
+                    #[allow(clippy::cast_possible_truncation)]
+                    [Some(hi), Some(lo)] => (hi * 16 + lo) as u8,
+                    _ => return Err(invalid("\\x needs two hex digits")),
+                }
+            }
+            Some(c) => return Err(invalid(&format!("unknown escape '\\{}'", char::from(c)))),
+            None => return Err(invalid("trailing '\\'")),
+        };
+        out.push(byte);
+    }
+    Ok(out)
+}
+
+/// `--timestamps` format: what goes in front of each line of output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Timestamps {
+    /// Time since the command started, `00:01:02.345`
+    Relative,
+    /// UTC wall-clock time, ISO-8601: `2026-01-02T03:04:05.678Z`
+    Absolute,
+}
+
+impl Timestamps {
+    fn from_str(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "relative" => Some(Self::Relative),
+            "absolute" => Some(Self::Absolute),
+            _ => None,
+        }
+    }
+}
+
+/// `--output-rate-policy`: what happens to output above `--output-rate-limit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum RatePolicy {
+    /// Throw the excess away, leaving a `[...N bytes suppressed...]` line
+    #[default]
+    Drop,
+    /// Stop reading until the rate allows more; the command blocks on a
+    /// full pipe
+    Block,
+}
+
+impl RatePolicy {
+    fn from_str(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "drop" => Some(Self::Drop),
+            "block" => Some(Self::Block),
             _ => None,
         }
     }
 }
 
+fn parse_rate_policy(val: &str) -> Result<RatePolicy, ParseError> {
+    RatePolicy::from_str(val).ok_or_else(|| ParseError {
+        message: format!(
+            "invalid --output-rate-policy: '{}' (use 'drop' or 'block')",
+            val
+        ),
+    })
+}
+
+/* bytes per second: 64K, 1M, 1M/s */
+fn parse_output_rate(val: &str) -> Result<u64, ParseError> {
+    let size = val.strip_suffix("/s").unwrap_or(val);
+    crate::rlimit::parse_mem_limit(size)
+        .ok()
+        .filter(|&rate| rate > 0)
+        .ok_or_else(|| ParseError {
+            message: format!(
+                "invalid --output-rate-limit: '{}' (use bytes per second, e.g. 64K or 1M)",
+                val
+            ),
+        })
+}
+
+fn parse_timestamps(val: &str) -> Result<Timestamps, ParseError> {
+    Timestamps::from_str(val).ok_or_else(|| ParseError {
+        message: format!(
+            "invalid --timestamps format: '{}' (use 'relative' or 'absolute')",
+            val
+        ),
+    })
+}
+
+fn parse_pgroup(val: &str) -> Result<ProcessGroup, ParseError> {
+    ProcessGroup::from_str(val).ok_or_else(|| ParseError {
+        message: format!(
+            "invalid --pgroup mode: '{}' (use 'inherit', 'new' or 'session')",
+            val
+        ),
+    })
+}
+
+fn parse_arch(val: &str) -> Result<Arch, ParseError> {
+    Arch::from_str(val).ok_or_else(|| ParseError {
+        message: format!("invalid --arch: '{}' (use 'arm64' or 'x86_64')", val),
+    })
+}
+
+fn parse_throttle_notify(val: &str) -> Result<ThrottleNotify, ParseError> {
+    ThrottleNotify::from_str(val).ok_or_else(|| ParseError {
+        message: format!(
+            "invalid --cpu-throttle-notify: '{}' (use 'env', 'signal' or 'both')",
+            val
+        ),
+    })
+}
+
+fn parse_monitor_qos(val: &str) -> Result<QosClass, ParseError> {
+    QosClass::from_str(val).ok_or_else(|| ParseError {
+        message: format!(
+            "invalid --monitor-qos class: '{}' (use 'user-interactive', 'user-initiated', 'default', 'utility' or 'background')",
+            val
+        ),
+    })
+}
+
+fn parse_json_fd(val: &str) -> Result<i32, ParseError> {
+    val.parse::<i32>()
+        .ok()
+        .filter(|fd| *fd >= 0)
+        .ok_or_else(|| ParseError {
+            message: format!(
+                "invalid --json-fd: '{}' (must be a file descriptor number)",
+                val
+            ),
+        })
+}
+
+fn parse_wait_pid(val: &str) -> Result<i32, ParseError> {
+    val.parse::<i32>()
+        .ok()
+        .filter(|pid| *pid > 0)
+        .ok_or_else(|| ParseError {
+            message: format!("invalid --wait-pid: '{}' (must be a process ID)", val),
+        })
+}
+
+fn parse_flaky_exit(val: &str) -> Result<FlakyExit, ParseError> {
+    FlakyExit::parse(val).ok_or_else(|| ParseError {
+        message: format!(
+            "invalid --flaky-exit: '{}' (use 'pass', 'fail' or an exit code 0-255)",
+            val
+        ),
+    })
+}
+
+/* --runs, --max-runs, --detect-flaky: at least one */
+fn parse_run_count(flag: &str, val: &str) -> Result<u32, ParseError> {
+    val.parse::<u32>()
+        .ok()
+        .filter(|n| *n > 0)
+        .ok_or_else(|| ParseError {
+            message: format!("invalid {}: '{}' (must be a count of 1 or more)", flag, val),
+        })
+}
+
+/* --spawn-retry: 0 (off) up to MAX_SPAWN_RETRIES */
+fn parse_spawn_retry(val: &str) -> Result<u32, ParseError> {
+    let max = crate::runner::MAX_SPAWN_RETRIES;
+    val.parse::<u32>()
+        .ok()
+        .filter(|n| *n <= max)
+        .ok_or_else(|| ParseError {
+            message: format!("invalid --spawn-retry: '{}' (must be 0 to {})", val, max),
+        })
+}
+
+/* --flaky-threshold: "5%", "2.5" - a percentage, one decimal at most, as
+ * tenths of a percent */
+fn parse_flaky_threshold(val: &str) -> Result<u32, ParseError> {
+    let digits = val.strip_suffix('%').unwrap_or(val);
+    let (whole, tenth) = digits.split_once('.').unwrap_or((digits, "0"));
+    let all_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let permille = if all_digits(whole) && all_digits(tenth) && tenth.len() == 1 {
+        whole
+            .parse::<u32>()
+            .ok()
+            .and_then(|w| w.checked_mul(10))
+            .map(|w| w + u32::from(tenth.as_bytes()[0] - b'0'))
+            .filter(|p| *p <= 1000)
+    } else {
+        None
+    };
+    permille.ok_or_else(|| ParseError {
+        message: format!(
+            "invalid --flaky-threshold: '{}' (use a percentage from 0 to 100, e.g. 5%)",
+            val
+        ),
+    })
+}
+
+/* --run-id: passed through as given, if it fits on a log line */
+fn parse_run_id(val: &str) -> Result<&str, ParseError> {
+    if crate::run_id::is_valid(val) {
+        Ok(val)
+    } else {
+        Err(ParseError {
+            message: format!(
+                "invalid --run-id: '{}' (1 to {} characters, no control characters)",
+                val.escape_debug(),
+                crate::run_id::MAX_LEN
+            ),
+        })
+    }
+}
+
+/* --file-mode: permission bits in octal, as chmod takes them */
+fn parse_file_mode(val: &str) -> Result<u32, ParseError> {
+    u32::from_str_radix(val, 8)
+        .ok()
+        .filter(|mode| !val.is_empty() && !val.starts_with('+') && *mode <= 0o777)
+        .ok_or_else(|| ParseError {
+            message: format!(
+                "invalid --file-mode: '{}' (must be octal permission bits, e.g. 0600)",
+                val
+            ),
+        })
+}
+
+/// Where the duration came from, which decides what the operands are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DurationFrom {
+    /// The first operand; the rest are the command. Also: none given.
+    #[default]
+    Operand,
+    /// `-t`/`--duration`: every operand is the command, and `TIMEOUT` is
+    /// ignored.
+    Option,
+    /// `TIMEOUT`, with no operand in its place: none at all, or `--` came
+    /// before the first one.
+    Env,
+}
+
+/// The command line, parsed but not yet checked against the environment.
+/// [`into_owned`](Args::into_owned) and [`RunConfig::from_args`] take it
+/// the rest of the way, as the binary does.
+///
+/// [`RunConfig::from_args`]: crate::runner::RunConfig::from_args
+///
+/// ```
+/// use procguard::args::parse_from_slice;
+/// use procguard::{RunConfig, Signal};
+///
+/// let args = parse_from_slice(&["-s", "INT", "5s", "make", "-j8"]).unwrap();
+/// assert_eq!(args.signal.as_str(), "INT");
+/// assert_eq!(args.command.as_ref().unwrap().as_str(), "make");
+///
+/// let owned = args.into_owned();
+/// let config = RunConfig::from_args(&owned, owned.duration.as_deref().unwrap()).unwrap();
+/// assert_eq!(config.signal, Signal::SIGINT);
+/// ```
 #[derive(Debug, Clone, Default)]
 pub struct Args<'a> {
     pub json: bool,
+    pub json_fd: Option<i32>, /* None = stdout */
     pub signal: ArgValue<'a>,
     pub kill_after: Option<ArgValue<'a>>,
     pub preserve_status: bool,
     pub foreground: bool,
     pub verbose: bool,
-    pub quiet: bool,
+    pub quiet: u8, /* number of -q: 1 hides warnings, 2 hides errors too */
+    pub tag: Option<ArgValue<'a>>, /* replaces the "timeout:" stderr prefix */
+    pub run_id: Option<ArgValue<'a>>, /* correlation id for every output */
     pub timeout_exit_code: Option<u8>,
+    pub no_warn_exit_code: bool, /* a reserved --timeout-exit-code is deliberate */
+    pub strict: bool,            /* a reserved --timeout-exit-code is an error */
+    pub kill_self_on_timeout: Option<ArgValue<'a>>, /* signal to die of on timeout */
+    pub launchd_mode: Option<LaunchdMode>, /* how a stopped run ends for launchd */
     pub on_timeout: Option<ArgValue<'a>>,
+    pub on_limit: Option<ArgValue<'a>>, /* hook for a limit kill; else --on-timeout's */
     pub on_timeout_limit: ArgValue<'a>,
+    pub hook_budget: HookBudget, /* hook time out of the grace period or not */
+    pub hook_async: bool,        /* hook runs alongside the signal and grace */
+    pub verify_signal_delivery: bool, /* check the command can act on our signals */
+    pub on_stopped: Option<StoppedPolicy>, /* ...and what to do if it can't */
+    pub debugger_grace: Option<ArgValue<'a>>, /* hold off while a debugger is attached */
+    pub crash_hook: Option<ArgValue<'a>>, /* run if COMMAND crashed */
     pub confine: Confine,
     pub confine_specified: bool, /* true if user explicitly set -c/--confine */
+    pub active_min: Option<ArgValue<'a>>, /* awake time --confine hybrid waits for */
+    pub on_sleep: SleepPolicy,   /* system sleep during the run */
+    pub on_child_stop: Option<ChildStopPolicy>, /* the command stopping itself */
+    pub pgroup: Option<ProcessGroup>, /* None = decide from --foreground */
+    pub arch: Option<Arch>,      /* slice of a universal binary to run */
+    pub tty_foreground: bool,    /* hand the terminal to the child */
+    pub restore_tty: bool,       /* put terminal settings back afterwards */
+    pub monitor_qos: Option<QosClass>, /* our own QoS, not the child's */
     pub wait_for_file: Option<ArgValue<'a>>,
+    pub wait_for_file_gone: Option<ArgValue<'a>>, /* lockfile: wait for it to go away */
+    pub wait_for_file_contains: Option<ArgValue<'a>>, /* marker in --wait-for-file */
     pub wait_for_file_timeout: Option<ArgValue<'a>>,
+    pub flock: Option<ArgValue<'a>>, /* single instance: hold this lock while running */
+    pub flock_timeout: Option<ArgValue<'a>>, /* how long to wait for it */
+    pub flock_queue: Option<ArgValue<'a>>, /* wait in line for it, "" = no cap */
+    pub only_between: Option<Window>, /* local time of day the command may run in */
+    pub window_wait: Option<ArgValue<'a>>, /* how long to wait for it to open */
+    pub window_stop: bool,           /* stop the command when it closes */
+    pub every: Option<ArgValue<'a>>, /* --every: run again on this cadence */
+    pub repeat_for: Option<ArgValue<'a>>, /* --for: ... for this long */
+    pub stop_on: Option<StopOn>,     /* which run ends the repeat early */
+    pub runs: Option<u32>,           /* --runs: this many times, back to back */
+    pub max_runs: Option<u32>,       /* --every stops after this many */
+    pub flaky_threshold: Option<u32>, /* failures allowed, tenths of a percent */
+    pub detect_flaky: Option<u32>,   /* rerun up to this many times on failure */
+    pub flaky_exit: Option<FlakyExit>, /* exit status of a flaky test */
     pub retry: Option<ArgValue<'a>>,
     pub retry_delay: Option<ArgValue<'a>>,
     pub retry_backoff: Option<ArgValue<'a>>,
+    pub spawn_retry: Option<u32>, /* tries again at a transient spawn failure */
+    pub spawn_retry_delay: Option<ArgValue<'a>>,
     pub heartbeat: Option<ArgValue<'a>>,
     pub stdin_timeout: Option<ArgValue<'a>>,
-    pub stdin_passthrough: bool, /* non-consuming stdin watchdog */
+    pub stdin_mode: StdinMode,         /* how the stdin watchdog looks */
+    pub child_stdin: ChildStdin,       /* what the command reads instead of our stdin */
+    pub stdin_inject: Option<Vec<u8>>, /* written to the command's stdin on timeout */
+    pub stdin_inject_wait: Option<ArgValue<'a>>, /* how long it gets to quit on it */
+    pub explain: bool,                 /* print timeline of decisions at exit */
+    pub sleep: bool,                   /* no command: just wait out DURATION */
+    pub wait_pid: Option<i32>,         /* no command: wait for this process */
+    pub crash_report: bool,            /* look for a DiagnosticReports file on crash */
+    pub result_file: Option<ArgValue<'a>>, /* JSON result, written atomically */
+    pub pidfile: Option<ArgValue<'a>>, /* child pid + ours while the child runs */
+    pub export_remaining: Option<ArgValue<'a>>, /* env var the budget goes in */
+    pub export_remaining_file: Option<ArgValue<'a>>, /* file kept at the time left */
+    pub extend_env_file: Option<ArgValue<'a>>, /* TIMEOUT_EXTEND=DUR, re-read every second */
+    pub extend_max: Option<ArgValue<'a>>, /* cap on it, default the timeout */
+    pub file_mode: Option<u32>,        /* exact mode for the files above, umask aside */
+    pub no_fsync: bool,                /* skip fsync before renaming them into place */
+    pub temp_dir: Option<ArgValue<'a>>, /* parent of the per-attempt TMPDIR, "" = $TMPDIR */
+    pub keep_temp_on_failure: bool,    /* leave a failed attempt's TMPDIR behind */
+    pub print_pid: bool,               /* "started" JSON line after each spawn */
+    pub proc_tree: bool,               /* record what the command forks and execs */
+    pub kill_escaped: bool,            /* SIGKILL what left the group, at the end */
+    pub reap_orphans: bool,            /* SIGKILL and reap what stayed in it */
+    pub also_kill: Option<AlsoKill>,   /* more pids to signal at a stop */
+    pub prefix_output: Option<ArgValue<'a>>, /* tag in front of each output line */
+    pub timestamps: Option<Timestamps>, /* time in front of each output line */
+    pub raw_output: bool,              /* never rewrite the command's output */
+    pub merge_output: bool,            /* command's stderr into its stdout, by us */
+    pub output_rate_limit: Option<u64>, /* bytes/sec of output let through */
+    pub output_rate_policy: RatePolicy, /* what happens above it */
     pub mem_limit: Option<ArgValue<'a>>, /* e.g. 1G */
     pub cpu_time: Option<ArgValue<'a>>, /* cpu seconds via rlimit */
     pub cpu_percent: Option<ArgValue<'a>>, /* cpu throttling percentage */
+    pub cpu_throttle_window: Option<ArgValue<'a>>, /* its smoothing window */
+    pub cpu_throttle_gain: Option<ArgValue<'a>>, /* its proportional gain */
+    pub cpu_throttle_quantum: Option<ArgValue<'a>>, /* its duty-cycle period */
+    pub cpu_throttle_notify: Option<ThrottleNotify>, /* telling the command */
+    pub poll_interval: Option<ArgValue<'a>>, /* every polling watcher's tick */
+    pub mem_poll_interval: Option<ArgValue<'a>>, /* ...--mem-limit's alone */
+    pub cpu_poll_interval: Option<ArgValue<'a>>, /* ...--cpu-percent's */
+    pub stdin_poll_interval: Option<ArgValue<'a>>, /* ...--stdin-mode watch's */
     pub duration: Option<ArgValue<'a>>,
+    pub duration_from: DurationFrom,
     pub command: Option<ArgValue<'a>>,
     pub args: Vec<ArgValue<'a>>,
+    pub operand_index: Vec<usize>, /* slice index of each duration/command/arg, in order */
+    pub response_file: Option<usize>, /* slice index of the first @file duration/command */
 }
 
 /// Owned version for when we need 'static lifetime (after env var resolution)
 #[derive(Debug, Clone, Default)]
 pub struct OwnedArgs {
     pub json: bool,
+    pub json_fd: Option<i32>,
     pub signal: String,
     pub kill_after: Option<String>,
     pub preserve_status: bool,
     pub foreground: bool,
     pub verbose: bool,
-    pub quiet: bool,
+    pub quiet: u8,
+    pub tag: Option<String>,
+    pub run_id: Option<String>,
     pub timeout_exit_code: Option<u8>,
+    pub no_warn_exit_code: bool,
+    pub strict: bool,
+    pub kill_self_on_timeout: Option<String>,
+    pub launchd_mode: Option<LaunchdMode>,
     pub on_timeout: Option<String>,
+    pub on_limit: Option<String>,
     pub on_timeout_limit: String,
+    pub hook_budget: HookBudget,
+    pub hook_async: bool,
+    pub verify_signal_delivery: bool,
+    pub on_stopped: Option<StoppedPolicy>,
+    pub debugger_grace: Option<String>,
+    pub crash_hook: Option<String>,
     pub confine: Confine,
     pub confine_specified: bool, /* true if user explicitly set -c/--confine */
+    pub personality: Personality, /* who we were invoked as, see parse_args_as */
+    pub active_min: Option<String>,
+    pub on_sleep: SleepPolicy,
+    pub on_child_stop: Option<ChildStopPolicy>,
+    pub pgroup: Option<ProcessGroup>,
+    pub arch: Option<Arch>,
+    pub tty_foreground: bool,
+    pub restore_tty: bool,
+    pub monitor_qos: Option<QosClass>,
     pub wait_for_file: Option<String>,
+    pub wait_for_file_gone: Option<String>,
+    pub wait_for_file_contains: Option<String>,
     pub wait_for_file_timeout: Option<String>,
+    pub flock: Option<String>,
+    pub flock_timeout: Option<String>,
+    pub flock_queue: Option<String>,
+    pub only_between: Option<Window>,
+    pub window_wait: Option<String>,
+    pub window_stop: bool,
+    pub every: Option<String>,
+    pub repeat_for: Option<String>,
+    pub stop_on: Option<StopOn>,
+    pub runs: Option<u32>,
+    pub max_runs: Option<u32>,
+    pub flaky_threshold: Option<u32>,
+    pub detect_flaky: Option<u32>,
+    pub flaky_exit: Option<FlakyExit>,
     pub retry: Option<String>,
     pub retry_delay: Option<String>,
     pub retry_backoff: Option<String>,
+    pub spawn_retry: Option<u32>,
+    pub spawn_retry_delay: Option<String>,
     pub heartbeat: Option<String>,
     pub stdin_timeout: Option<String>,
-    pub stdin_passthrough: bool,
+    pub stdin_mode: StdinMode,
+    pub child_stdin: ChildStdin,
+    pub stdin_inject: Option<Vec<u8>>,
+    pub stdin_inject_wait: Option<String>,
+    pub explain: bool,
+    pub sleep: bool,
+    pub wait_pid: Option<i32>,
+    pub crash_report: bool,
+    pub result_file: Option<String>,
+    pub pidfile: Option<String>,
+    pub export_remaining: Option<String>,
+    pub export_remaining_file: Option<String>,
+    pub extend_env_file: Option<String>,
+    pub extend_max: Option<String>,
+    pub file_mode: Option<u32>,
+    pub no_fsync: bool,
+    pub temp_dir: Option<String>,
+    pub keep_temp_on_failure: bool,
+    pub print_pid: bool,
+    pub proc_tree: bool,
+    pub kill_escaped: bool,
+    pub reap_orphans: bool,
+    pub also_kill: Option<AlsoKill>,
+    pub prefix_output: Option<String>,
+    pub timestamps: Option<Timestamps>,
+    pub raw_output: bool,
+    pub merge_output: bool,
+    pub output_rate_limit: Option<u64>,
+    pub output_rate_policy: RatePolicy,
     pub mem_limit: Option<String>,
     pub cpu_time: Option<String>,
     pub cpu_percent: Option<String>,
+    pub cpu_throttle_window: Option<String>,
+    pub cpu_throttle_gain: Option<String>,
+    pub cpu_throttle_quantum: Option<String>,
+    pub cpu_throttle_notify: Option<ThrottleNotify>,
+    pub poll_interval: Option<String>,
+    pub mem_poll_interval: Option<String>,
+    pub cpu_poll_interval: Option<String>,
+    pub stdin_poll_interval: Option<String>,
     pub duration: Option<String>,
-    pub command: Option<String>,
-    pub args: Vec<String>,
+    pub duration_from: DurationFrom,
+    pub command: Option<String>, /* lossy UTF-8, for display */
+    pub args: Vec<String>,       /* lossy UTF-8, for display */
+    /// Duration, command and args as raw bytes, in argv order. This is what
+    /// the child gets - argv needn't be UTF-8.
+    pub operands: Vec<Vec<u8>>,
+}
+
+impl OwnedArgs {
+    /// How the run says things on stderr: `--tag`, else the name we were
+    /// invoked as, at the quiet level `-q` gave.
+    #[must_use]
+    pub fn log(&self) -> crate::log::Logger<'_> {
+        let tag = self.tag.as_deref().unwrap_or(self.personality.name());
+        crate::log::Logger::new(tag, self.quiet)
+    }
 }
 
 impl<'a> Args<'a> {
+    /* an operand has been taken as the duration. -t doesn't count: options
+     * can still follow it */
+    fn past_duration(&self) -> bool {
+        self.duration.is_some() && self.duration_from == DurationFrom::Operand
+    }
+
+    /* -t/--duration. after an operand was taken as the duration it's too
+     * late: that operand would have to become the command */
+    fn set_duration(&mut self, value: ArgValue<'a>) -> Result<(), ParseError> {
+        if let Some(operand) = self.duration.as_ref().filter(|_| self.past_duration()) {
+            return Err(ParseError {
+                message: format!(
+                    "--duration given after the DURATION operand '{}'",
+                    operand.as_str()
+                ),
+            });
+        }
+        self.duration = Some(value);
+        self.duration_from = DurationFrom::Option;
+        Ok(())
+    }
+
+    /* positional operand: duration first, then command, then its args */
+    fn push_positional(&mut self, arg: &'a str, index: usize) {
+        /* @file stands for the arguments in it, but only where ours go.
+         * the command's own args and anything after -- don't come here */
+        if self.response_file.is_none() && arg.len() > 1 && arg.starts_with('@') {
+            self.response_file = Some(index);
+        }
+        if self.duration.is_none() {
+            self.duration = Some(ArgValue::Borrowed(arg));
+        } else if self.command.is_none() {
+            self.command = Some(ArgValue::Borrowed(arg));
+        } else {
+            self.args.push(ArgValue::Borrowed(arg));
+        }
+        self.operand_index.push(index);
+    }
+
     /// Convert to owned version (for main.rs which needs 'static)
+    ///
+    /// `operands` is filled from the parsed strings; [`parse_args`] uses the
+    /// original argv bytes instead.
     pub fn into_owned(self) -> OwnedArgs {
+        let operands = self
+            .duration
+            .iter()
+            .filter(|_| self.duration_from == DurationFrom::Operand)
+            .chain(self.command.iter())
+            .chain(self.args.iter())
+            .map(|v| v.as_str().as_bytes().to_vec())
+            .collect();
+        self.into_owned_with(operands)
+    }
+
+    fn into_owned_with(self, operands: Vec<Vec<u8>>) -> OwnedArgs {
         OwnedArgs {
             json: self.json,
+            json_fd: self.json_fd,
             signal: self.signal.into_owned(),
             kill_after: self.kill_after.map(|v| v.into_owned()),
             preserve_status: self.preserve_status,
             foreground: self.foreground,
             verbose: self.verbose,
             quiet: self.quiet,
+            tag: self.tag.map(|v| v.into_owned()),
+            run_id: self.run_id.map(|v| v.into_owned()),
             timeout_exit_code: self.timeout_exit_code,
+            no_warn_exit_code: self.no_warn_exit_code,
+            strict: self.strict,
+            kill_self_on_timeout: self.kill_self_on_timeout.map(|v| v.into_owned()),
+            launchd_mode: self.launchd_mode,
             on_timeout: self.on_timeout.map(|v| v.into_owned()),
+            on_limit: self.on_limit.map(|v| v.into_owned()),
             on_timeout_limit: self.on_timeout_limit.into_owned(),
+            hook_budget: self.hook_budget,
+            hook_async: self.hook_async,
+            verify_signal_delivery: self.verify_signal_delivery,
+            on_stopped: self.on_stopped,
+            debugger_grace: self.debugger_grace.map(|v| v.into_owned()),
+            crash_hook: self.crash_hook.map(|v| v.into_owned()),
             confine: self.confine,
             confine_specified: self.confine_specified,
+            personality: Personality::default(),
+            active_min: self.active_min.map(|v| v.into_owned()),
+            on_sleep: self.on_sleep,
+            on_child_stop: self.on_child_stop,
+            pgroup: self.pgroup,
+            arch: self.arch,
+            tty_foreground: self.tty_foreground,
+            restore_tty: self.restore_tty,
+            monitor_qos: self.monitor_qos,
             wait_for_file: self.wait_for_file.map(|v| v.into_owned()),
+            wait_for_file_gone: self.wait_for_file_gone.map(|v| v.into_owned()),
+            wait_for_file_contains: self.wait_for_file_contains.map(|v| v.into_owned()),
             wait_for_file_timeout: self.wait_for_file_timeout.map(|v| v.into_owned()),
+            flock: self.flock.map(|v| v.into_owned()),
+            flock_timeout: self.flock_timeout.map(|v| v.into_owned()),
+            flock_queue: self.flock_queue.map(|v| v.into_owned()),
+            only_between: self.only_between,
+            window_wait: self.window_wait.map(|v| v.into_owned()),
+            window_stop: self.window_stop,
+            every: self.every.map(|v| v.into_owned()),
+            repeat_for: self.repeat_for.map(|v| v.into_owned()),
+            stop_on: self.stop_on,
+            runs: self.runs,
+            max_runs: self.max_runs,
+            flaky_threshold: self.flaky_threshold,
+            detect_flaky: self.detect_flaky,
+            flaky_exit: self.flaky_exit,
             retry: self.retry.map(|v| v.into_owned()),
             retry_delay: self.retry_delay.map(|v| v.into_owned()),
             retry_backoff: self.retry_backoff.map(|v| v.into_owned()),
+            spawn_retry: self.spawn_retry,
+            spawn_retry_delay: self.spawn_retry_delay.map(|v| v.into_owned()),
             heartbeat: self.heartbeat.map(|v| v.into_owned()),
             stdin_timeout: self.stdin_timeout.map(|v| v.into_owned()),
-            stdin_passthrough: self.stdin_passthrough,
+            stdin_mode: self.stdin_mode,
+            child_stdin: self.child_stdin,
+            stdin_inject: self.stdin_inject,
+            stdin_inject_wait: self.stdin_inject_wait.map(|v| v.into_owned()),
+            explain: self.explain,
+            sleep: self.sleep,
+            wait_pid: self.wait_pid,
+            crash_report: self.crash_report,
+            result_file: self.result_file.map(|v| v.into_owned()),
+            pidfile: self.pidfile.map(|v| v.into_owned()),
+            export_remaining: self.export_remaining.map(|v| v.into_owned()),
+            export_remaining_file: self.export_remaining_file.map(|v| v.into_owned()),
+            extend_env_file: self.extend_env_file.map(|v| v.into_owned()),
+            extend_max: self.extend_max.map(|v| v.into_owned()),
+            file_mode: self.file_mode,
+            no_fsync: self.no_fsync,
+            temp_dir: self.temp_dir.map(|v| v.into_owned()),
+            keep_temp_on_failure: self.keep_temp_on_failure,
+            print_pid: self.print_pid,
+            proc_tree: self.proc_tree,
+            kill_escaped: self.kill_escaped,
+            reap_orphans: self.reap_orphans,
+            also_kill: self.also_kill,
+            prefix_output: self.prefix_output.map(|v| v.into_owned()),
+            timestamps: self.timestamps,
+            raw_output: self.raw_output,
+            merge_output: self.merge_output,
+            output_rate_limit: self.output_rate_limit,
+            output_rate_policy: self.output_rate_policy,
             mem_limit: self.mem_limit.map(|v| v.into_owned()),
             cpu_time: self.cpu_time.map(|v| v.into_owned()),
             cpu_percent: self.cpu_percent.map(|v| v.into_owned()),
+            cpu_throttle_window: self.cpu_throttle_window.map(|v| v.into_owned()),
+            cpu_throttle_gain: self.cpu_throttle_gain.map(|v| v.into_owned()),
+            cpu_throttle_quantum: self.cpu_throttle_quantum.map(|v| v.into_owned()),
+            cpu_throttle_notify: self.cpu_throttle_notify,
+            poll_interval: self.poll_interval.map(|v| v.into_owned()),
+            mem_poll_interval: self.mem_poll_interval.map(|v| v.into_owned()),
+            cpu_poll_interval: self.cpu_poll_interval.map(|v| v.into_owned()),
+            stdin_poll_interval: self.stdin_poll_interval.map(|v| v.into_owned()),
             duration: self.duration.map(|v| v.into_owned()),
+            duration_from: self.duration_from,
             command: self.command.map(|v| v.into_owned()),
             args: self.args.into_iter().map(|v| v.into_owned()).collect(),
+            operands,
         }
     }
 }
@@ -250,24 +1227,112 @@ impl core::fmt::Display for ParseError {
 /// parse from Darwin's argc/argv, applying env var fallbacks
 /// returns OwnedArgs since we convert from C strings
 pub fn parse_args() -> Result<OwnedArgs, ParseError> {
-    let args = get_args_from_darwin();
-    let parsed = parse_from_slice(&args[1..])?;
-    let mut owned = parsed.into_owned();
+    parse_args_as(Personality::Procguard)
+}
+
+/// [`parse_args`] as `personality`: with [`Personality::Gnu`], anything GNU
+/// timeout doesn't know is refused with its message, and the environment
+/// and @files are left alone
+pub fn parse_args_as(personality: Personality) -> Result<OwnedArgs, ParseError> {
+    let gnu = personality == Personality::Gnu;
+    let mut raw = get_args_from_darwin();
+    /* hidden: diff our exit codes and timing against GNU's (compat.rs) */
+    if !gnu && raw.get(1).is_some_and(|a| **a == *b"--compat-check") {
+        let ours = String::from_utf8_lossy(&raw[0]).into_owned();
+        let reference = raw.get(2).map(|r| String::from_utf8_lossy(r).into_owned());
+        let code = crate::compat::run(&ours, reference.as_deref());
+        // SAFETY: exit is always safe
+        unsafe { libc::exit(i32::from(code)) };
+    }
+    if !gnu && let Some(opts) = get_timeout_env(b"TIMEOUT_OPTS\0") {
+        let words = env_options(&opts)?;
+        let at = raw.len().min(1);
+        raw.splice(at..at, words.into_iter().map(Cow::Owned));
+    }
+    if !gnu && raw.iter().skip(1).any(|a| a.starts_with(b"@")) {
+        expand_response_files(&mut raw)?;
+    }
+    /* TIMEOUT_<OPTION>s go ahead of everything, like TIMEOUT_OPTS */
+    let mut from_env = Vec::new();
+    if !gnu && any_timeout_var() {
+        let view: Vec<Cow<'_, str>> = raw
+            .iter()
+            .skip(1)
+            .map(|a| String::from_utf8_lossy(a))
+            .collect();
+        from_env = env_fallbacks(&view, |var| get_env(format!("{var}\0").as_bytes()))?;
+        let at = raw.len().min(1);
+        raw.splice(
+            at..at,
+            from_env
+                .iter()
+                .map(|(_, o)| Cow::Owned(o.clone().into_bytes())),
+        );
+    }
+    /* options are ASCII, so parse a lossy view (borrowed unless an arg isn't
+     * UTF-8); operands come from the raw bytes so the child sees exactly
+     * what we were given */
+    let args: Vec<Cow<'_, str>> = raw.iter().map(|a| String::from_utf8_lossy(a)).collect();
+    /* argv can legally be empty (execve with argv = {NULL}) */
+    let args = args.get(1..).unwrap_or_default();
+    if gnu {
+        check_gnu_options(args, personality.name())?;
+    }
+    /* the command line alone parsed, so the variables are what clash */
+    let parsed = parse_from_slice(args).map_err(|e| {
+        if from_env.is_empty() {
+            return e;
+        }
+        let vars: Vec<&str> = from_env.iter().map(|(var, _)| var.as_str()).collect();
+        ParseError {
+            message: format!("{} (with {} set)", e.message, vars.join(", ")),
+        }
+    })?;
+    let operands = parsed
+        .operand_index
+        .iter()
+        .map(|&i| raw.get(i + 1).map_or_else(Vec::new, |a| a.to_vec()))
+        .collect();
+    let mut owned = parsed.into_owned_with(operands);
+    owned.personality = personality;
+
+    if gnu {
+        if owned.signal.is_empty() {
+            owned.signal = "TERM".to_string();
+        }
+        /* GNU's -k 0 is no -k at all, not our KILL-at-the-deadline */
+        if owned.kill_after.as_deref().is_some_and(|k| {
+            crate::duration::parse_duration(k).is_ok_and(|d| crate::duration::is_no_timeout(&d))
+        }) {
+            owned.kill_after = None;
+        }
+        return Ok(owned);
+    }
 
     /* apply env var fallbacks: CLI > env > default */
     if owned.signal.is_empty() {
-        owned.signal = get_env(b"TIMEOUT_SIGNAL\0").unwrap_or_else(|| "TERM".to_string());
+        owned.signal = get_timeout_env(b"TIMEOUT_SIGNAL\0").unwrap_or_else(|| "TERM".to_string());
+    }
+    if !any_timeout_var() {
+        return Ok(owned);
     }
     if owned.kill_after.is_none() {
         owned.kill_after = get_env(b"TIMEOUT_KILL_AFTER\0");
     }
     if owned.duration.is_none() {
         owned.duration = get_env(b"TIMEOUT\0");
+        if owned.duration.is_some() {
+            owned.duration_from = DurationFrom::Env;
+        }
     }
-    if owned.wait_for_file.is_none() {
+    /* --wait-for-file-gone on the command line takes the env var's place */
+    if owned.wait_for_file.is_none() && owned.wait_for_file_gone.is_none() {
         owned.wait_for_file = get_env(b"TIMEOUT_WAIT_FOR_FILE\0");
     }
-    if owned.wait_for_file_timeout.is_none() {
+    /* only means anything with a file to wait for */
+    if (owned.wait_for_file.is_some() || owned.wait_for_file_gone.is_some())
+        && owned.wait_for_file_timeout.is_none()
+    {
         owned.wait_for_file_timeout = get_env(b"TIMEOUT_WAIT_FOR_FILE_TIMEOUT\0");
     }
     if owned.retry.is_none() {
@@ -283,19 +1348,407 @@ pub fn parse_args() -> Result<OwnedArgs, ParseError> {
     Ok(owned)
 }
 
-/// parse from slice (for testing and internal use)
-pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError> {
-    let mut result = Args {
-        signal: ArgValue::Borrowed(""), // will apply env fallback later
-        on_timeout_limit: ArgValue::Borrowed("5s"),
-        ..Default::default()
+/*
+ * TIMEOUT_OPTS: options for build systems that can only set the
+ * environment, split as sh splits words and put ahead of the command line.
+ * so the command line wins for an option that takes one value, and
+ * TIMEOUT_OPTS wins over the other TIMEOUT_* variables, which only fill in
+ * what's still unset. options only, each with its value: the duration and
+ * command (and --, and @files) belong on the command line.
+ */
+fn env_options(opts: &str) -> Result<Vec<Vec<u8>>, ParseError> {
+    let fail = |why: String| ParseError {
+        message: format!("TIMEOUT_OPTS: {why}"),
+    };
+    let words = crate::response_file::split_words(opts.as_bytes()).map_err(fail)?;
+    let mut view: Vec<Cow<'_, str>> = words.iter().map(|w| String::from_utf8_lossy(w)).collect();
+    /* stands in for the duration; an option left without its value takes it */
+    view.push(Cow::Borrowed("0"));
+    let scanned = scan_slice(&view).map_err(|e| fail(e.message))?;
+    if scanned.duration.is_none() && scanned.command.is_some() {
+        return Err(fail("'--' belongs on the command line".to_string()));
+    }
+    match scanned.operand_index.first() {
+        Some(&i) if i < words.len() => Err(fail(format!(
+            "'{}' isn't an option (the duration and command go on the command line)",
+            view[i]
+        ))),
+        Some(_) => Ok(words),
+        None => Err(fail(format!(
+            "'{}' is missing its value",
+            view[words.len() - 1]
+        ))),
+    }
+}
+
+/*
+ * TIMEOUT_<OPTION>: every option in the --help tables can come from a
+ * variable named for its long form, --mem-limit from TIMEOUT_MEM_LIMIT. one
+ * rule for all of them: the command line (TIMEOUT_OPTS and @files
+ * included) > TIMEOUT_<OPTION> > the default. a variable only fills in an
+ * option that isn't given at all - TIMEOUT_RETRY_DELAY doesn't add to a
+ * --retry-delay, it's ignored.
+ *
+ * a flag's variable is 1, true or yes to set it, 0, false or no to leave
+ * it off. an option whose value is optional ([=SIG]) takes yes or no, or
+ * its value. an empty variable is an unset one.
+ *
+ * none for -h and -V, -t (that's TIMEOUT) or --sleep and --wait-pid,
+ * which change what the operands are. none either for the options that
+ * name something a run holds or writes: the variables are inherited, so a
+ * procguard the command runs would take the same lock, pidfile or fd as
+ * the one running it. the variables older than these (LEGACY_ENV) keep
+ * their own handling in parse_args_as, by the same rule.
+ */
+const NO_ENV: [&str; 11] = [
+    "help",
+    "version",
+    "duration",
+    "sleep",
+    "wait-pid",
+    "flock",
+    "pidfile",
+    "result-file",
+    "json-fd",
+    "also-kill",
+    "temp-dir",
+];
+const LEGACY_ENV: [&str; 7] = [
+    "signal",
+    "kill-after",
+    "wait-for-file",
+    "wait-for-file-timeout",
+    "retry",
+    "heartbeat",
+    "stdin-timeout",
+];
+
+/// The variable `--long` falls back to: `TIMEOUT_MEM_LIMIT` for `mem-limit`.
+#[must_use]
+pub fn env_var_name(long: &str) -> String {
+    format!("TIMEOUT_{}", long.to_ascii_uppercase().replace('-', "_"))
+}
+
+/* the options a TIMEOUT_<OPTION> variable can give */
+fn env_fallback_options() -> impl Iterator<Item = &'static OptHelp> {
+    GNU_OPTIONS
+        .iter()
+        .chain(EXTENSION_OPTIONS)
+        .filter(|o| !NO_ENV.contains(&o.long) && !LEGACY_ENV.contains(&o.long))
+}
+
+/* the long names of the options args gives, up to the command */
+fn given_options<S: AsRef<str>>(args: &[S]) -> Result<Vec<&'static str>, ParseError> {
+    let parsed = parse_from_slice(args)?;
+    let end = parsed
+        .command
+        .as_ref()
+        .and_then(|_| {
+            let at = usize::from(parsed.past_duration());
+            parsed.operand_index.get(at).copied()
+        })
+        .unwrap_or(args.len());
+    let table = || GNU_OPTIONS.iter().chain(EXTENSION_OPTIONS);
+    let mut given = Vec::new();
+    let mut i = 0;
+    while i < end {
+        let arg = args[i].as_ref();
+        i += 1;
+        if arg == "--" {
+            break;
+        }
+        if parsed.operand_index.contains(&(i - 1)) {
+            continue;
+        }
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, attached) = long
+                .split_once('=')
+                .map_or((long, false), |(n, _)| (n, true));
+            if let Some(opt) = table().find(|o| o.long == name) {
+                given.push(opt.long);
+                if opt.value.starts_with(' ') && !attached {
+                    i += 1; /* its value */
+                }
+            }
+            continue;
+        }
+        /* a cluster, -vq or -k5: up to the first option that takes a value */
+        for (at, c) in arg.char_indices().skip(1) {
+            let Some(opt) = table().find(|o| o.short == Some(c)) else {
+                continue;
+            };
+            given.push(opt.long);
+            if opt.value.starts_with(' ') {
+                if at + c.len_utf8() == arg.len() {
+                    i += 1;
+                }
+                break;
+            }
+        }
+    }
+    Ok(given)
+}
+
+/* TIMEOUT_<OPTION>'s value as the option it stands for, None to leave
+ * the option off */
+fn env_option(opt: &OptHelp, var: &str, value: &str) -> Result<Option<String>, ParseError> {
+    let bare = || Ok(Some(format!("--{}", opt.long)));
+    match (opt.value, value) {
+        ("", "1" | "true" | "yes") => bare(),
+        ("", "0" | "false" | "no") => Ok(None),
+        ("", _) => Err(ParseError {
+            message: format!("{var}: '{value}' isn't 1 or 0 (or true/false, yes/no)"),
+        }),
+        (v, "true" | "yes") if v.starts_with('[') => bare(),
+        (v, "false" | "no") if v.starts_with('[') => Ok(None),
+        _ => Ok(Some(format!("--{}={}", opt.long, value))),
+    }
+}
+
+/*
+ * the options the TIMEOUT_<OPTION> variables fill in around args, with the
+ * variable each came from. lookup reads a variable. each is checked on its
+ * own here, so a bad value names its variable.
+ *
+ * the variables are inherited, so one set for a procguard running a command
+ * reaches any procguard that command runs too. --sleep and --wait-pid refuse
+ * most options; an inherited TIMEOUT_JSON=1 mustn't turn a nested
+ * `procguard --sleep 1s` into a usage error, so with either of them a
+ * variable for an option they can't take is left out
+ */
+fn env_fallbacks<S: AsRef<str>>(
+    args: &[S],
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<(String, String)>, ParseError> {
+    let given = given_options(args)?;
+    let standalone = parse_from_slice(args).is_ok_and(|a| a.sleep || a.wait_pid.is_some());
+    let fits = |option: &str| {
+        let trial: Vec<&str> = core::iter::once(option)
+            .chain(args.iter().map(AsRef::as_ref))
+            .collect();
+        parse_from_slice(&trial).is_ok()
+    };
+    let mut fallbacks = Vec::new();
+    for opt in env_fallback_options().filter(|o| !given.contains(&o.long)) {
+        let var = env_var_name(opt.long);
+        let Some(value) = lookup(&var).filter(|v| !v.is_empty()) else {
+            continue;
+        };
+        let Some(option) = env_option(opt, &var, &value)? else {
+            continue;
+        };
+        /* "0" stands in for the duration, as in env_options */
+        scan_slice(&[option.as_str(), "0"]).map_err(|e| ParseError {
+            message: format!("{var}: {}", e.message),
+        })?;
+        if standalone && !fits(&option) {
+            continue;
+        }
+        fallbacks.push((var, option));
+    }
+    Ok(fallbacks)
+}
+
+/*
+ * gtimeout: the options GNU timeout takes ("+k:s:fpv" and their long
+ * forms), with getopt's messages word for word. stops at the first operand,
+ * like GNU's. --help and --version print GNU-style and exit here; what's
+ * left goes through parse_from_slice, which takes every one of these.
+ * long options must be spelled out - getopt's abbreviations aren't.
+ */
+fn check_gnu_options<S: AsRef<str>>(args: &[S], name: &str) -> Result<(), ParseError> {
+    let fail = |message| Err(ParseError { message });
+    let mut i = 0;
+    while let Some(arg) = args.get(i).map(AsRef::as_ref) {
+        i += 1;
+        if arg == "--" || arg == "-" || !arg.starts_with('-') {
+            return Ok(());
+        }
+        if let Some(long) = arg.strip_prefix("--") {
+            let (opt, attached) = long
+                .split_once('=')
+                .map_or((long, false), |(o, _)| (o, true));
+            match opt {
+                "help" | "version" if !attached => {
+                    if opt == "help" {
+                        print_gnu_help(name);
+                    } else {
+                        print_gnu_version(name);
+                    }
+                    // SAFETY: exit is always safe
+                    unsafe { libc::exit(0) };
+                }
+                "signal" | "kill-after" if !attached => {
+                    if args.get(i).is_none() {
+                        return fail(format!("option '--{opt}' requires an argument"));
+                    }
+                    i += 1;
+                }
+                "signal" | "kill-after" => {}
+                "preserve-status" | "foreground" | "verbose" | "help" | "version" => {
+                    if attached {
+                        return fail(format!("option '--{opt}' doesn't allow an argument"));
+                    }
+                }
+                _ => return fail(format!("unrecognized option '{arg}'")),
+            }
+            continue;
+        }
+        /* clustered: -pv, -sKILL, -vk 5 */
+        for (at, c) in arg.char_indices().skip(1) {
+            match c {
+                'p' | 'f' | 'v' => {}
+                's' | 'k' => {
+                    if at + 1 == arg.len() {
+                        if args.get(i).is_none() {
+                            return fail(format!("option requires an argument -- '{c}'"));
+                        }
+                        i += 1;
+                    }
+                    break;
+                }
+                _ => return fail(format!("invalid option -- '{c}'")),
+            }
+        }
+    }
+    Ok(())
+}
+
+/* GNU timeout's --help, with the program name we were invoked as */
+fn print_gnu_help(name: &str) {
+    let mut out = crate::io::BufWriter::stdout();
+    let _ = write!(
+        out,
+        r#"Usage: {name} [OPTION] DURATION COMMAND [ARG]...
+  or:  {name} [OPTION]
+Start COMMAND, and kill it if still running after DURATION.
+
+Mandatory arguments to long options are mandatory for short options too.
+      --preserve-status
+                 exit with the same status as COMMAND, even when the
+                   command times out
+      --foreground
+                 when not running timeout directly from a shell prompt,
+                   allow COMMAND to read from the TTY and get TTY signals;
+                   in this mode, children of COMMAND will not be timed out
+  -k, --kill-after=DURATION
+                 also send a KILL signal if COMMAND is still running
+                   this long after the initial signal was sent
+  -s, --signal=SIGNAL
+                 specify the signal to be sent on timeout;
+                   SIGNAL may be a name like 'HUP' or a number;
+                   see 'kill -l' for a list of signals
+  -v, --verbose  diagnose to stderr any signal sent upon timeout
+      --help        display this help and exit
+      --version     output version information and exit
+
+DURATION is a floating point number with an optional suffix:
+'s' for seconds (the default), 'm' for minutes, 'h' for hours or 'd' for days.
+A duration of 0 disables the associated timeout.
+
+Upon timeout, send the TERM signal to COMMAND, if no other SIGNAL specified.
+The TERM signal kills any process that does not block or catch that signal.
+It may be necessary to use the KILL signal, since this signal can't be caught.
+
+EXIT status:
+  124  if COMMAND times out, and --preserve-status is not specified
+  125  if the timeout command itself fails
+  126  if COMMAND is found but cannot be invoked
+  127  if COMMAND cannot be found
+  137  if COMMAND (or timeout itself) is sent the KILL (9) signal (128+9)
+  -    the exit status of COMMAND otherwise
+
+This is procguard in GNU mode; run it as 'procguard' for its extensions.
+"#
+    );
+}
+
+/* GNU's first line is "NAME (PACKAGE) VERSION"; scrapers read that */
+fn print_gnu_version(name: &str) {
+    let mut out = crate::io::BufWriter::stdout();
+    let _ = writeln!(
+        out,
+        "{name} (procguard) {}\nLicense: MIT <https://opensource.org/licenses/MIT>",
+        env!("CARGO_PKG_VERSION")
+    );
+}
+
+/*
+ * replace each @file in the duration/command slot with the arguments in
+ * the file (see response_file.rs), until none is left. a file may name
+ * another; naming one already expanded is a cycle. MAX_FILES expansions
+ * and MAX_BYTES of file contents at most.
+ *
+ * each round parses the whole line to find the next @file, so options
+ * the file adds are seen before its own @ operands are. -h/-V before an
+ * @file still print and exit right away.
+ */
+fn expand_response_files(raw: &mut Vec<Cow<'static, [u8]>>) -> Result<(), ParseError> {
+    use crate::response_file::{MAX_BYTES, MAX_FILES, split};
+
+    let mut expanded: Vec<Vec<u8>> = Vec::new();
+    let mut budget = MAX_BYTES;
+    loop {
+        let at = {
+            let args: Vec<Cow<'_, str>> = raw.iter().map(|a| String::from_utf8_lossy(a)).collect();
+            match parse_from_slice(args.get(1..).unwrap_or_default())?.response_file {
+                Some(i) => i + 1,
+                None => return Ok(()),
+            }
+        };
+        let path = raw[at][1..].to_vec();
+        let name = String::from_utf8_lossy(&path).into_owned();
+        if expanded.contains(&path) {
+            return Err(ParseError {
+                message: format!("response file '{}' includes itself", name),
+            });
+        }
+        if expanded.len() >= MAX_FILES {
+            return Err(ParseError {
+                message: format!("too many nested response files (max {})", MAX_FILES),
+            });
+        }
+
+        let contents = crate::io::read_file(&path, budget).map_err(|errno| ParseError {
+            message: if errno == libc::EFBIG {
+                format!(
+                    "response file '{}' too large (max {} MiB in all)",
+                    name,
+                    MAX_BYTES >> 20
+                )
+            } else {
+                format!("can't read response file '{}': errno {}", name, errno)
+            },
+        })?;
+        budget -= contents.len();
+        let words = split(&contents).map_err(|e| ParseError {
+            message: format!("response file '{}': {}", name, e),
+        })?;
+        raw.splice(at..=at, words.into_iter().map(Cow::Owned));
+        expanded.push(path);
+    }
+}
+
+/// parse from slice (for testing and internal use)
+pub fn parse_from_slice<'a, S: AsRef<str>>(args: &'a [S]) -> Result<Args<'a>, ParseError> {
+    let result = scan_slice(args)?;
+    check_conflicts(&result)?;
+    Ok(result)
+}
+
+/* the options and operands as given, not yet checked against each other */
+fn scan_slice<'a, S: AsRef<str>>(args: &'a [S]) -> Result<Args<'a>, ParseError> {
+    let mut result = Args {
+        signal: ArgValue::Borrowed(""), // will apply env fallback later
+        on_timeout_limit: ArgValue::Borrowed("5s"),
+        ..Default::default()
     };
 
     let mut i = 0;
     let mut saw_separator = false;
 
     while i < args.len() {
-        let arg = &args[i];
+        let arg = args[i].as_ref();
 
         /* after --, everything is command + args */
         if saw_separator {
@@ -304,6 +1757,7 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
             } else {
                 result.args.push(ArgValue::Borrowed(arg));
             }
+            result.operand_index.push(i);
             i += 1;
             continue;
         }
@@ -318,39 +1772,75 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
         /* once command is set, all remaining args go to the command */
         if result.command.is_some() {
             result.args.push(ArgValue::Borrowed(arg));
+            result.operand_index.push(i);
+            i += 1;
+            continue;
+        }
+
+        /* past the duration a short option is the command's, as the
+         * cluster arm below decides: TIMEOUT=5s procguard sh -c CMD */
+        if result.past_duration() && arg.len() > 1 && arg.starts_with('-') && !arg.starts_with("--")
+        {
+            result.push_positional(arg, i);
             i += 1;
             continue;
         }
 
-        match arg.as_str() {
+        match arg {
             "--help" | "-h" => {
                 print_help();
                 // SAFETY: exit is always safe
                 unsafe { libc::exit(0) };
             }
             "--version" | "-V" => {
-                print_version();
+                print_version(
+                    result.verbose || wants_verbose(&args[i + 1..]),
+                    result.json || wants_json(&args[i + 1..]),
+                );
                 // SAFETY: exit is always safe
                 unsafe { libc::exit(0) };
             }
             "--json" => result.json = true,
+            /* a destination for the JSON implies --json */
+            "--json-fd" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--json-fd requires a file descriptor number".to_string(),
+                })?;
+                result.json_fd = Some(parse_json_fd(val)?);
+                result.json = true;
+            }
+            s if s.starts_with("--json-fd=") => {
+                result.json_fd = Some(parse_json_fd(&s[10..])?);
+                result.json = true;
+            }
+            "--json-stderr" => {
+                result.json_fd = Some(2);
+                result.json = true;
+            }
             "-p" | "--preserve-status" => result.preserve_status = true,
             "-f" | "--foreground" => result.foreground = true,
             "-v" | "--verbose" => result.verbose = true,
-            "-q" | "--quiet" => result.quiet = true,
+            "-q" | "--quiet" => result.quiet = result.quiet.saturating_add(1),
 
             /* options with values: -s SIGNAL or --signal=SIGNAL */
             "-s" => {
                 i += 1;
-                result.signal = ArgValue::Borrowed(args.get(i).ok_or_else(|| ParseError {
-                    message: "-s requires a signal name or number".to_string(),
-                })?);
+                result.signal =
+                    ArgValue::Borrowed(args.get(i).map(AsRef::as_ref).ok_or_else(|| {
+                        ParseError {
+                            message: "-s requires a signal name or number".to_string(),
+                        }
+                    })?);
             }
             "--signal" => {
                 i += 1;
-                result.signal = ArgValue::Borrowed(args.get(i).ok_or_else(|| ParseError {
-                    message: "--signal requires a value".to_string(),
-                })?);
+                result.signal =
+                    ArgValue::Borrowed(args.get(i).map(AsRef::as_ref).ok_or_else(|| {
+                        ParseError {
+                            message: "--signal requires a value".to_string(),
+                        }
+                    })?);
             }
             s if s.starts_with("--signal=") => {
                 result.signal = ArgValue::Borrowed(&s[9..]);
@@ -358,19 +1848,31 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
 
             "-k" => {
                 i += 1;
-                result.kill_after = Some(ArgValue::Borrowed(args.get(i).ok_or_else(|| {
-                    ParseError {
+                result.kill_after = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
                         message: "-k requires a duration".to_string(),
-                    }
-                })?));
+                    })?,
+                ));
+            }
+            "--duration" => {
+                i += 1;
+                result.set_duration(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--duration requires a value".to_string(),
+                    })?,
+                ))?;
+            }
+            s if s.starts_with("--duration=") => {
+                result.set_duration(ArgValue::Borrowed(&s[11..]))?;
             }
+
             "--kill-after" => {
                 i += 1;
-                result.kill_after = Some(ArgValue::Borrowed(args.get(i).ok_or_else(|| {
-                    ParseError {
+                result.kill_after = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
                         message: "--kill-after requires a value".to_string(),
-                    }
-                })?));
+                    })?,
+                ));
             }
             s if s.starts_with("--kill-after=") => {
                 result.kill_after = Some(ArgValue::Borrowed(&s[13..]));
@@ -378,7 +1880,7 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
 
             "--timeout-exit-code" => {
                 i += 1;
-                let val = args.get(i).ok_or_else(|| ParseError {
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
                     message: "--timeout-exit-code requires a value".to_string(),
                 })?;
                 result.timeout_exit_code = Some(val.parse().map_err(|_| ParseError {
@@ -391,80 +1893,410 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
                     message: format!("invalid exit code: '{val}' (must be 0-255)"),
                 })?);
             }
+            "--no-warn-exit-code" => result.no_warn_exit_code = true,
+            "--strict" => result.strict = true,
+            "--kill-self-on-timeout" => {
+                result.kill_self_on_timeout = Some(ArgValue::Borrowed("ALRM"));
+            }
+            s if s.starts_with("--kill-self-on-timeout=") => {
+                result.kill_self_on_timeout = Some(ArgValue::Borrowed(&s[23..]));
+            }
+            "--launchd-mode" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--launchd-mode requires a value (stop or relaunch)".to_string(),
+                })?;
+                result.launchd_mode = Some(parse_launchd_mode(val)?);
+            }
+            s if s.starts_with("--launchd-mode=") => {
+                result.launchd_mode = Some(parse_launchd_mode(&s[15..])?);
+            }
 
             "--on-timeout" => {
                 i += 1;
-                result.on_timeout = Some(ArgValue::Borrowed(args.get(i).ok_or_else(|| {
-                    ParseError {
+                result.on_timeout = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
                         message: "--on-timeout requires a command".to_string(),
-                    }
-                })?));
+                    })?,
+                ));
             }
             s if s.starts_with("--on-timeout=") => {
                 result.on_timeout = Some(ArgValue::Borrowed(&s[13..]));
             }
 
+            "--on-limit" => {
+                i += 1;
+                result.on_limit = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--on-limit requires a command".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--on-limit=") => {
+                result.on_limit = Some(ArgValue::Borrowed(&s[11..]));
+            }
+
             "--on-timeout-limit" => {
                 i += 1;
                 result.on_timeout_limit =
-                    ArgValue::Borrowed(args.get(i).ok_or_else(|| ParseError {
-                        message: "--on-timeout-limit requires a duration".to_string(),
+                    ArgValue::Borrowed(args.get(i).map(AsRef::as_ref).ok_or_else(|| {
+                        ParseError {
+                            message: "--on-timeout-limit requires a duration".to_string(),
+                        }
                     })?);
             }
             s if s.starts_with("--on-timeout-limit=") => {
                 result.on_timeout_limit = ArgValue::Borrowed(&s[19..]);
             }
+            "--hook-budget" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--hook-budget requires a value (separate or shared)".to_string(),
+                })?;
+                result.hook_budget = parse_hook_budget(val)?;
+            }
+            s if s.starts_with("--hook-budget=") => {
+                result.hook_budget = parse_hook_budget(&s[14..])?;
+            }
+            "--hook-async" => {
+                result.hook_async = true;
+            }
+            "--verify-signal-delivery" => result.verify_signal_delivery = true,
+            "--on-stopped" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--on-stopped requires a value (continue or kill)".to_string(),
+                })?;
+                result.on_stopped = Some(parse_stopped_policy(val)?);
+            }
+            s if s.starts_with("--on-stopped=") => {
+                result.on_stopped = Some(parse_stopped_policy(&s[13..])?);
+            }
+            "--debugger-grace" => {
+                i += 1;
+                result.debugger_grace = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--debugger-grace requires a duration".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--debugger-grace=") => {
+                result.debugger_grace = Some(ArgValue::Borrowed(&s[17..]));
+            }
+
+            "--crash-hook" => {
+                i += 1;
+                result.crash_hook = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--crash-hook requires a command".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--crash-hook=") => {
+                result.crash_hook = Some(ArgValue::Borrowed(&s[13..]));
+            }
 
             "--confine" | "-c" => {
                 i += 1;
-                let val = args.get(i).ok_or_else(|| ParseError {
-                    message: "--confine requires a value (wall or active)".to_string(),
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--confine requires a value (wall, active or hybrid)".to_string(),
                 })?;
                 result.confine = Confine::from_str(val).ok_or_else(|| ParseError {
-                    message: format!("invalid confine mode: '{}' (use 'wall' or 'active')", val),
+                    message: format!(
+                        "invalid confine mode: '{}' (use 'wall', 'active' or 'hybrid')",
+                        val
+                    ),
                 })?;
                 result.confine_specified = true;
             }
             s if s.starts_with("--confine=") => {
                 let val = &s[10..];
                 result.confine = Confine::from_str(val).ok_or_else(|| ParseError {
-                    message: format!("invalid confine mode: '{}' (use 'wall' or 'active')", val),
+                    message: format!(
+                        "invalid confine mode: '{}' (use 'wall', 'active' or 'hybrid')",
+                        val
+                    ),
                 })?;
                 result.confine_specified = true;
             }
 
+            "--active-min" => {
+                i += 1;
+                result.active_min = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--active-min requires a duration".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--active-min=") => {
+                result.active_min = Some(ArgValue::Borrowed(&s[13..]));
+            }
+
+            "--on-sleep" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--on-sleep requires a value (continue, pause or fail)".to_string(),
+                })?;
+                result.on_sleep = parse_sleep_policy(val)?;
+            }
+            s if s.starts_with("--on-sleep=") => {
+                result.on_sleep = parse_sleep_policy(&s[11..])?;
+            }
+
+            "--on-child-stop" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--on-child-stop requires a value (continue, pause or fail)"
+                        .to_string(),
+                })?;
+                result.on_child_stop = Some(parse_child_stop_policy(val)?);
+            }
+            s if s.starts_with("--on-child-stop=") => {
+                result.on_child_stop = Some(parse_child_stop_policy(&s[16..])?);
+            }
+
+            "--tag" | "--prefix" => {
+                i += 1;
+                result.tag = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--tag requires a value".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--tag=") => {
+                result.tag = Some(ArgValue::Borrowed(&s[6..]));
+            }
+            s if s.starts_with("--prefix=") => {
+                result.tag = Some(ArgValue::Borrowed(&s[9..]));
+            }
+            "--run-id" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--run-id requires a value".to_string(),
+                })?;
+                result.run_id = Some(ArgValue::Borrowed(parse_run_id(val)?));
+            }
+            s if s.starts_with("--run-id=") => {
+                result.run_id = Some(ArgValue::Borrowed(parse_run_id(&s[9..])?));
+            }
+
+            "--tty-foreground" => result.tty_foreground = true,
+            "--restore-tty" => result.restore_tty = true,
+            "--pgroup" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--pgroup requires a value (inherit, new or session)".to_string(),
+                })?;
+                result.pgroup = Some(parse_pgroup(val)?);
+            }
+            s if s.starts_with("--pgroup=") => {
+                result.pgroup = Some(parse_pgroup(&s[9..])?);
+            }
+            "--arch" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--arch requires a value (arm64 or x86_64)".to_string(),
+                })?;
+                result.arch = Some(parse_arch(val)?);
+            }
+            s if s.starts_with("--arch=") => {
+                result.arch = Some(parse_arch(&s[7..])?);
+            }
+            "--monitor-qos" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--monitor-qos requires a value (e.g. user-interactive)".to_string(),
+                })?;
+                result.monitor_qos = Some(parse_monitor_qos(val)?);
+            }
+            s if s.starts_with("--monitor-qos=") => {
+                result.monitor_qos = Some(parse_monitor_qos(&s[14..])?);
+            }
+
             "--wait-for-file" => {
                 i += 1;
-                result.wait_for_file = Some(ArgValue::Borrowed(args.get(i).ok_or_else(|| {
-                    ParseError {
+                result.wait_for_file = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
                         message: "--wait-for-file requires a path".to_string(),
-                    }
-                })?));
+                    })?,
+                ));
             }
             s if s.starts_with("--wait-for-file=") => {
                 result.wait_for_file = Some(ArgValue::Borrowed(&s[16..]));
             }
 
+            "--wait-for-file-gone" => {
+                i += 1;
+                result.wait_for_file_gone = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--wait-for-file-gone requires a path".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--wait-for-file-gone=") => {
+                result.wait_for_file_gone = Some(ArgValue::Borrowed(&s[21..]));
+            }
+
+            "--wait-for-file-contains" => {
+                i += 1;
+                result.wait_for_file_contains = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--wait-for-file-contains requires a string".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--wait-for-file-contains=") => {
+                result.wait_for_file_contains = Some(ArgValue::Borrowed(&s[25..]));
+            }
+
             "--wait-for-file-timeout" => {
                 i += 1;
-                result.wait_for_file_timeout =
-                    Some(ArgValue::Borrowed(args.get(i).ok_or_else(|| {
-                        ParseError {
-                            message: "--wait-for-file-timeout requires a duration".to_string(),
-                        }
-                    })?));
+                result.wait_for_file_timeout = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--wait-for-file-timeout requires a duration".to_string(),
+                    })?,
+                ));
             }
             s if s.starts_with("--wait-for-file-timeout=") => {
                 result.wait_for_file_timeout = Some(ArgValue::Borrowed(&s[24..]));
             }
+            "--flock" => {
+                i += 1;
+                result.flock = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--flock requires a path".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--flock=") => {
+                result.flock = Some(ArgValue::Borrowed(&s[8..]));
+            }
+            "--flock-timeout" => {
+                i += 1;
+                result.flock_timeout = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--flock-timeout requires a duration".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--flock-timeout=") => {
+                result.flock_timeout = Some(ArgValue::Borrowed(&s[16..]));
+            }
+            "--flock-queue" => result.flock_queue = Some(ArgValue::Borrowed("")),
+            s if s.starts_with("--flock-queue=") => {
+                result.flock_queue = Some(ArgValue::Borrowed(&s[14..]));
+            }
+            "--only-between" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--only-between requires a time window (HH:MM-HH:MM)".to_string(),
+                })?;
+                result.only_between = Some(parse_window(val)?);
+            }
+            s if s.starts_with("--only-between=") => {
+                result.only_between = Some(parse_window(&s[15..])?);
+            }
+            "--window-wait" => {
+                i += 1;
+                result.window_wait = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--window-wait requires a duration".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--window-wait=") => {
+                result.window_wait = Some(ArgValue::Borrowed(&s[14..]));
+            }
+            "--window-stop" => result.window_stop = true,
+            "--every" => {
+                i += 1;
+                result.every = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--every requires a duration".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--every=") => {
+                result.every = Some(ArgValue::Borrowed(&s[8..]));
+            }
+            "--for" => {
+                i += 1;
+                result.repeat_for = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--for requires a duration".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--for=") => {
+                result.repeat_for = Some(ArgValue::Borrowed(&s[6..]));
+            }
+            "--stop-on" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--stop-on requires a policy (never, timeout, failure)".to_string(),
+                })?;
+                result.stop_on = Some(parse_stop_on(val)?);
+            }
+            s if s.starts_with("--stop-on=") => {
+                result.stop_on = Some(parse_stop_on(&s[10..])?);
+            }
+            "--detect-flaky" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--detect-flaky requires a count".to_string(),
+                })?;
+                result.detect_flaky = Some(parse_run_count("--detect-flaky", val)?);
+            }
+            s if s.starts_with("--detect-flaky=") => {
+                result.detect_flaky = Some(parse_run_count("--detect-flaky", &s[15..])?);
+            }
+            "--flaky-exit" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--flaky-exit requires a policy (pass, fail or an exit code)"
+                        .to_string(),
+                })?;
+                result.flaky_exit = Some(parse_flaky_exit(val)?);
+            }
+            s if s.starts_with("--flaky-exit=") => {
+                result.flaky_exit = Some(parse_flaky_exit(&s[13..])?);
+            }
+            "--runs" | "--max-runs" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: format!("{} requires a count", arg),
+                })?;
+                let n = parse_run_count(arg, val)?;
+                if arg == "--runs" {
+                    result.runs = Some(n);
+                } else {
+                    result.max_runs = Some(n);
+                }
+            }
+            s if s.starts_with("--runs=") => {
+                result.runs = Some(parse_run_count("--runs", &s[7..])?);
+            }
+            s if s.starts_with("--max-runs=") => {
+                result.max_runs = Some(parse_run_count("--max-runs", &s[11..])?);
+            }
+            "--flaky-threshold" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--flaky-threshold requires a percentage".to_string(),
+                })?;
+                result.flaky_threshold = Some(parse_flaky_threshold(val)?);
+            }
+            s if s.starts_with("--flaky-threshold=") => {
+                result.flaky_threshold = Some(parse_flaky_threshold(&s[18..])?);
+            }
 
             "--retry" => {
                 i += 1;
-                result.retry = Some(ArgValue::Borrowed(args.get(i).ok_or_else(|| {
-                    ParseError {
+                result.retry = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
                         message: "--retry requires a count".to_string(),
-                    }
-                })?));
+                    })?,
+                ));
             }
             s if s.starts_with("--retry=") => {
                 result.retry = Some(ArgValue::Borrowed(&s[8..]));
@@ -472,11 +2304,11 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
 
             "--retry-delay" => {
                 i += 1;
-                result.retry_delay = Some(ArgValue::Borrowed(args.get(i).ok_or_else(|| {
-                    ParseError {
+                result.retry_delay = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
                         message: "--retry-delay requires a duration".to_string(),
-                    }
-                })?));
+                    })?,
+                ));
             }
             s if s.starts_with("--retry-delay=") => {
                 result.retry_delay = Some(ArgValue::Borrowed(&s[14..]));
@@ -484,31 +2316,53 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
 
             "--retry-backoff" => {
                 i += 1;
-                result.retry_backoff = Some(ArgValue::Borrowed(args.get(i).ok_or_else(|| {
-                    ParseError {
+                result.retry_backoff = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
                         message: "--retry-backoff requires a multiplier (e.g., 2x)".to_string(),
-                    }
-                })?));
+                    })?,
+                ));
             }
             s if s.starts_with("--retry-backoff=") => {
                 result.retry_backoff = Some(ArgValue::Borrowed(&s[16..]));
             }
 
+            "--spawn-retry" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--spawn-retry requires a count".to_string(),
+                })?;
+                result.spawn_retry = Some(parse_spawn_retry(val)?);
+            }
+            s if s.starts_with("--spawn-retry=") => {
+                result.spawn_retry = Some(parse_spawn_retry(&s[14..])?);
+            }
+            "--spawn-retry-delay" => {
+                i += 1;
+                result.spawn_retry_delay = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--spawn-retry-delay requires a duration".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--spawn-retry-delay=") => {
+                result.spawn_retry_delay = Some(ArgValue::Borrowed(&s[20..]));
+            }
+
             "-H" => {
                 i += 1;
-                result.heartbeat = Some(ArgValue::Borrowed(args.get(i).ok_or_else(|| {
-                    ParseError {
+                result.heartbeat = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
                         message: "-H requires a duration".to_string(),
-                    }
-                })?));
+                    })?,
+                ));
             }
             "--heartbeat" => {
                 i += 1;
-                result.heartbeat = Some(ArgValue::Borrowed(args.get(i).ok_or_else(|| {
-                    ParseError {
+                result.heartbeat = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
                         message: "--heartbeat requires a duration".to_string(),
-                    }
-                })?));
+                    })?,
+                ));
             }
             s if s.starts_with("--heartbeat=") => {
                 result.heartbeat = Some(ArgValue::Borrowed(&s[12..]));
@@ -516,19 +2370,19 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
 
             "-S" => {
                 i += 1;
-                result.stdin_timeout = Some(ArgValue::Borrowed(args.get(i).ok_or_else(|| {
-                    ParseError {
+                result.stdin_timeout = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
                         message: "-S requires a duration".to_string(),
-                    }
-                })?));
+                    })?,
+                ));
             }
             "--stdin-timeout" => {
                 i += 1;
-                result.stdin_timeout = Some(ArgValue::Borrowed(args.get(i).ok_or_else(|| {
-                    ParseError {
+                result.stdin_timeout = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
                         message: "--stdin-timeout requires a duration".to_string(),
-                    }
-                })?));
+                    })?,
+                ));
             }
             s if s.starts_with("--stdin-timeout=") => {
                 result.stdin_timeout = Some(ArgValue::Borrowed(&s[16..]));
@@ -536,11 +2390,11 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
 
             "--mem-limit" => {
                 i += 1;
-                result.mem_limit = Some(ArgValue::Borrowed(args.get(i).ok_or_else(|| {
-                    ParseError {
+                result.mem_limit = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
                         message: "--mem-limit requires a value".to_string(),
-                    }
-                })?));
+                    })?,
+                ));
             }
             s if s.starts_with("--mem-limit=") => {
                 result.mem_limit = Some(ArgValue::Borrowed(&s[12..]));
@@ -548,11 +2402,11 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
 
             "--cpu-time" => {
                 i += 1;
-                result.cpu_time = Some(ArgValue::Borrowed(args.get(i).ok_or_else(|| {
-                    ParseError {
+                result.cpu_time = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
                         message: "--cpu-time requires a duration".to_string(),
-                    }
-                })?));
+                    })?,
+                ));
             }
             s if s.starts_with("--cpu-time=") => {
                 result.cpu_time = Some(ArgValue::Borrowed(&s[11..]));
@@ -560,18 +2414,317 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
 
             "--cpu-percent" => {
                 i += 1;
-                result.cpu_percent = Some(ArgValue::Borrowed(args.get(i).ok_or_else(|| {
-                    ParseError {
+                result.cpu_percent = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
                         message: "--cpu-percent requires a value".to_string(),
-                    }
-                })?));
+                    })?,
+                ));
             }
             s if s.starts_with("--cpu-percent=") => {
                 result.cpu_percent = Some(ArgValue::Borrowed(&s[14..]));
             }
 
-            "--stdin-passthrough" => {
-                result.stdin_passthrough = true;
+            "--cpu-throttle-window" => {
+                i += 1;
+                result.cpu_throttle_window = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--cpu-throttle-window requires a duration".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--cpu-throttle-window=") => {
+                result.cpu_throttle_window = Some(ArgValue::Borrowed(&s[22..]));
+            }
+
+            "--cpu-throttle-gain" => {
+                i += 1;
+                result.cpu_throttle_gain = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--cpu-throttle-gain requires a value".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--cpu-throttle-gain=") => {
+                result.cpu_throttle_gain = Some(ArgValue::Borrowed(&s[20..]));
+            }
+
+            "--cpu-throttle-quantum" => {
+                i += 1;
+                result.cpu_throttle_quantum = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--cpu-throttle-quantum requires a duration".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--cpu-throttle-quantum=") => {
+                result.cpu_throttle_quantum = Some(ArgValue::Borrowed(&s[23..]));
+            }
+
+            "--cpu-throttle-notify" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--cpu-throttle-notify requires a value (env, signal or both)"
+                        .to_string(),
+                })?;
+                result.cpu_throttle_notify = Some(parse_throttle_notify(val)?);
+            }
+            s if s.starts_with("--cpu-throttle-notify=") => {
+                result.cpu_throttle_notify = Some(parse_throttle_notify(&s[22..])?);
+            }
+
+            "--poll-interval" => {
+                i += 1;
+                result.poll_interval = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--poll-interval requires a duration".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--poll-interval=") => {
+                result.poll_interval = Some(ArgValue::Borrowed(&s[16..]));
+            }
+
+            "--mem-poll-interval" => {
+                i += 1;
+                result.mem_poll_interval = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--mem-poll-interval requires a duration".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--mem-poll-interval=") => {
+                result.mem_poll_interval = Some(ArgValue::Borrowed(&s[20..]));
+            }
+
+            "--cpu-poll-interval" => {
+                i += 1;
+                result.cpu_poll_interval = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--cpu-poll-interval requires a duration".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--cpu-poll-interval=") => {
+                result.cpu_poll_interval = Some(ArgValue::Borrowed(&s[20..]));
+            }
+
+            "--stdin-poll-interval" => {
+                i += 1;
+                result.stdin_poll_interval = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--stdin-poll-interval requires a duration".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--stdin-poll-interval=") => {
+                result.stdin_poll_interval = Some(ArgValue::Borrowed(&s[22..]));
+            }
+
+            "--stdin" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--stdin requires a value (null, close or file:PATH)".to_string(),
+                })?;
+                result.child_stdin = parse_child_stdin(val)?;
+            }
+            s if s.starts_with("--stdin=") => {
+                result.child_stdin = parse_child_stdin(&s[8..])?;
+            }
+            "--stdin-passthrough" => {
+                result.stdin_mode = StdinMode::Watch;
+            }
+            "--stdin-mode" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--stdin-mode requires a value (consume, watch or pty)".to_string(),
+                })?;
+                result.stdin_mode = parse_stdin_mode(val)?;
+            }
+            s if s.starts_with("--stdin-mode=") => {
+                result.stdin_mode = parse_stdin_mode(&s[13..])?;
+            }
+            "--stdin-inject-on-timeout" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--stdin-inject-on-timeout requires a value".to_string(),
+                })?;
+                result.stdin_inject = Some(parse_inject(val)?);
+            }
+            s if s.starts_with("--stdin-inject-on-timeout=") => {
+                result.stdin_inject = Some(parse_inject(&s[26..])?);
+            }
+            "--stdin-inject-wait" => {
+                i += 1;
+                result.stdin_inject_wait = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--stdin-inject-wait requires a duration".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--stdin-inject-wait=") => {
+                result.stdin_inject_wait = Some(ArgValue::Borrowed(&s[20..]));
+            }
+
+            "--explain" => result.explain = true,
+            "--sleep" => result.sleep = true,
+            "--wait-pid" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--wait-pid requires a process ID".to_string(),
+                })?;
+                result.wait_pid = Some(parse_wait_pid(val)?);
+            }
+            s if s.starts_with("--wait-pid=") => {
+                result.wait_pid = Some(parse_wait_pid(&s[11..])?);
+            }
+            "--proc-tree" => result.proc_tree = true,
+            /* escapes are found in the tree, so it implies --proc-tree */
+            "--kill-escaped" => {
+                result.kill_escaped = true;
+                result.proc_tree = true;
+            }
+            "--reap-orphans" => result.reap_orphans = true,
+            "--also-kill" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--also-kill requires a value (PID,PID... or a file of pids)"
+                        .to_string(),
+                })?;
+                result.also_kill = Some(parse_also_kill(val)?);
+            }
+            s if s.starts_with("--also-kill=") => {
+                result.also_kill = Some(parse_also_kill(&s[12..])?);
+            }
+            "--crash-report" => result.crash_report = true,
+            "--result-file" => {
+                i += 1;
+                result.result_file = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--result-file requires a path".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--result-file=") => {
+                result.result_file = Some(ArgValue::Borrowed(&s[14..]));
+            }
+            "--pidfile" => {
+                i += 1;
+                result.pidfile = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--pidfile requires a path".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--pidfile=") => {
+                result.pidfile = Some(ArgValue::Borrowed(&s[10..]));
+            }
+            "--export-remaining" => {
+                i += 1;
+                result.export_remaining = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--export-remaining requires a variable name".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--export-remaining=") => {
+                result.export_remaining = Some(ArgValue::Borrowed(&s[19..]));
+            }
+            "--export-remaining-file" => {
+                i += 1;
+                result.export_remaining_file = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--export-remaining-file requires a path".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--export-remaining-file=") => {
+                result.export_remaining_file = Some(ArgValue::Borrowed(&s[24..]));
+            }
+            "--extend-env-file" => {
+                i += 1;
+                result.extend_env_file = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--extend-env-file requires a path".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--extend-env-file=") => {
+                result.extend_env_file = Some(ArgValue::Borrowed(&s[18..]));
+            }
+            "--extend-max" => {
+                i += 1;
+                result.extend_max = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--extend-max requires a duration".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--extend-max=") => {
+                result.extend_max = Some(ArgValue::Borrowed(&s[13..]));
+            }
+            "--file-mode" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--file-mode requires an octal mode".to_string(),
+                })?;
+                result.file_mode = Some(parse_file_mode(val)?);
+            }
+            s if s.starts_with("--file-mode=") => {
+                result.file_mode = Some(parse_file_mode(&s[12..])?);
+            }
+            "--no-fsync" => result.no_fsync = true,
+            "--temp-dir" => result.temp_dir = Some(ArgValue::Borrowed("")),
+            s if s.starts_with("--temp-dir=") => {
+                result.temp_dir = Some(ArgValue::Borrowed(&s[11..]));
+            }
+            "--keep-temp-on-failure" => result.keep_temp_on_failure = true,
+            "--prefix-output" => {
+                i += 1;
+                result.prefix_output = Some(ArgValue::Borrowed(
+                    args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                        message: "--prefix-output requires a value".to_string(),
+                    })?,
+                ));
+            }
+            s if s.starts_with("--prefix-output=") => {
+                result.prefix_output = Some(ArgValue::Borrowed(&s[16..]));
+            }
+            "--timestamps" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--timestamps requires a value (relative or absolute)".to_string(),
+                })?;
+                result.timestamps = Some(parse_timestamps(val)?);
+            }
+            s if s.starts_with("--timestamps=") => {
+                result.timestamps = Some(parse_timestamps(&s[13..])?);
+            }
+            "--raw-output" => result.raw_output = true,
+            "--merge-output" => result.merge_output = true,
+            "--output-rate-limit" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--output-rate-limit requires a value (bytes per second)".to_string(),
+                })?;
+                result.output_rate_limit = Some(parse_output_rate(val)?);
+            }
+            s if s.starts_with("--output-rate-limit=") => {
+                result.output_rate_limit = Some(parse_output_rate(&s[20..])?);
+            }
+            "--output-rate-policy" => {
+                i += 1;
+                let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| ParseError {
+                    message: "--output-rate-policy requires a value (drop or block)".to_string(),
+                })?;
+                result.output_rate_policy = parse_rate_policy(val)?;
+            }
+            s if s.starts_with("--output-rate-policy=") => {
+                result.output_rate_policy = parse_rate_policy(&s[21..])?;
+            }
+            /* the pid goes out on the JSON stream, so it implies --json */
+            "--print-pid" => {
+                result.print_pid = true;
+                result.json = true;
             }
 
             /* unknown long option */
@@ -584,17 +2737,10 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
             /* short option cluster like -pfv or unknown -x */
             s if s.starts_with('-') && s.len() > 1 && !s.starts_with("--") => {
                 /* could be a negative number for duration, check if we're in positional mode */
-                if result.duration.is_some() || s.chars().nth(1).is_some_and(|c| c.is_ascii_digit())
-                {
+                if result.past_duration() || s.chars().nth(1).is_some_and(|c| c.is_ascii_digit()) {
                     /* looks like a negative number or command starting with - */
                     /* treat as positional */
-                    if result.duration.is_none() {
-                        result.duration = Some(ArgValue::Borrowed(arg));
-                    } else if result.command.is_none() {
-                        result.command = Some(ArgValue::Borrowed(arg));
-                    } else {
-                        result.args.push(ArgValue::Borrowed(arg));
-                    }
+                    result.push_positional(arg, i);
                 } else {
                     /* parse short option cluster */
                     let bytes = s.as_bytes();
@@ -617,7 +2763,10 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
                             b'V' => {
                                 /* -V must be standalone, not in a cluster */
                                 if bytes.len() == 2 {
-                                    print_version();
+                                    print_version(
+                                        result.verbose || wants_verbose(&args[i + 1..]),
+                                        result.json || wants_json(&args[i + 1..]),
+                                    );
                                     // SAFETY: exit is always safe
                                     unsafe { libc::exit(0) };
                                 } else {
@@ -630,7 +2779,7 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
                             b'p' => result.preserve_status = true,
                             b'f' => result.foreground = true,
                             b'v' => result.verbose = true,
-                            b'q' => result.quiet = true,
+                            b'q' => result.quiet = result.quiet.saturating_add(1),
                             b's' => {
                                 /* rest of cluster or next arg is the value */
                                 if j + 1 < bytes.len() {
@@ -639,12 +2788,13 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
                                     break;
                                 } else {
                                     i += 1;
-                                    result.signal =
-                                        ArgValue::Borrowed(args.get(i).ok_or_else(|| {
+                                    result.signal = ArgValue::Borrowed(
+                                        args.get(i).map(AsRef::as_ref).ok_or_else(|| {
                                             ParseError {
                                                 message: "-s requires a signal name".to_string(),
                                             }
-                                        })?);
+                                        })?,
+                                    );
                                 }
                             }
                             b'k' => {
@@ -654,12 +2804,13 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
                                     break;
                                 } else {
                                     i += 1;
-                                    result.kill_after =
-                                        Some(ArgValue::Borrowed(args.get(i).ok_or_else(|| {
+                                    result.kill_after = Some(ArgValue::Borrowed(
+                                        args.get(i).map(AsRef::as_ref).ok_or_else(|| {
                                             ParseError {
                                                 message: "-k requires a duration".to_string(),
                                             }
-                                        })?));
+                                        })?,
+                                    ));
                                 }
                             }
                             b'c' => {
@@ -668,7 +2819,7 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
                                     result.confine =
                                         Confine::from_str(val).ok_or_else(|| ParseError {
                                             message: format!(
-                                                "invalid confine mode: '{}' (use 'wall' or 'active')",
+                                                "invalid confine mode: '{}' (use 'wall', 'active' or 'hybrid')",
                                                 val
                                             ),
                                         })?;
@@ -676,31 +2827,50 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
                                     break;
                                 } else {
                                     i += 1;
-                                    let val = args.get(i).ok_or_else(|| ParseError {
-                                        message: "-c requires a value (wall or active)".to_string(),
+                                    let val = args.get(i).map(AsRef::as_ref).ok_or_else(|| {
+                                        ParseError {
+                                            message: "-c requires a value (wall, active or hybrid)"
+                                                .to_string(),
+                                        }
                                     })?;
                                     result.confine =
                                         Confine::from_str(val).ok_or_else(|| ParseError {
                                             message: format!(
-                                                "invalid confine mode: '{}' (use 'wall' or 'active')",
+                                                "invalid confine mode: '{}' (use 'wall', 'active' or 'hybrid')",
                                                 val
                                             ),
                                         })?;
                                     result.confine_specified = true;
                                 }
                             }
+                            b't' => {
+                                if j + 1 < bytes.len() {
+                                    result.set_duration(ArgValue::Owned(s[j + 1..].to_string()))?;
+                                    break;
+                                } else {
+                                    i += 1;
+                                    result.set_duration(ArgValue::Borrowed(
+                                        args.get(i).map(AsRef::as_ref).ok_or_else(|| {
+                                            ParseError {
+                                                message: "-t requires a duration".to_string(),
+                                            }
+                                        })?,
+                                    ))?;
+                                }
+                            }
                             b'r' => {
                                 if j + 1 < bytes.len() {
                                     result.retry = Some(ArgValue::Owned(s[j + 1..].to_string()));
                                     break;
                                 } else {
                                     i += 1;
-                                    result.retry =
-                                        Some(ArgValue::Borrowed(args.get(i).ok_or_else(|| {
+                                    result.retry = Some(ArgValue::Borrowed(
+                                        args.get(i).map(AsRef::as_ref).ok_or_else(|| {
                                             ParseError {
                                                 message: "-r requires a retry count".to_string(),
                                             }
-                                        })?));
+                                        })?,
+                                    ));
                                 }
                             }
                             b'H' => {
@@ -710,12 +2880,13 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
                                     break;
                                 } else {
                                     i += 1;
-                                    result.heartbeat =
-                                        Some(ArgValue::Borrowed(args.get(i).ok_or_else(|| {
+                                    result.heartbeat = Some(ArgValue::Borrowed(
+                                        args.get(i).map(AsRef::as_ref).ok_or_else(|| {
                                             ParseError {
                                                 message: "-H requires a duration".to_string(),
                                             }
-                                        })?));
+                                        })?,
+                                    ));
                                 }
                             }
                             b'S' => {
@@ -725,17 +2896,20 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
                                     break;
                                 } else {
                                     i += 1;
-                                    result.stdin_timeout =
-                                        Some(ArgValue::Borrowed(args.get(i).ok_or_else(|| {
+                                    result.stdin_timeout = Some(ArgValue::Borrowed(
+                                        args.get(i).map(AsRef::as_ref).ok_or_else(|| {
                                             ParseError {
                                                 message: "-S requires a duration".to_string(),
                                             }
-                                        })?));
+                                        })?,
+                                    ));
                                 }
                             }
-                            c => {
+                            _ => {
+                                /* report the whole character, not a UTF-8 lead byte */
+                                let c = s[j..].chars().next().unwrap_or('?');
                                 return Err(ParseError {
-                                    message: format!("unknown option: -{}", c as char),
+                                    message: format!("unknown option: -{}", c),
                                 });
                             }
                         }
@@ -745,28 +2919,256 @@ pub fn parse_from_slice<'a>(args: &'a [String]) -> Result<Args<'a>, ParseError>
             }
 
             /* positional args: duration, command, args... */
-            _ => {
-                if result.duration.is_none() {
-                    result.duration = Some(ArgValue::Borrowed(arg));
-                } else if result.command.is_none() {
-                    result.command = Some(ArgValue::Borrowed(arg));
-                } else {
-                    result.args.push(ArgValue::Borrowed(arg));
-                }
-            }
+            _ => result.push_positional(arg, i),
         }
 
         i += 1;
     }
 
-    /* validate conflicts */
-    if result.verbose && result.quiet {
+    Ok(result)
+}
+
+/* options that can't go together, or need one another */
+fn check_conflicts(result: &Args<'_>) -> Result<(), ParseError> {
+    /* --sleep and --wait-pid run no command: nothing to signal, watch or
+     * (for --sleep) report on */
+    let standalone = if result.sleep {
+        Some("--sleep")
+    } else if result.wait_pid.is_some() {
+        Some("--wait-pid")
+    } else {
+        None
+    };
+    if let Some(mode) = standalone {
+        let other = [
+            (result.sleep && result.wait_pid.is_some(), "--wait-pid"),
+            (result.kill_after.is_some(), "--kill-after"),
+            (result.preserve_status, "--preserve-status"),
+            (result.foreground, "--foreground"),
+            (result.arch.is_some(), "--arch"),
+            (
+                result.sleep && (result.json || result.json_fd.is_some()),
+                "--json",
+            ),
+            (result.on_timeout.is_some(), "--on-timeout"),
+            (result.on_limit.is_some(), "--on-limit"),
+            (result.retry.is_some(), "--retry"),
+            (result.heartbeat.is_some(), "--heartbeat"),
+            (result.stdin_timeout.is_some(), "--stdin-timeout"),
+            (result.mem_limit.is_some(), "--mem-limit"),
+            (result.cpu_time.is_some(), "--cpu-time"),
+            (result.cpu_percent.is_some(), "--cpu-percent"),
+            (
+                result.every.is_some() || result.runs.is_some(),
+                "--every/--runs",
+            ),
+            (result.confine == Confine::Hybrid, "--confine hybrid"),
+            (result.extend_env_file.is_some(), "--extend-env-file"),
+            (result.also_kill.is_some(), "--also-kill"),
+            (result.on_child_stop.is_some(), "--on-child-stop"),
+        ]
+        .into_iter()
+        .find_map(|(given, name)| given.then_some(name));
+        if let Some(other) = other {
+            return Err(ParseError {
+                message: format!("{mode} cannot be used with {other}"),
+            });
+        }
+    }
+    if result.verbose && result.quiet > 0 {
         return Err(ParseError {
             message: "-v/--verbose cannot be used with -q/--quiet".to_string(),
         });
     }
+    if result.wait_for_file.is_some() && result.wait_for_file_gone.is_some() {
+        return Err(ParseError {
+            message: "--wait-for-file cannot be used with --wait-for-file-gone".to_string(),
+        });
+    }
+    if result.wait_for_file_contains.is_some() && result.wait_for_file.is_none() {
+        return Err(ParseError {
+            message: "--wait-for-file-contains requires --wait-for-file".to_string(),
+        });
+    }
+    if result.confine == Confine::Hybrid && result.active_min.is_none() {
+        return Err(ParseError {
+            message: "--confine hybrid requires --active-min".to_string(),
+        });
+    }
+    if result.active_min.is_some() && result.confine != Confine::Hybrid {
+        return Err(ParseError {
+            message: "--active-min requires --confine hybrid".to_string(),
+        });
+    }
+    /* --no-warn-exit-code says the code was chosen knowingly: that's what
+     * --strict wants to be sure of */
+    if let Some(code) = result.timeout_exit_code
+        && result.strict
+        && !result.no_warn_exit_code
+        && crate::error::exit_codes::RESERVED.contains(&code)
+    {
+        return Err(ParseError {
+            message: format!(
+                "--strict: --timeout-exit-code {code} is a reserved exit code (125-137); \
+                 add --no-warn-exit-code if it's meant"
+            ),
+        });
+    }
+    if result.kill_self_on_timeout.is_some() && result.timeout_exit_code.is_some() {
+        return Err(ParseError {
+            message: "--kill-self-on-timeout cannot be used with --timeout-exit-code".to_string(),
+        });
+    }
+    /* each decides how a timeout ends; --launchd-mode already has */
+    if result.launchd_mode.is_some() {
+        let other = if result.kill_self_on_timeout.is_some() {
+            Some("--kill-self-on-timeout")
+        } else if result.timeout_exit_code.is_some() {
+            Some("--timeout-exit-code")
+        } else if result.preserve_status {
+            Some("--preserve-status")
+        } else {
+            None
+        };
+        if let Some(other) = other {
+            return Err(ParseError {
+                message: format!("--launchd-mode cannot be used with {other}"),
+            });
+        }
+    }
+    if result.spawn_retry_delay.is_some() && result.spawn_retry.is_none() {
+        return Err(ParseError {
+            message: "--spawn-retry-delay requires --spawn-retry".to_string(),
+        });
+    }
+    if result.flock_timeout.is_some() && result.flock.is_none() {
+        return Err(ParseError {
+            message: "--flock-timeout requires --flock".to_string(),
+        });
+    }
+    if result.flock_queue.is_some() && result.flock.is_none() {
+        return Err(ParseError {
+            message: "--flock-queue requires --flock".to_string(),
+        });
+    }
+    if result.flock_queue.is_some() && result.flock_timeout.is_some() {
+        return Err(ParseError {
+            message: "--flock-queue cannot be used with --flock-timeout".to_string(),
+        });
+    }
+    if result.only_between.is_none() {
+        for (set, flag) in [
+            (result.window_wait.is_some(), "--window-wait"),
+            (result.window_stop, "--window-stop"),
+        ] {
+            if set {
+                return Err(ParseError {
+                    message: format!("{} requires --only-between", flag),
+                });
+            }
+        }
+    }
+    match (&result.every, &result.repeat_for) {
+        (Some(_), None) => {
+            return Err(ParseError {
+                message: "--every requires --for".to_string(),
+            });
+        }
+        (None, Some(_)) => {
+            return Err(ParseError {
+                message: "--for requires --every".to_string(),
+            });
+        }
+        _ => {}
+    }
+    if result.runs.is_some() && result.every.is_some() {
+        return Err(ParseError {
+            message: "--runs cannot be used with --every (use --max-runs)".to_string(),
+        });
+    }
+    if result.max_runs.is_some() && result.every.is_none() {
+        return Err(ParseError {
+            message: "--max-runs requires --every".to_string(),
+        });
+    }
+    if result.detect_flaky.is_some() {
+        for (set, flag) in [
+            (result.every.is_some(), "--every"),
+            (result.runs.is_some(), "--runs"),
+            (result.stop_on.is_some(), "--stop-on"),
+            (result.flaky_threshold.is_some(), "--flaky-threshold"),
+        ] {
+            if set {
+                return Err(ParseError {
+                    message: format!("{} cannot be used with --detect-flaky", flag),
+                });
+            }
+        }
+    } else if result.flaky_exit.is_some() {
+        return Err(ParseError {
+            message: "--flaky-exit requires --detect-flaky".to_string(),
+        });
+    }
+    if result.every.is_none() && result.runs.is_none() {
+        for (set, flag) in [
+            (result.stop_on.is_some(), "--stop-on"),
+            (result.flaky_threshold.is_some(), "--flaky-threshold"),
+        ] {
+            if set {
+                return Err(ParseError {
+                    message: format!("{} requires --every or --runs", flag),
+                });
+            }
+        }
+    }
+    if (result.file_mode.is_some() || result.no_fsync)
+        && result.result_file.is_none()
+        && result.pidfile.is_none()
+        && result.export_remaining_file.is_none()
+    {
+        let flag = if result.file_mode.is_some() {
+            "--file-mode"
+        } else {
+            "--no-fsync"
+        };
+        return Err(ParseError {
+            message: format!("{flag} requires --result-file, --pidfile or --export-remaining-file"),
+        });
+    }
+    if result.extend_max.is_some() && result.extend_env_file.is_none() {
+        return Err(ParseError {
+            message: "--extend-max requires --extend-env-file".to_string(),
+        });
+    }
+    if result.keep_temp_on_failure && result.temp_dir.is_none() {
+        return Err(ParseError {
+            message: "--keep-temp-on-failure requires --temp-dir".to_string(),
+        });
+    }
+    if result.stdin_inject_wait.is_some() && result.stdin_inject.is_none() {
+        return Err(ParseError {
+            message: "--stdin-inject-wait requires --stdin-inject-on-timeout".to_string(),
+        });
+    }
+    if result.on_stopped.is_some() && !result.verify_signal_delivery {
+        return Err(ParseError {
+            message: "--on-stopped requires --verify-signal-delivery".to_string(),
+        });
+    }
+    for (flag, path) in [
+        ("--wait-for-file", &result.wait_for_file),
+        ("--wait-for-file-gone", &result.wait_for_file_gone),
+    ] {
+        if let Some(path) = path
+            && let Err(dir) = crate::wait::split_pattern(path.as_str())
+        {
+            return Err(ParseError {
+                message: format!("{flag}: wildcards only work in the file name, not in '{dir}'"),
+            });
+        }
+    }
 
-    Ok(result)
+    Ok(())
 }
 
 /// for testing - parse from iterator without env fallbacks
@@ -792,65 +3194,878 @@ where
     Ok(owned)
 }
 
-fn print_version() {
+/* optional subsystems (Cargo features), for --version --verbose/--json */
+const FEATURES: [(&str, bool); 5] = [
+    ("rlimit", cfg!(feature = "rlimit")),
+    ("throttle", cfg!(feature = "throttle")),
+    ("proc-info", cfg!(feature = "proc-info")),
+    ("crash-report", cfg!(feature = "crash-report")),
+    ("alloc-stats", cfg!(feature = "alloc-stats")),
+];
+
+/* -v after --version still counts: `procguard --version --verbose` */
+fn wants_verbose<S: AsRef<str>>(rest: &[S]) -> bool {
+    rest.iter()
+        .any(|a| matches!(a.as_ref(), "-v" | "--verbose"))
+}
+
+/* likewise --json: `procguard --version --json` */
+fn wants_json<S: AsRef<str>>(rest: &[S]) -> bool {
+    rest.iter().any(|a| a.as_ref() == "--json")
+}
+
+fn print_version(verbose: bool, json: bool) {
+    if json {
+        crate::io::print_str(&version_json());
+        crate::io::print_str("\n");
+        return;
+    }
     crate::io::print_str("procguard ");
     crate::io::print_str(env!("CARGO_PKG_VERSION"));
     crate::io::print_str(
         "\nThe formally verified process supervisor for macOS.\nCopyright (c) 2025 Alexandre Bouveur\nLicense: MIT <https://opensource.org/licenses/MIT>\n",
     );
+    if verbose {
+        /* +name compiled in, -name left out */
+        crate::io::print_str("features:");
+        for (name, enabled) in FEATURES {
+            crate::io::print_str(if enabled { " +" } else { " -" });
+            crate::io::print_str(name);
+        }
+        crate::io::print_str("\n");
+    }
 }
 
-fn print_help() {
-    crate::io::print_str(
+/*
+ * --version --json, for wrappers feature-detecting before they pass a flag:
+ * {"name":"procguard","version":"1.2.3","git_hash":"0123456789ab",
+ *  "features":["rlimit",...],"schema_versions":[58]}
+ * features lists only what's compiled in; git_hash is null when the build
+ * had no repo to ask.
+ */
+fn version_json() -> String {
+    let mut w = crate::json::JsonWriter::with_capacity(256);
+    w.begin_object();
+    w.field_str("name", "procguard");
+    w.field_str("version", env!("CARGO_PKG_VERSION"));
+    w.key("git_hash");
+    match option_env!("PROCGUARD_GIT_HASH") {
+        Some(hash) => w.str(hash),
+        None => w.null(),
+    };
+    w.key("features").begin_array();
+    for (name, _) in FEATURES.iter().filter(|(_, enabled)| *enabled) {
+        w.str(name);
+    }
+    w.end_array();
+    /* --json results this binary can produce */
+    w.key("schema_versions").begin_array();
+    w.u64(crate::json::SCHEMA_VERSION);
+    w.end_array();
+    w.end_object();
+    w.finish()
+}
+
+/*
+ * --help is generated from the tables below, so it lists exactly what the
+ * parser takes (test_help_lists_every_option holds the two together) and
+ * lays out the same way every time for scripts that grep it: option text
+ * in the first HELP_COLUMN columns, description wrapped at HELP_WIDTH.
+ * GNU timeout's options come first, procguard's own after them.
+ */
+struct OptHelp {
+    short: Option<char>,
+    long: &'static str,
+    value: &'static str, /* " <VALUE>", "[=VALUE]" or "" for a flag */
+    help: &'static str,  /* one paragraph, wrapped when printed */
+}
+
+const HELP_COLUMN: usize = 34;
+const HELP_WIDTH: usize = 80;
+
+const fn opt(
+    short: Option<char>,
+    long: &'static str,
+    value: &'static str,
+    help: &'static str,
+) -> OptHelp {
+    OptHelp {
+        short,
+        long,
+        value,
+        help,
+    }
+}
+
+/* the options GNU timeout has, with its meaning (plus -h and -V) */
+const GNU_OPTIONS: &[OptHelp] = &[
+    opt(
+        Some('s'),
+        "signal",
+        " <SIGNAL>",
+        "Signal to send on timeout [env: TIMEOUT_SIGNAL] [default: TERM]",
+    ),
+    opt(
+        Some('k'),
+        "kill-after",
+        " <DURATION>",
+        "Send KILL signal if still running after DURATION (0 = skip SIGNAL, send KILL \
+         at the deadline) [env: TIMEOUT_KILL_AFTER]",
+    ),
+    opt(
+        Some('p'),
+        "preserve-status",
+        "",
+        "Exit with same status as COMMAND, even on timeout",
+    ),
+    opt(
+        Some('f'),
+        "foreground",
+        "",
+        "Allow COMMAND to read from TTY and get TTY signals",
+    ),
+    opt(
+        Some('v'),
+        "verbose",
+        "",
+        "Diagnose to stderr any signal sent upon timeout",
+    ),
+    opt(Some('h'), "help", "", "Print help"),
+    opt(
+        Some('V'),
+        "version",
+        "",
+        "Print version (with --json: version, git hash, features and JSON schema \
+         versions as one JSON object)",
+    ),
+];
+
+const EXTENSION_OPTIONS: &[OptHelp] = &[
+    opt(
+        Some('t'),
+        "duration",
+        " <DURATION>",
+        "The duration, in place of the DURATION operand: every operand is then COMMAND \
+         and its args, and TIMEOUT is ignored",
+    ),
+    opt(
+        Some('q'),
+        "quiet",
+        "",
+        "Suppress procguard's warnings and diagnostics on stderr; repeat (-qq) to \
+         suppress errors too",
+    ),
+    opt(
+        None,
+        "tag",
+        " <TAG>",
+        "Prefix our stderr lines with TAG instead of the program name (alias: --prefix)",
+    ),
+    opt(
+        None,
+        "run-id",
+        " <ID>",
+        "Correlation ID for this run, in every JSON object and in heartbeats (default: \
+         generated)",
+    ),
+    opt(
+        None,
+        "timeout-exit-code",
+        " <CODE>",
+        "Exit with CODE instead of 124 when timeout occurs",
+    ),
+    opt(
+        None,
+        "no-warn-exit-code",
+        "",
+        "Don't warn when --timeout-exit-code is a reserved code (125-137)",
+    ),
+    opt(
+        None,
+        "strict",
+        "",
+        "Refuse a reserved --timeout-exit-code (125-137) instead of warning",
+    ),
+    opt(
+        None,
+        "kill-self-on-timeout",
+        "[=SIG]",
+        "On timeout, die of SIG (default: ALRM) instead of exiting 124, after the \
+         result is reported",
+    ),
+    opt(
+        None,
+        "launchd-mode",
+        " <MODE>",
+        "For a launchd job: on timeout or a limit kill, exit 0 so KeepAlive won't \
+         relaunch (stop), or die of SIGTERM so it will (relaunch)",
+    ),
+    opt(
+        None,
+        "on-timeout",
+        " <CMD>",
+        "Run CMD before sending the timeout signal (%p = PID)",
+    ),
+    opt(
+        None,
+        "on-limit",
+        " <CMD>",
+        "Run CMD before sending the signal when --mem-limit is exceeded (%p = PID); \
+         without it, --on-timeout runs then too",
+    ),
+    opt(
+        None,
+        "on-timeout-limit",
+        " <DUR>",
+        "Timeout for the --on-timeout and --crash-hook commands [default: 5s]",
+    ),
+    opt(
+        None,
+        "hook-budget",
+        " <POLICY>",
+        "separate: the --on-timeout hook runs before the --kill-after grace; shared: \
+         it runs out of it, cut off there [default: separate]",
+    ),
+    opt(
+        None,
+        "hook-async",
+        "",
+        "Start the --on-timeout hook and send the signal right away, the hook \
+         running through the --kill-after grace",
+    ),
+    opt(
+        None,
+        "verify-signal-delivery",
+        "",
+        "After each signal, check COMMAND can act on it: one that's stopped (by a \
+         debugger, say) would sit out the whole --kill-after grace",
+    ),
+    opt(
+        None,
+        "on-stopped",
+        " <POLICY>",
+        "What --verify-signal-delivery does with a stopped COMMAND: 'continue' \
+         (default, SIGCONT it) or 'kill' (SIGKILL it now)",
+    ),
+    opt(
+        None,
+        "debugger-grace",
+        " <DUR>",
+        "If a debugger is attached to COMMAND when the timeout comes due, wait up \
+         to DUR for it to detach before enforcing it",
+    ),
+    opt(
+        None,
+        "crash-hook",
+        " <CMD>",
+        "Run CMD if COMMAND crashed (%p = PID, %s = signal name, %r = crash report \
+         path, empty if none found)",
+    ),
+    opt(
+        None,
+        "pgroup",
+        " <MODE>",
+        "Process group for COMMAND: 'new' (default, own group), 'inherit' (share ours, \
+         like -f) or 'session' (setsid)",
+    ),
+    opt(
+        None,
+        "arch",
+        " <ARCH>",
+        "Run COMMAND's 'arm64' or 'x86_64' slice (x86_64 on Apple Silicon runs it under \
+         Rosetta); fails if the binary has no such slice",
+    ),
+    opt(
+        None,
+        "tty-foreground",
+        "",
+        "Make COMMAND the terminal's foreground job (Ctrl-C and TTY input go to it); \
+         restored on exit",
+    ),
+    opt(
+        None,
+        "restore-tty",
+        "",
+        "Save terminal settings and restore them (plus an ANSI reset) after COMMAND \
+         exits or is killed",
+    ),
+    opt(
+        None,
+        "monitor-qos",
+        " <CLASS>",
+        "Run procguard itself (not COMMAND) at this QoS class so deadlines stay on time \
+         under load: 'user-interactive', 'user-initiated', 'default', 'utility', \
+         'background'",
+    ),
+    opt(
+        Some('c'),
+        "confine",
+        " <MODE>",
+        "Time measurement mode: 'wall' (default, includes sleep), 'active' (excludes \
+         system sleep, faster, for benchmarks) or 'hybrid' (wall deadline, but also at \
+         least --active-min awake)",
+    ),
+    opt(
+        None,
+        "active-min",
+        " <DURATION>",
+        "Awake time that must pass before a hybrid timeout fires",
+    ),
+    opt(
+        None,
+        "on-sleep",
+        " <POLICY>",
+        "If the machine sleeps during the run: 'continue' (default, as --confine says), \
+         'pause' (sleep doesn't count) or 'fail' (stop COMMAND on wake, as a timeout)",
+    ),
+    opt(
+        None,
+        "on-child-stop",
+        " <POLICY>",
+        "Watch for COMMAND stopping itself (SIGSTOP, ^Z): 'continue' (the clock runs on), \
+         'pause' (stopped time doesn't count) or 'fail' (stop it, as a timeout); the time \
+         stopped goes in the JSON result",
+    ),
+    opt(
+        None,
+        "sleep",
+        "",
+        "Run no COMMAND: sleep for DURATION, by the --confine clock (wall by default, \
+         for timeout too), so time the machine spends asleep counts. A stop signal ends \
+         it early, as it would sleep(1)",
+    ),
+    opt(
+        None,
+        "wait-pid",
+        " <PID>",
+        "Run no COMMAND: wait up to DURATION (0: no limit) for process PID, which needn't \
+         be our child, to exit. Exits 0 once it has (or if it isn't running), 124 if it's \
+         still running; nothing is signalled. --json reports what happened",
+    ),
+    opt(
+        None,
+        "wait-for-file",
+        " <PATH>",
+        "Wait for file to exist before starting command; the file name may be a glob \
+         ('/tmp/ready.*') [env: TIMEOUT_WAIT_FOR_FILE]",
+    ),
+    opt(
+        None,
+        "wait-for-file-gone",
+        " <PATH>",
+        "Wait for file (or every glob match) to go away, e.g. a lockfile",
+    ),
+    opt(
+        None,
+        "wait-for-file-contains",
+        " <TEXT>",
+        "Also wait until the file contains TEXT",
+    ),
+    opt(
+        None,
+        "wait-for-file-timeout",
+        " <DUR>",
+        "Timeout for --wait-for-file (default: wait forever) \
+         [env: TIMEOUT_WAIT_FOR_FILE_TIMEOUT]",
+    ),
+    opt(
+        None,
+        "flock",
+        " <PATH>",
+        "Run only while holding an exclusive lock on PATH (created if missing), so \
+         one instance runs at a time; exit 75 if it's held",
+    ),
+    opt(
+        None,
+        "flock-timeout",
+        " <DUR>",
+        "Wait up to DUR for the --flock lock (default: don't wait)",
+    ),
+    opt(
+        None,
+        "flock-queue",
+        "[=MAX]",
+        "Wait for the --flock lock in arrival order behind other queued \
+         instances, giving up after MAX in line (default: no limit)",
+    ),
+    opt(
+        None,
+        "only-between",
+        " <HH:MM-HH:MM>",
+        "Run only inside this daily window of local time (may span midnight); \
+         exit 75 outside it",
+    ),
+    opt(
+        None,
+        "window-wait",
+        " <DUR>",
+        "Wait up to DUR for the --only-between window to open (default: don't wait)",
+    ),
+    opt(
+        None,
+        "window-stop",
+        "",
+        "Stop COMMAND when the --only-between window closes, as a timeout",
+    ),
+    opt(
+        None,
+        "every",
+        " <DUR>",
+        "Run COMMAND again every DUR, each run under the timeout (needs --for)",
+    ),
+    opt(
+        None,
+        "for",
+        " <DUR>",
+        "Keep --every going for DUR; no run starts after that",
+    ),
+    opt(
+        None,
+        "stop-on",
+        " <POLICY>",
+        "End --every early after a run that hit: never (default), timeout, failure",
+    ),
+    opt(None, "max-runs", " <N>", "Stop --every after N runs"),
+    opt(
+        None,
+        "runs",
+        " <N>",
+        "Run COMMAND N times back to back, each under the timeout (flaky test \
+         detection)",
+    ),
+    opt(
+        None,
+        "flaky-threshold",
+        " <PCT>",
+        "With --runs/--every, exit 0 unless more than PCT% of the runs failed \
+         (default: 0%)",
+    ),
+    opt(
+        None,
+        "detect-flaky",
+        " <N>",
+        "If COMMAND fails, rerun it up to N times; a pass makes it flaky, none \
+         consistently failing",
+    ),
+    opt(
+        None,
+        "flaky-exit",
+        " <POLICY>",
+        "Exit status of a flaky COMMAND: pass (0, default), fail (the failure's) \
+         or a code",
+    ),
+    opt(
+        Some('r'),
+        "retry",
+        " <N>",
+        "Retry command up to N times on timeout [env: TIMEOUT_RETRY]",
+    ),
+    opt(
+        None,
+        "retry-delay",
+        " <DURATION>",
+        "Delay between retries [default: 0]",
+    ),
+    opt(
+        None,
+        "retry-backoff",
+        " <Nx>",
+        "Multiply delay by N each retry (e.g., 2x for exponential)",
+    ),
+    opt(
+        None,
+        "spawn-retry",
+        " <N>",
+        "Try a spawn that failed with ETXTBSY or EAGAIN again, up to N times (max 10)",
+    ),
+    opt(
+        None,
+        "spawn-retry-delay",
+        " <T>",
+        "Wait before the first spawn retry, doubled for each one after (default: 100ms)",
+    ),
+    opt(
+        Some('H'),
+        "heartbeat",
+        " <DURATION>",
+        "Print status to stderr at regular intervals (for CI) [env: TIMEOUT_HEARTBEAT]",
+    ),
+    opt(
+        Some('S'),
+        "stdin-timeout",
+        " <DURATION>",
+        "Kill command if stdin has no activity for DURATION [env: TIMEOUT_STDIN_TIMEOUT]",
+    ),
+    opt(
+        None,
+        "stdin",
+        " <SOURCE>",
+        "COMMAND's stdin: 'null' (/dev/null), 'close' (no fd 0) or 'file:PATH'; -S \
+         still watches procguard's own stdin",
+    ),
+    opt(
+        None,
+        "stdin-mode",
+        " <MODE>",
+        "How -S watches stdin: 'consume' (default, reads and discards it), 'watch' \
+         (doesn't read; on a terminal, uses its last-read time) or 'pty' (COMMAND reads \
+         a pseudo-terminal that procguard types stdin into)",
+    ),
+    opt(None, "stdin-passthrough", "", "Same as --stdin-mode watch"),
+    opt(
+        None,
+        "stdin-inject-on-timeout",
+        " <TEXT>",
+        "On timeout, write TEXT to COMMAND's stdin first ('quit\\n'; \\n \\r \\t \\e \\0 \
+         \\\\ \\xHH escapes), then signal if it hasn't exited; stdin then goes through \
+         procguard",
+    ),
+    opt(
+        None,
+        "stdin-inject-wait",
+        " <DUR>",
+        "How long COMMAND gets to exit on TEXT [default: 1s]",
+    ),
+    opt(None, "json", "", "Output result as JSON (for scripting/CI)"),
+    opt(
+        None,
+        "json-fd",
+        " <N>",
+        "Write the JSON result to file descriptor N instead of stdout (implies --json); \
+         not inherited by COMMAND",
+    ),
+    opt(None, "json-stderr", "", "Same as --json-fd 2"),
+    opt(
+        None,
+        "explain",
+        "",
+        "Print a timeline of what happened (spawn, signals, exit) to stderr when done",
+    ),
+    opt(
+        None,
+        "proc-tree",
+        "",
+        "Record the processes COMMAND forks and execs (pid, parent, name) for the JSON \
+         result and --explain (macOS only)",
+    ),
+    opt(
+        None,
+        "kill-escaped",
+        "",
+        "SIGKILL processes that left COMMAND's process group (daemonized) and still run \
+         when it's done, if still the same executable (implies --proc-tree)",
+    ),
+    opt(
+        None,
+        "reap-orphans",
+        "",
+        "SIGKILL and reap what COMMAND left running in its process group once it's done \
+         (its backgrounded jobs); counted in the JSON result",
+    ),
+    opt(
+        None,
+        "also-kill",
+        " <PIDS|FILE>",
+        "At a timeout or --mem-limit kill, signal these processes along with COMMAND, SIGKILL \
+         after --kill-after included: a comma-separated list of pids, or a file of them re-read \
+         each time",
+    ),
+    opt(
+        None,
+        "prefix-output",
+        " <TAG>",
+        "Put TAG in front of every line COMMAND writes to stdout and stderr (COMMAND \
+         then writes to a pipe, not a TTY)",
+    ),
+    opt(
+        None,
+        "timestamps",
+        " <FMT>",
+        "Put the time in front of every line COMMAND writes: 'relative' (since start) \
+         or 'absolute' (UTC ISO-8601); off for a stream once it turns out not to be UTF-8",
+    ),
+    opt(
+        None,
+        "raw-output",
+        "",
+        "Pass COMMAND's output through untouched, overriding --prefix-output and \
+         --timestamps",
+    ),
+    opt(
+        None,
+        "merge-output",
+        "",
+        "Send COMMAND's stderr to stdout through one pipe, so lines keep the order they \
+         were written in (2>&1)",
+    ),
+    opt(
+        None,
+        "output-rate-limit",
+        " <RATE>",
+        "Let at most RATE bytes/sec of COMMAND's output through (e.g. 64K, 1M), bursts \
+         up to one second's worth",
+    ),
+    opt(
+        None,
+        "output-rate-policy",
+        " <P>",
+        "Above the rate: 'drop' (default, leaves a \"[...N bytes suppressed...]\" line) \
+         or 'block' (stop reading; COMMAND waits on the full pipe)",
+    ),
+    opt(
+        None,
+        "crash-report",
+        "",
+        "If COMMAND crashes, wait up to 3s for its macOS crash report and add the path \
+         to the JSON result",
+    ),
+    opt(
+        None,
+        "result-file",
+        " <PATH>",
+        "Also write the JSON result to PATH (atomic: temp file + rename), whatever \
+         COMMAND does to stdout",
+    ),
+    opt(
+        None,
+        "pidfile",
+        " <PATH>",
+        "Write COMMAND's PID and procguard's (one per line) to PATH after spawning; \
+         removed once COMMAND exits",
+    ),
+    opt(
+        None,
+        "export-remaining",
+        " <VAR>",
+        "Set VAR in COMMAND's environment to the timeout in seconds (e.g. 90 or 2.5), \
+         for tools that take their own",
+    ),
+    opt(
+        None,
+        "export-remaining-file",
+        " <PATH>",
+        "Keep PATH at the seconds left, rewritten every second while COMMAND runs; \
+         removed once it exits",
+    ),
+    opt(
+        None,
+        "extend-env-file",
+        " <PATH>",
+        "Re-read PATH every second while COMMAND runs; TIMEOUT_EXTEND=DURATION in it \
+         pushes the timeout back that far",
+    ),
+    opt(
+        None,
+        "extend-max",
+        " <DURATION>",
+        "Cap on TIMEOUT_EXTEND (default: the timeout again)",
+    ),
+    opt(
+        None,
+        "file-mode",
+        " <MODE>",
+        "Create the files above with exactly these permissions (octal, e.g. 0600), \
+         whatever the umask",
+    ),
+    opt(
+        None,
+        "no-fsync",
+        "",
+        "Don't fsync the files above before renaming them into place (faster, but a \
+         crash can leave them empty)",
+    ),
+    opt(
+        None,
+        "temp-dir",
+        "[=DIR]",
+        "Give each attempt a fresh TMPDIR under DIR (default: $TMPDIR or /tmp), \
+         removed with its contents once COMMAND exits or is killed",
+    ),
+    opt(
+        None,
+        "keep-temp-on-failure",
+        "",
+        "Keep the --temp-dir of an attempt that didn't exit 0, and print its path",
+    ),
+    opt(
+        None,
+        "print-pid",
+        "",
+        "Write a \"started\" JSON line with COMMAND's PID as soon as it's spawned, ahead \
+         of the result (implies --json)",
+    ),
+    opt(
+        None,
+        "mem-limit",
+        " <BYTES>",
+        "Soft memory limit enforced via polling (e.g., 512M or 512MiB, 500MB, 25% of \
+         RAM). Note: checked every 100ms (--poll-interval); rapid spikes may escape \
+         detection",
+    ),
+    opt(
+        None,
+        "cpu-time",
+        " <DURATION>[:<HARD>]",
+        "Set RLIMIT_CPU (total CPU time) for the command: SIGXCPU at DURATION, SIGKILL \
+         at HARD [default: HARD = DURATION]",
+    ),
+    opt(
+        None,
+        "cpu-percent",
+        " <PCT>",
+        "Throttle CPU to PCT via SIGSTOP/SIGCONT (100 = 1 core, 400 = 4 cores)",
+    ),
+    opt(
+        None,
+        "cpu-throttle-window",
+        " <DUR>",
+        "Window --cpu-percent smooths CPU use over, and the most unused budget it \
+         carries over; 0 = integral control only [default: 1s]",
+    ),
+    opt(
+        None,
+        "cpu-throttle-gain",
+        " <G>",
+        "How early --cpu-percent stops COMMAND running hot, and resumes it once it \
+         cools, 0 to 10 [default: 0.5]",
+    ),
+    opt(
+        None,
+        "cpu-throttle-quantum",
+        " <DUR>",
+        "Period --cpu-percent runs and stops COMMAND in, each stop sized to the \
+         overshoot, 10ms to 1s [default: 100ms]",
+    ),
+    opt(
+        None,
+        "cpu-throttle-notify",
+        " <HOW>",
+        "Tell COMMAND --cpu-percent may stop it: env (TIMEOUT_THROTTLED=1), signal \
+         (SIGUSR1 as throttling starts and ends) or both",
+    ),
+    opt(
+        None,
+        "poll-interval",
+        " <DUR>",
+        "How often --mem-limit, --cpu-percent and --stdin-mode watch look at COMMAND, \
+         10ms to 60s: longer costs less CPU and battery, shorter reacts sooner \
+         [default: 100ms; stdin watch: only at its deadline]",
+    ),
+    opt(
+        None,
+        "mem-poll-interval",
+        " <DUR>",
+        "--poll-interval for --mem-limit alone",
+    ),
+    opt(
+        None,
+        "cpu-poll-interval",
+        " <DUR>",
+        "--poll-interval for --cpu-percent alone",
+    ),
+    opt(
+        None,
+        "stdin-poll-interval",
+        " <DUR>",
+        "--poll-interval for --stdin-mode watch alone",
+    ),
+];
+
+/* "  -s, --signal <SIGNAL>", padded to HELP_COLUMN, then the description
+ * wrapped at HELP_WIDTH. an option too wide for the column gets its
+ * description on the next line */
+fn write_opt_help(w: &mut impl Write, opt: &OptHelp) {
+    let mut left = String::from("  ");
+    match opt.short {
+        Some(c) => {
+            left.push('-');
+            left.push(c);
+            left.push_str(", ");
+        }
+        None => left.push_str("    "),
+    }
+    left.push_str("--");
+    left.push_str(opt.long);
+    left.push_str(opt.value);
+    let _ = w.write_str(&left);
+
+    let mut col = left.len();
+    if col + 2 > HELP_COLUMN {
+        let _ = w.write_str("\n");
+        col = 0;
+    }
+    let mut first = true;
+    for word in opt.help.split(' ').filter(|w| !w.is_empty()) {
+        if !first && col + 1 + word.len() > HELP_WIDTH {
+            let _ = w.write_str("\n");
+            col = 0;
+            first = true;
+        }
+        if col < HELP_COLUMN {
+            let _ = write!(w, "{:1$}", "", HELP_COLUMN - col);
+            col = HELP_COLUMN;
+        } else if !first {
+            let _ = w.write_str(" ");
+            col += 1;
+        }
+        let _ = w.write_str(word);
+        col += word.len();
+        first = false;
+    }
+    let _ = w.write_str("\n");
+}
+
+fn write_help(w: &mut impl Write) {
+    let _ = w.write_str(
         r#"Usage: procguard [OPTIONS] DURATION COMMAND [ARG]...
+       procguard [OPTIONS] -t DURATION COMMAND [ARG]...
+       procguard --sleep [-c MODE] DURATION
+       procguard --wait-pid PID [-c MODE] [--json] DURATION
 
 The formally verified process supervisor for macOS.
 Provides timeout enforcement, resource limits, and process lifecycle control.
 
-When invoked as 'timeout' (symlink), defaults to --confine active for GNU compatibility.
+When invoked as 'timeout' (symlink), defaults to --confine active for GNU
+compatibility. When invoked as 'gtimeout', takes GNU timeout's options only
+and words errors like it.
 
 Arguments:
   DURATION  Time before sending signal (30, 30s, 100ms, 500us, 1.5m, 2h, 1d)
   COMMAND   Command to run
   ARG       Arguments for the command
+  @FILE     In place of DURATION or COMMAND: read arguments from FILE, one per
+            line ("..." for spaces or escapes, # comments); use -- before a
+            COMMAND named @...
 
-Options:
-  -s, --signal <SIGNAL>           Signal to send on timeout [env: TIMEOUT_SIGNAL] [default: TERM]
-  -k, --kill-after <DURATION>     Send KILL signal if still running after DURATION [env: TIMEOUT_KILL_AFTER]
-  -p, --preserve-status           Exit with same status as COMMAND, even on timeout
-  -f, --foreground                Allow COMMAND to read from TTY and get TTY signals
-  -v, --verbose                   Diagnose to stderr any signal sent upon timeout
-  -q, --quiet                     Suppress procguard's own diagnostic output to stderr
-      --timeout-exit-code <CODE>  Exit with CODE instead of 124 when timeout occurs
-      --on-timeout <CMD>          Run CMD before sending the timeout signal (%p = PID)
-      --on-timeout-limit <DUR>    Timeout for the --on-timeout hook command [default: 5s]
-  -c, --confine <MODE>            Time measurement mode: 'wall' (default, includes sleep) or
-                                  'active' (excludes system sleep, faster, for benchmarks)
-      --wait-for-file <PATH>      Wait for file to exist before starting command
-                                  [env: TIMEOUT_WAIT_FOR_FILE]
-      --wait-for-file-timeout <DUR>  Timeout for --wait-for-file (default: wait forever)
-                                  [env: TIMEOUT_WAIT_FOR_FILE_TIMEOUT]
-  -r, --retry <N>                 Retry command up to N times on timeout [env: TIMEOUT_RETRY]
-      --retry-delay <DURATION>    Delay between retries [default: 0]
-      --retry-backoff <Nx>        Multiply delay by N each retry (e.g., 2x for exponential)
-  -H, --heartbeat <DURATION>      Print status to stderr at regular intervals (for CI)
-                                  [env: TIMEOUT_HEARTBEAT]
-  -S, --stdin-timeout <DURATION>  Kill command if stdin has no activity for DURATION
-      --stdin-passthrough         Use non-consuming stdin idle detection (paired with -S)
-                                  [env: TIMEOUT_STDIN_TIMEOUT]
-      --json                      Output result as JSON (for scripting/CI)
-  -h, --help                      Print help
-  -V, --version                   Print version
-      --mem-limit <BYTES>         Soft memory limit enforced via polling (e.g., 512M, 2G)
-                                  Note: checked every 100ms; rapid spikes may escape detection
-      --cpu-time <DURATION>       Set RLIMIT_CPU (total CPU time) for the command
-      --cpu-percent <PCT>         Throttle CPU to PCT via SIGSTOP/SIGCONT
-                                  (100 = 1 core, 400 = 4 cores; low values may stutter)
+With TIMEOUT set, the DURATION operand may be left out. Which is used:
+  -t DURATION        always; every operand is COMMAND, TIMEOUT is ignored
+  -- COMMAND         TIMEOUT; everything after -- is COMMAND
+  DURATION COMMAND   the operand, if it's a duration (with a warning that
+                     TIMEOUT was passed over); else TIMEOUT, and the
+                     operand is COMMAND
+  OPERAND            TIMEOUT, and a lone operand is COMMAND (DURATION
+                     with --sleep or --wait-pid, which run none)
 
+Options (GNU timeout):
+"#,
+    );
+    for opt in GNU_OPTIONS {
+        write_opt_help(w, opt);
+    }
+    let _ = w.write_str("\nExtensions (procguard):\n");
+    for opt in EXTENSION_OPTIONS {
+        write_opt_help(w, opt);
+    }
+    let _ = w.write_str(
+        r#"
 Aliases:
-  timeout                         GNU-compatible alias (defaults to --confine active)
+  timeout                         GNU-compatible, defaults to --confine active
+  gtimeout                        GNU timeout's options and messages only
 
 Exit status:
+  70  if procguard is still running 30s past every deadline it had (failsafe)
+  75  if the --flock lock is held by another instance
+  75  if outside the --only-between window
   124 if COMMAND times out, and --preserve-status is not specified
   124 if --wait-for-file times out
   124 if --stdin-timeout triggers (stdin idle)
@@ -862,6 +4077,8 @@ Exit status:
 
 Environment:
   TIMEOUT         Default duration if not specified on command line
+  TIMEOUT_OPTS    Options, split like sh words, read ahead of the command
+                  line (which wins) and over the variables below
   TIMEOUT_SIGNAL  Default signal (overridden by -s)
   TIMEOUT_KILL_AFTER  Default kill-after duration (overridden by -k)
   TIMEOUT_RETRY   Default retry count (overridden by -r/--retry)
@@ -869,10 +4086,21 @@ Environment:
   TIMEOUT_STDIN_TIMEOUT  Default stdin idle timeout
   TIMEOUT_WAIT_FOR_FILE  Default file to wait for
   TIMEOUT_WAIT_FOR_FILE_TIMEOUT  Default timeout for wait-for-file
+  TIMEOUT_<OPTION>  Any other option, named for its long form:
+                  TIMEOUT_MEM_LIMIT=2G, TIMEOUT_JSON=1 (a flag takes 1 or
+                  0). Only fills in what the command line and TIMEOUT_OPTS
+                  leave out, and what --sleep or --wait-pid can take. None
+                  for -t, --sleep, --wait-pid or the options a run holds
+                  or writes: --flock, --pidfile, --result-file, --json-fd,
+                  --also-kill and --temp-dir
 "#,
     );
 }
 
+fn print_help() {
+    write_help(&mut crate::io::BufWriter::stdout());
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -887,7 +4115,7 @@ mod tests {
         assert!(!args.preserve_status);
         assert!(!args.foreground);
         assert!(!args.verbose);
-        assert!(!args.quiet);
+        assert_eq!(args.quiet, 0);
         assert!(args.kill_after.is_none());
         assert!(!args.json);
         assert!(args.timeout_exit_code.is_none());
@@ -922,7 +4150,7 @@ mod tests {
         assert!(args.preserve_status);
         assert!(args.foreground);
         assert!(args.verbose);
-        assert!(!args.quiet);
+        assert_eq!(args.quiet, 0);
         assert_eq!(args.timeout_exit_code, Some(42));
         assert_eq!(args.on_timeout, Some("echo %p".to_string()));
         assert_eq!(args.duration, Some("30s".to_string()));
@@ -949,7 +4177,7 @@ mod tests {
         assert!(args.preserve_status);
         assert!(args.foreground);
         assert!(args.verbose);
-        assert!(!args.quiet);
+        assert_eq!(args.quiet, 0);
         assert_eq!(args.duration, Some("1h".to_string()));
         assert_eq!(args.command, Some("cmd".to_string()));
     }
@@ -977,31 +4205,397 @@ mod tests {
     #[test]
     fn test_quiet_flag() {
         let args = try_parse_from(["procguard", "-q", "5s", "cmd"]).unwrap();
-        assert!(args.quiet);
+        assert_eq!(args.quiet, 1);
     }
 
     #[test]
-    fn test_timeout_exit_code() {
-        let args = try_parse_from(["procguard", "--timeout-exit-code", "99", "5s", "cmd"]).unwrap();
-        assert_eq!(args.timeout_exit_code, Some(99));
+    fn test_quiet_levels() {
+        let args = try_parse_from(["procguard", "-qq", "5s", "cmd"]).unwrap();
+        assert_eq!(args.quiet, 2);
+        let args = try_parse_from(["procguard", "-q", "--quiet", "5s", "cmd"]).unwrap();
+        assert_eq!(args.quiet, 2);
     }
 
     #[test]
-    fn test_on_timeout() {
-        let args = try_parse_from(["procguard", "--on-timeout", "echo %p", "5s", "cmd"]).unwrap();
-        assert_eq!(args.on_timeout, Some("echo %p".to_string()));
+    fn test_pgroup_flag() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.pgroup, None);
+        let args = try_parse_from(["procguard", "--pgroup", "session", "5s", "cmd"]).unwrap();
+        assert_eq!(args.pgroup, Some(ProcessGroup::Session));
+        let args = try_parse_from(["procguard", "--pgroup=inherit", "5s", "cmd"]).unwrap();
+        assert_eq!(args.pgroup, Some(ProcessGroup::Inherit));
+        assert!(try_parse_from(["procguard", "--pgroup=bogus", "5s", "cmd"]).is_err());
     }
 
     #[test]
-    fn test_short_option_cluster() {
-        let args = try_parse_from(["procguard", "-pfv", "5s", "cmd"]).unwrap();
-        assert!(args.preserve_status);
-        assert!(args.foreground);
-        assert!(args.verbose);
+    fn test_arch_flag() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.arch, None);
+        let args = try_parse_from(["procguard", "--arch", "x86_64", "5s", "cmd"]).unwrap();
+        assert_eq!(args.arch, Some(Arch::X86_64));
+        let args = try_parse_from(["procguard", "--arch=arm64", "5s", "cmd"]).unwrap();
+        assert_eq!(args.arch, Some(Arch::Arm64));
+        assert!(try_parse_from(["procguard", "--arch=ppc", "5s", "cmd"]).is_err());
+        assert!(try_parse_from(["procguard", "--sleep", "--arch", "arm64", "5s"]).is_err());
     }
 
     #[test]
-    fn test_equals_syntax() {
+    fn test_monitor_qos_flag() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.monitor_qos, None);
+        let args = try_parse_from([
+            "procguard",
+            "--monitor-qos",
+            "user-interactive",
+            "5s",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.monitor_qos, Some(QosClass::UserInteractive));
+        let args = try_parse_from(["procguard", "--monitor-qos=utility", "5s", "cmd"]).unwrap();
+        assert_eq!(args.monitor_qos, Some(QosClass::Utility));
+        assert!(try_parse_from(["procguard", "--monitor-qos=realtime", "5s", "cmd"]).is_err());
+    }
+
+    #[test]
+    fn test_tty_foreground_flag() {
+        let args = try_parse_from(["procguard", "--tty-foreground", "5s", "vim"]).unwrap();
+        assert!(args.tty_foreground);
+        assert!(!args.restore_tty);
+
+        let args = try_parse_from(["procguard", "--restore-tty", "5s", "vim"]).unwrap();
+        assert!(args.restore_tty);
+    }
+
+    #[test]
+    fn test_tag_flag() {
+        let args = try_parse_from(["procguard", "--tag", "build", "5s", "cmd"]).unwrap();
+        assert_eq!(args.tag, Some("build".to_string()));
+        let args = try_parse_from(["procguard", "--prefix=outer", "5s", "cmd"]).unwrap();
+        assert_eq!(args.tag, Some("outer".to_string()));
+        assert!(try_parse_from(["procguard", "--tag"]).is_err());
+    }
+
+    #[test]
+    fn test_run_id_flag() {
+        let args = try_parse_from(["procguard", "--run-id", "shard-3", "5s", "cmd"]).unwrap();
+        assert_eq!(args.run_id, Some("shard-3".to_string()));
+        let args = try_parse_from(["procguard", "--run-id=ci/42", "5s", "cmd"]).unwrap();
+        assert_eq!(args.run_id, Some("ci/42".to_string()));
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.run_id, None);
+        assert!(try_parse_from(["procguard", "--run-id"]).is_err());
+        assert!(try_parse_from(["procguard", "--run-id=", "5s", "cmd"]).is_err());
+        let err = try_parse_from(["procguard", "--run-id", "a\nb", "5s", "cmd"]).unwrap_err();
+        assert!(
+            err.message.contains("invalid --run-id: 'a\\nb'"),
+            "{}",
+            err.message
+        );
+    }
+
+    #[test]
+    fn test_timeout_exit_code() {
+        let args = try_parse_from(["procguard", "--timeout-exit-code", "99", "5s", "cmd"]).unwrap();
+        assert_eq!(args.timeout_exit_code, Some(99));
+    }
+
+    #[test]
+    fn test_strict_exit_code() {
+        let parse = |argv: &[&str]| {
+            let mut full = vec!["procguard"];
+            full.extend_from_slice(argv);
+            full.extend(["5s", "cmd"]);
+            try_parse_from(full)
+        };
+        /* warned about at the timeout, not refused */
+        assert!(parse(&["--timeout-exit-code", "137"]).is_ok());
+        let err = parse(&["--strict", "--timeout-exit-code", "137"]).unwrap_err();
+        assert!(err.message.contains("--strict: --timeout-exit-code 137"));
+        assert!(parse(&["--strict", "--timeout-exit-code", "124"]).is_ok());
+        assert!(parse(&["--strict", "--timeout-exit-code", "138"]).is_ok());
+        assert!(parse(&["--strict"]).is_ok());
+        let args = parse(&["--strict", "--no-warn-exit-code", "--timeout-exit-code=125"]).unwrap();
+        assert!(args.strict && args.no_warn_exit_code);
+    }
+
+    #[test]
+    fn test_launchd_mode() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.launchd_mode, None);
+        let args = try_parse_from(["procguard", "--launchd-mode", "stop", "5s", "cmd"]).unwrap();
+        assert_eq!(args.launchd_mode, Some(LaunchdMode::Stop));
+        let args = try_parse_from(["procguard", "--launchd-mode=Relaunch", "5s", "cmd"]).unwrap();
+        assert_eq!(args.launchd_mode, Some(LaunchdMode::Relaunch));
+
+        let err = try_parse_from(["procguard", "--launchd-mode=always", "5s", "cmd"]).unwrap_err();
+        assert!(err.message.contains("invalid --launchd-mode"));
+        assert!(try_parse_from(["procguard", "--launchd-mode"]).is_err());
+        for other in [
+            &["--preserve-status"][..],
+            &["--kill-self-on-timeout"],
+            &["--timeout-exit-code", "3"],
+        ] {
+            let mut argv = vec!["procguard", "--launchd-mode=stop"];
+            argv.extend_from_slice(other);
+            argv.extend_from_slice(&["5s", "cmd"]);
+            let err = try_parse_from(argv).unwrap_err();
+            assert!(
+                err.message
+                    .contains(&format!("--launchd-mode cannot be used with {}", other[0])),
+                "{}",
+                err.message
+            );
+        }
+    }
+
+    #[test]
+    fn test_duration_option() {
+        let operands = |args: OwnedArgs| -> Vec<String> {
+            args.operands
+                .into_iter()
+                .map(|o| String::from_utf8(o).unwrap())
+                .collect()
+        };
+        let args = try_parse_from(["procguard", "5", "sleep", "1"]).unwrap();
+        assert_eq!(args.duration_from, DurationFrom::Operand);
+        assert_eq!(operands(args), ["5", "sleep", "1"]);
+
+        /* every operand is the command, even one that's a duration */
+        for argv in [
+            &["procguard", "-t", "30s", "5", "sleep"][..],
+            &["procguard", "-t30s", "5", "sleep"],
+            &["procguard", "--duration", "30s", "5", "sleep"],
+            &["procguard", "--duration=30s", "5", "sleep"],
+        ] {
+            let args = try_parse_from(argv.iter().copied()).unwrap();
+            assert_eq!(args.duration, Some("30s".to_string()), "{argv:?}");
+            assert_eq!(args.duration_from, DurationFrom::Option);
+            assert_eq!(args.command, Some("5".to_string()));
+            assert_eq!(operands(args), ["5", "sleep"]);
+        }
+        /* options still parse after it, short ones included */
+        let args =
+            try_parse_from(["procguard", "-t", "1m", "-v", "-k", "5s", "make", "-j8"]).unwrap();
+        assert!(args.verbose);
+        assert_eq!(args.kill_after, Some("5s".to_string()));
+        assert_eq!(args.command, Some("make".to_string()));
+        assert_eq!(args.args, ["-j8"]);
+        let args = try_parse_from(["procguard", "-vt", "1m", "true"]).unwrap();
+        assert!(args.verbose);
+        assert_eq!(args.duration, Some("1m".to_string()));
+
+        /* too late once an operand is the duration; a short -t there is
+         * the command's, as any short option is */
+        let err = try_parse_from(["procguard", "5", "--duration", "1m", "true"]).unwrap_err();
+        assert_eq!(
+            err.message,
+            "--duration given after the DURATION operand '5'"
+        );
+        let args = try_parse_from(["procguard", "5", "-t", "1m"]).unwrap();
+        assert_eq!(args.command, Some("-t".to_string()));
+        assert_eq!(
+            try_parse_from(["procguard", "-t"]).unwrap_err().message,
+            "-t requires a duration"
+        );
+    }
+
+    #[test]
+    fn test_sleep_mode() {
+        let args = try_parse_from(["procguard", "--sleep", "5s"]).unwrap();
+        assert!(args.sleep);
+        assert_eq!(args.duration, Some("5s".to_string()));
+        assert!(args.command.is_none());
+        let args = try_parse_from(["procguard", "-c", "active", "--sleep", "1m"]).unwrap();
+        assert!(args.sleep);
+        assert_eq!(args.confine, Confine::Active);
+        assert!(!try_parse_from(["procguard", "5s", "cmd"]).unwrap().sleep);
+
+        for (other, name) in [
+            (&["-k", "1s"][..], "--kill-after"),
+            (&["--json"], "--json"),
+            (&["--mem-limit", "1G"], "--mem-limit"),
+            (&["-c", "hybrid"], "--confine hybrid"),
+        ] {
+            let mut argv = vec!["procguard", "--sleep"];
+            argv.extend_from_slice(other);
+            argv.push("5s");
+            let err = try_parse_from(argv).unwrap_err();
+            assert_eq!(err.message, format!("--sleep cannot be used with {name}"));
+        }
+    }
+
+    #[test]
+    fn test_wait_pid_mode() {
+        let args = try_parse_from(["procguard", "--wait-pid", "4242", "10m"]).unwrap();
+        assert_eq!(args.wait_pid, Some(4242));
+        assert_eq!(args.duration, Some("10m".to_string()));
+        assert!(args.command.is_none());
+        let args = try_parse_from(["procguard", "--json", "--wait-pid=7", "0"]).unwrap();
+        assert_eq!(args.wait_pid, Some(7));
+        assert!(args.json);
+        assert_eq!(
+            try_parse_from(["procguard", "5s", "cmd"]).unwrap().wait_pid,
+            None
+        );
+
+        for bad in ["0", "-1", "abc", "4294967296"] {
+            let err = try_parse_from(["procguard", "--wait-pid", bad, "5s"]).unwrap_err();
+            assert_eq!(
+                err.message,
+                format!("invalid --wait-pid: '{bad}' (must be a process ID)")
+            );
+        }
+        for (other, name) in [
+            (&["-k", "1s"][..], "--kill-after"),
+            (&["--retry", "2"], "--retry"),
+            (&["--mem-limit", "1G"], "--mem-limit"),
+        ] {
+            let mut argv = vec!["procguard", "--wait-pid", "42"];
+            argv.extend_from_slice(other);
+            argv.push("5s");
+            let err = try_parse_from(argv).unwrap_err();
+            assert_eq!(
+                err.message,
+                format!("--wait-pid cannot be used with {name}")
+            );
+        }
+        let err = try_parse_from(["procguard", "--sleep", "--wait-pid", "42", "5s"]).unwrap_err();
+        assert_eq!(err.message, "--sleep cannot be used with --wait-pid");
+    }
+
+    #[test]
+    fn test_poll_intervals() {
+        let args = try_parse_from([
+            "procguard",
+            "--poll-interval",
+            "1s",
+            "--mem-poll-interval=50ms",
+            "--cpu-poll-interval",
+            "250ms",
+            "--stdin-poll-interval=2s",
+            "5s",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.poll_interval, Some("1s".to_string()));
+        assert_eq!(args.mem_poll_interval, Some("50ms".to_string()));
+        assert_eq!(args.cpu_poll_interval, Some("250ms".to_string()));
+        assert_eq!(args.stdin_poll_interval, Some("2s".to_string()));
+
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert!(args.poll_interval.is_none());
+        assert!(try_parse_from(["procguard", "--cpu-poll-interval"]).is_err());
+    }
+
+    #[test]
+    fn test_cpu_throttle_tuning() {
+        let args = try_parse_from([
+            "procguard",
+            "--cpu-percent",
+            "50",
+            "--cpu-throttle-window",
+            "2s",
+            "--cpu-throttle-gain=1.5",
+            "--cpu-throttle-quantum=50ms",
+            "5s",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.cpu_throttle_window, Some("2s".to_string()));
+        assert_eq!(args.cpu_throttle_gain, Some("1.5".to_string()));
+        assert_eq!(args.cpu_throttle_quantum, Some("50ms".to_string()));
+        assert!(try_parse_from(["procguard", "--cpu-throttle-gain"]).is_err());
+        assert!(try_parse_from(["procguard", "--cpu-throttle-quantum"]).is_err());
+    }
+
+    #[test]
+    fn test_cpu_throttle_notify() {
+        let args = try_parse_from(["procguard", "--cpu-percent=50", "5s", "cmd"]).unwrap();
+        assert_eq!(args.cpu_throttle_notify, None);
+        let args =
+            try_parse_from(["procguard", "--cpu-throttle-notify", "signal", "5s", "cmd"]).unwrap();
+        assert_eq!(args.cpu_throttle_notify, Some(ThrottleNotify::Signal));
+        let args =
+            try_parse_from(["procguard", "--cpu-throttle-notify=BOTH", "5s", "cmd"]).unwrap();
+        assert_eq!(args.cpu_throttle_notify, Some(ThrottleNotify::Both));
+        assert!(try_parse_from(["procguard", "--cpu-throttle-notify=off", "5s", "cmd"]).is_err());
+        assert!(try_parse_from(["procguard", "--cpu-throttle-notify"]).is_err());
+    }
+
+    #[test]
+    fn test_kill_self_on_timeout() {
+        let args = try_parse_from(["procguard", "--kill-self-on-timeout", "5s", "cmd"]).unwrap();
+        assert_eq!(args.kill_self_on_timeout, Some("ALRM".to_string()));
+        let args =
+            try_parse_from(["procguard", "--kill-self-on-timeout=USR1", "5s", "cmd"]).unwrap();
+        assert_eq!(args.kill_self_on_timeout, Some("USR1".to_string()));
+
+        let err = try_parse_from([
+            "procguard",
+            "--kill-self-on-timeout",
+            "--timeout-exit-code",
+            "3",
+            "5s",
+            "cmd",
+        ])
+        .unwrap_err();
+        assert!(
+            err.message
+                .contains("cannot be used with --timeout-exit-code")
+        );
+    }
+
+    #[test]
+    fn test_on_timeout() {
+        let args = try_parse_from(["procguard", "--on-timeout", "echo %p", "5s", "cmd"]).unwrap();
+        assert_eq!(args.on_timeout, Some("echo %p".to_string()));
+    }
+
+    #[test]
+    fn test_on_limit() {
+        let args = try_parse_from(["procguard", "--on-limit", "echo %p", "5s", "cmd"]).unwrap();
+        assert_eq!(args.on_limit, Some("echo %p".to_string()));
+        assert!(args.on_timeout.is_none());
+        let args = try_parse_from(["procguard", "--on-limit=vmmap %p", "5s", "cmd"]).unwrap();
+        assert_eq!(args.on_limit, Some("vmmap %p".to_string()));
+        assert!(try_parse_from(["procguard", "--on-limit"]).is_err());
+    }
+
+    #[test]
+    fn test_crash_hook() {
+        let args = try_parse_from(["procguard", "--crash-hook", "upload %r", "5s", "cmd"]).unwrap();
+        assert_eq!(args.crash_hook, Some("upload %r".to_string()));
+        let args = try_parse_from(["procguard", "--crash-hook=echo %s", "5s", "cmd"]).unwrap();
+        assert_eq!(args.crash_hook, Some("echo %s".to_string()));
+        assert!(try_parse_from(["procguard", "--crash-hook"]).is_err());
+    }
+
+    #[test]
+    fn test_short_option_cluster() {
+        let args = try_parse_from(["procguard", "-pfv", "5s", "cmd"]).unwrap();
+        assert!(args.preserve_status);
+        assert!(args.foreground);
+        assert!(args.verbose);
+    }
+
+    #[test]
+    fn test_short_option_after_duration() {
+        /* -c past the duration is the command's: with TIMEOUT=5s set,
+         * procguard sh -c CMD runs sh -c CMD */
+        let args = try_parse_from(["procguard", "sh", "-c", "exit 3"]).unwrap();
+        assert_eq!(args.duration.as_deref(), Some("sh"));
+        assert_eq!(args.command.as_deref(), Some("-c"));
+        assert_eq!(args.args, ["exit 3"]);
+        assert!(!args.confine_specified);
+
+        /* ahead of it, it's still ours */
+        let args = try_parse_from(["procguard", "-c", "active", "5s", "cmd"]).unwrap();
+        assert!(args.confine_specified);
+    }
+
+    #[test]
+    fn test_equals_syntax() {
         let args = try_parse_from([
             "timeout",
             "--signal=INT",
@@ -1021,6 +4615,161 @@ mod tests {
         assert!(result.unwrap_err().message.contains("unknown option"));
     }
 
+    #[test]
+    fn test_operands_track_argv_slots() {
+        /* the second "--" belongs to the command */
+        let argv: Vec<String> = ["-v", "5", "--", "sh", "--", "-c", "x"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let parsed = parse_from_slice(&argv).unwrap();
+        assert_eq!(parsed.operand_index, [1, 3, 4, 5, 6]);
+
+        let owned = parsed.into_owned();
+        let operands: Vec<&[u8]> = owned.operands.iter().map(Vec::as_slice).collect();
+        assert_eq!(operands, [&b"5"[..], b"sh", b"--", b"-c", b"x"]);
+    }
+
+    #[test]
+    fn test_parse_borrowed_strs() {
+        /* parse_args hands over Cow<str>s; anything AsRef<str> works */
+        let argv = ["-s", "KILL", "5", "sh"];
+        let parsed = parse_from_slice(&argv).unwrap();
+        assert_eq!(parsed.signal.as_str(), "KILL");
+        assert_eq!(parsed.command.as_ref().map(ArgValue::as_str), Some("sh"));
+    }
+
+    #[test]
+    fn test_unknown_short_option_non_ascii() {
+        let result = try_parse_from(["procguard", "-vé", "5s", "cmd"]);
+        assert_eq!(result.unwrap_err().message, "unknown option: -é");
+    }
+
+    #[test]
+    fn test_personality_from_argv0() {
+        assert_eq!(Personality::from_argv0("procguard"), Personality::Procguard);
+        assert_eq!(
+            Personality::from_argv0("/usr/local/bin/darwin-timeout"),
+            Personality::Procguard
+        );
+        assert_eq!(Personality::from_argv0("timeout"), Personality::Timeout);
+        assert_eq!(
+            Personality::from_argv0("/opt/homebrew/bin/gtimeout"),
+            Personality::Gnu
+        );
+        assert_eq!(Personality::Gnu.name(), "gtimeout");
+    }
+
+    fn help_text() -> String {
+        let mut help = String::new();
+        write_help(&mut help);
+        help
+    }
+
+    #[test]
+    fn test_help_lists_every_option() {
+        /* every option scan_slice matches on, found in its source */
+        let source = include_str!("args.rs");
+        let start = source.find("fn scan_slice").unwrap();
+        let body = &source[start..start + source[start..].find("\n}\n").unwrap()];
+        let mut options = Vec::new();
+        for (i, _) in body.match_indices("\"-") {
+            let name: String = body[i + 1..]
+                .chars()
+                .take_while(|&c| c == '-' || c.is_ascii_alphanumeric())
+                .collect();
+            let next = body[i + 1 + name.len()..].chars().next();
+            if name.len() > 1 && matches!(next, Some('"' | '=')) {
+                options.push(name);
+            }
+        }
+        for (i, _) in body.match_indices("b'") {
+            if body[i + 3..].starts_with("' =>") {
+                options.push(format!("-{}", &body[i + 2..i + 3]));
+            }
+        }
+        assert!(options.len() > 50, "{options:?}");
+
+        /* each one in --help as a whole word, not inside a longer one */
+        let help = help_text();
+        for option in &options {
+            let found = help.match_indices(option.as_str()).any(|(i, _)| {
+                let before = help[..i].chars().next_back();
+                let after = help[i + option.len()..].chars().next();
+                !matches!(before, Some('-' | 'a'..='z'))
+                    && !matches!(after, Some('-' | 'a'..='z' | 'A'..='Z'))
+            });
+            assert!(found, "{option} missing from --help");
+        }
+    }
+
+    #[test]
+    fn test_help_table_parses() {
+        /* and the other way: everything --help offers is taken. --help and
+         * --version would exit */
+        for opt in GNU_OPTIONS.iter().chain(EXTENSION_OPTIONS) {
+            if matches!(opt.long, "help" | "version") {
+                continue;
+            }
+            let long = format!("--{}", opt.long);
+            let mut args = vec!["procguard", long.as_str()];
+            if opt.value.starts_with(' ') {
+                args.push("1");
+            }
+            args.extend(["5s", "cmd"]);
+            if let Err(e) = try_parse_from(args) {
+                assert!(!e.message.contains("unknown option"), "{long}: {e}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_help_layout() {
+        let help = help_text();
+        for line in help.lines() {
+            assert!(line.len() <= HELP_WIDTH, "too wide: {line}");
+        }
+        /* GNU's options first, in their own section */
+        let gnu = help.find("Options (GNU timeout):").unwrap();
+        let ext = help.find("Extensions (procguard):").unwrap();
+        assert!(gnu < help.find("--preserve-status").unwrap());
+        assert!(help.find("--preserve-status").unwrap() < ext);
+        assert!(ext < help.find("\n      --json ").unwrap());
+        /* descriptions start in one column, or on the next line */
+        assert!(help.contains("\n  -s, --signal <SIGNAL>           Signal to send"));
+        assert!(help.contains(
+            "\n      --kill-self-on-timeout[=SIG]\n                                  On timeout"
+        ));
+    }
+
+    #[test]
+    fn test_check_gnu_options() {
+        let check = |args: &[&str]| check_gnu_options(args, "gtimeout").map_err(|e| e.message);
+        assert!(check(&["-s", "KILL", "-k", "5", "-pfv", "1", "cmd"]).is_ok());
+        assert!(check(&["-vsKILL", "--kill-after=5", "--signal", "HUP", "1", "cmd"]).is_ok());
+        /* options after the first operand belong to the command */
+        assert!(check(&["1", "cmd", "--json", "-q"]).is_ok());
+        assert!(check(&["--", "--json"]).is_ok());
+
+        assert_eq!(
+            check(&["--json", "1", "cmd"]).unwrap_err(),
+            "unrecognized option '--json'"
+        );
+        assert_eq!(check(&["-pq", "1"]).unwrap_err(), "invalid option -- 'q'");
+        assert_eq!(
+            check(&["-k"]).unwrap_err(),
+            "option requires an argument -- 'k'"
+        );
+        assert_eq!(
+            check(&["--signal"]).unwrap_err(),
+            "option '--signal' requires an argument"
+        );
+        assert_eq!(
+            check(&["--verbose=yes", "1"]).unwrap_err(),
+            "option '--verbose' doesn't allow an argument"
+        );
+    }
+
     #[test]
     fn test_missing_signal_value() {
         let result = try_parse_from(["procguard", "-s"]);
@@ -1065,61 +4814,466 @@ mod tests {
     }
 
     #[test]
-    fn test_confine_short_flag_embedded() {
-        let args = try_parse_from(["procguard", "-cwall", "5s", "cmd"]).unwrap();
-        assert_eq!(args.confine, Confine::Wall);
+    fn test_confine_hybrid() {
+        let args = try_parse_from([
+            "procguard",
+            "-c",
+            "hybrid",
+            "--active-min",
+            "10m",
+            "1h",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.confine, Confine::Hybrid);
+        assert_eq!(args.active_min.as_deref(), Some("10m"));
+
+        let args =
+            try_parse_from(["procguard", "-chybrid", "--active-min=30s", "1m", "cmd"]).unwrap();
+        assert_eq!(args.confine, Confine::Hybrid);
+        assert_eq!(args.active_min.as_deref(), Some("30s"));
+
+        /* one without the other makes no sense */
+        let err = try_parse_from(["procguard", "-c", "hybrid", "1h", "cmd"]).unwrap_err();
+        assert!(err.message.contains("requires --active-min"));
+        let err = try_parse_from(["procguard", "--active-min=10m", "1h", "cmd"]).unwrap_err();
+        assert!(err.message.contains("requires --confine hybrid"));
+        assert!(try_parse_from(["procguard", "--active-min"]).is_err());
+    }
+
+    #[test]
+    fn test_on_sleep() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.on_sleep, SleepPolicy::Continue);
+        let args = try_parse_from(["procguard", "--on-sleep", "pause", "5s", "cmd"]).unwrap();
+        assert_eq!(args.on_sleep, SleepPolicy::Pause);
+        let args = try_parse_from(["procguard", "--on-sleep=FAIL", "5s", "cmd"]).unwrap();
+        assert_eq!(args.on_sleep, SleepPolicy::Fail);
+
+        let err = try_parse_from(["procguard", "--on-sleep=snooze", "5s", "cmd"]).unwrap_err();
+        assert!(err.message.contains("invalid --on-sleep"));
+        assert!(try_parse_from(["procguard", "--on-sleep"]).is_err());
+    }
+
+    #[test]
+    fn test_on_child_stop() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.on_child_stop, None);
+        let args = try_parse_from(["procguard", "--on-child-stop", "pause", "5s", "cmd"]).unwrap();
+        assert_eq!(args.on_child_stop, Some(ChildStopPolicy::Pause));
+        let args = try_parse_from(["procguard", "--on-child-stop=FAIL", "5s", "cmd"]).unwrap();
+        assert_eq!(args.on_child_stop, Some(ChildStopPolicy::Fail));
+
+        let err = try_parse_from(["procguard", "--on-child-stop=resume", "5s", "cmd"]).unwrap_err();
+        assert!(err.message.contains("invalid --on-child-stop"));
+        assert!(try_parse_from(["procguard", "--on-child-stop"]).is_err());
+        let err =
+            try_parse_from(["procguard", "--sleep", "--on-child-stop", "pause", "1s"]).unwrap_err();
+        assert!(err.message.contains("--on-child-stop"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_hook_budget() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.hook_budget, HookBudget::Separate);
+        let args = try_parse_from(["procguard", "--hook-budget", "shared", "5s", "cmd"]).unwrap();
+        assert_eq!(args.hook_budget, HookBudget::Shared);
+        let args = try_parse_from(["procguard", "--hook-budget=Separate", "5s", "cmd"]).unwrap();
+        assert_eq!(args.hook_budget, HookBudget::Separate);
+
+        let err = try_parse_from(["procguard", "--hook-budget=both", "5s", "cmd"]).unwrap_err();
+        assert!(err.message.contains("invalid --hook-budget"));
+        assert!(try_parse_from(["procguard", "--hook-budget"]).is_err());
+
+        assert!(!args.hook_async);
+        let args = try_parse_from(["procguard", "--hook-async", "-k1s", "5s", "cmd"]).unwrap();
+        assert!(args.hook_async);
+        assert_eq!(args.hook_budget, HookBudget::Separate);
+    }
+
+    #[test]
+    fn test_verify_signal_delivery() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert!(!args.verify_signal_delivery);
+        assert_eq!(args.on_stopped, None);
+        let args = try_parse_from(["procguard", "--verify-signal-delivery", "5s", "cmd"]).unwrap();
+        assert!(args.verify_signal_delivery);
+        let args = try_parse_from([
+            "procguard",
+            "--verify-signal-delivery",
+            "--on-stopped=KILL",
+            "5s",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.on_stopped, Some(StoppedPolicy::Kill));
+
+        let err =
+            try_parse_from(["procguard", "--on-stopped", "continue", "5s", "cmd"]).unwrap_err();
+        assert_eq!(
+            err.message,
+            "--on-stopped requires --verify-signal-delivery"
+        );
+        let err = try_parse_from([
+            "procguard",
+            "--verify-signal-delivery",
+            "--on-stopped=wait",
+            "5s",
+            "cmd",
+        ])
+        .unwrap_err();
+        assert!(err.message.contains("invalid --on-stopped"));
+    }
+
+    #[test]
+    fn test_debugger_grace() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert!(args.debugger_grace.is_none());
+        let args = try_parse_from(["procguard", "--debugger-grace", "2m", "5s", "cmd"]).unwrap();
+        assert_eq!(args.debugger_grace.as_deref(), Some("2m"));
+        let args = try_parse_from(["procguard", "--debugger-grace=30s", "5s", "cmd"]).unwrap();
+        assert_eq!(args.debugger_grace.as_deref(), Some("30s"));
+        let err = try_parse_from(["procguard", "--debugger-grace"]).unwrap_err();
+        assert_eq!(err.message, "--debugger-grace requires a duration");
+    }
+
+    #[test]
+    fn test_confine_short_flag_embedded() {
+        let args = try_parse_from(["procguard", "-cwall", "5s", "cmd"]).unwrap();
+        assert_eq!(args.confine, Confine::Wall);
+    }
+
+    #[test]
+    fn test_wait_for_file() {
+        let args =
+            try_parse_from(["procguard", "--wait-for-file", "/tmp/ready", "5s", "cmd"]).unwrap();
+        assert_eq!(args.wait_for_file, Some("/tmp/ready".to_string()));
+        assert!(args.wait_for_file_timeout.is_none());
+    }
+
+    #[test]
+    fn test_wait_for_file_equals_syntax() {
+        let args =
+            try_parse_from(["procguard", "--wait-for-file=/tmp/ready", "5s", "cmd"]).unwrap();
+        assert_eq!(args.wait_for_file, Some("/tmp/ready".to_string()));
+    }
+
+    #[test]
+    fn test_wait_for_file_with_timeout() {
+        let args = try_parse_from([
+            "timeout",
+            "--wait-for-file",
+            "/tmp/ready",
+            "--wait-for-file-timeout",
+            "30s",
+            "5s",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.wait_for_file, Some("/tmp/ready".to_string()));
+        assert_eq!(args.wait_for_file_timeout, Some("30s".to_string()));
+    }
+
+    #[test]
+    fn test_wait_for_file_timeout_equals_syntax() {
+        let args = try_parse_from([
+            "timeout",
+            "--wait-for-file=/tmp/ready",
+            "--wait-for-file-timeout=1m",
+            "5s",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.wait_for_file, Some("/tmp/ready".to_string()));
+        assert_eq!(args.wait_for_file_timeout, Some("1m".to_string()));
+    }
+
+    #[test]
+    fn test_flock_flags() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.flock, None);
+        let args = try_parse_from([
+            "procguard",
+            "--flock",
+            "/tmp/job.lock",
+            "--flock-timeout=30s",
+            "5s",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.flock, Some("/tmp/job.lock".to_string()));
+        assert_eq!(args.flock_timeout, Some("30s".to_string()));
+        assert!(try_parse_from(["procguard", "--flock"]).is_err());
+        let err = try_parse_from(["procguard", "--flock-timeout", "1s", "5s", "cmd"]);
+        assert!(err.unwrap_err().message.contains("requires --flock"));
+    }
+
+    #[test]
+    fn test_only_between_flags() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.only_between, None);
+        let args = try_parse_from([
+            "procguard",
+            "--only-between",
+            "22:00-06:00",
+            "--window-wait=8h",
+            "--window-stop",
+            "5s",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.only_between, Window::parse("22:00-06:00"));
+        assert_eq!(args.window_wait, Some("8h".to_string()));
+        assert!(args.window_stop);
+        let args = try_parse_from(["procguard", "--only-between=9:00-17:00", "5s", "cmd"]);
+        assert_eq!(args.unwrap().only_between, Window::parse("09:00-17:00"));
+
+        let err = try_parse_from(["procguard", "--only-between", "22-06", "5s", "cmd"]);
+        assert!(err.unwrap_err().message.contains("invalid --only-between"));
+        assert!(try_parse_from(["procguard", "--only-between"]).is_err());
+        let err = try_parse_from(["procguard", "--window-stop", "5s", "cmd"]);
+        assert!(err.unwrap_err().message.contains("requires --only-between"));
+        let err = try_parse_from(["procguard", "--window-wait", "1h", "5s", "cmd"]);
+        assert!(err.unwrap_err().message.contains("requires --only-between"));
+    }
+
+    #[test]
+    fn test_repeat_flags() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.every, None);
+        assert_eq!(args.stop_on, None);
+        let args = try_parse_from([
+            "procguard",
+            "--every",
+            "5m",
+            "--for=2h",
+            "--stop-on=Failure",
+            "30s",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.every, Some("5m".to_string()));
+        assert_eq!(args.repeat_for, Some("2h".to_string()));
+        assert_eq!(args.stop_on, Some(StopOn::Failure));
+        let args = try_parse_from([
+            "procguard",
+            "--every=1m",
+            "--for",
+            "1h",
+            "--stop-on",
+            "timeout",
+            "5s",
+            "cmd",
+        ]);
+        assert_eq!(args.unwrap().stop_on, Some(StopOn::Timeout));
+
+        let err = try_parse_from(["procguard", "--every", "5m", "5s", "cmd"]);
+        assert!(err.unwrap_err().message.contains("--every requires --for"));
+        let err = try_parse_from(["procguard", "--for", "1h", "5s", "cmd"]);
+        assert!(err.unwrap_err().message.contains("--for requires --every"));
+        let err = try_parse_from(["procguard", "--stop-on", "failure", "5s", "cmd"]);
+        assert!(
+            err.unwrap_err()
+                .message
+                .contains("--stop-on requires --every")
+        );
+        let args = try_parse_from(["procguard", "--runs", "3", "--stop-on=failure", "5s", "cmd"]);
+        assert_eq!(args.unwrap().stop_on, Some(StopOn::Failure));
+        let err = try_parse_from(["procguard", "--stop-on=sometimes", "5s", "cmd"]);
+        assert!(err.unwrap_err().message.contains("invalid --stop-on"));
+        assert!(try_parse_from(["procguard", "--every"]).is_err());
+    }
+
+    #[test]
+    fn test_runs_flags() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.runs, None);
+        assert_eq!(args.flaky_threshold, None);
+        let args = try_parse_from([
+            "procguard",
+            "--runs",
+            "20",
+            "--flaky-threshold",
+            "5%",
+            "5s",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.runs, Some(20));
+        assert_eq!(args.flaky_threshold, Some(50));
+        let args = try_parse_from([
+            "procguard",
+            "--every=1m",
+            "--for=1h",
+            "--max-runs=10",
+            "--flaky-threshold=2.5",
+            "5s",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.max_runs, Some(10));
+        assert_eq!(args.flaky_threshold, Some(25));
+
+        for (pct, permille) in [("0", 0), ("100%", 1000), ("0.1%", 1), ("12.0", 120)] {
+            assert_eq!(parse_flaky_threshold(pct).unwrap(), permille, "{pct}");
+        }
+        for bad in [
+            "", "%", "5%%", "-1", "100.1", "101", "2.55", "1.", ".5", "five",
+        ] {
+            assert!(parse_flaky_threshold(bad).is_err(), "{bad:?}");
+        }
+
+        let err = try_parse_from(["procguard", "--runs", "0", "5s", "cmd"]);
+        assert!(err.unwrap_err().message.contains("invalid --runs"));
+        let err = try_parse_from(["procguard", "--max-runs", "5", "5s", "cmd"]);
+        assert!(
+            err.unwrap_err()
+                .message
+                .contains("--max-runs requires --every")
+        );
+        let err = try_parse_from(["procguard", "--flaky-threshold=5", "5s", "cmd"]);
+        assert!(
+            err.unwrap_err()
+                .message
+                .contains("requires --every or --runs")
+        );
+        let err = try_parse_from([
+            "procguard",
+            "--runs",
+            "5",
+            "--every",
+            "1s",
+            "--for",
+            "1m",
+            "5s",
+            "cmd",
+        ]);
+        assert!(err.unwrap_err().message.contains("use --max-runs"));
+    }
+
+    #[test]
+    fn test_detect_flaky_flags() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.detect_flaky, None);
+        assert_eq!(args.flaky_exit, None);
+        let args = try_parse_from(["procguard", "--detect-flaky", "3", "5s", "cmd"]).unwrap();
+        assert_eq!(args.detect_flaky, Some(3));
+        let args = try_parse_from([
+            "procguard",
+            "--detect-flaky=2",
+            "--flaky-exit",
+            "fail",
+            "5s",
+            "cmd",
+        ]);
+        assert_eq!(args.unwrap().flaky_exit, Some(FlakyExit::Fail));
+        let args = try_parse_from([
+            "procguard",
+            "--detect-flaky=2",
+            "--flaky-exit=42",
+            "5s",
+            "cmd",
+        ]);
+        assert_eq!(args.unwrap().flaky_exit, Some(FlakyExit::Code(42)));
+
+        let err = try_parse_from(["procguard", "--detect-flaky", "0", "5s", "cmd"]);
+        assert!(err.unwrap_err().message.contains("invalid --detect-flaky"));
+        let err = try_parse_from(["procguard", "--flaky-exit=pass", "5s", "cmd"]);
+        assert!(err.unwrap_err().message.contains("requires --detect-flaky"));
+        let err = try_parse_from([
+            "procguard",
+            "--detect-flaky=2",
+            "--flaky-exit=x",
+            "5s",
+            "cmd",
+        ]);
+        assert!(err.unwrap_err().message.contains("invalid --flaky-exit"));
+        let err = try_parse_from(["procguard", "--detect-flaky=2", "--runs=3", "5s", "cmd"]);
+        assert!(
+            err.unwrap_err()
+                .message
+                .contains("--runs cannot be used with --detect-flaky")
+        );
+    }
+
+    #[test]
+    fn test_flock_queue_flags() {
+        let args = try_parse_from(["procguard", "--flock=/tmp/l", "--flock-queue", "5s", "cmd"]);
+        assert_eq!(args.unwrap().flock_queue, Some(String::new()));
+        let args = try_parse_from([
+            "procguard",
+            "--flock=/tmp/l",
+            "--flock-queue=10m",
+            "5s",
+            "cmd",
+        ]);
+        assert_eq!(args.unwrap().flock_queue, Some("10m".to_string()));
+        let err = try_parse_from(["procguard", "--flock-queue", "5s", "cmd"]);
+        assert!(err.unwrap_err().message.contains("requires --flock"));
+        let err = try_parse_from([
+            "procguard",
+            "--flock=/tmp/l",
+            "--flock-queue",
+            "--flock-timeout=1s",
+            "5s",
+            "cmd",
+        ]);
+        assert!(err.unwrap_err().message.contains("cannot be used with"));
     }
 
     #[test]
-    fn test_wait_for_file() {
-        let args =
-            try_parse_from(["procguard", "--wait-for-file", "/tmp/ready", "5s", "cmd"]).unwrap();
-        assert_eq!(args.wait_for_file, Some("/tmp/ready".to_string()));
-        assert!(args.wait_for_file_timeout.is_none());
+    fn test_wait_for_file_missing_path() {
+        let result = try_parse_from(["procguard", "--wait-for-file"]);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().message.contains("requires a path"));
     }
 
     #[test]
-    fn test_wait_for_file_equals_syntax() {
+    fn test_wait_for_file_gone_and_contains() {
         let args =
-            try_parse_from(["procguard", "--wait-for-file=/tmp/ready", "5s", "cmd"]).unwrap();
-        assert_eq!(args.wait_for_file, Some("/tmp/ready".to_string()));
-    }
+            try_parse_from(["procguard", "--wait-for-file-gone=/tmp/lock", "5s", "cmd"]).unwrap();
+        assert_eq!(args.wait_for_file_gone, Some("/tmp/lock".to_string()));
+        assert!(args.wait_for_file.is_none());
 
-    #[test]
-    fn test_wait_for_file_with_timeout() {
         let args = try_parse_from([
-            "timeout",
+            "procguard",
             "--wait-for-file",
-            "/tmp/ready",
-            "--wait-for-file-timeout",
-            "30s",
+            "/tmp/app.log",
+            "--wait-for-file-contains",
+            "listening on",
             "5s",
             "cmd",
         ])
         .unwrap();
-        assert_eq!(args.wait_for_file, Some("/tmp/ready".to_string()));
-        assert_eq!(args.wait_for_file_timeout, Some("30s".to_string()));
-    }
+        assert_eq!(
+            args.wait_for_file_contains,
+            Some("listening on".to_string())
+        );
 
-    #[test]
-    fn test_wait_for_file_timeout_equals_syntax() {
-        let args = try_parse_from([
-            "timeout",
-            "--wait-for-file=/tmp/ready",
-            "--wait-for-file-timeout=1m",
+        let err =
+            try_parse_from(["procguard", "--wait-for-file-contains=x", "5s", "cmd"]).unwrap_err();
+        assert!(err.message.contains("requires --wait-for-file"));
+        let err = try_parse_from([
+            "procguard",
+            "--wait-for-file=/tmp/a",
+            "--wait-for-file-gone=/tmp/b",
             "5s",
             "cmd",
         ])
-        .unwrap();
-        assert_eq!(args.wait_for_file, Some("/tmp/ready".to_string()));
-        assert_eq!(args.wait_for_file_timeout, Some("1m".to_string()));
+        .unwrap_err();
+        assert!(err.message.contains("cannot be used with"));
+        assert!(try_parse_from(["procguard", "--wait-for-file-gone"]).is_err());
     }
 
     #[test]
-    fn test_wait_for_file_missing_path() {
-        let result = try_parse_from(["procguard", "--wait-for-file"]);
-        assert!(result.is_err());
-        assert!(result.unwrap_err().message.contains("requires a path"));
+    fn test_wait_for_file_glob() {
+        let args =
+            try_parse_from(["procguard", "--wait-for-file", "/tmp/ready.*", "5s", "cmd"]).unwrap();
+        assert_eq!(args.wait_for_file, Some("/tmp/ready.*".to_string()));
+
+        let err = try_parse_from(["procguard", "--wait-for-file", "/tmp/*/ready", "5s", "cmd"])
+            .unwrap_err();
+        assert!(err.message.contains("'/tmp/*'"), "{}", err.message);
     }
 
     #[test]
@@ -1223,6 +5377,33 @@ mod tests {
         assert!(result.unwrap_err().message.contains("requires a count"));
     }
 
+    #[test]
+    fn test_spawn_retry() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.spawn_retry, None);
+        let args = try_parse_from(["procguard", "--spawn-retry", "5", "5s", "cmd"]).unwrap();
+        assert_eq!(args.spawn_retry, Some(5));
+        let args = try_parse_from([
+            "procguard",
+            "--spawn-retry=3",
+            "--spawn-retry-delay=50ms",
+            "5s",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.spawn_retry, Some(3));
+        assert_eq!(args.spawn_retry_delay, Some("50ms".to_string()));
+
+        for bad in ["11", "-1", "many", ""] {
+            let err = try_parse_from(["procguard", "--spawn-retry", bad, "5s", "cmd"]).unwrap_err();
+            assert!(err.message.contains("invalid --spawn-retry"), "{bad}");
+        }
+        assert!(try_parse_from(["procguard", "--spawn-retry"]).is_err());
+        let err =
+            try_parse_from(["procguard", "--spawn-retry-delay", "1s", "5s", "cmd"]).unwrap_err();
+        assert_eq!(err.message, "--spawn-retry-delay requires --spawn-retry");
+    }
+
     #[test]
     fn test_retry_delay_missing_duration() {
         let result = try_parse_from(["procguard", "--retry-delay"]);
@@ -1343,10 +5524,78 @@ mod tests {
         ])
         .unwrap();
 
-        assert!(args.stdin_passthrough);
+        assert_eq!(args.stdin_mode, StdinMode::Watch);
         assert_eq!(args.stdin_timeout, Some("10s".to_string()));
     }
 
+    #[test]
+    fn test_stdin_mode() {
+        let args = try_parse_from(["procguard", "-S", "10s", "5s", "cmd"]).unwrap();
+        assert_eq!(args.stdin_mode, StdinMode::Consume);
+        let args =
+            try_parse_from(["procguard", "-S", "10s", "--stdin-mode", "pty", "5s", "cmd"]).unwrap();
+        assert_eq!(args.stdin_mode, StdinMode::Pty);
+        let args =
+            try_parse_from(["procguard", "-S", "10s", "--stdin-mode=Watch", "5s", "cmd"]).unwrap();
+        assert_eq!(args.stdin_mode, StdinMode::Watch);
+
+        let err = try_parse_from(["procguard", "--stdin-mode=tee", "5s", "cmd"]).unwrap_err();
+        assert!(err.message.contains("invalid --stdin-mode"));
+        assert!(try_parse_from(["procguard", "--stdin-mode"]).is_err());
+    }
+
+    #[test]
+    fn test_child_stdin() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.child_stdin, ChildStdin::Inherit);
+        let args = try_parse_from(["procguard", "--stdin", "null", "5s", "cmd"]).unwrap();
+        assert_eq!(args.child_stdin, ChildStdin::Null);
+        let args = try_parse_from(["procguard", "--stdin=close", "5s", "cmd"]).unwrap();
+        assert_eq!(args.child_stdin, ChildStdin::Close);
+        let args = try_parse_from(["procguard", "--stdin=file:in:put.txt", "5s", "cmd"]).unwrap();
+        assert_eq!(args.child_stdin, ChildStdin::File("in:put.txt".to_string()));
+
+        for bad in ["file:", "zero", "NULL"] {
+            let err = try_parse_from(["procguard", "--stdin", bad, "5s", "cmd"]).unwrap_err();
+            assert!(err.message.contains("invalid --stdin"));
+        }
+        assert!(try_parse_from(["procguard", "--stdin"]).is_err());
+    }
+
+    #[test]
+    fn test_stdin_inject() {
+        let args = try_parse_from([
+            "procguard",
+            "--stdin-inject-on-timeout",
+            "quit\\n",
+            "--stdin-inject-wait=2s",
+            "5s",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.stdin_inject.as_deref(), Some(&b"quit\n"[..]));
+        assert_eq!(args.stdin_inject_wait, Some("2s".to_string()));
+
+        let args = try_parse_from([
+            "procguard",
+            "--stdin-inject-on-timeout=\\x03\\e:q!\\r\\\\",
+            "5s",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.stdin_inject.as_deref(), Some(&b"\x03\x1b:q!\r\\"[..]));
+        assert!(args.stdin_inject_wait.is_none());
+
+        for bad in ["", "a\\", "\\q", "\\x4"] {
+            let err = try_parse_from(["procguard", "--stdin-inject-on-timeout", bad, "5s", "cmd"])
+                .unwrap_err();
+            assert!(err.message.contains("invalid --stdin-inject-on-timeout"));
+        }
+        let err =
+            try_parse_from(["procguard", "--stdin-inject-wait", "1s", "5s", "cmd"]).unwrap_err();
+        assert!(err.message.contains("requires --stdin-inject-on-timeout"));
+    }
+
     #[test]
     fn test_stdin_timeout_equals_syntax() {
         let args = try_parse_from(["procguard", "--stdin-timeout=1m", "5s", "cmd"]).unwrap();
@@ -1403,4 +5652,513 @@ mod tests {
         assert!(result.is_err());
         assert!(result.unwrap_err().message.contains("requires a duration"));
     }
+
+    #[test]
+    fn test_json_fd_flag() {
+        let args = try_parse_from(["procguard", "--json", "5s", "cmd"]).unwrap();
+        assert_eq!(args.json_fd, None);
+
+        let args = try_parse_from(["procguard", "--json-fd", "3", "5s", "cmd"]).unwrap();
+        assert!(args.json);
+        assert_eq!(args.json_fd, Some(3));
+
+        let args = try_parse_from(["procguard", "--json-fd=7", "5s", "cmd"]).unwrap();
+        assert_eq!(args.json_fd, Some(7));
+
+        let args = try_parse_from(["procguard", "--json-stderr", "5s", "cmd"]).unwrap();
+        assert!(args.json);
+        assert_eq!(args.json_fd, Some(2));
+
+        assert!(try_parse_from(["procguard", "--json-fd", "-1", "5s", "cmd"]).is_err());
+        assert!(try_parse_from(["procguard", "--json-fd", "x", "5s", "cmd"]).is_err());
+        assert!(try_parse_from(["procguard", "--json-fd"]).is_err());
+    }
+
+    #[test]
+    fn test_result_file_flag() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.result_file, None);
+        let args =
+            try_parse_from(["procguard", "--result-file", "/tmp/r.json", "5s", "cmd"]).unwrap();
+        assert_eq!(args.result_file, Some("/tmp/r.json".to_string()));
+        let args = try_parse_from(["procguard", "--result-file=out.json", "5s", "cmd"]).unwrap();
+        assert_eq!(args.result_file, Some("out.json".to_string()));
+        assert!(try_parse_from(["procguard", "--result-file"]).is_err());
+    }
+
+    #[test]
+    fn test_explain_flag() {
+        let args = try_parse_from(["procguard", "--explain", "5s", "cmd"]).unwrap();
+        assert!(args.explain);
+
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert!(!args.explain);
+        assert!(!args.proc_tree);
+
+        let args = try_parse_from(["procguard", "--proc-tree", "5s", "cmd"]).unwrap();
+        assert!(args.proc_tree);
+        assert!(!args.kill_escaped);
+
+        let args = try_parse_from(["procguard", "--kill-escaped", "5s", "cmd"]).unwrap();
+        assert!(args.kill_escaped);
+        assert!(args.proc_tree);
+        assert!(!args.reap_orphans);
+
+        /* unlike --kill-escaped, no tree needed */
+        let args = try_parse_from(["procguard", "--reap-orphans", "5s", "cmd"]).unwrap();
+        assert!(args.reap_orphans);
+        assert!(!args.proc_tree);
+    }
+
+    #[test]
+    fn test_also_kill() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.also_kill, None);
+        let args = try_parse_from(["procguard", "--also-kill", "42,43", "5s", "cmd"]).unwrap();
+        assert_eq!(args.also_kill, Some(AlsoKill::Pids(alloc::vec![42, 43])));
+        let args = try_parse_from(["procguard", "--also-kill=emu.pid", "5s", "cmd"]).unwrap();
+        assert_eq!(args.also_kill, Some(AlsoKill::File("emu.pid".to_string())));
+
+        let err = try_parse_from(["procguard", "--also-kill", "1", "5s", "cmd"]).unwrap_err();
+        assert!(
+            err.message.contains("invalid --also-kill"),
+            "{}",
+            err.message
+        );
+        assert!(try_parse_from(["procguard", "--also-kill"]).is_err());
+        let err = try_parse_from(["procguard", "--sleep", "--also-kill", "42", "1s"]).unwrap_err();
+        assert!(err.message.contains("--also-kill"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_crash_report_flag() {
+        let args = try_parse_from(["procguard", "--crash-report", "5s", "cmd"]).unwrap();
+        assert!(args.crash_report);
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert!(!args.crash_report);
+    }
+
+    #[test]
+    fn test_version_json() {
+        let json = version_json();
+        assert!(
+            json.starts_with(r#"{"name":"procguard","version":""#),
+            "{json}"
+        );
+        assert!(json.contains(r#""git_hash":"#), "{json}");
+        assert!(json.contains(r#""schema_versions":[58]"#), "{json}");
+        /* only compiled-in features are listed */
+        assert_eq!(
+            json.contains(r#""crash-report""#),
+            cfg!(feature = "crash-report")
+        );
+        assert_eq!(
+            json.contains(r#""alloc-stats""#),
+            cfg!(feature = "alloc-stats")
+        );
+        assert!(json.ends_with("]}"), "{json}");
+
+        assert!(wants_json(&["--json"]));
+        assert!(!wants_json(&["-v"]));
+    }
+
+    #[test]
+    fn test_env_options() {
+        let words = |opts: &str| -> Vec<String> {
+            env_options(opts)
+                .unwrap()
+                .into_iter()
+                .map(|w| String::from_utf8(w).unwrap())
+                .collect()
+        };
+        assert_eq!(
+            words("-k 5s --mem-limit=1G --on-timeout 'kill -QUIT %p' -v"),
+            [
+                "-k",
+                "5s",
+                "--mem-limit=1G",
+                "--on-timeout",
+                "kill -QUIT %p",
+                "-v"
+            ]
+        );
+        assert!(words("").is_empty());
+        /* checked on their own: a requirement can be met on the command line */
+        assert_eq!(words("--keep-temp-on-failure"), ["--keep-temp-on-failure"]);
+        /* a value that looks like an operand is still a value */
+        assert_eq!(words("--tag @ci"), ["--tag", "@ci"]);
+        assert_eq!(words("-t 30s"), ["-t", "30s"]);
+
+        let err = |opts: &str| env_options(opts).unwrap_err().message;
+        assert_eq!(
+            err("--json 5s"),
+            "TIMEOUT_OPTS: '5s' isn't an option (the duration and command go on the command line)"
+        );
+        assert_eq!(err("-k"), "TIMEOUT_OPTS: '-k' is missing its value");
+        assert_eq!(
+            err("-v --"),
+            "TIMEOUT_OPTS: '--' belongs on the command line"
+        );
+        assert_eq!(err("--tag 'open"), "TIMEOUT_OPTS: unterminated ' quote");
+        assert!(err("--launchd-mode=always").starts_with("TIMEOUT_OPTS: invalid --launchd-mode"));
+    }
+
+    /* a value --long takes, for the table-wide tests */
+    fn sample_value(long: &str) -> &'static str {
+        [
+            "1",
+            "1s",
+            "10",
+            "x",
+            "1G",
+            "22:00-06:00",
+            "stop",
+            "active",
+            "pause",
+            "never",
+            "env",
+            "shared",
+            "new",
+            "arm64",
+            "utility",
+            "null",
+            "consume",
+            "drop",
+            "0600",
+            "relative",
+            "pass",
+            "kill",
+        ]
+        .into_iter()
+        .find(|v| scan_slice(&[format!("--{long}={v}").as_str(), "0"]).is_ok())
+        .unwrap_or_else(|| panic!("no sample value for --{long}"))
+    }
+
+    #[test]
+    fn test_env_fallbacks() {
+        let fallbacks = |args: &[&str], env: &[(&str, &str)]| -> Vec<(String, String)> {
+            env_fallbacks(args, |var| {
+                env.iter()
+                    .find(|(name, _)| *name == var)
+                    .map(|(_, v)| v.to_string())
+            })
+            .unwrap()
+        };
+        /* every option: the command line > TIMEOUT_<OPTION> > the default */
+        for opt in env_fallback_options() {
+            let var = env_var_name(opt.long);
+            let long = format!("--{}", opt.long);
+            let (value, want) = match opt.value {
+                "" => ("1", long.clone()),
+                v if v.starts_with('[') => ("yes", long.clone()),
+                _ => {
+                    let v = sample_value(opt.long);
+                    (v, format!("{long}={v}"))
+                }
+            };
+            let env = [(var.as_str(), value)];
+            assert!(fallbacks(&["5s", "cmd"], &[]).is_empty(), "{var}");
+            assert_eq!(
+                fallbacks(&["5s", "cmd"], &env),
+                [(var.clone(), want.clone())],
+                "{var}"
+            );
+            let given = if opt.value.starts_with(' ') {
+                vec![long.as_str(), value, "5s", "cmd"]
+            } else {
+                vec![long.as_str(), "5s", "cmd"]
+            };
+            if parse_from_slice(&given).is_ok() {
+                assert!(fallbacks(&given, &env).is_empty(), "{var} over {long}");
+            }
+            /* the command's own arguments aren't ours */
+            let args = ["5s", "cmd", long.as_str()];
+            assert_eq!(fallbacks(&args, &env).len(), 1, "{var}");
+        }
+
+        assert_eq!(env_var_name("json-fd"), "TIMEOUT_JSON_FD");
+        /* what a run holds or writes isn't inherited by one it runs */
+        for long in [
+            "flock",
+            "pidfile",
+            "result-file",
+            "json-fd",
+            "also-kill",
+            "temp-dir",
+        ] {
+            assert!(env_fallback_options().all(|o| o.long != long), "{long}");
+            let var = env_var_name(long);
+            assert!(fallbacks(&["5s", "cmd"], &[(var.as_str(), "3")]).is_empty());
+        }
+        let names: Vec<String> = env_fallback_options()
+            .map(|o| env_var_name(o.long))
+            .collect();
+        for name in &names {
+            assert_eq!(names.iter().filter(|n| *n == name).count(), 1, "{name}");
+            /* taken: TIMEOUT_EXTEND is --extend-env-file's key, the rest ours */
+            assert!(
+                !["TIMEOUT_OPTS", "TIMEOUT_EXTEND", "TIMEOUT_THROTTLED"].contains(&name.as_str())
+            );
+        }
+
+        let json = [("TIMEOUT_JSON", "yes")];
+        let tag = [("TIMEOUT_TAG", "ci")];
+        let json_set = || vec![("TIMEOUT_JSON".to_string(), "--json".to_string())];
+        /* a value that looks like an option is still a value */
+        assert_eq!(
+            fallbacks(&["--tag", "--json", "5s", "cmd"], &json),
+            json_set()
+        );
+        /* clusters and short forms count */
+        assert!(fallbacks(&["-vt", "5s", "cmd"], &[("TIMEOUT_VERBOSE", "1")]).is_empty());
+        assert!(
+            fallbacks(
+                &["--json-stderr", "5s", "cmd"],
+                &[("TIMEOUT_JSON_STDERR", "1")]
+            )
+            .is_empty()
+        );
+        /* after --, everything is the command's */
+        assert_eq!(fallbacks(&["5s", "--", "cmd", "--json"], &json), json_set());
+        assert!(fallbacks(&["--tag=x", "5s", "cmd"], &tag).is_empty());
+        /* off, or empty: unset */
+        assert!(
+            fallbacks(
+                &["5s", "cmd"],
+                &[("TIMEOUT_JSON", "0"), ("TIMEOUT_TAG", "")]
+            )
+            .is_empty()
+        );
+        assert_eq!(
+            fallbacks(&["5s", "cmd"], &[("TIMEOUT_KILL_SELF_ON_TIMEOUT", "USR1")]),
+            [(
+                "TIMEOUT_KILL_SELF_ON_TIMEOUT".to_string(),
+                "--kill-self-on-timeout=USR1".to_string()
+            )]
+        );
+        /* the old variables aren't read here */
+        assert!(fallbacks(&["5s", "cmd"], &[("TIMEOUT_RETRY", "3")]).is_empty());
+        /* --sleep and --wait-pid take what they can of the inherited ones */
+        let inherited = [("TIMEOUT_JSON", "1"), ("TIMEOUT_TAG", "ci")];
+        assert_eq!(
+            fallbacks(&["--sleep", "1s"], &inherited),
+            [("TIMEOUT_TAG".to_string(), "--tag=ci".to_string())]
+        );
+        assert_eq!(fallbacks(&["--wait-pid", "1", "1s"], &inherited).len(), 2);
+
+        let err = |env: &[(&str, &str)]| {
+            env_fallbacks(&["5s", "cmd"], |var| {
+                env.iter()
+                    .find(|(name, _)| *name == var)
+                    .map(|(_, v)| v.to_string())
+            })
+            .unwrap_err()
+            .message
+        };
+        assert_eq!(
+            err(&[("TIMEOUT_JSON", "on")]),
+            "TIMEOUT_JSON: 'on' isn't 1 or 0 (or true/false, yes/no)"
+        );
+        assert!(
+            err(&[("TIMEOUT_SPAWN_RETRY", "99")])
+                .starts_with("TIMEOUT_SPAWN_RETRY: invalid --spawn-retry")
+        );
+    }
+
+    #[test]
+    fn test_response_file_slot() {
+        /* duration or command slot; never the command's args or after -- */
+        let marked = |args: &[&str]| parse_from_slice(args).unwrap().response_file;
+        assert_eq!(marked(&["@a"]), Some(0));
+        assert_eq!(marked(&["--json", "5s", "@a", "@b"]), Some(2));
+        assert_eq!(marked(&["5s", "cmd", "@a"]), None);
+        assert_eq!(marked(&["5s", "--", "@a"]), None);
+        assert_eq!(marked(&["--on-timeout", "@a", "5s", "cmd"]), None);
+        assert_eq!(marked(&["5s", "@"]), None);
+    }
+
+    #[test]
+    fn test_pidfile_flags() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.pidfile, None);
+        assert!(!args.print_pid);
+        let args = try_parse_from(["procguard", "--pidfile", "/tmp/p", "5s", "cmd"]).unwrap();
+        assert_eq!(args.pidfile, Some("/tmp/p".to_string()));
+        let args = try_parse_from(["procguard", "--pidfile=run.pid", "5s", "cmd"]).unwrap();
+        assert_eq!(args.pidfile, Some("run.pid".to_string()));
+        assert!(try_parse_from(["procguard", "--pidfile"]).is_err());
+
+        let args = try_parse_from(["procguard", "--print-pid", "5s", "cmd"]).unwrap();
+        assert!(args.print_pid);
+        assert!(args.json);
+    }
+
+    #[test]
+    fn test_prefix_output_flag() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.prefix_output, None);
+        let args =
+            try_parse_from(["procguard", "--prefix-output", "[job-42] ", "5s", "cmd"]).unwrap();
+        assert_eq!(args.prefix_output, Some("[job-42] ".to_string()));
+        let args = try_parse_from(["procguard", "--prefix-output=", "5s", "cmd"]).unwrap();
+        assert_eq!(args.prefix_output, Some(String::new()));
+        assert!(try_parse_from(["procguard", "--prefix-output"]).is_err());
+    }
+
+    #[test]
+    fn test_export_remaining_flags() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.export_remaining, None);
+        assert_eq!(args.export_remaining_file, None);
+        let args = try_parse_from([
+            "procguard",
+            "--export-remaining",
+            "JOB_TIMEOUT",
+            "5s",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.export_remaining, Some("JOB_TIMEOUT".to_string()));
+        let args = try_parse_from([
+            "procguard",
+            "--export-remaining=T",
+            "--export-remaining-file=left",
+            "5s",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.export_remaining, Some("T".to_string()));
+        assert_eq!(args.export_remaining_file, Some("left".to_string()));
+        assert!(try_parse_from(["procguard", "--export-remaining"]).is_err());
+        assert!(try_parse_from(["procguard", "--export-remaining-file"]).is_err());
+    }
+
+    #[test]
+    fn test_extend_env_file_flags() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.extend_env_file, None);
+        assert_eq!(args.extend_max, None);
+        let args =
+            try_parse_from(["procguard", "--extend-env-file", "ci.env", "5s", "cmd"]).unwrap();
+        assert_eq!(args.extend_env_file, Some("ci.env".to_string()));
+        let args = try_parse_from([
+            "procguard",
+            "--extend-env-file=ci.env",
+            "--extend-max=10m",
+            "5s",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.extend_env_file, Some("ci.env".to_string()));
+        assert_eq!(args.extend_max, Some("10m".to_string()));
+        assert!(try_parse_from(["procguard", "--extend-env-file"]).is_err());
+        assert!(try_parse_from(["procguard", "--extend-env-file=e", "--extend-max"]).is_err());
+        let err = try_parse_from(["procguard", "--extend-max", "1m", "5s", "cmd"]);
+        assert!(
+            err.unwrap_err()
+                .message
+                .contains("requires --extend-env-file")
+        );
+        let err = try_parse_from(["procguard", "--sleep", "--extend-env-file=e", "5s"]);
+        assert!(err.unwrap_err().message.contains("cannot be used with"));
+    }
+
+    #[test]
+    fn test_file_mode_flags() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.file_mode, None);
+        assert!(!args.no_fsync);
+        let args = try_parse_from([
+            "procguard",
+            "--result-file=r.json",
+            "--file-mode",
+            "0600",
+            "--no-fsync",
+            "5s",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.file_mode, Some(0o600));
+        assert!(args.no_fsync);
+        let args = try_parse_from(["procguard", "--pidfile=p", "--file-mode=640", "5s", "cmd"]);
+        assert_eq!(args.unwrap().file_mode, Some(0o640));
+        for bad in ["", "0800", "1777", "rw", "+600"] {
+            let mode = std::format!("--file-mode={bad}");
+            let err = try_parse_from(["procguard", "--pidfile=p", mode.as_str(), "5s", "cmd"]);
+            assert!(
+                err.unwrap_err().message.contains("invalid --file-mode"),
+                "{bad}"
+            );
+        }
+        let err = try_parse_from(["procguard", "--no-fsync", "5s", "cmd"]);
+        assert!(err.unwrap_err().message.contains("requires --result-file"));
+    }
+
+    #[test]
+    fn test_temp_dir_flags() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.temp_dir, None);
+        assert!(!args.keep_temp_on_failure);
+        let args = try_parse_from(["procguard", "--temp-dir", "5s", "cmd"]).unwrap();
+        assert_eq!(args.temp_dir, Some(String::new()));
+        let args = try_parse_from([
+            "procguard",
+            "--temp-dir=/scratch",
+            "--keep-temp-on-failure",
+            "5s",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.temp_dir, Some("/scratch".to_string()));
+        assert!(args.keep_temp_on_failure);
+        let err = try_parse_from(["procguard", "--keep-temp-on-failure", "5s", "cmd"]);
+        assert!(err.unwrap_err().message.contains("requires --temp-dir"));
+    }
+
+    #[test]
+    fn test_timestamps_flag() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.timestamps, None);
+        assert!(!args.raw_output);
+        let args = try_parse_from(["procguard", "--timestamps", "relative", "5s", "cmd"]).unwrap();
+        assert_eq!(args.timestamps, Some(Timestamps::Relative));
+        let args = try_parse_from(["procguard", "--timestamps=ABSOLUTE", "5s", "cmd"]).unwrap();
+        assert_eq!(args.timestamps, Some(Timestamps::Absolute));
+        assert!(try_parse_from(["procguard", "--timestamps=iso", "5s", "cmd"]).is_err());
+        assert!(try_parse_from(["procguard", "--timestamps"]).is_err());
+        let args = try_parse_from(["procguard", "--raw-output", "5s", "cmd"]).unwrap();
+        assert!(args.raw_output);
+    }
+
+    #[test]
+    fn test_merge_output_flag() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert!(!args.merge_output);
+        let args = try_parse_from(["procguard", "--merge-output", "5s", "cmd"]).unwrap();
+        assert!(args.merge_output);
+    }
+
+    #[test]
+    fn test_output_rate_flags() {
+        let args = try_parse_from(["procguard", "5s", "cmd"]).unwrap();
+        assert_eq!(args.output_rate_limit, None);
+        assert_eq!(args.output_rate_policy, RatePolicy::Drop);
+        let args = try_parse_from([
+            "procguard",
+            "--output-rate-limit",
+            "64K",
+            "--output-rate-policy=block",
+            "5s",
+            "cmd",
+        ])
+        .unwrap();
+        assert_eq!(args.output_rate_limit, Some(64 * 1024));
+        assert_eq!(args.output_rate_policy, RatePolicy::Block);
+        let args = try_parse_from(["procguard", "--output-rate-limit=1M/s", "5s", "cmd"]).unwrap();
+        assert_eq!(args.output_rate_limit, Some(1024 * 1024));
+        assert!(try_parse_from(["procguard", "--output-rate-limit=0", "5s", "cmd"]).is_err());
+        assert!(try_parse_from(["procguard", "--output-rate-limit=fast", "5s", "cmd"]).is_err());
+        assert!(try_parse_from(["procguard", "--output-rate-policy=stall", "5s", "cmd"]).is_err());
+        assert!(try_parse_from(["procguard", "--output-rate-limit"]).is_err());
+    }
 }
@@ -0,0 +1,305 @@
+/*
+ * compat.rs
+ *
+ * `procguard --compat-check [GNU-TIMEOUT]`, hidden: run one matrix of
+ * command lines under us and under GNU timeout, and list where the two
+ * differ - in the exit code, or in when the exit came (a deadline one of
+ * them kept and the other didn't). GNU parity is what `timeout` and
+ * `gtimeout` promise; this makes it something CI can check instead of
+ * something the next extension quietly breaks.
+ *
+ * we run as invoked: `timeout --compat-check` checks the timeout
+ * personality, `gtimeout --compat-check` is refused like any option GNU
+ * doesn't have. both sides go through `sh -c 'exec "$@" >/dev/null 2>&1'`,
+ * so their messages stay out of the report.
+ *
+ * the reference is the path given, else the first of REFERENCES that can
+ * be run. finding none isn't a failure: there's nothing to compare with.
+ * exit 0 if every scenario matched, 1 if one didn't, 125 if the check
+ * itself couldn't run. a difference listed in KNOWN is reported, but
+ * doesn't fail the check.
+ */
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::time::Duration;
+
+use crate::error::exit_codes;
+use crate::io::BufWriter;
+use crate::runner::{RunConfig, RunResult, run_command, status_to_exit_code, wall_now_ns};
+
+/// Where GNU timeout lives when no path is given: Homebrew's coreutils
+/// (Apple Silicon, then Intel), then a system one.
+pub const REFERENCES: [&str; 4] = [
+    "/opt/homebrew/bin/gtimeout",
+    "/usr/local/bin/gtimeout",
+    "/usr/bin/timeout",
+    "/bin/timeout",
+];
+
+/// Elapsed times further apart than this, plus a fifth of the longer one,
+/// count as a timing difference. Wide enough for a loaded CI machine, not
+/// for a deadline missed by a second.
+pub const TIMING_SLACK_MS: u64 = 250;
+
+/* each side gets this long before it counts as hung */
+const SCENARIO_LIMIT: Duration = Duration::from_secs(10);
+
+/* the arguments after the timeout binary. GNU's own options only, and
+ * durations short enough that the matrix takes seconds */
+const SCENARIOS: &[&[&str]] = &[
+    &["5", "true"],
+    &["5", "false"],
+    &["5", "sh", "-c", "exit 7"],
+    &["5", "sh", "-c", "kill -TERM $$"],
+    &["0.2", "sleep", "5"],
+    &["0.005m", "sleep", "5"],
+    &["0", "sh", "-c", "sleep 0.3; exit 3"],
+    &["--preserve-status", "0.2", "sleep", "5"],
+    &["--preserve-status", "5", "sh", "-c", "exit 9"],
+    &["-s", "INT", "0.2", "sleep", "5"],
+    &["-s", "INT", "--preserve-status", "0.2", "sleep", "5"],
+    &["-s", "KILL", "0.2", "sleep", "5"],
+    &["-k", "0.3", "0.2", "sh", "-c", "trap '' TERM; sleep 5"],
+    &[
+        "--preserve-status",
+        "-k",
+        "0.3",
+        "0.2",
+        "sh",
+        "-c",
+        "trap '' TERM; sleep 5",
+    ],
+    &["-k", "5", "0.2", "sh", "-c", "trap 'exit 4' TERM; sleep 5"],
+    &["--foreground", "0.2", "sleep", "5"],
+    &["-v", "0.2", "sleep", "5"],
+    &["5", "/nonexistent/command"],
+    &["5", "/"],
+    &["bogus", "true"],
+    &["-s", "NOPE", "1", "true"],
+    &["--no-such-option", "1", "true"],
+    &["5"],
+];
+
+/*
+ * where `timeout` means to differ from GNU, and how. GNU exits 128+9, not
+ * 124, when the command it timed out died of SIGKILL; `timeout` exits 124
+ * (or --timeout-exit-code) for every timeout, so a script tells a timeout
+ * from a crash by one code. `gtimeout` exits 137 like GNU
+ */
+const KNOWN: &[(&[&str], &str)] = &[
+    (&["-s", "KILL", "0.2", "sleep", "5"], "exit 124 vs 137"),
+    (
+        &["-k", "0.3", "0.2", "sh", "-c", "trap '' TERM; sleep 5"],
+        "exit 124 vs 137",
+    ),
+];
+
+/* the difference KNOWN expects for args, if any */
+fn known(args: &[&str]) -> Option<&'static str> {
+    KNOWN
+        .iter()
+        .find(|(scenario, _)| *scenario == args)
+        .map(|(_, why)| *why)
+}
+
+/// How one side ended one scenario.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Observed {
+    /// Exit code as a shell reports it (128 + N for signal N), or None if
+    /// it was still running after the scenario limit.
+    pub code: Option<u8>,
+    /// How long it ran, in milliseconds.
+    pub elapsed_ms: u64,
+}
+
+/// Why two [`Observed`] differ, if they do.
+#[must_use]
+pub fn difference(ours: Observed, reference: Observed) -> Option<String> {
+    if ours.code != reference.code {
+        let code = |c: Option<u8>| c.map_or_else(|| "hung".to_string(), |c| c.to_string());
+        return Some(format!(
+            "exit {} vs {}",
+            code(ours.code),
+            code(reference.code)
+        ));
+    }
+    let longer = ours.elapsed_ms.max(reference.elapsed_ms);
+    if ours.elapsed_ms.abs_diff(reference.elapsed_ms) > TIMING_SLACK_MS + longer / 5 {
+        return Some(format!(
+            "took {}ms vs {}ms",
+            ours.elapsed_ms, reference.elapsed_ms
+        ));
+    }
+    None
+}
+
+/* `timeout` with args under sh, output dropped. Err: couldn't run sh */
+fn observe(timeout: &str, args: &[&str]) -> Result<Observed, String> {
+    let argv: Vec<String> = ["-c", "exec \"$@\" >/dev/null 2>&1", "sh", timeout]
+        .iter()
+        .chain(args)
+        .map(|a| (*a).to_string())
+        .collect();
+    let config = RunConfig {
+        timeout: SCENARIO_LIMIT,
+        kill_after: Some(Duration::from_secs(1)),
+        ..RunConfig::default()
+    };
+    let start = wall_now_ns().map_err(|e| e.to_string())?;
+    let result = run_command("sh", &argv, &config).map_err(|e| e.to_string())?;
+    let elapsed_ms = wall_now_ns()
+        .map_err(|e| e.to_string())?
+        .saturating_sub(start)
+        / 1_000_000;
+    let code = match result {
+        RunResult::Completed { status, .. } => Some(status_to_exit_code(&status)),
+        _ => None,
+    };
+    Ok(Observed { code, elapsed_ms })
+}
+
+/* X_OK, as the file system sees it */
+fn runnable(path: &str) -> bool {
+    let mut c_path = Vec::with_capacity(path.len() + 1);
+    c_path.extend_from_slice(path.as_bytes());
+    c_path.push(0);
+    // SAFETY: c_path is NUL-terminated and outlives the call.
+    unsafe { libc::access(c_path.as_ptr().cast(), libc::X_OK) == 0 }
+}
+
+/// Run the matrix with `ours` (our argv\[0\]) against `reference`, or the
+/// first of [`REFERENCES`] that can be run; report on stdout. Returns the
+/// exit code: 0 all matched (or nothing to compare with), 1 some differ,
+/// 125 the check couldn't run.
+pub fn run(ours: &str, reference: Option<&str>) -> u8 {
+    let mut out = BufWriter::stdout();
+    let reference = match reference {
+        /* a bare name is left to sh's PATH search */
+        Some(path) if path.contains('/') && !runnable(path) => {
+            let _ = writeln!(out, "compat-check: can't run '{}'", path);
+            let _ = out.flush();
+            return exit_codes::INTERNAL_ERROR;
+        }
+        Some(path) => path,
+        None => match REFERENCES.iter().find(|p| runnable(p)) {
+            Some(path) => path,
+            None => {
+                let _ = writeln!(
+                    out,
+                    "compat-check: no GNU timeout found (looked in {}); give its path to compare",
+                    REFERENCES.join(", ")
+                );
+                let _ = out.flush();
+                return 0;
+            }
+        },
+    };
+    let _ = writeln!(out, "compat-check: {} against {}", ours, reference);
+    let _ = out.flush();
+
+    let mut differ = 0;
+    let mut expected = 0;
+    for args in SCENARIOS {
+        let (ours_seen, reference_seen) = match (observe(ours, args), observe(reference, args)) {
+            (Ok(a), Ok(b)) => (a, b),
+            (Err(e), _) | (_, Err(e)) => {
+                let _ = writeln!(out, "compat-check: can't run scenarios: {}", e);
+                let _ = out.flush();
+                return exit_codes::INTERNAL_ERROR;
+            }
+        };
+        let line = args.join(" ");
+        match difference(ours_seen, reference_seen) {
+            Some(why) if known(args) == Some(why.as_str()) => {
+                expected += 1;
+                let _ = writeln!(out, "known {}: {}", line, why);
+            }
+            Some(why) => {
+                differ += 1;
+                let _ = writeln!(out, "DIFF  {}: {}", line, why);
+            }
+            None => {
+                let code = ours_seen.code.map_or(-1, i32::from);
+                let _ = writeln!(
+                    out,
+                    "ok    {}: exit {}, {}ms vs {}ms",
+                    line, code, ours_seen.elapsed_ms, reference_seen.elapsed_ms
+                );
+            }
+        }
+        let _ = out.flush();
+    }
+    let _ = writeln!(
+        out,
+        "compat-check: {} of {} scenarios differ ({} known)",
+        differ,
+        SCENARIOS.len(),
+        expected
+    );
+    let _ = out.flush();
+    u8::from(differ > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_difference() {
+        let seen = |code, elapsed_ms| Observed { code, elapsed_ms };
+        assert_eq!(difference(seen(Some(124), 210), seen(Some(124), 205)), None);
+        assert_eq!(
+            difference(seen(Some(125), 5), seen(Some(124), 205)).as_deref(),
+            Some("exit 125 vs 124")
+        );
+        assert_eq!(
+            difference(seen(None, 10_000), seen(Some(124), 500)).as_deref(),
+            Some("exit hung vs 124")
+        );
+        /* a deadline a second late is a difference, scheduling noise isn't */
+        assert_eq!(difference(seen(Some(0), 400), seen(Some(0), 200)), None);
+        assert_eq!(
+            difference(seen(Some(124), 1_200), seen(Some(124), 200)).as_deref(),
+            Some("took 1200ms vs 200ms")
+        );
+    }
+
+    #[test]
+    fn test_known_are_scenarios() {
+        for (args, why) in KNOWN {
+            assert!(SCENARIOS.contains(args), "{args:?}");
+            assert_eq!(known(args), Some(*why));
+        }
+        assert_eq!(known(&["5", "true"]), None);
+    }
+
+    #[test]
+    fn test_scenarios_are_gnu_options() {
+        /* nothing GNU timeout doesn't take, except the one meant to fail.
+         * "-c" and the rest after the duration are the command's */
+        for args in SCENARIOS {
+            let duration = args
+                .iter()
+                .position(|a| a.starts_with(|c: char| c.is_ascii_digit() || c == 'b'));
+            for arg in args[..duration.unwrap_or(0)]
+                .iter()
+                .filter(|a| a.starts_with('-'))
+            {
+                assert!(
+                    matches!(
+                        *arg,
+                        "-s" | "-k"
+                            | "-v"
+                            | "--preserve-status"
+                            | "--foreground"
+                            | "--no-such-option"
+                    ),
+                    "{arg}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,222 @@
+/*
+ * crash.rs
+ *
+ * find the crash report macOS wrote for a child that died of SIGSEGV,
+ * SIGABRT and friends (--crash-report).
+ *
+ * ReportCrash catches the mach exception behind the signal - EXC_BAD_ACCESS
+ * for SIGSEGV/SIGBUS, EXC_CRASH for SIGABRT - and writes
+ * <process>-<date>-<time>.ips (.crash before macOS 12) under
+ * ~/Library/Logs/DiagnosticReports, or /Library/Logs/DiagnosticReports
+ * for root. simulator apps crash as host processes and land in the same
+ * place. the file shows up a second or so after the exit, so we poll a
+ * short while.
+ *
+ * the process name in the file name can differ from argv[0] (symlinks,
+ * truncation), so we don't rely on it: a candidate is any report modified
+ * since the spawn, and it's ours if its header has our pid. .ips bodies
+ * carry `"pid" : 123`, .crash files `Process: name [123]`.
+ *
+ * built without the crash-report feature, or off macOS, nothing is ever
+ * found.
+ */
+#![cfg_attr(
+    any(not(feature = "crash-report"), not(target_os = "macos")),
+    allow(dead_code)
+)]
+
+use alloc::string::String;
+use core::time::Duration;
+
+/// How long to wait for a report to appear after the crash.
+pub const REPORT_WINDOW: Duration = Duration::from_secs(3);
+
+/* between directory scans */
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/* the pid is in the first few hundred bytes of either format */
+const HEAD_LEN: usize = 4096;
+
+const SYSTEM_REPORTS: &str = "/Library/Logs/DiagnosticReports";
+
+/// Path of the crash report for `pid`, written no earlier than
+/// `since_epoch_ms`. Waits up to `window` for it to appear; None if it
+/// doesn't.
+#[cfg(all(feature = "crash-report", target_os = "macos"))]
+pub fn find_report(pid: u32, since_epoch_ms: u64, window: Duration) -> Option<String> {
+    let user = crate::args::get_env(b"HOME\0").map(|home| {
+        let mut dir = home;
+        dir.push_str(SYSTEM_REPORTS);
+        dir
+    });
+    let since_s = since_epoch_ms / 1000;
+    let mut waited = Duration::ZERO;
+    loop {
+        let dirs = [user.as_deref(), Some(SYSTEM_REPORTS)];
+        if let Some(path) = dirs
+            .into_iter()
+            .flatten()
+            .find_map(|dir| scan_dir(dir, pid, since_s))
+        {
+            return Some(path);
+        }
+        if waited >= window {
+            return None;
+        }
+        crate::wait::kqueue_delay(POLL_INTERVAL, None);
+        waited += POLL_INTERVAL;
+    }
+}
+
+/// Always None: built without crash-report, or not on macOS.
+#[cfg(not(all(feature = "crash-report", target_os = "macos")))]
+pub fn find_report(_pid: u32, _since_epoch_ms: u64, _window: Duration) -> Option<String> {
+    None
+}
+
+/* first report in `dir` modified at or after `since_s` that names `pid` */
+#[cfg(all(feature = "crash-report", target_os = "macos"))]
+fn scan_dir(dir: &str, pid: u32, since_s: u64) -> Option<String> {
+    let mut path = String::with_capacity(dir.len() + 64);
+    path.push_str(dir);
+    path.push('\0');
+    // SAFETY: path is null-terminated
+    let handle = unsafe { libc::opendir(path.as_ptr().cast()) };
+    if handle.is_null() {
+        return None;
+    }
+
+    let mut found = None;
+    loop {
+        // SAFETY: handle is an open DIR, only used by this loop
+        let entry = unsafe { libc::readdir(handle) };
+        if entry.is_null() {
+            break;
+        }
+        // SAFETY: readdir returned a valid dirent; d_name is null-terminated
+        // and stays valid until the next readdir on this handle.
+        #[allow(clippy::multiple_unsafe_ops_per_block)]
+        let name = unsafe { core::ffi::CStr::from_ptr((*entry).d_name.as_ptr()) }.to_bytes();
+        if !(name.ends_with(b".ips") || name.ends_with(b".crash")) {
+            continue;
+        }
+        let Ok(name) = core::str::from_utf8(name) else {
+            continue;
+        };
+
+        path.clear();
+        path.push_str(dir);
+        path.push('/');
+        path.push_str(name);
+        path.push('\0');
+        if modified_since(&path, since_s) && head_mentions_pid(&path, pid) {
+            path.pop(); /* the terminator */
+            found = Some(path);
+            break;
+        }
+    }
+    // SAFETY: handle is open and closed once here
+    unsafe { libc::closedir(handle) };
+    found
+}
+
+/* stat mtime >= since_s. `path` is null-terminated */
+#[cfg(all(feature = "crash-report", target_os = "macos"))]
+fn modified_since(path: &str, since_s: u64) -> bool {
+    // SAFETY: libc::stat is a C struct with no invalid bit patterns; zeroing is valid.
+    let mut st: libc::stat = unsafe { core::mem::zeroed() };
+    // SAFETY: path is null-terminated, st is valid for writes
+    if unsafe { libc::stat(path.as_ptr().cast(), &raw mut st) } != 0 {
+        return false;
+    }
+    u64::try_from(st.st_mtime).is_ok_and(|m| m >= since_s)
+}
+
+/* read the first HEAD_LEN bytes of `path` (null-terminated) and look for pid */
+#[cfg(all(feature = "crash-report", target_os = "macos"))]
+fn head_mentions_pid(path: &str, pid: u32) -> bool {
+    // SAFETY: path is null-terminated
+    let fd = unsafe { libc::open(path.as_ptr().cast(), libc::O_RDONLY | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return false;
+    }
+    let mut head = [0u8; HEAD_LEN];
+    // SAFETY: fd is open, head is valid for HEAD_LEN bytes
+    let n = unsafe { libc::read(fd, head.as_mut_ptr().cast(), HEAD_LEN) };
+    // SAFETY: fd is open and closed once here
+    unsafe { libc::close(fd) };
+    usize::try_from(n).is_ok_and(|n| mentions_pid(&head[..n], pid))
+}
+
+/*
+ * does a report header name this pid? matches the .ips body field
+ * `"pid" : 123` (any spacing) and the .crash line `Process: name [123]`.
+ * the digits must end there, so 123 doesn't match 1234.
+ */
+fn mentions_pid(head: &[u8], pid: u32) -> bool {
+    let mut digits = crate::io::StackBuf::<10>::new();
+    let _ = core::fmt::Write::write_fmt(&mut digits, format_args!("{pid}"));
+    let digits = digits.as_bytes();
+
+    /* `"pid"`, optional spaces, `:`, optional spaces, digits, non-digit */
+    let ips = head.windows(5).enumerate().any(|(i, w)| {
+        if w != b"\"pid\"" {
+            return false;
+        }
+        let rest = skip_spaces(&head[i + 5..]);
+        let Some(rest) = rest.strip_prefix(b":") else {
+            return false;
+        };
+        number_is(skip_spaces(rest), digits)
+    });
+    if ips {
+        return true;
+    }
+
+    /* `[digits]` on the Process: line */
+    head.split(|&b| b == b'\n')
+        .find(|line| line.starts_with(b"Process:"))
+        .and_then(|line| {
+            let open = line.iter().position(|&b| b == b'[')?;
+            line[open + 1..].strip_prefix(digits)
+        })
+        .is_some_and(|rest| rest.starts_with(b"]"))
+}
+
+fn skip_spaces(s: &[u8]) -> &[u8] {
+    let n = s.iter().take_while(|&&b| b == b' ').count();
+    &s[n..]
+}
+
+/* s starts with exactly these digits, not followed by another digit */
+fn number_is(s: &[u8], digits: &[u8]) -> bool {
+    s.starts_with(digits) && !s.get(digits.len()).is_some_and(u8::is_ascii_digit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mentions_pid_ips() {
+        let head = b"{\"app_name\":\"crasher\",\"bug_type\":\"309\"}\n{\n  \"uptime\" : 100,\n  \"pid\" : 4242,\n";
+        assert!(mentions_pid(head, 4242));
+        assert!(!mentions_pid(head, 424));
+        assert!(!mentions_pid(head, 42421));
+        assert!(mentions_pid(b"{\"pid\":7,", 7));
+    }
+
+    #[test]
+    fn test_mentions_pid_crash() {
+        let head = b"Process:               crasher [4242]\nPath:   /tmp/crasher\n";
+        assert!(mentions_pid(head, 4242));
+        assert!(!mentions_pid(head, 424));
+        assert!(!mentions_pid(b"Parent Process:  zsh [4242]\n", 4242));
+    }
+
+    #[test]
+    fn test_missing_report_is_none() {
+        /* no process has pid 0; returns after the (empty) window */
+        assert_eq!(find_report(0, u64::MAX, Duration::ZERO), None);
+    }
+}
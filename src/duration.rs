@@ -6,10 +6,17 @@
  *
  * Uses integer math internally (nanosecond precision) to avoid pulling in
  * the ~6KB f64::from_str machinery from libstd.
+ *
+ * format_duration goes the other way, in the same grammar. Both are public
+ * API other tools build on to accept what we accept, so the grammar only
+ * grows: a string that parses today parses to the same Duration in every
+ * later version, and format_duration's output for a given Duration doesn't
+ * change.
  */
 
 use alloc::format;
 use alloc::string::ToString;
+use core::fmt;
 use core::time::Duration;
 
 use crate::error::{Result, TimeoutError};
@@ -69,7 +76,7 @@ pub fn parse_duration(input: &str) -> Result<Duration> {
         .and_then(|n| n.checked_div(1_000_000_000)) // scale back from fixed-point
         .ok_or(TimeoutError::DurationOverflow)?;
 
-    /* cap at u64::MAX seconds */
+    /* cap at u64::MAX nanoseconds (about 584 years) */
     if total_nanos > u64::MAX as u128 {
         return Err(TimeoutError::DurationOverflow);
     }
@@ -113,15 +120,17 @@ fn parse_decimal_to_nanos(s: &str) -> Result<u128> {
     let frac_val: u128 = if frac_part.is_empty() {
         0
     } else {
+        /* every digit must be valid, even the ones past 9 we drop -
+         * "1.1234567890x5" is garbage, not 1.123456789 */
+        if !frac_part.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(TimeoutError::InvalidDuration(format!(
+                "invalid number '{s}'"
+            )));
+        }
         /* stack buffer - no heap allocation */
         let mut frac_buf = [b'0'; 9];
-        for (i, b) in frac_part.bytes().take(9).enumerate() {
-            if !b.is_ascii_digit() {
-                return Err(TimeoutError::InvalidDuration(format!(
-                    "invalid number '{s}'"
-                )));
-            }
-            frac_buf[i] = b;
+        for (dst, b) in frac_buf.iter_mut().zip(frac_part.bytes()) {
+            *dst = b;
         }
         /* SAFETY: frac_buf contains only ASCII digits */
         let frac_str = unsafe { core::str::from_utf8_unchecked(&frac_buf) };
@@ -148,7 +157,82 @@ fn split_number_and_suffix(input: &str) -> (&str, &str) {
     (&input[..suffix_start], &input[suffix_start..])
 }
 
-/* zero duration = no timeout, run forever */
+/// A [`Duration`] written in the grammar [`parse_duration`] reads; see
+/// [`format_duration`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormattedDuration(Duration);
+
+/* largest unit first, so the first exact one is the shortest form */
+const UNITS: [(u128, &str); 6] = [
+    (86_400_000_000_000, "d"),
+    (3_600_000_000_000, "h"),
+    (60_000_000_000, "m"),
+    (1_000_000_000, "s"),
+    (1_000_000, "ms"),
+    (1_000, "us"),
+];
+
+impl fmt::Display for FormattedDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nanos = self.0.as_nanos();
+        if nanos == 0 {
+            return f.write_str("0");
+        }
+        for (unit, suffix) in UNITS {
+            if nanos.is_multiple_of(unit) {
+                return write!(f, "{}{}", nanos / unit, suffix);
+            }
+        }
+        /* not a whole number of microseconds: seconds with up to 9
+         * fractional digits, trailing zeros dropped */
+        let mut frac = self.0.subsec_nanos();
+        let mut digits = 9;
+        while frac.is_multiple_of(10) {
+            frac /= 10;
+            digits -= 1;
+        }
+        write!(f, "{}.{:0digits$}s", self.0.as_secs(), frac)
+    }
+}
+
+/// Write `duration` in the shortest form [`parse_duration`] reads back
+/// exactly: the largest unit it's a whole number of ("90s", "2h", "1500us"),
+/// fractional seconds below a microsecond ("1.000000001s"), and "0" for
+/// zero.
+///
+/// `parse_duration(&format_duration(d).to_string())` is `Ok(d)` for every
+/// `d` up to the most `parse_duration` accepts, `u64::MAX` nanoseconds
+/// (about 584 years); the output is canonical, formatting what it parses
+/// to gives the same string back.
+///
+/// # Examples
+///
+/// ```
+/// use procguard::duration::{format_duration, parse_duration};
+/// use std::time::Duration;
+///
+/// assert_eq!(format_duration(Duration::from_secs(90)).to_string(), "90s");
+/// assert_eq!(format_duration(Duration::from_secs(7200)).to_string(), "2h");
+/// assert_eq!(format_duration(Duration::from_millis(1500)).to_string(), "1500ms");
+/// assert_eq!(format_duration(Duration::ZERO).to_string(), "0");
+///
+/// let d = parse_duration("1.5m").unwrap();
+/// assert_eq!(format_duration(d).to_string(), "90s");
+/// ```
+#[must_use]
+pub const fn format_duration(duration: Duration) -> FormattedDuration {
+    FormattedDuration(duration)
+}
+
+/// Whether `duration` means no timeout at all: zero runs forever, as with
+/// GNU timeout.
+///
+/// ```
+/// use procguard::{is_no_timeout, parse_duration};
+///
+/// assert!(is_no_timeout(&parse_duration("0").unwrap()));
+/// assert!(!is_no_timeout(&parse_duration("1ms").unwrap()));
+/// ```
 #[must_use]
 pub const fn is_no_timeout(duration: &Duration) -> bool {
     duration.is_zero()
@@ -252,4 +336,67 @@ mod tests {
         assert!(parse_duration("abc").is_err());
         assert!(parse_duration("s").is_err());
     }
+
+    #[test]
+    fn test_format() {
+        let fmt = |d| format!("{}", format_duration(d));
+        assert_eq!(fmt(Duration::ZERO), "0");
+        assert_eq!(fmt(Duration::from_secs(30)), "30s");
+        assert_eq!(fmt(Duration::from_secs(90)), "90s");
+        assert_eq!(fmt(Duration::from_secs(120)), "2m");
+        assert_eq!(fmt(Duration::from_secs(7200)), "2h");
+        assert_eq!(fmt(Duration::from_secs(43200)), "12h");
+        assert_eq!(fmt(Duration::from_secs(172_800)), "2d");
+        assert_eq!(fmt(Duration::from_millis(1500)), "1500ms");
+        assert_eq!(fmt(Duration::from_micros(1)), "1us");
+        assert_eq!(fmt(Duration::from_nanos(1)), "0.000000001s");
+        assert_eq!(fmt(Duration::new(1, 500)), "1.0000005s");
+        assert_eq!(
+            fmt(Duration::new(u64::MAX, 1)),
+            "18446744073709551615.000000001s"
+        );
+    }
+
+    #[test]
+    fn test_format_roundtrip() {
+        let durations = [
+            Duration::ZERO,
+            Duration::from_nanos(1),
+            Duration::from_nanos(999),
+            Duration::from_micros(1500),
+            Duration::from_millis(999),
+            Duration::new(59, 999_999_999),
+            Duration::from_secs(86_399),
+            Duration::from_secs(86_400 * 365),
+            Duration::from_nanos(u64::MAX),
+            Duration::from_nanos(u64::MAX - 1),
+        ];
+        for d in durations {
+            let s = format!("{}", format_duration(d));
+            assert_eq!(parse_duration(&s).unwrap(), d, "{s}");
+        }
+        /* and canonical: what a string parses to formats back to the
+         * shortest form of it */
+        for (input, canonical) in [
+            ("1.5m", "90s"),
+            ("0.5d", "12h"),
+            ("60s", "1m"),
+            ("0s", "0"),
+            ("1000us", "1ms"),
+        ] {
+            let d = parse_duration(input).unwrap();
+            assert_eq!(format!("{}", format_duration(d)), canonical);
+        }
+    }
+
+    #[test]
+    fn test_invalid_digits_past_precision() {
+        /* only 9 fractional digits are kept, but all of them are checked */
+        assert!(parse_duration("1.1234567890x5").is_err());
+        assert!(parse_duration("1.123456789-1s").is_err());
+        assert_eq!(
+            parse_duration("1.1234567891").unwrap(),
+            Duration::new(1, 123_456_789)
+        );
+    }
 }
@@ -0,0 +1,485 @@
+/*
+ * epoll.rs
+ *
+ * kq.rs for linux: the same Kqueue, builders and kevent-shaped changes,
+ * on top of epoll. lib.rs mounts this file as `kq`, so the wait loops in
+ * runner/ and wait.rs stay written once, in kqueue terms.
+ *
+ * epoll only watches fds, so each filter gets one:
+ *
+ *   EVFILT_PROC   pidfd_open(pid) - readable once the process exits (5.3+)
+ *   EVFILT_TIMER  timerfd on CLOCK_MONOTONIC - stops during suspend, like
+ *                 kqueue timers; the callers re-check their own clocks
+ *   EVFILT_READ   the fd itself
+ *
+ * no signalfd: forwarded signals already arrive as bytes on forward.rs's
+ * self-pipe, which is just another EVFILT_READ here.
+ *
+ * kqueue behaviour the callers rely on, and how it's kept:
+ * - oneshot filters are gone once they fire: the slot is dropped and its
+ *   fd closed as the event is handed out
+ * - re-adding a filter modifies it: a timer is re-armed, anything else is
+ *   left as is
+ * - a change that fails comes back as an EV_ERROR event with the errno in
+ *   `data` (ESRCH for a reaped pid, EBADF for a closed stdin, ENOENT for
+ *   deleting something that isn't there)
+ * - regular files are always readable. epoll refuses them (EPERM), so such
+ *   a slot skips epoll and reports ready on every wait until deleted
+ */
+
+use core::cell::Cell;
+
+use crate::errno::errno;
+
+type RawFd = i32;
+
+/* same values as darwin's, nothing outside this file looks at them */
+pub(crate) const EVFILT_READ: i16 = -1;
+pub(crate) const EVFILT_PROC: i16 = -5;
+pub(crate) const EVFILT_TIMER: i16 = -7;
+pub(crate) const EV_ADD: u16 = 0x0001;
+pub(crate) const EV_DELETE: u16 = 0x0002;
+pub(crate) const EV_ONESHOT: u16 = 0x0010;
+pub(crate) const EV_ERROR: u16 = 0x4000;
+pub(crate) const EV_EOF: u16 = 0x8000;
+const NOTE_EXIT: u32 = 0x8000_0000;
+
+/* filters one queue holds at once. a wait registers at most nine */
+const TRACKED: usize = 12;
+
+/// A change to submit, or the event [`Kqueue::wait`] returns - the
+/// fields of `struct kevent` the callers use.
+#[derive(Clone, Copy)]
+pub(crate) struct Event {
+    pub(crate) ident: usize,
+    pub(crate) filter: i16,
+    pub(crate) flags: u16,
+    pub(crate) fflags: u32,
+    pub(crate) data: isize,
+}
+
+#[derive(Clone, Copy)]
+struct Slot {
+    ident: usize,
+    filter: i16,
+    /* what epoll watches: pidfd or timerfd (ours to close), or ident */
+    fd: RawFd,
+    /* regular file: not in epoll, always readable */
+    ready: bool,
+}
+
+impl Slot {
+    const fn owns_fd(&self) -> bool {
+        self.filter != EVFILT_READ
+    }
+}
+
+/// An epoll instance standing in for a kqueue, closed on drop along with
+/// the pidfds and timerfds behind its filters.
+pub(crate) struct Kqueue {
+    fd: RawFd,
+    slots: Cell<[Option<Slot>; TRACKED]>,
+}
+
+impl Kqueue {
+    /// New queue, or the errno if the kernel refused (fd limit).
+    pub(crate) fn new() -> Result<Self, i32> {
+        // SAFETY: epoll_create1 has no preconditions, returns -1 on error
+        let fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if fd < 0 {
+            Err(errno())
+        } else {
+            Ok(Self {
+                fd,
+                slots: Cell::new([None; TRACKED]),
+            })
+        }
+    }
+
+    /// Delete every filter, along with any events they left pending. Call
+    /// before reusing the queue for a new wait.
+    pub(crate) fn reset(&self) {
+        for slot in self.slots.replace([None; TRACKED]).into_iter().flatten() {
+            self.forget(slot);
+        }
+    }
+
+    /* take a slot out of epoll, close its fd if it's ours. DEL first: close
+     * alone only unregisters once every copy of the fd is gone, and a
+     * child caught between fork and exec still holds one */
+    fn forget(&self, slot: Slot) {
+        if !slot.ready {
+            // SAFETY: self.fd is our epoll fd; a null event is allowed for DEL
+            unsafe {
+                libc::epoll_ctl(self.fd, libc::EPOLL_CTL_DEL, slot.fd, core::ptr::null_mut())
+            };
+        }
+        if slot.owns_fd() {
+            // SAFETY: fd is a pidfd/timerfd we opened and only close here
+            unsafe { libc::close(slot.fd) };
+        }
+    }
+
+    /* apply one change to the slot table. Err(errno) for the caller to
+     * report as an EV_ERROR event */
+    fn apply(&self, slots: &mut [Option<Slot>; TRACKED], c: &Event) -> Result<(), i32> {
+        let found = slots
+            .iter()
+            .position(|s| s.is_some_and(|s| s.ident == c.ident && s.filter == c.filter));
+
+        if c.flags & EV_DELETE != 0 {
+            let slot = found.and_then(|i| slots[i].take()).ok_or(libc::ENOENT)?;
+            self.forget(slot);
+            return Ok(());
+        }
+        if c.flags & EV_ADD == 0 {
+            return Ok(());
+        }
+
+        if let Some(i) = found {
+            /* re-adding modifies: only a timer has anything to change */
+            if let Some(slot) = slots[i]
+                && c.filter == EVFILT_TIMER
+            {
+                arm_timer(slot.fd, c.data)?;
+            }
+            return Ok(());
+        }
+
+        let Some(free) = slots.iter().position(Option::is_none) else {
+            debug_assert!(false, "epoll registration table full");
+            return Err(libc::ENOMEM);
+        };
+        let mut slot = Slot {
+            ident: c.ident,
+            filter: c.filter,
+            fd: open_filter_fd(c)?,
+            ready: false,
+        };
+
+        let mut ev = libc::epoll_event {
+            events: libc::EPOLLIN as u32,
+            u64: free as u64,
+        };
+        // SAFETY: self.fd is our epoll fd, slot.fd an open fd, ev a valid event
+        let ret = unsafe { libc::epoll_ctl(self.fd, libc::EPOLL_CTL_ADD, slot.fd, &raw mut ev) };
+        if ret < 0 {
+            let err = errno();
+            if err == libc::EPERM && c.filter == EVFILT_READ {
+                slot.ready = true;
+            } else {
+                if slot.owns_fd() {
+                    // SAFETY: fd is the pidfd/timerfd open_filter_fd just opened
+                    unsafe { libc::close(slot.fd) };
+                }
+                return Err(err);
+            }
+        }
+        slots[free] = Some(slot);
+        Ok(())
+    }
+
+    /// Submit `changes`, then block until one event arrives in `event`.
+    /// No timeout - register a timer for that. `Err(errno)` on failure,
+    /// EINTR included; callers decide whether to retry.
+    pub(crate) fn wait(&self, changes: &[Event], event: &mut Event) -> Result<(), i32> {
+        let mut slots = self.slots.get();
+        let applied = changes
+            .iter()
+            .try_for_each(|c| self.apply(&mut slots, c).map_err(|err| (c, err)));
+        self.slots.set(slots);
+
+        if let Err((c, err)) = applied {
+            #[allow(clippy::cast_possible_wrap)]
+            let data = err as isize;
+            *event = Event {
+                flags: EV_ERROR,
+                data,
+                ..*c
+            };
+            return Ok(());
+        }
+
+        let (index, hangup) = match slots.iter().position(|s| s.is_some_and(|s| s.ready)) {
+            Some(i) => (i, false),
+            None => {
+                let mut ev = libc::epoll_event { events: 0, u64: 0 };
+                // SAFETY: self.fd is our epoll fd; ev is a valid buffer for one
+                // event; -1 means wait without a timeout.
+                let n = unsafe { libc::epoll_wait(self.fd, &raw mut ev, 1, -1) };
+                if n < 0 {
+                    return Err(errno());
+                }
+                let hangup = (libc::EPOLLHUP | libc::EPOLLERR) as u32;
+                #[allow(clippy::cast_possible_truncation)]
+                (ev.u64 as usize, ev.events & hangup != 0)
+            }
+        };
+
+        let Some(slot) = slots.get(index).copied().flatten() else {
+            return Err(libc::EINTR); /* stale index: can't happen, wait again */
+        };
+        *event = Event {
+            ident: slot.ident,
+            filter: slot.filter,
+            ..empty()
+        };
+        if hangup && slot.filter == EVFILT_READ {
+            event.flags = EV_EOF;
+        }
+        if slot.owns_fd() {
+            /* oneshot: fired means gone */
+            event.data = 1;
+            if slot.filter == EVFILT_PROC {
+                event.fflags = NOTE_EXIT;
+            }
+            slots[index] = None;
+            self.slots.set(slots);
+            self.forget(slot);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Kqueue {
+    fn drop(&mut self) {
+        self.reset();
+        // SAFETY: fd came from epoll_create1() in new() and is closed exactly once here
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+/* the fd epoll watches for a new filter */
+fn open_filter_fd(c: &Event) -> Result<RawFd, i32> {
+    match c.filter {
+        EVFILT_PROC => {
+            // SAFETY: pidfd_open takes a pid and flags, returns an fd or -1
+            let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, c.ident as libc::pid_t, 0) };
+            #[allow(clippy::cast_possible_truncation)]
+            if fd < 0 {
+                Err(errno())
+            } else {
+                Ok(fd as RawFd)
+            }
+        }
+        EVFILT_TIMER => {
+            // SAFETY: timerfd_create has no preconditions, returns -1 on error
+            let fd = unsafe {
+                libc::timerfd_create(
+                    libc::CLOCK_MONOTONIC,
+                    libc::TFD_CLOEXEC | libc::TFD_NONBLOCK,
+                )
+            };
+            if fd < 0 {
+                return Err(errno());
+            }
+            arm_timer(fd, c.data).inspect_err(|_| {
+                // SAFETY: fd is the timerfd we just created
+                unsafe { libc::close(fd) };
+            })?;
+            Ok(fd)
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        _ => Ok(c.ident as RawFd),
+    }
+}
+
+/* (re)start a timerfd, `ns` from now. zero would disarm it, and a kqueue
+ * timer of zero fires at once, so it becomes 1ns */
+fn arm_timer(fd: RawFd, ns: isize) -> Result<(), i32> {
+    #[allow(clippy::cast_sign_loss)]
+    let ns = (ns as u64).max(1);
+    #[allow(clippy::cast_possible_wrap)]
+    let spec = libc::itimerspec {
+        it_interval: libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        },
+        it_value: libc::timespec {
+            tv_sec: (ns / 1_000_000_000) as libc::time_t,
+            tv_nsec: (ns % 1_000_000_000) as libc::c_long,
+        },
+    };
+    // SAFETY: fd is an open timerfd; spec is valid; old value not wanted
+    let ret = unsafe { libc::timerfd_settime(fd, 0, &raw const spec, core::ptr::null_mut()) };
+    if ret < 0 { Err(errno()) } else { Ok(()) }
+}
+
+/// Zeroed event, for the output buffer or a change slot that's off.
+pub(crate) const fn empty() -> Event {
+    Event {
+        ident: 0,
+        filter: 0,
+        flags: 0,
+        fflags: 0,
+        data: 0,
+    }
+}
+
+/// Oneshot: `pid` exited.
+#[allow(clippy::cast_sign_loss)]
+pub(crate) const fn proc_exit(pid: i32) -> Event {
+    Event {
+        ident: pid as usize,
+        filter: EVFILT_PROC,
+        flags: EV_ADD | EV_ONESHOT,
+        fflags: NOTE_EXIT,
+        ..empty()
+    }
+}
+
+/// Oneshot nanosecond timer. `ident` tells timers on one queue apart;
+/// `ns` is clamped to what the kernel accepts.
+#[allow(clippy::cast_possible_wrap)]
+pub(crate) const fn timer(ident: usize, ns: u64) -> Event {
+    let ns = if ns > isize::MAX as u64 {
+        isize::MAX as u64
+    } else {
+        ns
+    };
+    Event {
+        ident,
+        filter: EVFILT_TIMER,
+        flags: EV_ADD | EV_ONESHOT,
+        data: ns as isize,
+        ..empty()
+    }
+}
+
+/// `fd` is readable. Stays registered after firing. With `enabled` false
+/// the slot is a no-op until its flags are set.
+#[allow(clippy::cast_sign_loss)]
+pub(crate) const fn read(fd: RawFd, enabled: bool) -> Event {
+    Event {
+        ident: fd as usize,
+        filter: EVFILT_READ,
+        flags: if enabled { EV_ADD } else { 0 },
+        ..empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builders() {
+        let t = timer(7, u64::MAX);
+        assert_eq!((t.ident, t.filter), (7, EVFILT_TIMER));
+        assert_eq!(t.data, isize::MAX, "timer clamps to isize");
+        assert_eq!(timer(1, 500).data, 500);
+
+        assert_eq!(read(3, false).flags, 0);
+        assert_eq!(read(3, true).flags, EV_ADD);
+
+        let p = proc_exit(42);
+        assert_eq!((p.ident, p.fflags), (42, NOTE_EXIT));
+    }
+
+    #[test]
+    fn test_timer_fires() {
+        let kq = Kqueue::new().expect("epoll");
+        let mut event = empty();
+        kq.wait(&[timer(1, 1_000_000)], &mut event).expect("wait");
+        assert_eq!((event.ident, event.filter), (1, EVFILT_TIMER));
+    }
+
+    #[test]
+    fn test_reset_discards_leftovers() {
+        let kq = Kqueue::new().expect("epoll");
+        let mut event = empty();
+        /* both fire; one is returned, the other stays pending */
+        kq.wait(&[timer(1, 1_000_000), timer(2, 1_000_000)], &mut event)
+            .expect("wait");
+        kq.reset();
+        kq.wait(&[timer(3, 1_000_000)], &mut event).expect("wait");
+        assert_eq!(event.ident, 3, "stale timer survived reset");
+    }
+
+    #[test]
+    fn test_proc_exit_and_reaped_pid() {
+        let mut child = std::process::Command::new("true").spawn().expect("spawn");
+        #[allow(clippy::cast_possible_wrap)]
+        let pid = child.id() as i32;
+        let kq = Kqueue::new().expect("epoll");
+        let mut event = empty();
+        kq.wait(&[proc_exit(pid), timer(1, 5_000_000_000)], &mut event)
+            .expect("wait");
+        assert_eq!((event.ident, event.filter), (pid as usize, EVFILT_PROC));
+        assert_eq!(event.fflags, NOTE_EXIT);
+
+        /* once reaped, the pid is gone: ESRCH, like kqueue */
+        child.wait().expect("wait");
+        kq.reset();
+        kq.wait(&[proc_exit(pid)], &mut event).expect("wait");
+        assert_eq!(event.flags, EV_ERROR);
+        assert_eq!(event.data, libc::ESRCH as isize);
+    }
+
+    #[test]
+    fn test_reset_after_unfired_proc() {
+        /* a timeout, then a retry: the first child's pidfd is dropped
+         * unfired and must not wake the next wait. spawning the second
+         * child before the reset is what leaves a copy of it around */
+        let kq = Kqueue::new().expect("epoll");
+        let mut event = empty();
+        let mut first = std::process::Command::new("sleep")
+            .arg("5")
+            .spawn()
+            .expect("spawn");
+        for _ in 0..2 {
+            #[allow(clippy::cast_possible_wrap)]
+            let pid = first.id() as i32;
+            kq.wait(&[proc_exit(pid), timer(1, 50_000_000)], &mut event)
+                .expect("wait");
+            assert_eq!(event.filter, EVFILT_TIMER, "stale proc event");
+            first.kill().expect("kill");
+            first.wait().expect("wait");
+            first = std::process::Command::new("sleep")
+                .arg("5")
+                .spawn()
+                .expect("spawn");
+            kq.reset();
+        }
+        first.kill().expect("kill");
+        first.wait().expect("wait");
+    }
+
+    #[test]
+    fn test_delete_missing_is_enoent() {
+        let kq = Kqueue::new().expect("epoll");
+        let mut event = empty();
+        let delete = Event {
+            flags: EV_DELETE,
+            ..read(0, true)
+        };
+        kq.wait(&[delete], &mut event).expect("wait");
+        assert_eq!((event.ident, event.flags), (0, EV_ERROR));
+        assert_eq!(event.data, libc::ENOENT as isize);
+    }
+
+    #[test]
+    fn test_pipe_read_and_eof() {
+        let mut fds = [0i32; 2];
+        // SAFETY: fds is a valid two-element buffer
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let kq = Kqueue::new().expect("epoll");
+        let mut event = empty();
+
+        // SAFETY: fds[1] is the write end we just opened
+        unsafe { libc::write(fds[1], b"x".as_ptr().cast(), 1) };
+        kq.wait(&[read(fds[0], true)], &mut event).expect("wait");
+        assert_eq!((event.ident, event.filter), (fds[0] as usize, EVFILT_READ));
+        assert_eq!(event.flags & EV_EOF, 0);
+
+        /* level-triggered and still registered: no changes needed */
+        // SAFETY: fds[1] is open, closed once here
+        unsafe { libc::close(fds[1]) };
+        kq.wait(&[], &mut event).expect("wait");
+        assert_ne!(event.flags & EV_EOF, 0, "closed writer should read as EOF");
+
+        drop(kq);
+        // SAFETY: fds[0] is open, closed once here
+        unsafe { libc::close(fds[0]) };
+    }
+}
@@ -0,0 +1,227 @@
+/*
+ * errno.rs
+ *
+ * errno, read in one place and named in one place. every module that
+ * makes a libc call used to carry its own errno() and its own ENOENT;
+ * now they share these.
+ *
+ * Errno names the values we act on or that a user can do something
+ * about, so a spawn failure reads "text file busy (ETXTBSY)" instead of
+ * "errno 26". anything else stays a number: Other(errno).
+ */
+
+use core::fmt;
+
+unsafe extern "C" {
+    /* thread-local errno: __error() on macOS, __errno_location() on linux */
+    #[cfg_attr(target_os = "linux", link_name = "__errno_location")]
+    fn __error() -> *mut i32;
+}
+
+/// A binary for no CPU we can run. Linux says ENOEXEC instead.
+#[cfg(target_os = "macos")]
+pub const EBADARCH: i32 = 86;
+
+/// errno as the last failed libc call on this thread left it.
+#[inline]
+#[must_use]
+pub fn errno() -> i32 {
+    // SAFETY: __error always returns a valid pointer to this thread's errno.
+    // The dereference and call share that invariant.
+    #[allow(clippy::multiple_unsafe_ops_per_block)]
+    unsafe {
+        *__error()
+    }
+}
+
+/// An errno by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Errno {
+    /// EPERM
+    NotPermitted,
+    /// ENOENT
+    NotFound,
+    /// ESRCH
+    NoSuchProcess,
+    /// EINTR
+    Interrupted,
+    /// EAGAIN: out of something for now - at a spawn, processes.
+    TryAgain,
+    /// E2BIG: the arguments and environment together are over ARG_MAX.
+    ArgListTooLong,
+    /// ENOEXEC
+    ExecFormat,
+    /// ECHILD: nothing (left) to wait for.
+    NoChild,
+    /// ENOMEM
+    OutOfMemory,
+    /// EACCES
+    PermissionDenied,
+    /// ETXTBSY: the binary is open for writing - a build still linking it.
+    TextFileBusy,
+    /// EBADARCH (macOS): no slice for a CPU we can run.
+    #[cfg(target_os = "macos")]
+    BadArch,
+    /// Any other errno.
+    Other(i32),
+}
+
+impl Errno {
+    /// The name for `errno`, or [`Errno::Other`].
+    #[must_use]
+    pub const fn from_raw(errno: i32) -> Self {
+        match errno {
+            libc::EPERM => Self::NotPermitted,
+            libc::ENOENT => Self::NotFound,
+            libc::ESRCH => Self::NoSuchProcess,
+            libc::EINTR => Self::Interrupted,
+            libc::EAGAIN => Self::TryAgain,
+            libc::E2BIG => Self::ArgListTooLong,
+            libc::ENOEXEC => Self::ExecFormat,
+            libc::ECHILD => Self::NoChild,
+            libc::ENOMEM => Self::OutOfMemory,
+            libc::EACCES => Self::PermissionDenied,
+            libc::ETXTBSY => Self::TextFileBusy,
+            #[cfg(target_os = "macos")]
+            EBADARCH => Self::BadArch,
+            other => Self::Other(other),
+        }
+    }
+
+    /// The errno [`Errno::from_raw`] named.
+    #[must_use]
+    pub const fn raw(self) -> i32 {
+        match self {
+            Self::NotPermitted => libc::EPERM,
+            Self::NotFound => libc::ENOENT,
+            Self::NoSuchProcess => libc::ESRCH,
+            Self::Interrupted => libc::EINTR,
+            Self::TryAgain => libc::EAGAIN,
+            Self::ArgListTooLong => libc::E2BIG,
+            Self::ExecFormat => libc::ENOEXEC,
+            Self::NoChild => libc::ECHILD,
+            Self::OutOfMemory => libc::ENOMEM,
+            Self::PermissionDenied => libc::EACCES,
+            Self::TextFileBusy => libc::ETXTBSY,
+            #[cfg(target_os = "macos")]
+            Self::BadArch => EBADARCH,
+            Self::Other(errno) => errno,
+        }
+    }
+
+    /// The errno as [`errno`] reads it now.
+    #[inline]
+    #[must_use]
+    pub fn last() -> Self {
+        Self::from_raw(errno())
+    }
+
+    /// ENOEXEC, or EBADARCH on macOS: a file exec can't make sense of.
+    #[must_use]
+    pub const fn is_exec_format(self) -> bool {
+        match self {
+            Self::ExecFormat => true,
+            #[cfg(target_os = "macos")]
+            Self::BadArch => true,
+            _ => false,
+        }
+    }
+
+    /// Whether a spawn that failed with this may work a moment later: a
+    /// binary still being written, or a process table that was full.
+    #[must_use]
+    pub const fn is_transient(self) -> bool {
+        matches!(self, Self::TextFileBusy | Self::TryAgain)
+    }
+
+    /// The C name (`"ETXTBSY"`); None for [`Errno::Other`].
+    #[must_use]
+    pub const fn name(self) -> Option<&'static str> {
+        Some(match self {
+            Self::NotPermitted => "EPERM",
+            Self::NotFound => "ENOENT",
+            Self::NoSuchProcess => "ESRCH",
+            Self::Interrupted => "EINTR",
+            Self::TryAgain => "EAGAIN",
+            Self::ArgListTooLong => "E2BIG",
+            Self::ExecFormat => "ENOEXEC",
+            Self::NoChild => "ECHILD",
+            Self::OutOfMemory => "ENOMEM",
+            Self::PermissionDenied => "EACCES",
+            Self::TextFileBusy => "ETXTBSY",
+            #[cfg(target_os = "macos")]
+            Self::BadArch => "EBADARCH",
+            Self::Other(_) => return None,
+        })
+    }
+
+    /* strerror's wording, lowercased the way our messages are */
+    const fn describe(self) -> &'static str {
+        match self {
+            Self::NotPermitted => "operation not permitted",
+            Self::NotFound => "no such file or directory",
+            Self::NoSuchProcess => "no such process",
+            Self::Interrupted => "interrupted system call",
+            Self::TryAgain => "resource temporarily unavailable",
+            Self::ArgListTooLong => "argument list too long",
+            Self::ExecFormat => "exec format error",
+            Self::NoChild => "no child processes",
+            Self::OutOfMemory => "cannot allocate memory",
+            Self::PermissionDenied => "permission denied",
+            Self::TextFileBusy => "text file busy",
+            #[cfg(target_os = "macos")]
+            Self::BadArch => "bad CPU type in executable",
+            Self::Other(_) => "",
+        }
+    }
+}
+
+/// `"text file busy (ETXTBSY)"`, or `"errno N"` for [`Errno::Other`].
+impl fmt::Display for Errno {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{} ({})", self.describe(), name),
+            None => write!(f, "errno {}", self.raw()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_round_trip() {
+        for raw in 0..200 {
+            assert_eq!(Errno::from_raw(raw).raw(), raw);
+        }
+        assert_eq!(Errno::from_raw(libc::ETXTBSY), Errno::TextFileBusy);
+        assert_eq!(Errno::from_raw(libc::EDOM), Errno::Other(libc::EDOM));
+        assert!(Errno::TextFileBusy.is_transient() && Errno::TryAgain.is_transient());
+        assert!(!Errno::OutOfMemory.is_transient() && !Errno::NotFound.is_transient());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Errno::TextFileBusy.to_string(), "text file busy (ETXTBSY)");
+        assert_eq!(
+            Errno::ArgListTooLong.to_string(),
+            "argument list too long (E2BIG)"
+        );
+        assert_eq!(
+            Errno::Other(libc::EDOM).to_string(),
+            alloc::format!("errno {}", libc::EDOM)
+        );
+    }
+
+    #[test]
+    fn test_errno_reads_last_failure() {
+        // SAFETY: closing an fd that was never opened only sets errno
+        let ret = unsafe { libc::close(-1) };
+        assert_eq!(ret, -1);
+        assert_eq!(errno(), libc::EBADF);
+        assert_eq!(Errno::last(), Errno::Other(libc::EBADF));
+    }
+}
@@ -10,7 +10,19 @@
 use alloc::string::String;
 use core::fmt;
 
+use crate::errno::Errno;
+use crate::lookup::NotRunnable;
+use crate::process::SpawnError;
+use crate::signal::{Signal, signal_name};
+
 /// exit codes per GNU coreutils convention. don't change these.
+///
+/// ```
+/// use procguard::exit_codes;
+///
+/// assert_eq!(exit_codes::TIMEOUT, 124);
+/// assert!(exit_codes::RESERVED.contains(&(128 + 9)));
+/// ```
 pub mod exit_codes {
     /// Command ran too long (timed out)
     pub const TIMEOUT: u8 = 124;
@@ -20,31 +32,91 @@ pub mod exit_codes {
     pub const CANNOT_INVOKE: u8 = 126;
     /// Command not found
     pub const NOT_FOUND: u8 = 127;
+    /// procguard outlived every deadline it had and gave up on itself
+    /// (EX_SOFTWARE, see failsafe.rs)
+    pub const FAILSAFE: u8 = 70;
+    /// `--flock`: another instance holds the lock (EX_TEMPFAIL, try later)
+    pub const LOCK_BUSY: u8 = 75;
+    /// `--only-between`: outside the time window, and it doesn't open within
+    /// `--window-wait` (EX_TEMPFAIL too)
+    pub const OUTSIDE_WINDOW: u8 = 75;
+    /// Codes that already mean something when a command is run: ours
+    /// (125-127) and a death by signals 1-9 (128 + N). A
+    /// `--timeout-exit-code` in here reads as one of them.
+    pub const RESERVED: core::ops::RangeInclusive<u8> = 125..=137;
 }
 
-/* everything that can go wrong */
+/// Everything that can go wrong, from a bad option to a failed spawn.
+/// [`TimeoutError::exit_code`] maps each to the code the CLI exits with.
+///
+/// ```
+/// use procguard::{TimeoutError, exit_codes, parse_signal};
+///
+/// let err = parse_signal("SIGFOO").unwrap_err();
+/// assert!(matches!(err, TimeoutError::InvalidSignal(_)));
+/// assert_eq!(err.exit_code(), exit_codes::INTERNAL_ERROR);
+/// ```
 #[derive(Debug)]
 #[non_exhaustive]
 pub enum TimeoutError {
+    /// A duration that doesn't parse.
     InvalidDuration(String),
+    /// A duration below zero.
     NegativeDuration,
+    /// A duration too large to represent.
     DurationOverflow,
+    /// A `--mem-limit` that doesn't parse.
     InvalidMemoryLimit(String),
+    /// A `--cpu-time` that doesn't parse.
     InvalidCpuTime(String),
+    /// A `--cpu-percent` that doesn't parse.
     InvalidCpuPercent(String),
+    /// A signal name or number we don't know.
     InvalidSignal(String),
+    /// The command isn't on PATH.
     CommandNotFound(String),
+    /// The command exists but we may not run it.
     PermissionDenied(String),
-    SpawnError(i32),  // errno from spawn
-    SignalError(i32), // errno from libc signal calls
+    /// What our PATH search found instead of a command.
+    NotRunnable(NotRunnable),
+    /// errno from spawn (or wait).
+    SpawnError(Errno),
+    /// errno from libc signal calls.
+    SignalError(i32),
+    /// The command's process group couldn't be set up.
     ProcessGroupError(String),
+    /// errno from setrlimit.
     ResourceLimitError(i32),
+    /// errno from attaching the `--cpu-percent` throttle.
     ThrottleAttachError(i32),
+    /// errno from stopping or continuing a throttled command.
     ThrottleControlError(i32),
+    /// A bug, or a state we didn't expect to reach.
     Internal(String),
-    WaitForFileTimeout(String), // file path that we timed out waiting for
-    WaitForFileError(String, i32), // file path + errno from stat
-    TimebaseError,              // mach_timebase_info returned invalid data (zero denominator)
+    /// Options that can't work together (or in this build).
+    InvalidOptions(String),
+    /// File path that we timed out waiting for.
+    WaitForFileTimeout(String),
+    /// File path + errno from stat.
+    WaitForFileError(String, i32),
+    /// `--stdin` file path + errno from open.
+    StdinFileError(String, i32),
+    /// `--temp-dir` parent + errno from mkdtemp/setenv.
+    TempDirError(String, i32),
+    /// `--flock` path someone else held past the wait.
+    LockBusy(String),
+    /// `--flock` path + live tickets still ahead of ours.
+    LockQueueExpired(String, u32),
+    /// `--flock` path + errno from open/flock.
+    LockError(String, i32),
+    /// `--only-between` window that isn't open (and won't be in time).
+    OutsideWindow(String),
+    /// `--wait-pid` pid + errno from watching it.
+    WaitPidError(i32, i32),
+    /// Stop signal during a wait, before the command started.
+    Interrupted(Signal),
+    /// mach_timebase_info returned invalid data (zero denominator).
+    TimebaseError,
 }
 
 impl fmt::Display for TimeoutError {
@@ -59,7 +131,13 @@ impl fmt::Display for TimeoutError {
             Self::InvalidSignal(s) => write!(f, "invalid signal: {s}"),
             Self::CommandNotFound(s) => write!(f, "command not found: {s}"),
             Self::PermissionDenied(s) => write!(f, "permission denied: {s}"),
-            Self::SpawnError(errno) => write!(f, "failed to spawn process: errno {errno}"),
+            Self::NotRunnable(e) => write!(f, "{e}"),
+            Self::SpawnError(Errno::TextFileBusy) => write!(
+                f,
+                "failed to spawn process: {} - is it still being written?",
+                Errno::TextFileBusy
+            ),
+            Self::SpawnError(errno) => write!(f, "failed to spawn process: {errno}"),
             Self::SignalError(errno) => write!(f, "signal error: errno {errno}"),
             Self::ProcessGroupError(s) => write!(f, "process group error: {s}"),
             Self::ResourceLimitError(errno) => {
@@ -72,10 +150,39 @@ impl fmt::Display for TimeoutError {
                 write!(f, "failed to control CPU throttle: kern_return {errno}")
             }
             Self::Internal(s) => write!(f, "internal error: {s}"),
+            Self::InvalidOptions(s) => write!(f, "{s}"),
             Self::WaitForFileTimeout(path) => write!(f, "timed out waiting for file: {path}"),
             Self::WaitForFileError(path, errno) => {
                 write!(f, "error checking file '{path}': errno {errno}")
             }
+            Self::StdinFileError(path, errno) => {
+                write!(f, "can't open stdin file '{path}': errno {errno}")
+            }
+            Self::TempDirError(parent, errno) => {
+                write!(f, "can't create temp dir in '{parent}': errno {errno}")
+            }
+            Self::LockBusy(path) => write!(f, "lock '{path}' is held by another process"),
+            Self::LockQueueExpired(path, 0) => {
+                write!(f, "gave up queueing for lock '{path}': still held")
+            }
+            Self::LockQueueExpired(path, ahead) => {
+                write!(
+                    f,
+                    "gave up queueing for lock '{path}' with {ahead} still ahead"
+                )
+            }
+            Self::LockError(path, errno) => write!(f, "can't lock '{path}': errno {errno}"),
+            Self::OutsideWindow(window) => write!(f, "outside the time window {window}"),
+            Self::WaitPidError(pid, errno) => {
+                write!(f, "can't watch process {pid}: errno {errno}")
+            }
+            Self::Interrupted(sig) => {
+                write!(
+                    f,
+                    "interrupted by {} before the command started",
+                    signal_name(*sig)
+                )
+            }
             Self::TimebaseError => {
                 write!(f, "invalid mach timebase info (zero denominator)")
             }
@@ -84,12 +191,27 @@ impl fmt::Display for TimeoutError {
 }
 
 impl TimeoutError {
-    /* map errors to exit codes. 126 vs 127 matters to scripts. */
+    /// The exit code the CLI reports this error with. 126 vs 127 matters
+    /// to scripts.
+    ///
+    /// ```
+    /// use procguard::{TimeoutError, exit_codes};
+    ///
+    /// let err = TimeoutError::CommandNotFound("no-such-tool".into());
+    /// assert_eq!(err.exit_code(), exit_codes::NOT_FOUND);
+    /// assert_eq!(err.to_string(), "command not found: no-such-tool");
+    /// ```
     #[must_use]
     pub fn exit_code(&self) -> u8 {
         match self {
             Self::CommandNotFound(_) => exit_codes::NOT_FOUND,
             Self::PermissionDenied(_) => exit_codes::CANNOT_INVOKE,
+            Self::NotRunnable(e) => e.problem.exit_code(),
+            /* exec refused the command itself: GNU's "cannot invoke" */
+            Self::SpawnError(e) if e.is_exec_format() => exit_codes::CANNOT_INVOKE,
+            Self::SpawnError(Errno::TextFileBusy | Errno::ArgListTooLong) => {
+                exit_codes::CANNOT_INVOKE
+            }
             Self::InvalidDuration(_)
             | Self::NegativeDuration
             | Self::DurationOverflow
@@ -104,20 +226,102 @@ impl TimeoutError {
             | Self::ThrottleAttachError(_)
             | Self::ThrottleControlError(_)
             | Self::Internal(_)
+            | Self::InvalidOptions(_)
             | Self::WaitForFileError(_, _)
+            | Self::StdinFileError(_, _)
+            | Self::TempDirError(_, _)
+            | Self::LockError(_, _)
+            | Self::WaitPidError(_, _)
             | Self::TimebaseError => exit_codes::INTERNAL_ERROR,
             // file-wait timeout uses same code as command timeout (124)
             Self::WaitForFileTimeout(_) => exit_codes::TIMEOUT,
+            Self::LockBusy(_) | Self::LockQueueExpired(_, _) => exit_codes::LOCK_BUSY,
+            Self::OutsideWindow(_) => exit_codes::OUTSIDE_WINDOW,
+            /* what a shell reports for a death by signal, if we don't die */
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            Self::Interrupted(sig) => 128u8.wrapping_add(sig.as_raw() as u8),
+        }
+    }
+
+    /// For a command that couldn't be started, a stable name for why
+    /// (`"error_kind"` in JSON): a [`crate::lookup::Problem`] name.
+    #[must_use]
+    pub fn error_kind(&self) -> Option<&'static str> {
+        match self {
+            Self::NotRunnable(e) => Some(e.problem.as_str()),
+            Self::CommandNotFound(_) => Some("not_found"),
+            Self::PermissionDenied(_) => Some("permission_denied"),
+            Self::SpawnError(Errno::TextFileBusy) => Some("text_file_busy"),
+            Self::SpawnError(Errno::ArgListTooLong) => Some("argument_list_too_long"),
+            Self::SpawnError(Errno::OutOfMemory) => Some("out_of_memory"),
+            _ => None,
         }
     }
 }
 
+/// What a failed spawn or wait means for the run: not found and
+/// permission denied keep their own exit codes, the rest keep their errno.
+impl From<SpawnError> for TimeoutError {
+    fn from(e: SpawnError) -> Self {
+        match e {
+            SpawnError::NotFound(s) => Self::CommandNotFound(s),
+            SpawnError::PermissionDenied(s) => Self::PermissionDenied(s),
+            SpawnError::NotRunnable(e) => Self::NotRunnable(e),
+            SpawnError::Spawn(errno) | SpawnError::Wait(errno) => Self::SpawnError(errno),
+            SpawnError::InvalidArg => Self::Internal("invalid argument".into()),
+        }
+    }
+}
+
+/// A result with a [`TimeoutError`].
+///
+/// ```
+/// use core::time::Duration;
+/// use procguard::{Result, parse_duration};
+///
+/// fn doubled(s: &str) -> Result<Duration> {
+///     Ok(parse_duration(s)? * 2)
+/// }
+/// assert_eq!(doubled("1m").unwrap(), Duration::from_secs(120));
+/// assert!(doubled("soon").is_err());
+/// ```
 pub type Result<T> = core::result::Result<T, TimeoutError>;
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_from_spawn_error() {
+        let busy = TimeoutError::from(SpawnError::Spawn(Errno::TextFileBusy));
+        assert_eq!(
+            alloc::format!("{busy}"),
+            "failed to spawn process: text file busy (ETXTBSY) - is it still being written?"
+        );
+        assert_eq!(busy.exit_code(), exit_codes::CANNOT_INVOKE);
+        assert_eq!(busy.error_kind(), Some("text_file_busy"));
+
+        let too_big = TimeoutError::from(SpawnError::Spawn(Errno::ArgListTooLong));
+        assert_eq!(too_big.exit_code(), exit_codes::CANNOT_INVOKE);
+        assert_eq!(too_big.error_kind(), Some("argument_list_too_long"));
+
+        let nomem = TimeoutError::from(SpawnError::Spawn(Errno::OutOfMemory));
+        assert_eq!(
+            alloc::format!("{nomem}"),
+            "failed to spawn process: cannot allocate memory (ENOMEM)"
+        );
+        assert_eq!(nomem.exit_code(), exit_codes::INTERNAL_ERROR);
+        assert_eq!(nomem.error_kind(), Some("out_of_memory"));
+
+        let other = TimeoutError::from(SpawnError::Wait(Errno::Other(libc::EDOM)));
+        assert_eq!(other.exit_code(), exit_codes::INTERNAL_ERROR);
+        assert_eq!(other.error_kind(), None);
+        assert!(matches!(
+            TimeoutError::from(SpawnError::NotFound("x".into())),
+            TimeoutError::CommandNotFound(_)
+        ));
+    }
+
     #[test]
     fn test_timebase_error_display() {
         let err = TimeoutError::TimebaseError;
@@ -0,0 +1,1083 @@
+/*
+ * events.rs
+ *
+ * What happened during a run, in order. runner/ records, main.rs formats.
+ *
+ * Fixed-size array like Attempts - no allocation while the child runs, and a
+ * chatty heartbeat can't grow memory. When full, new events are counted as
+ * dropped. Exit events are the exception: they overwrite the last slot so the
+ * timeline never loses its ending.
+ *
+ * Timestamps come from the run's own clock (wall or active, same one the
+ * timeout uses) and are stored as offsets from the first recorded event.
+ * The realtime clock is read once, at that first event, so offsets can be
+ * turned into epoch times for lining up with other logs.
+ *
+ * The log also keeps MonitorLag: how late our own timer wakeups were. Not
+ * events - one sample per wakeup would flood the array - just running
+ * totals.
+ *
+ * With --proc-tree it also carries the command's process tree (proctree.rs),
+ * on the same clock. And what the command was doing when the timeout
+ * fired, sampled before anything was sent to it, and what --cpu-percent
+ * did to it. And the run's warnings (log.rs) and id (run_id.rs), so two
+ * runs in one process don't report each other's.
+ */
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::also_kill::Target;
+use crate::errno::Errno;
+use crate::json::JsonWriter;
+use crate::log::{Logger, Warning, Warnings};
+use crate::proc_info::Snapshot;
+use crate::proctree::ProcTree;
+use crate::reaper::Orphans;
+use crate::run_id::RunId;
+use crate::signal::{Signal, signal_name};
+use crate::throttle::ThrottleStats;
+
+/// Events an [`EventLog`] holds. Plenty for spawn + signals + exit across
+/// 32 attempts; heartbeats drop first.
+pub const MAX_EVENTS: usize = 64;
+
+/// Kind of supervision decision. `value` meaning depends on the kind.
+///
+/// ```
+/// use procguard::EventKind;
+///
+/// assert_eq!(EventKind::Spawned.as_str(), "spawned");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EventKind {
+    /// Child spawned. value = pid
+    Spawned,
+    /// Heartbeat tick. value = ticks skipped just before it (it ran late)
+    Heartbeat,
+    /// Highest memory sample seen this attempt. value = bytes
+    MemoryPeak,
+    /// Timeout fired. value = 0 wall clock, 1 stdin idle, 2 system sleep,
+    /// 3 time window closed
+    TimedOut,
+    /// Memory limit exceeded. value = bytes at the time
+    MemoryLimit,
+    /// We received a signal to forward. value = signal number
+    SignalReceived,
+    /// We sent a signal to the child. value = signal number
+    SignalSent,
+    /// hook started. value = 0 on-timeout, 1 crash, 2 on-limit
+    HookStarted,
+    /// hook exited. value = exit code
+    HookExited,
+    /// hook was killed for exceeding its limit. value unused
+    HookTimedOut,
+    /// hook failed to start or wait. value unused
+    HookFailed,
+    /// Waiting before the next attempt. value = delay in ms
+    RetryScheduled,
+    /// Child exited normally. value = exit code
+    Exited,
+    /// Child was terminated by a signal. value = signal number
+    Killed,
+    /// The machine slept while the command ran. value = ms asleep
+    Slept,
+    /// `--stdin-inject-on-timeout` input written. value = bytes
+    StdinInjected,
+    /// `--extend-env-file` changed the extension. value = ms past the
+    /// timeout, in all
+    Extended,
+    /// The spawn failed with a transient errno and is tried again
+    /// (`--spawn-retry`). value = errno
+    SpawnRetried,
+    /// `--verify-signal-delivery` found the command stopped after a signal
+    /// it so couldn't act on. value = that signal
+    FoundStopped,
+    /// The timeout came due with a debugger attached, and `--debugger-grace`
+    /// held off until it detached or the grace ran out. value = ms waited
+    DebuggerWait,
+    /// `--on-child-stop` found the command stopped (SIGSTOP, SIGTSTP).
+    /// value unused
+    ChildStopped,
+    /// ...and running again. value = ms it was seen stopped
+    ChildContinued,
+}
+
+impl EventKind {
+    #[inline]
+    const fn is_exit(self) -> bool {
+        matches!(self, Self::Exited | Self::Killed)
+    }
+
+    /// Stable name, used as `"event"` in JSON.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Spawned => "spawned",
+            Self::Heartbeat => "heartbeat",
+            Self::MemoryPeak => "memory_peak",
+            Self::TimedOut => "timed_out",
+            Self::MemoryLimit => "memory_limit",
+            Self::SignalReceived => "signal_received",
+            Self::SignalSent => "signal_sent",
+            Self::HookStarted => "hook_started",
+            Self::HookExited => "hook_exited",
+            Self::HookTimedOut => "hook_timed_out",
+            Self::HookFailed => "hook_failed",
+            Self::RetryScheduled => "retry_scheduled",
+            Self::Exited => "exited",
+            Self::Killed => "killed",
+            Self::Slept => "system_sleep",
+            Self::StdinInjected => "stdin_injected",
+            Self::Extended => "timeout_extended",
+            Self::SpawnRetried => "spawn_retried",
+            Self::FoundStopped => "found_stopped",
+            Self::DebuggerWait => "debugger_wait",
+            Self::ChildStopped => "child_stopped",
+            Self::ChildContinued => "child_continued",
+        }
+    }
+}
+
+/// How late the monitor's timer wakeups were, across all attempts.
+///
+/// A wakeup that was due at T and ran at T+lag means every deadline we
+/// enforced was up to `lag` late. Large values mean timeout itself was
+/// starved for CPU; see `--monitor-qos`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MonitorLag {
+    /// Wakeups measured.
+    pub samples: u32,
+    /// The latest one.
+    pub max_ns: u64,
+    /// All of them added up.
+    pub total_ns: u64,
+}
+
+impl MonitorLag {
+    /// Average lag, 0 with no samples.
+    #[inline]
+    #[must_use]
+    pub const fn mean_ns(&self) -> u64 {
+        if self.samples == 0 {
+            0
+        } else {
+            self.total_ns / self.samples as u64
+        }
+    }
+}
+
+/// One recorded event. `at_ns` is relative to the first event in the log.
+///
+/// ```
+/// use procguard::{EventKind, EventLog};
+///
+/// let mut log = EventLog::new();
+/// log.record(EventKind::Spawned, 5_000_000_000, 42);
+/// log.record(EventKind::Exited, 5_250_000_000, 0);
+///
+/// let exit = log.as_slice()[1];
+/// assert_eq!(exit.kind, EventKind::Exited);
+/// assert_eq!(exit.at_ns, 250_000_000);
+/// assert_eq!(exit.value, 0);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Event {
+    /// What happened.
+    pub kind: EventKind,
+    /// When, in nanoseconds since the first event.
+    pub at_ns: u64,
+    /// A pid, signal, exit code or duration, depending on `kind`.
+    pub value: u64,
+}
+
+const EMPTY_EVENT: Event = Event {
+    kind: EventKind::Spawned,
+    at_ns: 0,
+    value: 0,
+};
+
+/// Timeline of a run. Pass to [`crate::runner::run_with_retry_events`].
+///
+/// ```
+/// use core::time::Duration;
+/// use procguard::{EventKind, EventLog, RunConfig, run_with_retry_events};
+///
+/// let config = RunConfig {
+///     timeout: Duration::from_secs(10),
+///     ..RunConfig::default()
+/// };
+/// let mut events = EventLog::new();
+/// let args = ["-c".to_string(), "exit 0".to_string()];
+/// run_with_retry_events("sh", &args, &config, &mut events).unwrap();
+///
+/// let first = events.as_slice()[0];
+/// assert_eq!(first.kind, EventKind::Spawned);
+/// assert_eq!(first.at_ns, 0);
+/// ```
+pub struct EventLog {
+    data: [Event; MAX_EVENTS],
+    len: usize,
+    dropped: u32,
+    origin_ns: Option<u64>,
+    origin_epoch_ms: u64,
+    lag: MonitorLag,
+    slept_ns: u64,
+    child_stopped_ns: Option<u64>,
+    extended_ns: u64,
+    heartbeats: u32,
+    heartbeats_missed: u64,
+    spawn_retries: u32,
+    at_timeout: Option<Snapshot>,
+    translated: Option<bool>,
+    traced: Option<bool>,
+    throttle: Option<ThrottleStats>,
+    orphans: Option<Orphans>,
+    also_killed: Option<Vec<Target>>,
+    warnings: Warnings,
+    run_id: Option<RunId>,
+    tree: ProcTree,
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventLog {
+    /// An empty log.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            data: [EMPTY_EVENT; MAX_EVENTS],
+            len: 0,
+            dropped: 0,
+            origin_ns: None,
+            origin_epoch_ms: 0,
+            lag: MonitorLag {
+                samples: 0,
+                max_ns: 0,
+                total_ns: 0,
+            },
+            slept_ns: 0,
+            child_stopped_ns: None,
+            extended_ns: 0,
+            heartbeats: 0,
+            heartbeats_missed: 0,
+            spawn_retries: 0,
+            at_timeout: None,
+            translated: None,
+            traced: None,
+            throttle: None,
+            orphans: None,
+            also_killed: None,
+            warnings: Warnings::new(),
+            run_id: None,
+            tree: ProcTree::new(),
+        }
+    }
+
+    /* clock reading -> offset from first event. clock going backwards clamps to 0. */
+    #[inline]
+    pub(crate) fn offset(&mut self, now_ns: u64) -> u64 {
+        let origin = match self.origin_ns {
+            Some(origin) => origin,
+            None => {
+                self.origin_ns = Some(now_ns);
+                self.origin_epoch_ms = realtime_ms();
+                now_ns
+            }
+        };
+        now_ns.saturating_sub(origin)
+    }
+
+    /// Realtime clock (ms since the Unix epoch) at the first event, 0 if
+    /// nothing was recorded.
+    #[inline]
+    pub fn origin_epoch_ms(&self) -> u64 {
+        self.origin_epoch_ms
+    }
+
+    /// Append an event. `now_ns` is a reading of the run's clock.
+    pub fn record(&mut self, kind: EventKind, now_ns: u64, value: u64) {
+        let at_ns = self.offset(now_ns);
+        let event = Event { kind, at_ns, value };
+        if self.len < MAX_EVENTS {
+            self.data[self.len] = event;
+            self.len += 1;
+        } else if kind.is_exit() {
+            /* keep the ending, sacrifice whatever was last */
+            self.data[MAX_EVENTS - 1] = event;
+            self.dropped = self.dropped.saturating_add(1);
+        } else {
+            self.dropped = self.dropped.saturating_add(1);
+        }
+    }
+
+    /// Record a running maximum: updates the existing event of this kind for
+    /// the current attempt if `value` is higher, otherwise appends.
+    pub fn record_peak(&mut self, kind: EventKind, now_ns: u64, value: u64) {
+        /* only look back to the latest spawn - each attempt gets its own peak */
+        let current = self.data[..self.len]
+            .iter()
+            .rposition(|e| e.kind == kind || e.kind == EventKind::Spawned)
+            .filter(|&i| self.data[i].kind == kind);
+
+        match current {
+            Some(i) if value > self.data[i].value => {
+                let at_ns = self.offset(now_ns);
+                self.data[i].at_ns = at_ns;
+                self.data[i].value = value;
+            }
+            Some(_) => {}
+            None => self.record(kind, now_ns, value),
+        }
+    }
+
+    /// Add one timer wakeup that ran `lag_ns` after it was due.
+    pub fn record_lag(&mut self, lag_ns: u64) {
+        let lag = &mut self.lag;
+        lag.samples = lag.samples.saturating_add(1);
+        lag.max_ns = lag.max_ns.max(lag_ns);
+        lag.total_ns = lag.total_ns.saturating_add(lag_ns);
+    }
+
+    /// Timer wakeup lag so far.
+    #[inline]
+    pub fn lag(&self) -> MonitorLag {
+        self.lag
+    }
+
+    /// Note that the machine slept for `slept_ns` while the command ran.
+    pub fn record_sleep(&mut self, now_ns: u64, slept_ns: u64) {
+        self.slept_ns = self.slept_ns.saturating_add(slept_ns);
+        self.record(EventKind::Slept, now_ns, slept_ns / 1_000_000);
+    }
+
+    /// Time the machine spent asleep while a command ran, across all
+    /// attempts.
+    #[inline]
+    pub fn slept_ns(&self) -> u64 {
+        self.slept_ns
+    }
+
+    /// Add `stopped_ns` to the time `--on-child-stop` saw the command
+    /// stopped; 0 to note that it's looking.
+    pub fn add_child_stopped(&mut self, stopped_ns: u64) {
+        let total = self.child_stopped_ns.get_or_insert(0);
+        *total = total.saturating_add(stopped_ns);
+    }
+
+    /// Time the command was seen stopped, across all attempts. None if
+    /// `--on-child-stop` wasn't looking.
+    #[inline]
+    pub fn child_stopped_ns(&self) -> Option<u64> {
+        self.child_stopped_ns
+    }
+
+    /// Note that `--extend-env-file` now puts the deadline `extended_ns`
+    /// past the timeout.
+    pub fn record_extension(&mut self, now_ns: u64, extended_ns: u64) {
+        self.extended_ns = extended_ns;
+        self.record(EventKind::Extended, now_ns, extended_ns / 1_000_000);
+    }
+
+    /// How far `--extend-env-file` had put the deadline past the timeout
+    /// when the last attempt ended.
+    #[inline]
+    pub fn extended_ns(&self) -> u64 {
+        self.extended_ns
+    }
+
+    /// A heartbeat tick, after `missed` ticks that came due while the
+    /// monitor was held up and were skipped rather than bunched up.
+    pub fn record_heartbeat(&mut self, now_ns: u64, missed: u64) {
+        self.heartbeats = self.heartbeats.saturating_add(1);
+        self.heartbeats_missed = self.heartbeats_missed.saturating_add(missed);
+        self.record(EventKind::Heartbeat, now_ns, missed);
+    }
+
+    /// Heartbeat ticks sent and skipped, across all attempts.
+    #[inline]
+    pub fn heartbeats(&self) -> (u32, u64) {
+        (self.heartbeats, self.heartbeats_missed)
+    }
+
+    /// A spawn that failed with `errno`, to be tried again.
+    pub fn record_spawn_retry(&mut self, now_ns: u64, errno: Errno) {
+        self.spawn_retries = self.spawn_retries.saturating_add(1);
+        self.record(
+            EventKind::SpawnRetried,
+            now_ns,
+            u64::from(errno.raw().unsigned_abs()),
+        );
+    }
+
+    /// Spawns tried again, across all attempts.
+    #[inline]
+    pub fn spawn_retries(&self) -> u32 {
+        self.spawn_retries
+    }
+
+    /// The command as the timeout, or the memory limit, found it. A later
+    /// one (the next attempt's) replaces it.
+    #[inline]
+    pub fn record_at_timeout(&mut self, snapshot: Snapshot) {
+        self.at_timeout = Some(snapshot);
+    }
+
+    /// The command as the last timeout or memory limit found it, None if
+    /// neither fired.
+    #[inline]
+    pub fn at_timeout(&self) -> Option<Snapshot> {
+        self.at_timeout
+    }
+
+    /// One look at whether the command runs under Rosetta (see
+    /// [`crate::proc_info::is_translated`]); `None` couldn't tell. Seen
+    /// translated once, in any attempt, the run counts as translated.
+    pub fn record_translated(&mut self, translated: Option<bool>) {
+        if let Some(translated) = translated {
+            self.translated = Some(self.translated == Some(true) || translated);
+        }
+    }
+
+    /// Whether the command ran under Rosetta, None if no look could tell.
+    #[inline]
+    pub fn translated(&self) -> Option<bool> {
+        self.translated
+    }
+
+    /// Whether a debugger was attached when the timeout came due (see
+    /// [`crate::proc_info::is_traced`]); sticks like
+    /// [`EventLog::record_translated`].
+    pub fn record_traced(&mut self, traced: Option<bool>) {
+        if let Some(traced) = traced {
+            self.traced = Some(self.traced == Some(true) || traced);
+        }
+    }
+
+    /// Whether a timeout found the command being debugged, None if none
+    /// fired or no look could tell.
+    #[inline]
+    pub fn traced(&self) -> Option<bool> {
+        self.traced
+    }
+
+    /// What `--cpu-percent` did to one attempt; summed with the others'.
+    pub fn record_throttle(&mut self, stats: &ThrottleStats) {
+        self.throttle.get_or_insert_default().add(stats);
+    }
+
+    /// What `--cpu-percent` did across all attempts, None if it never
+    /// throttled one.
+    #[inline]
+    pub fn throttle(&self) -> Option<ThrottleStats> {
+        self.throttle
+    }
+
+    /// What `--reap-orphans` swept up after one attempt; summed with the
+    /// others'.
+    pub fn record_orphans(&mut self, orphans: Orphans) {
+        self.orphans.get_or_insert_default().add(orphans);
+    }
+
+    /// What `--reap-orphans` swept up across all attempts, None without it.
+    #[inline]
+    pub fn orphans(&self) -> Option<Orphans> {
+        self.orphans
+    }
+
+    /// Record a warning and print it to stderr through `log`, unless it's
+    /// quiet. Recorded regardless, so --json still lists it.
+    pub fn warn(&mut self, log: Logger<'_>, w: Warning) {
+        self.warnings.record(w);
+        log.warn(w);
+    }
+
+    /// The warnings recorded for this run.
+    #[inline]
+    pub const fn warnings(&self) -> &Warnings {
+        &self.warnings
+    }
+
+    /// Give the run an id: its JSON carries it as `run_id`, and with
+    /// [`RunId::given`] its heartbeats too.
+    pub fn set_run_id(&mut self, id: RunId) {
+        self.run_id = Some(id);
+    }
+
+    /// The run's id, None if it was given none.
+    #[inline]
+    pub const fn run_id(&self) -> Option<&RunId> {
+        self.run_id.as_ref()
+    }
+
+    /// What `--also-kill` sent each of its processes, None without it.
+    #[inline]
+    pub fn also_killed(&self) -> Option<&[Target]> {
+        self.also_killed.as_deref()
+    }
+
+    #[inline]
+    pub(crate) fn also_killed_mut(&mut self) -> &mut Vec<Target> {
+        self.also_killed.get_or_insert_default()
+    }
+
+    /// The command's process tree, empty without `--proc-tree`.
+    #[inline]
+    pub fn tree(&self) -> &ProcTree {
+        &self.tree
+    }
+
+    #[inline]
+    pub(crate) fn tree_mut(&mut self) -> &mut ProcTree {
+        &mut self.tree
+    }
+
+    /// The events, oldest first.
+    #[inline]
+    pub fn as_slice(&self) -> &[Event] {
+        &self.data[..self.len]
+    }
+
+    /// Events recorded.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether none were recorded.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Events that didn't fit in the log.
+    #[inline]
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// Human-readable timeline, one event per line. Used by `--explain`.
+    pub fn write_timeline<W: fmt::Write>(&self, out: &mut W) -> fmt::Result {
+        for event in self.as_slice() {
+            let mut offset = OffsetBuf::new();
+            write_offset(&mut offset, event.at_ns)?;
+            write!(out, "  {:<10}", offset.as_str())?;
+            write_description(out, event)?;
+            out.write_char('\n')?;
+        }
+        if self.dropped > 0 {
+            writeln!(out, "  ({} more events not recorded)", self.dropped)?;
+        }
+        Ok(())
+    }
+
+    /// JSON array of events: `[{"event":"spawned","offset_ms":0,
+    /// "epoch_ms":...,"pid":123},...]`. The value gets a key named for
+    /// what it means; kinds without one leave it out.
+    pub fn write_json<W: fmt::Write>(&self, w: &mut JsonWriter<W>) {
+        w.begin_array();
+        for event in self.as_slice() {
+            let offset_ms = event.at_ns / 1_000_000;
+            w.begin_object();
+            w.field_str("event", event.kind.as_str());
+            w.field_u64("offset_ms", offset_ms);
+            w.field_u64("epoch_ms", self.origin_epoch_ms.saturating_add(offset_ms));
+            write_json_value(w, event);
+            w.end_object();
+        }
+        w.end_array();
+    }
+}
+
+fn write_json_value<W: fmt::Write>(w: &mut JsonWriter<W>, event: &Event) {
+    let v = event.value;
+    match event.kind {
+        EventKind::Spawned => w.field_u64("pid", v),
+        EventKind::MemoryPeak | EventKind::MemoryLimit => w.field_u64("bytes", v),
+        EventKind::TimedOut => match v {
+            1 => w.field_str("reason", "stdin_idle"),
+            2 => w.field_str("reason", "system_sleep"),
+            3 => w.field_str("reason", "window_closed"),
+            4 => w.field_str("reason", "child_stopped"),
+            _ => w.field_str("reason", "wall_clock"),
+        },
+        EventKind::SignalReceived
+        | EventKind::SignalSent
+        | EventKind::Killed
+        | EventKind::FoundStopped => w.field_str("signal", signal_str(v)),
+        EventKind::HookStarted => match v {
+            1 => w.field_str("hook", "crash"),
+            2 => w.field_str("hook", "on_limit"),
+            _ => w.field_str("hook", "on_timeout"),
+        },
+        EventKind::HookExited | EventKind::Exited => w.field_u64("code", v),
+        EventKind::RetryScheduled => w.field_u64("delay_ms", v),
+        EventKind::Slept => w.field_u64("slept_ms", v),
+        EventKind::StdinInjected => w.field_u64("bytes", v),
+        EventKind::Extended => w.field_u64("extended_ms", v),
+        EventKind::SpawnRetried => w.field_u64("errno", v),
+        EventKind::DebuggerWait => w.field_u64("waited_ms", v),
+        EventKind::ChildContinued => w.field_u64("stopped_ms", v),
+        EventKind::Heartbeat if v > 0 => w.field_u64("missed", v),
+        EventKind::Heartbeat
+        | EventKind::HookTimedOut
+        | EventKind::HookFailed
+        | EventKind::ChildStopped => w,
+    };
+}
+
+/* wall-clock time for the epoch anchor; 0 if the clock can't be read */
+pub(crate) fn realtime_ms() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: ts is a valid timespec for clock_gettime to fill in
+    if unsafe { libc::clock_gettime(libc::CLOCK_REALTIME, &raw mut ts) } != 0 {
+        return 0;
+    }
+    let secs = u64::try_from(ts.tv_sec).unwrap_or(0);
+    let ms = u64::try_from(ts.tv_nsec).unwrap_or(0) / 1_000_000;
+    secs.saturating_mul(1000).saturating_add(ms)
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn signal_str(value: u64) -> &'static str {
+    Signal::try_from_raw(value as i32).map_or("signal", signal_name)
+}
+
+fn write_description<W: fmt::Write>(out: &mut W, event: &Event) -> fmt::Result {
+    match event.kind {
+        EventKind::Spawned => write!(out, "spawned pid {}", event.value),
+        EventKind::Heartbeat => match event.value {
+            0 => out.write_str("heartbeat"),
+            n => write!(out, "heartbeat ({n} missed)"),
+        },
+        EventKind::MemoryPeak => {
+            out.write_str("memory peaked at ")?;
+            write_bytes(out, event.value)
+        }
+        EventKind::TimedOut => match event.value {
+            1 => out.write_str("timeout fired (stdin idle)"),
+            2 => out.write_str("timeout fired (system sleep)"),
+            3 => out.write_str("timeout fired (time window closed)"),
+            4 => out.write_str("timeout fired (child stopped)"),
+            _ => out.write_str("timeout fired (wall clock)"),
+        },
+        EventKind::MemoryLimit => {
+            out.write_str("memory limit exceeded at ")?;
+            write_bytes(out, event.value)
+        }
+        EventKind::SignalReceived => write!(out, "received {}", signal_str(event.value)),
+        EventKind::SignalSent => write!(out, "{} sent", signal_str(event.value)),
+        EventKind::HookStarted => match event.value {
+            1 => out.write_str("crash hook started"),
+            2 => out.write_str("on-limit hook started"),
+            _ => out.write_str("on-timeout hook started"),
+        },
+        EventKind::HookExited => write!(out, "hook exited with code {}", event.value),
+        EventKind::HookTimedOut => out.write_str("hook timed out, killed"),
+        EventKind::HookFailed => out.write_str("hook failed"),
+        EventKind::RetryScheduled => write!(out, "retrying in {}ms", event.value),
+        EventKind::Exited => write!(out, "child exited with code {}", event.value),
+        EventKind::Killed => write!(out, "child killed by {}", signal_str(event.value)),
+        EventKind::Slept => write!(out, "system slept for {}ms", event.value),
+        EventKind::StdinInjected => write!(out, "wrote {} bytes to stdin", event.value),
+        EventKind::Extended => write!(out, "timeout extended by {}ms", event.value),
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        EventKind::SpawnRetried => write!(
+            out,
+            "spawn failed: {}, retrying",
+            Errno::from_raw(event.value as i32)
+        ),
+        EventKind::FoundStopped => write!(
+            out,
+            "child is stopped, can't act on {}",
+            signal_str(event.value)
+        ),
+        EventKind::DebuggerWait => {
+            write!(out, "waited {}ms for the debugger to detach", event.value)
+        }
+        EventKind::ChildStopped => out.write_str("child stopped"),
+        EventKind::ChildContinued => {
+            write!(out, "child continued after {}ms stopped", event.value)
+        }
+    }
+}
+
+/* "+0ms", "+850ms", "+60s", "+120.4s" - integer math only, no floats */
+fn write_offset<W: fmt::Write>(out: &mut W, ns: u64) -> fmt::Result {
+    let ms = ns / 1_000_000;
+    if ms < 1000 {
+        return write!(out, "+{}ms", ms);
+    }
+    let secs = ms / 1000;
+    let tenths = (ms % 1000) / 100;
+    if tenths == 0 {
+        write!(out, "+{}s", secs)
+    } else {
+        write!(out, "+{}.{}s", secs, tenths)
+    }
+}
+
+/* "512B", "64.5K", "1.2G" - binary units to match --mem-limit */
+fn write_bytes<W: fmt::Write>(out: &mut W, bytes: u64) -> fmt::Result {
+    const UNITS: [(u64, char); 4] = [
+        (1 << 40, 'T'),
+        (1 << 30, 'G'),
+        (1 << 20, 'M'),
+        (1 << 10, 'K'),
+    ];
+    for (size, suffix) in UNITS {
+        if bytes >= size {
+            let whole = bytes / size;
+            let tenths = (bytes % size) * 10 / size;
+            return if tenths == 0 {
+                write!(out, "{}{}", whole, suffix)
+            } else {
+                write!(out, "{}.{}{}", whole, tenths, suffix)
+            };
+        }
+    }
+    write!(out, "{}B", bytes)
+}
+
+/* tiny stack buffer so offsets can be padded without allocating */
+struct OffsetBuf {
+    buf: [u8; 24],
+    len: usize,
+}
+
+impl OffsetBuf {
+    const fn new() -> Self {
+        Self {
+            buf: [0; 24],
+            len: 0,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        /* only ever written through fmt::Write with ASCII digits and units */
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("")
+    }
+}
+
+impl fmt::Write for OffsetBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let bytes = s.as_bytes();
+        let end = self.len.checked_add(bytes.len()).ok_or(fmt::Error)?;
+        if end > self.buf.len() {
+            return Err(fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+
+    fn timeline(log: &EventLog) -> String {
+        let mut s = String::new();
+        log.write_timeline(&mut s).unwrap();
+        s
+    }
+
+    #[test]
+    fn test_offsets_relative_to_first_event() {
+        let mut log = EventLog::new();
+        log.record(EventKind::Spawned, 5_000_000_000, 42);
+        log.record(EventKind::Heartbeat, 65_000_000_000, 0);
+        assert_eq!(log.as_slice()[0].at_ns, 0);
+        assert_eq!(log.as_slice()[1].at_ns, 60_000_000_000);
+    }
+
+    #[test]
+    fn test_write_json() {
+        let mut log = EventLog::new();
+        log.record(EventKind::Spawned, 1_000_000_000, 42);
+        log.record(EventKind::TimedOut, 1_500_000_000, 0);
+        log.record(EventKind::SignalSent, 1_500_000_000, 15);
+        log.record(EventKind::Killed, 1_502_000_000, 15);
+        log.origin_epoch_ms = 1_700_000_000_000;
+
+        let mut w = JsonWriter::new();
+        log.write_json(&mut w);
+        assert_eq!(
+            w.finish(),
+            concat!(
+                r#"[{"event":"spawned","offset_ms":0,"epoch_ms":1700000000000,"pid":42},"#,
+                r#"{"event":"timed_out","offset_ms":500,"epoch_ms":1700000000500,"reason":"wall_clock"},"#,
+                r#"{"event":"signal_sent","offset_ms":500,"epoch_ms":1700000000500,"signal":"SIGTERM"},"#,
+                r#"{"event":"killed","offset_ms":502,"epoch_ms":1700000000502,"signal":"SIGTERM"}]"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_record_sleep() {
+        let mut log = EventLog::new();
+        log.record(EventKind::Spawned, 0, 42);
+        log.record_sleep(3_000_000_000, 2_500_000_000);
+        log.record_sleep(9_000_000_000, 1_000_000_000);
+        assert_eq!(log.slept_ns(), 3_500_000_000);
+        assert_eq!(log.as_slice()[1].value, 2500);
+        assert!(timeline(&log).contains("system slept for 2500ms"));
+
+        log.origin_epoch_ms = 1_700_000_000_000;
+        let mut w = JsonWriter::new();
+        log.write_json(&mut w);
+        assert!(w.finish().contains(
+            r#"{"event":"system_sleep","offset_ms":3000,"epoch_ms":1700000003000,"slept_ms":2500}"#
+        ));
+    }
+
+    #[test]
+    fn test_child_stops() {
+        let mut log = EventLog::new();
+        assert_eq!(log.child_stopped_ns(), None);
+        log.add_child_stopped(0);
+        assert_eq!(log.child_stopped_ns(), Some(0));
+        log.record(EventKind::ChildStopped, 1_000_000_000, 0);
+        log.add_child_stopped(400_000_000);
+        log.add_child_stopped(300_000_000);
+        log.record(EventKind::ChildContinued, 1_700_000_000, 700);
+        assert_eq!(log.child_stopped_ns(), Some(700_000_000));
+        let out = timeline(&log);
+        assert!(out.contains("child stopped"), "{out}");
+        assert!(out.contains("child continued after 700ms stopped"), "{out}");
+
+        let mut w = JsonWriter::new();
+        log.write_json(&mut w);
+        let json = w.finish();
+        assert!(
+            json.contains(r#""event":"child_stopped","offset_ms":0,"#),
+            "{json}"
+        );
+        assert!(json.contains(r#""stopped_ms":700}"#), "{json}");
+    }
+
+    #[test]
+    fn test_hook_started_names_hook() {
+        let mut log = EventLog::new();
+        log.record(EventKind::HookStarted, 0, 0);
+        log.record(EventKind::HookStarted, 0, 1);
+        log.record(EventKind::HookStarted, 0, 2);
+        log.origin_epoch_ms = 1_700_000_000_000;
+
+        let mut w = JsonWriter::new();
+        log.write_json(&mut w);
+        let json = w.finish();
+        assert!(json.contains(
+            r#""event":"hook_started","offset_ms":0,"epoch_ms":1700000000000,"hook":"on_timeout"}"#
+        ));
+        assert!(json.contains(r#""hook":"crash"}"#));
+        assert!(json.contains(r#""hook":"on_limit"}"#));
+
+        let out = timeline(&log);
+        assert!(out.contains("on-timeout hook started"));
+        assert!(out.contains("crash hook started"));
+        assert!(out.contains("on-limit hook started"));
+    }
+
+    #[test]
+    fn test_origin_epoch_set_on_first_event() {
+        let mut log = EventLog::new();
+        assert_eq!(log.origin_epoch_ms(), 0);
+        log.record(EventKind::Spawned, 5, 1);
+        assert!(log.origin_epoch_ms() > 0);
+    }
+
+    #[test]
+    fn test_record_lag() {
+        let mut log = EventLog::new();
+        assert_eq!(log.lag().mean_ns(), 0);
+        log.record_lag(2_000_000);
+        log.record_lag(10_000_000);
+        log.record_lag(0);
+        let lag = log.lag();
+        assert_eq!(lag.samples, 3);
+        assert_eq!(lag.max_ns, 10_000_000);
+        assert_eq!(lag.mean_ns(), 4_000_000);
+        /* lag isn't an event */
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn test_record_spawn_retry() {
+        let mut log = EventLog::new();
+        log.record_spawn_retry(0, Errno::TextFileBusy);
+        log.record_spawn_retry(100_000_000, Errno::TryAgain);
+        assert_eq!(log.spawn_retries(), 2);
+        assert!(timeline(&log).contains("spawn failed: text file busy (ETXTBSY), retrying"));
+        let mut w = JsonWriter::new();
+        log.write_json(&mut w);
+        let json = w.finish();
+        assert!(
+            json.contains(&alloc::format!(
+                r#"{{"event":"spawn_retried","offset_ms":0,"epoch_ms":{},"errno":{}}}"#,
+                log.origin_epoch_ms(),
+                libc::ETXTBSY
+            )),
+            "{json}"
+        );
+    }
+
+    #[test]
+    fn test_record_heartbeat() {
+        let mut log = EventLog::new();
+        log.record_heartbeat(1_000_000_000, 0);
+        log.record_heartbeat(4_000_000_000, 2);
+        assert_eq!(log.heartbeats(), (2, 2));
+        assert!(timeline(&log).contains("heartbeat (2 missed)"));
+        let mut w = JsonWriter::new();
+        log.write_json(&mut w);
+        let json = w.finish();
+        assert!(
+            json.contains(r#""event":"heartbeat","offset_ms":3000,"#),
+            "{json}"
+        );
+        assert!(json.contains(r#","missed":2}"#), "{json}");
+    }
+
+    #[test]
+    fn test_record_translated_sticks() {
+        let mut log = EventLog::new();
+        log.record_translated(None);
+        assert_eq!(log.translated(), None);
+        log.record_translated(Some(false));
+        assert_eq!(log.translated(), Some(false));
+        /* exec'd into x86_64 after the first look; gone by the last */
+        log.record_translated(Some(true));
+        log.record_translated(Some(false));
+        log.record_translated(None);
+        assert_eq!(log.translated(), Some(true));
+    }
+
+    #[test]
+    fn test_record_throttle_sums_attempts() {
+        let mut log = EventLog::new();
+        assert_eq!(log.throttle(), None);
+        let attempt = ThrottleStats {
+            stopped_ns: 500_000_000,
+            stops: 5,
+            cpu_ns: 250_000_000,
+            wall_ns: 1_000_000_000,
+        };
+        log.record_throttle(&attempt);
+        log.record_throttle(&attempt);
+        let all = log.throttle().unwrap();
+        assert_eq!((all.stopped_ns, all.stops), (1_000_000_000, 10));
+        assert_eq!(all.cpu_percent_tenths(), 250);
+    }
+
+    #[test]
+    fn test_clock_backwards_clamps_to_zero() {
+        let mut log = EventLog::new();
+        log.record(EventKind::Spawned, 1_000, 1);
+        log.record(EventKind::Heartbeat, 500, 0);
+        assert_eq!(log.as_slice()[1].at_ns, 0);
+    }
+
+    #[test]
+    fn test_full_log_drops_but_keeps_exit() {
+        let mut log = EventLog::new();
+        for i in 0..(MAX_EVENTS as u64 + 5) {
+            log.record(EventKind::Heartbeat, i, 0);
+        }
+        assert_eq!(log.len(), MAX_EVENTS);
+        assert_eq!(log.dropped(), 5);
+
+        log.record(EventKind::Exited, 1_000, 0);
+        assert_eq!(log.as_slice()[MAX_EVENTS - 1].kind, EventKind::Exited);
+        assert_eq!(log.dropped(), 6);
+    }
+
+    #[test]
+    fn test_record_peak_keeps_maximum() {
+        let mut log = EventLog::new();
+        log.record(EventKind::Spawned, 0, 1);
+        log.record_peak(EventKind::MemoryPeak, 100, 10);
+        log.record_peak(EventKind::MemoryPeak, 200, 30);
+        log.record_peak(EventKind::MemoryPeak, 300, 20);
+        assert_eq!(log.len(), 2);
+        assert_eq!(log.as_slice()[1].value, 30);
+        assert_eq!(log.as_slice()[1].at_ns, 200);
+    }
+
+    #[test]
+    fn test_record_peak_per_attempt() {
+        let mut log = EventLog::new();
+        log.record(EventKind::Spawned, 0, 1);
+        log.record_peak(EventKind::MemoryPeak, 100, 50);
+        log.record(EventKind::Spawned, 200, 2);
+        log.record_peak(EventKind::MemoryPeak, 300, 10);
+        assert_eq!(log.len(), 4);
+        assert_eq!(log.as_slice()[3].value, 10);
+    }
+
+    #[test]
+    fn test_timeline_format() {
+        let mut log = EventLog::new();
+        let s = 1_000_000_000;
+        log.record(EventKind::Spawned, 0, 4242);
+        log.record(EventKind::Heartbeat, 60 * s, 0);
+        log.record_peak(EventKind::MemoryPeak, 90 * s, 1_300_000_000); /* ~1.2G */
+        log.record(EventKind::TimedOut, 120 * s, 0);
+        log.record(EventKind::SignalSent, 120 * s, 15);
+        log.record(EventKind::Killed, 120 * s + 400_000_000, 15);
+
+        let out = timeline(&log);
+        let lines: alloc::vec::Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "  +0ms      spawned pid 4242");
+        assert_eq!(lines[1], "  +60s      heartbeat");
+        assert_eq!(lines[2], "  +90s      memory peaked at 1.2G");
+        assert_eq!(lines[3], "  +120s     timeout fired (wall clock)");
+        assert_eq!(lines[4], "  +120s     SIGTERM sent");
+        assert_eq!(lines[5], "  +120.4s   child killed by SIGTERM");
+    }
+
+    #[test]
+    fn test_timeline_reports_dropped() {
+        let mut log = EventLog::new();
+        for i in 0..(MAX_EVENTS as u64 + 3) {
+            log.record(EventKind::Heartbeat, i, 0);
+        }
+        assert!(timeline(&log).ends_with("(3 more events not recorded)\n"));
+    }
+
+    #[test]
+    fn test_write_bytes_units() {
+        let render = |b| {
+            let mut s = String::new();
+            write_bytes(&mut s, b).unwrap();
+            s
+        };
+        assert_eq!(render(512), "512B");
+        assert_eq!(render(1024), "1K");
+        assert_eq!(render(1536), "1.5K");
+        assert_eq!(render(512 * 1024 * 1024), "512M");
+        assert_eq!(render(2 * 1024 * 1024 * 1024), "2G");
+    }
+
+    #[test]
+    fn test_write_offset() {
+        let render = |ns| {
+            let mut s = String::new();
+            write_offset(&mut s, ns).unwrap();
+            s
+        };
+        assert_eq!(render(0), "+0ms");
+        assert_eq!(render(999_999_999), "+999ms");
+        assert_eq!(render(1_000_000_000), "+1s");
+        assert_eq!(render(1_450_000_000), "+1.4s");
+    }
+}
@@ -0,0 +1,172 @@
+/*
+ * extend.rs
+ *
+ * --extend-env-file PATH: a deadline CI can push back while the command
+ * runs, with nothing more than a file write - for runners where a socket
+ * or a signal to us isn't an option. the file is KEY=VALUE lines, the
+ * shape CI env files already have; we read one key:
+ *
+ *     TIMEOUT_EXTEND=10m
+ *
+ * the value is the whole extension, past the original timeout, not an
+ * increment: re-reading the same file changes nothing, and writing a
+ * smaller value takes time back. it's capped at --extend-max (default:
+ * the timeout again, so at most double). the last TIMEOUT_EXTEND wins;
+ * other keys, blank lines and # comments are skipped.
+ *
+ * the file is re-read every REREAD_INTERVAL_NS. a missing file, or one
+ * without the key, leaves the extension where it was - a writer replacing
+ * it non-atomically shouldn't cut the deadline short. every change that
+ * takes effect is logged, and a value that isn't a duration is logged and
+ * ignored, once per version of the file.
+ */
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use crate::duration::{format_duration, parse_duration};
+use crate::log::Logger;
+
+/// The key read from the file.
+pub const KEY: &str = "TIMEOUT_EXTEND";
+
+/// How often the file is re-read while the command runs.
+pub const REREAD_INTERVAL_NS: u64 = 1_000_000_000;
+
+/* far more than a few KEY=VALUE lines; anything bigger isn't ours */
+const FILE_LIMIT: usize = 64 * 1024;
+
+/// The `TIMEOUT_EXTEND` value in `contents`: `Ok(None)` without one,
+/// `Err(value)` when the last one isn't a duration. An `export ` prefix
+/// and quotes around the value are allowed, as shells write them.
+pub fn parse(contents: &[u8]) -> Result<Option<Duration>, String> {
+    let text = String::from_utf8_lossy(contents);
+    /* from the end: the last one wins */
+    let value = text
+        .lines()
+        .rev()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = line.split_once('=')?;
+            (key.trim() == KEY).then_some(value.trim())
+        })
+        .next();
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let unquoted = ['"', '\'']
+        .iter()
+        .find_map(|&q| value.strip_prefix(q)?.strip_suffix(q))
+        .unwrap_or(value);
+    parse_duration(unquoted)
+        .map(Some)
+        .map_err(|_| value.to_string())
+}
+
+/// One attempt's view of the file: the extension in force and the
+/// contents it came from.
+pub struct ExtendFile<'a> {
+    path: &'a str,
+    max: Duration,
+    log: Logger<'a>,
+    applied: Duration,
+    /* the contents last acted on: the same again isn't re-parsed or
+     * re-logged */
+    seen: Option<Vec<u8>>,
+}
+
+impl<'a> ExtendFile<'a> {
+    /// Nothing applied yet. `max` caps the extension; the changes go to
+    /// `log`, unless it's quiet.
+    #[must_use]
+    pub const fn new(path: &'a str, max: Duration, log: Logger<'a>) -> Self {
+        Self {
+            path,
+            max,
+            log,
+            applied: Duration::ZERO,
+            seen: None,
+        }
+    }
+
+    /// The extension in force.
+    #[inline]
+    #[must_use]
+    pub const fn applied(&self) -> Duration {
+        self.applied
+    }
+
+    /// Re-read the file. `Some(extension)` when the extension changed.
+    pub fn poll(&mut self) -> Option<Duration> {
+        let contents = crate::io::read_file(self.path.as_bytes(), FILE_LIMIT).ok()?;
+        if self.seen.as_deref() == Some(contents.as_slice()) {
+            return None;
+        }
+        let asked = parse(&contents);
+        self.seen = Some(contents);
+        let asked = match asked {
+            Ok(Some(asked)) => asked,
+            Ok(None) => return None,
+            Err(value) => {
+                crate::log_info!(
+                    self.log,
+                    "ignoring {}={} in '{}': not a duration",
+                    KEY,
+                    value,
+                    self.path
+                );
+                return None;
+            }
+        };
+        let extension = asked.min(self.max);
+        if extension == self.applied {
+            return None;
+        }
+        if asked > self.max {
+            crate::log_info!(
+                self.log,
+                "timeout extended by {} ({}={} in '{}', capped by --extend-max)",
+                format_duration(extension),
+                KEY,
+                format_duration(asked),
+                self.path
+            );
+        } else {
+            crate::log_info!(
+                self.log,
+                "timeout extended by {} (was {}, from '{}')",
+                format_duration(extension),
+                format_duration(self.applied),
+                self.path
+            );
+        }
+        self.applied = extension;
+        Some(extension)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let secs = |s| Ok(Some(Duration::from_secs(s)));
+        assert_eq!(parse(b""), Ok(None));
+        assert_eq!(parse(b"CI=true\nOTHER_TIMEOUT_EXTEND=5m\n"), Ok(None));
+        assert_eq!(parse(b"TIMEOUT_EXTEND=30s"), secs(30));
+        assert_eq!(parse(b"TIMEOUT_EXTEND=30s\nTIMEOUT_EXTEND=2m\n"), secs(120));
+        assert_eq!(
+            parse(b"# TIMEOUT_EXTEND=1h\nTIMEOUT_EXTEND = 90\n"),
+            secs(90)
+        );
+        assert_eq!(parse(b"export TIMEOUT_EXTEND=\"5m\"\n"), secs(300));
+        assert_eq!(parse(b"TIMEOUT_EXTEND='0'\r\n"), secs(0));
+        assert_eq!(parse(b"TIMEOUT_EXTEND=soon\n"), Err("soon".to_string()));
+        /* the last one counts, even when an earlier one was bad */
+        assert_eq!(parse(b"TIMEOUT_EXTEND=soon\nTIMEOUT_EXTEND=1m"), secs(60));
+    }
+}
@@ -0,0 +1,185 @@
+/*
+ * failsafe.rs
+ *
+ * last line of defence against procguard hanging itself. a bug in the
+ * monitor loop, or a child stuck in uninterruptible sleep that SIGKILL
+ * can't reap, could otherwise keep us waiting forever - and a timeout
+ * tool that never returns is worse than none.
+ *
+ * arm() starts an ITIMER_REAL for a bound the run can't legitimately
+ * outlive (RunConfig::lifetime_bound plus SLACK). if it fires, the handler
+ * says so on stderr and _exit()s with exit_codes::FAILSAFE, without
+ * touching the child: whatever wedged us may well have wedged that too.
+ *
+ * the timer is per process and isn't inherited across fork, so the
+ * command never sees it, and exec resets our handler for it. a stray
+ * SIGALRM from outside - or the timer firing early because it counted
+ * time asleep - is told apart by the clock: before the deadline the
+ * handler re-arms for the rest and returns.
+ *
+ * the binary arms it; the library never does, a SIGALRM handler isn't
+ * ours to take in someone else's process. what the handler says is made
+ * by arm() from the run's Logger, since the handler can't format a line
+ * or look anything up.
+ */
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use core::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+use core::time::Duration;
+
+use crate::error::exit_codes;
+use crate::log::{Logger, QUIET_ALL};
+
+/// Room past the computed bound: spawn, reaping, writing the result.
+pub const SLACK: Duration = Duration::from_secs(30);
+
+/* awake_ns() when the failsafe is due, 0 = disarmed */
+static DEADLINE_NS: AtomicU64 = AtomicU64::new(0);
+
+/* the line the handler writes, empty under -qq; null until the first arm */
+static MESSAGE: AtomicPtr<String> = AtomicPtr::new(core::ptr::null_mut());
+
+/* the bound is in awake time (--confine active, --on-sleep pause don't
+ * count sleep), so the clock mustn't count it either: on macOS that's
+ * CLOCK_UPTIME_RAW, on Linux CLOCK_MONOTONIC already stops in suspend */
+#[cfg(target_os = "macos")]
+const AWAKE_CLOCK: libc::clockid_t = libc::CLOCK_UPTIME_RAW;
+#[cfg(not(target_os = "macos"))]
+const AWAKE_CLOCK: libc::clockid_t = libc::CLOCK_MONOTONIC;
+
+/* awake time in ns; clock_gettime is async-signal-safe */
+fn awake_ns() -> u64 {
+    // SAFETY: zeroed timespec is valid for clock_gettime to fill in
+    let mut ts: libc::timespec = unsafe { core::mem::zeroed() };
+    // SAFETY: ts is a valid timespec that outlives the call
+    unsafe { libc::clock_gettime(AWAKE_CLOCK, &raw mut ts) };
+    #[allow(clippy::cast_sign_loss)]
+    (ts.tv_sec as u64)
+        .saturating_mul(1_000_000_000)
+        .saturating_add(ts.tv_nsec as u64)
+}
+
+/* libc only declares it for the BSDs; glibc has it all the same */
+unsafe extern "C" {
+    fn setitimer(
+        which: libc::c_int,
+        new_value: *const libc::itimerval,
+        old_value: *mut libc::itimerval,
+    ) -> libc::c_int;
+}
+
+/* one-shot ITIMER_REAL, ns from now (0 cancels). false if it failed */
+fn set_timer(ns: u64) -> bool {
+    let secs = ns / 1_000_000_000;
+    let timer = libc::itimerval {
+        it_interval: libc::timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        },
+        it_value: libc::timeval {
+            tv_sec: libc::time_t::try_from(secs).unwrap_or(libc::time_t::MAX),
+            /* round up so a set timer never reads as 0 = cancelled */
+            #[allow(clippy::cast_possible_truncation)]
+            tv_usec: (ns % 1_000_000_000).div_ceil(1000) as libc::suseconds_t,
+        },
+    };
+    // SAFETY: timer is a valid itimerval; the old value isn't wanted
+    unsafe { setitimer(libc::ITIMER_REAL, &raw const timer, core::ptr::null_mut()) == 0 }
+}
+
+extern "C" fn on_alarm(_sig: i32) {
+    let deadline = DEADLINE_NS.load(Ordering::SeqCst);
+    if deadline == 0 {
+        return;
+    }
+    let now = awake_ns();
+    if now < deadline {
+        /* not ours, or early: wait out the rest */
+        set_timer(deadline - now);
+        return;
+    }
+    let message = MESSAGE.load(Ordering::SeqCst);
+    if !message.is_null() {
+        // SAFETY: arm() stored it from Box::into_raw and never frees one it
+        // stored
+        let line = unsafe { &*message };
+        // SAFETY: write is async-signal-safe and the buffer outlives it
+        unsafe { libc::write(libc::STDERR_FILENO, line.as_ptr().cast(), line.len()) };
+    }
+    // SAFETY: _exit is async-signal-safe and doesn't run atexit handlers
+    unsafe { libc::_exit(i32::from(exit_codes::FAILSAFE)) };
+}
+
+/* the handler's line for log. one that says something else replaces it,
+ * and the old one is left: a handler on another thread may be writing it */
+fn set_message(log: Logger<'_>) {
+    let line = if log.quiet_level() < QUIET_ALL {
+        format!(
+            "{}: failsafe: still running past every deadline, giving up\n",
+            log.tag()
+        )
+    } else {
+        String::new()
+    };
+    let current = MESSAGE.load(Ordering::SeqCst);
+    // SAFETY: non-null, it came from Box::into_raw below and is never freed
+    if current.is_null() || unsafe { *current != line } {
+        MESSAGE.store(Box::into_raw(Box::new(line)), Ordering::SeqCst);
+    }
+}
+
+/// Exit with [`exit_codes::FAILSAFE`] if we're still around `after` from
+/// now, saying so through `log`. Replaces any earlier deadline. False if
+/// the handler or timer couldn't be set, in which case nothing is armed.
+pub fn arm(after: Duration, log: Logger<'_>) -> bool {
+    set_message(log);
+    let after_ns = after
+        .as_secs()
+        .saturating_mul(1_000_000_000)
+        .saturating_add(u64::from(after.subsec_nanos()))
+        .max(1);
+    DEADLINE_NS.store(awake_ns().saturating_add(after_ns), Ordering::SeqCst);
+
+    // SAFETY: sigaction struct is zeroed then filled in; on_alarm is an
+    // extern "C" fn(i32). sigemptyset/sigaction get pointers to a local
+    // that outlives the calls. Grouped since they build one change.
+    #[allow(clippy::multiple_unsafe_ops_per_block)]
+    let installed = unsafe {
+        let mut sa: libc::sigaction = core::mem::zeroed();
+        sa.sa_sigaction = on_alarm as *const () as usize;
+        sa.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&raw mut sa.sa_mask);
+        libc::sigaction(libc::SIGALRM, &sa, core::ptr::null_mut()) == 0
+    };
+    if !installed || !set_timer(after_ns) {
+        disarm();
+        return false;
+    }
+    true
+}
+
+/// Cancel the deadline set by [`arm`]. The handler stays, and ignores a
+/// SIGALRM that turns up after this.
+pub fn disarm() {
+    DEADLINE_NS.store(0, Ordering::SeqCst);
+    set_timer(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arm_and_disarm() {
+        assert!(arm(Duration::from_secs(60), Logger::default()));
+        assert!(DEADLINE_NS.load(Ordering::SeqCst) > awake_ns());
+        /* early and stray: re-armed, not fatal */
+        on_alarm(libc::SIGALRM);
+        disarm();
+        assert_eq!(DEADLINE_NS.load(Ordering::SeqCst), 0);
+        /* disarmed: a late SIGALRM is ignored */
+        on_alarm(libc::SIGALRM);
+    }
+}
@@ -0,0 +1,431 @@
+/*
+ * forward.rs
+ *
+ * signal forwarding dispatcher: one process-wide handler, one pipe per
+ * running monitor.
+ *
+ * signal dispositions are per-process, so the handler has to be shared. the
+ * old design also shared a single pipe, which is fine for the CLI (one child)
+ * but not for a library running several commands on several threads: the
+ * first monitor to read the byte would eat the signal, and cleanup from one
+ * caller would close the pipe under the others.
+ *
+ * now every monitor opens a Route - its own non-blocking pipe, with the write
+ * end published in a fixed slot table. the handler writes the signal number
+ * to every published slot, so a SIGTERM to us reaches every child we're
+ * watching. the table is a plain array of atomics because a handler can't
+ * take locks or allocate.
+ *
+ * closing a route is the delicate part: the handler might have loaded the fd
+ * just before we cleared the slot. handlers bump IN_HANDLER before touching
+ * the table, so after clearing the slot we wait for that to drain to zero -
+ * anyone who starts later sees the empty slot. only then is close() safe.
+ *
+ * the pipe is only a wakeup. each route also has a PENDING bitmask, set
+ * when the 1-byte write fails - a flood of signals can fill the pipe, and
+ * EAGAIN would otherwise drop the signal silently. the monitor loop checks
+ * the mask before every wait, since a full pipe it has already drained
+ * won't wake kqueue again. the mask loses order and repeats, which is fine:
+ * the first signal ends the run anyway.
+ *
+ * signals that arrive while no route is open (between setup and spawn, or
+ * between retries) are parked in UNROUTED and handed to the next route, so
+ * they aren't silently dropped.
+ *
+ * the stop signals (all but USR1/USR2) also set STOP, so the CLI can cut
+ * a wait before the command short, and so it can tell, once the result is
+ * out, that it was asked to stop with nobody to pass it on to: it writes
+ * the result whole first, then dies of the signal (main.rs).
+ *
+ * install/uninstall is refcounted: each SignalForwardGuard holds one
+ * reference, the handler goes back to SIG_DFL when the last one is dropped.
+ */
+
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, Ordering};
+
+use crate::errno::errno;
+use crate::signal::Signal;
+
+type RawFd = i32;
+
+/* signals we catch and forward - the "please stop" family plus USR1/USR2 */
+const FORWARDED: [i32; 6] = [
+    libc::SIGTERM,
+    libc::SIGINT,
+    libc::SIGHUP,
+    libc::SIGQUIT,
+    libc::SIGUSR1,
+    libc::SIGUSR2,
+];
+
+/* concurrent monitors that can receive forwarded signals. more than this
+ * still run, they just don't get signals forwarded */
+const MAX_ROUTES: usize = 32;
+
+/* ROUTES slot states besides a write fd */
+const FREE: i32 = -1;
+const CLOSING: i32 = -2;
+
+/* write ends of the per-monitor pipes */
+static ROUTES: [AtomicI32; MAX_ROUTES] = [const { AtomicI32::new(FREE) }; MAX_ROUTES];
+
+/* per-slot signals whose pipe write failed, 1 << signo */
+static PENDING: [AtomicU32; MAX_ROUTES] = [const { AtomicU32::new(0) }; MAX_ROUTES];
+
+/* handlers currently walking ROUTES */
+static IN_HANDLER: AtomicU32 = AtomicU32::new(0);
+
+/* bitmask of signals that arrived with no route open, 1 << signo */
+static UNROUTED: AtomicU32 = AtomicU32::new(0);
+
+/* the forwarded signals that ask us to stop, rather than pass a message */
+const STOP_MASK: u32 =
+    1 << libc::SIGTERM | 1 << libc::SIGINT | 1 << libc::SIGHUP | 1 << libc::SIGQUIT;
+
+/* set by the handler on any signal in STOP_MASK, routed or not */
+pub(crate) static STOP: AtomicBool = AtomicBool::new(false);
+
+/* live SignalForwardGuards; changed only under INSTALL_LOCK */
+static INSTALLED: AtomicU32 = AtomicU32::new(0);
+static INSTALL_LOCK: AtomicBool = AtomicBool::new(false);
+
+/* held while installing/uninstalling, so sigaction() calls from racing
+ * setup/cleanup can't interleave with the refcount change */
+struct InstallLock;
+
+impl InstallLock {
+    fn acquire() -> Self {
+        while INSTALL_LOCK
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        Self
+    }
+}
+
+impl Drop for InstallLock {
+    fn drop(&mut self) {
+        INSTALL_LOCK.store(false, Ordering::Release);
+    }
+}
+
+/* point every forwarded signal at `handler` (SIG_DFL or our fn).
+ * false if any sigaction() failed */
+fn set_handlers(handler: usize, flags: i32) -> bool {
+    // SAFETY: sigaction struct is zeroed then filled in; handler is SIG_DFL
+    // or an extern "C" fn(i32). sigemptyset/sigaction get pointers to a
+    // local that outlives the calls. Grouped since they build one change.
+    #[allow(clippy::multiple_unsafe_ops_per_block)]
+    unsafe {
+        let mut sa: libc::sigaction = core::mem::zeroed();
+        sa.sa_sigaction = handler;
+        sa.sa_flags = flags;
+        libc::sigemptyset(&raw mut sa.sa_mask);
+        /* no short-circuit: a reset to SIG_DFL must reach every signal */
+        let mut ok = true;
+        for sig in FORWARDED {
+            ok &= libc::sigaction(sig, &sa, core::ptr::null_mut()) == 0;
+        }
+        ok
+    }
+}
+
+/// Take a reference on the shared handler, installing it on the first one.
+/// `Err(errno)` (and no reference taken) if it couldn't be installed.
+pub(crate) fn install() -> Result<(), i32> {
+    let _lock = InstallLock::acquire();
+    if INSTALLED.load(Ordering::SeqCst) == 0
+        && !set_handlers(dispatch as *const () as usize, libc::SA_RESTART)
+    {
+        let err = errno();
+        /* don't leave some signals caught and others not */
+        set_handlers(libc::SIG_DFL, 0);
+        return Err(err);
+    }
+    INSTALLED.fetch_add(1, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Whether any guard currently holds the handler.
+pub(crate) fn installed() -> bool {
+    INSTALLED.load(Ordering::SeqCst) > 0
+}
+
+/// Drop a reference; the last one restores default dispositions.
+/// Extra calls are ignored.
+pub(crate) fn uninstall() {
+    let _lock = InstallLock::acquire();
+    match INSTALLED.load(Ordering::SeqCst) {
+        0 => {}
+        1 => {
+            set_handlers(libc::SIG_DFL, 0);
+            INSTALLED.store(0, Ordering::SeqCst);
+            UNROUTED.store(0, Ordering::SeqCst);
+            STOP.store(false, Ordering::SeqCst);
+        }
+        n => INSTALLED.store(n - 1, Ordering::SeqCst),
+    }
+}
+
+/* the handler: fan the signal out to every open route. only atomics and
+ * write(), both async-signal-safe */
+extern "C" fn dispatch(sig: i32) {
+    IN_HANDLER.fetch_add(1, Ordering::SeqCst);
+    #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+    let byte: u8 = sig as u8;
+    let bit = if (1..32).contains(&sig) { 1 << sig } else { 0 };
+    let mut delivered = false;
+    for (slot, pending) in ROUTES.iter().zip(&PENDING) {
+        let fd = slot.load(Ordering::SeqCst);
+        if fd >= 0 {
+            // SAFETY: fd is a route's write end; Route::drop waits for
+            // IN_HANDLER to drain before closing it, so it's still open.
+            // 1-byte buffer on the stack.
+            let written = unsafe { libc::write(fd, (&raw const byte).cast(), 1) };
+            if written != 1 {
+                /* pipe full - the monitor picks it up from the mask */
+                pending.fetch_or(bit, Ordering::SeqCst);
+            }
+            delivered = true;
+        }
+    }
+    if !delivered {
+        UNROUTED.fetch_or(bit, Ordering::SeqCst);
+    }
+    if bit & STOP_MASK != 0 {
+        STOP.store(true, Ordering::Release);
+    }
+    IN_HANDLER.fetch_sub(1, Ordering::SeqCst);
+}
+
+/// The stop signals that came in with no route open, taken so the next
+/// route doesn't get them; the lowest-numbered stands for them all.
+pub(crate) fn take_unrouted_stop() -> Option<Signal> {
+    let mask = UNROUTED.fetch_and(!STOP_MASK, Ordering::SeqCst) & STOP_MASK;
+    if mask == 0 {
+        return None;
+    }
+    #[allow(clippy::cast_possible_wrap)]
+    Signal::try_from_raw(mask.trailing_zeros() as i32)
+}
+
+/// Whether `sig` is one of the stop signals (SIGTERM, SIGINT, SIGHUP,
+/// SIGQUIT) rather than a message for the command (SIGUSR1/SIGUSR2).
+pub(crate) const fn is_stop(sig: Signal) -> bool {
+    let raw = sig.as_raw();
+    raw > 0 && raw < 32 && STOP_MASK & (1 << raw) != 0
+}
+
+/// Hand back a stop signal a route took but had no command for (the
+/// retry delay), so [`take_unrouted_stop`] finds it once the result is out.
+pub(crate) fn park_stop(sig: Signal) {
+    if is_stop(sig) {
+        UNROUTED.fetch_or(1 << sig.as_raw(), Ordering::SeqCst);
+        STOP.store(true, Ordering::Release);
+    }
+}
+
+/* non-blocking, close-on-exec pipe: (read, write) */
+fn open_pipe() -> Option<(RawFd, RawFd)> {
+    let mut fds = [0i32; 2];
+    // SAFETY: fds is a valid 2-element array, pipe() writes exactly 2 fds
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    for fd in fds {
+        // SAFETY: fd was just returned by pipe(); fcntl takes no pointers.
+        // Grouped since they share that invariant.
+        #[allow(clippy::multiple_unsafe_ops_per_block)]
+        let ok = unsafe {
+            /* non-blocking is required - the handler must never block */
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            /* CLOEXEC is best-effort - fd leak to child is harmless */
+            let _ = libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+            flags >= 0 && libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) >= 0
+        };
+        if !ok {
+            close_pair(fds[0], fds[1]);
+            return None;
+        }
+    }
+    Some((fds[0], fds[1]))
+}
+
+fn close_pair(read_fd: RawFd, write_fd: RawFd) {
+    // SAFETY: both fds came from pipe() and are closed exactly once here.
+    // Grouped since they share that invariant.
+    #[allow(clippy::multiple_unsafe_ops_per_block)]
+    unsafe {
+        libc::close(read_fd);
+        libc::close(write_fd);
+    }
+}
+
+/// One monitor's subscription to forwarded signals.
+///
+/// The read end becomes readable when a forwarded signal arrives. Unsubscribes
+/// and closes the pipe on drop.
+pub(crate) struct Route {
+    read_fd: RawFd,
+    write_fd: RawFd,
+    slot: usize,
+}
+
+impl Route {
+    /// Subscribe. `Err(errno)` if no pipe could be created, `Err(0)` if all
+    /// slots are taken.
+    pub(crate) fn open() -> Result<Self, i32> {
+        let Some((read_fd, write_fd)) = open_pipe() else {
+            return Err(errno());
+        };
+        let Some(slot) = ROUTES.iter().position(|s| {
+            s.compare_exchange(FREE, write_fd, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        }) else {
+            close_pair(read_fd, write_fd);
+            return Err(0);
+        };
+
+        /* claim anything that came in while nobody was listening. after the
+         * slot is published, so a signal lands either there or here */
+        PENDING[slot].fetch_or(UNROUTED.swap(0, Ordering::SeqCst), Ordering::SeqCst);
+
+        Ok(Self {
+            read_fd,
+            write_fd,
+            slot,
+        })
+    }
+
+    /// Fd to watch for readability; decode with [`Route::take_signal`].
+    pub(crate) const fn fd(&self) -> RawFd {
+        self.read_fd
+    }
+
+    /// Next forwarded signal: from the pipe, else from the pending mask.
+    pub(crate) fn take_signal(&self) -> Option<Signal> {
+        read_signal_from_pipe(self.read_fd).or_else(|| self.take_pending())
+    }
+
+    /// A signal that didn't fit in the pipe, lowest number first. Check
+    /// before blocking - nothing in the pipe will announce it.
+    pub(crate) fn take_pending(&self) -> Option<Signal> {
+        let pending = &PENDING[self.slot];
+        let mask = pending.load(Ordering::SeqCst);
+        if mask == 0 {
+            return None;
+        }
+        let sig = mask.trailing_zeros();
+        pending.fetch_and(!(1 << sig), Ordering::SeqCst);
+        #[allow(clippy::cast_possible_wrap)]
+        Signal::try_from_raw(sig as i32).or(Some(Signal::SIGTERM))
+    }
+}
+
+impl Drop for Route {
+    fn drop(&mut self) {
+        /* CLOSING, not FREE: a new route mustn't claim the slot (and its
+         * PENDING word) until we're done with both */
+        ROUTES[self.slot].store(CLOSING, Ordering::SeqCst);
+        /* a handler that loaded our fd before the store may still be
+         * writing to it - wait it out before the fd number can be reused */
+        while IN_HANDLER.load(Ordering::SeqCst) != 0 {
+            core::hint::spin_loop();
+        }
+        close_pair(self.read_fd, self.write_fd);
+        PENDING[self.slot].store(0, Ordering::SeqCst);
+        ROUTES[self.slot].store(FREE, Ordering::SeqCst);
+    }
+}
+
+/* decode one signal byte from a route's read end, None if it's empty */
+fn read_signal_from_pipe(fd: RawFd) -> Option<Signal> {
+    let mut buf = [0u8; 1];
+    // SAFETY: buf is a valid 1-byte buffer, fd is the read end of our pipe.
+    // read() will return -1 with EAGAIN if no data (non-blocking fd).
+    let n = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), 1) };
+    if n > 0 {
+        /* Decode the signal number written by dispatch */
+        Signal::try_from_raw(i32::from(buf[0])).or(Some(Signal::SIGTERM))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* one test: the tables are process-wide, parallel tests would see each
+     * other's routes. dispatch() is called directly rather than raising a
+     * real signal, so nothing else in the test binary is disturbed */
+    #[test]
+    fn test_routes() {
+        assert!(!installed());
+        assert_eq!(install(), Ok(()));
+        assert_eq!(install(), Ok(()));
+
+        /* a signal with nobody listening is parked for the next route */
+        dispatch(libc::SIGHUP);
+        let a = Route::open().expect("route a");
+        assert_eq!(a.take_signal(), Some(Signal::SIGHUP));
+        assert_eq!(a.take_signal(), None);
+
+        /* every open route gets every signal */
+        let b = Route::open().expect("route b");
+        dispatch(libc::SIGUSR1);
+        assert_eq!(a.take_signal(), Some(Signal::SIGUSR1));
+        assert_eq!(b.take_signal(), Some(Signal::SIGUSR1));
+
+        /* a closed route frees its slot and stops receiving */
+        let slot = b.slot;
+        drop(b);
+        assert_eq!(ROUTES[slot].load(Ordering::SeqCst), FREE);
+        dispatch(libc::SIGTERM);
+        assert_eq!(a.take_signal(), Some(Signal::SIGTERM));
+
+        /* fill a's pipe: further signals land in the pending mask */
+        let junk = [libc::SIGINT as u8; 4096];
+        // SAFETY: a's write end is non-blocking, junk outlives the calls
+        while unsafe { libc::write(a.write_fd, junk.as_ptr().cast(), junk.len()) } > 0 {}
+        dispatch(libc::SIGUSR2);
+        assert_eq!(a.take_pending(), Some(Signal::SIGUSR2));
+        assert_eq!(a.take_pending(), None);
+        drop(a);
+
+        /* with no route, a stop signal is there to be taken once, and
+         * other signals are left for the next route */
+        assert!(STOP.load(Ordering::SeqCst));
+        dispatch(libc::SIGUSR1);
+        dispatch(libc::SIGINT);
+        dispatch(libc::SIGTERM);
+        assert_eq!(take_unrouted_stop(), Some(Signal::SIGINT));
+        assert_eq!(take_unrouted_stop(), None);
+
+        /* a stop signal handed back comes out the same way; USR1 doesn't */
+        park_stop(Signal::SIGUSR1);
+        park_stop(Signal::SIGHUP);
+        assert_eq!(take_unrouted_stop(), Some(Signal::SIGHUP));
+        assert_eq!(take_unrouted_stop(), None);
+        let c = Route::open().expect("route c");
+        assert_eq!(c.take_signal(), Some(Signal::SIGUSR1));
+        assert_eq!(c.take_signal(), None);
+        drop(c);
+
+        /* refcounted: still installed after one uninstall, gone after two,
+         * and extra uninstalls are harmless */
+        uninstall();
+        assert!(installed());
+        uninstall();
+        uninstall();
+        assert!(!installed());
+    }
+
+    #[test]
+    fn test_forwarded_signals_fit_mask() {
+        /* UNROUTED and PENDING are u32 bitmasks indexed by signal number */
+        assert!(FORWARDED.iter().all(|&s| (1..32).contains(&s)));
+    }
+}
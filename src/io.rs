@@ -4,36 +4,119 @@
  * no_std I/O primitives.
  * direct writes to stdout/stderr via libc::write.
  *
- * no buffering - each write is a syscall. that's fine for CLI output
- * which is typically a single line at a time.
+ * every write goes through write_all/write_all_vectored: short writes
+ * (pipes, ttys) are resumed and EINTR retried, so a line is never cut off
+ * halfway by a signal landing mid-write.
+ *
+ * the print macros format into a BufWriter, a small stack buffer that
+ * flushes when full and once at the end of the macro. a typical line is one
+ * write(2) instead of one per format fragment, which keeps lines from
+ * several writers sharing a pipe from interleaving. lines longer than the
+ * buffer go out in buffer-sized pieces, nothing is allocated.
+ *
+ * StackBuf is for output that has to be assembled whole before it's
+ * written (the JSON result): a stack array that only moves to the heap if
+ * the output outgrows it.
+ *
+ * files written whole (--result-file and friends) go through safe_file.rs.
+ * prepare_output_fd is for --json-fd: any descriptor the caller handed us.
  */
 
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::fmt::{self, Write};
 
+use crate::errno::errno;
+
 /* file descriptors */
 const STDOUT: i32 = 1;
 const STDERR: i32 = 2;
 
+/* one line of heartbeat/verbose/JSON-ish output, with room to spare */
+const BUF_SIZE: usize = 512;
+
+/* iovecs per writev call; callers pass a handful of pieces */
+const MAX_IOV: usize = 8;
+
 unsafe extern "C" {
     fn write(fd: i32, buf: *const u8, count: usize) -> isize;
+    fn writev(fd: i32, iov: *const libc::iovec, iovcnt: i32) -> isize;
+}
+
+/// Write all of `data` to `fd`, retrying short writes and EINTR. Returns
+/// errno on failure.
+pub fn write_all(fd: i32, mut data: &[u8]) -> Result<(), i32> {
+    while !data.is_empty() {
+        // SAFETY: data is a valid byte slice, fd is the caller's descriptor
+        let n = unsafe { write(fd, data.as_ptr(), data.len()) };
+        if n < 0 {
+            let err = errno();
+            if err == libc::EINTR {
+                continue;
+            }
+            return Err(err);
+        }
+        data = &data[n.unsigned_abs()..];
+    }
+    Ok(())
+}
+
+/// Write all of `parts`, in order, with as few writev(2) calls as the
+/// kernel allows. Same guarantees as [`write_all`]. At most 8 parts.
+pub fn write_all_vectored(fd: i32, parts: &[&[u8]]) -> Result<(), i32> {
+    debug_assert!(parts.len() <= MAX_IOV);
+    let mut iov = [libc::iovec {
+        iov_base: core::ptr::null_mut(),
+        iov_len: 0,
+    }; MAX_IOV];
+    let mut count = 0;
+    for part in parts.iter().filter(|p| !p.is_empty()).take(MAX_IOV) {
+        iov[count] = libc::iovec {
+            iov_base: part.as_ptr().cast_mut().cast(),
+            iov_len: part.len(),
+        };
+        count += 1;
+    }
+
+    let mut first = 0;
+    while first < count {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        // SAFETY: iov[first..count] describe live slices borrowed from parts;
+        // writev only reads through them
+        let n = unsafe { writev(fd, iov[first..].as_ptr(), (count - first) as i32) };
+        if n < 0 {
+            let err = errno();
+            if err == libc::EINTR {
+                continue;
+            }
+            return Err(err);
+        }
+        /* skip what went out: whole iovecs, then the front of a partial one */
+        let mut done = n.unsigned_abs();
+        while first < count && done >= iov[first].iov_len {
+            done -= iov[first].iov_len;
+            first += 1;
+        }
+        if done > 0 {
+            let cur = &mut iov[first];
+            // SAFETY: done < iov_len, so the offset stays inside the slice
+            cur.iov_base = unsafe { cur.iov_base.cast::<u8>().add(done) }.cast();
+            cur.iov_len -= done;
+        }
+    }
+    Ok(())
 }
 
 /// Write bytes to stdout
 #[inline]
 pub fn write_stdout(s: &[u8]) {
-    // SAFETY: s is a valid byte slice, STDOUT is always valid
-    unsafe {
-        write(STDOUT, s.as_ptr(), s.len());
-    }
+    let _ = write_all(STDOUT, s);
 }
 
 /// Write bytes to stderr
 #[inline]
 pub fn write_stderr(s: &[u8]) {
-    // SAFETY: s is a valid byte slice, STDERR is always valid
-    unsafe {
-        write(STDERR, s.as_ptr(), s.len());
-    }
+    let _ = write_all(STDERR, s);
 }
 
 /// Write a string to stdout
@@ -48,6 +131,132 @@ pub fn eprint_str(s: &str) {
     write_stderr(s.as_bytes());
 }
 
+/// Stack buffer in front of write(2). Flushes when full, on [`flush`], and
+/// on drop. Implements `core::fmt::Write`, so `write!` into it assembles a
+/// line without allocating.
+///
+/// [`flush`]: BufWriter::flush
+pub struct BufWriter {
+    fd: i32,
+    len: usize,
+    buf: [u8; BUF_SIZE],
+}
+
+impl BufWriter {
+    #[inline]
+    pub const fn new(fd: i32) -> Self {
+        Self {
+            fd,
+            len: 0,
+            buf: [0; BUF_SIZE],
+        }
+    }
+
+    #[inline]
+    pub const fn stdout() -> Self {
+        Self::new(STDOUT)
+    }
+
+    #[inline]
+    pub const fn stderr() -> Self {
+        Self::new(STDERR)
+    }
+
+    /// Queue bytes. Anything that doesn't fit goes out right away, along
+    /// with what was already buffered, in one writev.
+    pub fn write_bytes(&mut self, data: &[u8]) -> Result<(), i32> {
+        if data.len() <= BUF_SIZE - self.len {
+            self.buf[self.len..self.len + data.len()].copy_from_slice(data);
+            self.len += data.len();
+            return Ok(());
+        }
+        let pending = self.len;
+        self.len = 0;
+        write_all_vectored(self.fd, &[&self.buf[..pending], data])
+    }
+
+    /// Write out whatever is buffered.
+    pub fn flush(&mut self) -> Result<(), i32> {
+        let pending = self.len;
+        self.len = 0;
+        write_all(self.fd, &self.buf[..pending])
+    }
+}
+
+impl Write for BufWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+impl Drop for BufWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// Stack buffer that spills to the heap once `N` bytes aren't enough.
+/// Implements `core::fmt::Write`, never fails.
+pub struct StackBuf<const N: usize> {
+    len: usize,
+    buf: [u8; N],
+    /* everything written so far, once it stopped fitting in buf */
+    heap: Vec<u8>,
+}
+
+impl<const N: usize> StackBuf<N> {
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            len: 0,
+            buf: [0; N],
+            heap: Vec::new(),
+        }
+    }
+
+    /// Everything written so far.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        if self.spilled() {
+            &self.heap
+        } else {
+            &self.buf[..self.len]
+        }
+    }
+
+    /// Whether the contents outgrew the stack buffer.
+    #[inline]
+    pub fn spilled(&self) -> bool {
+        !self.heap.is_empty()
+    }
+
+    pub fn write_bytes(&mut self, data: &[u8]) {
+        if self.spilled() {
+            self.heap.extend_from_slice(data);
+        } else if data.len() <= N - self.len {
+            self.buf[self.len..self.len + data.len()].copy_from_slice(data);
+            self.len += data.len();
+        } else {
+            self.heap.reserve(2 * N + data.len());
+            self.heap.extend_from_slice(&self.buf[..self.len]);
+            self.heap.extend_from_slice(data);
+        }
+    }
+}
+
+impl<const N: usize> Default for StackBuf<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Write for StackBuf<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
 /// A writer that outputs to stderr via direct syscall.
 /// Implements core::fmt::Write for use with write!/writeln! macros.
 pub struct StderrWriter;
@@ -69,12 +278,225 @@ impl Write for StdoutWriter {
     }
 }
 
+/* path bytes + NUL, or EINVAL if the path has a NUL inside */
+pub(crate) fn c_path(parts: &[&[u8]]) -> Result<Vec<u8>, i32> {
+    let mut buf = Vec::with_capacity(parts.iter().map(|p| p.len()).sum::<usize>() + 1);
+    for part in parts {
+        if part.contains(&0) {
+            return Err(libc::EINVAL);
+        }
+        buf.extend_from_slice(part);
+    }
+    buf.push(0);
+    Ok(buf)
+}
+
+/// Check that `fd` is open for writing, and keep it from leaking into the
+/// command (stdio excepted - the command is meant to share those).
+/// Returns errno on failure.
+pub fn prepare_output_fd(fd: i32) -> Result<(), i32> {
+    // SAFETY: F_GETFL takes no pointer argument; a bad fd gives EBADF
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 {
+        return Err(errno());
+    }
+    if flags & libc::O_ACCMODE == libc::O_RDONLY {
+        return Err(libc::EBADF);
+    }
+    /* a CI reader waiting for EOF on this fd shouldn't wait for the
+     * command's grandchildren too */
+    // SAFETY: fd is open (checked above), F_SETFD takes an int flag
+    if fd > 2 && unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+        return Err(errno());
+    }
+    Ok(())
+}
+
+/// Remove `path`. Returns errno on failure.
+pub fn remove_file(path: &str) -> Result<(), i32> {
+    let path = c_path(&[path.as_bytes()])?;
+    // SAFETY: path is NUL-terminated
+    if unsafe { libc::unlink(path.as_ptr().cast()) } == 0 {
+        Ok(())
+    } else {
+        Err(errno())
+    }
+}
+
+/// Create a fresh directory, mode 0700, from `template` - a path ending in
+/// `XXXXXX`, which mkdtemp() replaces. Returns the path, or errno.
+pub fn make_temp_dir(template: &str) -> Result<String, i32> {
+    let mut path = c_path(&[template.as_bytes()])?;
+    // SAFETY: path is NUL-terminated and mkdtemp only rewrites the X's in place
+    if unsafe { libc::mkdtemp(path.as_mut_ptr().cast()) }.is_null() {
+        return Err(errno());
+    }
+    path.pop();
+    /* the X's became [A-Za-z0-9], the rest is the UTF-8 we passed in */
+    String::from_utf8(path).map_err(|_| libc::EINVAL)
+}
+
+/* past this many levels remove_tree() gives up on a branch, rather than
+ * hold a descriptor per level */
+const MAX_TREE_DEPTH: u32 = 64;
+
+/// Remove the directory at `path` and everything in it, like `rm -rf`.
+/// Symlinks are removed, never followed. Returns the last errno seen if
+/// anything was left behind.
+pub fn remove_tree(path: &str) -> Result<(), i32> {
+    let path = c_path(&[path.as_bytes()])?;
+    // SAFETY: path is NUL-terminated
+    let fd = unsafe {
+        libc::open(
+            path.as_ptr().cast(),
+            libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(errno());
+    }
+    let cleared = clear_dir(fd, 0);
+    // SAFETY: path is NUL-terminated
+    if unsafe { libc::rmdir(path.as_ptr().cast()) } != 0 {
+        return Err(cleared.err().unwrap_or_else(errno));
+    }
+    Ok(())
+}
+
+/* empty the directory open at fd, which this takes over. entries are
+ * unlinked as readdir() hands them out; a directory can skip entries when
+ * changed mid-read (APFS does), so it's read again until a pass finds
+ * nothing left to remove */
+fn clear_dir(fd: i32, depth: u32) -> Result<(), i32> {
+    // SAFETY: fd is an open directory; the DIR takes ownership of it
+    let dir = unsafe { libc::fdopendir(fd) };
+    if dir.is_null() {
+        let err = errno();
+        // SAFETY: fdopendir failed, so fd is still ours to close
+        unsafe { libc::close(fd) };
+        return Err(err);
+    }
+    let mut result = Ok(());
+    loop {
+        let mut removed = 0u32;
+        loop {
+            // SAFETY: dir is open until closedir below
+            let entry = unsafe { libc::readdir(dir) };
+            if entry.is_null() {
+                break;
+            }
+            // SAFETY: readdir returned a valid dirent; d_name is NUL-terminated
+            // and stays valid until the next readdir on this handle
+            #[allow(clippy::multiple_unsafe_ops_per_block)]
+            let name = unsafe { core::ffi::CStr::from_ptr((*entry).d_name.as_ptr()) };
+            if matches!(name.to_bytes(), b"." | b"..") {
+                continue;
+            }
+            match remove_entry(fd, name, depth) {
+                Ok(()) => removed += 1,
+                Err(err) => result = Err(err),
+            }
+        }
+        if removed == 0 {
+            break;
+        }
+        // SAFETY: dir is open
+        unsafe { libc::rewinddir(dir) };
+    }
+    // SAFETY: dir is open and closed exactly once here, which closes fd
+    unsafe { libc::closedir(dir) };
+    result
+}
+
+/* unlink one entry of the directory at fd, emptying it first if it's a
+ * directory itself */
+fn remove_entry(fd: i32, name: &core::ffi::CStr, depth: u32) -> Result<(), i32> {
+    // SAFETY: fd is an open directory, name is NUL-terminated
+    if unsafe { libc::unlinkat(fd, name.as_ptr(), 0) } == 0 {
+        return Ok(());
+    }
+    /* a directory: EPERM on macOS, EISDIR on linux */
+    let err = errno();
+    if err != libc::EPERM && err != libc::EISDIR {
+        return Err(err);
+    }
+    if depth >= MAX_TREE_DEPTH {
+        return Err(libc::ENAMETOOLONG);
+    }
+    // SAFETY: fd is an open directory, name is NUL-terminated
+    let sub = unsafe {
+        libc::openat(
+            fd,
+            name.as_ptr(),
+            libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+        )
+    };
+    if sub < 0 {
+        return Err(err);
+    }
+    let cleared = clear_dir(sub, depth + 1);
+    // SAFETY: fd is an open directory, name is NUL-terminated
+    if unsafe { libc::unlinkat(fd, name.as_ptr(), libc::AT_REMOVEDIR) } != 0 {
+        return Err(cleared.err().unwrap_or_else(errno));
+    }
+    Ok(())
+}
+
+/// Contents of the file at `path`, if it's no longer than `limit` bytes.
+/// Returns errno on failure, EFBIG for a longer file.
+pub fn read_file(path: &[u8], limit: usize) -> Result<Vec<u8>, i32> {
+    let path = c_path(&[path])?;
+    // SAFETY: path is NUL-terminated
+    let fd = unsafe { libc::open(path.as_ptr().cast(), libc::O_RDONLY | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(errno());
+    }
+
+    /* read one byte past the limit to tell "exactly limit" from "more" */
+    let mut buf = Vec::new();
+    let result = loop {
+        if buf.len() > limit {
+            break Err(libc::EFBIG);
+        }
+        buf.reserve(4096.min(limit + 1 - buf.len()));
+        let spare = buf.spare_capacity_mut();
+        // SAFETY: fd is open; spare is valid for spare.len() bytes
+        let n = unsafe { libc::read(fd, spare.as_mut_ptr().cast(), spare.len()) };
+        match usize::try_from(n) {
+            Ok(0) => break Ok(()),
+            // SAFETY: read initialized the first n spare bytes
+            Ok(n) => unsafe { buf.set_len(buf.len() + n) },
+            Err(_) if errno() == libc::EINTR => {}
+            Err(_) => break Err(errno()),
+        }
+    };
+    // SAFETY: fd was opened above and is closed exactly once here
+    unsafe { libc::close(fd) };
+    result.map(|()| buf)
+}
+
+/* decimal digits of n into buf, no allocation */
+pub(crate) fn format_u32(mut n: u32, buf: &mut [u8; 10]) -> &[u8] {
+    let mut i = buf.len();
+    loop {
+        i -= 1;
+        buf[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    &buf[i..]
+}
+
 /// Print to stderr (no newline)
 #[macro_export]
 macro_rules! eprint {
     ($($arg:tt)*) => {{
         use core::fmt::Write;
-        let _ = write!($crate::io::StderrWriter, $($arg)*);
+        let mut w = $crate::io::BufWriter::stderr();
+        let _ = write!(w, $($arg)*);
+        let _ = w.flush();
     }};
 }
 
@@ -86,8 +508,10 @@ macro_rules! eprintln {
     }};
     ($($arg:tt)*) => {{
         use core::fmt::Write;
-        let _ = write!($crate::io::StderrWriter, $($arg)*);
-        $crate::io::write_stderr(b"\n");
+        let mut w = $crate::io::BufWriter::stderr();
+        let _ = write!(w, $($arg)*);
+        let _ = w.write_bytes(b"\n");
+        let _ = w.flush();
     }};
 }
 
@@ -96,7 +520,9 @@ macro_rules! eprintln {
 macro_rules! print {
     ($($arg:tt)*) => {{
         use core::fmt::Write;
-        let _ = write!($crate::io::StdoutWriter, $($arg)*);
+        let mut w = $crate::io::BufWriter::stdout();
+        let _ = write!(w, $($arg)*);
+        let _ = w.flush();
     }};
 }
 
@@ -108,8 +534,10 @@ macro_rules! println {
     }};
     ($($arg:tt)*) => {{
         use core::fmt::Write;
-        let _ = write!($crate::io::StdoutWriter, $($arg)*);
-        $crate::io::write_stdout(b"\n");
+        let mut w = $crate::io::BufWriter::stdout();
+        let _ = write!(w, $($arg)*);
+        let _ = w.write_bytes(b"\n");
+        let _ = w.flush();
     }};
 }
 
@@ -143,6 +571,110 @@ mod tests {
         write_stdout(b"test stdout write\n");
     }
 
+    #[test]
+    fn test_remove_tree() {
+        let template = std::format!("{}/procguard-tree.XXXXXX", std::env::temp_dir().display());
+        let dir = make_temp_dir(&template).unwrap();
+        assert!(!dir.ends_with("XXXXXX"));
+        let outside = std::format!("{dir}.outside");
+        std::fs::write(&outside, b"keep").unwrap();
+
+        std::fs::create_dir_all(std::format!("{dir}/a/b/c")).unwrap();
+        for i in 0..50 {
+            std::fs::write(std::format!("{dir}/a/f{i}"), b"x").unwrap();
+        }
+        std::fs::write(std::format!("{dir}/a/b/c/deep"), b"x").unwrap();
+        std::os::unix::fs::symlink(&outside, std::format!("{dir}/a/link")).unwrap();
+        /* a symlink to a directory: removed, not followed */
+        std::os::unix::fs::symlink(std::env::temp_dir(), std::format!("{dir}/tmp")).unwrap();
+
+        remove_tree(&dir).unwrap();
+        assert!(!std::path::Path::new(&dir).exists());
+        assert_eq!(std::fs::read(&outside).unwrap(), b"keep");
+        let _ = std::fs::remove_file(&outside);
+
+        assert_eq!(remove_tree(&dir), Err(libc::ENOENT));
+        assert_eq!(
+            make_temp_dir("/nonexistent-dir/x.XXXXXX"),
+            Err(libc::ENOENT)
+        );
+    }
+
+    /* pipe with a reader thread; returns everything written before close */
+    fn through_pipe(f: impl FnOnce(i32)) -> std::vec::Vec<u8> {
+        use std::io::Read;
+        use std::os::fd::FromRawFd;
+
+        let mut fds = [0i32; 2];
+        // SAFETY: fds is a valid 2-element array for pipe() to fill
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let reader = std::thread::spawn(move || {
+            // SAFETY: fds[0] is the read end we own from here on
+            let mut file = unsafe { std::fs::File::from_raw_fd(fds[0]) };
+            let mut out = std::vec::Vec::new();
+            file.read_to_end(&mut out).unwrap();
+            out
+        });
+        f(fds[1]);
+        // SAFETY: write end is ours, closed once so the reader sees EOF
+        unsafe { libc::close(fds[1]) };
+        reader.join().unwrap()
+    }
+
+    #[test]
+    fn test_stack_buf_spills() {
+        let mut b = StackBuf::<8>::new();
+        b.write_bytes(b"abcd");
+        b.write_bytes(b"efgh");
+        assert!(!b.spilled());
+        assert_eq!(b.as_bytes(), b"abcdefgh");
+        b.write_bytes(b"i");
+        assert!(b.spilled());
+        b.write_bytes(b"jk");
+        assert_eq!(b.as_bytes(), b"abcdefghijk");
+    }
+
+    #[test]
+    fn test_write_all_vectored_large() {
+        /* bigger than a pipe buffer: forces partial writev and resumption
+         * in the middle of an iovec */
+        let big = std::vec![b'x'; 200_000];
+        let out = through_pipe(|fd| {
+            write_all_vectored(fd, &[b"head:", &big, b"", b":tail"]).unwrap();
+        });
+        assert_eq!(out.len(), 5 + big.len() + 5);
+        assert!(out.starts_with(b"head:x"));
+        assert!(out.ends_with(b"x:tail"));
+    }
+
+    #[test]
+    fn test_buf_writer_overflow_keeps_order() {
+        use core::fmt::Write;
+        let long = "y".repeat(BUF_SIZE * 3);
+        let out = through_pipe(|fd| {
+            let mut w = BufWriter::new(fd);
+            let _ = write!(w, "a-{}", 1);
+            /* larger than the buffer: goes out with what's pending */
+            let _ = w.write_str(&long);
+            let _ = write!(w, "end");
+            /* drop flushes */
+        });
+        assert_eq!(out, std::format!("a-1{}end", long).as_bytes());
+    }
+
+    #[test]
+    fn test_prepare_output_fd() {
+        assert_eq!(prepare_output_fd(STDERR), Ok(()));
+        assert_eq!(prepare_output_fd(9999), Err(libc::EBADF));
+    }
+
+    #[test]
+    fn test_format_u32() {
+        let mut buf = [0u8; 10];
+        assert_eq!(format_u32(0, &mut buf), b"0");
+        assert_eq!(format_u32(4_294_967_295, &mut buf), b"4294967295");
+    }
+
     #[test]
     fn test_writer_fmt() {
         use core::fmt::Write;
@@ -0,0 +1,504 @@
+/*
+ * json.rs
+ *
+ * tiny JSON writer for --json output. compact, single line, no floats.
+ *
+ * the writer tracks commas and nesting itself, so callers just say
+ * "field, field, nested object, field" and can't produce `{,"a":1}` or
+ * forget to escape a string. nesting state is a bitmask, one bit per level
+ * (64 levels, far more than our schema uses) - no allocation beyond the
+ * output String.
+ *
+ * misuse (a value in an object without a key, unbalanced end_*) is a bug in
+ * our code, not bad input: debug_assert catches it in tests, release builds
+ * emit whatever was asked for.
+ *
+ * output goes to a String by default, or any fmt::Write via JsonWriter::on -
+ * the binary builds its result in an io::StackBuf so the common case never
+ * touches the heap. write errors from the sink are ignored; a sink that can
+ * fail has to say so itself.
+ */
+
+use alloc::string::String;
+use core::fmt::{self, Write};
+
+/// `schema_version` of the `--json` result (see docs/json-output.md).
+/// 18: the --proc-tree `process_tree` object.
+/// 19: the `timestamps_disabled` warning (--timestamps).
+/// 20: `slept_ms`, the `system_sleep` timeout reason and timeline event
+/// (--on-sleep).
+/// 21: `clock` may be `hybrid` (--confine hybrid).
+/// 22: the `*_exceeds_timeout` interval warnings.
+/// 23: the `exited_on_input` child state, the `stdin_injected` event.
+/// 24: `escaped_processes`, the `processes_escaped` warning.
+/// 25: `lock_wait_ms` (`--flock`).
+/// 26: `lock_queue` (`--flock-queue`).
+/// 27: `window_wait_ms` and the `window_closed` timeout reason (`--only-between`).
+/// 28: the `repeat` status and its `repeat` object (`--every`).
+/// 29: the `aggregate` object (`--runs`, `--every`, `--retry`), `max_runs`.
+/// 30: `classification` and the `passed` stop reason (`--detect-flaky`).
+/// 31: `hook_limit_ms`, plus `hook_budget` and `stop_bound_ms` on timeouts
+/// (`--hook-budget`).
+/// 32: `hook_async` on timeouts with a hook (`--hook-async`).
+/// 33: the `cpu_limit` status; `cpu_time_hard_ms`, `sigxcpu_sent` in
+/// `limits` (`--cpu-time SOFT:HARD`).
+/// 34: `external_kill`, `external_signal`, `external_signal_num`.
+/// 35: `exit_reason_namespace`, `exit_reason_code`.
+/// 36: `"aborted"` in `attempt_results` (a stop signal between retries).
+/// 37: `heartbeats` (ticks, missed), `missed` on `heartbeat` events.
+/// 38: `at_timeout` (cpu_time_ms, memory_bytes, threads, state) on timeouts.
+/// 39: the `wait_pid` status (`--wait-pid`).
+/// 40: the `ambiguous_duration` warning.
+/// 41: `extended_ms` and the `timeout_extended` event (`--extend-env-file`).
+/// 42: `error_kind`, `command_path`, `searched`, `built_for` on errors starting
+/// the command.
+/// 43: `arch` on results and `wrong_architecture` errors (`--arch`).
+/// 44: `translated` and the `translated_timeout` warning.
+/// 45: `cpu_throttle_window_ms`, `cpu_throttle_gain` in `limits`.
+/// 46: `cpu_throttle_quantum_ms` in `limits`; `cpu_sleep_ms` follows it.
+/// 47: `throttle`.
+/// 48: `cpu_throttle_notify` in `limits`.
+/// 49: `run_id` in every object, `attempt` in `attempt_results` and
+/// `started` lines.
+/// 50: `error_kind` `text_file_busy`, `argument_list_too_long` and
+/// `out_of_memory` for a spawn that failed with that errno.
+/// 51: `spawn_retries`, and the `spawn_retried` timeline event.
+/// 52: the `stopped_at_signal` warning and `found_stopped` timeline event.
+/// 53: `child_traced`, its warning and the `debugger_wait` timeline event.
+/// 54: `orphans` (`--reap-orphans`).
+/// 55: `also_kill` (`--also-kill`).
+/// 56: `at_timeout` and the hook fields on memory limits too (`--on-limit`).
+/// 57: `child_stopped_ms`, the `child_stopped` timeout reason and timeline events (`--on-child-stop`).
+/// 58: `orphans.adopted` renamed `orphans.swept`.
+pub const SCHEMA_VERSION: u64 = 58;
+
+/// Streaming JSON builder.
+///
+/// ```
+/// use procguard::json::JsonWriter;
+///
+/// let mut w = JsonWriter::new();
+/// w.begin_object();
+/// w.field_str("status", "ok");
+/// w.key("codes").begin_array();
+/// w.u64(1).u64(2);
+/// w.end_array();
+/// w.end_object();
+/// assert_eq!(w.finish(), r#"{"status":"ok","codes":[1,2]}"#);
+/// ```
+pub struct JsonWriter<W = String> {
+    out: W,
+    /* bit n set: container at depth n has at least one item */
+    nonempty: u64,
+    /* bit n set: container at depth n is an object */
+    objects: u64,
+    depth: u32,
+    after_key: bool,
+}
+
+impl Default for JsonWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JsonWriter {
+    /// Write into a new `String`.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self::on(String::new())
+    }
+
+    /// Start with a preallocated buffer.
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::on(String::with_capacity(capacity))
+    }
+}
+
+impl<W: Write> JsonWriter<W> {
+    /// Write into `out` instead of a String.
+    #[must_use]
+    pub const fn on(out: W) -> Self {
+        Self {
+            out,
+            nonempty: 0,
+            objects: 0,
+            depth: 0,
+            after_key: false,
+        }
+    }
+
+    /// The JSON written so far.
+    #[must_use]
+    pub fn finish(self) -> W {
+        debug_assert!(self.depth == 0, "unclosed JSON container");
+        self.out
+    }
+
+    #[inline]
+    fn put(&mut self, s: &str) {
+        let _ = self.out.write_str(s);
+    }
+
+    #[inline]
+    fn put_char(&mut self, c: char) {
+        let _ = self.out.write_char(c);
+    }
+
+    #[inline]
+    fn bit(&self) -> u64 {
+        1u64 << (self.depth - 1)
+    }
+
+    #[inline]
+    fn in_object(&self) -> bool {
+        self.depth > 0 && self.objects & self.bit() != 0
+    }
+
+    /* comma before a new item in the current container */
+    fn separate(&mut self) {
+        if self.depth == 0 {
+            return;
+        }
+        let bit = self.bit();
+        if self.nonempty & bit != 0 {
+            self.put_char(',');
+        }
+        self.nonempty |= bit;
+    }
+
+    /* before any value: after a key nothing to do, else it's an array item */
+    fn value_start(&mut self) {
+        if self.after_key {
+            self.after_key = false;
+        } else {
+            debug_assert!(!self.in_object(), "JSON object value without a key");
+            self.separate();
+        }
+    }
+
+    fn open(&mut self, bracket: char, object: bool) -> &mut Self {
+        self.value_start();
+        debug_assert!(self.depth < 64, "JSON nested too deep");
+        self.put_char(bracket);
+        self.depth += 1;
+        let bit = self.bit();
+        self.nonempty &= !bit;
+        if object {
+            self.objects |= bit;
+        } else {
+            self.objects &= !bit;
+        }
+        self
+    }
+
+    fn close(&mut self, bracket: char, object: bool) -> &mut Self {
+        debug_assert!(
+            self.depth > 0 && self.in_object() == object,
+            "unbalanced JSON"
+        );
+        debug_assert!(!self.after_key, "JSON key without a value");
+        self.put_char(bracket);
+        self.depth = self.depth.saturating_sub(1);
+        self
+    }
+
+    /// `{`
+    pub fn begin_object(&mut self) -> &mut Self {
+        self.open('{', true)
+    }
+
+    /// `}`
+    pub fn end_object(&mut self) -> &mut Self {
+        self.close('}', true)
+    }
+
+    /// `[`
+    pub fn begin_array(&mut self) -> &mut Self {
+        self.open('[', false)
+    }
+
+    /// `]`
+    pub fn end_array(&mut self) -> &mut Self {
+        self.close(']', false)
+    }
+
+    /// Object key. The next call writes its value.
+    pub fn key(&mut self, key: &str) -> &mut Self {
+        debug_assert!(self.in_object(), "JSON key outside an object");
+        debug_assert!(!self.after_key, "JSON key without a value");
+        self.separate();
+        write_escaped(&mut self.out, key);
+        self.put_char(':');
+        self.after_key = true;
+        self
+    }
+
+    /// String value, escaped.
+    pub fn str(&mut self, v: &str) -> &mut Self {
+        self.value_start();
+        write_escaped(&mut self.out, v);
+        self
+    }
+
+    /// String value produced by a `Display` impl, escaped like [`str`].
+    ///
+    /// [`str`]: JsonWriter::str
+    pub fn display(&mut self, v: impl fmt::Display) -> &mut Self {
+        self.value_start();
+        self.put_char('"');
+        let _ = write!(Escaper(&mut self.out), "{}", v);
+        self.put_char('"');
+        self
+    }
+
+    /// Number value.
+    pub fn u64(&mut self, v: u64) -> &mut Self {
+        self.value_start();
+        let _ = write!(self.out, "{}", v);
+        self
+    }
+
+    /// Number value.
+    pub fn i64(&mut self, v: i64) -> &mut Self {
+        self.value_start();
+        let _ = write!(self.out, "{}", v);
+        self
+    }
+
+    /// A tenths value as a decimal number: 955 is written `95.5`. For
+    /// percentages without floating point.
+    pub fn tenths(&mut self, v: u64) -> &mut Self {
+        self.value_start();
+        let _ = write!(self.out, "{}.{}", v / 10, v % 10);
+        self
+    }
+
+    /// `true` or `false`.
+    pub fn bool(&mut self, v: bool) -> &mut Self {
+        self.value_start();
+        self.put(if v { "true" } else { "false" });
+        self
+    }
+
+    /// `null`
+    pub fn null(&mut self) -> &mut Self {
+        self.value_start();
+        self.put("null");
+        self
+    }
+
+    /// Bytes as a string. Invalid UTF-8 is written as `\ufffd`, one per
+    /// bad sequence, like `String::from_utf8_lossy` but without the copy.
+    pub fn bytes_lossy(&mut self, v: &[u8]) -> &mut Self {
+        self.value_start();
+        self.put_char('"');
+        for chunk in v.utf8_chunks() {
+            escape_into(&mut self.out, chunk.valid());
+            if !chunk.invalid().is_empty() {
+                self.put("\\ufffd");
+            }
+        }
+        self.put_char('"');
+        self
+    }
+
+    /// Bytes as a standard (padded) base64 string - exact, for data that
+    /// may not be text.
+    pub fn base64(&mut self, v: &[u8]) -> &mut Self {
+        const ALPHABET: &[u8; 64] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        self.value_start();
+        self.put_char('"');
+        for group in v.chunks(3) {
+            let b = [
+                group[0],
+                group.get(1).copied().unwrap_or(0),
+                group.get(2).copied().unwrap_or(0),
+            ];
+            let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+            for i in 0..4 {
+                if i <= group.len() {
+                    self.put_char(char::from(ALPHABET[((n >> (18 - 6 * i)) & 63) as usize]));
+                } else {
+                    self.put_char('=');
+                }
+            }
+        }
+        self.put_char('"');
+        self
+    }
+
+    /// `v` as a number, or `null` for `None`.
+    pub fn opt_i64(&mut self, v: Option<i64>) -> &mut Self {
+        match v {
+            Some(v) => self.i64(v),
+            None => self.null(),
+        }
+    }
+
+    /* "key":value shorthands - most of our output is flat fields */
+
+    /// `"key":"v"`
+    pub fn field_str(&mut self, key: &str, v: &str) -> &mut Self {
+        self.key(key).str(v)
+    }
+
+    /// `"key":v`
+    pub fn field_u64(&mut self, key: &str, v: u64) -> &mut Self {
+        self.key(key).u64(v)
+    }
+
+    /// `"key":v`
+    pub fn field_i64(&mut self, key: &str, v: i64) -> &mut Self {
+        self.key(key).i64(v)
+    }
+
+    /// `"key":true`
+    pub fn field_bool(&mut self, key: &str, v: bool) -> &mut Self {
+        self.key(key).bool(v)
+    }
+}
+
+/* escapes everything written through it, for display() */
+struct Escaper<'a, W>(&'a mut W);
+
+impl<W: Write> Write for Escaper<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        escape_into(self.0, s);
+        Ok(())
+    }
+}
+
+fn write_escaped(out: &mut impl Write, s: &str) {
+    let _ = out.write_char('"');
+    escape_into(out, s);
+    let _ = out.write_char('"');
+}
+
+/* quotes, backslashes and control characters; everything else verbatim */
+fn escape_into(out: &mut impl Write, s: &str) {
+    for c in s.chars() {
+        let _ = match c {
+            '"' => out.write_str("\\\""),
+            '\\' => out.write_str("\\\\"),
+            '\n' => out.write_str("\\n"),
+            '\r' => out.write_str("\\r"),
+            '\t' => out.write_str("\\t"),
+            c if c < '\x20' => write!(out, "\\u{:04x}", c as u32),
+            c => out.write_char(c),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scalars() {
+        let mut w = JsonWriter::new();
+        w.begin_array();
+        w.u64(u64::MAX).i64(-1).bool(true).bool(false).null();
+        w.opt_i64(Some(3)).opt_i64(None).str("s");
+        w.tenths(955).tenths(0).tenths(1000);
+        w.end_array();
+        assert_eq!(
+            w.finish(),
+            r#"[18446744073709551615,-1,true,false,null,3,null,"s",95.5,0.0,100.0]"#
+        );
+    }
+
+    #[test]
+    fn test_escaping() {
+        let mut w = JsonWriter::new();
+        w.begin_object();
+        w.field_str("q\"k", "a\"b\\c\nd\re\tf\u{1}g\u{1f}h é ✓");
+        w.end_object();
+        assert_eq!(
+            w.finish(),
+            r#"{"q\"k":"a\"b\\c\nd\re\tf\u0001g\u001fh é ✓"}"#
+        );
+    }
+
+    #[test]
+    fn test_display_is_escaped() {
+        let mut w = JsonWriter::new();
+        w.display(format_args!("say \"{}\"", 42));
+        assert_eq!(w.finish(), r#""say \"42\"""#);
+    }
+
+    #[test]
+    fn test_bytes_lossy() {
+        let mut w = JsonWriter::new();
+        w.begin_array();
+        w.bytes_lossy(b"caf\xe9 \"x\"\n");
+        w.bytes_lossy("ok \u{fffd}".as_bytes());
+        w.bytes_lossy(b"\xff\xfe");
+        w.end_array();
+        /* a replaced byte is escaped; a real U+FFFD passes through as-is */
+        assert_eq!(w.finish(), r#"["caf\ufffd \"x\"\n","ok �","\ufffd\ufffd"]"#);
+    }
+
+    #[test]
+    fn test_base64() {
+        /* RFC 4648 test vectors, plus bytes that aren't UTF-8 */
+        let cases: [(&[u8], &str); 8] = [
+            (b"", ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"foob", "Zm9vYg=="),
+            (b"fooba", "Zm9vYmE="),
+            (b"foobar", "Zm9vYmFy"),
+            (b"\xff\xfe\x00", "//4A"),
+        ];
+        for (input, want) in cases {
+            let mut w = JsonWriter::new();
+            w.base64(input);
+            assert_eq!(w.finish(), alloc::format!("\"{want}\""));
+        }
+    }
+
+    #[test]
+    fn test_nesting_and_commas() {
+        let mut w = JsonWriter::new();
+        w.begin_object();
+        w.field_u64("a", 1);
+        w.key("empty_obj").begin_object().end_object();
+        w.key("empty_arr").begin_array().end_array();
+        w.key("list").begin_array();
+        for i in 0..3 {
+            w.begin_object().field_u64("i", i).end_object();
+        }
+        w.begin_array().begin_array().end_array().u64(9).end_array();
+        w.end_array();
+        w.field_bool("z", true);
+        w.end_object();
+        assert_eq!(
+            w.finish(),
+            r#"{"a":1,"empty_obj":{},"empty_arr":[],"list":[{"i":0},{"i":1},{"i":2},[[],9]],"z":true}"#
+        );
+    }
+
+    #[test]
+    fn test_custom_sink() {
+        let mut w = JsonWriter::on(crate::io::StackBuf::<16>::new());
+        w.begin_object().field_str("k", "v\"").end_object();
+        let out = w.finish();
+        assert!(!out.spilled());
+        assert_eq!(out.as_bytes(), br#"{"k":"v\""}"#);
+    }
+
+    #[test]
+    fn test_sibling_containers_reset_state() {
+        /* an inner container's item count must not leak into the next one */
+        let mut w = JsonWriter::new();
+        w.begin_array();
+        w.begin_array().u64(1).u64(2).end_array();
+        w.begin_array().u64(3).end_array();
+        w.end_array();
+        assert_eq!(w.finish(), "[[1,2],[3]]");
+    }
+}
@@ -0,0 +1,326 @@
+/*
+ * kq.rs
+ *
+ * owned kqueue fd plus builders for the few filters we use.
+ *
+ * every wait loop used to create its kqueue by hand and close() it on each
+ * of a dozen exits - miss one and the fd leaks, once per attempt. Kqueue
+ * closes itself on drop, so an early return or `?` can't leak it.
+ *
+ * the builders return plain libc::kevent values: callers still keep a
+ * changes array and flip flags (EV_DELETE, re-arming a oneshot) between
+ * waits, they just don't spell out six fields to do it.
+ *
+ * one Kqueue serves a whole run - every attempt, grace period, hook and
+ * retry delay - instead of a kqueue() + close() per wait. the catch is
+ * leftovers: a timer that didn't fire, a proc filter for a child we reaped
+ * with a plain wait(), or an event that fired but was never collected would
+ * all show up in the next wait. so the queue remembers what it registered,
+ * and reset() deletes all of it (EV_DELETE also discards pending events)
+ * before the next wait registers its own filters.
+ *
+ * epoll.rs is the linux twin and has to keep the same surface.
+ */
+
+use core::cell::Cell;
+use core::ptr;
+
+use crate::errno::errno;
+
+/* flags and filters callers match events against, named via kq:: so the
+ * linux twin can supply its own */
+pub(crate) use libc::{
+    EV_ADD, EV_DELETE, EV_EOF, EV_ERROR, EV_ONESHOT, EVFILT_PROC, EVFILT_READ, EVFILT_TIMER,
+};
+
+type RawFd = i32;
+
+/* distinct (ident, filter) pairs one queue tracks between resets. a wait
+ * registers at most nine */
+const TRACKED: usize = 12;
+
+/// A kqueue, closed on drop.
+pub(crate) struct Kqueue {
+    fd: RawFd,
+    /* (ident, filter) of filters added since the last reset() */
+    added: Cell<[Option<(usize, i16)>; TRACKED]>,
+}
+
+impl Kqueue {
+    /// New kqueue, or the errno if the kernel refused (fd limit).
+    pub(crate) fn new() -> Result<Self, i32> {
+        // SAFETY: kqueue() has no preconditions, returns -1 on error
+        let fd = unsafe { libc::kqueue() };
+        if fd < 0 {
+            Err(errno())
+        } else {
+            Ok(Self {
+                fd,
+                added: Cell::new([None; TRACKED]),
+            })
+        }
+    }
+
+    /// Delete every filter registered through [`Kqueue::wait`], along with
+    /// any events they left pending. Call before reusing the queue for a
+    /// new wait. Filters that already went away (oneshots that fired, exited
+    /// processes) are skipped by the kernel.
+    pub(crate) fn reset(&self) {
+        let added = self.added.replace([None; TRACKED]);
+        let mut deletes = [empty(); TRACKED];
+        let mut n = 0;
+        for (ident, filter) in added.into_iter().flatten() {
+            deletes[n] = libc::kevent {
+                ident,
+                filter,
+                flags: libc::EV_DELETE | libc::EV_RECEIPT,
+                ..empty()
+            };
+            n += 1;
+        }
+        self.delete(&mut deletes[..n]);
+    }
+
+    /* EV_RECEIPT: one result per change (ENOENT for the ones already gone),
+     * returned in place, so nothing stops early and nothing blocks */
+    fn delete(&self, deletes: &mut [libc::kevent]) {
+        if deletes.is_empty() {
+            return;
+        }
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        let n = deletes.len() as i32;
+        let zero = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: fd is our open kqueue; deletes is a valid slice of n kevents
+        // used as both change and result list, which kevent() allows; zero
+        // is a valid timespec.
+        unsafe { libc::kevent(self.fd, deletes.as_ptr(), n, deletes.as_mut_ptr(), n, &zero) };
+    }
+
+    /* remember EV_ADDs, forget EV_DELETEs */
+    fn track(&self, changes: &[libc::kevent]) {
+        let mut added = self.added.get();
+        for c in changes {
+            let key = Some((c.ident, c.filter));
+            if c.flags & libc::EV_DELETE != 0 {
+                if let Some(slot) = added.iter_mut().find(|a| **a == key) {
+                    *slot = None;
+                }
+            } else if c.flags & libc::EV_ADD != 0 && !added.contains(&key) {
+                let free = added.iter_mut().find(|a| a.is_none());
+                debug_assert!(free.is_some(), "kqueue registration table full");
+                if let Some(slot) = free {
+                    *slot = key;
+                }
+            }
+        }
+        self.added.set(added);
+    }
+
+    /// Submit `changes`, then block until one event arrives in `event`.
+    /// No timeout - register a timer for that. `Err(errno)` on failure,
+    /// EINTR included; callers decide whether to retry.
+    pub(crate) fn wait(
+        &self,
+        changes: &[libc::kevent],
+        event: &mut libc::kevent,
+    ) -> Result<(), i32> {
+        self.track(changes);
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        let nchanges = changes.len() as i32;
+        // SAFETY: fd is our open kqueue; changes is a valid slice of nchanges
+        // kevents; event is a valid buffer for one; null timeout means wait.
+        let n = unsafe { libc::kevent(self.fd, changes.as_ptr(), nchanges, event, 1, ptr::null()) };
+        if n < 0 { Err(errno()) } else { Ok(()) }
+    }
+
+    /// The queue's own descriptor: readable while events are pending, so
+    /// another queue can wait on this one.
+    #[cfg(feature = "proc-info")]
+    pub(crate) fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    /// Submit one change that stays until the queue closes, or its fd
+    /// does. Not tracked, so reset() leaves it alone: for a queue that
+    /// never waits, or a vnode watch whose fd the caller closes. Err(errno)
+    /// if the kernel refused it - ESRCH for a process that's gone.
+    ///
+    /// macOS only, for proctree.rs and wait.rs; the linux twin has no
+    /// equivalent.
+    pub(crate) fn register(&self, change: &libc::kevent) -> Result<(), i32> {
+        // SAFETY: fd is our open kqueue; change is one valid kevent; with
+        // no event buffer, errors come back as -1 and errno.
+        let n = unsafe { libc::kevent(self.fd, change, 1, ptr::null_mut(), 0, ptr::null()) };
+        if n < 0 { Err(errno()) } else { Ok(()) }
+    }
+
+    /// Collect pending events into `events` without blocking. How many
+    /// there were; 0 on error.
+    #[cfg(feature = "proc-info")]
+    pub(crate) fn poll(&self, events: &mut [libc::kevent]) -> usize {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        let nevents = events.len() as i32;
+        let zero = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: fd is our open kqueue; events is a valid buffer for
+        // nevents kevents; zero is a valid timespec.
+        let n =
+            unsafe { libc::kevent(self.fd, ptr::null(), 0, events.as_mut_ptr(), nevents, &zero) };
+        usize::try_from(n).unwrap_or(0)
+    }
+}
+
+impl Drop for Kqueue {
+    fn drop(&mut self) {
+        // SAFETY: fd came from kqueue() in new() and is closed exactly once here
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+/// Zeroed kevent, for the output buffer or a change slot that's off.
+pub(crate) const fn empty() -> libc::kevent {
+    libc::kevent {
+        ident: 0,
+        filter: 0,
+        flags: 0,
+        fflags: 0,
+        data: 0,
+        udata: ptr::null_mut(),
+    }
+}
+
+/// Oneshot: `pid` exited.
+#[allow(clippy::cast_sign_loss)]
+pub(crate) const fn proc_exit(pid: i32) -> libc::kevent {
+    libc::kevent {
+        ident: pid as usize,
+        filter: libc::EVFILT_PROC,
+        flags: libc::EV_ADD | libc::EV_ONESHOT,
+        fflags: libc::NOTE_EXIT,
+        ..empty()
+    }
+}
+
+/// `pid` forked, exec'd or exited. Stays until the process is gone.
+#[cfg(feature = "proc-info")]
+#[allow(clippy::cast_sign_loss)]
+pub(crate) const fn proc_lifetime(pid: i32) -> libc::kevent {
+    libc::kevent {
+        ident: pid as usize,
+        filter: libc::EVFILT_PROC,
+        flags: libc::EV_ADD,
+        fflags: libc::NOTE_FORK | libc::NOTE_EXEC | libc::NOTE_EXIT,
+        ..empty()
+    }
+}
+
+/// Oneshot nanosecond timer. `ident` tells timers on one kqueue apart;
+/// `ns` is clamped to what the kernel accepts.
+#[allow(clippy::cast_possible_wrap)]
+pub(crate) const fn timer(ident: usize, ns: u64) -> libc::kevent {
+    let ns = if ns > isize::MAX as u64 {
+        isize::MAX as u64
+    } else {
+        ns
+    };
+    libc::kevent {
+        ident,
+        filter: libc::EVFILT_TIMER,
+        flags: libc::EV_ADD | libc::EV_ONESHOT,
+        fflags: libc::NOTE_NSECONDS,
+        data: ns as isize,
+        ..empty()
+    }
+}
+
+/// The file or directory `fd` was opened on (O_EVTONLY) was written to,
+/// extended, deleted, renamed or had its attributes changed. EV_CLEAR: one
+/// wakeup per batch of changes. Goes away when `fd` is closed.
+#[allow(clippy::cast_sign_loss)]
+pub(crate) const fn vnode(fd: RawFd) -> libc::kevent {
+    libc::kevent {
+        ident: fd as usize,
+        filter: libc::EVFILT_VNODE,
+        flags: libc::EV_ADD | libc::EV_CLEAR,
+        fflags: libc::NOTE_WRITE
+            | libc::NOTE_EXTEND
+            | libc::NOTE_DELETE
+            | libc::NOTE_RENAME
+            | libc::NOTE_ATTRIB,
+        ..empty()
+    }
+}
+
+/// `fd` is readable. Stays registered after firing. With `enabled` false
+/// the slot is a no-op until its flags are set.
+#[allow(clippy::cast_sign_loss)]
+pub(crate) const fn read(fd: RawFd, enabled: bool) -> libc::kevent {
+    libc::kevent {
+        ident: fd as usize,
+        filter: libc::EVFILT_READ,
+        flags: if enabled { libc::EV_ADD } else { 0 },
+        ..empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builders() {
+        let t = timer(7, u64::MAX);
+        assert_eq!((t.ident, t.filter), (7, libc::EVFILT_TIMER));
+        assert_eq!(t.data, isize::MAX, "timer clamps to isize");
+        assert_eq!(timer(1, 500).data, 500);
+
+        assert_eq!(read(3, false).flags, 0);
+        assert_eq!(read(3, true).flags, libc::EV_ADD);
+
+        let p = proc_exit(42);
+        assert_eq!((p.ident, p.fflags), (42, libc::NOTE_EXIT));
+
+        let v = vnode(5);
+        assert_eq!((v.ident, v.filter), (5, libc::EVFILT_VNODE));
+        assert_ne!(v.flags & libc::EV_CLEAR, 0);
+    }
+
+    #[test]
+    fn test_timer_fires() {
+        let kq = Kqueue::new().expect("kqueue");
+        let mut event = empty();
+        kq.wait(&[timer(1, 1_000_000)], &mut event).expect("kevent");
+        assert_eq!((event.ident, event.filter), (1, libc::EVFILT_TIMER));
+    }
+
+    #[test]
+    #[cfg(feature = "proc-info")]
+    fn test_register_and_poll() {
+        let kq = Kqueue::new().expect("kqueue");
+        let mut events = [empty(); 4];
+        assert_eq!(kq.poll(&mut events), 0, "nothing pending");
+        /* -1 is never a process */
+        assert_eq!(kq.register(&proc_lifetime(-1)), Err(libc::ESRCH));
+        // SAFETY: getpid has no preconditions
+        let me = unsafe { libc::getpid() };
+        assert_eq!(kq.register(&proc_lifetime(me)), Ok(()));
+        assert_eq!(kq.poll(&mut events), 0);
+    }
+
+    #[test]
+    fn test_reset_discards_leftovers() {
+        let kq = Kqueue::new().expect("kqueue");
+        let mut event = empty();
+        /* both fire; one is returned, the other stays pending */
+        kq.wait(&[timer(1, 1_000_000), timer(2, 1_000_000)], &mut event)
+            .expect("kevent");
+        kq.reset();
+        kq.wait(&[timer(3, 1_000_000)], &mut event).expect("kevent");
+        assert_eq!(event.ident, 3, "stale timer survived reset");
+    }
+}
@@ -15,19 +15,31 @@
 //!
 //! ## Platform Support
 //!
-//! **macOS only.** This crate uses Darwin kernel APIs not available on other platforms.
+//! **macOS first.** This crate is built around Darwin kernel APIs.
 //! iOS support is planned for a future release (library subset only, no process spawning).
 //!
+//! **Linux (glibc, kernel 5.3+) is a secondary target** with the same CLI, JSON
+//! schema, retry and hook semantics. The Darwin calls are swapped for their
+//! nearest equivalents: epoll with pidfd and timerfd for kqueue, `CLOCK_BOOTTIME`
+//! for `mach_continuous_time`, procfs for libproc. `--monitor-qos` is accepted
+//! but does nothing there.
+//!
 //! ## Library Usage
 //!
-//! The primary entry points are [`run_command`] and [`run_with_retry`]:
+//! The CLI parses its options into [`Args`] and builds a [`RunConfig`]
+//! from them with [`RunConfig::from_args`]. A program embedding procguard
+//! skips both: it fills in a `RunConfig` itself, starting from
+//! `..RunConfig::default()` for everything it doesn't care about, and calls
+//! [`run_command`] (one attempt) or [`run_with_retry`] (with `retry_count`
+//! attempts). There is no stdout, JSON or exit code on this path - the
+//! [`RunResult`] is the report.
 //!
-//! ```ignore
+//! ```rust
 //! use procguard::{RunConfig, RunResult, Signal, run_command, setup_signal_forwarding};
 //! use std::time::Duration;
 //!
-//! // Set up signal forwarding (optional but recommended)
-//! let _ = setup_signal_forwarding();
+//! // Set up signal forwarding (optional but recommended); undone on drop
+//! let _forwarding = setup_signal_forwarding();
 //!
 //! // Configure the timeout
 //! let config = RunConfig {
@@ -38,7 +50,7 @@
 //! };
 //!
 //! // Run a command
-//! let args = ["-c".to_string(), "sleep 10".to_string()];
+//! let args = ["-c".to_string(), "echo hello".to_string()];
 //! match run_command("sh", &args, &config) {
 //!     Ok(RunResult::Completed { status, rusage }) => {
 //!         println!("Command exited with code {:?}", status.code());
@@ -52,23 +64,45 @@
 //! }
 //! ```
 //!
+//! Every item re-exported below has an example of its own, run as a
+//! doctest.
+//!
+//! When only the verdict matters, [`Outcome`] flattens a result into
+//! success, failure, crash, timeout, limit or forwarded signal - the same
+//! classification the CLI uses for its exit code and JSON `status`.
+//!
 //! ## Parsing Utilities
 //!
-//! Helper functions for parsing duration and signal specifications:
+//! The CLI's duration and signal grammars, for tools that want to accept
+//! exactly what procguard does:
 //!
 //! ```rust
-//! use procguard::{parse_duration, parse_signal, signal::Signal};
+//! use procguard::{format_duration, parse_duration, parse_signal, signal_name, Signal};
 //! use core::time::Duration;
 //!
-//! // Parse duration strings
+//! // Parse duration strings, and write them back
 //! let dur = parse_duration("30s").unwrap();
 //! assert_eq!(dur, Duration::from_secs(30));
+//! assert_eq!(format_duration(dur).to_string(), "30s");
 //!
-//! // Parse signal specifications
+//! // Parse signal specifications, by name or number
 //! let sig = parse_signal("TERM").unwrap();
 //! assert_eq!(sig, Signal::SIGTERM);
+//! assert_eq!(signal_name(sig), "SIGTERM");
+//! assert!(Signal::ALL.contains(&sig));
 //! ```
 //!
+//! ## Waiting
+//!
+//! The sleep-aware waits the CLI uses are available on their own in
+//! [`wait`]: [`wait::delay`] sleeps on a kqueue timer (counting system
+//! sleep too with [`wait::delay_confined`], as `--sleep` does) and
+//! [`wait::wait_for_path`] waits for a file to appear, go away or contain
+//! a string. [`wait::wait_for_window`] waits for a time-of-day [`Window`]
+//! to open, and [`wait::wait_for_pid`] for any process to exit (as
+//! `--wait-pid` does). All take a [`wait::Cancel`] - a descriptor or an
+//! `AtomicBool` - to stop early from another thread or a signal handler.
+//!
 //! ## Features
 //!
 //! - **Zero-CPU waiting** via kqueue - no polling
@@ -85,12 +119,21 @@
 //! in minor versions. Use `..RunConfig::default()` when constructing to ensure
 //! forward compatibility. The [`RunResult`] enum is marked `#[non_exhaustive]`,
 //! so match arms should include a wildcard pattern.
+//!
+//! The parsing utilities are stable: [`parse_duration`],
+//! [`format_duration`], [`parse_signal`], [`signal_name`],
+//! [`signal_number`] and [`Signal::ALL`]. Their grammars only grow - input
+//! that parses keeps parsing to the same value, and `format_duration`'s
+//! output for a given duration doesn't change. [`Signal`] is
+//! `#[non_exhaustive]` so signals can be added.
 
 #![cfg_attr(not(any(debug_assertions, test, doc)), no_std)]
+#![deny(missing_docs)]
 
-/* fail fast on unsupported platforms - darwin APIs required */
-#[cfg(not(target_os = "macos"))]
-compile_error!("procguard requires macOS (iOS support planned for future release)");
+/* fail fast on unsupported platforms - darwin APIs required, or their
+ * linux stand-ins */
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+compile_error!("procguard requires macOS or Linux (iOS support planned for future release)");
 
 extern crate alloc;
 
@@ -101,28 +144,103 @@ pub mod io;
 mod panic;
 #[doc(hidden)]
 pub mod proc_info;
+/// Spawning with `posix_spawn`, process groups and exit statuses.
 pub mod process;
+/// The command's process tree, as `--proc-tree` records it.
+pub mod proctree;
+/// Darwin QoS classes for our own thread (`--monitor-qos`).
+pub mod qos;
+/// Memory and CPU time limits: parsing and `setrlimit`.
 pub mod rlimit;
 #[doc(hidden)]
 pub mod sync;
 #[doc(hidden)]
 pub mod throttle;
 
+/// `--also-kill`: other processes to signal along with the command.
+pub mod also_kill;
+/// Command-line parsing for the binary. Its fields mirror the options,
+/// which `--help` and the README document.
+#[allow(missing_docs)]
 pub mod args;
+#[doc(hidden)]
+pub mod compat;
+#[doc(hidden)]
+pub mod crash;
+/// The duration grammar: `30s`, `1.5h`, `0` for none.
 pub mod duration;
+/// errno, read and named in one place.
+pub mod errno;
+/// [`TimeoutError`] and the GNU exit codes.
 pub mod error;
+/// The timeline of a run: [`EventLog`].
+pub mod events;
+/// `--extend-env-file`: a deadline pushed back by writing a file.
+pub mod extend;
+#[doc(hidden)]
+pub mod failsafe;
+mod forward;
+/// The JSON writer and [`json::SCHEMA_VERSION`].
+pub mod json;
+#[cfg(target_os = "macos")]
+mod kq;
+/* same surface as kq.rs, on epoll/pidfd/timerfd */
+#[cfg(target_os = "linux")]
+mod epoll;
+#[cfg(target_os = "linux")]
+use epoll as kq;
+#[cfg(target_os = "linux")]
+#[doc(hidden)]
+pub mod linux;
+/// Everything procguard says on stderr, and its [`Warning`]s.
+pub mod log;
+/// Why a command can't be run, found by walking PATH.
+pub mod lookup;
+/// [`Outcome`]: a run's verdict in one flat enum.
+pub mod outcome;
+mod output;
+/// `--reap-orphans`: killing what the command left in its process group.
+pub mod reaper;
+/// `--every`, `--runs`: running a command on a schedule.
+pub mod repeat;
+#[doc(hidden)]
+pub mod response_file;
+/// `--run-id`: one id in everything a run reports.
+pub mod run_id;
+/// Spawn, watch the clock, escalate: [`run_command`] and friends.
 pub mod runner;
+#[doc(hidden)]
+pub mod safe_file;
+/// [`Signal`] and the signal grammar.
 pub mod signal;
+/// Pass/fail counts and duration percentiles over repeated runs.
+pub mod stats;
+/// Checked nanosecond arithmetic for deadlines.
 pub mod time_math;
+/// The controlling terminal, for interactive children.
+pub mod tty;
+/// Sleep-aware waits: delays, files, pids, time windows.
 pub mod wait;
+/// `--only-between`: a time-of-day [`Window`].
+pub mod window;
 
+#[cfg(feature = "alloc-stats")]
+pub use allocator::AllocStats;
 pub use args::Args;
-pub use duration::{is_no_timeout, parse_duration};
+pub use duration::{format_duration, is_no_timeout, parse_duration};
 pub use error::{Result, TimeoutError, exit_codes};
-pub use process::ResourceUsage;
-pub use rlimit::{ResourceLimits, parse_cpu_percent, parse_cpu_time, parse_mem_limit};
+pub use events::{Event, EventKind, EventLog};
+pub use log::{Interval, Warning};
+pub use outcome::{LimitKind, Outcome};
+pub use process::{ProcessGroup, ResourceUsage};
+pub use repeat::{Flakiness, FlakyExit, Schedule, StopOn};
+pub use rlimit::{
+    ResourceLimits, parse_cpu_percent, parse_cpu_time, parse_cpu_time_pair, parse_mem_limit,
+};
 pub use runner::{
-    AttemptResult, Attempts, HookResult, MAX_RETRIES, RunConfig, RunResult, TimeoutReason,
-    cleanup_signal_forwarding, run_command, run_with_retry, setup_signal_forwarding,
+    AttemptResult, Attempts, ChildState, Escalation, HookResult, MAX_RETRIES, PollIntervals,
+    RunConfig, RunResult, SignalForwardGuard, TimeoutReason, run_command, run_crash_hook,
+    run_with_retry, run_with_retry_bytes, run_with_retry_events, setup_signal_forwarding,
 };
 pub use signal::{Signal, parse_signal, signal_name, signal_number};
+pub use window::Window;
@@ -0,0 +1,111 @@
+/*
+ * linux.rs
+ *
+ * the darwin calls outside the event loop, rebuilt from linux parts. the
+ * modules using them keep their darwin spelling and import these instead
+ * of the extern declarations, so the timing code reads the same on both.
+ *
+ * clocks:
+ *   mach_continuous_time   -> CLOCK_BOOTTIME, keeps counting through suspend
+ *   clock_gettime_nsec_np  -> clock_gettime, same clock ids where they exist
+ *   mach_timebase_info     -> 1/1, both clocks are nanoseconds already
+ *
+ * argv: no _NSGetArgv. glibc hands .init_array functions the same
+ * (argc, argv, envp) main gets - std reads its args that way too - so we
+ * keep the pointers from there.
+ */
+
+use core::ffi::{c_char, c_int};
+use core::sync::atomic::{AtomicI32, AtomicPtr, Ordering};
+
+/* layout of darwin's struct mach_timebase_info */
+#[repr(C)]
+pub struct MachTimebaseInfo {
+    pub numer: u32,
+    pub denom: u32,
+}
+
+/* nanoseconds on `clock`, 0 if the kernel doesn't know it */
+fn clock_ns(clock: libc::clockid_t) -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: ts is a valid timespec to fill in
+    if unsafe { libc::clock_gettime(clock, &raw mut ts) } != 0 {
+        return 0;
+    }
+    #[allow(clippy::cast_sign_loss)]
+    (ts.tv_sec as u64)
+        .saturating_mul(1_000_000_000)
+        .saturating_add(ts.tv_nsec as u64)
+}
+
+/* unsafe only to match the extern declarations they replace */
+
+/// # Safety
+/// None - unsafe to match darwin's declaration.
+pub unsafe fn mach_continuous_time() -> u64 {
+    clock_ns(libc::CLOCK_BOOTTIME)
+}
+
+/// # Safety
+/// `info` must be valid for writes, as on darwin.
+pub unsafe fn mach_timebase_info(info: *mut MachTimebaseInfo) -> i32 {
+    // SAFETY: the caller passes a valid MachTimebaseInfo
+    unsafe { info.write(MachTimebaseInfo { numer: 1, denom: 1 }) };
+    0
+}
+
+/// # Safety
+/// None - unsafe to match darwin's declaration.
+pub unsafe fn clock_gettime_nsec_np(clock: libc::clockid_t) -> u64 {
+    clock_ns(clock)
+}
+
+static ARGC: AtomicI32 = AtomicI32::new(0);
+static ARGV: AtomicPtr<*const c_char> = AtomicPtr::new(core::ptr::null_mut());
+
+extern "C" fn keep_args(argc: c_int, argv: *const *const c_char, _envp: *const *const c_char) {
+    ARGC.store(argc, Ordering::Relaxed);
+    ARGV.store(argv.cast_mut(), Ordering::Relaxed);
+}
+
+#[used]
+#[unsafe(link_section = ".init_array.00099")]
+static ARGS_INIT: extern "C" fn(c_int, *const *const c_char, *const *const c_char) = keep_args;
+
+/// argc and argv as main got them. (0, null) if the C library didn't pass
+/// them to .init_array (musl).
+pub(crate) fn raw_argv() -> (c_int, *const *const c_char) {
+    let argv = ARGV.load(Ordering::Relaxed).cast_const();
+    if argv.is_null() {
+        (0, argv)
+    } else {
+        (ARGC.load(Ordering::Relaxed), argv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clocks_advance() {
+        // SAFETY: no preconditions, see above
+        let a = unsafe { mach_continuous_time() };
+        // SAFETY: as above
+        let b = unsafe { mach_continuous_time() };
+        assert!(a > 0 && b >= a);
+        // SAFETY: no preconditions, see above
+        let raw = unsafe { clock_gettime_nsec_np(libc::CLOCK_MONOTONIC_RAW) };
+        assert!(raw > 0);
+    }
+
+    #[test]
+    fn test_raw_argv_captured() {
+        let (argc, argv) = raw_argv();
+        assert!(argc >= 1, "glibc passes argv to .init_array");
+        assert!(!argv.is_null());
+    }
+}
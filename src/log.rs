@@ -0,0 +1,790 @@
+/*
+ * log.rs
+ *
+ * Everything procguard itself says on stderr goes through here: errors,
+ * warnings, verbose diagnostics. Two knobs apply to all of it:
+ *
+ * - quiet level: 0 prints everything, 1 (-q) drops warnings and diagnostics
+ *   but keeps errors, 2 (-qq) drops errors too
+ * - tag: the "timeout:" prefix, replaceable with --tag so nested wrappers
+ *   writing to one stderr can be told apart
+ *
+ * Both are the run's, not the process's: a Logger carries them, made from
+ * the RunConfig (RunConfig::log) in the library and from the command line
+ * (OwnedArgs::log) in main, so two runs in one process can say things
+ * their own way.
+ *
+ * Each line is formatted first and written with one write(2), so lines from
+ * several wrappers sharing a pipe don't interleave mid-line.
+ *
+ * Free-text warnings on stderr break consumers that merge stdout and stderr
+ * and expect one JSON line. So every warning is a variant here with a stable
+ * code, gets recorded in the run's EventLog, and main.rs copies the recorded
+ * set into the JSON "warnings" array. Per run, not per process: a library
+ * running commands on several threads keeps each run's warnings to itself.
+ *
+ * Storage (Warnings) is one slot per kind: a presence bit plus two u64
+ * payload words. No allocation, and a warning repeated across retries shows
+ * up once (last payload wins).
+ */
+
+use alloc::string::String;
+use core::fmt::{self, Write};
+
+use crate::signal::{Signal, signal_name};
+
+/// `-q`: warnings and diagnostics off, errors still printed.
+pub const QUIET_WARNINGS: u8 = 1;
+/// `-qq`: errors off as well. Exit codes and --json still report them.
+pub const QUIET_ALL: u8 = 2;
+
+/// Prefix when no `--tag` is given and the library is left to choose.
+pub const DEFAULT_TAG: &str = "timeout";
+
+/// An option whose interval is weighed against the timeout, for
+/// [`Warning::IntervalExceedsTimeout`].
+///
+/// ```
+/// use procguard::{Interval, Warning};
+///
+/// let w = Warning::IntervalExceedsTimeout {
+///     option: Interval::Heartbeat,
+///     interval_ms: 60_000,
+///     timeout_ms: 30_000,
+/// };
+/// assert_eq!(w.code(), "heartbeat_exceeds_timeout");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Interval {
+    /// `--heartbeat`: no heartbeat prints before the timeout.
+    Heartbeat,
+    /// `--stdin-timeout`: the main timeout always gets there first.
+    StdinTimeout,
+    /// How often `--mem-limit` polls: the limit may go unchecked.
+    MemoryCheck,
+}
+
+const INTERVALS: [Interval; 3] = [
+    Interval::Heartbeat,
+    Interval::StdinTimeout,
+    Interval::MemoryCheck,
+];
+
+/// Something worth telling the user that doesn't stop the run.
+///
+/// Printed on stderr (unless `-q`) and kept for the JSON `warnings`
+/// array; a library caller reads a run's back with
+/// [`EventLog::warnings`](crate::events::EventLog::warnings).
+///
+/// ```
+/// use procguard::Warning;
+///
+/// let w = Warning::RetryCapped { requested: 50, max: 31 };
+/// assert_eq!(w.code(), "retry_capped");
+/// assert_eq!(w.to_string(), "retry count 50 capped to maximum 31");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Warning {
+    /// `--timeout-exit-code` collides with 125-137 and a timeout happened.
+    ReservedExitCode {
+        /// The `--timeout-exit-code`.
+        code: u8,
+    },
+    /// `--cpu-percent` leaves the child under a millisecond of each
+    /// `--cpu-throttle-quantum`: it stutters.
+    LowCpuPercent {
+        /// The `--cpu-percent`.
+        percent: u32,
+        /// The `--cpu-throttle-quantum`.
+        quantum_ms: u64,
+    },
+    /// `--retry` above the attempt cap.
+    RetryCapped {
+        /// The `--retry` asked for.
+        requested: u32,
+        /// The retries there will be.
+        max: u32,
+    },
+    /// `--on-timeout-limit` longer than the timeout itself.
+    HookLimitExceedsTimeout {
+        /// The `--on-timeout-limit`.
+        limit_ms: u64,
+        /// The timeout.
+        timeout_ms: u64,
+    },
+    /// A monitor wakeup ran this late - timeout itself was starved for CPU.
+    MonitorStarved {
+        /// How late the wakeup ran.
+        lag_ms: u64,
+    },
+    /// Signals to timeout won't reach the command: handlers or pipe couldn't
+    /// be set up. `errno` is 0 when too many runs are forwarding at once.
+    SignalForwardingUnavailable {
+        /// Why, or 0.
+        errno: i32,
+    },
+    /// `--timestamps` stopped for the command's stdout (`fd` 1) or stderr
+    /// (2): it wrote something that isn't UTF-8.
+    TimestampsDisabled {
+        /// 1 or 2.
+        fd: i32,
+    },
+    /// `count` processes of the command's tree outlived it outside its
+    /// process group (`--proc-tree`); `--kill-escaped` sent `killed` of
+    /// them SIGKILL.
+    ProcessesEscaped {
+        /// Processes that escaped.
+        count: u32,
+        /// Of those, sent SIGKILL.
+        killed: u32,
+    },
+    /// `TIMEOUT` is set, but the first operand is a duration too and was
+    /// taken as the one.
+    AmbiguousDuration {
+        /// The operand, taken as the duration.
+        operand_ms: u64,
+        /// `TIMEOUT`, not used.
+        env_ms: u64,
+    },
+    /// The command timed out running under Rosetta translation, which
+    /// makes it slower than the timeout may have been sized for.
+    TranslatedTimeout {
+        /// The timeout it ran into.
+        timeout_ms: u64,
+    },
+    /// `--verify-signal-delivery` found the command stopped after `signal`,
+    /// which it couldn't act on; it was resumed with SIGCONT, or `killed`.
+    StoppedAtSignal {
+        /// The signal it was found stopped after.
+        signal: i32,
+        /// Whether it was killed rather than resumed.
+        killed: bool,
+    },
+    /// The command timed out with a debugger attached; `--debugger-grace`
+    /// held off enforcement for `waited_ms` first.
+    ChildTraced {
+        /// How long we waited for the debugger to detach.
+        waited_ms: u64,
+    },
+    /// An interval option that can't do its job within the timeout. One
+    /// kind (code, slot) per [`Interval`].
+    IntervalExceedsTimeout {
+        /// Which option.
+        option: Interval,
+        /// Its interval.
+        interval_ms: u64,
+        /// The timeout.
+        timeout_ms: u64,
+    },
+}
+
+/* the interval kinds take the last slots, in INTERVALS order */
+const KINDS: usize = 12 + INTERVALS.len();
+
+impl Warning {
+    /// Stable machine-readable code, used as `"code"` in JSON.
+    #[must_use]
+    pub const fn code(&self) -> &'static str {
+        match self {
+            Self::ReservedExitCode { .. } => "reserved_exit_code",
+            Self::LowCpuPercent { .. } => "low_cpu_percent",
+            Self::RetryCapped { .. } => "retry_capped",
+            Self::HookLimitExceedsTimeout { .. } => "hook_limit_exceeds_timeout",
+            Self::MonitorStarved { .. } => "monitor_starved",
+            Self::SignalForwardingUnavailable { .. } => "signal_forwarding_unavailable",
+            Self::TimestampsDisabled { .. } => "timestamps_disabled",
+            Self::ProcessesEscaped { .. } => "processes_escaped",
+            Self::AmbiguousDuration { .. } => "ambiguous_duration",
+            Self::TranslatedTimeout { .. } => "translated_timeout",
+            Self::StoppedAtSignal { .. } => "stopped_at_signal",
+            Self::ChildTraced { .. } => "child_traced",
+            Self::IntervalExceedsTimeout { option, .. } => match option {
+                Interval::Heartbeat => "heartbeat_exceeds_timeout",
+                Interval::StdinTimeout => "stdin_timeout_exceeds_timeout",
+                Interval::MemoryCheck => "memory_check_exceeds_timeout",
+            },
+        }
+    }
+
+    const fn slot(&self) -> usize {
+        match self {
+            Self::ReservedExitCode { .. } => 0,
+            Self::LowCpuPercent { .. } => 1,
+            Self::RetryCapped { .. } => 2,
+            Self::HookLimitExceedsTimeout { .. } => 3,
+            Self::MonitorStarved { .. } => 4,
+            Self::SignalForwardingUnavailable { .. } => 5,
+            Self::TimestampsDisabled { .. } => 6,
+            Self::ProcessesEscaped { .. } => 7,
+            Self::AmbiguousDuration { .. } => 8,
+            Self::TranslatedTimeout { .. } => 9,
+            Self::StoppedAtSignal { .. } => 10,
+            Self::ChildTraced { .. } => 11,
+            Self::IntervalExceedsTimeout { option, .. } => 12 + *option as usize,
+        }
+    }
+
+    const fn payload(&self) -> (u64, u64) {
+        match *self {
+            Self::ReservedExitCode { code } => (code as u64, 0),
+            Self::LowCpuPercent {
+                percent,
+                quantum_ms,
+            } => (percent as u64, quantum_ms),
+            Self::RetryCapped { requested, max } => (requested as u64, max as u64),
+            Self::HookLimitExceedsTimeout {
+                limit_ms,
+                timeout_ms,
+            } => (limit_ms, timeout_ms),
+            Self::MonitorStarved { lag_ms } => (lag_ms, 0),
+            #[allow(clippy::cast_sign_loss)]
+            Self::SignalForwardingUnavailable { errno } => (errno as u64, 0),
+            #[allow(clippy::cast_sign_loss)]
+            Self::TimestampsDisabled { fd } => (fd as u64, 0),
+            Self::ProcessesEscaped { count, killed } => (count as u64, killed as u64),
+            Self::AmbiguousDuration { operand_ms, env_ms } => (operand_ms, env_ms),
+            Self::TranslatedTimeout { timeout_ms } => (timeout_ms, 0),
+            #[allow(clippy::cast_sign_loss)]
+            Self::StoppedAtSignal { signal, killed } => (signal as u64, killed as u64),
+            Self::ChildTraced { waited_ms } => (waited_ms, 0),
+            Self::IntervalExceedsTimeout {
+                interval_ms,
+                timeout_ms,
+                ..
+            } => (interval_ms, timeout_ms),
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    const fn from_slot(slot: usize, a: u64, b: u64) -> Option<Self> {
+        match slot {
+            0 => Some(Self::ReservedExitCode { code: a as u8 }),
+            1 => Some(Self::LowCpuPercent {
+                percent: a as u32,
+                quantum_ms: b,
+            }),
+            2 => Some(Self::RetryCapped {
+                requested: a as u32,
+                max: b as u32,
+            }),
+            3 => Some(Self::HookLimitExceedsTimeout {
+                limit_ms: a,
+                timeout_ms: b,
+            }),
+            4 => Some(Self::MonitorStarved { lag_ms: a }),
+            #[allow(clippy::cast_possible_wrap)]
+            5 => Some(Self::SignalForwardingUnavailable { errno: a as i32 }),
+            #[allow(clippy::cast_possible_wrap)]
+            6 => Some(Self::TimestampsDisabled { fd: a as i32 }),
+            7 => Some(Self::ProcessesEscaped {
+                count: a as u32,
+                killed: b as u32,
+            }),
+            8 => Some(Self::AmbiguousDuration {
+                operand_ms: a,
+                env_ms: b,
+            }),
+            9 => Some(Self::TranslatedTimeout { timeout_ms: a }),
+            #[allow(clippy::cast_possible_wrap)]
+            10 => Some(Self::StoppedAtSignal {
+                signal: a as i32,
+                killed: b != 0,
+            }),
+            11 => Some(Self::ChildTraced { waited_ms: a }),
+            12..KINDS => Some(Self::IntervalExceedsTimeout {
+                option: INTERVALS[slot - 12],
+                interval_ms: a,
+                timeout_ms: b,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/* "60s" when whole seconds, "1500ms" otherwise */
+struct Ms(u64);
+
+impl fmt::Display for Ms {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_multiple_of(1000) {
+            write!(f, "{}s", self.0 / 1000)
+        } else {
+            write!(f, "{}ms", self.0)
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::ReservedExitCode { code } => write!(
+                f,
+                "--timeout-exit-code {} may conflict with reserved exit codes (125-137)",
+                code
+            ),
+            Self::LowCpuPercent {
+                percent,
+                quantum_ms,
+            } => write!(
+                f,
+                "--cpu-percent {} leaves under 1ms of each {}ms --cpu-throttle-quantum; \
+                 may cause stuttery execution",
+                percent, quantum_ms
+            ),
+            Self::RetryCapped { requested, max } => {
+                write!(f, "retry count {} capped to maximum {}", requested, max)
+            }
+            Self::HookLimitExceedsTimeout {
+                limit_ms,
+                timeout_ms,
+            } => write!(
+                f,
+                "--on-timeout-limit ({}) exceeds main timeout ({})",
+                Ms(limit_ms),
+                Ms(timeout_ms)
+            ),
+            Self::MonitorStarved { lag_ms } => write!(
+                f,
+                "timeout woke {} late under load; deadlines may fire late (see --monitor-qos)",
+                Ms(lag_ms)
+            ),
+            Self::SignalForwardingUnavailable { errno: 0 } => write!(
+                f,
+                "too many concurrent runs; signals won't be forwarded to the command"
+            ),
+            Self::SignalForwardingUnavailable { errno } => write!(
+                f,
+                "signal forwarding unavailable (errno {}); signals won't be forwarded to the command",
+                errno
+            ),
+            Self::TimestampsDisabled { fd } => write!(
+                f,
+                "command's {} isn't UTF-8 text; --timestamps turned off for it",
+                if fd == 2 { "stderr" } else { "stdout" }
+            ),
+            Self::ProcessesEscaped { count, killed } => {
+                write!(
+                    f,
+                    "{} process{} left the command's process group and outlived it",
+                    count,
+                    if count == 1 { "" } else { "es" }
+                )?;
+                match killed {
+                    0 => write!(f, " (see escaped_processes; --kill-escaped cleans up)"),
+                    n if n == count => write!(f, "; killed (--kill-escaped)"),
+                    n => write!(f, "; {} of them killed (--kill-escaped)", n),
+                }
+            }
+            Self::AmbiguousDuration { operand_ms, env_ms } => write!(
+                f,
+                "the first operand ({}) was taken as the duration over TIMEOUT ({}); \
+                 use -t DURATION, or -- before the command for TIMEOUT's",
+                Ms(operand_ms),
+                Ms(env_ms)
+            ),
+            Self::TranslatedTimeout { timeout_ms } => write!(
+                f,
+                "command timed out after {} running under Rosetta translation, which is \
+                 slower; allow it more time or run it native (--arch arm64)",
+                Ms(timeout_ms)
+            ),
+            Self::StoppedAtSignal { signal, killed } => {
+                let name = Signal::try_from_raw(signal).map_or("a signal", signal_name);
+                write!(
+                    f,
+                    "command was stopped when sent {}, so couldn't act on it; {}",
+                    name,
+                    if killed {
+                        "killed it rather than wait out the grace period"
+                    } else {
+                        "resumed it with SIGCONT"
+                    }
+                )
+            }
+            Self::ChildTraced { waited_ms: 0 } => write!(
+                f,
+                "command timed out with a debugger attached, which can hold back signals; \
+                 --debugger-grace pauses the timeout while one is"
+            ),
+            Self::ChildTraced { waited_ms } => write!(
+                f,
+                "command timed out with a debugger attached; waited {} of --debugger-grace \
+                 for it to detach",
+                Ms(waited_ms)
+            ),
+            Self::IntervalExceedsTimeout {
+                option,
+                interval_ms,
+                timeout_ms,
+            } => {
+                let (what, consequence) = match option {
+                    Interval::Heartbeat => ("--heartbeat", "no heartbeat will print"),
+                    Interval::StdinTimeout => ("--stdin-timeout", "it can never fire"),
+                    Interval::MemoryCheck => (
+                        "--mem-limit check interval",
+                        "the limit may never be checked",
+                    ),
+                };
+                write!(
+                    f,
+                    "{} ({}) exceeds main timeout ({}); {}",
+                    what,
+                    Ms(interval_ms),
+                    Ms(timeout_ms),
+                    consequence
+                )
+            }
+        }
+    }
+}
+
+/// The warnings one run recorded, one of each kind (a repeat replaces the
+/// earlier one). Kept by [`EventLog`](crate::events::EventLog).
+///
+/// ```
+/// use procguard::Warning;
+/// use procguard::log::Warnings;
+///
+/// let mut warnings = Warnings::new();
+/// warnings.record(Warning::MonitorStarved { lag_ms: 300 });
+/// warnings.record(Warning::MonitorStarved { lag_ms: 500 });
+/// assert_eq!(
+///     warnings.iter().collect::<Vec<_>>(),
+///     [Warning::MonitorStarved { lag_ms: 500 }]
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Warnings {
+    present: u32,
+    payload: [[u64; 2]; KINDS],
+}
+
+impl Default for Warnings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Warnings {
+    /// None recorded.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            present: 0,
+            payload: [[0; 2]; KINDS],
+        }
+    }
+
+    /// Record one, without printing it.
+    pub const fn record(&mut self, w: Warning) {
+        let slot = w.slot();
+        let (a, b) = w.payload();
+        self.payload[slot] = [a, b];
+        self.present |= 1 << slot;
+    }
+
+    /// Whether none was recorded.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.present == 0
+    }
+
+    /// The recorded warnings, in a fixed order (by kind, not by time).
+    pub fn iter(&self) -> impl Iterator<Item = Warning> + '_ {
+        (0..KINDS).filter_map(move |slot| {
+            if self.present & (1 << slot) == 0 {
+                return None;
+            }
+            let [a, b] = self.payload[slot];
+            Warning::from_slot(slot, a, b)
+        })
+    }
+}
+
+/// Where procguard's own stderr lines go, and which of them do: the tag
+/// they start with and the quiet level. Cheap to copy; make one from the
+/// run's settings with [`RunConfig::log`](crate::RunConfig::log).
+///
+/// ```
+/// use procguard::log::{Logger, QUIET_WARNINGS};
+///
+/// let log = Logger::new("ci", QUIET_WARNINGS);
+/// assert_eq!(log.tag(), "ci");
+/// assert!(log.is_quiet());
+/// log.info(format_args!("dropped under -q"));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Logger<'a> {
+    tag: &'a str,
+    quiet: u8,
+}
+
+impl Default for Logger<'_> {
+    fn default() -> Self {
+        Self::new(DEFAULT_TAG, 0)
+    }
+}
+
+impl<'a> Logger<'a> {
+    /// Lines prefixed `tag: `, at quiet level `quiet` (the number of `-q`
+    /// given). Levels above [`QUIET_ALL`] behave like it.
+    #[inline]
+    #[must_use]
+    pub const fn new(tag: &'a str, quiet: u8) -> Self {
+        Self { tag, quiet }
+    }
+
+    /// Prefix for our stderr lines, without the colon.
+    #[inline]
+    #[must_use]
+    pub const fn tag(&self) -> &'a str {
+        self.tag
+    }
+
+    /// The quiet level.
+    #[inline]
+    #[must_use]
+    pub const fn quiet_level(&self) -> u8 {
+        self.quiet
+    }
+
+    /// Whether warnings and diagnostics are off (`-q` or more).
+    #[inline]
+    #[must_use]
+    pub const fn is_quiet(&self) -> bool {
+        self.quiet >= QUIET_WARNINGS
+    }
+
+    /* "tag: message\n" in one write (BufWriter holds a typical line) */
+    fn emit(&self, args: fmt::Arguments<'_>) {
+        let mut line = crate::io::BufWriter::stderr();
+        let _ = write!(line, "{}: ", self.tag);
+        let _ = line.write_fmt(args);
+        let _ = line.write_bytes(b"\n");
+        let _ = line.flush();
+    }
+
+    /// Print an error, unless `-qq`.
+    pub fn error(&self, args: fmt::Arguments<'_>) {
+        if self.quiet < QUIET_ALL {
+            self.emit(args);
+        }
+    }
+
+    /// Print a diagnostic (verbose output, heartbeats), unless `-q`.
+    pub fn info(&self, args: fmt::Arguments<'_>) {
+        if !self.is_quiet() {
+            self.emit(args);
+        }
+    }
+
+    /// Print a warning to stderr, unless `-q`. This doesn't record it:
+    /// [`EventLog::warn`](crate::events::EventLog::warn) does both, so
+    /// --json lists it even under `-q`.
+    pub fn warn(&self, w: Warning) {
+        if !self.is_quiet() {
+            self.emit(format_args!("warning: {}", w));
+        }
+    }
+
+    /// Send a line to the system log: syslog(3), which macOS files in the
+    /// unified log, so `log show` finds it. For decisions that matter to
+    /// someone who isn't reading our stderr, like launchd, which sends it
+    /// nowhere by default. Not subject to `-q`; stderr is the caller's call.
+    pub fn system(&self, args: fmt::Arguments<'_>) {
+        let mut line = String::new();
+        let _ = write!(line, "{}: ", self.tag);
+        let _ = line.write_fmt(args);
+        /* syslog wants a C string; a NUL in the message would cut it short */
+        let mut bytes = line.into_bytes();
+        bytes.retain(|&b| b != 0);
+        bytes.push(0);
+        // SAFETY: the format is a static C string with one %s, and bytes is
+        // NUL-terminated with no NUL before the end, alive for the call.
+        unsafe {
+            libc::syslog(libc::LOG_NOTICE, c"%s".as_ptr(), bytes.as_ptr());
+        }
+    }
+}
+
+/// `log_error!(log, "...", args)` - [`Logger::error`] with format arguments.
+#[macro_export]
+macro_rules! log_error {
+    ($log:expr, $($arg:tt)*) => {
+        $log.error(format_args!($($arg)*))
+    };
+}
+
+/// `log_info!(log, "...", args)` - [`Logger::info`] with format arguments.
+#[macro_export]
+macro_rules! log_info {
+    ($log:expr, $($arg:tt)*) => {
+        $log.info(format_args!($($arg)*))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slot_roundtrip() {
+        let all = [
+            Warning::ReservedExitCode { code: 130 },
+            Warning::LowCpuPercent {
+                percent: 5,
+                quantum_ms: 10,
+            },
+            Warning::RetryCapped {
+                requested: 100,
+                max: 31,
+            },
+            Warning::HookLimitExceedsTimeout {
+                limit_ms: 60_000,
+                timeout_ms: 1_500,
+            },
+            Warning::MonitorStarved { lag_ms: 300 },
+            Warning::SignalForwardingUnavailable { errno: 24 },
+            Warning::TimestampsDisabled { fd: 2 },
+            Warning::ProcessesEscaped {
+                count: 3,
+                killed: 2,
+            },
+            Warning::AmbiguousDuration {
+                operand_ms: 5_000,
+                env_ms: 30_000,
+            },
+            Warning::TranslatedTimeout { timeout_ms: 60_000 },
+            Warning::StoppedAtSignal {
+                signal: 15,
+                killed: true,
+            },
+            Warning::ChildTraced { waited_ms: 2_000 },
+            Warning::IntervalExceedsTimeout {
+                option: Interval::Heartbeat,
+                interval_ms: 600_000,
+                timeout_ms: 60_000,
+            },
+            Warning::IntervalExceedsTimeout {
+                option: Interval::StdinTimeout,
+                interval_ms: 10_000,
+                timeout_ms: 5_000,
+            },
+            Warning::IntervalExceedsTimeout {
+                option: Interval::MemoryCheck,
+                interval_ms: 100,
+                timeout_ms: 50,
+            },
+        ];
+        for w in all {
+            let (a, b) = w.payload();
+            assert_eq!(Warning::from_slot(w.slot(), a, b), Some(w));
+        }
+    }
+
+    #[test]
+    fn test_codes_are_distinct() {
+        let codes = [
+            Warning::ReservedExitCode { code: 0 }.code(),
+            Warning::LowCpuPercent {
+                percent: 0,
+                quantum_ms: 0,
+            }
+            .code(),
+            Warning::RetryCapped {
+                requested: 0,
+                max: 0,
+            }
+            .code(),
+            Warning::HookLimitExceedsTimeout {
+                limit_ms: 0,
+                timeout_ms: 0,
+            }
+            .code(),
+            Warning::MonitorStarved { lag_ms: 0 }.code(),
+            Warning::SignalForwardingUnavailable { errno: 0 }.code(),
+            Warning::TimestampsDisabled { fd: 1 }.code(),
+            Warning::ProcessesEscaped {
+                count: 0,
+                killed: 0,
+            }
+            .code(),
+            Warning::AmbiguousDuration {
+                operand_ms: 0,
+                env_ms: 0,
+            }
+            .code(),
+            Warning::TranslatedTimeout { timeout_ms: 0 }.code(),
+            Warning::StoppedAtSignal {
+                signal: 0,
+                killed: false,
+            }
+            .code(),
+            Warning::ChildTraced { waited_ms: 0 }.code(),
+        ]
+        .into_iter()
+        .chain(INTERVALS.map(|option| {
+            Warning::IntervalExceedsTimeout {
+                option,
+                interval_ms: 0,
+                timeout_ms: 0,
+            }
+            .code()
+        }))
+        .collect::<alloc::vec::Vec<_>>();
+        for (i, a) in codes.iter().enumerate() {
+            for b in &codes[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_hook_limit_message_units() {
+        let w = Warning::HookLimitExceedsTimeout {
+            limit_ms: 60_000,
+            timeout_ms: 1_500,
+        };
+        assert_eq!(
+            alloc::format!("{}", w),
+            "--on-timeout-limit (60s) exceeds main timeout (1500ms)"
+        );
+    }
+
+    #[test]
+    fn test_escaped_message() {
+        let w = |count, killed| alloc::format!("{}", Warning::ProcessesEscaped { count, killed });
+        assert_eq!(
+            w(1, 0),
+            "1 process left the command's process group and outlived it \
+             (see escaped_processes; --kill-escaped cleans up)"
+        );
+        assert!(w(2, 2).ends_with("outlived it; killed (--kill-escaped)"));
+        assert!(w(3, 1).ends_with("; 1 of them killed (--kill-escaped)"));
+    }
+
+    #[test]
+    fn test_interval_message() {
+        let w = Warning::IntervalExceedsTimeout {
+            option: Interval::Heartbeat,
+            interval_ms: 600_000,
+            timeout_ms: 60_000,
+        };
+        assert_eq!(
+            alloc::format!("{}", w),
+            "--heartbeat (600s) exceeds main timeout (60s); no heartbeat will print"
+        );
+    }
+
+    #[test]
+    fn test_default_tag() {
+        assert_eq!(Logger::default().tag(), "timeout");
+        assert_eq!(Logger::default().quiet_level(), 0);
+        /* one logger's settings are its own */
+        let quiet = Logger::new("ci", QUIET_ALL);
+        assert!(quiet.is_quiet());
+        assert_eq!(Logger::default(), Logger::new(DEFAULT_TAG, 0));
+    }
+}
@@ -0,0 +1,518 @@
+/*
+ * lookup.rs
+ *
+ * why a command can't be run, where posix_spawnp would only say ENOENT
+ * or EACCES. before the spawn we walk PATH the way execvp does - each
+ * entry in order, an empty one meaning the current directory, a command
+ * with a '/' taken as given - and look at every candidate: stat for what
+ * is there, lstat for a symlink that points nowhere, faccessat(X_OK) for
+ * the execute bit. the first runnable one is what the spawn will find;
+ * with none, the spawn never happens and the error says what each
+ * candidate was. like execvp, a candidate we may not run beats one that
+ * isn't there: that's 126, not 127.
+ *
+ * the architecture is only looked at once the spawn fails with EBADARCH
+ * or ENOEXEC: Rosetta runs x86_64 on arm64, so a binary built for another
+ * CPU is no reason not to try. then the file's header (Mach-O, thin or
+ * universal, or ELF) says what it was built for. with --arch it's looked
+ * at before the spawn as well: a binary without that slice isn't tried.
+ */
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ffi::CStr;
+use core::fmt;
+
+use crate::errno::errno;
+
+/* execvp's search path when PATH isn't set */
+const DEFAULT_PATH: &[u8] = b"/usr/bin:/bin";
+
+/* enough for a universal binary's header and its table of slices */
+const HEADER_LEN: usize = 512;
+
+/* what our own binary was built for, as the names below have it */
+#[cfg(target_arch = "aarch64")]
+const NATIVE_ARCH: &str = "arm64";
+#[cfg(target_arch = "x86_64")]
+const NATIVE_ARCH: &str = "x86_64";
+#[cfg(not(any(target_arch = "aarch64", target_arch = "x86_64")))]
+const NATIVE_ARCH: &str = "this CPU";
+
+/// What's wrong with a command, or with one place it was looked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Problem {
+    /// Nothing by that name.
+    NotFound,
+    /// A symlink by that name that leads nowhere (or in a loop).
+    DanglingSymlink,
+    /// A directory by that name.
+    IsDirectory,
+    /// A file we have no execute permission on.
+    NotExecutable,
+    /// Behind a directory we may not search.
+    PermissionDenied,
+    /// A binary built for another CPU.
+    WrongArchitecture,
+}
+
+impl Problem {
+    /// Stable name, used as `"error_kind"` in JSON.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::NotFound => "not_found",
+            Self::DanglingSymlink => "dangling_symlink",
+            Self::IsDirectory => "is_directory",
+            Self::NotExecutable => "not_executable",
+            Self::PermissionDenied => "permission_denied",
+            Self::WrongArchitecture => "wrong_architecture",
+        }
+    }
+
+    /// 127 for nothing there to run, 126 for something that can't be.
+    #[must_use]
+    pub const fn exit_code(self) -> u8 {
+        match self {
+            Self::NotFound | Self::DanglingSymlink => crate::error::exit_codes::NOT_FOUND,
+            _ => crate::error::exit_codes::CANNOT_INVOKE,
+        }
+    }
+
+    /* execvp's choice between candidates: higher wins, then PATH order */
+    const fn rank(self) -> u8 {
+        match self {
+            Self::NotFound => 0,
+            Self::DanglingSymlink => 1,
+            _ => 2,
+        }
+    }
+}
+
+/// One place the command was looked for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// The path tried.
+    pub path: String,
+    /// What was wrong with it.
+    pub problem: Problem,
+    /// What execve would have failed with there.
+    pub errno: i32,
+}
+
+/// Why a command can't be run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotRunnable {
+    /// The command as given.
+    pub command: String,
+    /// The problem execvp would report: the worst of the candidates.
+    pub problem: Problem,
+    /// Every place it was looked for, in PATH order; just the command
+    /// when it has a '/'.
+    pub searched: Vec<Candidate>,
+    /// PATH was set, to nothing.
+    pub empty_path: bool,
+    /// With [`Problem::WrongArchitecture`]: the CPUs it was built for.
+    pub built_for: Vec<&'static str>,
+    /// With [`Problem::WrongArchitecture`]: the CPU asked for with
+    /// `--arch`; `None` for ours.
+    pub wanted: Option<&'static str>,
+}
+
+impl NotRunnable {
+    /// The candidate the problem is about; `None` when nothing was found.
+    #[must_use]
+    pub fn path(&self) -> Option<&str> {
+        if self.problem == Problem::NotFound {
+            return None;
+        }
+        self.searched
+            .iter()
+            .find(|c| c.problem == self.problem)
+            .map(|c| c.path.as_str())
+    }
+}
+
+impl fmt::Display for NotRunnable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self.path().unwrap_or(&self.command);
+        match self.problem {
+            Problem::NotFound if self.command.contains('/') => {
+                write!(f, "command not found: {}", self.command)
+            }
+            Problem::NotFound => {
+                write!(f, "command not found: {} (", self.command)?;
+                if self.empty_path {
+                    f.write_str("PATH is empty; ")?;
+                }
+                f.write_str("searched ")?;
+                for (i, candidate) in self.searched.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    let dir = candidate.path.rsplit_once('/').map_or(".", |(dir, _)| dir);
+                    f.write_str(if dir.is_empty() { "/" } else { dir })?;
+                    /* a missing file is what "not found" means; anything
+                     * else about the entry is worth knowing */
+                    if candidate.errno != libc::ENOENT {
+                        write!(f, " [errno {}]", candidate.errno)?;
+                    }
+                }
+                f.write_str(")")
+            }
+            Problem::DanglingSymlink => {
+                write!(f, "command not found: {path} is a symlink to nothing")
+            }
+            Problem::IsDirectory => write!(f, "permission denied: {path} is a directory"),
+            Problem::NotExecutable => write!(f, "permission denied: {path} is not executable"),
+            Problem::PermissionDenied => {
+                write!(
+                    f,
+                    "permission denied: {path} is in a directory we can't search"
+                )
+            }
+            Problem::WrongArchitecture => {
+                let wanted = self.wanted.unwrap_or(NATIVE_ARCH);
+                /* the slice is there; this machine can't run it (Rosetta
+                 * missing, say) */
+                if self.built_for.contains(&wanted) {
+                    return write!(f, "can't run {path} as {wanted} on this machine");
+                }
+                write!(f, "can't run {path}: built for ")?;
+                for (i, arch) in self.built_for.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str("/")?;
+                    }
+                    f.write_str(arch)?;
+                }
+                write!(f, ", not {wanted}")
+            }
+        }
+    }
+}
+
+/* what execve would make of path: Ok if it would try to run it */
+fn look(path: &[u8]) -> Result<(), (Problem, i32)> {
+    let c_path = crate::io::c_path(&[path]).map_err(|e| (Problem::NotFound, e))?;
+    // SAFETY: zeroed stat struct is valid for stat to fill in
+    let mut st: libc::stat = unsafe { core::mem::zeroed() };
+    // SAFETY: c_path is NUL-terminated, st is a valid stat buffer
+    if unsafe { libc::stat(c_path.as_ptr().cast(), &raw mut st) } != 0 {
+        let e = errno();
+        return Err(match e {
+            libc::ELOOP => (Problem::DanglingSymlink, e),
+            libc::EACCES => (Problem::PermissionDenied, e),
+            libc::ENOENT => {
+                /* nothing there, or a link to nothing */
+                // SAFETY: as for stat above
+                let linked = unsafe { libc::lstat(c_path.as_ptr().cast(), &raw mut st) } == 0;
+                let problem = if linked {
+                    Problem::DanglingSymlink
+                } else {
+                    Problem::NotFound
+                };
+                (problem, e)
+            }
+            _ => (Problem::NotFound, e),
+        });
+    }
+    if st.st_mode & libc::S_IFMT == libc::S_IFDIR {
+        return Err((Problem::IsDirectory, libc::EACCES));
+    }
+    /* the effective ids are the ones execve checks */
+    // SAFETY: c_path is NUL-terminated
+    let runnable = unsafe {
+        libc::faccessat(
+            libc::AT_FDCWD,
+            c_path.as_ptr().cast(),
+            libc::X_OK,
+            libc::AT_EACCESS,
+        )
+    } == 0;
+    if runnable {
+        Ok(())
+    } else {
+        Err((Problem::NotExecutable, errno()))
+    }
+}
+
+/* PATH as execvp reads it: None when unset */
+fn path_var() -> Option<&'static [u8]> {
+    // SAFETY: the name is NUL-terminated; getenv returns NULL or a
+    // NUL-terminated string that lives as long as the environment, which
+    // we don't change while this is in use
+    let ptr = unsafe { libc::getenv(c"PATH".as_ptr()) };
+    // SAFETY: non-null, from getenv
+    (!ptr.is_null()).then(|| unsafe { CStr::from_ptr(ptr) }.to_bytes())
+}
+
+/// Find `command` as the spawn will: the path it will run, or why nothing
+/// can be.
+pub fn resolve(command: &[u8]) -> Result<String, NotRunnable> {
+    let path_var = path_var();
+    let mut searched: Vec<Candidate> = Vec::new();
+    let mut candidate = |path: Vec<u8>| -> Option<String> {
+        let path_str = String::from_utf8_lossy(&path).into_owned();
+        match look(&path) {
+            Ok(()) => Some(path_str),
+            Err((problem, errno)) => {
+                searched.push(Candidate {
+                    path: path_str,
+                    problem,
+                    errno,
+                });
+                None
+            }
+        }
+    };
+
+    let found = if command.contains(&b'/') {
+        candidate(command.to_vec())
+    } else if command.is_empty() {
+        None
+    } else {
+        path_var
+            .unwrap_or(DEFAULT_PATH)
+            .split(|&b| b == b':')
+            .find_map(|dir| {
+                let mut path = Vec::with_capacity(dir.len() + 1 + command.len());
+                if !dir.is_empty() {
+                    path.extend_from_slice(dir);
+                    path.push(b'/');
+                }
+                path.extend_from_slice(command);
+                candidate(path)
+            })
+    };
+    if let Some(path) = found {
+        return Ok(path);
+    }
+
+    /* the first of the highest rank, as execvp's errno would be */
+    let problem = searched
+        .iter()
+        .map(|c| c.problem)
+        .reduce(|worst, p| if p.rank() > worst.rank() { p } else { worst })
+        .unwrap_or(Problem::NotFound);
+    Err(NotRunnable {
+        command: String::from_utf8_lossy(command).into_owned(),
+        problem,
+        searched,
+        empty_path: path_var.is_some_and(<[u8]>::is_empty),
+        built_for: Vec::new(),
+        wanted: None,
+    })
+}
+
+/// After the spawn of `path` (found by [`resolve`]) failed with EBADARCH
+/// or ENOEXEC: if its header names the CPUs it was built for and the one
+/// `wanted` (ours, without `--arch`) isn't one, say so. Another CPU that
+/// is one was asked for and still couldn't run: that's said too.
+#[must_use]
+pub fn check_architecture(
+    command: &[u8],
+    path: &str,
+    wanted: Option<&'static str>,
+) -> Option<NotRunnable> {
+    let header = read_header(path.as_bytes())?;
+    let built_for = built_for(&header);
+    let runs = built_for.contains(&wanted.unwrap_or(NATIVE_ARCH));
+    if built_for.is_empty() || (runs && wanted.is_none_or(|w| w == NATIVE_ARCH)) {
+        return None;
+    }
+    Some(NotRunnable {
+        command: String::from_utf8_lossy(command).into_owned(),
+        problem: Problem::WrongArchitecture,
+        searched: alloc::vec![Candidate {
+            path: String::from(path),
+            problem: Problem::WrongArchitecture,
+            errno: libc::ENOEXEC,
+        }],
+        empty_path: false,
+        built_for,
+        wanted,
+    })
+}
+
+/* the first HEADER_LEN bytes of path, or fewer if that's all there is */
+fn read_header(path: &[u8]) -> Option<[u8; HEADER_LEN]> {
+    let c_path = crate::io::c_path(&[path]).ok()?;
+    // SAFETY: c_path is NUL-terminated
+    let fd = unsafe { libc::open(c_path.as_ptr().cast(), libc::O_RDONLY | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return None;
+    }
+    let mut header = [0u8; HEADER_LEN];
+    // SAFETY: fd is open; header is valid for HEADER_LEN bytes
+    let n = unsafe { libc::read(fd, header.as_mut_ptr().cast(), HEADER_LEN) };
+    // SAFETY: fd was opened above and is closed exactly once here
+    unsafe { libc::close(fd) };
+    (n > 0).then_some(header)
+}
+
+/* Mach-O cputype -> name */
+const fn macho_arch(cputype: u32) -> &'static str {
+    match cputype {
+        7 => "i386",
+        0x0100_0007 => "x86_64",
+        12 => "arm",
+        0x0100_000c => "arm64",
+        0x0200_000c => "arm64_32",
+        18 => "ppc",
+        0x0100_0012 => "ppc64",
+        _ => "unknown",
+    }
+}
+
+/* ELF e_machine -> name, as Mach-O would call it */
+const fn elf_arch(machine: u16) -> &'static str {
+    match machine {
+        3 => "i386",
+        62 => "x86_64",
+        40 => "arm",
+        183 => "arm64",
+        20 => "ppc",
+        21 => "ppc64",
+        243 => "riscv",
+        _ => "unknown",
+    }
+}
+
+/// The CPUs a binary was built for, from the start of the file: one for
+/// thin Mach-O and ELF, each slice of a universal binary. Empty for
+/// anything else (a script, say).
+#[must_use]
+pub fn built_for(header: &[u8]) -> Vec<&'static str> {
+    let u32_at = |at: usize, be: bool| -> Option<u32> {
+        let bytes: [u8; 4] = header.get(at..at + 4)?.try_into().ok()?;
+        Some(if be {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+    let mut archs = Vec::new();
+    match header.get(..4) {
+        /* thin Mach-O, 32 or 64 bit, either byte order */
+        Some([0xce | 0xcf, 0xfa, 0xed, 0xfe]) => archs.extend(u32_at(4, false).map(macho_arch)),
+        Some([0xfe, 0xed, 0xfa, 0xce | 0xcf]) => archs.extend(u32_at(4, true).map(macho_arch)),
+        /* universal: a table of slices, 20 bytes each (32 in fat64). a
+         * Java class file starts the same way, with a count in the
+         * thousands */
+        Some([0xca, 0xfe, 0xba, magic @ (0xbe | 0xbf)]) => {
+            let entry = if *magic == 0xbe { 20 } else { 32 };
+            let count = u32_at(4, true).unwrap_or(0);
+            if count <= 32 {
+                for i in 0..count as usize {
+                    match u32_at(8 + i * entry, true) {
+                        Some(cputype) => archs.push(macho_arch(cputype)),
+                        None => break,
+                    }
+                }
+            }
+        }
+        Some([0x7f, b'E', b'L', b'F']) => {
+            let be = header.get(5) == Some(&2);
+            if let Some(&[a, b]) = header.get(18..20) {
+                let machine = if be {
+                    u16::from_be_bytes([a, b])
+                } else {
+                    u16::from_le_bytes([a, b])
+                };
+                archs.push(elf_arch(machine));
+            }
+        }
+        _ => {}
+    }
+    archs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_built_for() {
+        assert!(built_for(b"#!/bin/sh\necho hi\n").is_empty());
+        assert!(built_for(b"").is_empty());
+
+        let mut thin = [0u8; 32];
+        thin[..4].copy_from_slice(&[0xcf, 0xfa, 0xed, 0xfe]);
+        thin[4..8].copy_from_slice(&0x0100_0007u32.to_le_bytes());
+        assert_eq!(built_for(&thin), ["x86_64"]);
+
+        let mut fat = [0u8; 48];
+        fat[..4].copy_from_slice(&[0xca, 0xfe, 0xba, 0xbe]);
+        fat[4..8].copy_from_slice(&2u32.to_be_bytes());
+        fat[8..12].copy_from_slice(&0x0100_0007u32.to_be_bytes());
+        fat[28..32].copy_from_slice(&0x0100_000cu32.to_be_bytes());
+        assert_eq!(built_for(&fat), ["x86_64", "arm64"]);
+
+        /* a class file: version 52 where the slice count would be */
+        fat[4..8].copy_from_slice(&52u32.to_be_bytes());
+        assert!(built_for(&fat).is_empty());
+
+        let mut elf = [0u8; 64];
+        elf[..4].copy_from_slice(b"\x7fELF");
+        elf[5] = 1;
+        elf[18..20].copy_from_slice(&183u16.to_le_bytes());
+        assert_eq!(built_for(&elf), ["arm64"]);
+    }
+
+    #[test]
+    fn test_not_found_message() {
+        let candidate = |path: &str, errno| Candidate {
+            path: String::from(path),
+            problem: Problem::NotFound,
+            errno,
+        };
+        let err = NotRunnable {
+            command: String::from("frob"),
+            problem: Problem::NotFound,
+            searched: alloc::vec![
+                candidate("/usr/bin/frob", libc::ENOENT),
+                candidate("/etc/passwd/frob", libc::ENOTDIR),
+                candidate("frob", libc::ENOENT),
+            ],
+            empty_path: false,
+            built_for: Vec::new(),
+            wanted: None,
+        };
+        assert_eq!(
+            alloc::format!("{err}"),
+            alloc::format!(
+                "command not found: frob (searched /usr/bin, /etc/passwd [errno {}], .)",
+                libc::ENOTDIR
+            )
+        );
+        assert_eq!(err.path(), None);
+        assert_eq!(err.problem.exit_code(), 127);
+    }
+
+    #[test]
+    fn test_wrong_architecture_message() {
+        let mut err = NotRunnable {
+            command: String::from("tool"),
+            problem: Problem::WrongArchitecture,
+            searched: alloc::vec![Candidate {
+                path: String::from("/opt/bin/tool"),
+                problem: Problem::WrongArchitecture,
+                errno: libc::ENOEXEC,
+            }],
+            empty_path: false,
+            built_for: alloc::vec!["arm64"],
+            wanted: Some("x86_64"),
+        };
+        assert_eq!(
+            alloc::format!("{err}"),
+            "can't run /opt/bin/tool: built for arm64, not x86_64"
+        );
+        err.built_for.push("x86_64");
+        assert_eq!(
+            alloc::format!("{err}"),
+            "can't run /opt/bin/tool as x86_64 on this machine"
+        );
+        assert_eq!(err.problem.exit_code(), 126);
+    }
+}
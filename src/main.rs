@@ -2,32 +2,54 @@
  * main.rs
  *
  * Parse args, call runner, format output. Boring on purpose.
- * The interesting stuff is in runner.rs.
+ * The interesting stuff is in runner/.
  *
  * --json is for CI. Format is stable, don't change field names.
  *
  * Dual binary support:
  * - "procguard": wall-clock default (survives sleep)
  * - "timeout": GNU-compatible active-time default (pauses on sleep)
- * Detection is via argv[0] - if invoked as "timeout", defaults to --confine active.
+ * - "gtimeout": strictly GNU - its options and messages only, active time
+ * Detection is via argv[0] (see Personality).
  */
 
 #![cfg_attr(not(any(debug_assertions, test, doc)), no_std)]
 #![cfg_attr(not(any(debug_assertions, test, doc)), no_main)]
 
+use alloc::collections::VecDeque;
 use alloc::string::{String, ToString};
-use alloc::vec;
 use alloc::vec::Vec;
 use core::fmt::Write as FmtWrite;
+use core::time::Duration;
 
-use procguard::args::{Confine, OwnedArgs, parse_args};
-use procguard::duration::parse_duration;
-use procguard::error::exit_codes;
+use procguard::args::{
+    Confine, DurationFrom, HookBudget, LaunchdMode, OwnedArgs, Personality, parse_args_as,
+};
+use procguard::duration::{format_duration, is_no_timeout, parse_duration};
+use procguard::error::{TimeoutError, exit_codes};
+use procguard::events::{EventKind, EventLog, MonitorLag};
+use procguard::failsafe;
+use procguard::io::StackBuf;
+use procguard::json::JsonWriter;
+use procguard::log::Warning;
+use procguard::lookup::NotRunnable;
+use procguard::outcome::Outcome;
+use procguard::proc_info::Snapshot;
+use procguard::process::Arch;
+use procguard::repeat::{Flakiness, Schedule, StopOn};
+use procguard::run_id::{self, RunId};
 use procguard::runner::{
-    AttemptResult, RunConfig, RunResult, run_with_retry, setup_signal_forwarding,
+    AttemptResult, HookResult, RunConfig, RunResult, TimeoutReason, run_crash_hook,
+    run_with_retry_bytes, setup_signal_forwarding, stop_requested, take_unforwarded_stop,
+};
+use procguard::safe_file::{self, FileOptions};
+use procguard::signal::Signal;
+use procguard::stats::RunStats;
+use procguard::wait::{
+    Cancel, FileCondition, PidWait, QueueWait, lock_file, lock_file_queued, wait_for_path,
+    wait_for_pid, wait_for_window,
 };
-use procguard::wait::wait_for_file;
-use procguard::{eprintln, println};
+use procguard::{eprint, eprintln, log_error, log_info};
 
 /* import alloc crate in no_std mode */
 #[cfg(not(any(debug_assertions, test, doc)))]
@@ -37,18 +59,23 @@ extern crate alloc;
 #[cfg(any(debug_assertions, test, doc))]
 use std as alloc;
 
-/* mach_continuous_time for elapsed timing - same as runner.rs */
+/* mach_continuous_time for elapsed timing - same as runner/clock.rs */
+#[cfg(target_os = "macos")]
 #[repr(C)]
 struct MachTimebaseInfo {
     numer: u32,
     denom: u32,
 }
 
+#[cfg(target_os = "macos")]
 unsafe extern "C" {
     fn mach_continuous_time() -> u64;
     fn mach_timebase_info(info: *mut MachTimebaseInfo) -> i32;
 }
 
+#[cfg(target_os = "linux")]
+use procguard::linux::{MachTimebaseInfo, mach_continuous_time, mach_timebase_info};
+
 use core::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
 
 /* cached timebase (packed as numer << 32 | denom, 0 = not initialized) */
@@ -97,55 +124,58 @@ fn precise_now_ns() -> Option<u64> {
     Some(result as u64)
 }
 
-/// Resolve duration/command from args and TIMEOUT env var.
-///
-/// When TIMEOUT env is set, the user may omit the duration from CLI.
-/// Clap still parses positional args left-to-right, so "echo hello"
-/// becomes duration="echo", command="hello". We detect this by checking
-/// if the parsed "duration" is actually valid. If not, and TIMEOUT env
-/// is set, we shift: env becomes duration, the parsed "duration" becomes command.
+/*
+ * which operand is the duration, if any, and which are the command. in
+ * order of precedence:
+ *
+ * 1. -t/--duration: it's the duration, every operand is the command and
+ *    TIMEOUT isn't looked at
+ * 2. no operand in the duration's place (none given, or -- first): TIMEOUT,
+ *    which parse_args has already put there
+ * 3. TIMEOUT set, the first operand a duration with a command after it:
+ *    the operand wins, with a warning - both were meant as durations, and
+ *    one of them wasn't meant. -t or -- says which
+ * 4. TIMEOUT set, the first operand not a duration (or alone, for a mode
+ *    that needs a command): TIMEOUT, and every operand is the command
+ * 5. no TIMEOUT: the first operand, whatever it is
+ *
+ * returns the duration and the command's argv, as raw bytes straight from
+ * our own argv (empty = no command).
+ */
 #[inline]
-fn resolve_args(
-    args: &OwnedArgs,
+fn resolve_args<'a>(
+    args: &'a OwnedArgs,
     timeout_env: Option<&str>,
-) -> (Option<String>, Option<String>, Vec<String>) {
-    match (&args.duration, &args.command, timeout_env) {
-        /* Both duration and command provided on CLI, AND env var set - need disambiguation */
-        (Some(dur), Some(cmd), Some(env_dur)) => {
-            /* Check if this is actually an env fallback case:
-             * If TIMEOUT env is set and the "duration" doesn't parse,
-             * then user intended: TIMEOUT=dur cmd arg1 arg2
-             * Clap saw: duration=cmd, command=arg1, args=[arg2...]
-             */
-            if parse_duration(dur).is_err() {
-                /* Shift: env=duration, dur=command, cmd+args=args */
-                let mut new_args = vec![cmd.clone()];
-                new_args.extend(args.args.iter().cloned());
-                (Some(env_dur.to_string()), Some(dur.clone()), new_args)
-            } else {
-                /* Both CLI duration and TIMEOUT env are valid - warn about ambiguity */
-                eprintln!(
-                    "warning: TIMEOUT env var set but '{}' also looks like a valid duration; \
-                     using CLI argument (use -- separator to disambiguate)",
-                    dur
+    events: &mut EventLog,
+) -> (Option<String>, &'a [Vec<u8>]) {
+    /* operands are [duration, command, args...] as given on the CLI */
+    let after_duration = args.operands.get(1..).unwrap_or_default();
+    /* --sleep and --wait-pid run none: a lone operand is their duration */
+    let needs_command = !args.sleep && args.wait_pid.is_none();
+    match (&args.duration, args.duration_from, timeout_env) {
+        (dur, DurationFrom::Option | DurationFrom::Env, _) => (dur.clone(), &args.operands),
+        (Some(dur), DurationFrom::Operand, Some(env_dur)) => {
+            let operand = parse_duration(dur);
+            if operand.is_err() || (needs_command && args.command.is_none()) {
+                /* TIMEOUT=5s timeout make -j8: every operand is the command */
+                return (Some(env_dur.to_string()), &args.operands);
+            }
+            if args.command.is_some()
+                && let (Ok(operand), Ok(env)) = (operand, parse_duration(env_dur))
+            {
+                let ms = |d: Duration| u64::try_from(d.as_millis()).unwrap_or(u64::MAX);
+                events.warn(
+                    args.log(),
+                    Warning::AmbiguousDuration {
+                        operand_ms: ms(operand),
+                        env_ms: ms(env),
+                    },
                 );
-                (Some(dur.clone()), Some(cmd.clone()), args.args.clone())
             }
+            (Some(dur.clone()), after_duration)
         }
-        /* Both provided on CLI, no env - fast path, no parse_duration check needed */
-        (Some(dur), Some(cmd), None) => (Some(dur.clone()), Some(cmd.clone()), args.args.clone()),
-        /* Only one positional: duration from env, first positional is command */
-        (Some(first_pos), None, Some(env_dur)) => (
-            Some(env_dur.to_string()),
-            Some(first_pos.clone()),
-            args.args.clone(),
-        ),
-        /* Only duration provided, no command */
-        (Some(dur), None, None) => (Some(dur.clone()), None, args.args.clone()),
-        /* No positionals, but env set */
-        (None, _, Some(env_dur)) => (Some(env_dur.to_string()), None, args.args.clone()),
-        /* Nothing provided */
-        (None, _, None) => (None, None, args.args.clone()),
+        (Some(dur), DurationFrom::Operand, None) => (Some(dur.clone()), after_duration),
+        (None, _, _) => (None, &[]),
     }
 }
 
@@ -164,66 +194,223 @@ fn main() {
 
 /* shared implementation */
 fn run_main() -> u8 {
-    let mut args = match parse_args() {
+    #[cfg(feature = "alloc-stats")]
+    let mut allocs = AllocReport(procguard::AllocStats::now(), String::new());
+
+    /* argv[0] detection: "timeout" defaults to --confine active (GNU
+     * behavior), "gtimeout" is GNU timeout and nothing more */
+    let personality = Personality::detect();
+    let gnu = personality == Personality::Gnu;
+    let prog_name = personality.name();
+
+    let mut args = match parse_args_as(personality) {
         Ok(args) => args,
         Err(e) => {
-            eprintln!("procguard: {}", e);
+            eprintln!("{}: {}", prog_name, e);
+            if gnu {
+                try_help(prog_name);
+            }
             return exit_codes::INTERNAL_ERROR;
         }
     };
 
-    /* argv[0] detection: when invoked as "timeout", default to --confine active (GNU behavior) */
-    let is_timeout_alias = procguard::args::get_argv0()
-        .map(|s| {
-            /* extract basename (e.g., "/usr/local/bin/timeout" -> "timeout") */
-            s.rsplit('/').next().unwrap_or(&s) == "timeout"
-        })
-        .unwrap_or(false);
-    if is_timeout_alias && !args.confine_specified {
+    /* --sleep is for the time that passes: wall unless asked otherwise */
+    if personality != Personality::Procguard && !args.confine_specified && !args.sleep {
         args.confine = Confine::Active;
     }
+    #[cfg(feature = "alloc-stats")]
+    {
+        allocs.1 = args.log().tag().into();
+    }
+
+    /* this run's timeline, warnings and id, from the first warning on:
+     * every JSON object carries the id */
+    let mut events = EventLog::new();
+    events.set_run_id(RunId::new(args.run_id.clone()));
 
-    /* binary name for error messages */
-    let prog_name = if is_timeout_alias {
-        "timeout"
+    let timeout_env = if gnu {
+        None
     } else {
-        "procguard"
+        procguard::args::get_timeout_env(b"TIMEOUT\0")
     };
+    let (duration_str, command_argv) = resolve_args(&args, timeout_env.as_deref(), &mut events);
 
-    let timeout_env = procguard::args::get_env(b"TIMEOUT\0");
-    let (duration_str, command, extra_args) = resolve_args(&args, timeout_env.as_deref());
-
-    let (duration_str, command) = match (duration_str, command) {
-        (Some(d), Some(c)) => (d, c),
-        (None, _) => {
-            if !args.quiet {
-                eprintln!(
-                    "{}: missing duration (provide as argument or set TIMEOUT env var)",
-                    prog_name
+    if args.sleep {
+        return match (duration_str, command_argv) {
+            (Some(d), []) => sleep(&args, &d),
+            (None, _) => {
+                log_error!(
+                    args.log(),
+                    "missing duration (provide as argument or set TIMEOUT env var)"
                 );
+                exit_codes::INTERNAL_ERROR
             }
+            (Some(_), _) => {
+                log_error!(args.log(), "--sleep runs no command");
+                exit_codes::INTERNAL_ERROR
+            }
+        };
+    }
+    if let Some(pid) = args.wait_pid {
+        return match (duration_str, command_argv) {
+            (Some(d), []) => wait_pid(&args, pid, &d, &mut events),
+            (None, _) => {
+                log_error!(
+                    args.log(),
+                    "missing duration (provide as argument or set TIMEOUT env var)"
+                );
+                exit_codes::INTERNAL_ERROR
+            }
+            (Some(_), _) => {
+                log_error!(args.log(), "--wait-pid runs no command");
+                exit_codes::INTERNAL_ERROR
+            }
+        };
+    }
+
+    let (duration_str, command, extra_args) = match (duration_str, command_argv.split_first()) {
+        (Some(d), Some((c, rest))) => (d, c, rest),
+        /* GNU says the same for either, and only that */
+        _ if gnu => {
+            try_help(prog_name);
+            return exit_codes::INTERNAL_ERROR;
+        }
+        (None, _) => {
+            log_error!(
+                args.log(),
+                "missing duration (provide as argument or set TIMEOUT env var)"
+            );
             return exit_codes::INTERNAL_ERROR;
         }
         (Some(_), None) => {
-            if !args.quiet {
-                eprintln!("{}: missing command", prog_name);
-            }
+            log_error!(args.log(), "missing command");
             return exit_codes::INTERNAL_ERROR;
         }
     };
 
     let config = match RunConfig::from_args(&args, &duration_str) {
         Ok(config) => config,
+        Err(e) if gnu => {
+            gnu_config_error(&e, &args, &duration_str);
+            return e.exit_code();
+        }
         Err(e) => {
-            if !args.quiet {
-                eprintln!("{}: {}", prog_name, e);
-            }
+            log_error!(args.log(), "{}", e);
             return e.exit_code();
         }
     };
+    /* on stderr and in the JSON */
+    for warning in config.warnings() {
+        events.warn(args.log(), warning);
+    }
+
+    /* like the RunConfig options, refused rather than silently ignored */
+    if args.crash_report && !cfg!(feature = "crash-report") {
+        log_error!(
+            args.log(),
+            "--crash-report is not available: built without the 'crash-report' feature"
+        );
+        return exit_codes::INTERNAL_ERROR;
+    }
+
+    /* check --json-fd now, not after a two-hour run */
+    if let Some(fd) = args.json_fd
+        && let Err(errno) = procguard::io::prepare_output_fd(fd)
+    {
+        log_error!(
+            args.log(),
+            "--json-fd {}: not writable (errno {})",
+            fd,
+            errno
+        );
+        return exit_codes::INTERNAL_ERROR;
+    }
+
+    /* --every/--for: a typo fails now, before any waiting */
+    let schedule = match (args.every.as_deref(), args.repeat_for.as_deref()) {
+        (Some(every), Some(budget)) => {
+            let every = match parse_duration(every) {
+                Ok(d) => d,
+                Err(e) => {
+                    log_error!(args.log(), "invalid --every: {}", e);
+                    return exit_codes::INTERNAL_ERROR;
+                }
+            };
+            let budget = match parse_duration(budget) {
+                Ok(d) => d,
+                Err(e) => {
+                    log_error!(args.log(), "invalid --for: {}", e);
+                    return exit_codes::INTERNAL_ERROR;
+                }
+            };
+            let Some(schedule) = Schedule::new(every, budget) else {
+                log_error!(args.log(), "invalid --every: must be greater than zero");
+                return exit_codes::INTERNAL_ERROR;
+            };
+            Some(schedule)
+        }
+        _ => None,
+    };
+
+    /*
+     * signal forwarding from here until we return: a stop signal during
+     * the waits below ends them (and the run, which never starts); one
+     * during the run goes to the command; one after it waits until the
+     * result is written whole, then we die of it (deliver_stop)
+     */
+    let _forwarding = setup_signal_forwarding().map_err(|w| events.warn(args.log(), w));
 
-    /* Wait for file if --wait-for-file is set (before starting command) */
-    if let Some(ref path) = args.wait_for_file {
+    /* --only-between: first, so nothing below (a lock above all) is held
+     * while waiting for the window */
+    let mut window_wait_ms = None;
+    if let Some(window) = args.only_between {
+        let max_wait = match args.window_wait.as_deref().map(parse_duration) {
+            Some(Ok(d)) => d,
+            Some(Err(e)) => {
+                log_error!(args.log(), "invalid --window-wait: {}", e);
+                return exit_codes::INTERNAL_ERROR;
+            }
+            None => Duration::ZERO,
+        };
+        let waiting = args.verbose && !max_wait.is_zero() && !window.is_open();
+        if waiting {
+            log_info!(
+                args.log(),
+                "waiting for time window {} (up to {})",
+                window,
+                format_duration(max_wait)
+            );
+        }
+        match wait_for_window(window, max_wait, Cancel::Flag(stop_requested())) {
+            Ok(Some(waited)) => {
+                window_wait_ms = Some(u64::try_from(waited.as_millis()).unwrap_or(u64::MAX));
+                if waiting {
+                    log_info!(args.log(), "time window {} open, starting command", window);
+                }
+            }
+            Ok(None) => return interrupted(&args, command_argv, &events),
+            Err(e) => {
+                if !args.json {
+                    log_error!(args.log(), "{}", e);
+                }
+                emit_json(&args, || json_error(&e, 0, command_argv, &events));
+                return e.exit_code();
+            }
+        }
+    }
+
+    /* Wait for file if --wait-for-file(-gone) is set (before starting command) */
+    let wait = match (&args.wait_for_file, &args.wait_for_file_gone) {
+        (Some(path), _) => Some((
+            path,
+            args.wait_for_file_contains
+                .as_deref()
+                .map_or(FileCondition::Exists, FileCondition::Contains),
+        )),
+        (None, Some(path)) => Some((path, FileCondition::Gone)),
+        (None, None) => None,
+    };
+    if let Some((path, condition)) = wait {
         let wait_timeout = args
             .wait_for_file_timeout
             .as_ref()
@@ -233,68 +420,200 @@ fn run_main() -> u8 {
         let wait_timeout = match wait_timeout {
             Ok(t) => t,
             Err(e) => {
-                if !args.quiet {
-                    eprintln!("{}: invalid --wait-for-file-timeout: {}", prog_name, e);
-                }
+                log_error!(args.log(), "invalid --wait-for-file-timeout: {}", e);
                 return exit_codes::INTERNAL_ERROR;
             }
         };
 
-        if args.verbose && !args.quiet {
+        if args.verbose {
+            let what = match condition {
+                FileCondition::Gone => "to go away",
+                FileCondition::Contains(_) => "to contain the marker",
+                _ => "to appear",
+            };
             match wait_timeout {
                 Some(d) => {
                     let secs = d.as_secs();
                     let tenths = d.subsec_millis() / 100;
-                    eprintln!(
-                        "{}: waiting for file '{}' (timeout: {}.{}s)",
-                        prog_name, path, secs, tenths
+                    log_info!(
+                        args.log(),
+                        "waiting for file '{}' {} (timeout: {}.{}s)",
+                        path,
+                        what,
+                        secs,
+                        tenths
                     );
                 }
-                None => eprintln!("{}: waiting for file '{}' (no timeout)", prog_name, path),
+                None => log_info!(
+                    args.log(),
+                    "waiting for file '{}' {} (no timeout)",
+                    path,
+                    what
+                ),
             }
         }
 
-        if let Err(e) = wait_for_file(path, wait_timeout, config.confine) {
-            if args.json {
-                print_json_error(&e, 0);
-            } else if !args.quiet {
-                eprintln!("{}: {}", prog_name, e);
+        let cancel = Cancel::Flag(stop_requested());
+        let found = match wait_for_path(path, condition, wait_timeout, config.confine, cancel) {
+            Ok(Some(found)) => found,
+            Ok(None) => return interrupted(&args, command_argv, &events),
+            Err(e) => {
+                if !args.json {
+                    log_error!(args.log(), "{}", e);
+                }
+                emit_json(&args, || json_error(&e, 0, command_argv, &events));
+                return e.exit_code();
             }
-            return e.exit_code();
-        }
+        };
 
-        if args.verbose && !args.quiet {
-            eprintln!("{}: file '{}' found, starting command", prog_name, path);
+        if args.verbose {
+            match condition {
+                FileCondition::Gone => {
+                    log_info!(args.log(), "file '{}' gone, starting command", found)
+                }
+                _ => log_info!(args.log(), "file '{}' found, starting command", found),
+            }
         }
     }
 
-    /* Set up signal forwarding before spawning child */
-    let _ = setup_signal_forwarding();
+    /* --flock: one instance at a time. held until we return, past the
+     * result; every other way out (signals, exit) closes it too */
+    let mut lock_wait_ms = None;
+    let mut lock_queue: Option<QueueWait> = None;
+    let _lock = match &args.flock {
+        Some(path) => {
+            let flock_timeout = match args.flock_timeout.as_deref().map(parse_duration) {
+                Some(Ok(d)) => d,
+                Some(Err(e)) => {
+                    log_error!(args.log(), "invalid --flock-timeout: {}", e);
+                    return exit_codes::INTERNAL_ERROR;
+                }
+                None => Duration::ZERO,
+            };
+            /* --flock-queue: "" waits as long as it takes */
+            let max_queue = match args.flock_queue.as_deref() {
+                Some("") | None => None,
+                Some(s) => match parse_duration(s) {
+                    Ok(d) => Some(d),
+                    Err(e) => {
+                        log_error!(args.log(), "invalid --flock-queue: {}", e);
+                        return exit_codes::INTERNAL_ERROR;
+                    }
+                },
+            };
+            if args.verbose {
+                log_info!(args.log(), "locking '{}'", path);
+            }
+            let wait_start = precise_now_ns().unwrap_or(0);
+            let cancel = Cancel::Flag(stop_requested());
+            let locked = if args.flock_queue.is_some() {
+                lock_file_queued(path, max_queue, config.confine, cancel).map(|queued| {
+                    queued.map(|(lock, wait)| {
+                        lock_queue = Some(wait);
+                        lock
+                    })
+                })
+            } else {
+                lock_file(path, Some(flock_timeout), config.confine, cancel)
+            };
+            match locked {
+                Ok(None) => return interrupted(&args, command_argv, &events),
+                Ok(lock) => {
+                    let waited = precise_now_ns()
+                        .unwrap_or(wait_start)
+                        .saturating_sub(wait_start);
+                    lock_wait_ms = Some(waited / 1_000_000);
+                    lock
+                }
+                Err(e) => {
+                    if !args.json {
+                        log_error!(args.log(), "{}", e);
+                    }
+                    emit_json(&args, || json_error(&e, 0, command_argv, &events));
+                    return e.exit_code();
+                }
+            }
+        }
+        None => None,
+    };
 
+    let waits = Waits {
+        window_ms: window_wait_ms,
+        lock_ms: lock_wait_ms,
+        lock_queue,
+    };
     let start_ns = precise_now_ns().unwrap_or(0);
-    let result = run_with_retry(&command, &extra_args, &config);
+    if schedule.is_some() || args.runs.is_some() || args.detect_flaky.is_some() {
+        let code = run_repeat(
+            &args,
+            &config,
+            schedule,
+            command,
+            extra_args,
+            command_argv,
+            &waits,
+            &mut events,
+        );
+        deliver_stop();
+        return code;
+    }
+    /* from here on, whatever wedges, we exit; see failsafe.rs */
+    if let Some(bound) = config.lifetime_bound() {
+        failsafe::arm(bound.saturating_add(failsafe::SLACK), args.log());
+    }
+    let result = run_with_retry_bytes(command, extra_args, &config, &mut events);
+    failsafe::disarm();
     let elapsed_ms = precise_now_ns()
         .unwrap_or(start_ns)
         .saturating_sub(start_ns)
         / 1_000_000;
 
-    match result {
+    /* before --explain, so the timeline has the crash hook in it */
+    let (crash_report, crash_hook) = match &result {
+        Ok((run_result, _)) => after_crash(&args, &config, run_result, &mut events),
+        Err(_) => (None, None),
+    };
+
+    /* --explain: timeline goes to stderr, independent of --quiet/--json */
+    if args.explain {
+        print_explain(&events, &config);
+    }
+
+    let code = match result {
         Ok((run_result, attempts)) => {
-            let exit_code = run_result.exit_code(args.preserve_status, config.timeout_exit_code);
+            let outcome = Outcome::from(&run_result);
+            let exit_code = if gnu && !args.preserve_status && gnu_killed(&run_result) {
+                128 + Signal::SIGKILL as u8
+            } else {
+                launchd_exit_code(
+                    &args,
+                    outcome,
+                    run_result.exit_code(args.preserve_status, config.timeout_exit_code),
+                )
+            };
 
             /* Warn if custom exit code conflicts with reserved codes (only when timeout occurs) */
             if let Some(code) = args.timeout_exit_code
                 && matches!(run_result, RunResult::TimedOut { .. })
-                && (125..=137).contains(&code)
+                && exit_codes::RESERVED.contains(&code)
+                && !args.no_warn_exit_code
+            {
+                events.warn(args.log(), Warning::ReservedExitCode { code });
+            }
+
+            /* so the timeout doesn't get the blame for jetsam's SIGKILL */
+            if args.verbose
+                && let Some(sig) = run_result.external_kill()
             {
-                eprintln!(
-                    "warning: --timeout-exit-code {} may conflict with reserved exit codes (125-137)",
-                    code
+                log_info!(
+                    args.log(),
+                    "command was killed by {}, which procguard didn't send",
+                    procguard::signal::signal_name(sig)
                 );
             }
 
-            if args.json {
-                print_json_output(
+            emit_json(&args, || {
+                json_output(
                     &run_result,
                     elapsed_ms,
                     exit_code,
@@ -303,32 +622,735 @@ fn run_main() -> u8 {
                     &config.limits,
                     config.cpu_throttle,
                     config.confine,
-                );
+                    config.arch,
+                    config.hook_budget,
+                    config.hook_async,
+                    config.stop_bound(),
+                    &waits,
+                    &events,
+                    command_argv,
+                    crash_report.as_deref(),
+                    crash_hook.as_ref(),
+                )
+            });
+
+            /* --kill-self-on-timeout: the result is out, die of the signal */
+            if let Some(sig) = config.kill_self_on_timeout
+                && matches!(run_result, RunResult::TimedOut { .. })
+            {
+                procguard::signal::raise_default(sig);
             }
+            launchd_relaunch(&args, outcome);
 
             exit_code
         }
         Err(e) => {
-            if args.json {
-                print_json_error(&e, elapsed_ms);
-            } else if !args.quiet {
-                eprintln!("{}: {}", prog_name, e);
+            if !args.json {
+                log_error!(args.log(), "{}", e);
             }
+            emit_json(&args, || json_error(&e, elapsed_ms, command_argv, &events));
             e.exit_code()
         }
+    };
+    /* GNU timeout exits with the command's status whatever came in late */
+    if !gnu {
+        deliver_stop();
+    }
+    code
+}
+
+/* --sleep: wait DURATION out on the --confine clock. a stop signal ends
+ * it, and we die of that signal the way sleep(1) would */
+fn sleep(args: &OwnedArgs, duration_str: &str) -> u8 {
+    let duration = match parse_duration(duration_str) {
+        Ok(d) => d,
+        Err(e) => {
+            log_error!(args.log(), "{}", e);
+            return e.exit_code();
+        }
+    };
+    if args.verbose {
+        let clock = match args.confine {
+            Confine::Active => "awake time",
+            _ => "wall clock",
+        };
+        log_info!(
+            args.log(),
+            "sleeping {} ({})",
+            format_duration(duration),
+            clock
+        );
+    }
+    let _forwarding = setup_signal_forwarding().map_err(|w| args.log().warn(w));
+    if procguard::wait::delay_confined(duration, args.confine, Cancel::Flag(stop_requested())) {
+        return 0;
+    }
+    let sig = take_unforwarded_stop().unwrap_or(procguard::signal::Signal::SIGTERM);
+    if args.verbose {
+        log_info!(
+            args.log(),
+            "sleep ended early by {}",
+            procguard::signal::signal_name(sig)
+        );
+    }
+    procguard::signal::raise_default(sig);
+    TimeoutError::Interrupted(sig).exit_code()
+}
+
+/* --wait-pid: wait up to DURATION (0: for good) for a process we didn't
+ * start to exit. 0 once it has, or if there's none to wait for; 124 if
+ * it's still running. nothing is sent to it either way. a stop signal
+ * ends the wait, the result says so, and we die of the signal */
+fn wait_pid(args: &OwnedArgs, pid: i32, duration_str: &str, events: &mut EventLog) -> u8 {
+    let duration = match parse_duration(duration_str) {
+        Ok(d) => d,
+        Err(e) => {
+            log_error!(args.log(), "{}", e);
+            return e.exit_code();
+        }
+    };
+    if let Some(fd) = args.json_fd
+        && let Err(errno) = procguard::io::prepare_output_fd(fd)
+    {
+        log_error!(
+            args.log(),
+            "--json-fd {}: not writable (errno {})",
+            fd,
+            errno
+        );
+        return exit_codes::INTERNAL_ERROR;
+    }
+    let timeout = (!is_no_timeout(&duration)).then_some(duration);
+    if args.verbose {
+        match timeout {
+            Some(d) => log_info!(
+                args.log(),
+                "waiting up to {} for process {}",
+                format_duration(d),
+                pid
+            ),
+            None => log_info!(args.log(), "waiting for process {}", pid),
+        }
+    }
+
+    let _forwarding = setup_signal_forwarding().map_err(|w| events.warn(args.log(), w));
+    let start_ns = precise_now_ns().unwrap_or(0);
+    let waited = wait_for_pid(pid, timeout, args.confine, Cancel::Flag(stop_requested()));
+    let elapsed_ms = precise_now_ns().unwrap_or(0).saturating_sub(start_ns) / 1_000_000;
+
+    let waited = match waited {
+        Ok(waited) => waited,
+        Err(e) => {
+            if !args.json {
+                log_error!(args.log(), "{}", e);
+            }
+            emit_json(args, || json_error(&e, elapsed_ms, &[], events));
+            return e.exit_code();
+        }
+    };
+    let signal =
+        (waited == PidWait::Cancelled).then(|| take_unforwarded_stop().unwrap_or(Signal::SIGTERM));
+    let code = match (waited, signal) {
+        (_, Some(sig)) => TimeoutError::Interrupted(sig).exit_code(),
+        (PidWait::TimedOut, _) => exit_codes::TIMEOUT,
+        _ => 0,
+    };
+    if args.verbose {
+        match (waited, signal) {
+            (_, Some(sig)) => log_info!(
+                args.log(),
+                "wait ended early by {}",
+                procguard::signal::signal_name(sig)
+            ),
+            (PidWait::Exited, _) => log_info!(args.log(), "process {} exited", pid),
+            (PidWait::TimedOut, _) => log_info!(args.log(), "process {} still running", pid),
+            _ => log_info!(args.log(), "process {} isn't running", pid),
+        }
+    }
+    emit_json(args, || {
+        json_wait_pid(args, pid, timeout, waited, signal, code, elapsed_ms, events)
+    });
+    if let Some(sig) = signal {
+        procguard::signal::raise_default(sig);
+    }
+    code
+}
+
+/* a stop signal came in after the last attempt, with no command left to
+ * pass it to. the result went out whole first; now it's acted on */
+fn deliver_stop() {
+    if let Some(sig) = take_unforwarded_stop() {
+        procguard::signal::raise_default(sig);
+    }
+}
+
+/* a stop signal ended a wait before the command started: the result says
+ * so, then we die of the signal as we would have without forwarding */
+fn interrupted(args: &OwnedArgs, argv: &[Vec<u8>], events: &EventLog) -> u8 {
+    let sig = take_unforwarded_stop().unwrap_or(procguard::signal::Signal::SIGTERM);
+    let e = TimeoutError::Interrupted(sig);
+    if !args.json {
+        log_error!(args.log(), "{}", e);
+    }
+    emit_json(args, || json_error(&e, 0, argv, events));
+    procguard::signal::raise_default(sig);
+    e.exit_code()
+}
+
+/* the report keeps this many runs of a --every repeat; its counts cover
+ * them all */
+const REPEAT_RESULTS: usize = 1000;
+
+/* one run of a --every repeat, as the report lists it */
+struct RunSummary {
+    started_ms: u64, /* since the first run started */
+    elapsed_ms: u64,
+    outcome: Outcome,
+    signal: Option<Signal>, /* sent to it, or the one it crashed of */
+    exit_code: u8,          /* what this run alone would have exited with */
+    attempts: Option<u32>,  /* with --retry */
+}
+
+/* what a --every, --runs or --detect-flaky repeat came to */
+struct RepeatReport {
+    schedule: Option<Schedule>, /* None: back to back */
+    max_runs: Option<u32>,
+    stop_on: StopOn,
+    /* "budget", "max_runs", "policy", "passed" or "signal" */
+    stopped: &'static str,
+    skipped: u64, /* slots missed while a run overran */
+    stats: RunStats,
+    flaky_threshold: Option<u32>,
+    flakiness: Option<Flakiness>, /* --detect-flaky's verdict */
+    results: VecDeque<RunSummary>,
+}
+
+/* the signal a run ended with: ours, or its own crash */
+fn run_signal(result: &RunResult) -> Option<Signal> {
+    match result {
+        RunResult::TimedOut { signal, .. }
+        | RunResult::MemoryLimitExceeded { signal, .. }
+        | RunResult::CpuLimitExceeded { signal, .. }
+        | RunResult::SignalForwarded { signal, .. } => Some(*signal),
+        _ => result.crash_signal(),
+    }
+}
+
+/*
+ * --every/--for, --runs: run the command on the schedule (or back to back)
+ * until the budget or the run count is spent, --stop-on says a run ends
+ * it, or a stop signal comes in (during a run it's forwarded as usual;
+ * between runs it ends the wait). the exit status is that of the last run
+ * that didn't succeed, 0 if they all did - or, with --flaky-threshold, if
+ * no more of them failed than that. an error starting a run ends the
+ * repeat with that error's result.
+ *
+ * --detect-flaky N: back to back too, up to N reruns, ending at the first
+ * run that passes; a flaky test exits as --flaky-exit says.
+ */
+#[allow(clippy::too_many_arguments)]
+fn run_repeat(
+    args: &OwnedArgs,
+    config: &RunConfig,
+    schedule: Option<Schedule>,
+    command: &[u8],
+    extra_args: &[Vec<u8>],
+    argv: &[Vec<u8>],
+    waits: &Waits,
+    events: &mut EventLog,
+) -> u8 {
+    let start_ns = precise_now_ns().unwrap_or(0);
+    let since_start = || {
+        Duration::from_nanos(
+            precise_now_ns()
+                .unwrap_or(start_ns)
+                .saturating_sub(start_ns),
+        )
+    };
+    let ms = |d: Duration| u64::try_from(d.as_millis()).unwrap_or(u64::MAX);
+
+    let mut report = RepeatReport {
+        schedule,
+        max_runs: args
+            .runs
+            .or(args.max_runs)
+            .or(args.detect_flaky.map(|n| n.saturating_add(1))),
+        stop_on: args.stop_on.unwrap_or_default(),
+        stopped: "budget",
+        skipped: 0,
+        stats: RunStats::new(),
+        flaky_threshold: args.flaky_threshold,
+        flakiness: None,
+        results: VecDeque::new(),
+    };
+    let mut code = 0;
+    let mut slot = 0;
+    let mut timed_out = false;
+    let last_timed_out = loop {
+        let started = since_start();
+        if args.verbose {
+            match (schedule, report.max_runs) {
+                (Some(s), _) => log_info!(
+                    args.log(),
+                    "run {} (every {})",
+                    report.stats.runs() + 1,
+                    format_duration(s.every())
+                ),
+                (None, Some(n)) => {
+                    log_info!(args.log(), "run {} of {}", report.stats.runs() + 1, n)
+                }
+                (None, None) => {}
+            }
+        }
+        /* each run is bounded on its own; the waits between aren't */
+        if let Some(bound) = config.lifetime_bound() {
+            failsafe::arm(bound.saturating_add(failsafe::SLACK), args.log());
+        }
+        let result = run_with_retry_bytes(command, extra_args, config, events);
+        failsafe::disarm();
+        let (run_result, attempts) = match result {
+            Ok(r) => r,
+            Err(e) => {
+                if !args.json {
+                    log_error!(args.log(), "{}", e);
+                }
+                emit_json(args, || json_error(&e, ms(since_start()), argv, events));
+                return e.exit_code();
+            }
+        };
+        after_crash(args, config, &run_result, events);
+
+        let outcome = Outcome::from(&run_result);
+        let exit_code = run_result.exit_code(args.preserve_status, config.timeout_exit_code);
+        let run_timed_out = matches!(run_result, RunResult::TimedOut { .. });
+        timed_out |= run_timed_out;
+        let elapsed_ms = ms(since_start().saturating_sub(started));
+        report.stats.record(outcome.is_success(), elapsed_ms);
+        if !outcome.is_success() {
+            code = exit_code;
+        }
+        if report.results.len() == REPEAT_RESULTS {
+            report.results.pop_front();
+        }
+        report.results.push_back(RunSummary {
+            started_ms: ms(started),
+            elapsed_ms,
+            outcome,
+            signal: run_signal(&run_result),
+            exit_code,
+            attempts: (config.retry_count > 0).then_some(attempts.len() as u32),
+        });
+
+        if matches!(outcome, Outcome::ForwardedSignal { .. }) {
+            report.stopped = "signal";
+            break run_timed_out;
+        }
+        if args.detect_flaky.is_some() {
+            if outcome.is_success() {
+                report.stopped = "passed";
+                break run_timed_out;
+            }
+            if args.verbose && report.max_runs.is_some_and(|n| report.stats.runs() < n) {
+                log_info!(
+                    args.log(),
+                    "run {} failed (exit {}), rerunning",
+                    report.stats.runs(),
+                    exit_code
+                );
+            }
+        }
+        if report.stop_on.stops(outcome) {
+            if args.verbose {
+                log_info!(
+                    args.log(),
+                    "stopping after run {} (exit {}, --stop-on {})",
+                    report.stats.runs(),
+                    exit_code,
+                    report.stop_on.as_str()
+                );
+            }
+            report.stopped = "policy";
+            break run_timed_out;
+        }
+        if report.max_runs.is_some_and(|n| report.stats.runs() >= n) {
+            report.stopped = "max_runs";
+            break run_timed_out;
+        }
+        /* back to back: straight on to the next, unless we were told to
+         * stop in between */
+        let Some(schedule) = schedule else {
+            if stop_requested().load(AtomicOrdering::Acquire) {
+                report.stopped = "signal";
+                break run_timed_out;
+            }
+            continue;
+        };
+        let Some(next) = schedule.next_slot(slot, since_start()) else {
+            if args.verbose {
+                log_info!(
+                    args.log(),
+                    "--for {} spent after {} runs",
+                    format_duration(schedule.budget()),
+                    report.stats.runs()
+                );
+            }
+            break run_timed_out;
+        };
+        let missed = next - slot - 1;
+        if missed > 0 {
+            report.skipped += missed;
+            if args.verbose {
+                log_info!(
+                    args.log(),
+                    "run {} overran, skipping {} run(s)",
+                    report.stats.runs(),
+                    missed
+                );
+            }
+        }
+        slot = next;
+        let wait = schedule.slot_start(next).saturating_sub(since_start());
+        if !procguard::wait::delay(wait, Cancel::Flag(stop_requested())) {
+            report.stopped = "signal";
+            break run_timed_out;
+        }
+    };
+    let elapsed_ms = ms(since_start());
+    /* --flaky-threshold: a few failures are tolerated */
+    if let Some(threshold) = report.flaky_threshold
+        && !report.stats.failures_exceed(threshold)
+    {
+        code = 0;
+    }
+    if args.detect_flaky.is_some() {
+        report.flakiness = Flakiness::classify(&report.stats);
+        if report.flakiness == Some(Flakiness::Flaky) {
+            code = args.flaky_exit.unwrap_or_default().exit_code(code);
+        }
+        if args.verbose
+            && let Some(flakiness) = report.flakiness
+        {
+            log_info!(
+                args.log(),
+                "{} after {} run(s)",
+                flakiness.as_str(),
+                report.stats.runs()
+            );
+        }
+    }
+
+    if args.explain {
+        print_explain(events, config);
+    }
+    if let Some(custom) = args.timeout_exit_code
+        && timed_out
+        && exit_codes::RESERVED.contains(&custom)
+        && !args.no_warn_exit_code
+    {
+        events.warn(args.log(), Warning::ReservedExitCode { code: custom });
+    }
+    /* --launchd-mode: the last run decides, as for --kill-self-on-timeout */
+    let last = report.results.back().map(|r| r.outcome);
+    if let Some(outcome) = last {
+        code = launchd_exit_code(args, outcome, code);
+    }
+    emit_json(args, || {
+        json_repeat(
+            &report,
+            code,
+            elapsed_ms,
+            config.confine,
+            waits,
+            events,
+            argv,
+        )
+    });
+
+    /* --kill-self-on-timeout: as for one run, if the last one timed out */
+    if let Some(sig) = config.kill_self_on_timeout
+        && last_timed_out
+    {
+        procguard::signal::raise_default(sig);
+    }
+    if let Some(outcome) = last {
+        launchd_relaunch(args, outcome);
+    }
+    code
+}
+
+/* gtimeout: GNU timeout exits 128+9, not 124, when the command it timed
+ * out died of SIGKILL - -s KILL or the -k escalation - so a caller can
+ * tell a command that had to be forced from one that went on TERM */
+fn gnu_killed(run_result: &RunResult) -> bool {
+    match run_result {
+        RunResult::TimedOut {
+            signal,
+            killed,
+            status,
+            ..
+        } => status.map_or(*killed || *signal == Signal::SIGKILL, |s| {
+            s.signal() == Some(Signal::SIGKILL as i32)
+        }),
+        _ => false,
+    }
+}
+
+/*
+ * --launchd-mode: a run procguard stopped (timeout, limit kill) ends the
+ * way the job's KeepAlive should take it. stop exits 0, which
+ * SuccessfulExit = false leaves alone; relaunch dies of SIGTERM once the
+ * result is out, which launchd relaunches. the decision goes to the system
+ * log, since a launchd job's stderr usually goes nowhere
+ */
+fn launchd_stopped(outcome: Outcome) -> bool {
+    matches!(
+        outcome,
+        Outcome::TimedOut { .. } | Outcome::LimitExceeded { .. }
+    )
+}
+
+fn launchd_exit_code(args: &OwnedArgs, outcome: Outcome, code: u8) -> u8 {
+    if args.launchd_mode != Some(LaunchdMode::Stop) || !launchd_stopped(outcome) {
+        return code;
+    }
+    args.log().system(format_args!(
+        "launchd-mode stop: command ended with {}, exiting 0 instead of {} so launchd won't relaunch it",
+        outcome.as_str(),
+        code
+    ));
+    if args.verbose {
+        log_info!(
+            args.log(),
+            "launchd-mode stop: exiting 0 instead of {} after {}",
+            code,
+            outcome.as_str()
+        );
+    }
+    0
+}
+
+fn launchd_relaunch(args: &OwnedArgs, outcome: Outcome) {
+    if args.launchd_mode != Some(LaunchdMode::Relaunch) || !launchd_stopped(outcome) {
+        return;
+    }
+    args.log().system(format_args!(
+        "launchd-mode relaunch: command ended with {}, exiting on SIGTERM so launchd relaunches it",
+        outcome.as_str()
+    ));
+    if args.verbose {
+        log_info!(
+            args.log(),
+            "launchd-mode relaunch: dying of SIGTERM after {}",
+            outcome.as_str()
+        );
+    }
+    procguard::signal::raise_default(Signal::SIGTERM);
+}
+
+/*
+ * the command crashed: look for the report macOS writes for it
+ * (--crash-report, or a --crash-hook that wants %r) and run --crash-hook.
+ * the lookup waits up to crash::REPORT_WINDOW, which is why it's opt-in.
+ * pid and start time come from the last attempt's spawn.
+ */
+/* GNU's pointer after a usage error */
+fn try_help(prog_name: &str) {
+    eprintln!("Try '{} --help' for more information.", prog_name);
+}
+
+/* gtimeout: RunConfig's errors worded the way GNU timeout words them. only
+ * GNU's options got this far, so only their errors can come up */
+fn gnu_config_error(err: &TimeoutError, args: &OwnedArgs, duration_str: &str) {
+    match err {
+        TimeoutError::InvalidDuration(_)
+        | TimeoutError::NegativeDuration
+        | TimeoutError::DurationOverflow => {
+            let bad = if parse_duration(duration_str).is_err() {
+                duration_str
+            } else {
+                args.kill_after.as_deref().unwrap_or_default()
+            };
+            log_error!(args.log(), "invalid time interval '{}'", bad);
+            try_help(Personality::Gnu.name());
+        }
+        TimeoutError::InvalidSignal(_) => {
+            log_error!(args.log(), "'{}': invalid signal", args.signal);
+            try_help(Personality::Gnu.name());
+        }
+        _ => log_error!(args.log(), "{}", err),
     }
 }
 
+fn after_crash(
+    args: &OwnedArgs,
+    config: &RunConfig,
+    result: &RunResult,
+    events: &mut EventLog,
+) -> (Option<String>, Option<HookResult>) {
+    let Some(signal) = result.crash_signal() else {
+        return (None, None);
+    };
+    let Some(spawned) = events
+        .as_slice()
+        .iter()
+        .rev()
+        .find(|e| e.kind == EventKind::Spawned)
+        .copied()
+    else {
+        return (None, None);
+    };
+    let pid = u32::try_from(spawned.value).unwrap_or(0);
+
+    let wants_report = args.crash_report
+        || args
+            .crash_hook
+            .as_ref()
+            .is_some_and(|cmd| cmd.contains("%r"));
+    let report = if wants_report {
+        let since_ms = events.origin_epoch_ms() + spawned.at_ns / 1_000_000;
+        procguard::crash::find_report(pid, since_ms, procguard::crash::REPORT_WINDOW)
+    } else {
+        None
+    };
+    if args.verbose && wants_report {
+        match &report {
+            Some(path) => log_info!(
+                args.log(),
+                "command crashed ({}), report: {}",
+                procguard::signal::signal_name(signal),
+                path
+            ),
+            None => log_info!(
+                args.log(),
+                "command crashed ({}), no crash report found",
+                procguard::signal::signal_name(signal)
+            ),
+        }
+    }
+
+    #[allow(clippy::cast_possible_wrap)]
+    let hook = args
+        .crash_hook
+        .as_ref()
+        .map(|cmd| run_crash_hook(cmd, pid as i32, signal, report.as_deref(), config, events));
+    (report, hook)
+}
+
 /*
- * Print JSON output as a single line.
+ * --json prints the result on stdout (or --json-fd), --result-file writes
+ * it to a file; either, both or neither. Built only if someone wants it.
  *
- * JSON is built in memory first, then written with a single println! call.
- * This minimizes the window where a signal could interrupt output, though
- * SIGKILL during the write could still produce partial output. CI systems
- * parsing this output should validate JSON before processing.
+ * The file is written even when we're exiting because of a forwarded
+ * signal - that path returns through here like any other - so an
+ * orchestrator always finds a result.
+ */
+fn emit_json(args: &OwnedArgs, build: impl FnOnce() -> JsonBuf) {
+    if !args.json && args.result_file.is_none() {
+        return;
+    }
+    #[cfg(feature = "alloc-stats")]
+    let before = procguard::AllocStats::now();
+    let json = build();
+    #[cfg(feature = "alloc-stats")]
+    report_allocs(
+        args.log(),
+        "building the result",
+        procguard::AllocStats::now().since(before),
+    );
+    let line: [&[u8]; 2] = [json.as_bytes(), b"\n"];
+    if args.json {
+        let fd = args.json_fd.unwrap_or(1);
+        if let Err(errno) = procguard::io::write_all_vectored(fd, &line) {
+            log_error!(args.log(), "can't write JSON to fd {}: errno {}", fd, errno);
+        }
+    }
+    let opts = FileOptions {
+        mode: args.file_mode,
+        fsync: !args.no_fsync,
+    };
+    if let Some(ref path) = args.result_file
+        && let Err(errno) = safe_file::write_atomic(path, &line, opts)
+    {
+        log_error!(
+            args.log(),
+            "can't write result file '{}': errno {}",
+            path,
+            errno
+        );
+    }
+}
+
+/* --features alloc-stats: what we allocated, on stderr at exit */
+#[cfg(feature = "alloc-stats")]
+struct AllocReport(procguard::AllocStats, String);
+
+#[cfg(feature = "alloc-stats")]
+impl Drop for AllocReport {
+    fn drop(&mut self) {
+        let log = procguard::log::Logger::new(&self.1, 0);
+        report_allocs(log, "total", procguard::AllocStats::now().since(self.0));
+    }
+}
+
+#[cfg(feature = "alloc-stats")]
+fn report_allocs(log: procguard::log::Logger<'_>, what: &str, stats: procguard::AllocStats) {
+    eprintln!(
+        "{}: allocations ({}): {} ({} bytes)",
+        log.tag(),
+        what,
+        stats.count,
+        stats.bytes
+    );
+}
+
+/* print the recorded timeline for --explain, built in memory then one write */
+fn print_explain(events: &EventLog, config: &RunConfig) {
+    let mut out = String::with_capacity(64 * (events.len() + 4));
+    let mut polling = String::new();
+    let _ = config.write_polling(&mut polling);
+    if !polling.is_empty() {
+        let _ = writeln!(out, "{}: polling:", config.log().tag());
+        out.push_str(&polling);
+    }
+    match events.run_id().and_then(RunId::given) {
+        Some(id) => {
+            let _ = writeln!(out, "{}: timeline (run {}):", config.log().tag(), id);
+        }
+        None => {
+            let _ = writeln!(out, "{}: timeline:", config.log().tag());
+        }
+    }
+    let _ = events.write_timeline(&mut out);
+    if !events.tree().is_empty() {
+        let _ = writeln!(out, "{}: process tree:", config.log().tag());
+        let _ = events.tree().write_tree(&mut out);
+    }
+    eprint!("{}", out);
+}
+
+/* the JSON result is built here: on the stack for anything but long
+ * timelines or huge command lines, so the usual run allocates nothing for
+ * its output */
+type JsonBuf = StackBuf<4096>;
+
+/* how long we waited before the command could start, for the result */
+struct Waits {
+    window_ms: Option<u64>,        /* --only-between */
+    lock_ms: Option<u64>,          /* --flock */
+    lock_queue: Option<QueueWait>, /* --flock-queue */
+}
+
+/*
+ * Build the JSON result as a single line.
+ *
+ * JSON is built in memory first, then written with a single call by
+ * emit_json. This minimizes the window where a signal could interrupt
+ * output, though SIGKILL during the write could still produce partial
+ * output on stdout. --result-file doesn't have that problem: the file is
+ * renamed into place only once complete.
  */
 #[allow(clippy::too_many_arguments)]
-fn print_json_output(
+fn json_output(
     result: &RunResult,
     elapsed_ms: u64,
     exit_code: u8,
@@ -337,107 +1359,235 @@ fn print_json_output(
     limits: &procguard::ResourceLimits,
     cpu_throttle: Option<procguard::throttle::CpuThrottleConfig>,
     confine: Confine,
-) {
-    /* Schema version 8: added clock field for time measurement mode */
-    const SCHEMA_VERSION: u8 = 8;
-
-    /* convert Confine to JSON string */
-    let clock_str = match confine {
-        Confine::Wall => "wall",
-        Confine::Active => "active",
-        _ => "unknown", /* future-proof for #[non_exhaustive] */
-    };
-
-    /* helper to append rusage fields to JSON string */
-    fn append_rusage(json: &mut String, rusage: Option<&procguard::process::ResourceUsage>) {
+    arch: Option<Arch>,
+    hook_budget: HookBudget,
+    hook_async: bool,
+    stop_bound: Option<Duration>,
+    waits: &Waits,
+    events: &EventLog,
+    argv: &[Vec<u8>],
+    crash_report: Option<&str>,
+    crash_hook: Option<&HookResult>,
+) -> JsonBuf {
+    /* helper to write rusage fields */
+    fn write_rusage(
+        w: &mut JsonWriter<JsonBuf>,
+        rusage: Option<&procguard::process::ResourceUsage>,
+    ) {
         if let Some(r) = rusage {
-            let _ = write!(
-                json,
-                r#","user_time_ms":{},"system_time_ms":{},"max_rss_kb":{}"#,
-                r.user_time_ms(),
-                r.system_time_ms(),
-                r.max_rss_kb
-            );
+            w.field_u64("user_time_ms", r.user_time_ms());
+            w.field_u64("system_time_ms", r.system_time_ms());
+            w.field_u64("max_rss_kb", r.max_rss_kb);
+        }
+    }
+
+    /* helper to write the hook_* fields, for whichever hook ran */
+    fn write_hook(w: &mut JsonWriter<JsonBuf>, hook: Option<&HookResult>) {
+        if let Some(h) = hook {
+            w.field_bool("hook_ran", h.ran);
+            w.key("hook_exit_code").opt_i64(h.exit_code.map(i64::from));
+            w.field_bool("hook_timed_out", h.timed_out);
+            w.field_u64("hook_elapsed_ms", h.elapsed_ms);
+            w.field_u64("hook_limit_ms", h.limit_ms);
         }
     }
 
-    /* helper to append attempt_results array if retries were configured */
-    fn append_attempts(json: &mut String, attempts: &[AttemptResult], retry_count: u32) {
+    /* helper to write the attempt_results array if retries were configured */
+    fn write_attempts(w: &mut JsonWriter<JsonBuf>, attempts: &[AttemptResult], retry_count: u32) {
         if retry_count == 0 {
             return;
         }
-        let _ = write!(json, r#","attempts":{}"#, attempts.len());
-        json.push_str(r#","attempt_results":["#);
-        for (i, a) in attempts.iter().enumerate() {
-            if i > 0 {
-                json.push(',');
-            }
+        w.field_u64("attempts", attempts.len() as u64);
+        w.key("attempt_results").begin_array();
+        let mut stats = RunStats::new();
+        for (n, a) in (1u64..).zip(attempts) {
             /* exit_code is null for timeout/signal, integer for completed */
-            let exit_str = a
-                .exit_code
-                .map_or_else(|| "null".into(), |c| alloc::format!("{}", c));
-            let _ = write!(
-                json,
-                r#"{{"status":"{}","exit_code":{},"elapsed_ms":{}}}"#,
-                a.status, exit_str, a.elapsed_ms
-            );
+            w.begin_object();
+            w.field_u64("attempt", n);
+            w.field_str("status", a.status);
+            w.key("exit_code").opt_i64(a.exit_code.map(i64::from));
+            w.field_u64("elapsed_ms", a.elapsed_ms);
+            w.end_object();
+            /* the retry a stop signal called off never ran */
+            if a.status != "aborted" {
+                stats.record(
+                    a.status == "completed" && a.exit_code == Some(0),
+                    a.elapsed_ms,
+                );
+            }
         }
-        json.push(']');
+        w.end_array();
+        write_aggregate(w, &stats, None);
     }
 
-    /* helper to append resource limits metadata if configured */
-    fn append_limits(
-        json: &mut String,
+    /* helper to write resource limits metadata if configured */
+    fn write_limits(
+        w: &mut JsonWriter<JsonBuf>,
         limits: &procguard::ResourceLimits,
         cpu_throttle: Option<procguard::throttle::CpuThrottleConfig>,
+        rusage: Option<&procguard::process::ResourceUsage>,
     ) {
         if limits.mem_bytes.is_none() && limits.cpu_time.is_none() && cpu_throttle.is_none() {
             return;
         }
 
-        json.push_str(r#","limits":{"#);
-        let mut wrote = false;
+        w.key("limits").begin_object();
         if let Some(bytes) = limits.mem_bytes {
-            let _ = write!(json, r#""mem_bytes":{}"#, bytes);
-            wrote = true;
+            w.field_u64("mem_bytes", bytes);
         }
         if let Some(cpu) = limits.cpu_time {
-            if wrote {
-                json.push(',');
-            }
-            let _ = write!(json, r#""cpu_time_ms":{}"#, cpu.as_millis());
-            wrote = true;
+            w.field_u64(
+                "cpu_time_ms",
+                u64::try_from(cpu.as_millis()).unwrap_or(u64::MAX),
+            );
         }
-        if let Some(cfg) = cpu_throttle {
-            if wrote {
-                json.push(',');
-            }
-            let _ = write!(
-                json,
-                r#""cpu_percent":{},"cpu_interval_ms":{},"cpu_sleep_ms":{}"#,
-                cfg.percent.get(),
-                cfg.interval_ns / 1_000_000,
-                cfg.sleep_ns / 1_000_000
+        if let Some(hard) = limits.cpu_time_hard {
+            w.field_u64(
+                "cpu_time_hard_ms",
+                u64::try_from(hard.as_millis()).unwrap_or(u64::MAX),
             );
         }
-        json.push('}');
+        /* past the soft limit, the kernel sent it SIGXCPU - whether or
+         * not that's what ended it */
+        if let (Some((soft, _)), Some(rusage)) = (limits.cpu_secs(), rusage) {
+            let used_ms = rusage
+                .user_time_ms()
+                .saturating_add(rusage.system_time_ms());
+            w.field_bool(
+                "sigxcpu_sent",
+                procguard::rlimit::cpu_limit_reached(used_ms, soft),
+            );
+        }
+        if let Some(cfg) = cpu_throttle {
+            w.field_u64("cpu_percent", u64::from(cfg.percent.get()));
+            w.field_u64("cpu_interval_ms", cfg.interval_ns / 1_000_000);
+            w.field_u64("cpu_sleep_ms", cfg.max_sleep_ns() / 1_000_000);
+            w.field_u64("cpu_throttle_quantum_ms", cfg.quantum_ns / 1_000_000);
+            w.field_u64("cpu_throttle_window_ms", cfg.window_ns / 1_000_000);
+            w.key("cpu_throttle_gain")
+                .tenths(u64::from(cfg.gain_tenths));
+            w.field_str("cpu_throttle_notify", cfg.notify.as_str());
+        }
+        w.end_object();
     }
 
-    match result {
-        RunResult::Completed { status, rusage } => {
-            let code = status.code().unwrap_or(-1);
-            let mut json = String::with_capacity(256);
-            let _ = write!(
-                json,
-                r#"{{"schema_version":{},"status":"completed","clock":"{}","exit_code":{},"elapsed_ms":{}"#,
-                SCHEMA_VERSION, clock_str, code, elapsed_ms
+    /* --proc-tree: what the command started, on the timeline's clock, and
+     * what of it got away */
+    fn write_process_tree(w: &mut JsonWriter<JsonBuf>, tree: &procguard::proctree::ProcTree) {
+        if tree.is_empty() {
+            return;
+        }
+        w.key("process_tree").begin_object();
+        w.field_u64("dropped", u64::from(tree.dropped()));
+        w.key("processes");
+        tree.write_json(w);
+        w.end_object();
+        w.key("escaped_processes");
+        tree.write_escaped_json(w);
+    }
+
+    /* signal name, number, killed flag and child_state for timeout/memory_limit */
+    fn write_signal(w: &mut JsonWriter<JsonBuf>, signal: procguard::signal::Signal) {
+        w.field_str("signal", procguard::signal::signal_name(signal));
+        w.field_i64(
+            "signal_num",
+            i64::from(procguard::signal::signal_number(signal)),
+        );
+    }
+
+    /* a fatal signal the command died of that we never sent */
+    fn write_external(w: &mut JsonWriter<JsonBuf>, result: &RunResult) {
+        let signal = result.external_kill();
+        w.field_bool("external_kill", signal.is_some());
+        if let Some(sig) = signal {
+            w.field_str("external_signal", procguard::signal::signal_name(sig));
+            w.field_i64(
+                "external_signal_num",
+                i64::from(procguard::signal::signal_number(sig)),
             );
-            append_rusage(&mut json, Some(rusage));
-            append_attempts(&mut json, attempts, retry_count);
-            append_limits(&mut json, limits, cpu_throttle);
-            json.push('}');
-            println!("{}", json);
         }
+    }
+
+    /* the kernel's exit reason, null where there is none (and off macOS) */
+    fn write_exit_reason(w: &mut JsonWriter<JsonBuf>, result: &RunResult) {
+        let reason = result.exit_reason();
+        w.key("exit_reason_namespace");
+        match reason {
+            Some(r) => w.str(r.namespace_name()),
+            None => w.null(),
+        };
+        w.key("exit_reason_code")
+            .opt_i64(reason.map(|r| i64::try_from(r.code).unwrap_or(i64::MAX)));
+    }
+
+    /* the command as the timeout found it, before the hook or a signal;
+     * null fields where it couldn't be read */
+    fn write_at_timeout(w: &mut JsonWriter<JsonBuf>, snapshot: Option<Snapshot>) {
+        let to_i64 = |v: u64| i64::try_from(v).unwrap_or(i64::MAX);
+        w.key("at_timeout");
+        let Some(snap) = snapshot else {
+            w.null();
+            return;
+        };
+        w.begin_object();
+        w.key("cpu_time_ms")
+            .opt_i64(snap.cpu_time_ns.map(|ns| to_i64(ns / 1_000_000)));
+        w.key("memory_bytes").opt_i64(snap.memory_bytes.map(to_i64));
+        w.key("threads").opt_i64(snap.threads.map(i64::from));
+        w.key("state");
+        match snap.status {
+            Some(status) => w.str(status.as_str()),
+            None => w.null(),
+        };
+        w.end_object();
+    }
+
+    let mut w = JsonWriter::on(JsonBuf::new());
+    run_id::begin_result(&mut w, events.run_id());
+    /* same classification the exit code came from */
+    let outcome = Outcome::from(result);
+    w.field_str("status", outcome.as_str());
+    w.field_str("clock", clock_name(confine));
+    if let Some(arch) = arch {
+        w.field_str("arch", arch.as_str());
+    }
+
+    /* hook fields if hook was run, and how long it and the grace period
+     * could keep the command going between them - at a timeout or a
+     * memory limit alike */
+    let write_stop_hook = |w: &mut JsonWriter<JsonBuf>, hook: Option<&HookResult>| {
+        write_hook(w, hook);
+        if hook.is_some() {
+            w.field_str("hook_budget", hook_budget.as_str());
+            w.field_bool("hook_async", hook_async);
+            w.key("stop_bound_ms")
+                .opt_i64(stop_bound.map(|d| i64::try_from(d.as_millis()).unwrap_or(i64::MAX)));
+        }
+    };
+
+    match result {
+        RunResult::Completed { status, rusage } => match outcome {
+            /* died of SIGSEGV, SIGABRT, ... on its own - not our signal */
+            Outcome::Crashed { signal } => {
+                write_signal(&mut w, signal);
+                w.field_bool("core_dumped", status.core_dumped());
+                w.key("crash_report");
+                match crash_report {
+                    Some(path) => w.str(path),
+                    None => w.null(),
+                };
+                w.field_u64("exit_code", u64::from(exit_code));
+                w.field_u64("elapsed_ms", elapsed_ms);
+                write_rusage(&mut w, Some(rusage));
+                write_hook(&mut w, crash_hook);
+            }
+            _ => {
+                w.field_i64("exit_code", i64::from(status.code().unwrap_or(-1)));
+                write_external(&mut w, result);
+                w.field_u64("elapsed_ms", elapsed_ms);
+                write_rusage(&mut w, Some(rusage));
+            }
+        },
         RunResult::TimedOut {
             signal,
             killed,
@@ -445,59 +1595,22 @@ fn print_json_output(
             rusage,
             hook,
             reason,
+            child_state,
         } => {
-            let sig_num = procguard::signal::signal_number(*signal);
-            let status_code = status.and_then(|s| s.code()).unwrap_or(-1);
-            let sig_name = procguard::signal::signal_name(*signal);
-            let reason_str = match reason {
-                procguard::runner::TimeoutReason::WallClock => "wall_clock",
-                procguard::runner::TimeoutReason::StdinIdle => "stdin_idle",
-                _ => "unknown", /* future-proof for #[non_exhaustive] */
-            };
-
-            /* Build the JSON incrementally */
-            let mut json = String::with_capacity(512);
-            let _ = write!(
-                json,
-                r#"{{"schema_version":{},"status":"timeout","clock":"{}","timeout_reason":"{}","signal":"{}","signal_num":{},"killed":{},"command_exit_code":{},"exit_code":{},"elapsed_ms":{}"#,
-                SCHEMA_VERSION,
-                clock_str,
-                reason_str,
-                sig_name,
-                sig_num,
-                killed,
-                status_code,
-                exit_code,
-                elapsed_ms
+            w.field_str("timeout_reason", timeout_reason_name(*reason));
+            write_signal(&mut w, *signal);
+            w.field_bool("killed", *killed);
+            w.field_str("child_state", child_state.as_str());
+            write_external(&mut w, result);
+            w.field_i64(
+                "command_exit_code",
+                i64::from(status.and_then(|s| s.code()).unwrap_or(-1)),
             );
-
-            /* Add rusage fields if available */
-            append_rusage(&mut json, rusage.as_ref());
-
-            /* Add hook fields if hook was run */
-            if let Some(h) = hook {
-                match h.exit_code {
-                    Some(c) => {
-                        let _ = write!(
-                            json,
-                            r#","hook_ran":{},"hook_exit_code":{},"hook_timed_out":{},"hook_elapsed_ms":{}"#,
-                            h.ran, c, h.timed_out, h.elapsed_ms
-                        );
-                    }
-                    None => {
-                        let _ = write!(
-                            json,
-                            r#","hook_ran":{},"hook_exit_code":null,"hook_timed_out":{},"hook_elapsed_ms":{}"#,
-                            h.ran, h.timed_out, h.elapsed_ms
-                        );
-                    }
-                }
-            }
-
-            append_attempts(&mut json, attempts, retry_count);
-            append_limits(&mut json, limits, cpu_throttle);
-            json.push('}');
-            println!("{}", json);
+            w.field_u64("exit_code", u64::from(exit_code));
+            w.field_u64("elapsed_ms", elapsed_ms);
+            write_rusage(&mut w, rusage.as_ref());
+            write_at_timeout(&mut w, events.at_timeout());
+            write_stop_hook(&mut w, hook.as_ref());
         }
         RunResult::MemoryLimitExceeded {
             signal,
@@ -506,102 +1619,421 @@ fn print_json_output(
             rusage,
             limit_bytes,
             actual_bytes,
+            hook,
+            child_state,
         } => {
-            let sig_num = procguard::signal::signal_number(*signal);
-            let status_code = status.and_then(|s| s.code()).unwrap_or(-1);
-            let sig_name = procguard::signal::signal_name(*signal);
-
-            let mut json = String::with_capacity(512);
-            let _ = write!(
-                json,
-                r#"{{"schema_version":{},"status":"memory_limit","clock":"{}","signal":"{}","signal_num":{},"killed":{},"command_exit_code":{},"exit_code":{},"elapsed_ms":{},"limit_bytes":{},"actual_bytes":{}"#,
-                SCHEMA_VERSION,
-                clock_str,
-                sig_name,
-                sig_num,
-                killed,
-                status_code,
-                exit_code,
-                elapsed_ms,
-                limit_bytes,
-                actual_bytes
+            write_signal(&mut w, *signal);
+            w.field_bool("killed", *killed);
+            w.field_str("child_state", child_state.as_str());
+            write_external(&mut w, result);
+            w.field_i64(
+                "command_exit_code",
+                i64::from(status.and_then(|s| s.code()).unwrap_or(-1)),
             );
-
-            append_rusage(&mut json, rusage.as_ref());
-            append_attempts(&mut json, attempts, retry_count);
-            append_limits(&mut json, limits, cpu_throttle);
-            json.push('}');
-            println!("{}", json);
+            w.field_u64("exit_code", u64::from(exit_code));
+            w.field_u64("elapsed_ms", elapsed_ms);
+            w.field_u64("limit_bytes", *limit_bytes);
+            w.field_u64("actual_bytes", *actual_bytes);
+            write_rusage(&mut w, rusage.as_ref());
+            write_at_timeout(&mut w, events.at_timeout());
+            write_stop_hook(&mut w, hook.as_ref());
+        }
+        /* the kernel's signal at RLIMIT_CPU, not ours */
+        RunResult::CpuLimitExceeded {
+            signal,
+            status,
+            rusage,
+            limit_ms,
+            actual_ms,
+        } => {
+            write_signal(&mut w, *signal);
+            let hard = *signal == Signal::SIGKILL;
+            w.field_str("limit", if hard { "hard" } else { "soft" });
+            w.field_i64("command_exit_code", i64::from(status.code().unwrap_or(-1)));
+            w.field_u64("exit_code", u64::from(exit_code));
+            w.field_u64("elapsed_ms", elapsed_ms);
+            w.field_u64("limit_ms", *limit_ms);
+            w.field_u64("actual_ms", *actual_ms);
+            write_rusage(&mut w, Some(rusage));
         }
         RunResult::SignalForwarded {
             signal,
             status,
             rusage,
         } => {
-            let sig_num = procguard::signal::signal_number(*signal);
-            let status_code = status.and_then(|s| s.code()).unwrap_or(-1);
-            let mut json = String::with_capacity(256);
-            let _ = write!(
-                json,
-                r#"{{"schema_version":{},"status":"signal_forwarded","clock":"{}","signal":"{}","signal_num":{},"command_exit_code":{},"exit_code":{},"elapsed_ms":{}"#,
-                SCHEMA_VERSION,
-                clock_str,
-                procguard::signal::signal_name(*signal),
-                sig_num,
-                status_code,
-                exit_code,
-                elapsed_ms
+            write_signal(&mut w, *signal);
+            write_external(&mut w, result);
+            w.field_i64(
+                "command_exit_code",
+                i64::from(status.and_then(|s| s.code()).unwrap_or(-1)),
             );
-            append_rusage(&mut json, rusage.as_ref());
-            append_attempts(&mut json, attempts, retry_count);
-            append_limits(&mut json, limits, cpu_throttle);
-            json.push('}');
-            println!("{}", json);
+            w.field_u64("exit_code", u64::from(exit_code));
+            w.field_u64("elapsed_ms", elapsed_ms);
+            write_rusage(&mut w, rusage.as_ref());
         }
         _ => {
             /* future-proof for #[non_exhaustive] - unknown variant */
-            let mut json = String::with_capacity(128);
-            let _ = write!(
-                json,
-                r#"{{"schema_version":{},"status":"unknown","clock":"{}","exit_code":{},"elapsed_ms":{}"#,
-                SCHEMA_VERSION, clock_str, exit_code, elapsed_ms
-            );
-            append_attempts(&mut json, attempts, retry_count);
-            append_limits(&mut json, limits, cpu_throttle);
-            json.push('}');
-            println!("{}", json);
+            w.field_u64("exit_code", u64::from(exit_code));
+            w.field_u64("elapsed_ms", elapsed_ms);
         }
     }
+
+    write_exit_reason(&mut w, result);
+    write_argv(&mut w, argv);
+    write_attempts(&mut w, attempts, retry_count);
+    write_limits(&mut w, limits, cpu_throttle, result.resource_usage());
+    write_monitoring(&mut w, events, waits);
+    write_process_tree(&mut w, events.tree());
+    write_warnings(&mut w, events);
+    w.end_object();
+    w.finish()
 }
 
-fn print_json_error(err: &procguard::error::TimeoutError, elapsed_ms: u64) {
-    const SCHEMA_VERSION: u8 = 8;
+/* the JSON names for the clock and the timeout reason */
+fn clock_name(confine: Confine) -> &'static str {
+    match confine {
+        Confine::Wall => "wall",
+        Confine::Active => "active",
+        Confine::Hybrid => "hybrid",
+        _ => "unknown", /* future-proof for #[non_exhaustive] */
+    }
+}
 
-    let exit_code = err.exit_code();
-    /* Escape control characters for valid JSON */
-    let msg = escape_json_string(&err.to_string());
-    println!(
-        r#"{{"schema_version":{},"status":"error","error":"{}","exit_code":{},"elapsed_ms":{}}}"#,
-        SCHEMA_VERSION, msg, exit_code, elapsed_ms
+fn timeout_reason_name(reason: TimeoutReason) -> &'static str {
+    match reason {
+        TimeoutReason::WallClock => "wall_clock",
+        TimeoutReason::StdinIdle => "stdin_idle",
+        TimeoutReason::SystemSleep => "system_sleep",
+        TimeoutReason::WindowClosed => "window_closed",
+        TimeoutReason::ChildStopped => "child_stopped",
+        _ => "unknown", /* future-proof for #[non_exhaustive] */
+    }
+}
+
+/*
+ * what every result ends with, one run or a --every repeat: whether
+ * forwarding worked, how late our wakeups ran, the sleep, the waits
+ * before the command started and the timeline
+ */
+fn write_monitoring(w: &mut JsonWriter<JsonBuf>, events: &EventLog, waits: &Waits) {
+    /* false if signals sent to us may not have reached the command; the
+     * warning says why */
+    let forwarding = !events
+        .warnings()
+        .iter()
+        .any(|w| matches!(w, Warning::SignalForwardingUnavailable { .. }));
+    w.field_bool("signal_forwarding", forwarding);
+
+    let lag: MonitorLag = events.lag();
+    w.key("monitor_lag_ms").begin_object();
+    w.field_u64("samples", u64::from(lag.samples));
+    w.field_u64("max", lag.max_ns / 1_000_000);
+    w.field_u64("mean", lag.mean_ns() / 1_000_000);
+    w.end_object();
+
+    w.field_u64("slept_ms", events.slept_ns() / 1_000_000);
+    w.key("child_stopped_ms").opt_i64(
+        events
+            .child_stopped_ns()
+            .map(|ns| i64::try_from(ns / 1_000_000).unwrap_or(i64::MAX)),
     );
+    w.field_u64("extended_ms", events.extended_ns() / 1_000_000);
+    w.field_u64("spawn_retries", u64::from(events.spawn_retries()));
+    w.key("translated");
+    match events.translated() {
+        Some(translated) => w.bool(translated),
+        None => w.null(),
+    };
+    w.key("child_traced");
+    match events.traced() {
+        Some(traced) => w.bool(traced),
+        None => w.null(),
+    };
+    let (ticks, missed) = events.heartbeats();
+    w.key("heartbeats").begin_object();
+    w.field_u64("ticks", u64::from(ticks));
+    w.field_u64("missed", missed);
+    w.end_object();
+    w.key("orphans");
+    if let Some(orphans) = events.orphans() {
+        w.begin_object();
+        w.field_u64("swept", u64::from(orphans.swept));
+        w.field_u64("killed", u64::from(orphans.killed));
+        w.end_object();
+    } else {
+        w.null();
+    }
+    w.key("also_kill");
+    if let Some(targets) = events.also_killed() {
+        w.begin_array();
+        for target in targets {
+            w.begin_object();
+            w.field_i64("pid", i64::from(target.pid));
+            w.key("signal");
+            match target.signal {
+                Some(sig) => w.str(procguard::signal::signal_name(sig)),
+                None => w.null(),
+            };
+            w.field_bool("killed", target.killed);
+            w.key("errno")
+                .opt_i64(target.error.map(|e| i64::from(e.raw())));
+            w.end_object();
+        }
+        w.end_array();
+    } else {
+        w.null();
+    }
+    if let Some(ms) = waits.window_ms {
+        w.field_u64("window_wait_ms", ms);
+    }
+    if let Some(ms) = waits.lock_ms {
+        w.field_u64("lock_wait_ms", ms);
+    }
+    if let Some(queue) = waits.lock_queue {
+        w.key("lock_queue").begin_object();
+        w.field_u64("ahead", u64::from(queue.ahead));
+        w.field_u64(
+            "queued_ms",
+            u64::try_from(queue.queued.as_millis()).unwrap_or(u64::MAX),
+        );
+        w.end_object();
+    }
+    if let Some(stats) = events.throttle() {
+        w.key("throttle").begin_object();
+        w.field_u64("stopped_ms", stats.stopped_ns / 1_000_000);
+        w.field_u64("stops", u64::from(stats.stops));
+        w.key("cpu_percent").tenths(stats.cpu_percent_tenths());
+        w.end_object();
+    }
+
+    /* the event timeline with its epoch anchor */
+    w.key("timeline").begin_object();
+    w.field_u64("origin_epoch_ms", events.origin_epoch_ms());
+    w.field_u64("dropped", u64::from(events.dropped()));
+    w.key("events");
+    events.write_json(w);
+    w.end_object();
 }
 
-/* escape string for JSON - handles quotes, backslashes, control chars */
-fn escape_json_string(s: &str) -> String {
-    let mut result = String::with_capacity(s.len());
-    for c in s.chars() {
-        match c {
-            '"' => result.push_str("\\\""),
-            '\\' => result.push_str("\\\\"),
-            '\n' => result.push_str("\\n"),
-            '\r' => result.push_str("\\r"),
-            '\t' => result.push_str("\\t"),
-            c if c < '\x20' => {
-                /* Escape other control characters as \uXXXX */
-                let _ = write!(result, "\\u{:04x}", c as u32);
-            }
-            c => result.push(c),
+/*
+ * pass/fail counts and durations over the runs of a repeat or the attempts
+ * of a --retry. percentiles are RunStats's: within 1/16 above 32ms
+ */
+fn write_aggregate(w: &mut JsonWriter<JsonBuf>, stats: &RunStats, flaky_threshold: Option<u32>) {
+    w.key("aggregate").begin_object();
+    w.field_u64("runs", u64::from(stats.runs()));
+    w.field_u64("passed", u64::from(stats.passed()));
+    w.field_u64("failed", u64::from(stats.failed()));
+    w.key("success_pct")
+        .tenths(u64::from(stats.success_permille().unwrap_or(0)));
+    w.key("first_failure")
+        .opt_i64(stats.first_failure().map(i64::from));
+    for (key, ms) in [
+        ("min_ms", stats.min_ms()),
+        ("mean_ms", stats.mean_ms()),
+        ("p50_ms", stats.percentile_ms(50)),
+        ("p95_ms", stats.percentile_ms(95)),
+        ("max_ms", stats.max_ms()),
+    ] {
+        w.field_u64(key, ms.unwrap_or(0));
+    }
+    if let Some(threshold) = flaky_threshold {
+        w.key("flaky_threshold_pct").tenths(u64::from(threshold));
+        w.field_bool("threshold_exceeded", stats.failures_exceed(threshold));
+    }
+    w.end_object();
+}
+
+/*
+ * --every, --runs: the aggregate report. one entry per run, the most recent
+ * REPEAT_RESULTS of them; the counts cover every run
+ */
+fn json_repeat(
+    report: &RepeatReport,
+    exit_code: u8,
+    elapsed_ms: u64,
+    confine: Confine,
+    waits: &Waits,
+    events: &EventLog,
+    argv: &[Vec<u8>],
+) -> JsonBuf {
+    let ms = |d: Duration| u64::try_from(d.as_millis()).unwrap_or(u64::MAX);
+
+    let mut w = JsonWriter::on(JsonBuf::new());
+    run_id::begin_result(&mut w, events.run_id());
+    w.field_str("status", "repeat");
+    if let Some(flakiness) = report.flakiness {
+        w.field_str("classification", flakiness.as_str());
+    }
+    w.field_str("clock", clock_name(confine));
+    w.field_u64("exit_code", u64::from(exit_code));
+    w.field_u64("elapsed_ms", elapsed_ms);
+    write_argv(&mut w, argv);
+
+    w.key("repeat").begin_object();
+    if let Some(schedule) = report.schedule {
+        w.field_u64("every_ms", ms(schedule.every()));
+        w.field_u64("for_ms", ms(schedule.budget()));
+    }
+    if let Some(n) = report.max_runs {
+        w.field_u64("max_runs", u64::from(n));
+    }
+    w.field_str("stop_on", report.stop_on.as_str());
+    w.field_str("stopped", report.stopped);
+    w.field_u64("runs", u64::from(report.stats.runs()));
+    w.field_u64("failed", u64::from(report.stats.failed()));
+    w.field_u64("skipped", report.skipped);
+    w.field_u64(
+        "dropped",
+        u64::from(report.stats.runs()) - report.results.len() as u64,
+    );
+    w.key("results").begin_array();
+    for run in &report.results {
+        w.begin_object();
+        w.field_u64("started_ms", run.started_ms);
+        w.field_str("status", run.outcome.as_str());
+        w.field_u64("exit_code", u64::from(run.exit_code));
+        w.field_u64("elapsed_ms", run.elapsed_ms);
+        if let Outcome::TimedOut { reason, .. } = run.outcome {
+            w.field_str("timeout_reason", timeout_reason_name(reason));
+        }
+        if let Some(signal) = run.signal {
+            w.field_str("signal", procguard::signal::signal_name(signal));
+        }
+        if let Some(attempts) = run.attempts {
+            w.field_u64("attempts", u64::from(attempts));
+        }
+        w.end_object();
+    }
+    w.end_array();
+    w.end_object();
+    write_aggregate(&mut w, &report.stats, report.flaky_threshold);
+
+    write_monitoring(&mut w, events, waits);
+    write_warnings(&mut w, events);
+    w.end_object();
+    w.finish()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn json_wait_pid(
+    args: &OwnedArgs,
+    pid: i32,
+    timeout: Option<Duration>,
+    waited: PidWait,
+    signal: Option<Signal>,
+    exit_code: u8,
+    elapsed_ms: u64,
+    events: &EventLog,
+) -> JsonBuf {
+    let mut w = JsonWriter::on(JsonBuf::new());
+    run_id::begin_result(&mut w, events.run_id());
+    w.field_str("status", "wait_pid");
+    w.field_str("clock", clock_name(args.confine));
+    w.field_u64("exit_code", u64::from(exit_code));
+    w.field_u64("elapsed_ms", elapsed_ms);
+    if let Some(d) = timeout {
+        w.field_u64(
+            "timeout_ms",
+            u64::try_from(d.as_millis()).unwrap_or(u64::MAX),
+        );
+    }
+    w.field_i64("pid", i64::from(pid));
+    let outcome = match waited {
+        _ if signal.is_some() => "interrupted",
+        PidWait::Exited => "exited",
+        PidWait::TimedOut => "running",
+        _ => "not_running",
+    };
+    w.field_str("outcome", outcome);
+    if let Some(sig) = signal {
+        w.field_str("signal", procguard::signal::signal_name(sig));
+    }
+    write_warnings(&mut w, events);
+    w.end_object();
+    w.finish()
+}
+
+fn json_error(err: &TimeoutError, elapsed_ms: u64, argv: &[Vec<u8>], events: &EventLog) -> JsonBuf {
+    let mut w = JsonWriter::on(JsonBuf::new());
+    run_id::begin_result(&mut w, events.run_id());
+    w.field_str("status", "error");
+    w.key("error").display(err);
+    if let Some(kind) = err.error_kind() {
+        w.field_str("error_kind", kind);
+    }
+    if let TimeoutError::NotRunnable(lookup) = err {
+        write_lookup(&mut w, lookup);
+    }
+    w.field_u64("exit_code", u64::from(err.exit_code()));
+    w.field_u64("elapsed_ms", elapsed_ms);
+    write_argv(&mut w, argv);
+    write_warnings(&mut w, events);
+    w.end_object();
+    w.finish()
+}
+
+/* where the command was looked for, and what was found in each place */
+fn write_lookup(w: &mut JsonWriter<JsonBuf>, lookup: &NotRunnable) {
+    if let Some(path) = lookup.path() {
+        w.field_str("command_path", path);
+    }
+    w.key("searched").begin_array();
+    for candidate in &lookup.searched {
+        w.begin_object();
+        w.field_str("path", &candidate.path);
+        w.field_str("problem", candidate.problem.as_str());
+        w.field_i64("errno", i64::from(candidate.errno));
+        w.end_object();
+    }
+    w.end_array();
+    if let Some(arch) = lookup.wanted {
+        w.field_str("arch", arch);
+    }
+    if !lookup.built_for.is_empty() {
+        w.key("built_for").begin_array();
+        for arch in &lookup.built_for {
+            w.str(arch);
         }
+        w.end_array();
+    }
+}
+
+/*
+ * what ran, exactly: argv bytes go to the child untouched, so they needn't
+ * be UTF-8. the strings are lossy; if anything was lost, argv_base64 has the
+ * real bytes (command first).
+ */
+fn write_argv(w: &mut JsonWriter<JsonBuf>, argv: &[Vec<u8>]) {
+    let Some((command, args)) = argv.split_first() else {
+        return;
+    };
+    w.key("command").bytes_lossy(command);
+    w.key("args").begin_array();
+    for arg in args {
+        w.bytes_lossy(arg);
+    }
+    w.end_array();
+
+    let lossy = argv.iter().any(|a| core::str::from_utf8(a).is_err());
+    w.field_bool("argv_lossy", lossy);
+    if lossy {
+        w.key("argv_base64").begin_array();
+        for arg in argv {
+            w.base64(arg);
+        }
+        w.end_array();
+    }
+}
+
+/* the warnings array - always present so consumers needn't probe for it */
+fn write_warnings(w: &mut JsonWriter<JsonBuf>, events: &EventLog) {
+    w.key("warnings").begin_array();
+    for warning in events.warnings().iter() {
+        w.begin_object();
+        w.field_str("code", warning.code());
+        w.key("message").display(warning);
+        w.end_object();
     }
-    result
+    w.end_array();
 }
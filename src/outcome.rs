@@ -0,0 +1,282 @@
+/*
+ * outcome.rs
+ *
+ * what happened to a run, in one flat enum.
+ *
+ * RunResult carries everything we know (statuses, rusage, hook, child
+ * state); Outcome is the verdict callers actually branch on - did it
+ * succeed, fail, crash, time out, hit a limit or get a forwarded signal.
+ * the CLI derives both its exit code and the JSON "status" from it, so a
+ * library caller classifying results agrees with the binary.
+ */
+
+use crate::runner::{RunResult, TimeoutReason};
+use crate::signal::Signal;
+
+/// Classification of a [`RunResult`].
+///
+/// # Example
+///
+/// ```no_run
+/// use procguard::{Outcome, RunConfig, run_command, setup_signal_forwarding, signal_name};
+///
+/// let _guard = setup_signal_forwarding();
+/// let result = run_command("make", &["test".to_string()], &RunConfig::default()).unwrap();
+/// match Outcome::from(&result) {
+///     Outcome::Success { .. } => println!("passed"),
+///     Outcome::Failed { code } => println!("failed with {code}"),
+///     Outcome::Crashed { signal } => println!("crashed: {}", signal_name(signal)),
+///     other => println!("{}", other.as_str()),
+/// }
+/// ```
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Outcome {
+    /// Ran to completion and exited 0.
+    Success {
+        /// Always 0.
+        code: u8,
+    },
+    /// Hit the timeout (wall clock or stdin idle). `killed` if it took
+    /// SIGKILL after `--kill-after`.
+    TimedOut {
+        /// What ran out.
+        reason: TimeoutReason,
+        /// Whether it took SIGKILL.
+        killed: bool,
+    },
+    /// Died of a crash signal on its own (see [`Signal::is_crash`]).
+    Crashed {
+        /// The signal it died of.
+        signal: Signal,
+    },
+    /// Ran to completion with a nonzero code, or was killed by a signal
+    /// that isn't a crash (someone else's SIGTERM). `code` is what a shell
+    /// would report: the exit code, or 128 + signal.
+    Failed {
+        /// The exit code, or 128 + signal.
+        code: u8,
+    },
+    /// We received `signal` and forwarded it to the command.
+    ForwardedSignal {
+        /// The signal passed on.
+        signal: Signal,
+    },
+    /// Killed for exceeding a resource limit.
+    LimitExceeded {
+        /// Which limit.
+        kind: LimitKind,
+    },
+}
+
+/// Which limit [`Outcome::LimitExceeded`] refers to.
+///
+/// ```
+/// use procguard::{LimitKind, Outcome};
+///
+/// let outcome = Outcome::LimitExceeded { kind: LimitKind::Memory };
+/// assert_eq!(outcome.as_str(), "memory_limit");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LimitKind {
+    /// `--mem-limit`
+    Memory,
+    /// `--cpu-time`: the kernel's SIGXCPU or SIGKILL at RLIMIT_CPU
+    Cpu,
+}
+
+impl Outcome {
+    /// The JSON `status` string for this outcome: "completed" (both
+    /// [`Success`](Self::Success) and [`Failed`](Self::Failed)), "crashed",
+    /// "timeout", "memory_limit", "cpu_limit" or "signal_forwarded".
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Success { .. } | Self::Failed { .. } => "completed",
+            Self::Crashed { .. } => "crashed",
+            Self::TimedOut { .. } => "timeout",
+            Self::LimitExceeded {
+                kind: LimitKind::Memory,
+            } => "memory_limit",
+            Self::LimitExceeded {
+                kind: LimitKind::Cpu,
+            } => "cpu_limit",
+            Self::ForwardedSignal { .. } => "signal_forwarded",
+        }
+    }
+
+    /// True for [`Success`](Self::Success) only.
+    #[must_use]
+    pub const fn is_success(self) -> bool {
+        matches!(self, Self::Success { .. })
+    }
+}
+
+impl From<&RunResult> for Outcome {
+    fn from(result: &RunResult) -> Self {
+        match result {
+            RunResult::Completed { status, .. } => {
+                if let Some(signal) = status
+                    .signal()
+                    .and_then(Signal::try_from_raw)
+                    .filter(|sig| sig.is_crash())
+                {
+                    return Self::Crashed { signal };
+                }
+                match crate::runner::status_to_exit_code(status) {
+                    0 => Self::Success { code: 0 },
+                    code => Self::Failed { code },
+                }
+            }
+            RunResult::TimedOut { reason, killed, .. } => Self::TimedOut {
+                reason: *reason,
+                killed: *killed,
+            },
+            RunResult::MemoryLimitExceeded { .. } => Self::LimitExceeded {
+                kind: LimitKind::Memory,
+            },
+            RunResult::CpuLimitExceeded { .. } => Self::LimitExceeded {
+                kind: LimitKind::Cpu,
+            },
+            RunResult::SignalForwarded { signal, .. } => Self::ForwardedSignal { signal: *signal },
+        }
+    }
+}
+
+impl From<RunResult> for Outcome {
+    fn from(result: RunResult) -> Self {
+        Self::from(&result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{ChildState, RunConfig, run_command};
+    use core::time::Duration;
+
+    fn run_sh(script: &str) -> RunResult {
+        let config = RunConfig {
+            timeout: Duration::from_secs(10),
+            ..RunConfig::default()
+        };
+        let args = ["-c".to_string(), script.to_string()];
+        run_command("sh", &args, &config).unwrap()
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] /* spawns a process */
+    fn test_completed_outcomes() {
+        assert_eq!(
+            Outcome::from(run_sh("exit 0")),
+            Outcome::Success { code: 0 }
+        );
+        assert_eq!(Outcome::from(run_sh("exit 3")), Outcome::Failed { code: 3 });
+        assert_eq!(
+            Outcome::from(run_sh("ulimit -c 0; kill -SEGV $$")),
+            Outcome::Crashed {
+                signal: Signal::SIGSEGV
+            }
+        );
+        /* killed from outside, not a crash */
+        assert_eq!(
+            Outcome::from(run_sh("kill -TERM $$")),
+            Outcome::Failed { code: 143 }
+        );
+    }
+
+    #[test]
+    fn test_interrupted_outcomes() {
+        let timed_out = RunResult::TimedOut {
+            signal: Signal::SIGTERM,
+            killed: true,
+            status: None,
+            rusage: None,
+            hook: None,
+            reason: TimeoutReason::StdinIdle,
+            child_state: ChildState::Running,
+        };
+        assert_eq!(
+            Outcome::from(&timed_out),
+            Outcome::TimedOut {
+                reason: TimeoutReason::StdinIdle,
+                killed: true
+            }
+        );
+
+        let mem = RunResult::MemoryLimitExceeded {
+            signal: Signal::SIGKILL,
+            killed: true,
+            status: None,
+            rusage: None,
+            limit_bytes: 1024,
+            actual_bytes: 2048,
+            hook: None,
+            child_state: ChildState::Signaled,
+        };
+        assert_eq!(
+            Outcome::from(mem),
+            Outcome::LimitExceeded {
+                kind: LimitKind::Memory
+            }
+        );
+
+        let forwarded = RunResult::SignalForwarded {
+            signal: Signal::SIGINT,
+            status: None,
+            rusage: None,
+        };
+        assert_eq!(
+            Outcome::from(forwarded),
+            Outcome::ForwardedSignal {
+                signal: Signal::SIGINT
+            }
+        );
+    }
+
+    #[test]
+    fn test_as_str() {
+        assert_eq!(Outcome::Success { code: 0 }.as_str(), "completed");
+        assert_eq!(Outcome::Failed { code: 1 }.as_str(), "completed");
+        assert_eq!(
+            Outcome::Crashed {
+                signal: Signal::SIGABRT
+            }
+            .as_str(),
+            "crashed"
+        );
+        assert_eq!(
+            Outcome::TimedOut {
+                reason: TimeoutReason::WallClock,
+                killed: false
+            }
+            .as_str(),
+            "timeout"
+        );
+        assert_eq!(
+            Outcome::LimitExceeded {
+                kind: LimitKind::Memory
+            }
+            .as_str(),
+            "memory_limit"
+        );
+        assert_eq!(
+            Outcome::LimitExceeded {
+                kind: LimitKind::Cpu
+            }
+            .as_str(),
+            "cpu_limit"
+        );
+        assert_eq!(
+            Outcome::ForwardedSignal {
+                signal: Signal::SIGTERM
+            }
+            .as_str(),
+            "signal_forwarded"
+        );
+        assert!(Outcome::Success { code: 0 }.is_success());
+        assert!(!Outcome::Failed { code: 1 }.is_success());
+    }
+}
@@ -0,0 +1,821 @@
+/*
+ * output.rs
+ *
+ * output interposition: the command's stdout and stderr go into pipes we
+ * read, and we copy them to our own stdout and stderr, rewriting each line
+ * on the way (--timestamps, then --prefix-output). anything that needs to
+ * see the command's output as it happens hooks in here. --raw-output turns
+ * the rewriting off.
+ *
+ * the pipes' write ends are dup'd onto the command's fds 1 and 2 at spawn
+ * and closed on our side right after, so a stream ends (EOF) once the
+ * command and everything it started have closed it. the read ends are
+ * non-blocking and sit in the run's wait loop as two more readable fds;
+ * each wakeup reads what's there and writes it out at once.
+ *
+ * lines aren't buffered: a chunk is written as soon as it's read, with the
+ * prefix inserted at each line start. whether the next byte starts a line
+ * is carried across reads, so a line that arrives in pieces gets one
+ * prefix, and a prompt without a newline still shows up right away.
+ *
+ * the command sees pipes, not the terminal - programs that check isatty()
+ * may buffer or drop colors. bytes are passed through as they are, binary
+ * included; only line starts are touched. writing is blocking: if our own
+ * stdout is slow, so is the command, as it would be without us.
+ *
+ * a timestamp is the time a line's first byte was read, not written - the
+ * pipe adds no delay worth mentioning, the command's own buffering might.
+ * each stream is checked for UTF-8 as it goes (a character split across
+ * reads is fine); at the first invalid byte its timestamps stop for good
+ * with a warning, since lines in binary output are coincidence. the prefix
+ * stays: it was asked for by name.
+ *
+ * --merge-output makes it one pipe, dup'd onto both fds 1 and 2 and
+ * copied to our stdout: the kernel orders the writes, so lines come out
+ * in the order the command wrote them, each prefixed and stamped once.
+ * the second stream slot sits unused, already ended.
+ *
+ * --output-rate-limit is a token bucket shared by both streams, refilled
+ * at the rate and holding up to one second's worth. 'drop' reads as usual
+ * and throws away what the bucket can't cover, counting it per stream; the
+ * count goes out as a "[...N bytes suppressed...]" line ahead of the next
+ * output that gets through (or at the end); after a drop, output resumes
+ * only once a tenth of a second's worth is back, so markers don't come
+ * one per byte. 'block' reads no more than the bucket holds: with it
+ * empty, paused_ns() tells the wait loop to stop watching the pipes for a
+ * while, the pipe fills, and the command's writes block until we read
+ * again. 'block' delays output but never loses it: once the command is
+ * gone, what's left in the pipe goes out at once.
+ *
+ * after the command is reaped, finish() drains what's left without
+ * blocking. a background process still holding a pipe open keeps it from
+ * ending; whatever it writes after that point is lost.
+ */
+
+use alloc::vec::Vec;
+
+use crate::args::{Confine, RatePolicy, Timestamps};
+use crate::errno::errno;
+use crate::events::EventLog;
+use crate::log::{Logger, Warning};
+use crate::runner::RunConfig;
+
+/* read size per wakeup */
+const CHUNK: usize = 16 * 1024;
+
+/* 'block' resumes once this fraction of a second's worth is back */
+const RESUME_DIVISOR: u64 = 10;
+
+/// Does `config` need the command's output to go through us?
+pub(crate) fn wanted(config: &RunConfig) -> bool {
+    config.merge_output || config.output_rate_limit.is_some() || rewrites(config)
+}
+
+/* are lines rewritten at all? */
+fn rewrites(config: &RunConfig) -> bool {
+    !config.raw_output && (config.prefix_output.is_some() || config.timestamps.is_some())
+}
+
+/// How each line is rewritten.
+struct LineFormat {
+    prefix: Vec<u8>,
+    timestamps: Option<Timestamps>,
+    /* wall clock at open(), for relative timestamps */
+    started_ns: u64,
+}
+
+impl LineFormat {
+    /* the timestamp for lines starting now, with its separator; nothing
+     * without --timestamps */
+    fn timestamp(&self, out: &mut Vec<u8>) {
+        match self.timestamps {
+            None => {}
+            Some(Timestamps::Relative) => {
+                let now = crate::runner::event_now(Confine::Wall);
+                push_relative(out, now.saturating_sub(self.started_ns) / 1_000_000);
+                out.push(b' ');
+            }
+            Some(Timestamps::Absolute) => {
+                push_absolute(out, crate::events::realtime_ms());
+                out.push(b' ');
+            }
+        }
+    }
+}
+
+/* --output-rate-limit: bytes we may still pass on */
+struct RateLimit {
+    policy: RatePolicy,
+    /* bytes per second, also the bucket size */
+    rate: u64,
+    tokens: u64,
+    /* came up short: hold off until there's a useful amount again, not
+     * a marker line per byte earned */
+    short: bool,
+    /* wall clock at the last refill */
+    refilled_ns: u64,
+}
+
+impl RateLimit {
+    fn new(policy: RatePolicy, rate: u64, now_ns: u64) -> Self {
+        Self {
+            policy,
+            rate,
+            tokens: rate,
+            short: false,
+            refilled_ns: now_ns,
+        }
+    }
+
+    /* what's left now */
+    fn available(&mut self, now_ns: u64) -> u64 {
+        let elapsed = now_ns.saturating_sub(self.refilled_ns);
+        let earned = u128::from(elapsed) * u128::from(self.rate) / 1_000_000_000;
+        /* less than a byte earned: keep the clock where it was, or slow
+         * trickles never add up */
+        if earned > 0 {
+            let earned = u64::try_from(earned).unwrap_or(u64::MAX);
+            self.tokens = self.tokens.saturating_add(earned).min(self.rate);
+            self.refilled_ns = now_ns;
+        }
+        self.tokens
+    }
+
+    /* spend up to n; returns what was covered */
+    fn take(&mut self, n: u64, now_ns: u64) -> u64 {
+        let available = self.available(now_ns);
+        if self.short && available < self.resume_at() {
+            return 0;
+        }
+        let covered = n.min(available);
+        self.tokens -= covered;
+        self.short = covered < n;
+        covered
+    }
+
+    /* what's worth going on with: a tenth of a second's worth */
+    fn resume_at(&self) -> u64 {
+        (self.rate / RESUME_DIVISOR).max(1)
+    }
+
+    /* with the bucket empty: how long until it's worth reading again */
+    fn wait_ns(&self) -> u64 {
+        let missing = self.resume_at().saturating_sub(self.tokens);
+        let ns = (u128::from(missing) * 1_000_000_000).div_ceil(u128::from(self.rate));
+        u64::try_from(ns).unwrap_or(u64::MAX)
+    }
+}
+
+/* one of the command's output streams */
+struct Stream {
+    /* our read end, -1 once closed */
+    fd: i32,
+    /* where it goes: 1 or 2 */
+    target: i32,
+    /* the next byte read starts a line */
+    at_line_start: bool,
+    /* EOF seen; the fd stays open (and registered) until finish() */
+    ended: bool,
+    /* timestamps still on: everything so far was UTF-8 */
+    text: bool,
+    /* an incomplete UTF-8 sequence at the end of the last read */
+    utf8_tail: Vec<u8>,
+    /* bytes dropped by --output-rate-limit, not reported yet */
+    suppressed: u64,
+}
+
+impl Stream {
+    const fn new(fd: i32, target: i32) -> Self {
+        Self {
+            fd,
+            target,
+            at_line_start: true,
+            ended: false,
+            text: true,
+            utf8_tail: Vec::new(),
+            suppressed: 0,
+        }
+    }
+
+    /* a slot with nothing behind it (--merge-output's second) */
+    const fn unused() -> Self {
+        let mut stream = Self::new(-1, -1);
+        stream.ended = true;
+        stream
+    }
+}
+
+/// The command's side of the pipes, to dup onto its fds at spawn. Closed
+/// on drop - drop it right after spawning, or the streams never end.
+pub(crate) struct ChildEnds {
+    dups: [(i32, i32); 2],
+}
+
+impl ChildEnds {
+    /// (our fd, the command's fd) pairs for the spawn.
+    pub(crate) fn dups(&self) -> &[(i32, i32)] {
+        &self.dups
+    }
+}
+
+impl Drop for ChildEnds {
+    fn drop(&mut self) {
+        let [(first, _), (second, _)] = self.dups;
+        /* merged: both pairs share one write end */
+        let second = (second != first).then_some(second);
+        for fd in [Some(first), second].into_iter().flatten() {
+            // SAFETY: fd came from open_pipe() and is closed exactly once here
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
+/// Our side: copies the command's output to ours, rewriting lines.
+pub(crate) struct Interposer {
+    format: LineFormat,
+    limit: Option<RateLimit>,
+    streams: [Stream; 2],
+    buf: Vec<u8>,
+    out: Vec<u8>,
+    /* the current timestamp, and room to check UTF-8 across reads */
+    stamp: Vec<u8>,
+    scratch: Vec<u8>,
+}
+
+impl Interposer {
+    /// Pipes for the command's stdout and stderr, if `config` wants them.
+    /// `Err(errno)` if they couldn't be made (fd limit).
+    pub(crate) fn open(config: &RunConfig) -> Result<Option<(Self, ChildEnds)>, i32> {
+        if !wanted(config) {
+            return Ok(None);
+        }
+        let (out_read, out_write) = open_pipe()?;
+        let (err_stream, err_write) = if config.merge_output {
+            (Stream::unused(), out_write)
+        } else {
+            match open_pipe() {
+                Ok((err_read, err_write)) => (Stream::new(err_read, 2), err_write),
+                Err(errno) => {
+                    close_both(out_read, out_write);
+                    return Err(errno);
+                }
+            }
+        };
+        let rewrite = rewrites(config);
+        let now_ns = crate::runner::event_now(Confine::Wall);
+        let interposer = Self {
+            format: LineFormat {
+                prefix: match &config.prefix_output {
+                    Some(prefix) if rewrite => prefix.as_bytes().to_vec(),
+                    _ => Vec::new(),
+                },
+                timestamps: config.timestamps.filter(|_| rewrite),
+                started_ns: now_ns,
+            },
+            limit: config
+                .output_rate_limit
+                .map(|rate| RateLimit::new(config.output_rate_policy, rate, now_ns)),
+            streams: [Stream::new(out_read, 1), err_stream],
+            buf: Vec::new(),
+            out: Vec::new(),
+            stamp: Vec::new(),
+            scratch: Vec::new(),
+        };
+        let ends = ChildEnds {
+            dups: [(out_write, 1), (err_write, 2)],
+        };
+        Ok(Some((interposer, ends)))
+    }
+
+    /// Read ends still worth waiting on: not at EOF.
+    pub(crate) fn fds(&self) -> [Option<i32>; 2] {
+        self.streams
+            .each_ref()
+            .map(|s| (!s.ended && s.fd >= 0).then_some(s.fd))
+    }
+
+    /// `fd` is readable: copy what's there. False once it hit EOF - stop
+    /// waiting on it.
+    pub(crate) fn pump(&mut self, fd: i32, log: Logger<'_>, events: &mut EventLog) -> bool {
+        let Some(i) = self.streams.iter().position(|s| s.fd == fd) else {
+            return false;
+        };
+        self.copy(i, false, log, events);
+        !self.streams[i].ended
+    }
+
+    /// `--output-rate-policy block` ran out: stop waiting on the pipes for
+    /// this long. None when reading can go on.
+    pub(crate) fn paused_ns(&mut self) -> Option<u64> {
+        let limit = self.limit.as_mut()?;
+        let now_ns = crate::runner::event_now(Confine::Wall);
+        (limit.policy == RatePolicy::Block && limit.available(now_ns) == 0).then(|| limit.wait_ns())
+    }
+
+    /// The command is gone: copy whatever is still buffered in the pipes,
+    /// without waiting for more, and close them. 'block' doesn't hold
+    /// anything back here: it delays output, it doesn't lose it, and the
+    /// rest is at most a pipe's worth.
+    pub(crate) fn finish(&mut self, log: Logger<'_>, events: &mut EventLog) {
+        for i in 0..self.streams.len() {
+            if self.streams[i].fd < 0 {
+                continue;
+            }
+            while !self.streams[i].ended && self.copy(i, true, log, events) {}
+            if self.streams[i].suppressed > 0 {
+                self.out.clear();
+                self.suppressed_line(i, self.streams[i].text);
+                let _ = crate::io::write_all(self.streams[i].target, &self.out);
+            }
+            // SAFETY: fd came from open_pipe() and is closed exactly once:
+            // it's set to -1 right after
+            unsafe { libc::close(self.streams[i].fd) };
+            self.streams[i].fd = -1;
+        }
+    }
+
+    /* one read from stream i, rewritten and written out. true if there
+     * may be more right away. `finishing`: 'block' lets everything through */
+    fn copy(&mut self, i: usize, finishing: bool, log: Logger<'_>, events: &mut EventLog) -> bool {
+        if self.buf.len() < CHUNK {
+            self.buf.resize(CHUNK, 0);
+        }
+        let now_ns = crate::runner::event_now(Confine::Wall);
+        let want = match &mut self.limit {
+            Some(limit) if limit.policy == RatePolicy::Block && !finishing => {
+                usize::try_from(limit.available(now_ns)).map_or(CHUNK, |n| n.min(CHUNK))
+            }
+            _ => CHUNK,
+        };
+        if want == 0 {
+            return false;
+        }
+        let stream = &mut self.streams[i];
+        // SAFETY: stream.fd is our open read end; buf is valid for want <= CHUNK bytes
+        let n = unsafe { libc::read(stream.fd, self.buf.as_mut_ptr().cast(), want) };
+        let n = match n {
+            0 => {
+                stream.ended = true;
+                return false;
+            }
+            n if n < 0 => {
+                let err = errno();
+                /* EAGAIN: drained for now. anything else: treat as closed */
+                stream.ended = err != libc::EAGAIN && err != libc::EINTR;
+                return err == libc::EINTR;
+            }
+            n => n.unsigned_abs(),
+        };
+
+        /* what the rate lets through; 'drop' loses the rest. checked for
+         * UTF-8 as read, so a drop doesn't make the stream look binary */
+        let kept = match &mut self.limit {
+            Some(limit) if !(finishing && limit.policy == RatePolicy::Block) => {
+                let covered = limit.take(n as u64, now_ns);
+                usize::try_from(covered).unwrap_or(n)
+            }
+            _ => n,
+        };
+
+        let chunk = &self.buf[..n];
+
+        /* lines starting before this offset get a timestamp */
+        let stamp_until = if stream.text {
+            text_len(&mut stream.utf8_tail, chunk, &mut self.scratch)
+        } else {
+            0
+        };
+        if stream.text && stamp_until < n {
+            stream.text = false;
+            if self.format.timestamps.is_some() {
+                events.warn(log, Warning::TimestampsDisabled { fd: stream.target });
+            }
+        }
+        self.stamp.clear();
+        if stamp_until > 0 {
+            self.format.timestamp(&mut self.stamp);
+        }
+
+        self.out.clear();
+        if kept > 0 && stream.suppressed > 0 {
+            /* dropped earlier: say so before going on */
+            self.suppressed_line(i, stamp_until > 0);
+        }
+        let stream = &mut self.streams[i];
+        stream.suppressed = stream.suppressed.saturating_add((n - kept) as u64);
+        if kept == 0 {
+            return true;
+        }
+        let mut at = 0;
+        for line in self.buf[..kept].split_inclusive(|&b| b == b'\n') {
+            if stream.at_line_start {
+                if at < stamp_until {
+                    self.out.extend_from_slice(&self.stamp);
+                }
+                self.out.extend_from_slice(&self.format.prefix);
+            }
+            at += line.len();
+            self.out.extend_from_slice(line);
+            stream.at_line_start = line.ends_with(b"\n");
+        }
+        /* our stdout gone: nothing to do about it here - the command
+         * keeps running and its output is dropped */
+        let _ = crate::io::write_all(stream.target, &self.out);
+        true
+    }
+
+    /* "[...N bytes suppressed...]" for stream i, on a line of its own */
+    fn suppressed_line(&mut self, i: usize, stamp: bool) {
+        let stream = &mut self.streams[i];
+        if !stream.at_line_start {
+            self.out.push(b'\n');
+        }
+        if stamp {
+            self.format.timestamp(&mut self.out);
+        }
+        self.out.extend_from_slice(&self.format.prefix);
+        self.out.extend_from_slice(b"[...");
+        push_num(&mut self.out, stream.suppressed, 1);
+        self.out.extend_from_slice(b" bytes suppressed...]\n");
+        stream.at_line_start = true;
+        stream.suppressed = 0;
+    }
+}
+
+impl Drop for Interposer {
+    fn drop(&mut self) {
+        for stream in &self.streams {
+            if stream.fd >= 0 {
+                // SAFETY: fd came from open_pipe() and wasn't closed by finish()
+                unsafe { libc::close(stream.fd) };
+            }
+        }
+    }
+}
+
+/* how much of `chunk` continues the stream as valid UTF-8: all of it, or
+ * up to the first invalid byte. `tail` carries an incomplete character
+ * from one read to the next */
+fn text_len(tail: &mut Vec<u8>, chunk: &[u8], scratch: &mut Vec<u8>) -> usize {
+    let carried = tail.len();
+    let bytes = if tail.is_empty() {
+        chunk
+    } else {
+        scratch.clear();
+        scratch.extend_from_slice(tail);
+        scratch.extend_from_slice(chunk);
+        scratch.as_slice()
+    };
+    match core::str::from_utf8(bytes) {
+        Ok(_) => {
+            tail.clear();
+            chunk.len()
+        }
+        /* cut off mid-character: the rest may come with the next read */
+        Err(e) if e.error_len().is_none() => {
+            let rest = bytes[e.valid_up_to()..].to_vec();
+            *tail = rest;
+            chunk.len()
+        }
+        Err(e) => e.valid_up_to().saturating_sub(carried),
+    }
+}
+
+/* "HH:MM:SS.mmm", hours growing past 99 if need be */
+fn push_relative(out: &mut Vec<u8>, ms: u64) {
+    let secs = ms / 1000;
+    push_num(out, secs / 3600, 2);
+    out.push(b':');
+    push_num(out, secs / 60 % 60, 2);
+    out.push(b':');
+    push_num(out, secs % 60, 2);
+    out.push(b'.');
+    push_num(out, ms % 1000, 3);
+}
+
+/* "YYYY-MM-DDTHH:MM:SS.mmmZ" for ms since the Unix epoch */
+fn push_absolute(out: &mut Vec<u8>, epoch_ms: u64) {
+    let (year, month, day) = civil_from_days(epoch_ms / 86_400_000);
+    push_num(out, year, 4);
+    out.push(b'-');
+    push_num(out, month, 2);
+    out.push(b'-');
+    push_num(out, day, 2);
+    out.push(b'T');
+    push_relative(out, epoch_ms % 86_400_000);
+    out.push(b'Z');
+}
+
+/* days since 1970-01-01 -> (year, month, day), proleptic Gregorian.
+ * Howard Hinnant's civil_from_days, unsigned since we never go before 1970 */
+const fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/* n in decimal, zero-padded to at least `width` digits */
+fn push_num(out: &mut Vec<u8>, mut n: u64, width: usize) {
+    let mut digits = [0u8; 20];
+    let mut len = 0;
+    while n > 0 || len < width {
+        #[allow(clippy::cast_possible_truncation)]
+        let digit = (n % 10) as u8;
+        digits[len] = b'0' + digit;
+        n /= 10;
+        len += 1;
+    }
+    out.extend(digits[..len].iter().rev());
+}
+
+/*
+ * close-on-exec pipe: (read, write), read end non-blocking. neither end
+ * may land on 0-2 - with one of our own standard fds closed, pipe() would
+ * hand it out, and the wait loop takes fd 0 for stdin.
+ */
+fn open_pipe() -> Result<(i32, i32), i32> {
+    let mut fds = [0i32; 2];
+    // SAFETY: fds is a valid 2-element array, pipe() writes exactly 2 fds
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(errno());
+    }
+    for fd in &mut fds {
+        // SAFETY: fd was just returned by pipe(); F_DUPFD_CLOEXEC takes no
+        // pointers and returns a new fd >= 3 or -1
+        let moved = unsafe { libc::fcntl(*fd, libc::F_DUPFD_CLOEXEC, 3) };
+        // SAFETY: *fd is the original from pipe(), closed once here - the
+        // copy (or nothing, on error) replaces it
+        unsafe { libc::close(*fd) };
+        *fd = moved;
+    }
+    let [read, write] = fds;
+    // SAFETY: read is -1 or an fd we own; fcntl takes no pointers
+    #[allow(clippy::multiple_unsafe_ops_per_block)]
+    let nonblocking = read >= 0
+        && unsafe {
+            let flags = libc::fcntl(read, libc::F_GETFL);
+            flags >= 0 && libc::fcntl(read, libc::F_SETFL, flags | libc::O_NONBLOCK) >= 0
+        };
+    if write < 0 || !nonblocking {
+        let err = errno();
+        close_both(read, write);
+        return Err(err);
+    }
+    Ok((read, write))
+}
+
+fn close_both(read: i32, write: i32) {
+    for fd in [read, write] {
+        if fd >= 0 {
+            // SAFETY: fd is ours and closed exactly once here
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::{String, ToString};
+
+    fn prefixed(prefix: &str) -> RunConfig {
+        RunConfig {
+            prefix_output: Some(prefix.to_string()),
+            ..RunConfig::default()
+        }
+    }
+
+    fn run_chunks(prefix: &str, chunks: &[&[u8]]) -> Vec<u8> {
+        run_config(&prefixed(prefix), chunks)
+    }
+
+    /* write `chunks` into the stdout pipe, pump, read what came out of
+     * a stand-in for our stdout */
+    fn run_config(config: &RunConfig, chunks: &[&[u8]]) -> Vec<u8> {
+        run_recorded(config, chunks, &mut EventLog::new())
+    }
+
+    fn run_recorded(config: &RunConfig, chunks: &[&[u8]], events: &mut EventLog) -> Vec<u8> {
+        let (mut interposer, ends) = Interposer::open(config).unwrap().unwrap();
+        let (sink_read, sink_write) = open_pipe().unwrap();
+        interposer.streams[0].target = sink_write;
+        let (write_end, _) = ends.dups()[0];
+        let read_end = interposer.fds()[0].unwrap();
+        for chunk in chunks {
+            crate::io::write_all(write_end, chunk).unwrap();
+            assert!(interposer.pump(read_end, config.log(), events));
+        }
+        drop(ends);
+        interposer.finish(config.log(), events);
+
+        close_both(-1, sink_write);
+        let mut out = Vec::new();
+        let mut buf = [0u8; 256];
+        loop {
+            // SAFETY: sink_read is open, buf is valid for 256 bytes
+            let n = unsafe { libc::read(sink_read, buf.as_mut_ptr().cast(), buf.len()) };
+            if n <= 0 {
+                break;
+            }
+            out.extend_from_slice(&buf[..n.unsigned_abs()]);
+        }
+        close_both(sink_read, -1);
+        out
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] /* pipes are FFI */
+    fn test_prefix_each_line() {
+        assert_eq!(
+            run_chunks("[job] ", &[b"one\ntwo\n", b"three\n"]),
+            b"[job] one\n[job] two\n[job] three\n"
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] /* pipes are FFI */
+    fn test_partial_lines() {
+        /* a line in pieces gets one prefix; the last one has no newline */
+        assert_eq!(
+            run_chunks("> ", &[b"hel", b"lo\nwor", b"ld\n\n", b"prompt: "]),
+            b"> hello\n> world\n> \n> prompt: "
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] /* pipes are FFI */
+    fn test_bytes_untouched() {
+        assert_eq!(run_chunks("", &[b"\xff\x00\r\n\xfe"]), b"\xff\x00\r\n\xfe");
+    }
+
+    #[test]
+    fn test_not_wanted() {
+        assert!(!wanted(&RunConfig::default()));
+        assert!(Interposer::open(&RunConfig::default()).unwrap().is_none());
+        assert!(wanted(&prefixed("x")));
+        let raw = RunConfig {
+            timestamps: Some(Timestamps::Relative),
+            raw_output: true,
+            ..prefixed("x")
+        };
+        assert!(!wanted(&raw));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] /* pipes are FFI */
+    fn test_merge_one_pipe() {
+        let config = RunConfig {
+            merge_output: true,
+            raw_output: true,
+            ..prefixed("x ")
+        };
+        assert!(wanted(&config));
+        let (interposer, ends) = Interposer::open(&config).unwrap().unwrap();
+        let [(out, 1), (err, 2)] = *ends.dups() else {
+            panic!("dups: {:?}", ends.dups());
+        };
+        assert_eq!(out, err);
+        assert!(interposer.fds()[1].is_none());
+        /* --raw-output: piped, but nothing added */
+        assert!(interposer.format.prefix.is_empty());
+        drop(ends);
+        assert_eq!(run_config(&config, &[b"a\n", b"b\n"]), b"a\nb\n");
+    }
+
+    #[test]
+    fn test_rate_bucket() {
+        let mut limit = RateLimit::new(RatePolicy::Drop, 1000, 0);
+        assert_eq!(limit.take(600, 0), 600);
+        assert_eq!(limit.take(600, 0), 400);
+        assert_eq!(limit.available(0), 0);
+        /* a tenth of a second for a tenth of the rate */
+        assert_eq!(limit.wait_ns(), 100_000_000);
+        /* after coming up short, a few bytes aren't enough to go on */
+        assert_eq!(limit.take(600, 50_000_000), 0);
+        assert_eq!(limit.take(600, 100_000_000), 100);
+        assert_eq!(limit.available(500_000_000), 400);
+        /* never more than a second's worth */
+        assert_eq!(limit.available(60_000_000_000), 1000);
+        /* sub-byte trickles still add up */
+        let mut slow = RateLimit::new(RatePolicy::Drop, 1, 0);
+        assert_eq!(slow.take(1, 0), 1);
+        assert_eq!(slow.available(400_000_000), 0);
+        assert_eq!(slow.available(800_000_000), 0);
+        assert_eq!(slow.available(1_000_000_000), 1);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] /* pipes are FFI */
+    fn test_rate_limit_drop() {
+        let config = RunConfig {
+            output_rate_limit: Some(10),
+            ..prefixed("> ")
+        };
+        assert_eq!(
+            run_config(&config, &[b"0123456789abcdef\n", b"gone\n"]),
+            b"> 0123456789\n> [...12 bytes suppressed...]\n"
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] /* pipes are FFI */
+    fn test_rate_limit_block() {
+        let config = RunConfig {
+            output_rate_limit: Some(4),
+            output_rate_policy: RatePolicy::Block,
+            raw_output: true,
+            ..RunConfig::default()
+        };
+        assert!(wanted(&config));
+        let (mut interposer, ends) = Interposer::open(&config).unwrap().unwrap();
+        let (sink_read, sink_write) = open_pipe().unwrap();
+        interposer.streams[0].target = sink_write;
+        let (write_end, _) = ends.dups()[0];
+        crate::io::write_all(write_end, b"abcdefgh\n").unwrap();
+        let mut events = EventLog::new();
+        assert!(interposer.pump(interposer.fds()[0].unwrap(), config.log(), &mut events));
+        /* took 4, then stopped: the rest waits in the pipe */
+        assert!(interposer.paused_ns().is_some_and(|ns| ns > 0));
+        let mut buf = [0u8; 16];
+        // SAFETY: sink_read is open, buf is valid for 16 bytes
+        let n = unsafe { libc::read(sink_read, buf.as_mut_ptr().cast(), buf.len()) };
+        assert_eq!(&buf[..n.unsigned_abs()], b"abcd");
+        /* the command is gone: the rest comes out regardless */
+        drop(ends);
+        interposer.finish(config.log(), &mut events);
+        // SAFETY: as above
+        let n = unsafe { libc::read(sink_read, buf.as_mut_ptr().cast(), buf.len()) };
+        assert_eq!(&buf[..n.unsigned_abs()], b"efgh\n");
+        close_both(sink_read, sink_write);
+    }
+
+    #[test]
+    fn test_timestamp_formats() {
+        let format = |f: fn(&mut Vec<u8>, u64), v| {
+            let mut out = Vec::new();
+            f(&mut out, v);
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(format(push_relative, 0), "00:00:00.000");
+        assert_eq!(format(push_relative, 3_723_045), "01:02:03.045");
+        assert_eq!(format(push_relative, 360_000_000), "100:00:00.000");
+        assert_eq!(format(push_absolute, 0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(
+            format(push_absolute, 1_700_000_000_123),
+            "2023-11-14T22:13:20.123Z"
+        );
+        assert_eq!(
+            format(push_absolute, 951_782_400_000),
+            "2000-02-29T00:00:00.000Z"
+        );
+        assert_eq!(
+            format(push_absolute, 4_107_542_399_999),
+            "2100-02-28T23:59:59.999Z"
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] /* pipes are FFI */
+    fn test_timestamps_stop_at_binary() {
+        let config = RunConfig {
+            timestamps: Some(Timestamps::Relative),
+            ..prefixed("| ")
+        };
+        /* é split across reads is still text */
+        let mut events = EventLog::new();
+        let out = run_recorded(
+            &config,
+            &[b"caf\xc3", b"\xa9\nok\n", b"fine\n\xff\nbin\n", b"more\n"],
+            &mut events,
+        );
+        assert!(matches!(
+            events.warnings().iter().next(),
+            Some(Warning::TimestampsDisabled { .. })
+        ));
+        let out = String::from_utf8_lossy(&out);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 6, "{out}");
+        assert!(lines[0].starts_with("00:00:0") && lines[0].ends_with(" | café"));
+        assert!(lines[1].starts_with("00:00:0") && lines[1].ends_with(" | ok"));
+        /* the read with the bad byte: stamped up to it */
+        assert!(lines[2].starts_with("00:00:0") && lines[2].ends_with(" | fine"));
+        assert_eq!(lines[3], "| \u{fffd}");
+        assert_eq!(lines[4], "| bin");
+        assert_eq!(lines[5], "| more");
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] /* pipes are FFI */
+    fn test_pipe_avoids_standard_fds() {
+        let (read, write) = open_pipe().unwrap();
+        assert!(read > 2 && write > 2);
+        close_both(read, write);
+    }
+}
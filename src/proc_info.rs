@@ -10,7 +10,32 @@
  *
  * macOS SDK rusage_info_v4 has 36 uint64_t fields after uuid = 304 bytes total.
  * we allocate 512 bytes for future-proofing against v5/v6 additions.
+ *
+ * built without the proc-info feature, nothing here calls libproc: every
+ * query answers None, as if the process were gone. the layout constants
+ * stay, unused.
+ *
+ * linux has no libproc. /proc/<pid>/stat has the same three numbers (user
+ * and system time, resident pages) plus the scheduler state, so we read
+ * it and fill in the rusage_info fields everything else here reads.
+ * phys_footprint becomes RSS there, and the libproc layout constants go
+ * unused as they do without the feature.
+ *
+ * whether a process runs under Rosetta isn't in libproc either: the
+ * sysctl.proc_translated most code asks only answers for the caller, so
+ * for the child we read its kinfo_proc and look for P_TRANSLATED. linux
+ * has no Rosetta to ask about.
+ *
+ * the same p_flag has P_TRACED, set while a debugger is attached. linux
+ * says so as the TracerPid line of /proc/<pid>/status.
+ *
+ * the members of a process group: proc_listpids(PROC_PGRP_ONLY) on macOS,
+ * on linux every /proc/<pid>/stat whose pgrp field matches.
  */
+#![cfg_attr(
+    any(not(feature = "proc-info"), not(target_os = "macos")),
+    allow(dead_code)
+)]
 
 use crate::error::{Result, TimeoutError};
 use alloc::format;
@@ -41,8 +66,113 @@ const OFFSET_PHYS_FOOTPRINT: usize = 72; /* 16 + 7*8 */
 #[repr(C, align(8))]
 struct AlignedBuffer([u8; RUSAGE_BUFFER_SIZE]);
 
+#[cfg(all(feature = "proc-info", target_os = "macos"))]
 unsafe extern "C" {
     fn proc_pid_rusage(pid: i32, flavor: i32, buffer: *mut u8) -> i32;
+    fn proc_pidinfo(pid: i32, flavor: i32, arg: u64, buffer: *mut u8, size: i32) -> i32;
+    fn proc_listpids(kind: u32, typeinfo: u32, buffer: *mut i32, size: i32) -> i32;
+}
+
+/* proc_listpids kind: the pids whose process group is typeinfo */
+const PROC_PGRP_ONLY: u32 = 2;
+
+/* proc_pidinfo(PROC_PIDTBSDINFO) fills struct proc_bsdinfo, 136 bytes:
+ *
+ * offset 0: pbi_flags  (u32)
+ * offset 4: pbi_status (u32)  <-- SIDL/SRUN/SSLEEP/SSTOP/SZOMB
+ */
+const PROC_PIDTBSDINFO: i32 = 3;
+const BSDINFO_SIZE: usize = 136;
+const OFFSET_PBI_STATUS: usize = 4;
+
+/* p_stat values from <sys/proc.h> */
+const SIDL: u32 = 1;
+const SRUN: u32 = 2;
+const SSLEEP: u32 = 3;
+const SSTOP: u32 = 4;
+const SZOMB: u32 = 5;
+
+#[repr(C, align(8))]
+struct BsdInfoBuffer([u8; BSDINFO_SIZE]);
+
+/* proc_pidinfo(PROC_PIDTASKINFO) fills struct proc_taskinfo, 96 bytes:
+ * six u64 (sizes, times) then twelve i32 counters
+ *
+ * offset 84: pti_threadnum (i32)
+ *
+ * its times are mach ticks, not ns, on Apple Silicon - rusage has those */
+const PROC_PIDTASKINFO: i32 = 4;
+const TASKINFO_SIZE: usize = 96;
+const OFFSET_PTI_THREADNUM: usize = 84;
+
+#[repr(C, align(8))]
+struct TaskInfoBuffer([u8; TASKINFO_SIZE]);
+
+/* sysctl {CTL_KERN, KERN_PROC, KERN_PROC_PID, pid} fills struct
+ * kinfo_proc, 648 bytes on 64-bit. it starts with struct extern_proc:
+ *
+ * offset 0:  p_un      (16 bytes: two pointers, or a timeval)
+ * offset 16: p_vmspace (pointer)
+ * offset 24: p_sigacts (pointer)
+ * offset 32: p_flag    (i32)  <-- P_TRANSLATED, P_TRACED
+ */
+const KINFO_PROC_SIZE: usize = 648;
+const OFFSET_P_FLAG: usize = 32;
+const P_TRACED: i32 = 0x0000_0800;
+const P_TRANSLATED: i32 = 0x0002_0000;
+
+#[repr(C, align(8))]
+struct KinfoProcBuffer([u8; KINFO_PROC_SIZE]);
+
+/// Scheduler state of a process, from `proc_bsdinfo.pbi_status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcStatus {
+    /// Being created (fork in progress).
+    Idle,
+    Running,
+    Sleeping,
+    /// Stopped by SIGSTOP/SIGTSTP (or our CPU throttle).
+    Stopped,
+    /// Dead, waiting for its parent to reap it.
+    Zombie,
+}
+
+impl ProcStatus {
+    /// Stable name, as the JSON output gives it.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Idle => "idle",
+            Self::Running => "running",
+            Self::Sleeping => "sleeping",
+            Self::Stopped => "stopped",
+            Self::Zombie => "zombie",
+        }
+    }
+}
+
+/// What a process was doing at one instant (the JSON `"at_timeout"`).
+/// Each field is None where the build or the platform can't tell, or the
+/// process was gone before it was read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    /// User + system CPU time so far, ns.
+    pub cpu_time_ns: Option<u64>,
+    /// phys_footprint on macOS, RSS on linux, bytes.
+    pub memory_bytes: Option<u64>,
+    pub threads: Option<u32>,
+    pub status: Option<ProcStatus>,
+}
+
+/// Sample `pid` now: one rusage read, thread count, scheduler state.
+pub fn snapshot(pid: i32) -> Snapshot {
+    let stats = read_process_stats(pid);
+    Snapshot {
+        cpu_time_ns: stats.map(|s| s.cpu_time_ns),
+        memory_bytes: stats.map(|s| s.memory_bytes),
+        threads: get_thread_count(pid),
+        status: get_process_status(pid),
+    }
 }
 
 /* read u64 from buffer at offset (little-endian on arm64/x86_64) */
@@ -53,6 +183,7 @@ fn read_u64(buf: &[u8; RUSAGE_BUFFER_SIZE], offset: usize) -> u64 {
 }
 
 /* call proc_pid_rusage into oversized buffer, return raw buffer on success */
+#[cfg(all(feature = "proc-info", target_os = "macos"))]
 #[inline(never)]
 fn get_rusage_raw(pid: i32) -> Option<[u8; RUSAGE_BUFFER_SIZE]> {
     /* use aligned wrapper - kernel expects u64-aligned buffer */
@@ -71,6 +202,11 @@ fn get_rusage_raw(pid: i32) -> Option<[u8; RUSAGE_BUFFER_SIZE]> {
     Some(aligned.0)
 }
 
+#[cfg(not(feature = "proc-info"))]
+fn get_rusage_raw(_pid: i32) -> Option<[u8; RUSAGE_BUFFER_SIZE]> {
+    None
+}
+
 /* get process memory usage (phys_footprint) in bytes */
 pub fn get_process_memory(pid: i32) -> Option<u64> {
     let buf = get_rusage_raw(pid)?;
@@ -87,16 +223,429 @@ pub fn get_process_cpu_time(pid: i32) -> Option<u64> {
 
 /* get both memory and CPU time in one call for efficiency */
 pub fn get_process_stats(pid: i32) -> Result<ProcessStats> {
-    let buf = get_rusage_raw(pid)
-        .ok_or_else(|| TimeoutError::Internal(format!("proc_pid_rusage failed for pid {}", pid)))?;
+    read_process_stats(pid)
+        .ok_or_else(|| TimeoutError::Internal(format!("proc_pid_rusage failed for pid {}", pid)))
+}
 
-    Ok(ProcessStats {
+/* get_process_stats without the error: the wait loop calls this every
+ * wake, and a gone process is an answer there, not something to format */
+pub fn read_process_stats(pid: i32) -> Option<ProcessStats> {
+    let buf = get_rusage_raw(pid)?;
+    Some(ProcessStats {
         memory_bytes: read_u64(&buf, OFFSET_PHYS_FOOTPRINT),
         cpu_time_ns: read_u64(&buf, OFFSET_USER_TIME)
             .saturating_add(read_u64(&buf, OFFSET_SYSTEM_TIME)),
     })
 }
 
+/* get scheduler state (running, stopped, zombie...) via proc_pidinfo */
+#[cfg(all(feature = "proc-info", target_os = "macos"))]
+pub fn get_process_status(pid: i32) -> Option<ProcStatus> {
+    let mut buf = BsdInfoBuffer([0u8; BSDINFO_SIZE]);
+
+    /* arg = 1 asks the kernel to look up zombies too - without it a dead,
+     * unreaped process reads as ESRCH, which is the case we care about */
+    // SAFETY: proc_pidinfo writes at most `size` bytes into buffer, and we
+    // pass the real buffer length. buffer is 8-byte aligned via BsdInfoBuffer.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    let ret = unsafe {
+        proc_pidinfo(
+            pid,
+            PROC_PIDTBSDINFO,
+            1,
+            buf.0.as_mut_ptr(),
+            BSDINFO_SIZE as i32,
+        )
+    };
+
+    /* short read means a struct layout we don't know - don't guess */
+    #[allow(clippy::cast_sign_loss)]
+    if ret < 0 || ret as usize != BSDINFO_SIZE {
+        return None;
+    }
+
+    let bytes: [u8; 4] = buf.0[OFFSET_PBI_STATUS..OFFSET_PBI_STATUS + 4]
+        .try_into()
+        .ok()?;
+    match u32::from_ne_bytes(bytes) {
+        SIDL => Some(ProcStatus::Idle),
+        SRUN => Some(ProcStatus::Running),
+        SSLEEP => Some(ProcStatus::Sleeping),
+        SSTOP => Some(ProcStatus::Stopped),
+        SZOMB => Some(ProcStatus::Zombie),
+        _ => None,
+    }
+}
+
+#[cfg(not(feature = "proc-info"))]
+pub fn get_process_status(_pid: i32) -> Option<ProcStatus> {
+    None
+}
+
+/* get thread count via proc_pidinfo */
+#[cfg(all(feature = "proc-info", target_os = "macos"))]
+pub fn get_thread_count(pid: i32) -> Option<u32> {
+    let mut buf = TaskInfoBuffer([0u8; TASKINFO_SIZE]);
+
+    // SAFETY: proc_pidinfo writes at most `size` bytes into buffer, and we
+    // pass the real buffer length. buffer is 8-byte aligned via TaskInfoBuffer.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    let ret = unsafe {
+        proc_pidinfo(
+            pid,
+            PROC_PIDTASKINFO,
+            0,
+            buf.0.as_mut_ptr(),
+            TASKINFO_SIZE as i32,
+        )
+    };
+
+    /* short read means a struct layout we don't know - don't guess */
+    #[allow(clippy::cast_sign_loss)]
+    if ret < 0 || ret as usize != TASKINFO_SIZE {
+        return None;
+    }
+
+    let bytes: [u8; 4] = buf.0[OFFSET_PTI_THREADNUM..OFFSET_PTI_THREADNUM + 4]
+        .try_into()
+        .ok()?;
+    u32::try_from(i32::from_ne_bytes(bytes)).ok()
+}
+
+/* kinfo_proc's p_flag for pid; None if it's gone */
+#[cfg(all(feature = "proc-info", target_os = "macos"))]
+fn get_p_flag(pid: i32) -> Option<i32> {
+    let mut mib = [libc::CTL_KERN, libc::KERN_PROC, libc::KERN_PROC_PID, pid];
+    let mut buf = KinfoProcBuffer([0u8; KINFO_PROC_SIZE]);
+    let mut size: libc::size_t = KINFO_PROC_SIZE;
+
+    // SAFETY: mib names 4 ints; buf is KINFO_PROC_SIZE bytes, 8-aligned,
+    // and size says so; no new value is set
+    let ret = unsafe {
+        libc::sysctl(
+            mib.as_mut_ptr(),
+            4,
+            buf.0.as_mut_ptr().cast(),
+            &raw mut size,
+            core::ptr::null_mut(),
+            0,
+        )
+    };
+    /* a pid that isn't there comes back empty, not as an error */
+    if ret != 0 || size < OFFSET_P_FLAG + 4 {
+        return None;
+    }
+    let bytes: [u8; 4] = buf.0[OFFSET_P_FLAG..OFFSET_P_FLAG + 4].try_into().ok()?;
+    Some(i32::from_ne_bytes(bytes))
+}
+
+/// Whether `pid` runs under Rosetta translation (x86_64 code on Apple
+/// Silicon). `Some(false)` on an Intel Mac; None if it's gone, or off
+/// macOS.
+#[cfg(all(feature = "proc-info", target_os = "macos"))]
+pub fn is_translated(pid: i32) -> Option<bool> {
+    Some(get_p_flag(pid)? & P_TRANSLATED != 0)
+}
+
+/// Whether a debugger is attached to `pid` (P_TRACED). None if it's gone,
+/// or without the proc-info feature.
+#[cfg(all(feature = "proc-info", target_os = "macos"))]
+pub fn is_traced(pid: i32) -> Option<bool> {
+    Some(get_p_flag(pid)? & P_TRACED != 0)
+}
+
+#[cfg(not(all(feature = "proc-info", target_os = "macos")))]
+pub fn is_translated(_pid: i32) -> Option<bool> {
+    None
+}
+
+#[cfg(not(feature = "proc-info"))]
+pub fn get_thread_count(_pid: i32) -> Option<u32> {
+    None
+}
+
+/* proc_pidinfo(PROC_PIDEXITREASONBASICINFO) fills the packed struct
+ * proc_exitreasonbasicinfo, 24 bytes:
+ *
+ * offset 0: beri_namespace (u32)  <-- OS_REASON_* from <sys/reason.h>
+ * offset 4: beri_code      (u64)
+ * offset 12: beri_flags    (u64)
+ * offset 20: beri_reason_buf_size (u32)
+ *
+ * only answers for a zombie: the reason is attached at exit and goes
+ * with the reap. kill(2) attaches a signal reason; a plain exit() none */
+const PROC_PIDEXITREASONBASICINFO: i32 = 25;
+const EXITREASON_SIZE: usize = 24;
+const OFFSET_BERI_CODE: usize = 4;
+
+/// Why the kernel says a process ended: a namespace from `<sys/reason.h>`
+/// and a code within it. macOS only; what `NOTE_EXIT_DETAIL` summarizes
+/// in a few bits (`NOTE_EXIT_MEMORY` is the jetsam namespace).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExitReason {
+    pub namespace: u32,
+    /// For `jetsam` the kill cause, for `signal` the signal number.
+    pub code: u64,
+}
+
+impl ExitReason {
+    /// Name used as `"exit_reason_namespace"` in JSON: `OS_REASON_*`
+    /// lowercased, `"unknown"` past the ones we know.
+    #[must_use]
+    pub const fn namespace_name(&self) -> &'static str {
+        match self.namespace {
+            0 => "invalid",
+            1 => "jetsam",
+            2 => "signal",
+            3 => "codesigning",
+            4 => "hangtracer",
+            5 => "test",
+            6 => "dyld",
+            7 => "libxpc",
+            8 => "objc",
+            9 => "exec",
+            10 => "springboard",
+            11 => "tcc",
+            12 => "reportcrash",
+            13 => "coreanimation",
+            14 => "aggregated",
+            15 => "runningboard",
+            16 => "skywalk",
+            17 => "settings",
+            18 => "libsystem",
+            19 => "foundation",
+            20 => "watchdog",
+            21 => "metal",
+            22 => "watchkit",
+            23 => "guard",
+            24 => "analytics",
+            25 => "sandbox",
+            26 => "security",
+            27 => "endpointsecurity",
+            _ => "unknown",
+        }
+    }
+}
+
+#[repr(C, align(8))]
+struct ExitReasonBuffer([u8; EXITREASON_SIZE]);
+
+/* exit reason of a zombie child via proc_pidinfo. None once it's reaped,
+ * or if it just exited */
+#[cfg(all(feature = "proc-info", target_os = "macos"))]
+pub fn get_exit_reason(pid: i32) -> Option<ExitReason> {
+    let mut buf = ExitReasonBuffer([0u8; EXITREASON_SIZE]);
+
+    // SAFETY: proc_pidinfo writes at most `size` bytes into buffer, and we
+    // pass the real buffer length
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    let ret = unsafe {
+        proc_pidinfo(
+            pid,
+            PROC_PIDEXITREASONBASICINFO,
+            0,
+            buf.0.as_mut_ptr(),
+            EXITREASON_SIZE as i32,
+        )
+    };
+
+    /* ENOENT when there's no reason attached: a plain exit */
+    #[allow(clippy::cast_sign_loss)]
+    if ret < 0 || ret as usize != EXITREASON_SIZE {
+        return None;
+    }
+
+    let namespace: [u8; 4] = buf.0[..4].try_into().ok()?;
+    let code: [u8; 8] = buf.0[OFFSET_BERI_CODE..OFFSET_BERI_CODE + 8]
+        .try_into()
+        .ok()?;
+    Some(ExitReason {
+        namespace: u32::from_ne_bytes(namespace),
+        code: u64::from_ne_bytes(code),
+    })
+}
+
+#[cfg(not(all(feature = "proc-info", target_os = "macos")))]
+pub fn get_exit_reason(_pid: i32) -> Option<ExitReason> {
+    None
+}
+
+/* read /proc/<pid>/<name> into buf */
+#[cfg(all(feature = "proc-info", target_os = "linux"))]
+fn read_proc<'a>(pid: i32, name: &str, buf: &'a mut [u8; 1024]) -> Option<&'a [u8]> {
+    use core::fmt::Write;
+
+    let mut path = crate::io::StackBuf::<40>::new();
+    write!(path, "/proc/{pid}/{name}\0").ok()?;
+    // SAFETY: path is null-terminated; open returns -1 on error
+    let fd = unsafe {
+        libc::open(
+            path.as_bytes().as_ptr().cast(),
+            libc::O_RDONLY | libc::O_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return None;
+    }
+    // SAFETY: fd is open, buf is valid for buf.len() bytes
+    let n = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+    // SAFETY: fd is ours, closed once
+    unsafe { libc::close(fd) };
+    buf.get(..usize::try_from(n).ok()?)
+}
+
+/* /proc/<pid>/stat from the state field on: "pid (comm) S ppid ...".
+ * comm may hold spaces and parens, so cut at the last ')' */
+#[cfg(all(feature = "proc-info", target_os = "linux"))]
+fn read_proc_stat(pid: i32, buf: &mut [u8; 1024]) -> Option<&[u8]> {
+    let stat = read_proc(pid, "stat", buf)?;
+    let comm_end = stat.iter().rposition(|&b| b == b')')?;
+    stat.get(comm_end + 2..)
+}
+
+/* whitespace-separated field `i` as a number */
+#[cfg(all(feature = "proc-info", target_os = "linux"))]
+fn field(fields: &[u8], i: usize) -> Option<u64> {
+    let field = fields.split(u8::is_ascii_whitespace).nth(i)?;
+    core::str::from_utf8(field).ok()?.parse().ok()
+}
+
+#[cfg(all(feature = "proc-info", target_os = "linux"))]
+fn get_rusage_raw(pid: i32) -> Option<[u8; RUSAGE_BUFFER_SIZE]> {
+    /* stat(5) fields counted from the state field (3): utime 14 and
+     * stime 15 in clock ticks, rss 24 in pages */
+    const UTIME: usize = 14 - 3;
+    const STIME: usize = 15 - 3;
+    const RSS: usize = 24 - 3;
+
+    let mut buf = [0u8; 1024];
+    let fields = read_proc_stat(pid, &mut buf)?;
+    // SAFETY: sysconf takes no pointers
+    let tick = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    // SAFETY: as above
+    let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    let tick = u64::try_from(tick).ok().filter(|&t| t > 0)?;
+    let page = u64::try_from(page).ok()?;
+    let ticks_ns = |t: u64| t.saturating_mul(1_000_000_000) / tick;
+
+    let mut user = ticks_ns(field(fields, UTIME)?);
+    let system = ticks_ns(field(fields, STIME)?);
+    let rss = field(fields, RSS)?.saturating_mul(page);
+
+    /* ticks are 10ms - too coarse for short throttle intervals. schedstat
+     * has the on-CPU total in ns; keep the system share from stat */
+    let mut sched = [0u8; 1024];
+    if let Some(total) = read_proc(pid, "schedstat", &mut sched).and_then(|s| field(s, 0)) {
+        user = total.saturating_sub(system);
+    }
+
+    let mut raw = [0u8; RUSAGE_BUFFER_SIZE];
+    for (offset, v) in [
+        (OFFSET_USER_TIME, user),
+        (OFFSET_SYSTEM_TIME, system),
+        (OFFSET_PHYS_FOOTPRINT, rss),
+    ] {
+        raw[offset..offset + 8].copy_from_slice(&v.to_ne_bytes());
+    }
+    Some(raw)
+}
+
+#[cfg(all(feature = "proc-info", target_os = "linux"))]
+pub fn get_thread_count(pid: i32) -> Option<u32> {
+    /* num_threads is stat(5) field 20 */
+    const NUM_THREADS: usize = 20 - 3;
+
+    let mut buf = [0u8; 1024];
+    u32::try_from(field(read_proc_stat(pid, &mut buf)?, NUM_THREADS)?).ok()
+}
+
+/* "TracerPid:\t0" unless something ptraces it */
+#[cfg(all(feature = "proc-info", target_os = "linux"))]
+pub fn is_traced(pid: i32) -> Option<bool> {
+    let mut buf = [0u8; 1024];
+    let status = read_proc(pid, "status", &mut buf)?;
+    let line = status
+        .split(|&b| b == b'\n')
+        .find_map(|line| line.strip_prefix(b"TracerPid:"))?;
+    Some(field(line, 1)? != 0)
+}
+
+#[cfg(not(feature = "proc-info"))]
+pub fn is_traced(_pid: i32) -> Option<bool> {
+    None
+}
+
+#[cfg(all(feature = "proc-info", target_os = "linux"))]
+pub fn get_process_status(pid: i32) -> Option<ProcStatus> {
+    let mut buf = [0u8; 1024];
+    match read_proc_stat(pid, &mut buf)?.first()? {
+        b'R' => Some(ProcStatus::Running),
+        /* D is uninterruptible disk sleep, I an idle kernel thread */
+        b'S' | b'D' | b'I' => Some(ProcStatus::Sleeping),
+        b'T' | b't' => Some(ProcStatus::Stopped),
+        b'Z' => Some(ProcStatus::Zombie),
+        _ => None,
+    }
+}
+
+/* the processes in group pgid, zombies included, into pids; how many
+ * were written. a group bigger than pids is cut short */
+#[cfg(all(feature = "proc-info", target_os = "macos"))]
+pub fn list_group(pgid: i32, pids: &mut [i32]) -> usize {
+    let size = i32::try_from(core::mem::size_of_val(pids)).unwrap_or(i32::MAX);
+    #[allow(clippy::cast_sign_loss)]
+    // SAFETY: pids is valid for size bytes; proc_listpids writes at most
+    // that many and returns how many it wrote
+    let bytes = unsafe { proc_listpids(PROC_PGRP_ONLY, pgid as u32, pids.as_mut_ptr(), size) };
+    let count = usize::try_from(bytes).unwrap_or(0) / core::mem::size_of::<i32>();
+    count.min(pids.len())
+}
+
+#[cfg(all(feature = "proc-info", target_os = "linux"))]
+pub fn list_group(pgid: i32, pids: &mut [i32]) -> usize {
+    /* pgrp is stat(5) field 5 */
+    const PGRP: usize = 5 - 3;
+
+    // SAFETY: the path is NUL-terminated; opendir returns null on error
+    let dir = unsafe { libc::opendir(c"/proc".as_ptr()) };
+    if dir.is_null() {
+        return 0;
+    }
+    let mut count = 0;
+    let mut buf = [0u8; 1024];
+    while count < pids.len() {
+        // SAFETY: dir is open until the closedir below
+        let entry = unsafe { libc::readdir(dir) };
+        if entry.is_null() {
+            break;
+        }
+        // SAFETY: readdir returned a valid entry, good until the next readdir
+        let entry = unsafe { &*entry };
+        // SAFETY: d_name is NUL-terminated
+        let name = unsafe { core::ffi::CStr::from_ptr(entry.d_name.as_ptr()) };
+        let Some(pid) = core::str::from_utf8(name.to_bytes())
+            .ok()
+            .and_then(|name| name.parse::<i32>().ok())
+        else {
+            continue;
+        };
+        if read_proc_stat(pid, &mut buf).and_then(|stat| field(stat, PGRP))
+            == u64::try_from(pgid).ok()
+        {
+            pids[count] = pid;
+            count += 1;
+        }
+    }
+    // SAFETY: dir was opened above and is closed once
+    unsafe { libc::closedir(dir) };
+    count
+}
+
+#[cfg(not(feature = "proc-info"))]
+pub fn list_group(_pgid: i32, _pids: &mut [i32]) -> usize {
+    0
+}
+
 #[cfg_attr(test, derive(Debug))]
 #[derive(Clone, Copy)]
 pub struct ProcessStats {
@@ -104,7 +653,7 @@ pub struct ProcessStats {
     pub cpu_time_ns: u64,
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "proc-info"))]
 mod tests {
     use super::*;
 
@@ -132,6 +681,20 @@ mod tests {
         assert!(cpu.unwrap() > 0, "cpu time should be > 0");
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_get_process_status_self() {
+        /* we're running this test, so we can't be stopped or a zombie */
+        // SAFETY: getpid() always succeeds
+        let pid = unsafe { libc::getpid() };
+        let status = get_process_status(pid);
+        assert!(
+            matches!(status, Some(ProcStatus::Running | ProcStatus::Sleeping)),
+            "unexpected status for self: {:?}",
+            status
+        );
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     fn test_get_process_stats_self() {
@@ -144,6 +707,45 @@ mod tests {
         assert!(stats.cpu_time_ns > 0);
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_snapshot_self() {
+        // SAFETY: getpid() always succeeds
+        let pid = unsafe { libc::getpid() };
+        let snap = snapshot(pid);
+        assert!(snap.cpu_time_ns.is_some_and(|ns| ns > 0));
+        assert!(snap.memory_bytes.is_some_and(|b| b > 1_000_000));
+        assert!(snap.threads.is_some_and(|n| n >= 1), "{:?}", snap.threads);
+        assert!(matches!(
+            snap.status,
+            Some(ProcStatus::Running | ProcStatus::Sleeping)
+        ));
+        assert_eq!(snapshot(-1), Snapshot::default());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_list_group_self() {
+        // SAFETY: getpid() always succeeds
+        let pid = unsafe { libc::getpid() };
+        // SAFETY: getpgrp() always succeeds
+        let pgid = unsafe { libc::getpgrp() };
+        let mut pids = [0i32; 1024];
+        let count = list_group(pgid, &mut pids);
+        assert!(pids[..count].contains(&pid), "{:?}", &pids[..count]);
+        /* a group nobody is in */
+        assert_eq!(list_group(0x7fff_fff0, &mut pids), 0);
+    }
+
+    #[test]
+    fn test_exit_reason_namespace_name() {
+        let reason = |namespace| ExitReason { namespace, code: 0 };
+        assert_eq!(reason(1).namespace_name(), "jetsam");
+        assert_eq!(reason(2).namespace_name(), "signal");
+        assert_eq!(reason(3).namespace_name(), "codesigning");
+        assert_eq!(reason(1000).namespace_name(), "unknown");
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     fn test_invalid_pid() {
@@ -151,6 +753,7 @@ mod tests {
         assert!(get_process_memory(-1).is_none());
         assert!(get_process_cpu_time(-1).is_none());
         assert!(get_process_stats(-1).is_err());
+        assert!(get_exit_reason(-1).is_none());
     }
 }
 
@@ -8,16 +8,20 @@
  * it can use vfork internally and avoids copying page tables.
  *
  * On macOS, posix_spawnattr_t and posix_spawn_file_actions_t are opaque
- * pointers (*mut c_void) managed by the C library. We use RAII wrappers
- * to ensure proper initialization and cleanup via Drop.
+ * pointers (*mut c_void) managed by the C library; glibc makes them plain
+ * structs. Either way they start zeroed and init fills them in. We use RAII
+ * wrappers to ensure proper initialization and cleanup via Drop.
  */
 
 use alloc::ffi::CString;
 use alloc::string::String;
 use alloc::vec::Vec;
-use core::ffi::c_char;
+use core::ffi::{CStr, c_char};
 use core::ptr;
 
+use crate::errno::{Errno, errno};
+use crate::lookup::{self, NotRunnable};
+use crate::proc_info::ExitReason;
 use crate::rlimit::{ResourceLimits, apply_limits};
 
 /*
@@ -30,14 +34,163 @@ unsafe extern "C" {
     static environ: *const *const c_char;
 }
 
-/* errno values */
-const ENOENT: i32 = 2;
-const ESRCH: i32 = 3;
-const EACCES: i32 = 13;
-const EPERM: i32 = 1;
+#[cfg(target_os = "macos")]
+unsafe extern "C" {
+    /* <spawn.h>: the CPU types to prefer, in order, when picking a slice */
+    fn posix_spawnattr_setbinpref_np(
+        attr: *mut libc::posix_spawnattr_t,
+        count: libc::size_t,
+        pref: *mut libc::cpu_type_t,
+        ocount: *mut libc::size_t,
+    ) -> i32;
+}
+
+/* arch(1), for the fork + exec path where there's no spawn attribute */
+const ARCH_PATH: &CStr = c"/usr/bin/arch";
+
+/// Which process group (and session) the child is spawned into.
+///
+/// Decides what a timeout can reach: with `New` and `Session` the child
+/// leads its own group, so killpg() also catches its children. `Inherit`
+/// shares our group, so only the direct child can be signaled.
+///
+/// ```
+/// use procguard::{ProcessGroup, RunConfig};
+///
+/// let config = RunConfig {
+///     pgroup: Some(ProcessGroup::Session),
+///     ..RunConfig::default()
+/// };
+/// assert_eq!(config.process_group(), ProcessGroup::Session);
+/// assert!(!ProcessGroup::Inherit.owns_group());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum ProcessGroup {
+    /// Stay in our process group (what `--foreground` does).
+    Inherit,
+    /// Own process group in our session - the default.
+    #[default]
+    New,
+    /// Own session via setsid(). Detaches from the controlling terminal,
+    /// so terminal signals (Ctrl-C, SIGHUP on logout) no longer reach it.
+    Session,
+}
 
-/* signals */
-const SIGKILL: i32 = 9;
+impl ProcessGroup {
+    pub(crate) fn from_str(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "inherit" => Some(Self::Inherit),
+            "new" => Some(Self::New),
+            "session" => Some(Self::Session),
+            _ => None,
+        }
+    }
+
+    /// True when the child leads its own group and killpg() is safe.
+    #[inline]
+    #[must_use]
+    pub const fn owns_group(self) -> bool {
+        !matches!(self, Self::Inherit)
+    }
+}
+
+/// Which slice of a universal binary the child runs as (`--arch`).
+///
+/// On Apple Silicon `X86_64` runs the command under Rosetta; everywhere
+/// else only our own architecture can be asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Arch {
+    /// Apple Silicon, aarch64.
+    Arm64,
+    /// Intel; under Rosetta on Apple Silicon.
+    X86_64,
+}
+
+impl Arch {
+    pub(crate) fn from_str(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "arm64" | "aarch64" => Some(Self::Arm64),
+            "x86_64" | "x86-64" => Some(Self::X86_64),
+            _ => None,
+        }
+    }
+
+    /// The name Apple's tools use, as in `arch -x86_64`.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Arm64 => "arm64",
+            Self::X86_64 => "x86_64",
+        }
+    }
+
+    /// What we were built for, if it's one of these.
+    #[must_use]
+    pub const fn native() -> Option<Self> {
+        if cfg!(target_arch = "aarch64") {
+            Some(Self::Arm64)
+        } else if cfg!(target_arch = "x86_64") {
+            Some(Self::X86_64)
+        } else {
+            None
+        }
+    }
+
+    /* <mach/machine.h>: CPU_TYPE_ARM64 / CPU_TYPE_X86_64 */
+    #[cfg(target_os = "macos")]
+    const fn cpu_type(self) -> libc::cpu_type_t {
+        match self {
+            Self::Arm64 => 0x0100_000c,
+            Self::X86_64 => 0x0100_0007,
+        }
+    }
+}
+
+/// Where we sit relative to our session and process group.
+///
+/// launchd starts jobs as session leaders with no controlling terminal.
+/// An interactive shell starts us inside its job's group with a terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessContext {
+    /// getpgrp() == getpid()
+    pub group_leader: bool,
+    /// getsid(0) == getpid()
+    pub session_leader: bool,
+    /// /dev/tty can be opened, i.e. we have a controlling terminal
+    pub has_tty: bool,
+}
+
+impl ProcessContext {
+    /// Look at our own pid, group, session and terminal.
+    #[must_use]
+    pub fn detect() -> Self {
+        // SAFETY: getpid/getpgrp/getsid take no pointers and cannot fail for
+        // the calling process. Grouped since they share that invariant.
+        #[allow(clippy::multiple_unsafe_ops_per_block)]
+        let (pid, pgrp, sid) = unsafe { (libc::getpid(), libc::getpgrp(), libc::getsid(0)) };
+
+        // SAFETY: path is a NUL-terminated literal, O_NOCTTY so opening it
+        // can't make anything our controlling terminal.
+        let fd = unsafe {
+            libc::open(
+                c"/dev/tty".as_ptr(),
+                libc::O_RDONLY | libc::O_NOCTTY | libc::O_CLOEXEC,
+            )
+        };
+        if fd >= 0 {
+            // SAFETY: fd was just opened by us and isn't shared
+            unsafe { libc::close(fd) };
+        }
+
+        Self {
+            group_leader: pgrp == pid,
+            session_leader: sid == pid,
+            has_tty: fd >= 0,
+        }
+    }
+}
 
 /*
  * RAII wrapper for posix_spawnattr_t.
@@ -54,7 +207,9 @@ struct SpawnAttr {
 impl SpawnAttr {
     /* create and initialize spawn attributes */
     fn new() -> Result<Self, i32> {
-        let mut attr: libc::posix_spawnattr_t = ptr::null_mut();
+        // SAFETY: all-zero is a valid posix_spawnattr_t on both targets (null
+        // pointer on macOS, plain integers on linux); init overwrites it.
+        let mut attr: libc::posix_spawnattr_t = unsafe { core::mem::zeroed() };
         // SAFETY: attr is a valid pointer location for posix_spawnattr_init to populate
         let ret = unsafe { libc::posix_spawnattr_init(&mut attr) };
         if ret != 0 {
@@ -86,6 +241,22 @@ impl SpawnAttr {
         Ok(())
     }
 
+    /* run only the given slice of a universal binary */
+    #[cfg(target_os = "macos")]
+    fn set_binpref(&mut self, arch: Arch) -> Result<(), i32> {
+        let mut pref = arch.cpu_type();
+        let mut count: libc::size_t = 0;
+        // SAFETY: self.inner was initialized in new(); pref and count are
+        // valid for the one element passed
+        let ret = unsafe {
+            posix_spawnattr_setbinpref_np(&mut self.inner, 1, &raw mut pref, &raw mut count)
+        };
+        if ret != 0 {
+            return Err(ret);
+        }
+        Ok(())
+    }
+
     /* get raw pointer for FFI calls */
     fn as_ptr(&self) -> *const libc::posix_spawnattr_t {
         &self.inner
@@ -116,7 +287,8 @@ struct SpawnFileActions {
 impl SpawnFileActions {
     /* create and initialize file actions */
     fn new() -> Result<Self, i32> {
-        let mut actions: libc::posix_spawn_file_actions_t = ptr::null_mut();
+        // SAFETY: as for posix_spawnattr_t above
+        let mut actions: libc::posix_spawn_file_actions_t = unsafe { core::mem::zeroed() };
         // SAFETY: actions is a valid pointer location for init to populate
         let ret = unsafe { libc::posix_spawn_file_actions_init(&mut actions) };
         if ret != 0 {
@@ -132,6 +304,18 @@ impl SpawnFileActions {
     fn as_ptr(&self) -> *const libc::posix_spawn_file_actions_t {
         &self.inner
     }
+
+    /* the child gets `from` as `to`; from -1: the child has no `to` */
+    fn add_dup2(&mut self, from: i32, to: i32) -> Result<(), i32> {
+        let ret = if from < 0 {
+            // SAFETY: self.inner was initialized in new()
+            unsafe { libc::posix_spawn_file_actions_addclose(&mut self.inner, to) }
+        } else {
+            // SAFETY: self.inner was initialized in new()
+            unsafe { libc::posix_spawn_file_actions_adddup2(&mut self.inner, from, to) }
+        };
+        if ret != 0 { Err(ret) } else { Ok(()) }
+    }
 }
 
 impl Drop for SpawnFileActions {
@@ -157,6 +341,7 @@ pub struct RawChild {
 #[non_exhaustive]
 pub struct RawExitStatus {
     status: i32,
+    reason: Option<ExitReason>, /* the kernel's, read before the reap */
 }
 
 /// Resource usage from wait4() - CPU time and memory.
@@ -166,11 +351,25 @@ pub struct RawExitStatus {
 /// - Time values stored in microseconds internally, converted to milliseconds via truncation
 ///   (not rounding) in `user_time_ms()`/`system_time_ms()` to avoid float bloat.
 /// - RSS is truncated to KB (up to 1023 bytes lost per conversion).
+///
+/// ```
+/// use procguard::{RunConfig, RunResult, run_command};
+///
+/// let args = ["-c".to_string(), "exit 0".to_string()];
+/// let result = run_command("sh", &args, &RunConfig::default()).unwrap();
+/// let rusage = result.resource_usage().unwrap();
+/// assert!(rusage.max_rss_kb > 0);
+/// assert_eq!(rusage.user_time_ms(), rusage.user_time_us / 1000);
+/// assert!(matches!(result, RunResult::Completed { .. }));
+/// ```
 #[derive(Debug, Clone, Copy, Default)]
 pub struct ResourceUsage {
-    pub user_time_us: u64,   /* ru_utime in microseconds */
-    pub system_time_us: u64, /* ru_stime in microseconds */
-    pub max_rss_kb: u64,     /* ru_maxrss converted to KB (macOS reports bytes) */
+    /// User CPU time (`ru_utime`) in microseconds.
+    pub user_time_us: u64,
+    /// System CPU time (`ru_stime`) in microseconds.
+    pub system_time_us: u64,
+    /// Peak resident set size (`ru_maxrss`), in KB (macOS reports bytes).
+    pub max_rss_kb: u64,
 }
 
 impl ResourceUsage {
@@ -208,6 +407,20 @@ impl RawExitStatus {
         }
     }
 
+    /// The kernel's exit reason (jetsam, signal, codesigning...). macOS
+    /// only, and None for a plain exit().
+    #[inline]
+    pub fn exit_reason(&self) -> Option<ExitReason> {
+        self.reason
+    }
+
+    /// Whether the process left a core dump (WCOREDUMP). Only meaningful
+    /// when it died of a signal; needs `ulimit -c` above 0 to ever be true.
+    #[inline]
+    pub fn core_dumped(&self) -> bool {
+        self.signaled() && self.status & 0x80 != 0
+    }
+
     #[inline]
     fn exited_normally(&self) -> bool {
         (self.status & 0x7F) == 0
@@ -227,10 +440,14 @@ pub enum SpawnError {
     NotFound(String),
     /// Permission denied
     PermissionDenied(String),
-    /// Other spawn error with errno
-    Spawn(i32),
+    /// Our own PATH search found nothing that can run, or the binary
+    /// is for another CPU (see [`crate::lookup`])
+    NotRunnable(NotRunnable),
+    /// Any other spawn failure, by errno: `ETXTBSY` for a binary still
+    /// being written, `E2BIG` for too long an argv, ...
+    Spawn(Errno),
     /// Wait error
-    Wait(i32),
+    Wait(Errno),
     /// Invalid argument (null byte in string)
     InvalidArg,
 }
@@ -240,13 +457,19 @@ impl core::fmt::Display for SpawnError {
         match self {
             Self::NotFound(s) => write!(f, "command not found: {s}"),
             Self::PermissionDenied(s) => write!(f, "permission denied: {s}"),
-            Self::Spawn(e) => write!(f, "spawn error: errno {e}"),
-            Self::Wait(e) => write!(f, "wait error: errno {e}"),
+            Self::NotRunnable(e) => write!(f, "{e}"),
+            Self::Spawn(e) => write!(f, "spawn error: {e}"),
+            Self::Wait(e) => write!(f, "wait error: {e}"),
             Self::InvalidArg => write!(f, "invalid argument"),
         }
     }
 }
 
+/* a posix_spawn* call's return value, as the error */
+fn spawn_failed(errno: i32) -> SpawnError {
+    SpawnError::Spawn(Errno::from_raw(errno))
+}
+
 impl RawChild {
     /// Get the process ID
     #[inline]
@@ -257,9 +480,10 @@ impl RawChild {
     /// Wait for the process to exit, blocking. Returns exit status and resource usage.
     pub fn wait(&mut self) -> Result<(RawExitStatus, ResourceUsage), SpawnError> {
         if self.exited {
-            return Err(SpawnError::Wait(libc::ECHILD)); /* "No child processes" */
+            return Err(SpawnError::Wait(Errno::NoChild));
         }
 
+        let reason = zombie_exit_reason(self.pid, 0);
         let mut status: i32 = 0;
         // SAFETY: libc::rusage is a C struct that's safe to zero-initialize
         let mut rusage: libc::rusage = unsafe { core::mem::zeroed() };
@@ -274,21 +498,25 @@ impl RawChild {
                 if err == libc::EINTR {
                     continue; /* interrupted by signal, retry */
                 }
-                return Err(SpawnError::Wait(err));
+                return Err(SpawnError::Wait(Errno::from_raw(err)));
             }
             break;
         }
 
         self.exited = true;
-        Ok((RawExitStatus { status }, rusage_to_resource_usage(&rusage)))
+        Ok((
+            RawExitStatus { status, reason },
+            rusage_to_resource_usage(&rusage),
+        ))
     }
 
     /// Check if process has exited without blocking. Returns exit status and resource usage if exited.
     pub fn try_wait(&mut self) -> Result<Option<(RawExitStatus, ResourceUsage)>, SpawnError> {
         if self.exited {
-            return Err(SpawnError::Wait(libc::ECHILD)); /* already reaped */
+            return Err(SpawnError::Wait(Errno::NoChild)); /* already reaped */
         }
 
+        let reason = zombie_exit_reason(self.pid, libc::WNOHANG);
         let mut status: i32 = 0;
         // SAFETY: libc::rusage is a C struct that's safe to zero-initialize
         let mut rusage: libc::rusage = unsafe { core::mem::zeroed() };
@@ -296,7 +524,7 @@ impl RawChild {
         let ret = unsafe { libc::wait4(self.pid, &mut status, libc::WNOHANG, &mut rusage) };
 
         if ret < 0 {
-            return Err(SpawnError::Wait(errno()));
+            return Err(SpawnError::Wait(Errno::last()));
         }
 
         if ret == 0 {
@@ -306,7 +534,7 @@ impl RawChild {
 
         self.exited = true;
         Ok(Some((
-            RawExitStatus { status },
+            RawExitStatus { status, reason },
             rusage_to_resource_usage(&rusage),
         )))
     }
@@ -318,27 +546,53 @@ impl RawChild {
         }
 
         // SAFETY: kill is safe with any pid/signal
-        let ret = unsafe { libc::kill(self.pid, SIGKILL) };
+        let ret = unsafe { libc::kill(self.pid, libc::SIGKILL) };
 
         if ret < 0 {
-            let e = errno();
-            if e == ESRCH {
-                /* already dead */
-                return Ok(());
-            }
-            return Err(SpawnError::Wait(e));
+            return match Errno::last() {
+                Errno::NoSuchProcess => Ok(()), /* already dead */
+                e => Err(SpawnError::Wait(e)),
+            };
         }
 
         Ok(())
     }
 }
 
+/// Build a child's argv: `[command, args...]`.
+///
+/// Takes bytes, not `str` - arguments are passed through untouched whether
+/// or not they're valid UTF-8. Fails only on an interior NUL.
+pub fn build_argv<A: AsRef<[u8]>>(command: &[u8], args: &[A]) -> Result<Vec<CString>, SpawnError> {
+    let mut argv = Vec::with_capacity(args.len() + 1);
+    argv.push(CString::new(command).map_err(|_| SpawnError::InvalidArg)?);
+    for arg in args {
+        argv.push(CString::new(arg.as_ref()).map_err(|_| SpawnError::InvalidArg)?);
+    }
+    Ok(argv)
+}
+
+/* NULL-terminated pointer array over argv; borrows, so argv must outlive it */
+fn argv_ptrs(argv: &[CString]) -> Vec<*const c_char> {
+    let mut ptrs = Vec::with_capacity(argv.len() + 1);
+    for cstr in argv {
+        ptrs.push(cstr.as_ptr());
+    }
+    ptrs.push(ptr::null());
+    ptrs
+}
+
+/* command name for error messages - lossy is fine, it's only displayed */
+fn display_name(argv0: &CStr) -> String {
+    argv0.to_string_lossy().into_owned()
+}
+
 /// Spawn a command using posix_spawnp (searches PATH)
 ///
 /// # Arguments
 /// * `command` - The command to run
 /// * `args` - Arguments to pass (not including argv[0])
-/// * `use_process_group` - If true, put child in its own process group
+/// * `pgroup` - Process group/session to put the child in
 ///
 /// # Returns
 /// * `Ok(RawChild)` - The spawned child process
@@ -346,38 +600,63 @@ impl RawChild {
 pub fn spawn_command(
     command: &str,
     args: &[String],
-    use_process_group: bool,
+    pgroup: ProcessGroup,
 ) -> Result<RawChild, SpawnError> {
-    /* build argv: [command, args..., NULL] */
-    let cmd_cstr = CString::new(command).map_err(|_| SpawnError::InvalidArg)?;
+    spawn_argv(&build_argv(command.as_bytes(), args)?, pgroup)
+}
 
-    let mut argv_cstrs: Vec<CString> = Vec::with_capacity(args.len() + 2);
-    argv_cstrs.push(cmd_cstr.clone());
+/// Like [`spawn_command`], with a prebuilt argv (see [`build_argv`]).
+/// `argv[0]` is the command, searched in PATH.
+pub fn spawn_argv(argv: &[CString], pgroup: ProcessGroup) -> Result<RawChild, SpawnError> {
+    spawn_argv_dups(argv, pgroup, None, &[])
+}
 
-    for arg in args {
-        argv_cstrs.push(CString::new(arg.as_str()).map_err(|_| SpawnError::InvalidArg)?);
-    }
+/* spawn_argv, with (from, to) fd pairs dup2'd into the child (output.rs).
+ * from -1 closes `to` in the child instead (--stdin close) */
+pub(crate) fn spawn_argv_dups(
+    argv: &[CString],
+    pgroup: ProcessGroup,
+    arch: Option<Arch>,
+    dups: &[(i32, i32)],
+) -> Result<RawChild, SpawnError> {
+    let cmd_cstr = argv.first().ok_or(SpawnError::InvalidArg)?;
+    let argv_ptrs = argv_ptrs(argv);
 
-    /* build pointer array */
-    let mut argv_ptrs: Vec<*const c_char> = Vec::with_capacity(argv_cstrs.len() + 1);
-    for cstr in &argv_cstrs {
-        argv_ptrs.push(cstr.as_ptr());
-    }
-    argv_ptrs.push(ptr::null());
+    /* our own PATH search first: posix_spawnp's ENOENT or EACCES wouldn't
+     * say which candidate was what */
+    let path = lookup::resolve(cmd_cstr.to_bytes()).map_err(SpawnError::NotRunnable)?;
+    check_slice(cmd_cstr, &path, arch)?;
 
     /* initialize spawn attributes using RAII wrapper */
-    let mut attr = SpawnAttr::new().map_err(SpawnError::Spawn)?;
+    let mut attr = SpawnAttr::new().map_err(spawn_failed)?;
+    #[cfg(target_os = "macos")]
+    if let Some(arch) = arch {
+        attr.set_binpref(arch).map_err(spawn_failed)?;
+    }
 
-    /* set process group if requested */
-    if use_process_group {
-        #[allow(clippy::cast_possible_truncation)]
-        attr.set_flags(libc::POSIX_SPAWN_SETPGROUP as libc::c_short)
-            .map_err(SpawnError::Spawn)?;
-        attr.set_pgroup(0).map_err(SpawnError::Spawn)?; /* own group */
+    /* set process group / session if requested */
+    match pgroup {
+        ProcessGroup::Inherit => {}
+        ProcessGroup::New => {
+            #[allow(clippy::cast_possible_truncation)]
+            attr.set_flags(libc::POSIX_SPAWN_SETPGROUP as libc::c_short)
+                .map_err(spawn_failed)?;
+            attr.set_pgroup(0).map_err(spawn_failed)?; /* own group */
+        }
+        ProcessGroup::Session => {
+            /* setsid also makes a new group with pgid == pid */
+            #[allow(clippy::cast_possible_truncation)]
+            attr.set_flags(libc::POSIX_SPAWN_SETSID as libc::c_short)
+                .map_err(spawn_failed)?;
+        }
     }
 
-    /* initialize file actions using RAII wrapper (inherit stdin/stdout/stderr) */
-    let file_actions = SpawnFileActions::new().map_err(SpawnError::Spawn)?;
+    /* initialize file actions using RAII wrapper (inherit stdin/stdout/stderr
+     * unless redirected) */
+    let mut file_actions = SpawnFileActions::new().map_err(spawn_failed)?;
+    for &(from, to) in dups {
+        file_actions.add_dup2(from, to).map_err(spawn_failed)?;
+    }
 
     /* spawn the process */
     let mut pid: libc::pid_t = 0;
@@ -397,10 +676,16 @@ pub fn spawn_command(
     /* RAII: attr and file_actions are automatically destroyed when they go out of scope */
 
     if ret != 0 {
-        return Err(match ret {
-            ENOENT => SpawnError::NotFound(command.into()),
-            EACCES | EPERM => SpawnError::PermissionDenied(command.into()),
-            _ => SpawnError::Spawn(ret),
+        return Err(match Errno::from_raw(ret) {
+            Errno::NotFound => SpawnError::NotFound(display_name(cmd_cstr)),
+            Errno::PermissionDenied | Errno::NotPermitted => {
+                SpawnError::PermissionDenied(display_name(cmd_cstr))
+            }
+            e if e.is_exec_format() => {
+                lookup::check_architecture(cmd_cstr.to_bytes(), &path, arch.map(Arch::as_str))
+                    .map_or(SpawnError::Spawn(e), SpawnError::NotRunnable)
+            }
+            e => SpawnError::Spawn(e),
         });
     }
 
@@ -412,36 +697,91 @@ pub fn spawn_command(
 pub fn spawn_command_with_limits(
     command: &str,
     args: &[String],
-    use_process_group: bool,
+    pgroup: ProcessGroup,
     limits: &ResourceLimits,
 ) -> Result<RawChild, SpawnError> {
-    /* build argv: [command, args..., NULL] */
-    let cmd_cstr = CString::new(command).map_err(|_| SpawnError::InvalidArg)?;
+    spawn_argv_with_limits(&build_argv(command.as_bytes(), args)?, pgroup, limits)
+}
 
-    let mut argv_cstrs: Vec<CString> = Vec::with_capacity(args.len() + 2);
-    argv_cstrs.push(cmd_cstr.clone());
-    for arg in args {
-        argv_cstrs.push(CString::new(arg.as_str()).map_err(|_| SpawnError::InvalidArg)?);
-    }
+/// Like [`spawn_command_with_limits`], with a prebuilt argv (see [`build_argv`]).
+pub fn spawn_argv_with_limits(
+    argv: &[CString],
+    pgroup: ProcessGroup,
+    limits: &ResourceLimits,
+) -> Result<RawChild, SpawnError> {
+    spawn_argv_with_limits_dups(argv, pgroup, limits, None, &[])
+}
 
-    let mut argv_ptrs: Vec<*const c_char> = Vec::with_capacity(argv_cstrs.len() + 1);
-    for cstr in &argv_cstrs {
-        argv_ptrs.push(cstr.as_ptr());
-    }
-    argv_ptrs.push(ptr::null());
+/* spawn_argv_with_limits, with (from, to) fd pairs dup2'd into the child,
+ * or `to` closed for from -1 */
+pub(crate) fn spawn_argv_with_limits_dups(
+    argv: &[CString],
+    pgroup: ProcessGroup,
+    limits: &ResourceLimits,
+    arch: Option<Arch>,
+    dups: &[(i32, i32)],
+) -> Result<RawChild, SpawnError> {
+    let cmd_cstr = argv.first().ok_or(SpawnError::InvalidArg)?;
+
+    /* after the fork, a failed exec is only an exit code: say why now */
+    let path = lookup::resolve(cmd_cstr.to_bytes()).map_err(SpawnError::NotRunnable)?;
+    check_slice(cmd_cstr, &path, arch)?;
+
+    /* another architecture without a spawn attribute to ask for it: exec
+     * `arch -<arch> path args...` and let it pick the slice. built before
+     * the fork, the child only execs */
+    let arch_argv = match arch.filter(|&a| Some(a) != Arch::native()) {
+        Some(arch) => {
+            let mut flag = Vec::with_capacity(arch.as_str().len() + 1);
+            flag.push(b'-');
+            flag.extend_from_slice(arch.as_str().as_bytes());
+            let mut wrapped = Vec::with_capacity(argv.len() + 2);
+            wrapped.push(CString::from(ARCH_PATH));
+            wrapped.push(CString::new(flag).map_err(|_| SpawnError::InvalidArg)?);
+            wrapped.push(CString::new(path).map_err(|_| SpawnError::InvalidArg)?);
+            wrapped.extend_from_slice(&argv[1..]);
+            Some(wrapped)
+        }
+        None => None,
+    };
+    let (file, argv_ptrs) = match &arch_argv {
+        Some(wrapped) => (ARCH_PATH, argv_ptrs(wrapped)),
+        None => (cmd_cstr.as_c_str(), argv_ptrs(argv)),
+    };
 
     /* fork into parent and child */
     // SAFETY: fork() is safe - creates child process. returns pid in parent, 0 in child.
     let pid = unsafe { libc::fork() };
     if pid < 0 {
-        return Err(SpawnError::Spawn(errno()));
+        return Err(SpawnError::Spawn(Errno::last()));
     }
 
     if pid == 0 {
         /* child */
-        if use_process_group {
+        match pgroup {
+            ProcessGroup::Inherit => {}
             // SAFETY: setpgid(0,0) in child to create its own group.
-            unsafe { libc::setpgid(0, 0) };
+            ProcessGroup::New => unsafe {
+                libc::setpgid(0, 0);
+            },
+            // SAFETY: setsid() in the freshly forked child - it isn't a group
+            // leader yet, so this can't fail with EPERM.
+            ProcessGroup::Session => unsafe {
+                libc::setsid();
+            },
+        }
+
+        for &(from, to) in dups {
+            if from < 0 {
+                // SAFETY: close takes no pointers; to is ours to drop
+                unsafe { libc::close(to) };
+                continue;
+            }
+            // SAFETY: dup2 on fds the parent opened; no pointers
+            if unsafe { libc::dup2(from, to) } < 0 {
+                // SAFETY: _exit terminates child process immediately
+                unsafe { libc::_exit(125) };
+            }
         }
 
         /* apply resource limits before exec */
@@ -451,10 +791,10 @@ pub fn spawn_command_with_limits(
         }
 
         // SAFETY: execvp with valid argv pointers. On failure, returns -1.
-        let ret = unsafe { libc::execvp(cmd_cstr.as_ptr(), argv_ptrs.as_ptr()) };
+        let ret = unsafe { libc::execvp(file.as_ptr(), argv_ptrs.as_ptr()) };
         if ret < 0 {
             let e = errno();
-            let code = if e == ENOENT { 127 } else { 126 };
+            let code = if e == libc::ENOENT { 127 } else { 126 };
             // SAFETY: _exit terminates child process with error code
             unsafe { libc::_exit(code) };
         }
@@ -463,18 +803,55 @@ pub fn spawn_command_with_limits(
     Ok(RawChild { pid, exited: false })
 }
 
-/* get errno - on macOS this is a thread-local via __error() */
-#[inline]
-fn errno() -> i32 {
-    unsafe extern "C" {
-        fn __error() -> *mut i32;
+/* --arch: a binary without that slice fails here, before any spawn. one
+ * that has it, or a script, is left to the spawn */
+fn check_slice(cmd_cstr: &CStr, path: &str, arch: Option<Arch>) -> Result<(), SpawnError> {
+    let Some(arch) = arch else {
+        return Ok(());
+    };
+    match lookup::check_architecture(cmd_cstr.to_bytes(), path, Some(arch.as_str())) {
+        Some(e) if !e.built_for.contains(&arch.as_str()) => Err(SpawnError::NotRunnable(e)),
+        _ => Ok(()),
     }
-    // SAFETY: __error always returns valid pointer on macOS. The dereference and
-    // function call share the same invariant (pointer validity for thread-local errno).
-    #[allow(clippy::multiple_unsafe_ops_per_block)]
-    unsafe {
-        *__error()
+}
+
+/* the kernel's exit reason goes with the reap, so wait for the zombie
+ * without reaping it (WNOWAIT), ask, and let wait4 reap. `options` is 0
+ * to block or WNOHANG. only macOS attaches reasons - elsewhere, and if
+ * waitid fails, there's nothing to read and wait4 goes on as before */
+#[cfg(all(feature = "proc-info", target_os = "macos"))]
+fn zombie_exit_reason(pid: libc::pid_t, options: i32) -> Option<ExitReason> {
+    // SAFETY: siginfo_t is a C struct that's safe to zero-initialize
+    let mut info: libc::siginfo_t = unsafe { core::mem::zeroed() };
+    loop {
+        #[allow(clippy::cast_sign_loss)]
+        // SAFETY: info is a valid siginfo_t; waitid only writes to it
+        let ret = unsafe {
+            libc::waitid(
+                libc::P_PID,
+                pid as libc::id_t,
+                &mut info,
+                libc::WEXITED | libc::WNOWAIT | options,
+            )
+        };
+        if ret == 0 {
+            break;
+        }
+        if errno() != libc::EINTR {
+            return None;
+        }
     }
+    /* WNOHANG and still running: si_pid stays 0 */
+    if info.si_pid == 0 {
+        return None;
+    }
+    crate::proc_info::get_exit_reason(pid)
+}
+
+#[cfg(not(all(feature = "proc-info", target_os = "macos")))]
+#[inline]
+fn zombie_exit_reason(_pid: libc::pid_t, _options: i32) -> Option<ExitReason> {
+    None
 }
 
 /* convert libc::rusage to ResourceUsage. macOS reports ru_maxrss in bytes, divide by 1024 for KB. */
@@ -490,8 +867,12 @@ fn rusage_to_resource_usage(rusage: &libc::rusage) -> ResourceUsage {
     let system_time_us = (rusage.ru_stime.tv_sec.max(0) as u64)
         .saturating_mul(1_000_000)
         .saturating_add(rusage.ru_stime.tv_usec.max(0) as u64);
-    /* macOS: ru_maxrss is in bytes (i64), convert to KB. guard against negative. */
+    /* macOS: ru_maxrss is in bytes (i64), convert to KB. linux already
+     * reports KB. guard against negative. */
+    #[cfg(target_os = "macos")]
     let max_rss_kb = (rusage.ru_maxrss.max(0) as u64) / 1024;
+    #[cfg(target_os = "linux")]
+    let max_rss_kb = rusage.ru_maxrss.max(0) as u64;
 
     ResourceUsage {
         user_time_us,
@@ -545,45 +926,118 @@ mod tests {
         assert_eq!(rusage.max_rss_kb, 0);
     }
 
+    #[test]
+    fn test_build_argv_keeps_bytes() {
+        let argv = build_argv(b"cat", &[&b"caf\xe9"[..], b"\xff"]).unwrap();
+        assert_eq!(argv.len(), 3);
+        assert_eq!(argv[0].as_bytes(), b"cat");
+        assert_eq!(argv[1].as_bytes(), b"caf\xe9");
+        assert_eq!(argv[2].as_bytes(), b"\xff");
+    }
+
+    #[test]
+    fn test_build_argv_rejects_nul() {
+        assert!(matches!(
+            build_argv(b"echo", &["a\0b"]),
+            Err(SpawnError::InvalidArg)
+        ));
+        assert!(matches!(
+            spawn_argv(&[], ProcessGroup::Inherit),
+            Err(SpawnError::InvalidArg)
+        ));
+    }
+
     #[test]
     fn test_spawn_true() {
-        let mut child = spawn_command("true", &[], false).unwrap();
+        let mut child = spawn_command("true", &[], ProcessGroup::Inherit).unwrap();
         let (status, rusage) = child.wait().unwrap();
         assert_eq!(status.code(), Some(0));
-        /* rusage should have some values (at least max_rss > 0 for any process) */
-        assert!(rusage.max_rss_kb > 0);
+        /* rusage should have some values (at least max_rss > 0 for any process).
+         * nothing runs in under 100KB, so less means a bytes/KB mixup */
+        assert!(rusage.max_rss_kb > 100);
     }
 
     #[test]
     fn test_spawn_false() {
-        let mut child = spawn_command("false", &[], false).unwrap();
+        let mut child = spawn_command("false", &[], ProcessGroup::Inherit).unwrap();
         let (status, _rusage) = child.wait().unwrap();
         assert_eq!(status.code(), Some(1));
     }
 
+    #[test]
+    fn test_spawn_crash() {
+        /* ulimit -c 0: no core file, whatever the environment allows */
+        let args = vec![
+            String::from("-c"),
+            String::from("ulimit -c 0; kill -SEGV $$"),
+        ];
+        let mut child = spawn_command("sh", &args, ProcessGroup::Inherit).unwrap();
+        let (status, _rusage) = child.wait().unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGSEGV));
+        assert_eq!(status.code(), None);
+        assert!(!status.core_dumped());
+    }
+
     #[test]
     fn test_spawn_not_found() {
-        let result = spawn_command("nonexistent_command_12345", &[], false);
-        assert!(matches!(result, Err(SpawnError::NotFound(_))));
+        let result = spawn_command("nonexistent_command_12345", &[], ProcessGroup::Inherit);
+        assert!(matches!(
+            result,
+            Err(SpawnError::NotRunnable(NotRunnable {
+                problem: lookup::Problem::NotFound,
+                ..
+            }))
+        ));
     }
 
     #[test]
     fn test_spawn_with_args() {
         let args = vec![String::from("hello")];
-        let mut child = spawn_command("echo", &args, false).unwrap();
+        let mut child = spawn_command("echo", &args, ProcessGroup::Inherit).unwrap();
         let (status, _rusage) = child.wait().unwrap();
         assert_eq!(status.code(), Some(0));
     }
 
     #[test]
     fn test_try_wait() {
-        let mut child = spawn_command("sleep", &[String::from("0.1")], false).unwrap();
+        let mut child =
+            spawn_command("sleep", &[String::from("0.1")], ProcessGroup::Inherit).unwrap();
         /* should still be running */
         let result = child.try_wait().unwrap();
         assert!(result.is_none() || result.is_some()); /* might be fast */
         /* wait for completion */
         let _ = child.wait();
     }
+
+    #[test]
+    fn test_process_group_from_str() {
+        assert_eq!(
+            ProcessGroup::from_str("inherit"),
+            Some(ProcessGroup::Inherit)
+        );
+        assert_eq!(ProcessGroup::from_str("NEW"), Some(ProcessGroup::New));
+        assert_eq!(
+            ProcessGroup::from_str("session"),
+            Some(ProcessGroup::Session)
+        );
+        assert_eq!(ProcessGroup::from_str("group"), None);
+        assert!(!ProcessGroup::Inherit.owns_group());
+        assert!(ProcessGroup::Session.owns_group());
+    }
+
+    #[test]
+    fn test_spawn_session_leads_own_group() {
+        let mut child =
+            spawn_command("sleep", &[String::from("0.1")], ProcessGroup::Session).unwrap();
+        #[allow(clippy::cast_possible_wrap)]
+        let pid = child.id() as i32;
+        // SAFETY: getpgid/getsid on our own child, no pointers involved
+        #[allow(clippy::multiple_unsafe_ops_per_block)]
+        let (pgid, sid) = unsafe { (libc::getpgid(pid), libc::getsid(pid)) };
+        let _ = child.wait();
+        assert_eq!(pgid, pid, "child should lead its group");
+        assert_eq!(sid, pid, "child should lead its session");
+    }
 }
 
 /* -------------------------------------------------------------------------- */
@@ -0,0 +1,606 @@
+/*
+ * proctree.rs
+ *
+ * --proc-tree: every process the command started, as it forks and execs -
+ * pid, parent, name - for the JSON result and --explain. "make timed out"
+ * says little; "make -> sh -> cc1plus, still compiling" says where.
+ *
+ * kqueue's EVFILT_PROC reports NOTE_FORK, NOTE_EXEC and NOTE_EXIT for a
+ * watched pid. NOTE_TRACK, which would carry the watch over to children by
+ * itself, is refused (ENOTSUP) by every macOS kernel, so we do its job: on
+ * NOTE_FORK, ask libproc for the parent's children and watch the new ones.
+ * a new child is listed too, right after its watch is in place, in case it
+ * forked before we got to it. what this can miss is a process that forked
+ * and exited between two looks - the tree is a record of what we saw, not
+ * an audit. names come from proc_name(), which is the executable's name
+ * (truncated at 32 bytes), not argv[0].
+ *
+ * the tracker has a kqueue of its own, so its watches outlive the resets of
+ * the run's queue; the run's wait loop nests it as one readable fd and
+ * drains it when it fires. forks during a grace period or hook are
+ * collected by the final drain, late - by then some of the children may be
+ * gone and are missed.
+ *
+ * the table is fixed-size like EventLog, which owns it: no allocation
+ * while the child runs, and a fork bomb can't grow memory. it holds the
+ * last attempt's tree.
+ *
+ * once the command is reaped, whatever in the tree still runs outside its
+ * process group has escaped: it double-forked or called setsid(), so the
+ * group signal a timeout sends never reached it. those are marked, reported
+ * (escaped_processes, a warning) and with --kill-escaped sent SIGKILL -
+ * only if the pid still runs the executable we recorded, so a pid that was
+ * reused since isn't ours to kill.
+ *
+ * linux has no NOTE_FORK short of ptrace or a netlink socket that needs
+ * CAP_NET_ADMIN; --proc-tree is refused there, as it is without proc-info.
+ */
+#![cfg_attr(
+    any(not(feature = "proc-info"), not(target_os = "macos")),
+    allow(dead_code)
+)]
+
+use core::fmt;
+
+use crate::json::JsonWriter;
+
+/// Processes a [`ProcTree`] holds. A build fans out wide; past this, new
+/// processes are counted as dropped.
+pub const MAX_PROCS: usize = 128;
+
+/* proc_name() answers at most 2 * MAXCOMLEN bytes */
+const NAME_LEN: usize = 32;
+
+/// One process in the command's tree.
+#[derive(Debug, Clone, Copy)]
+pub struct Proc {
+    /// Its pid.
+    pub pid: u32,
+    /// Parent when we first saw it. Not updated if it's reparented.
+    pub ppid: u32,
+    /// When we first saw it, relative to the first event in the log.
+    pub at_ns: u64,
+    /// exec()s seen after it was first recorded.
+    pub execs: u32,
+    /// False if it was still running the last time we looked.
+    pub exited: bool,
+    /// Still running once the command was reaped, outside its process
+    /// group - out of reach of the signal a timeout sends.
+    pub escaped: bool,
+    /// Sent SIGKILL by `--kill-escaped`.
+    pub killed: bool,
+    name: [u8; NAME_LEN],
+    name_len: u8,
+}
+
+impl Proc {
+    /// Executable name, empty if it couldn't be read. Not necessarily UTF-8.
+    #[inline]
+    #[must_use]
+    pub fn name(&self) -> &[u8] {
+        &self.name[..usize::from(self.name_len)]
+    }
+
+    fn set_name(&mut self, name: &[u8]) {
+        let len = name.len().min(NAME_LEN);
+        self.name[..len].copy_from_slice(&name[..len]);
+        #[allow(clippy::cast_possible_truncation)]
+        {
+            self.name_len = len as u8;
+        }
+    }
+}
+
+const EMPTY_PROC: Proc = Proc {
+    pid: 0,
+    ppid: 0,
+    at_ns: 0,
+    execs: 0,
+    exited: false,
+    escaped: false,
+    killed: false,
+    name: [0; NAME_LEN],
+    name_len: 0,
+};
+
+/// Processes seen in the command's tree, parents before their children.
+/// Filled in with `--proc-tree` ([`crate::runner::RunConfig::proc_tree`]).
+pub struct ProcTree {
+    procs: [Proc; MAX_PROCS],
+    len: usize,
+    dropped: u32,
+}
+
+impl Default for ProcTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProcTree {
+    /// An empty tree.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            procs: [EMPTY_PROC; MAX_PROCS],
+            len: 0,
+            dropped: 0,
+        }
+    }
+
+    /* start over for a new attempt */
+    pub(crate) fn clear(&mut self) {
+        self.len = 0;
+        self.dropped = 0;
+    }
+
+    /// The processes, parents before their children.
+    #[inline]
+    pub fn as_slice(&self) -> &[Proc] {
+        &self.procs[..self.len]
+    }
+
+    /// Processes recorded.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether none were recorded.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Processes that didn't fit in the table.
+    #[inline]
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /* the live entry for pid - a pid reused within one run has an exited
+     * entry before it */
+    fn live_mut(&mut self, pid: u32) -> Option<&mut Proc> {
+        self.procs[..self.len]
+            .iter_mut()
+            .rev()
+            .find(|p| p.pid == pid && !p.exited)
+    }
+
+    /* already recorded and running? */
+    pub(crate) fn contains(&self, pid: u32) -> bool {
+        self.as_slice().iter().any(|p| p.pid == pid && !p.exited)
+    }
+
+    /// Record a new process. False if the table is full (it's counted as
+    /// dropped instead).
+    pub(crate) fn add(&mut self, pid: u32, ppid: u32, at_ns: u64, name: &[u8]) -> bool {
+        if self.len == MAX_PROCS {
+            self.dropped = self.dropped.saturating_add(1);
+            return false;
+        }
+        let mut proc = Proc {
+            pid,
+            ppid,
+            at_ns,
+            ..EMPTY_PROC
+        };
+        proc.set_name(name);
+        self.procs[self.len] = proc;
+        self.len += 1;
+        true
+    }
+
+    /// `pid` exec'd `name`.
+    pub(crate) fn exec(&mut self, pid: u32, name: &[u8]) {
+        if let Some(proc) = self.live_mut(pid) {
+            proc.execs = proc.execs.saturating_add(1);
+            if !name.is_empty() {
+                proc.set_name(name);
+            }
+        }
+    }
+
+    /// `pid` is gone.
+    pub(crate) fn exit(&mut self, pid: u32) {
+        if let Some(proc) = self.live_mut(pid) {
+            proc.exited = true;
+        }
+    }
+
+    /// Mark what's still running but no longer in `group`, the command's
+    /// process group, as escaped. Returns how many are.
+    pub(crate) fn mark_escaped(&mut self, group: i32) -> u32 {
+        let mut count = 0;
+        for proc in &mut self.procs[..self.len] {
+            if proc.exited {
+                continue;
+            }
+            #[allow(clippy::cast_possible_wrap)]
+            // SAFETY: getpgid only reads the pid; -1 if it's gone
+            let pgid = unsafe { libc::getpgid(proc.pid as i32) };
+            proc.escaped = pgid >= 0 && pgid != group;
+            count += u32::from(proc.escaped);
+        }
+        count
+    }
+
+    /// Processes that escaped the command's process group, see
+    /// [`Proc::escaped`].
+    pub fn escaped(&self) -> impl Iterator<Item = &Proc> {
+        self.as_slice().iter().filter(|p| p.escaped)
+    }
+
+    /* hops from the root; the root and orphans of the table (parent
+     * dropped) are 0 */
+    fn depth(&self, i: usize) -> usize {
+        let procs = self.as_slice();
+        let mut depth = 0;
+        let mut at = i;
+        /* parents come first, so only look behind - that also ends the walk */
+        while let Some(parent) = procs[..at].iter().rposition(|p| p.pid == procs[at].ppid) {
+            depth += 1;
+            at = parent;
+        }
+        depth
+    }
+
+    /// Indented tree, one process per line. Used by `--explain`.
+    pub fn write_tree<W: fmt::Write>(&self, out: &mut W) -> fmt::Result {
+        for (i, proc) in self.as_slice().iter().enumerate() {
+            let indent = 2 * (self.depth(i) + 1);
+            write!(out, "{:indent$}{} ", "", proc.pid)?;
+            match core::str::from_utf8(proc.name()) {
+                Ok(name) if !name.is_empty() => out.write_str(name)?,
+                _ => out.write_char('?')?,
+            }
+            match proc.execs {
+                0 => {}
+                1 => out.write_str(" (1 exec)")?,
+                n => write!(out, " ({n} execs)")?,
+            }
+            if proc.killed {
+                out.write_str(" [escaped, killed]")?;
+            } else if proc.escaped {
+                out.write_str(" [escaped]")?;
+            } else if !proc.exited {
+                out.write_str(" [running]")?;
+            }
+            out.write_char('\n')?;
+        }
+        if self.dropped > 0 {
+            writeln!(out, "  ({} more processes not recorded)", self.dropped)?;
+        }
+        Ok(())
+    }
+
+    /// JSON array of processes: `[{"pid":123,"ppid":100,"command":"make",
+    /// "offset_ms":0,"execs":0,"exited":true},...]`. `offset_ms` is on the
+    /// timeline's clock.
+    pub fn write_json<W: fmt::Write>(&self, w: &mut JsonWriter<W>) {
+        w.begin_array();
+        for proc in self.as_slice() {
+            w.begin_object();
+            w.field_u64("pid", u64::from(proc.pid));
+            w.field_u64("ppid", u64::from(proc.ppid));
+            w.key("command").bytes_lossy(proc.name());
+            w.field_u64("offset_ms", proc.at_ns / 1_000_000);
+            w.field_u64("execs", u64::from(proc.execs));
+            w.field_bool("exited", proc.exited);
+            w.end_object();
+        }
+        w.end_array();
+    }
+
+    /// JSON array of the escaped processes' pids: `[4251,4260]`.
+    pub fn write_escaped_json<W: fmt::Write>(&self, w: &mut JsonWriter<W>) {
+        w.begin_array();
+        for proc in self.escaped() {
+            w.u64(u64::from(proc.pid));
+        }
+        w.end_array();
+    }
+}
+
+#[cfg(all(feature = "proc-info", target_os = "macos"))]
+pub(crate) use live::Tracker;
+
+/// Never starts: built without proc-info, or not on macOS.
+#[cfg(not(all(feature = "proc-info", target_os = "macos")))]
+pub(crate) struct Tracker;
+
+#[cfg(not(all(feature = "proc-info", target_os = "macos")))]
+impl Tracker {
+    pub(crate) fn start(_root: i32, _tree: &mut ProcTree, _at_ns: u64) -> Option<Self> {
+        None
+    }
+
+    pub(crate) fn fd(&self) -> i32 {
+        -1
+    }
+
+    pub(crate) fn drain(&self, _tree: &mut ProcTree, _at_ns: u64) {}
+
+    pub(crate) fn kill_escaped(&self, _tree: &mut ProcTree, _at_ns: u64) -> u32 {
+        0
+    }
+}
+
+#[cfg(all(feature = "proc-info", target_os = "macos"))]
+mod live {
+    use super::ProcTree;
+    use crate::kq::{self, Kqueue};
+
+    unsafe extern "C" {
+        fn proc_listchildpids(ppid: i32, buffer: *mut core::ffi::c_void, size: i32) -> i32;
+        fn proc_name(pid: i32, buffer: *mut core::ffi::c_void, size: u32) -> i32;
+    }
+
+    /* children listed per proc_listchildpids call; a parent with more
+     * loses the rest to the next NOTE_FORK */
+    const MAX_CHILDREN: usize = 64;
+
+    /* processes adopted but not yet checked for children of their own */
+    const ADOPT_DEPTH: usize = 32;
+
+    /* proc_name() wants room for the terminator */
+    const NAME_BUF: usize = super::NAME_LEN + 1;
+
+    /* events collected per kevent() in drain() */
+    const BATCH: usize = 16;
+
+    /// Watches the command and everything it starts, feeding a [`ProcTree`].
+    pub(crate) struct Tracker {
+        queue: Kqueue,
+    }
+
+    impl Tracker {
+        /// Record `root` and start watching it. None if the kqueue can't
+        /// be made or root is already gone.
+        pub(crate) fn start(root: i32, tree: &mut ProcTree, at_ns: u64) -> Option<Self> {
+            tree.clear();
+            let queue = Kqueue::new().ok()?;
+            let watched = queue.register(&kq::proc_lifetime(root)).is_ok();
+            let mut buf = [0; NAME_BUF];
+            tree.add(root.unsigned_abs(), our_pid(), at_ns, name(root, &mut buf));
+            if !watched {
+                tree.exit(root.unsigned_abs());
+                return None;
+            }
+            let tracker = Self { queue };
+            /* whatever it managed to start before the watch was in place */
+            tracker.adopt_children(root, tree, at_ns);
+            Some(tracker)
+        }
+
+        /// The tracker's kqueue, readable while it has events to drain.
+        pub(crate) fn fd(&self) -> i32 {
+            self.queue.fd()
+        }
+
+        /// Apply every pending fork, exec and exit to `tree`. `at_ns` is
+        /// the first-seen time for new processes.
+        pub(crate) fn drain(&self, tree: &mut ProcTree, at_ns: u64) {
+            let mut events = [kq::empty(); BATCH];
+            loop {
+                let n = self.queue.poll(&mut events);
+                for event in &events[..n] {
+                    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+                    let pid = event.ident as i32;
+                    if event.flags & kq::EV_ERROR != 0 {
+                        continue;
+                    }
+                    /* one event can carry all three; children first, while
+                     * the parent is still there to list them */
+                    if event.fflags & libc::NOTE_FORK != 0 {
+                        self.adopt_children(pid, tree, at_ns);
+                    }
+                    if event.fflags & libc::NOTE_EXEC != 0 {
+                        let mut buf = [0; NAME_BUF];
+                        tree.exec(pid.unsigned_abs(), name(pid, &mut buf));
+                    }
+                    if event.fflags & libc::NOTE_EXIT != 0 {
+                        tree.exit(pid.unsigned_abs());
+                    }
+                }
+                if n < BATCH {
+                    return;
+                }
+            }
+        }
+
+        /// SIGKILL every escaped process still running what we recorded
+        /// it running, after one last drain for news of its exit or exec.
+        /// Returns how many were sent it.
+        pub(crate) fn kill_escaped(&self, tree: &mut ProcTree, at_ns: u64) -> u32 {
+            self.drain(tree, at_ns);
+            let mut killed = 0;
+            for proc in &mut tree.procs[..tree.len] {
+                if !proc.escaped || proc.exited {
+                    continue;
+                }
+                #[allow(clippy::cast_possible_wrap)]
+                let pid = proc.pid as i32;
+                let mut buf = [0; NAME_BUF];
+                if name(pid, &mut buf) != proc.name() {
+                    continue;
+                }
+                // SAFETY: kill takes no pointers; pid is a process we watch
+                proc.killed = unsafe { libc::kill(pid, libc::SIGKILL) } == 0;
+                killed += u32::from(proc.killed);
+            }
+            killed
+        }
+
+        /* record and watch children of `parent` we haven't seen, then
+         * theirs - depth-first, bounded by ADOPT_DEPTH pending parents */
+        fn adopt_children(&self, parent: i32, tree: &mut ProcTree, at_ns: u64) {
+            let mut pending = [0i32; ADOPT_DEPTH];
+            pending[0] = parent;
+            let mut top = 1;
+            while top > 0 {
+                top -= 1;
+                let ppid = pending[top];
+                let mut children = [0i32; MAX_CHILDREN];
+                for &child in list_children(ppid, &mut children) {
+                    if child <= 0 || tree.contains(child.unsigned_abs()) {
+                        continue;
+                    }
+                    /* watch before reading the name: an exec in between
+                     * then still shows up as NOTE_EXEC. ESRCH: gone
+                     * already - recorded, nothing to watch */
+                    let watched = self.queue.register(&kq::proc_lifetime(child)).is_ok();
+                    let mut buf = [0; NAME_BUF];
+                    let name = name(child, &mut buf);
+                    if !tree.add(child.unsigned_abs(), ppid.unsigned_abs(), at_ns, name) {
+                        return; /* full */
+                    }
+                    if !watched {
+                        tree.exit(child.unsigned_abs());
+                    } else if top < ADOPT_DEPTH {
+                        pending[top] = child;
+                        top += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    fn our_pid() -> u32 {
+        // SAFETY: getpid has no preconditions
+        unsafe { libc::getpid() }.unsigned_abs()
+    }
+
+    /* pids of `ppid`'s children, into `buf` */
+    fn list_children(ppid: i32, buf: &mut [i32; MAX_CHILDREN]) -> &[i32] {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        let size = size_of_val(buf) as i32;
+        // SAFETY: buf is valid for `size` bytes of pids
+        let n = unsafe { proc_listchildpids(ppid, buf.as_mut_ptr().cast(), size) };
+        let n = usize::try_from(n).unwrap_or(0).min(MAX_CHILDREN);
+        &buf[..n]
+    }
+
+    /* executable name, empty if the process is gone */
+    fn name(pid: i32, buf: &mut [u8; NAME_BUF]) -> &[u8] {
+        #[allow(clippy::cast_possible_truncation)]
+        let size = buf.len() as u32;
+        // SAFETY: buf is valid for `size` bytes
+        let n = unsafe { proc_name(pid, buf.as_mut_ptr().cast(), size) };
+        let n = usize::try_from(n).unwrap_or(0).min(super::NAME_LEN);
+        &buf[..n]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::String;
+
+    fn sample() -> ProcTree {
+        let mut tree = ProcTree::new();
+        tree.add(100, 1, 0, b"make");
+        tree.add(101, 100, 5_000_000, b"make");
+        tree.exec(101, b"sh");
+        tree.add(102, 101, 7_000_000, b"sh");
+        tree.exec(102, b"cc");
+        tree.add(103, 100, 9_000_000, b"make");
+        tree.exit(102);
+        tree.exit(101);
+        tree
+    }
+
+    #[test]
+    fn test_write_tree() {
+        let mut out = String::new();
+        sample().write_tree(&mut out).unwrap();
+        assert_eq!(
+            out,
+            concat!(
+                "  100 make [running]\n",
+                "    101 sh (1 exec)\n",
+                "      102 cc (1 exec)\n",
+                "    103 make [running]\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_write_json() {
+        let mut w = JsonWriter::new();
+        sample().write_json(&mut w);
+        let json = w.finish();
+        assert!(json.starts_with(
+            r#"[{"pid":100,"ppid":1,"command":"make","offset_ms":0,"execs":0,"exited":false},"#
+        ));
+        assert!(json.contains(
+            r#"{"pid":102,"ppid":101,"command":"cc","offset_ms":7,"execs":1,"exited":true}"#
+        ));
+    }
+
+    #[test]
+    fn test_pid_reuse() {
+        let mut tree = ProcTree::new();
+        tree.add(100, 1, 0, b"sh");
+        tree.add(200, 100, 0, b"true");
+        tree.exit(200);
+        assert!(!tree.contains(200));
+        /* same pid, new process: the exited entry stays as it was */
+        tree.add(200, 100, 0, b"sleep");
+        assert!(tree.contains(200));
+        tree.exec(200, b"sleep");
+        assert_eq!(tree.as_slice()[1].execs, 0);
+        assert_eq!(tree.as_slice()[2].execs, 1);
+    }
+
+    #[test]
+    fn test_mark_escaped() {
+        // SAFETY: getpid has no preconditions
+        let pid = unsafe { libc::getpid() };
+        // SAFETY: getpgrp has no preconditions
+        let group = unsafe { libc::getpgrp() };
+        let mut tree = ProcTree::new();
+        tree.add(pid.unsigned_abs(), 1, 0, b"test");
+        tree.add(pid.unsigned_abs() + 1, 1, 0, b"gone");
+        tree.exit(pid.unsigned_abs() + 1);
+        /* still in the group: not escaped */
+        assert_eq!(tree.mark_escaped(group), 0);
+        /* a group we're not in: escaped; the exited one never is */
+        assert_eq!(tree.mark_escaped(group + 1), 1);
+        assert_eq!(
+            tree.escaped().next().map(|p| p.pid),
+            Some(pid.unsigned_abs())
+        );
+
+        let mut out = String::new();
+        tree.write_tree(&mut out).unwrap();
+        assert!(out.starts_with(&alloc::format!("  {pid} test [escaped]\n")));
+        let mut w = JsonWriter::new();
+        tree.write_escaped_json(&mut w);
+        assert_eq!(w.finish(), alloc::format!("[{pid}]"));
+    }
+
+    #[test]
+    fn test_full_table_drops() {
+        let mut tree = ProcTree::new();
+        for pid in 0..(MAX_PROCS as u32 + 3) {
+            tree.add(pid + 2, 1, 0, b"x");
+        }
+        assert_eq!(tree.len(), MAX_PROCS);
+        assert_eq!(tree.dropped(), 3);
+        let mut out = String::new();
+        tree.write_tree(&mut out).unwrap();
+        assert!(out.ends_with("(3 more processes not recorded)\n"));
+
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.dropped(), 0);
+    }
+
+    #[test]
+    fn test_long_name_truncated() {
+        let mut tree = ProcTree::new();
+        tree.add(7, 1, 0, &[b'a'; 40]);
+        assert_eq!(tree.as_slice()[0].name(), &[b'a'; NAME_LEN][..]);
+    }
+}
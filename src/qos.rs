@@ -0,0 +1,113 @@
+/*
+ * qos.rs
+ *
+ * quality-of-service class for timeout's own thread (--monitor-qos).
+ *
+ * on a loaded machine the scheduler can sit on our kevent wakeup for a long
+ * time, and the deadline fires late. raising our QoS keeps enforcement timely
+ * without touching the child: it's applied after spawning, and posix_spawn
+ * children don't take QoS from the spawning thread anyway.
+ *
+ * linux has no QoS classes: the option parses, set_self() reports failure.
+ */
+
+#[cfg(target_os = "macos")]
+unsafe extern "C" {
+    fn pthread_set_qos_class_self_np(qos_class: u32, relative_priority: i32) -> i32;
+}
+
+/* <sys/qos.h> */
+const QOS_CLASS_USER_INTERACTIVE: u32 = 0x21;
+const QOS_CLASS_USER_INITIATED: u32 = 0x19;
+const QOS_CLASS_DEFAULT: u32 = 0x15;
+const QOS_CLASS_UTILITY: u32 = 0x11;
+const QOS_CLASS_BACKGROUND: u32 = 0x09;
+
+/// Darwin QoS class, highest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum QosClass {
+    /// UI work the user is waiting on this frame.
+    UserInteractive,
+    /// Work the user started and is waiting for.
+    UserInitiated,
+    /// Between user-initiated and utility.
+    Default,
+    /// Long-running work with a progress bar.
+    Utility,
+    /// Work nobody is waiting for; runs on efficiency cores.
+    Background,
+}
+
+impl QosClass {
+    pub(crate) fn from_str(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "user-interactive" => Some(Self::UserInteractive),
+            "user-initiated" => Some(Self::UserInitiated),
+            "default" => Some(Self::Default),
+            "utility" => Some(Self::Utility),
+            "background" => Some(Self::Background),
+            _ => None,
+        }
+    }
+
+    /// The name `--qos` takes: `"user-initiated"`.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::UserInteractive => "user-interactive",
+            Self::UserInitiated => "user-initiated",
+            Self::Default => "default",
+            Self::Utility => "utility",
+            Self::Background => "background",
+        }
+    }
+
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    const fn raw(self) -> u32 {
+        match self {
+            Self::UserInteractive => QOS_CLASS_USER_INTERACTIVE,
+            Self::UserInitiated => QOS_CLASS_USER_INITIATED,
+            Self::Default => QOS_CLASS_DEFAULT,
+            Self::Utility => QOS_CLASS_UTILITY,
+            Self::Background => QOS_CLASS_BACKGROUND,
+        }
+    }
+}
+
+/// Move the calling thread to `class`. Returns false if the kernel refused,
+/// which on Linux is always.
+#[cfg(target_os = "macos")]
+pub fn set_self(class: QosClass) -> bool {
+    // SAFETY: takes no pointers; an unknown class is rejected with EINVAL
+    unsafe { pthread_set_qos_class_self_np(class.raw(), 0) == 0 }
+}
+
+/// Move the calling thread to `class`: a no-op, QoS classes are Darwin's.
+#[cfg(target_os = "linux")]
+pub fn set_self(_class: QosClass) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_qos_class_from_str() {
+        for class in [
+            QosClass::UserInteractive,
+            QosClass::UserInitiated,
+            QosClass::Default,
+            QosClass::Utility,
+            QosClass::Background,
+        ] {
+            assert_eq!(QosClass::from_str(class.as_str()), Some(class));
+        }
+        assert_eq!(
+            QosClass::from_str("USER-INTERACTIVE"),
+            Some(QosClass::UserInteractive)
+        );
+        assert_eq!(QosClass::from_str("realtime"), None);
+    }
+}
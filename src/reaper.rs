@@ -0,0 +1,248 @@
+/*
+ * reaper.rs
+ *
+ * --reap-orphans: whatever the command started and left running in its
+ * process group, killed and reaped once the command itself is. a shell
+ * that backgrounds a server and exits leaves it there, re-parented and
+ * still holding the job's pipes open.
+ *
+ * linux lets us be a subreaper (PR_SET_CHILD_SUBREAPER): what the command
+ * orphans re-parents to procguard instead of init, so the sweep's waitpid
+ * collects it and nothing is left a zombie. the setting is process-wide,
+ * and procguard is a library too: it's held only while a run that asked
+ * for it is going (a count, like forward.rs's handlers), and put back as
+ * it was found after the last - an embedder that's a subreaper of its own
+ * stays one.
+ *
+ * macOS has no such thing. the request was for a supervisor process in
+ * between to adopt the orphans; we don't fork one. we supervise by
+ * process group alone: the group is the command's pid, and its members
+ * are found by listing it (proc_info::list_group). launchd adopts them
+ * there, and reaps them once they're killed. nothing is ever adopted by
+ * us on macOS, which is why the count is "swept" - what was in the group
+ * when we looked - and not "adopted".
+ *
+ * anything that left the group (setsid, setpgid) isn't found this way;
+ * that's --kill-escaped, from the process tree.
+ */
+
+#[cfg(target_os = "linux")]
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use crate::proc_info::{self, ProcStatus};
+
+/// What `--reap-orphans` found left behind, summed over attempts.
+///
+/// ```
+/// use procguard::reaper::Orphans;
+///
+/// let mut total = Orphans::default();
+/// total.add(Orphans { swept: 2, killed: 1 });
+/// total.add(Orphans { swept: 1, killed: 1 });
+/// assert_eq!(total, Orphans { swept: 3, killed: 2 });
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Orphans {
+    /// Processes still in the command's group once it was reaped. On
+    /// Linux they were re-parented to us; on macOS, which can't do that,
+    /// it's only what listing the group found: launchd is their parent.
+    pub swept: u32,
+    /// Those of them still running, and so sent SIGKILL; the others had
+    /// exited and were only reaped.
+    pub killed: u32,
+}
+
+impl Orphans {
+    /// Add another attempt's counts.
+    pub const fn add(&mut self, other: Self) {
+        self.swept = self.swept.saturating_add(other.swept);
+        self.killed = self.killed.saturating_add(other.killed);
+    }
+}
+
+/* most members of one group a sweep looks at */
+const MAX_GROUP: usize = 256;
+
+/* how long a sweep waits for the killed to exit, in 1ms steps */
+const REAP_WAIT_MS: u32 = 1000;
+
+/* live Subreapers, and whether the process was a subreaper before the
+ * first; changed only under HOLD_LOCK */
+#[cfg(target_os = "linux")]
+static HOLDERS: AtomicU32 = AtomicU32::new(0);
+#[cfg(target_os = "linux")]
+static WAS_SUBREAPER: AtomicBool = AtomicBool::new(false);
+#[cfg(target_os = "linux")]
+static HOLD_LOCK: AtomicBool = AtomicBool::new(false);
+
+/* held while the count changes, so a prctl() from one run can't undo
+ * another's */
+#[cfg(target_os = "linux")]
+struct HoldLock;
+
+#[cfg(target_os = "linux")]
+impl HoldLock {
+    fn acquire() -> Self {
+        while HOLD_LOCK
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        Self
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for HoldLock {
+    fn drop(&mut self) {
+        HOLD_LOCK.store(false, Ordering::Release);
+    }
+}
+
+/// Keeps the process a subreaper; see [`become_subreaper`]. Dropping the
+/// last one puts the setting back as it was before the first.
+#[must_use = "the process stops being a subreaper as soon as it's dropped"]
+#[derive(Debug)]
+pub struct Subreaper {
+    _private: (),
+}
+
+/// Have what our descendants orphan re-parent to us, for as long as the
+/// guard lives. Linux only; elsewhere, or if the kernel refuses, None.
+///
+/// ```
+/// use procguard::reaper::become_subreaper;
+///
+/// let guard = become_subreaper();
+/// assert_eq!(guard.is_some(), cfg!(target_os = "linux"));
+/// drop(guard); /* the last one: back as it was */
+/// ```
+pub fn become_subreaper() -> Option<Subreaper> {
+    #[cfg(target_os = "linux")]
+    {
+        let _lock = HoldLock::acquire();
+        if HOLDERS.load(Ordering::SeqCst) == 0 {
+            let was = is_subreaper();
+            WAS_SUBREAPER.store(was, Ordering::SeqCst);
+            // SAFETY: PR_SET_CHILD_SUBREAPER takes no pointers
+            if !was && unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0) } != 0 {
+                return None;
+            }
+        }
+        HOLDERS.fetch_add(1, Ordering::SeqCst);
+        Some(Subreaper { _private: () })
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Whether the process is a subreaper right now. Always false but on Linux.
+#[must_use]
+pub fn is_subreaper() -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        let mut on: i32 = 0;
+        // SAFETY: PR_GET_CHILD_SUBREAPER writes one int through the pointer,
+        // a local that outlives the call
+        let ret = unsafe { libc::prctl(libc::PR_GET_CHILD_SUBREAPER, &raw mut on, 0, 0, 0) };
+        ret == 0 && on != 0
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        false
+    }
+}
+
+impl Drop for Subreaper {
+    fn drop(&mut self) {
+        #[cfg(target_os = "linux")]
+        {
+            let _lock = HoldLock::acquire();
+            if HOLDERS.fetch_sub(1, Ordering::SeqCst) == 1 && !WAS_SUBREAPER.load(Ordering::SeqCst)
+            {
+                // SAFETY: PR_SET_CHILD_SUBREAPER takes no pointers
+                unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 0, 0, 0, 0) };
+            }
+        }
+    }
+}
+
+/// SIGKILL whatever is left in process `group`, and reap it where it's
+/// ours to reap. Call once the group's leader has been reaped.
+#[must_use]
+pub fn sweep(group: i32) -> Orphans {
+    let mut pids = [0i32; MAX_GROUP];
+    let count = proc_info::list_group(group, &mut pids);
+    let pids = &pids[..count];
+    if pids.is_empty() {
+        return Orphans::default();
+    }
+    let killed = pids
+        .iter()
+        .filter(|&&pid| proc_info::get_process_status(pid) != Some(ProcStatus::Zombie))
+        .count();
+    // SAFETY: killpg takes no pointers; a group gone by now is ESRCH
+    unsafe { libc::killpg(group, libc::SIGKILL) };
+    reap(pids);
+    #[allow(clippy::cast_possible_truncation)] /* at most MAX_GROUP */
+    Orphans {
+        swept: count as u32,
+        killed: killed as u32,
+    }
+}
+
+/* as subreaper, the group's members are our children by now: collect
+ * them, giving the killed REAP_WAIT_MS to go. ECHILD is one that isn't
+ * ours - it was never an orphan of the command's */
+#[cfg(target_os = "linux")]
+fn reap(pids: &[i32]) {
+    let mut budget = REAP_WAIT_MS;
+    for &pid in pids {
+        loop {
+            // SAFETY: a null status pointer is allowed
+            let ret = unsafe { libc::waitpid(pid, core::ptr::null_mut(), libc::WNOHANG) };
+            if ret != 0 || budget == 0 {
+                break;
+            }
+            budget -= 1;
+            crate::wait::sleep_ms(1);
+        }
+    }
+}
+
+/* launchd's to reap */
+#[cfg(not(target_os = "linux"))]
+const fn reap(_pids: &[i32]) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_orphans_add_saturates() {
+        let mut total = Orphans {
+            swept: u32::MAX,
+            killed: 1,
+        };
+        total.add(Orphans {
+            swept: 1,
+            killed: 2,
+        });
+        assert_eq!(
+            total,
+            Orphans {
+                swept: u32::MAX,
+                killed: 3
+            }
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] /* lists processes */
+    fn test_sweep_empty_group() {
+        assert_eq!(sweep(0x7fff_fff0), Orphans::default());
+    }
+}
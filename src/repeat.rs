@@ -0,0 +1,330 @@
+/*
+ * repeat.rs
+ *
+ * --every 5m --for 2h: run the command on a fixed cadence for a while.
+ * run k is due k * every after the first one started. a run that overruns
+ * its slot doesn't queue the ones it missed - the next run goes in the
+ * following free slot, as cron would have it. nothing new starts once the
+ * budget is spent; a run still going then finishes under its own timeout.
+ *
+ * --detect-flaky N is the other kind of repeat: run once, and only if that
+ * fails rerun up to N times, stopping at the first pass. a pass among the
+ * reruns makes the test flaky; none makes it consistently failing.
+ *
+ * each run is an ordinary run_with_retry, so the timeout, --retry and the
+ * hooks apply per run. this is only the schedule and the policies; the
+ * loop and the aggregate report are the CLI's.
+ */
+
+use core::time::Duration;
+
+use crate::outcome::Outcome;
+use crate::stats::RunStats;
+
+/// `--stop-on`: which run ends a repeat early. A forwarded signal always
+/// does, whatever this says.
+///
+/// ```
+/// use procguard::{Outcome, StopOn};
+///
+/// let stop = StopOn::parse("Failure").unwrap();
+/// assert!(stop.stops(Outcome::Failed { code: 1 }));
+/// assert!(!stop.stops(Outcome::Success { code: 0 }));
+/// assert!(!StopOn::Never.stops(Outcome::Failed { code: 1 }));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum StopOn {
+    /// Keep going until the budget is spent
+    #[default]
+    Never,
+    /// Stop after a run that timed out
+    Timeout,
+    /// Stop after any run that didn't succeed: nonzero exit, crash,
+    /// timeout or resource limit
+    Failure,
+}
+
+impl StopOn {
+    /// Parse "never", "timeout" or "failure", any case.
+    #[must_use]
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "never" => Some(Self::Never),
+            "timeout" => Some(Self::Timeout),
+            "failure" => Some(Self::Failure),
+            _ => None,
+        }
+    }
+
+    /// The name `parse` takes, as the JSON report gives it.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Never => "never",
+            Self::Timeout => "timeout",
+            Self::Failure => "failure",
+        }
+    }
+
+    /// Whether a run that ended in `outcome` stops the repeat.
+    #[must_use]
+    pub const fn stops(self, outcome: Outcome) -> bool {
+        match self {
+            Self::Never => false,
+            Self::Timeout => matches!(outcome, Outcome::TimedOut { .. }),
+            Self::Failure => !outcome.is_success(),
+        }
+    }
+}
+
+/// What `--detect-flaky` made of a test.
+///
+/// ```
+/// use procguard::Flakiness;
+/// use procguard::stats::RunStats;
+///
+/// let mut stats = RunStats::new();
+/// stats.record(false, 900);
+/// stats.record(true, 850);
+/// assert_eq!(Flakiness::classify(&stats), Some(Flakiness::Flaky));
+/// assert_eq!(Flakiness::Flaky.as_str(), "flaky");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Flakiness {
+    /// The first run passed; nothing was rerun
+    Passed,
+    /// The first run failed and a rerun passed
+    Flaky,
+    /// Every run failed
+    Failing,
+}
+
+impl Flakiness {
+    /// Classify the runs in `stats`, the first one first. `None` before
+    /// any run.
+    #[must_use]
+    pub const fn classify(stats: &RunStats) -> Option<Self> {
+        if stats.runs() == 0 {
+            None
+        } else if stats.first_failure().is_none() {
+            Some(Self::Passed)
+        } else if stats.passed() > 0 {
+            Some(Self::Flaky)
+        } else {
+            Some(Self::Failing)
+        }
+    }
+
+    /// The JSON `classification` string: "passed", "flaky" or "failing".
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Passed => "passed",
+            Self::Flaky => "flaky",
+            Self::Failing => "failing",
+        }
+    }
+}
+
+/// `--flaky-exit`: the exit status of a test `--detect-flaky` found flaky.
+///
+/// ```
+/// use procguard::FlakyExit;
+///
+/// assert_eq!(FlakyExit::parse("pass").unwrap().exit_code(1), 0);
+/// assert_eq!(FlakyExit::parse("fail").unwrap().exit_code(1), 1);
+/// assert_eq!(FlakyExit::parse("3").unwrap().exit_code(1), 3);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum FlakyExit {
+    /// 0, as the rerun that passed would have it
+    #[default]
+    Pass,
+    /// The failed runs' status, as if no rerun had passed
+    Fail,
+    /// This status, to tell flaky apart from both
+    Code(u8),
+}
+
+impl FlakyExit {
+    /// Parse "pass", "fail" (any case) or an exit status from 0 to 255.
+    #[must_use]
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "pass" => Some(Self::Pass),
+            "fail" => Some(Self::Fail),
+            code => code.parse().ok().map(Self::Code),
+        }
+    }
+
+    /// The exit status for a flaky test whose failed runs exited `failed`.
+    #[must_use]
+    pub const fn exit_code(self, failed: u8) -> u8 {
+        match self {
+            Self::Pass => 0,
+            Self::Fail => failed,
+            Self::Code(code) => code,
+        }
+    }
+}
+
+/// When the runs of a repeat are due: slot `k` starts `k * every` after
+/// the first, and no slot starts at or past `budget`.
+///
+/// ```
+/// use core::time::Duration;
+/// use procguard::Schedule;
+///
+/// let mins = |m: u64| Duration::from_secs(m * 60);
+/// let schedule = Schedule::new(mins(5), mins(60)).unwrap();
+/// assert_eq!(schedule.slot_start(2), mins(10));
+/// /* slot 0 ran 12 minutes: slots 1 and 2 are gone, the next is 3 */
+/// assert_eq!(schedule.next_slot(0, mins(12)), Some(3));
+/// assert_eq!(schedule.next_slot(11, mins(56)), None);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    every_ns: u64,
+    budget_ns: u64,
+}
+
+/* Duration to ns, capped (584 years is plenty of schedule) */
+fn as_ns(d: Duration) -> u64 {
+    u64::try_from(d.as_nanos()).unwrap_or(u64::MAX)
+}
+
+impl Schedule {
+    /// A run every `every` for `budget`. `None` if `every` is zero.
+    #[must_use]
+    pub fn new(every: Duration, budget: Duration) -> Option<Self> {
+        let every_ns = as_ns(every);
+        (every_ns > 0).then_some(Self {
+            every_ns,
+            budget_ns: as_ns(budget),
+        })
+    }
+
+    /// The interval between runs.
+    #[must_use]
+    pub const fn every(self) -> Duration {
+        Duration::from_nanos(self.every_ns)
+    }
+
+    /// How long runs keep starting.
+    #[must_use]
+    pub const fn budget(self) -> Duration {
+        Duration::from_nanos(self.budget_ns)
+    }
+
+    /// How far slot `slot` starts after the first.
+    #[must_use]
+    pub const fn slot_start(self, slot: u64) -> Duration {
+        Duration::from_nanos(self.every_ns.saturating_mul(slot))
+    }
+
+    /// The slot the run after the one in `slot` goes in, `elapsed` after
+    /// the first started: the next slot, or the first one not yet begun if
+    /// the run overran. `None` once that would start past the budget.
+    #[must_use]
+    pub fn next_slot(self, slot: u64, elapsed: Duration) -> Option<u64> {
+        let elapsed_ns = as_ns(elapsed);
+        let free = elapsed_ns.div_ceil(self.every_ns);
+        let next = free.max(slot.saturating_add(1));
+        (self.every_ns.saturating_mul(next) < self.budget_ns).then_some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outcome::LimitKind;
+    use crate::runner::TimeoutReason;
+    use crate::signal::Signal;
+
+    const fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    #[test]
+    fn test_stop_on() {
+        assert_eq!(StopOn::parse("Failure"), Some(StopOn::Failure));
+        assert_eq!(StopOn::parse("timeout"), Some(StopOn::Timeout));
+        assert_eq!(StopOn::parse("never"), Some(StopOn::Never));
+        assert_eq!(StopOn::parse("always"), None);
+        for policy in [StopOn::Never, StopOn::Timeout, StopOn::Failure] {
+            assert_eq!(StopOn::parse(policy.as_str()), Some(policy));
+        }
+
+        let timeout = Outcome::TimedOut {
+            reason: TimeoutReason::WallClock,
+            killed: false,
+        };
+        let failed = Outcome::Failed { code: 1 };
+        let crashed = Outcome::Crashed {
+            signal: Signal::SIGSEGV,
+        };
+        let limit = Outcome::LimitExceeded {
+            kind: LimitKind::Memory,
+        };
+        let ok = Outcome::Success { code: 0 };
+
+        assert!(!StopOn::Never.stops(timeout));
+        assert!(StopOn::Timeout.stops(timeout));
+        assert!(!StopOn::Timeout.stops(failed));
+        assert!(!StopOn::Timeout.stops(limit));
+        for outcome in [timeout, failed, crashed, limit] {
+            assert!(StopOn::Failure.stops(outcome));
+        }
+        assert!(!StopOn::Failure.stops(ok));
+    }
+
+    #[test]
+    fn test_flakiness() {
+        let classify = |runs: &[bool]| {
+            let mut stats = RunStats::new();
+            for &passed in runs {
+                stats.record(passed, 1);
+            }
+            Flakiness::classify(&stats)
+        };
+        assert_eq!(classify(&[]), None);
+        assert_eq!(classify(&[true]), Some(Flakiness::Passed));
+        assert_eq!(classify(&[false, true]), Some(Flakiness::Flaky));
+        assert_eq!(classify(&[false, false, true]), Some(Flakiness::Flaky));
+        assert_eq!(classify(&[false, false, false]), Some(Flakiness::Failing));
+
+        assert_eq!(FlakyExit::parse("PASS"), Some(FlakyExit::Pass));
+        assert_eq!(FlakyExit::parse("fail"), Some(FlakyExit::Fail));
+        assert_eq!(FlakyExit::parse("3"), Some(FlakyExit::Code(3)));
+        assert_eq!(FlakyExit::parse("256"), None);
+        assert_eq!(FlakyExit::parse("sometimes"), None);
+        assert_eq!(FlakyExit::Pass.exit_code(2), 0);
+        assert_eq!(FlakyExit::Fail.exit_code(2), 2);
+        assert_eq!(FlakyExit::Code(3).exit_code(2), 3);
+    }
+
+    #[test]
+    fn test_schedule() {
+        assert_eq!(Schedule::new(Duration::ZERO, secs(10)), None);
+
+        let s = Schedule::new(secs(5), secs(20)).unwrap();
+        assert_eq!(s.slot_start(3), secs(15));
+        /* on time: the next slot */
+        assert_eq!(s.next_slot(0, secs(1)), Some(1));
+        assert_eq!(s.next_slot(1, secs(6)), Some(2));
+        /* finishing right on a boundary takes that slot */
+        assert_eq!(s.next_slot(0, secs(5)), Some(1));
+        /* overran slots 1 and 2: 3 is the first not yet begun */
+        assert_eq!(s.next_slot(0, Duration::from_millis(12_500)), Some(3));
+        /* slot 4 would start at 20s, the end of the budget */
+        assert_eq!(s.next_slot(3, secs(16)), None);
+        assert_eq!(s.next_slot(2, secs(30)), None);
+
+        /* a budget shorter than the interval runs once */
+        let once = Schedule::new(secs(60), secs(10)).unwrap();
+        assert_eq!(once.next_slot(0, secs(1)), None);
+    }
+}
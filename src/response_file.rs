@@ -0,0 +1,229 @@
+/*
+ * response_file.rs
+ *
+ * `@file` arguments: `procguard @args.txt` reads the duration, options and
+ * command from args.txt, one argument per line, for command lines longer
+ * than ARG_MAX allows (linker invocations, test shards).
+ *
+ * format, per line:
+ * - leading/trailing spaces and tabs are trimmed, a trailing \r dropped
+ * - blank lines and lines starting with # are skipped
+ * - "..." is one argument with \\ \" \n \t escapes; use it for an empty
+ *   argument, surrounding whitespace, or a leading #
+ * - '...' is one argument, taken literally
+ * - anything else is the argument as written, inner spaces and quotes
+ *   included: `--on-timeout=kill -QUIT %p` is one argument
+ *
+ * TIMEOUT_OPTS, the options for build systems that can only set the
+ * environment, is one line split the way sh splits words (split_words):
+ * - spaces, tabs and newlines separate words
+ * - '...' is literal; "..." keeps spaces, with \\ \" \$ \` escapes
+ * - a backslash outside quotes takes the next byte as is
+ * - no expansion of any kind: $HOME, ~ and * are just bytes
+ *
+ * which arguments get expanded, reading the file and enforcing the caps
+ * below are parse_args's business; this file only splits.
+ */
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Largest response file we read, and the most all the files of one
+/// command line may add up to.
+pub const MAX_BYTES: usize = 16 * 1024 * 1024;
+
+/// How many `@file` expansions one command line may go through.
+pub const MAX_FILES: usize = 16;
+
+/// Split response file contents into arguments. Err names the line.
+pub fn split(contents: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let mut args = Vec::new();
+    for (n, line) in contents.split(|&b| b == b'\n').enumerate() {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let line = line.trim_ascii_start();
+        let line = trim_blank_end(line);
+        match line.first() {
+            None | Some(b'#') => {}
+            Some(b'"') => args.push(unquote(line).ok_or_else(|| bad_quote(n))?),
+            Some(b'\'') => match line.get(1..).and_then(|l| l.strip_suffix(b"'")) {
+                Some(inner) => args.push(inner.to_vec()),
+                None => return Err(bad_quote(n)),
+            },
+            Some(_) => args.push(line.to_vec()),
+        }
+    }
+    Ok(args)
+}
+
+/// Split a line into words, as sh would without expanding anything.
+/// Err says what's wrong (an unterminated quote, a trailing backslash).
+pub fn split_words(line: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let mut words = Vec::new();
+    /* None between words; Some even if empty, for "" */
+    let mut word: Option<Vec<u8>> = None;
+    let mut bytes = line.iter();
+    while let Some(&b) = bytes.next() {
+        match b {
+            b' ' | b'\t' | b'\n' => words.extend(word.take()),
+            b'\'' => {
+                let w = word.get_or_insert_with(Vec::new);
+                loop {
+                    match bytes.next() {
+                        Some(b'\'') => break,
+                        Some(&c) => w.push(c),
+                        None => return Err("unterminated ' quote".into()),
+                    }
+                }
+            }
+            b'"' => {
+                let w = word.get_or_insert_with(Vec::new);
+                loop {
+                    match bytes.next() {
+                        Some(b'"') => break,
+                        /* sh: backslash escapes only these inside "..." */
+                        Some(b'\\') => match bytes.next() {
+                            Some(&c @ (b'"' | b'\\' | b'$' | b'`')) => w.push(c),
+                            Some(b'\n') => {}
+                            Some(&c) => w.extend_from_slice(&[b'\\', c]),
+                            None => return Err("unterminated \" quote".into()),
+                        },
+                        Some(&c) => w.push(c),
+                        None => return Err("unterminated \" quote".into()),
+                    }
+                }
+            }
+            b'\\' => match bytes.next() {
+                /* line continuation */
+                Some(b'\n') => {}
+                Some(&c) => word.get_or_insert_with(Vec::new).push(c),
+                None => return Err("trailing backslash".into()),
+            },
+            _ => word.get_or_insert_with(Vec::new).push(b),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/* spaces and tabs only - a form feed or \v at the end is kept */
+fn trim_blank_end(line: &[u8]) -> &[u8] {
+    let n = line
+        .iter()
+        .rev()
+        .take_while(|&&b| b == b' ' || b == b'\t')
+        .count();
+    &line[..line.len() - n]
+}
+
+fn bad_quote(n: usize) -> String {
+    format!("line {}: unterminated quote", n + 1)
+}
+
+/* "..." with escapes. None if the closing quote is missing or isn't last */
+fn unquote(line: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(line.len());
+    let mut bytes = line.get(1..)?.iter();
+    while let Some(&b) = bytes.next() {
+        match b {
+            b'"' => return bytes.as_slice().is_empty().then_some(out),
+            b'\\' => out.push(match *bytes.next()? {
+                b'n' => b'\n',
+                b't' => b'\t',
+                /* \\ and \", and any other escaped byte as itself */
+                other => other,
+            }),
+            _ => out.push(b),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn words(s: &str) -> Vec<String> {
+        split(s.as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(|w| String::from_utf8(w).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_split_lines() {
+        assert_eq!(
+            words("--json\n  -s KILL\n10m\r\n\n# build\nmake\n-j8"),
+            vec!["--json", "-s KILL", "10m", "make", "-j8"]
+        );
+        assert_eq!(words(""), Vec::<String>::new());
+        assert_eq!(words("a\n"), vec!["a"]);
+        /* quotes inside an argument are just bytes */
+        assert_eq!(
+            words("--on-timeout=echo \"late\" %p"),
+            vec!["--on-timeout=echo \"late\" %p"]
+        );
+    }
+
+    #[test]
+    fn test_split_quotes() {
+        assert_eq!(
+            words("\"\"\n\"  padded \"\n\"a\\\"b\\\\c\\nd\"\n'#not a comment'\n'it\\s'"),
+            vec!["", "  padded ", "a\"b\\c\nd", "#not a comment", "it\\s"]
+        );
+        assert_eq!(
+            split(b"ok\n\"open").unwrap_err(),
+            "line 2: unterminated quote"
+        );
+        assert!(split(b"\"a\" b").is_err());
+        assert!(split(b"'").is_err());
+        assert!(split(b"\"trailing\\").is_err());
+    }
+
+    fn sh_words(s: &str) -> Vec<String> {
+        split_words(s.as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(|w| String::from_utf8(w).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            sh_words("  -k 5s\t--mem-limit=1G\n--json "),
+            vec!["-k", "5s", "--mem-limit=1G", "--json"]
+        );
+        assert_eq!(sh_words(""), Vec::<String>::new());
+        assert_eq!(sh_words(" \t "), Vec::<String>::new());
+        assert_eq!(
+            sh_words(r#"--on-timeout='kill -QUIT %p' --tag "ci job" a\ b"#),
+            vec!["--on-timeout=kill -QUIT %p", "--tag", "ci job", "a b"]
+        );
+        /* quotes join with what's next to them; "" is a word */
+        assert_eq!(sh_words(r#"x'y'"z" "" ''"#), vec!["xyz", "", ""]);
+        assert_eq!(
+            sh_words(r#""a\"b\\c\$d\n" 'e\f'"#),
+            vec![r#"a"b\c$d\n"#, r"e\f"]
+        );
+        /* nothing expanded */
+        assert_eq!(sh_words("$HOME ~ *"), vec!["$HOME", "~", "*"]);
+        assert_eq!(sh_words("a\\\nb"), vec!["ab"]);
+
+        assert_eq!(split_words(b"'open").unwrap_err(), "unterminated ' quote");
+        assert_eq!(
+            split_words(b"\"open\\\"").unwrap_err(),
+            "unterminated \" quote"
+        );
+        assert_eq!(split_words(b"end\\").unwrap_err(), "trailing backslash");
+        assert_eq!(split_words(b"caf\xe9").unwrap(), vec![b"caf\xe9".to_vec()]);
+    }
+
+    #[test]
+    fn test_split_keeps_bytes() {
+        /* not UTF-8: handed to the command as is */
+        assert_eq!(split(b"caf\xe9").unwrap(), vec![b"caf\xe9".to_vec()]);
+    }
+}
@@ -10,29 +10,73 @@ use alloc::string::ToString;
 use core::num::NonZeroU32;
 use core::time::Duration;
 
+use crate::errno::errno;
 use crate::error::{Result, TimeoutError};
 
+/// Kernel-enforced limits set in the child before exec.
+///
+/// ```
+/// use procguard::{ResourceLimits, parse_cpu_time_pair, parse_mem_limit};
+///
+/// let (soft, hard) = parse_cpu_time_pair("60s:70s").unwrap();
+/// let limits = ResourceLimits {
+///     mem_bytes: Some(parse_mem_limit("512M").unwrap()),
+///     cpu_time: Some(soft),
+///     cpu_time_hard: hard,
+/// };
+/// assert_eq!(limits.cpu_secs(), Some((60, 70)));
+/// assert!(ResourceLimits::default().is_empty());
+/// ```
 #[cfg_attr(test, derive(Debug))]
 #[derive(Clone, Copy, Default)]
 pub struct ResourceLimits {
-    pub mem_bytes: Option<u64>,     /* RLIMIT_AS */
-    pub cpu_time: Option<Duration>, /* RLIMIT_CPU (seconds) */
+    /// RLIMIT_AS, in bytes.
+    pub mem_bytes: Option<u64>,
+    /// RLIMIT_CPU (seconds): SIGXCPU here.
+    pub cpu_time: Option<Duration>,
+    /// RLIMIT_CPU hard limit: SIGKILL here. None: same as `cpu_time`.
+    pub cpu_time_hard: Option<Duration>,
 }
 
 impl ResourceLimits {
+    /// Whether no limit is set.
     #[inline]
     pub fn is_empty(&self) -> bool {
         self.mem_bytes.is_none() && self.cpu_time.is_none()
     }
+
+    /// The CPU times, in whole seconds as RLIMIT_CPU has them, at which
+    /// the kernel sends SIGXCPU (soft) and SIGKILL (hard).
+    #[must_use]
+    pub fn cpu_secs(&self) -> Option<(u64, u64)> {
+        let soft = self.cpu_time?.as_secs();
+        let hard = self.cpu_time_hard.map_or(soft, |hard| hard.as_secs());
+        Some((soft, hard.max(soft)))
+    }
 }
 
-/* parse memory strings like 1G, 512M, 1024, 64K. binary units. */
+/// Parse a memory size like `1G`, `512MiB`, `500MB`, `1024` or `25%`, in
+/// bytes. Every unit is binary: 1K, 1KB and 1KiB are all 1024 bytes, as
+/// they always were here. A percentage is of physical memory.
+///
+/// ```
+/// use procguard::parse_mem_limit;
+///
+/// assert_eq!(parse_mem_limit("1K").unwrap(), 1024);
+/// assert_eq!(parse_mem_limit("1KB").unwrap(), 1024);
+/// assert_eq!(parse_mem_limit("512MiB").unwrap(), 512 * 1024 * 1024);
+/// assert!(parse_mem_limit("lots").is_err());
+/// ```
 pub fn parse_mem_limit(input: &str) -> Result<u64> {
     let s = input.trim();
     if s.is_empty() {
         return Err(TimeoutError::InvalidMemoryLimit("empty".to_string()));
     }
 
+    if let Some(pct) = s.strip_suffix('%') {
+        return parse_mem_percent(pct.trim_end(), s);
+    }
+
     let (num, suffix) = split_number_suffix(s);
 
     /* require at least one digit - reject bare suffixes like "G" */
@@ -45,54 +89,167 @@ pub fn parse_mem_limit(input: &str) -> Result<u64> {
     let value = parse_u64(num)
         .map_err(|_| TimeoutError::InvalidMemoryLimit(format!("invalid memory value: '{s}'")))?;
 
-    let mult: u64 = match suffix.to_ascii_lowercase().as_str() {
-        "" | "b" => 1,
-        "k" | "kb" => 1024,
-        "m" | "mb" => 1024 * 1024,
-        "g" | "gb" => 1024 * 1024 * 1024,
-        "t" | "tb" => 1024_u64
-            .saturating_mul(1024)
-            .saturating_mul(1024)
-            .saturating_mul(1024),
+    let power: u32 = match suffix.to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 1,
+        "m" | "mb" | "mib" => 2,
+        "g" | "gb" | "gib" => 3,
+        "t" | "tb" | "tib" => 4,
         _ => {
             return Err(TimeoutError::InvalidMemoryLimit(format!(
-                "invalid memory suffix in '{s}'"
+                "invalid memory suffix in '{s}' (use K, M, G or T - KB/KiB, MB/MiB, \
+                 GB/GiB and TB/TiB mean the same - or a %)"
             )));
         }
     };
 
     value
-        .checked_mul(mult)
+        .checked_mul(1024_u64.pow(power))
         .ok_or_else(|| TimeoutError::InvalidMemoryLimit(format!("memory limit overflow: '{s}'")))
 }
 
-/* parse cpu time like 50s, 2m, 1h using Duration parser to reuse logic. */
+/* "25%": that share of physical memory, rounded down to a byte */
+fn parse_mem_percent(digits: &str, s: &str) -> Result<u64> {
+    let pct = match parse_u64(digits) {
+        Ok(pct) if !digits.is_empty() => pct,
+        _ => {
+            return Err(TimeoutError::InvalidMemoryLimit(format!(
+                "invalid memory percentage: '{s}' (a whole number, like 25%)"
+            )));
+        }
+    };
+    if !(1..=100).contains(&pct) {
+        return Err(TimeoutError::InvalidMemoryLimit(format!(
+            "memory percentage must be 1 to 100: '{s}'"
+        )));
+    }
+    let total = physical_memory().ok_or_else(|| {
+        TimeoutError::InvalidMemoryLimit(format!("can't read physical memory size for '{s}'"))
+    })?;
+    /* no overflow: total / 100 * 100 <= total */
+    Ok(total / 100 * pct + total % 100 * pct / 100)
+}
+
+/* physical memory in bytes */
+#[cfg(target_os = "macos")]
+fn physical_memory() -> Option<u64> {
+    let mut bytes: u64 = 0;
+    let mut size = core::mem::size_of::<u64>();
+    // SAFETY: sysctlbyname with valid name and properly sized buffer
+    let ret = unsafe {
+        libc::sysctlbyname(
+            c"hw.memsize".as_ptr(),
+            (&raw mut bytes).cast(),
+            &raw mut size,
+            core::ptr::null_mut(),
+            0,
+        )
+    };
+    (ret == 0 && bytes > 0).then_some(bytes)
+}
+
+/* linux: pages times page size, via sysconf */
+#[cfg(target_os = "linux")]
+fn physical_memory() -> Option<u64> {
+    // SAFETY: sysconf takes no pointers
+    let pages = unsafe { libc::sysconf(libc::_SC_PHYS_PAGES) };
+    // SAFETY: as above
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    let bytes = u64::try_from(pages)
+        .ok()?
+        .checked_mul(u64::try_from(page_size).ok()?)?;
+    (bytes > 0).then_some(bytes)
+}
+
+/// Parse a CPU time like `50s`, `2m`, `1h`: the [`parse_duration`]
+/// grammar.
+///
+/// [`parse_duration`]: crate::duration::parse_duration
+///
+/// ```
+/// use core::time::Duration;
+/// use procguard::parse_cpu_time;
+///
+/// assert_eq!(parse_cpu_time("2m").unwrap(), Duration::from_secs(120));
+/// ```
 pub fn parse_cpu_time(input: &str) -> Result<Duration> {
     let dur = crate::duration::parse_duration(input)?;
     Ok(dur)
 }
 
-/* parse cpu percent. allows >100 for multi-core (e.g., 400 = 4 cores max).
- * value is unbounded - will naturally max at machine's available cores. */
+/// Whether `used_ms` of CPU time, as wait4()'s rusage has it, reached a
+/// RLIMIT_CPU of `secs`. rusage comes up a little short of the time the
+/// kernel held against the limit (the user/system split is scaled, each
+/// part truncated), so within a twentieth of it counts.
+#[must_use]
+pub fn cpu_limit_reached(used_ms: u64, secs: u64) -> bool {
+    let limit_ms = secs.saturating_mul(1000);
+    used_ms >= limit_ms - limit_ms / 20
+}
+
+/// Parse a soft:hard pair like `60s:70s`, or a lone time for both. The
+/// soft limit is SIGXCPU, which the command may handle; the hard one is
+/// SIGKILL, so it can't come first.
+///
+/// ```
+/// use core::time::Duration;
+/// use procguard::parse_cpu_time_pair;
+///
+/// let secs = Duration::from_secs;
+/// assert_eq!(parse_cpu_time_pair("60s").unwrap(), (secs(60), None));
+/// assert_eq!(parse_cpu_time_pair("60s:70s").unwrap(), (secs(60), Some(secs(70))));
+/// assert!(parse_cpu_time_pair("70s:60s").is_err());
+/// ```
+pub fn parse_cpu_time_pair(input: &str) -> Result<(Duration, Option<Duration>)> {
+    let Some((soft, hard)) = input.split_once(':') else {
+        return Ok((parse_cpu_time(input)?, None));
+    };
+    let (soft, hard) = (parse_cpu_time(soft)?, parse_cpu_time(hard)?);
+    if hard.as_secs() < soft.as_secs() {
+        return Err(TimeoutError::InvalidCpuTime(format!(
+            "hard limit below the soft one in '{input}'"
+        )));
+    }
+    Ok((soft, Some(hard)))
+}
+
+/// Parse a `--cpu-percent`. Over 100 is allowed for multi-core (400 = 4
+/// cores at most); there's no upper bound, the machine's cores are one.
+///
+/// ```
+/// use procguard::parse_cpu_percent;
+///
+/// assert_eq!(parse_cpu_percent("400").unwrap().get(), 400);
+/// assert!(parse_cpu_percent("0").is_err());
+/// ```
 pub fn parse_cpu_percent(input: &str) -> Result<NonZeroU32> {
     let val: u32 = input
         .trim()
         .parse()
         .map_err(|_| TimeoutError::InvalidCpuPercent(format!("invalid cpu percent: '{input}'")))?;
 
-    if val == 0 {
-        return Err(TimeoutError::InvalidCpuPercent(format!(
-            "cpu percent must be > 0: {val}"
-        )));
-    }
-    Ok(NonZeroU32::new(val).unwrap())
+    NonZeroU32::new(val)
+        .ok_or_else(|| TimeoutError::InvalidCpuPercent(format!("cpu percent must be > 0: {val}")))
 }
 
+/// Set `limits` on the calling process. Meant for the child between fork
+/// and exec; RLIMIT_AS is best-effort (macOS refuses it).
 pub fn apply_limits(limits: &ResourceLimits) -> Result<()> {
+    /* without the rlimit feature RunConfig refuses --cpu-time, and
+     * RLIMIT_AS is best-effort anyway */
+    if !cfg!(feature = "rlimit") {
+        return Ok(());
+    }
+
     /* apply RLIMIT_AS if set
      * NOTE: macOS does NOT enforce RLIMIT_AS (returns EINVAL).
-     * We try anyway for potential future support or compatibility. */
-    if let Some(bytes) = limits.mem_bytes {
+     * We try anyway for potential future support or compatibility.
+     * linux does enforce it - on address space, which a process reserves
+     * far past its footprint, so small limits kill it in the loader.
+     * there --mem-limit is only the footprint sampled from procfs. */
+    if let Some(bytes) = limits.mem_bytes
+        && cfg!(target_os = "macos")
+    {
         let rlim = libc::rlimit {
             rlim_cur: bytes,
             rlim_max: bytes,
@@ -110,12 +267,11 @@ pub fn apply_limits(limits: &ResourceLimits) -> Result<()> {
         }
     }
 
-    if let Some(cpu) = limits.cpu_time {
+    if let Some((soft, hard)) = limits.cpu_secs() {
         /* RLIMIT_CPU uses seconds granularity */
-        let secs = cpu.as_secs();
         let rlim = libc::rlimit {
-            rlim_cur: secs,
-            rlim_max: secs,
+            rlim_cur: soft,
+            rlim_max: hard,
         };
         /* RLIMIT_CPU works on macOS */
         // SAFETY: setrlimit with valid rlimit struct
@@ -161,18 +317,6 @@ fn parse_u64(s: &str) -> core::result::Result<u64, ()> {
     Ok(result)
 }
 
-#[inline]
-fn errno() -> i32 {
-    unsafe extern "C" {
-        fn __error() -> *mut i32;
-    }
-    // SAFETY: __error always returns valid pointer on macOS.
-    #[allow(clippy::multiple_unsafe_ops_per_block)]
-    unsafe {
-        *__error()
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -211,6 +355,39 @@ mod tests {
         assert_eq!(parse_cpu_percent("\t100\n").unwrap().get(), 100);
     }
 
+    #[test]
+    fn test_parse_cpu_time_pair() {
+        let secs = Duration::from_secs;
+        assert_eq!(parse_cpu_time_pair("60s").unwrap(), (secs(60), None));
+        assert_eq!(
+            parse_cpu_time_pair("60s:70s").unwrap(),
+            (secs(60), Some(secs(70)))
+        );
+        assert_eq!(
+            parse_cpu_time_pair("1m:1m").unwrap(),
+            (secs(60), Some(secs(60)))
+        );
+        for bad in ["70s:60s", "60s:", ":70s", "60s:70s:80s", "x:70s"] {
+            assert!(parse_cpu_time_pair(bad).is_err(), "{bad}");
+        }
+
+        let limits = ResourceLimits {
+            cpu_time: Some(Duration::from_millis(1500)),
+            ..ResourceLimits::default()
+        };
+        assert_eq!(limits.cpu_secs(), Some((1, 1)));
+        let limits = ResourceLimits {
+            cpu_time_hard: Some(secs(3)),
+            ..limits
+        };
+        assert_eq!(limits.cpu_secs(), Some((1, 3)));
+
+        assert!(cpu_limit_reached(1000, 1));
+        assert!(cpu_limit_reached(983, 1));
+        assert!(!cpu_limit_reached(900, 1));
+        assert!(cpu_limit_reached(0, 0));
+    }
+
     #[test]
     fn test_parse_mem_limit_basic() {
         assert_eq!(parse_mem_limit("1024").unwrap(), 1024);
@@ -224,10 +401,42 @@ mod tests {
         assert_eq!(parse_mem_limit("1k").unwrap(), 1024);
         assert_eq!(parse_mem_limit("1m").unwrap(), 1024 * 1024);
         assert_eq!(parse_mem_limit("1g").unwrap(), 1024 * 1024 * 1024);
-        assert_eq!(parse_mem_limit("512MB").unwrap(), 512 * 1024 * 1024);
+        assert_eq!(parse_mem_limit("512MiB").unwrap(), 512 * 1024 * 1024);
+        assert_eq!(parse_mem_limit("2gib").unwrap(), 2 * 1024 * 1024 * 1024);
         assert_eq!(parse_mem_limit("2gb").unwrap(), 2 * 1024 * 1024 * 1024);
     }
 
+    #[test]
+    fn test_parse_mem_limit_kib_same_as_kb() {
+        assert_eq!(parse_mem_limit("1b").unwrap(), 1);
+        /* KB stays binary, as it was before KiB was accepted */
+        assert_eq!(parse_mem_limit("64KiB").unwrap(), 65_536);
+        assert_eq!(parse_mem_limit("64KB").unwrap(), 65_536);
+        assert_eq!(parse_mem_limit("1MiB").unwrap(), 1_048_576);
+        assert_eq!(parse_mem_limit("1MB").unwrap(), 1_048_576);
+        assert_eq!(parse_mem_limit("1TiB").unwrap(), 1 << 40);
+        assert_eq!(parse_mem_limit("1TB").unwrap(), 1 << 40);
+        assert!(parse_mem_limit("16777216TiB").is_err()); /* 2^64 */
+        let err = parse_mem_limit("1Mi").unwrap_err().to_string();
+        assert!(err.contains("KiB"), "{err}");
+    }
+
+    #[test]
+    fn test_parse_mem_limit_percent() {
+        let total = physical_memory().unwrap();
+        assert_eq!(parse_mem_limit("100%").unwrap(), total);
+        let quarter = parse_mem_limit("25%").unwrap();
+        assert!(quarter <= total / 4 && quarter + 1 >= total / 4);
+        assert_eq!(
+            parse_mem_limit(" 50 % ").unwrap(),
+            parse_mem_limit("50%").unwrap()
+        );
+
+        for bad in ["%", "0%", "101%", "12.5%", "-5%", "1G%"] {
+            assert!(parse_mem_limit(bad).is_err(), "{bad}");
+        }
+    }
+
     #[test]
     fn test_parse_mem_limit_zero() {
         /* zero is technically valid (no limit), but useless */
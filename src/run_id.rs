@@ -0,0 +1,138 @@
+/*
+ * run_id.rs
+ *
+ * --run-id ID: one string in everything a run reports, so the logs of
+ * parallel shards can be told apart and joined up again. every JSON
+ * object carries it as "run_id" - the result on stdout or --json-fd, the
+ * --result-file, --print-pid's "started" lines - right after
+ * schema_version, which is why they all start with begin_result().
+ *
+ * without --run-id one is made up from the clock and our pid, for the
+ * JSON only: a stderr line (heartbeats, the --explain timeline) carries
+ * an id only when one was asked for, since a made-up one means nothing
+ * to whoever reads it there.
+ *
+ * the id is the run's, not the process's: main makes one and keeps it in
+ * the run's EventLog, which everything that writes JSON or a heartbeat
+ * already has. a library caller that wants one does the same.
+ */
+
+use alloc::format;
+use alloc::string::String;
+use core::fmt;
+
+use crate::json::{JsonWriter, SCHEMA_VERSION};
+
+/// Longest `--run-id`, in bytes.
+pub const MAX_LEN: usize = 128;
+
+/// A run's id: the `--run-id` given, or one made up for the JSON.
+///
+/// ```
+/// use procguard::run_id::RunId;
+///
+/// let given = RunId::new(Some("shard-3".into()));
+/// assert_eq!(given.as_str(), "shard-3");
+/// assert_eq!(given.given(), Some("shard-3"));
+///
+/// let made_up = RunId::new(None);
+/// assert_eq!(made_up.as_str().len(), 36);
+/// assert_eq!(made_up.given(), None);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunId {
+    id: String,
+    given: bool,
+}
+
+impl RunId {
+    /// Use `given` as the id, or make one up.
+    #[must_use]
+    pub fn new(given: Option<String>) -> Self {
+        match given {
+            Some(id) => Self { id, given: true },
+            None => {
+                // SAFETY: getpid has no preconditions
+                let pid = unsafe { libc::getpid() }.unsigned_abs();
+                let clock_ns = crate::runner::wall_now_ns().unwrap_or(0);
+                Self {
+                    id: format_id(clock_ns, pid, crate::events::realtime_ms()),
+                    given: false,
+                }
+            }
+        }
+    }
+
+    /// The id, given or made up.
+    #[inline]
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.id
+    }
+
+    /// The id if `--run-id` gave it, for stderr lines.
+    #[inline]
+    #[must_use]
+    pub fn given(&self) -> Option<&str> {
+        self.given.then_some(self.id.as_str())
+    }
+}
+
+/// Whether `id` can be a `--run-id`: 1 to [`MAX_LEN`] bytes, no control
+/// characters - it ends up in single-line logs.
+#[must_use]
+pub fn is_valid(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_LEN && !id.chars().any(char::is_control)
+}
+
+/// A made-up id: UUID-shaped hex of a monotonic clock reading, our pid
+/// and the realtime clock, so two runs on one machine (or two machines
+/// started the same millisecond) don't share one.
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn format_id(clock_ns: u64, pid: u32, epoch_ms: u64) -> String {
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:04x}{:08x}",
+        (clock_ns >> 32) as u32,
+        (clock_ns >> 16) as u16,
+        clock_ns as u16,
+        (pid >> 16) as u16,
+        pid as u16,
+        epoch_ms as u32
+    )
+}
+
+/// Open a JSON object the way every one we write starts:
+/// `{"schema_version":N,"run_id":"..."`, without `run_id` for a run that
+/// has none.
+pub fn begin_result<W: fmt::Write>(w: &mut JsonWriter<W>, run_id: Option<&RunId>) {
+    w.begin_object();
+    w.field_u64("schema_version", SCHEMA_VERSION);
+    if let Some(id) = run_id {
+        w.field_str("run_id", id.as_str());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_id() {
+        assert_eq!(
+            format_id(0x0123_4567_89ab_cdef, 0x0001_0f2c, 1_700_000_000_123),
+            "01234567-89ab-cdef-0001-0f2ccfe5687b"
+        );
+        assert_eq!(format_id(0, 0, 0), "00000000-0000-0000-0000-000000000000");
+    }
+
+    #[test]
+    fn test_is_valid() {
+        assert!(is_valid("shard-3/7"));
+        assert!(is_valid("ci run 42"));
+        assert!(!is_valid(""));
+        assert!(!is_valid("a\nb"));
+        assert!(!is_valid(&"x".repeat(MAX_LEN + 1)));
+        assert!(is_valid(&"x".repeat(MAX_LEN)));
+    }
+}
@@ -0,0 +1,298 @@
+/*
+ * clock.rs
+ *
+ * Timing APIs - two modes based on --confine flag:
+ *
+ * Wall mode (default): mach_continuous_time()
+ *   - Continues counting during system sleep
+ *   - A 1-hour timeout fires when you open the lid after 7 hours of sleep
+ *   - This is what most users expect from a timeout
+ *
+ * Active mode: clock_gettime_nsec_np(CLOCK_MONOTONIC_RAW)
+ *   - Only counts awake/active time, pauses during sleep
+ *   - ~28% faster (no timebase conversion needed)
+ *   - Useful for benchmarks where idle time shouldn't count
+ *
+ * Hybrid mode: wall clock for the deadline, active clock on the side
+ *   - Fires once the wall deadline passed AND --active-min of awake time did
+ *   - A laptop asleep all night can't time out a job that barely got to run
+ *
+ * Empirically tested: CLOCK_MONOTONIC_RAW does NOT advance during pmset sleepnow.
+ * See tests/clock_api_comparison.rs for benchmarks and verification.
+ */
+
+use core::time::Duration;
+
+use crate::args::{Confine, SleepPolicy};
+use crate::error::{Result, TimeoutError};
+use crate::sync::AtomicOnce;
+use crate::time_math::remaining_ns;
+
+#[cfg(target_os = "macos")]
+#[repr(C)]
+struct MachTimebaseInfo {
+    numer: u32,
+    denom: u32,
+}
+
+#[cfg(target_os = "macos")]
+unsafe extern "C" {
+    fn mach_continuous_time() -> u64;
+    fn mach_timebase_info(info: *mut MachTimebaseInfo) -> i32;
+    fn clock_gettime_nsec_np(clock_id: libc::clockid_t) -> u64;
+}
+
+/* linux: CLOCK_BOOTTIME and clock_gettime behind the same names */
+#[cfg(target_os = "linux")]
+use crate::linux::{
+    MachTimebaseInfo, clock_gettime_nsec_np, mach_continuous_time, mach_timebase_info,
+};
+
+/* same id on linux */
+const CLOCK_MONOTONIC_RAW: libc::clockid_t = 4;
+#[cfg(target_os = "macos")]
+const CLOCK_UPTIME_RAW: libc::clockid_t = 8;
+/* stops during suspend, like UPTIME_RAW */
+#[cfg(target_os = "linux")]
+const CLOCK_UPTIME_RAW: libc::clockid_t = libc::CLOCK_MONOTONIC;
+
+/* get timebase ratio, cached forever. returns Err if denom is zero (invalid FFI data). */
+fn get_timebase_info() -> Result<(u64, u64)> {
+    static TIMEBASE: AtomicOnce<(u64, u64)> = AtomicOnce::new();
+
+    /* check if already cached */
+    if let Some(&cached) = TIMEBASE.get() {
+        return Ok(cached);
+    }
+
+    /* fetch from FFI */
+    let mut info = MachTimebaseInfo { numer: 0, denom: 0 };
+    // SAFETY: info is a valid MachTimebaseInfo struct with correct layout (#[repr(C)]).
+    // mach_timebase_info always succeeds on macOS and fills in numer/denom.
+    unsafe {
+        mach_timebase_info(&raw mut info);
+    }
+
+    /* validate FFI data - denom == 0 would cause division by zero */
+    if info.denom == 0 {
+        return Err(TimeoutError::TimebaseError);
+    }
+
+    let result = (u64::from(info.numer), u64::from(info.denom));
+    /* cache only on success */
+    let _ = TIMEBASE.set(result);
+    Ok(result)
+}
+
+/* Wall time in nanoseconds - includes system sleep (mach_continuous_time).
+ *
+ * # Errors
+ * Returns `TimebaseError` if mach_timebase_info returned invalid data (zero denominator).
+ */
+#[inline]
+pub(crate) fn wall_now_ns() -> Result<u64> {
+    let (numer, denom) = get_timebase_info()?;
+    // SAFETY: mach_continuous_time() has no preconditions, always returns valid u64.
+    let abs_time = unsafe { mach_continuous_time() };
+
+    /* Apple Silicon: numer == denom == 1, fast path avoids division */
+    if numer == denom {
+        return Ok(abs_time);
+    }
+
+    /* Intel: need conversion. u128 intermediate avoids overflow.
+     * Use checked_div as defense-in-depth (denom already validated above). */
+    let intermediate = u128::from(abs_time) * u128::from(numer);
+    let result = intermediate
+        .checked_div(u128::from(denom))
+        .ok_or(TimeoutError::TimebaseError)?;
+
+    #[allow(clippy::cast_possible_truncation)]
+    Ok(result as u64)
+}
+
+/* Active time in nanoseconds - excludes system sleep (CLOCK_MONOTONIC_RAW) */
+#[inline]
+pub(super) fn active_now_ns() -> u64 {
+    // SAFETY: clock_gettime_nsec_np with valid clock_id always succeeds on macOS
+    unsafe { clock_gettime_nsec_np(CLOCK_MONOTONIC_RAW) }
+}
+
+/* Scheduler time in nanoseconds - mach_absolute_time, stops during sleep
+ * just like kqueue timers do. Used to measure how late our wakeups are
+ * without counting a sleeping lid as lag. */
+#[inline]
+pub(super) fn sched_now_ns() -> u64 {
+    // SAFETY: clock_gettime_nsec_np with valid clock_id always succeeds on macOS
+    unsafe { clock_gettime_nsec_np(CLOCK_UPTIME_RAW) }
+}
+
+/* a single wakeup this late gets a warning */
+pub(super) const STARVED_LAG_NS: u64 = 250_000_000;
+
+/* less than this between two wakeups is clock noise, not a sleep */
+const MIN_SLEEP_NS: u64 = 1_000_000_000;
+
+/* with --on-sleep pause/fail, wake at least this often (in awake time) so
+ * a sleep is acted on soon after the machine comes back */
+const SLEEP_PROBE_NS: u64 = 1_000_000_000;
+
+/*
+ * system sleep, seen as the wall clock (mach_continuous_time) pulling
+ * ahead of the scheduler clock (mach_absolute_time), which stops while
+ * the machine is asleep. no IOKit power notifications: those need a
+ * CFRunLoop, and comparing the two clocks on every wakeup is exact anyway.
+ */
+pub(super) struct SleepWatch {
+    wall_ns: u64,
+    sched_ns: u64,
+    /* sleep already reported */
+    seen_ns: u64,
+}
+
+impl SleepWatch {
+    pub(super) fn new() -> Self {
+        Self::at(event_now(Confine::Wall), sched_now_ns())
+    }
+
+    const fn at(wall_ns: u64, sched_ns: u64) -> Self {
+        Self {
+            wall_ns,
+            sched_ns,
+            seen_ns: 0,
+        }
+    }
+
+    /* sleep since the last report, 0 for none */
+    pub(super) fn check(&mut self) -> u64 {
+        self.advance(event_now(Confine::Wall), sched_now_ns())
+    }
+
+    fn advance(&mut self, wall_ns: u64, sched_ns: u64) -> u64 {
+        let wall = wall_ns.saturating_sub(self.wall_ns);
+        let sched = sched_ns.saturating_sub(self.sched_ns);
+        let slept = wall.saturating_sub(sched);
+        if slept < self.seen_ns.saturating_add(MIN_SLEEP_NS) {
+            return 0;
+        }
+        let new = slept - self.seen_ns;
+        self.seen_ns = slept;
+        new
+    }
+}
+
+/* longest the loop may go without looking for a sleep it has to act on */
+pub(super) const fn sleep_probe_ns(policy: SleepPolicy) -> u64 {
+    match policy {
+        SleepPolicy::Continue => u64::MAX,
+        _ => SLEEP_PROBE_NS,
+    }
+}
+
+/* Get current time based on confine mode.
+ *
+ * # Errors
+ * Returns `TimebaseError` if wall mode and mach_timebase_info returned invalid data.
+ */
+#[inline]
+pub(super) fn precise_now_ns(confine: Confine) -> Result<u64> {
+    match confine {
+        Confine::Wall | Confine::Hybrid => wall_now_ns(),
+        Confine::Active => Ok(active_now_ns()),
+    }
+}
+
+/* --confine hybrid: awake time still owed before the timeout may fire */
+#[inline]
+pub(super) fn active_left_ns(active_deadline_ns: Option<u64>) -> u64 {
+    active_deadline_ns.map_or(0, |deadline| remaining_ns(active_now_ns(), deadline))
+}
+
+/* timestamp for the event log - recording is best-effort, never fails the run */
+#[inline]
+pub(crate) fn event_now(confine: Confine) -> u64 {
+    precise_now_ns(confine).unwrap_or(0)
+}
+
+/* signal numbers, exit codes: never negative in practice, clamp if they are */
+#[inline]
+pub(super) fn event_value(v: i32) -> u64 {
+    u64::try_from(v).unwrap_or(0)
+}
+
+/* max ns that fits in isize (~292 years on 64-bit) */
+pub(super) const MAX_TIMER_NS: u64 = isize::MAX as u64;
+
+/* duration to ns, clamped for kqueue */
+#[inline]
+pub(super) fn duration_to_ns(d: Duration) -> u64 {
+    d.as_secs()
+        .saturating_mul(1_000_000_000)
+        .saturating_add(u64::from(d.subsec_nanos()))
+        .min(MAX_TIMER_NS)
+}
+
+/* duration to ms as u64 (avoids u128 from as_millis()) */
+#[inline]
+pub(super) fn duration_ms(d: Duration) -> u64 {
+    d.as_secs()
+        .saturating_mul(1000)
+        .saturating_add(u64::from(d.subsec_millis()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sleep_watch() {
+        const S: u64 = 1_000_000_000;
+        let mut watch = SleepWatch::at(100 * S, 50 * S);
+        /* awake: both clocks move together */
+        assert_eq!(watch.advance(110 * S, 60 * S), 0);
+        /* drift under a second is noise */
+        assert_eq!(watch.advance(120 * S, 69 * S + S / 2), 0);
+        /* 30s asleep: only the wall clock moved */
+        assert_eq!(watch.advance(150 * S, 70 * S), 30 * S);
+        /* reported once */
+        assert_eq!(watch.advance(151 * S, 71 * S), 0);
+        assert_eq!(watch.advance(200 * S, 72 * S), 48 * S);
+
+        assert_eq!(sleep_probe_ns(SleepPolicy::Continue), u64::MAX);
+        assert_eq!(sleep_probe_ns(SleepPolicy::Fail), SLEEP_PROBE_NS);
+    }
+
+    /* test that timebase validation works on the happy path (denom != 0) */
+    #[test]
+    #[cfg_attr(miri, ignore)] /* mach_timebase_info is FFI */
+    fn test_get_timebase_info_succeeds() {
+        let result = get_timebase_info();
+        assert!(result.is_ok(), "timebase info should succeed on macOS");
+        let (numer, denom) = result.unwrap();
+        assert!(numer > 0, "numer should be positive");
+        assert!(denom > 0, "denom should be positive (validated)");
+    }
+
+    /* test that wall_now_ns returns a reasonable value */
+    #[test]
+    #[cfg_attr(miri, ignore)] /* mach_continuous_time is FFI */
+    fn test_wall_now_ns_succeeds() {
+        let result = wall_now_ns();
+        assert!(result.is_ok(), "wall_now_ns should succeed on macOS");
+        let ns = result.unwrap();
+        assert!(ns > 0, "time should be positive");
+    }
+
+    /* test that precise_now_ns works in both modes */
+    #[test]
+    #[cfg_attr(miri, ignore)] /* uses FFI */
+    fn test_precise_now_ns_both_modes() {
+        use crate::args::Confine;
+
+        let wall = precise_now_ns(Confine::Wall);
+        assert!(wall.is_ok(), "wall mode should succeed");
+
+        let active = precise_now_ns(Confine::Active);
+        assert!(active.is_ok(), "active mode should succeed");
+    }
+}
@@ -0,0 +1,439 @@
+/*
+ * escalation.rs
+ *
+ * stopping a command at its deadline: the signal, the grace period, then
+ * SIGKILL. Escalation names where that's got to.
+ */
+
+use alloc::vec::Vec;
+
+use crate::also_kill::Target;
+use crate::args::StoppedPolicy;
+use crate::errno::{Errno, errno};
+use crate::error::{Result, TimeoutError};
+use crate::events::{EventKind, EventLog};
+use crate::log::Warning;
+use crate::proc_info::{ProcStatus, get_process_status};
+use crate::process::{RawChild, RawExitStatus, ResourceUsage};
+use crate::signal::{Signal, signal_name};
+
+use super::clock::{duration_to_ns, event_now, event_value, wall_now_ns};
+use super::{ChildState, RunConfig};
+
+/// How far stopping a command has got. An attempt starts `Running` and
+/// ends `Reaped`, taking only the steps its config and the command call
+/// for:
+///
+/// ```text
+/// Running ──> SoftSignaled ──> Grace ──> Killed ──> Reaped
+///    │              │            │                    ^
+///    │              └────────────┴────────────────────┤
+///    ├──> Killed  (--kill-after 0)                    │
+///    └──────────────────────────────────────────────--┘
+/// ```
+///
+/// A command can be reaped from any stage: it exits on its own, on
+/// `--stdin-inject-on-timeout`'s input, on the soft signal, or on a
+/// signal we forward. Only [`Escalation::can_move_to`]'s moves are made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Escalation {
+    /// Not signaled by us (yet).
+    #[default]
+    Running,
+    /// [`RunConfig::signal`] sent.
+    SoftSignaled,
+    /// Waiting out [`RunConfig::kill_after`] - or no time at all, for a
+    /// command `verify_signal_delivery` found stuck.
+    Grace,
+    /// SIGKILL sent.
+    Killed,
+    /// Exit status collected. Nothing follows.
+    Reaped,
+}
+
+impl Escalation {
+    /// Whether an attempt can go from `self` straight to `next`.
+    #[must_use]
+    pub const fn can_move_to(self, next: Self) -> bool {
+        matches!(
+            (self, next),
+            (Self::Running, Self::SoftSignaled | Self::Killed)
+                | (Self::SoftSignaled, Self::Grace)
+                | (Self::Grace, Self::Killed)
+                | (
+                    Self::Running | Self::SoftSignaled | Self::Grace | Self::Killed,
+                    Self::Reaped
+                )
+        )
+    }
+
+    /// Move on to `next`, checking (in debug builds) that it's a move
+    /// [`can_move_to`](Self::can_move_to) allows.
+    pub fn advance(&mut self, next: Self) {
+        debug_assert!(
+            self.can_move_to(next),
+            "escalation can't go from {} to {}",
+            self.as_str(),
+            next.as_str()
+        );
+        *self = next;
+    }
+
+    /// Stable name: `"soft_signaled"`.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Running => "running",
+            Self::SoftSignaled => "soft_signaled",
+            Self::Grace => "grace",
+            Self::Killed => "killed",
+            Self::Reaped => "reaped",
+        }
+    }
+}
+
+/* grace period ran out: find out why the soft signal didn't work */
+pub(super) fn escalation_state(pid: i32, config: &RunConfig) -> ChildState {
+    let state = ChildState::probe(pid);
+    if config.verbose && !config.quiet && !config.gnu_messages {
+        let why = match state {
+            ChildState::Stopped => "is stopped and can't handle",
+            ChildState::Zombie => "exited but wasn't reaped after",
+            _ => "is still running after",
+        };
+        crate::log_info!(
+            config.log(),
+            "command {} {}",
+            why,
+            signal_name(config.signal)
+        );
+    }
+    state
+}
+
+/* --verify-signal-delivery: right after sending sig, can the command act
+ * on it? kill(pid, 0) says it's still there, its run state whether it's
+ * stopped - a debugger's SIGSTOP, a ^Z - in which case sig stays pending
+ * and the grace period runs out for nothing. --on-stopped continue sends
+ * SIGCONT, which wakes it as it's sent unless a debugger holds it. true:
+ * kill it now */
+pub(super) fn verify_delivery(
+    pid: i32,
+    sig: Signal,
+    config: &RunConfig,
+    events: &mut EventLog,
+) -> Result<bool> {
+    let Some(policy) = config.verify_signal_delivery else {
+        return Ok(false);
+    };
+    // SAFETY: signal 0 only checks that pid exists
+    if unsafe { libc::kill(pid, 0) } != 0 {
+        return Ok(false);
+    }
+    let state = ChildState::probe(pid);
+    if state != ChildState::Stopped {
+        if config.verbose && !config.quiet && !config.gnu_messages {
+            crate::log_info!(
+                config.log(),
+                "{} delivered, command {}",
+                signal_name(sig),
+                state.as_str()
+            );
+        }
+        return Ok(false);
+    }
+    events.record(
+        EventKind::FoundStopped,
+        event_now(config.confine),
+        event_value(sig.as_raw()),
+    );
+    let killed = match policy {
+        StoppedPolicy::Kill => true,
+        _ => {
+            send_signal_recorded(pid, Signal::SIGCONT, config, events)?;
+            ChildState::probe(pid) == ChildState::Stopped
+        }
+    };
+    events.warn(
+        config.log(),
+        Warning::StoppedAtSignal {
+            signal: sig.as_raw(),
+            killed,
+        },
+    );
+    Ok(killed)
+}
+
+/* --kill-after 0: SIGKILL at the deadline instead of config.signal */
+#[inline]
+pub(super) fn kill_only(config: &RunConfig) -> bool {
+    config.kill_after.is_some_and(|d| d.is_zero())
+}
+
+/* -v: the signal about to go to the command. GNU names it without the
+ * SIG and quotes the command, and scripts run as gtimeout may match that */
+pub(super) fn log_sending(config: &RunConfig, sig: Signal, command: &str, note: &str) {
+    if !config.verbose || config.quiet {
+        return;
+    }
+    let name = signal_name(sig);
+    if config.gnu_messages {
+        let name = name.strip_prefix("SIG").unwrap_or(name);
+        crate::log_info!(
+            config.log(),
+            "sending signal {} to command '{}'",
+            name,
+            command
+        );
+    } else {
+        crate::log_info!(config.log(), "sending signal {} to command{}", name, note);
+    }
+}
+
+/* SIGKILL and reap - used when there's no grace period to wait out */
+pub(super) fn sigkill_and_wait(
+    child: &mut RawChild,
+    command: &str,
+    pid: i32,
+    config: &RunConfig,
+    events: &mut EventLog,
+) -> Result<(RawExitStatus, ResourceUsage)> {
+    log_sending(config, Signal::SIGKILL, command, " (--kill-after 0)");
+    send_signal_recorded(pid, Signal::SIGKILL, config, events)?;
+    child.wait().map_err(TimeoutError::from)
+}
+
+/* --also-kill: sig to the extra processes too, as it goes to the command.
+ * the file is re-read for each. SIGKILL passes over one that's gone or a
+ * zombie, so it's only counted killed when there was something to kill */
+pub(super) fn signal_also(sig: Signal, config: &RunConfig, events: &mut EventLog) {
+    let Some(also_kill) = &config.also_kill else {
+        return;
+    };
+    signal_pids(sig, also_kill.pids(), config, events);
+}
+
+/* sig to each of pids, recorded against its Target */
+fn signal_pids(sig: Signal, pids: Vec<i32>, config: &RunConfig, events: &mut EventLog) {
+    let now = wall_now_ns().ok();
+    let mut sent = 0u32;
+    for pid in pids {
+        let targets = events.also_killed_mut();
+        let i = targets
+            .iter()
+            .position(|t| t.pid == pid)
+            .unwrap_or_else(|| {
+                targets.push(Target::new(pid));
+                targets.len() - 1
+            });
+        let target = &mut targets[i];
+        if sig == Signal::SIGKILL && is_gone(pid) {
+            continue;
+        }
+        // SAFETY: kill takes no pointers
+        if unsafe { libc::kill(pid, sig.as_raw()) } == 0 {
+            target.signal.get_or_insert(sig);
+            target.killed |= sig == Signal::SIGKILL;
+            target.error = None;
+            target.signaled_at_ns = target.signaled_at_ns.or(now);
+            sent += 1;
+        } else {
+            target.error = Some(Errno::last());
+        }
+    }
+    if sent > 0 && config.verbose && !config.quiet {
+        crate::log_info!(
+            config.log(),
+            "sending signal {} to {} more process{} (--also-kill)",
+            signal_name(sig),
+            sent,
+            if sent == 1 { "" } else { "es" }
+        );
+    }
+}
+
+/* how often finish_also looks for the extra processes to be gone */
+const ALSO_KILL_POLL_MS: u64 = 10;
+
+/* --also-kill, once the command is reaped: an extra process the soft
+ * signal reached has the rest of its own grace period - the command
+ * may have used less of it - then gets SIGKILL. without --kill-after
+ * it's left to go in its own time, as the command would be. only those
+ * the soft signal reached: the file isn't re-read, so a pid written to
+ * it since isn't killed without the warning the others had */
+pub(super) fn finish_also(config: &RunConfig, events: &mut EventLog) {
+    if config.also_kill.is_none() {
+        return;
+    }
+    let targets = events.also_killed_mut();
+    let pending = |t: &Target| t.signaled_at_ns.is_some() && !t.killed;
+    let deadline = config.kill_after.and_then(|grace| {
+        targets
+            .iter()
+            .filter(|t| pending(t))
+            .filter_map(|t| t.signaled_at_ns)
+            .max()
+            .map(|at| at.saturating_add(duration_to_ns(grace)))
+    });
+    if let Some(deadline) = deadline {
+        loop {
+            let targets = events.also_killed_mut();
+            if targets
+                .iter()
+                .filter(|t| pending(t))
+                .all(|t| is_gone(t.pid))
+            {
+                break;
+            }
+            if wall_now_ns().map_or(true, |now| now >= deadline) {
+                let pids = targets
+                    .iter()
+                    .filter(|t| pending(t))
+                    .map(|t| t.pid)
+                    .collect();
+                signal_pids(Signal::SIGKILL, pids, config, events);
+                break;
+            }
+            crate::wait::sleep_ms(ALSO_KILL_POLL_MS);
+        }
+    }
+    /* the next attempt's timeout starts their grace over */
+    for target in events.also_killed_mut() {
+        target.signaled_at_ns = None;
+    }
+}
+
+/* exited, or exited and waiting for a parent that isn't us to reap it */
+fn is_gone(pid: i32) -> bool {
+    // SAFETY: signal 0 only checks that pid exists
+    (unsafe { libc::kill(pid, 0) } != 0 && errno() == libc::ESRCH)
+        || get_process_status(pid) == Some(ProcStatus::Zombie)
+}
+
+/* send_signal plus a timeline entry, so --explain shows every signal we sent */
+pub(super) fn send_signal_recorded(
+    pid: i32,
+    signal: Signal,
+    config: &RunConfig,
+    events: &mut EventLog,
+) -> Result<()> {
+    events.record(
+        EventKind::SignalSent,
+        event_now(config.confine),
+        event_value(signal.as_raw()),
+    );
+    send_signal(pid, signal, config.process_group().owns_group())
+}
+
+/*
+ * Send signal to child.
+ *
+ * Normal mode: killpg() signals the whole process group, catches shell
+ * scripts with children. ESRCH means it's already dead, that's fine.
+ *
+ * Inherited group (foreground mode): just signal the one process - killpg
+ * would hit us too. Grandchildren won't get the signal though.
+ *
+ * killpg can fail with ESRCH even when process exists (race conditions),
+ * so we fall back to regular kill().
+ */
+fn send_signal(pid: i32, signal: Signal, use_group: bool) -> Result<()> {
+    let sig = signal.as_raw();
+
+    if !use_group {
+        // SAFETY: kill() is safe with any pid/signal combo, returns -1 on error
+        let ret = unsafe { libc::kill(pid, sig) };
+        if ret == 0 {
+            return Ok(());
+        }
+        let err = errno();
+        if err == libc::ESRCH {
+            return Ok(()); // already dead, that's fine
+        }
+        return Err(TimeoutError::SignalError(err));
+    }
+
+    /*
+     * try process group first. if ESRCH, fall back to just the process.
+     * orphaned children get reparented to init - that's unix for you.
+     */
+    // SAFETY: killpg() is safe with any pid/signal combo, returns -1 on error
+    let ret = unsafe { libc::killpg(pid, sig) };
+    if ret == 0 {
+        return Ok(());
+    }
+
+    let err = errno();
+    if err == libc::ESRCH {
+        /* group gone, try process directly */
+        // SAFETY: kill() is safe with any pid/signal combo, returns -1 on error
+        let ret = unsafe { libc::kill(pid, sig) };
+        if ret == 0 {
+            return Ok(());
+        }
+        let err = errno();
+        if err == libc::ESRCH {
+            return Ok(()); // already dead
+        }
+        return Err(TimeoutError::SignalError(err));
+    }
+
+    Err(TimeoutError::SignalError(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Skip under Miri: libc::kill is an unsupported foreign function */
+    #[test]
+    #[cfg(not(miri))]
+    fn test_send_signal_to_nonexistent_process() {
+        /* ESRCH should be handled gracefully */
+        let fake_pid = 99999i32;
+        let result = send_signal(fake_pid, Signal::SIGTERM, false);
+        assert!(result.is_ok(), "ESRCH should be handled gracefully");
+    }
+
+    #[test]
+    fn test_escalation_moves() {
+        use Escalation::{Grace, Killed, Reaped, Running, SoftSignaled};
+        let all = [Running, SoftSignaled, Grace, Killed, Reaped];
+        let allowed = [
+            (Running, SoftSignaled),
+            (Running, Killed),
+            (SoftSignaled, Grace),
+            (Grace, Killed),
+            (Running, Reaped),
+            (SoftSignaled, Reaped),
+            (Grace, Reaped),
+            (Killed, Reaped),
+        ];
+        for from in all {
+            for to in all {
+                assert_eq!(
+                    from.can_move_to(to),
+                    allowed.contains(&(from, to)),
+                    "{} -> {}",
+                    from.as_str(),
+                    to.as_str()
+                );
+            }
+        }
+
+        let mut stage = Escalation::default();
+        for next in [SoftSignaled, Grace, Killed, Reaped] {
+            stage.advance(next);
+            assert_eq!(stage, next);
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "escalation can't go from reaped to running")]
+    fn test_escalation_rejects_going_back() {
+        let mut stage = Escalation::Reaped;
+        stage.advance(Escalation::Running);
+    }
+}
@@ -0,0 +1,454 @@
+/*
+ * hooks.rs
+ *
+ * --on-timeout, --on-limit and --crash-hook: a shell command run
+ * alongside, or after, the one we're watching, with %p and friends filled
+ * in and a time limit of its own.
+ */
+
+use alloc::format;
+use alloc::string::String;
+use core::time::Duration;
+
+use crate::args::Confine;
+use crate::events::{EventKind, EventLog};
+use crate::kq::{self, Kqueue};
+use crate::log::Logger;
+use crate::process::{ProcessGroup, RawChild, RawExitStatus, spawn_command};
+use crate::signal::{Signal, signal_name};
+use crate::time_math::{advance_ns, deadline_reached, elapsed_ns, remaining_ns};
+
+use super::clock::{duration_ms, duration_to_ns, event_now, event_value, precise_now_ns};
+use super::{RunConfig, open_kqueue};
+
+/// What happened when we ran the on-timeout or crash hook.
+///
+/// ```
+/// use procguard::{EventLog, RunConfig, Signal, run_crash_hook};
+///
+/// let mut events = EventLog::new();
+/// let hook = run_crash_hook("exit 3", 1234, Signal::SIGSEGV, None, &RunConfig::default(), &mut events);
+/// assert!(hook.ran && !hook.timed_out);
+/// assert_eq!(hook.exit_code, Some(3));
+/// ```
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, Default)]
+pub struct HookResult {
+    /// Whether it started at all.
+    pub ran: bool,
+    /// `None` if it timed out or failed to start.
+    pub exit_code: Option<i32>,
+    /// Killed for running past `limit_ms`.
+    pub timed_out: bool,
+    /// How long it ran.
+    pub elapsed_ms: u64,
+    /// How long it was allowed to run.
+    pub limit_ms: u64,
+}
+
+/* timeline entry for how a hook ended */
+pub(super) fn record_hook_end(events: &mut EventLog, hook: &HookResult, confine: Confine) {
+    let now = event_now(confine);
+    match hook.exit_code {
+        Some(code) => events.record(EventKind::HookExited, now, event_value(code)),
+        None if hook.timed_out => events.record(EventKind::HookTimedOut, now, 0),
+        None => events.record(EventKind::HookFailed, now, 0),
+    }
+}
+
+/*
+ * Expand hook placeholders: `%x` becomes the value paired with x in
+ * `subs`, `%%` a literal %. Any other % sequence is left as written.
+ */
+fn expand_hook(cmd: &str, subs: &[(char, &str)]) -> String {
+    let mut out = String::with_capacity(cmd.len() + 16);
+    let mut chars = cmd.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let next = chars.clone().next();
+        if next == Some('%') {
+            out.push('%');
+            chars.next();
+        } else if let Some(&(_, value)) = subs.iter().find(|(key, _)| Some(*key) == next) {
+            out.push_str(value);
+            chars.next();
+        } else {
+            out.push('%');
+        }
+    }
+    out
+}
+
+/*
+ * Start the on-timeout or on-limit hook command with PID substitution;
+ * name is which, for its messages.
+ * The hook has a time limit to prevent hanging. We log but don't fail
+ * if the hook fails - the main timeout behavior must proceed.
+ *
+ * Substitution: %p -> PID, %% -> literal %
+ *
+ * Note: If the hook spawns processes that create their own process groups
+ * (e.g., via setsid or nohup), those won't be killed when the hook times out.
+ * Such orphans get reparented to init. For safety-critical use, hooks should
+ * not spawn long-lived background processes.
+ */
+pub(super) fn start_stop_hook<'a>(
+    name: &'static str,
+    cmd: &str,
+    pid: i32,
+    config: &'a RunConfig,
+) -> core::result::Result<RunningHook<'a>, HookResult> {
+    let expanded_cmd = expand_hook(cmd, &[('p', &format!("{}", pid))]);
+    start_hook(name, expanded_cmd, config.hook_limit(), config)
+}
+
+/// Run the `--crash-hook` command after the command crashed.
+///
+/// Substitution: `%p` -> PID, `%s` -> signal name (`SIGSEGV`), `%r` -> crash
+/// report path (empty if none), `%%` -> literal `%`. Time-limited by
+/// `config.on_timeout_limit`, like the on-timeout hook, and recorded in
+/// `events`. A hook that can't be started comes back with `ran: false`.
+///
+/// ```
+/// use procguard::{EventLog, RunConfig, Signal, run_crash_hook};
+///
+/// let mut events = EventLog::new();
+/// let hook = run_crash_hook(
+///     "test %p-%s = 77-SIGABRT",
+///     77,
+///     Signal::SIGABRT,
+///     None,
+///     &RunConfig::default(),
+///     &mut events,
+/// );
+/// assert_eq!(hook.exit_code, Some(0));
+/// ```
+pub fn run_crash_hook(
+    cmd: &str,
+    pid: i32,
+    signal: Signal,
+    report: Option<&str>,
+    config: &RunConfig,
+    events: &mut EventLog,
+) -> HookResult {
+    let Ok(queue) = open_kqueue() else {
+        return HookResult::default();
+    };
+    let expanded_cmd = expand_hook(
+        cmd,
+        &[
+            ('p', &format!("{}", pid)),
+            ('s', signal_name(signal)),
+            ('r', report.unwrap_or("")),
+        ],
+    );
+    events.record(EventKind::HookStarted, event_now(config.confine), 1);
+    let hook = run_hook(
+        "crash",
+        expanded_cmd,
+        config.on_timeout_limit,
+        config,
+        &queue,
+    );
+    record_hook_end(events, &hook, config.confine);
+    hook
+}
+
+/* spawn an expanded hook command and wait for it, up to `limit`.
+ * `name` is for the verbose log lines */
+fn run_hook(
+    name: &'static str,
+    expanded_cmd: String,
+    limit: Duration,
+    config: &RunConfig,
+    queue: &Kqueue,
+) -> HookResult {
+    match start_hook(name, expanded_cmd, limit, config) {
+        Ok(mut hook) => hook.finish(queue),
+        Err(failed) => failed,
+    }
+}
+
+/* spawn an expanded hook command without waiting for it. one that can't
+ * be started comes back as its result */
+fn start_hook<'a>(
+    name: &'static str,
+    expanded_cmd: String,
+    limit: Duration,
+    config: &'a RunConfig,
+) -> core::result::Result<RunningHook<'a>, HookResult> {
+    let log = config.verbose.then(|| config.log());
+    /* use 0 as fallback for timing if timebase fails - hook timing is best-effort */
+    let start_ns = precise_now_ns(config.confine).unwrap_or(0);
+
+    if let Some(log) = log {
+        crate::log_info!(log, "running {} hook: {}", name, expanded_cmd);
+    }
+
+    /* Run via shell to support complex commands.
+     * Use process group so we can kill hook and all its children on timeout. */
+    match spawn_command("sh", &[String::from("-c"), expanded_cmd], ProcessGroup::New) {
+        Ok(child) => Ok(RunningHook {
+            child,
+            name,
+            log,
+            confine: config.confine,
+            start_ns,
+            limit,
+            result: None,
+        }),
+        Err(e) => {
+            if let Some(log) = log {
+                crate::log_info!(log, "{} hook failed to start: {}", name, e);
+            }
+            Err(HookResult {
+                ran: false,
+                exit_code: None,
+                timed_out: false,
+                /* use checked elapsed - fallback to 0 on clock anomaly */
+                elapsed_ms: elapsed_ns(start_ns, precise_now_ns(config.confine).unwrap_or(0))
+                    .unwrap_or(0)
+                    / 1_000_000,
+                limit_ms: duration_ms(limit),
+            })
+        }
+    }
+}
+
+/*
+ * A hook that's been spawned. run_hook waits for it straight away;
+ * --hook-async leaves it running while wait_with_kqueue watches the
+ * command through the grace period, and that wait reaps it, or kills it
+ * at its limit, as it happens. Whatever is left is waited out by finish().
+ */
+pub(super) struct RunningHook<'a> {
+    pub(super) child: RawChild,
+    name: &'static str,
+    log: Option<Logger<'a>>, /* with -v */
+    confine: Confine,
+    start_ns: u64,
+    limit: Duration,
+    result: Option<HookResult>, /* set once it's reaped */
+}
+
+impl RunningHook<'_> {
+    #[allow(clippy::cast_possible_wrap)]
+    pub(super) fn pid(&self) -> i32 {
+        self.child.id() as i32
+    }
+
+    /* still to be reaped: its exit is worth watching for */
+    pub(super) fn running(&self) -> bool {
+        self.result.is_none()
+    }
+
+    pub(super) fn deadline_ns(&self) -> u64 {
+        advance_ns(self.start_ns, duration_to_ns(self.limit))
+    }
+
+    /* its exit event came in: reap it */
+    pub(super) fn reap(&mut self) -> &HookResult {
+        let outcome = match self.child.wait() {
+            Ok((status, _rusage)) => HookWaitResult::Exited(status),
+            Err(e) => HookWaitResult::Error(format!("{}", e)),
+        };
+        self.settle(outcome)
+    }
+
+    /* wait out whatever is left of its limit, then kill it */
+    pub(super) fn finish(&mut self, queue: &Kqueue) -> HookResult {
+        if self.running() {
+            let now_ns = precise_now_ns(self.confine).unwrap_or(0);
+            let left = remaining_ns(now_ns, self.deadline_ns());
+            let outcome = wait_for_hook_with_kqueue(
+                &mut self.child,
+                Duration::from_nanos(left),
+                self.confine,
+                queue,
+            );
+            return self.settle(outcome).clone();
+        }
+        self.result.clone().unwrap_or_default()
+    }
+
+    /* record how it went. one that timed out is killed and reaped here */
+    pub(super) fn settle(&mut self, outcome: HookWaitResult) -> &HookResult {
+        let name = self.name;
+        /* use checked elapsed - fallback to 0 on clock anomaly */
+        let elapsed_ms = elapsed_ns(self.start_ns, precise_now_ns(self.confine).unwrap_or(0))
+            .unwrap_or(0)
+            / 1_000_000;
+        let (exit_code, timed_out) = match outcome {
+            HookWaitResult::Exited(status) => {
+                let exit_code = status.code();
+                if let Some(log) = self.log
+                    && let Some(code) = exit_code
+                    && code != 0
+                {
+                    crate::log_info!(log, "{} hook exited with code {}", name, code);
+                }
+                (exit_code, false)
+            }
+            HookWaitResult::TimedOut => {
+                if let Some(log) = self.log {
+                    crate::log_info!(log, "{} hook timed out, killing", name);
+                }
+                /* Kill entire process group to get grandchildren too */
+                // SAFETY: killpg with valid pid and signal is safe
+                unsafe { libc::killpg(self.pid(), libc::SIGKILL) };
+                let _ = self.child.wait();
+                (None, true)
+            }
+            HookWaitResult::Error(e) => {
+                if let Some(log) = self.log {
+                    crate::log_info!(log, "{} hook wait failed: {}", name, e);
+                }
+                (None, false)
+            }
+        };
+        self.result.insert(HookResult {
+            ran: true,
+            exit_code,
+            timed_out,
+            elapsed_ms,
+            limit_ms: duration_ms(self.limit),
+        })
+    }
+}
+
+/* an error between starting an async hook and waiting it out mustn't
+ * leave it running */
+impl Drop for RunningHook<'_> {
+    fn drop(&mut self) {
+        if self.running() {
+            // SAFETY: killpg with valid pid and signal is safe
+            unsafe { libc::killpg(self.pid(), libc::SIGKILL) };
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/* Result of waiting for hook process */
+pub(super) enum HookWaitResult {
+    Exited(RawExitStatus),
+    TimedOut,
+    Error(String),
+}
+
+/*
+ * Wait for hook process using kqueue - zero CPU while waiting.
+ * Simpler than wait_with_kqueue since we don't need signal forwarding.
+ */
+fn wait_for_hook_with_kqueue(
+    child: &mut RawChild,
+    timeout: Duration,
+    confine: Confine,
+    queue: &Kqueue,
+) -> HookWaitResult {
+    #[allow(clippy::cast_possible_wrap)]
+    let pid = child.id() as i32;
+    /* use 0 as fallback for timing if timebase fails - hook timing is best-effort */
+    let start_ns = precise_now_ns(confine).unwrap_or(0);
+    let timeout_ns = duration_to_ns(timeout);
+    let deadline_ns = advance_ns(start_ns, timeout_ns);
+
+    /* the monitored child's filters are still on the run's queue */
+    queue.reset();
+
+    /* Watch for process exit and set timer (ident 2, different from main timer) */
+    let mut changes = [kq::proc_exit(pid), kq::timer(2, timeout_ns)];
+    let mut event = kq::empty();
+
+    loop {
+        /* Recalculate remaining time (handles EINTR correctly) */
+        let now_ns = precise_now_ns(confine).unwrap_or(deadline_ns); /* on error, trigger timeout */
+        if deadline_reached(now_ns, deadline_ns) {
+            return HookWaitResult::TimedOut;
+        }
+        changes[1] = kq::timer(2, remaining_ns(now_ns, deadline_ns));
+
+        if let Err(err) = queue.wait(&changes, &mut event) {
+            if err == libc::EINTR {
+                continue;
+            }
+            if err == libc::ESRCH {
+                /* Process already gone */
+                return match child.try_wait() {
+                    Ok(Some((status, _rusage))) => HookWaitResult::Exited(status),
+                    Ok(None) => match child.wait() {
+                        Ok((status, _rusage)) => HookWaitResult::Exited(status),
+                        Err(e) => HookWaitResult::Error(format!("{}", e)),
+                    },
+                    Err(e) => HookWaitResult::Error(format!("{}", e)),
+                };
+            }
+            return HookWaitResult::Error(format!("kevent failed: errno {}", err));
+        }
+        break;
+    }
+
+    /* Check for registration errors */
+    if (event.flags & kq::EV_ERROR) != 0 {
+        #[allow(clippy::cast_possible_truncation)]
+        let err_code = event.data as i32;
+        if err_code == libc::ESRCH {
+            return match child.wait() {
+                Ok((status, _rusage)) => HookWaitResult::Exited(status),
+                Err(e) => HookWaitResult::Error(format!("{}", e)),
+            };
+        }
+        return HookWaitResult::Error(format!("kqueue registration failed: errno {}", err_code));
+    }
+
+    if event.filter == kq::EVFILT_PROC {
+        match child.wait() {
+            Ok((status, _rusage)) => HookWaitResult::Exited(status),
+            Err(e) => HookWaitResult::Error(format!("{}", e)),
+        }
+    } else {
+        HookWaitResult::TimedOut
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_hook() {
+        let subs = [('p', "42"), ('s', "SIGSEGV"), ('r', "/tmp/a b.ips")];
+        assert_eq!(expand_hook("kill %p", &subs), "kill 42");
+        assert_eq!(
+            expand_hook("up \"%r\" %s", &subs),
+            "up \"/tmp/a b.ips\" SIGSEGV"
+        );
+        assert_eq!(expand_hook("100%% %p%%", &subs), "100% 42%");
+        assert_eq!(expand_hook("%%p", &subs), "%p");
+        /* unknown or trailing % stays */
+        assert_eq!(expand_hook("%x %", &subs), "%x %");
+        assert_eq!(expand_hook("%r", &[('p', "1")]), "%r");
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] /* spawns a process */
+    fn test_run_crash_hook() {
+        let config = RunConfig::default();
+        let mut events = EventLog::new();
+        let hook = run_crash_hook(
+            "test %s = SIGABRT && test %p = 7 && test -z \"%r\" && exit 3",
+            7,
+            Signal::SIGABRT,
+            None,
+            &config,
+            &mut events,
+        );
+        assert!(hook.ran);
+        assert_eq!(hook.exit_code, Some(3));
+        assert!(!hook.timed_out);
+        let kinds: Vec<_> = events.as_slice().iter().map(|e| e.kind).collect();
+        assert_eq!(kinds, [EventKind::HookStarted, EventKind::HookExited]);
+    }
+}
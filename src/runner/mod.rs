@@ -0,0 +1,2669 @@
+/*
+ * runner/mod.rs
+ *
+ * Spawn child, watch clock, kill if needed. All the tricky bits live here.
+ *
+ * kqueue: we tell the kernel "wake me when the process exits or the timer
+ * fires" and then sleep. Zero CPU while waiting. Polling would be dumb.
+ *
+ * mach_continuous_time: the old mach_absolute_time stops when your laptop
+ * sleeps. So a 1 hour timeout could take 8 hours if you close the lid.
+ * mach_continuous_time keeps counting through sleep. That's what people expect.
+ *
+ * Process groups: when you timeout a shell script that spawns children, you
+ * want to kill all of them, not just the shell. setpgid + killpg handles that.
+ * --foreground disables this for interactive stuff, --pgroup picks explicitly.
+ *
+ * Signal forwarding: if timeout gets SIGTERM (docker stop, system shutdown),
+ * we forward it to the child before dying. Otherwise you get orphans.
+ * Self-pipe trick: handler writes to pipe, kqueue watches it.
+ *
+ * The pieces, in the order a run meets them:
+ *
+ *   retry.rs      - attempts: --retry, --spawn-retry, the delays between
+ *   clock.rs      - the clocks deadlines are measured on, sleep detection
+ *   monitor.rs    - one attempt's wait: kqueue, heartbeats, stdin, limits
+ *   escalation.rs - the deadline or limit: signal, grace period, SIGKILL
+ *   hooks.rs      - --on-timeout, --on-limit and --crash-hook
+ *
+ * this file has the config, the results, and setting up an attempt.
+ */
+
+use alloc::ffi::CString;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write as _;
+use core::sync::atomic::AtomicBool;
+use core::time::Duration;
+
+use crate::also_kill::AlsoKill;
+use crate::args::{
+    ChildStdin, ChildStopPolicy, Confine, HookBudget, OwnedArgs, Personality, RatePolicy,
+    SleepPolicy, StdinMode, StoppedPolicy, Timestamps,
+};
+use crate::duration::{format_duration, is_no_timeout, parse_duration};
+use crate::errno::errno;
+use crate::error::{Result, TimeoutError, exit_codes};
+use crate::events::{EventKind, EventLog};
+use crate::forward::Route;
+use crate::kq::Kqueue;
+use crate::log::{self, Interval, Logger, Warning};
+use crate::outcome::Outcome;
+use crate::output::{ChildEnds, Interposer};
+use crate::proc_info::{ExitReason, ProcStatus, get_process_status};
+use crate::process::{
+    Arch, ProcessContext, ProcessGroup, RawExitStatus, ResourceUsage, SpawnError, build_argv,
+    spawn_argv_dups, spawn_argv_with_limits_dups,
+};
+use crate::proctree::Tracker;
+use crate::qos::{self, QosClass};
+use crate::reaper;
+use crate::rlimit::{
+    ResourceLimits, cpu_limit_reached, parse_cpu_percent, parse_cpu_time_pair, parse_mem_limit,
+};
+use crate::safe_file::{self, FileOptions};
+use crate::signal::{Signal, parse_signal, signal_name, signal_number};
+use crate::throttle::{self, CpuThrottleConfig};
+use crate::tty::{ForegroundGuard, StdinRelay, TermiosGuard};
+use crate::window::Window;
+
+mod clock;
+mod escalation;
+mod hooks;
+mod monitor;
+mod retry;
+
+pub(crate) use clock::{event_now, wall_now_ns};
+pub use escalation::Escalation;
+pub use hooks::{HookResult, run_crash_hook};
+pub use monitor::ThrottleContext;
+pub use retry::{
+    AttemptResult, Attempts, DEFAULT_SPAWN_RETRY_DELAY, MAX_RETRIES, MAX_SPAWN_RETRIES,
+    run_with_retry, run_with_retry_bytes, run_with_retry_events,
+};
+
+use clock::{duration_ms, duration_to_ns, event_value};
+use escalation::finish_also;
+use monitor::monitor_with_timeout;
+use retry::{max_attempts, retry_delay, sit_out_delay, spawn_retry_delay, spawn_retry_delays};
+
+/*
+ * Self-pipe trick for signal forwarding.
+ *
+ * Problem: we're blocked in kevent() waiting. If we get SIGTERM, we need to
+ * forward it to the child. But signal handlers can only call write() and
+ * _exit(), so we can't do much from the handler.
+ *
+ * Fix: create a pipe, handler writes a byte, kqueue watches the read end.
+ * Signal arrives, pipe becomes readable, kevent returns. Simple.
+ *
+ * signalfd would work but that's Linux only. EVFILT_SIGNAL exists but doesn't
+ * play nice with process monitoring. Pipe works everywhere.
+ *
+ * The handler is process-wide but each run gets its own pipe, so library
+ * callers can run commands on several threads at once - see forward.rs.
+ */
+
+/// Install the signal handlers that forward signals to running children.
+///
+/// Handles: SIGTERM, SIGINT, SIGHUP, SIGQUIT, SIGUSR1, SIGUSR2. Call before
+/// spawning; every `run_command` started while a guard is alive passes
+/// these signals on to its child. If the handlers couldn't be installed,
+/// returns [`Warning::SignalForwardingUnavailable`] for the caller to
+/// report; commands still run, they just don't get our signals.
+///
+/// Safe to call from several threads - each caller gets its own guard, and
+/// the handlers stay installed until the last guard is dropped.
+///
+/// ```
+/// use procguard::{RunConfig, run_command, setup_signal_forwarding};
+///
+/// let _forwarding = setup_signal_forwarding();
+/// let result = run_command("true", &[], &RunConfig::default());
+/// assert!(result.is_ok());
+/// /* handlers restored here, even on early return or panic */
+/// ```
+///
+/// # Errors
+/// [`Warning::SignalForwardingUnavailable`] with the errno `sigaction`
+/// or the signal pipe failed with.
+pub fn setup_signal_forwarding() -> core::result::Result<SignalForwardGuard, Warning> {
+    crate::forward::install()
+        .map(|()| SignalForwardGuard { _private: () })
+        .map_err(|errno| Warning::SignalForwardingUnavailable { errno })
+}
+
+/// Keeps signal forwarding installed; see [`setup_signal_forwarding`].
+///
+/// Dropping the last guard resets the signal handlers to default. Runs still
+/// in progress on other threads keep working but stop receiving forwarded
+/// signals.
+///
+/// ```
+/// use procguard::{SignalForwardGuard, setup_signal_forwarding};
+///
+/// let guard = setup_signal_forwarding();
+/// assert!(guard.is_ok());
+/// drop(guard); /* the last one: handlers back to default */
+/// ```
+#[must_use = "forwarding is uninstalled as soon as the guard is dropped"]
+#[derive(Debug)]
+pub struct SignalForwardGuard {
+    _private: (),
+}
+
+impl Drop for SignalForwardGuard {
+    fn drop(&mut self) {
+        crate::forward::uninstall();
+    }
+}
+
+/// Set once a signal asking us to stop (SIGTERM, SIGINT, SIGHUP, SIGQUIT)
+/// has come in while forwarding is installed. Hand it to a wait before
+/// the command starts as [`Cancel::Flag`](crate::wait::Cancel::Flag), so
+/// the signal ends the wait rather than reaching the command once it's
+/// spawned.
+pub fn stop_requested() -> &'static AtomicBool {
+    &crate::forward::STOP
+}
+
+/// A stop signal that came in while no command was running to forward it
+/// to - during a wait before it, or after the last attempt - taken so it's
+/// acted on once. The caller writes its result whole, then dies of it with
+/// [`raise_default`](crate::signal::raise_default).
+pub fn take_unforwarded_stop() -> Option<Signal> {
+    crate::forward::take_unrouted_stop()
+}
+
+/* this run's signal pipe. None without a guard (nothing to forward), or
+ * with a warning if we should forward but can't (fds exhausted, too many
+ * concurrent runs) - the run goes ahead either way */
+fn open_route(log: Logger<'_>, events: &mut EventLog) -> Option<Route> {
+    if !crate::forward::installed() {
+        return None;
+    }
+    Route::open()
+        .map_err(|errno| events.warn(log, Warning::SignalForwardingUnavailable { errno }))
+        .ok()
+}
+
+/* how often --mem-limit and --cpu-percent look at the child unless
+ * --poll-interval says otherwise, and the bounds on what it may say:
+ * below 10ms the looking costs more than it catches, past a minute a
+ * limit is hardly watched at all */
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const MIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Result of running a command with timeout.
+///
+/// This enum represents all possible outcomes when running a command:
+/// - `Completed`: command finished before timeout
+/// - `TimedOut`: command exceeded time limit
+/// - `MemoryLimitExceeded`: command exceeded memory limit
+/// - `CpuLimitExceeded`: the kernel ended it at the `--cpu-time` limit
+/// - `SignalForwarded`: parent received a signal and forwarded it
+///
+/// ```
+/// use core::time::Duration;
+/// use procguard::{ChildState, RunConfig, RunResult, Signal, TimeoutReason, run_command};
+///
+/// let config = RunConfig {
+///     timeout: Duration::from_millis(100),
+///     ..RunConfig::default()
+/// };
+/// let args = ["-c".to_string(), "sleep 5".to_string()];
+/// match run_command("sh", &args, &config).unwrap() {
+///     RunResult::TimedOut { signal, killed, reason, child_state, .. } => {
+///         assert_eq!(signal, Signal::SIGTERM);
+///         assert!(!killed);
+///         assert!(reason == TimeoutReason::WallClock);
+///         assert_eq!(child_state, ChildState::Signaled);
+///     }
+///     _ => unreachable!("sleep 5 outlives a 100ms timeout"),
+/// }
+/// ```
+#[cfg_attr(test, derive(Debug))]
+#[non_exhaustive]
+pub enum RunResult {
+    /// Exited before any limit.
+    Completed {
+        /// How it exited.
+        status: RawExitStatus,
+        /// What it used.
+        rusage: ResourceUsage,
+    },
+    /// Ran out of time and was sent `signal`.
+    TimedOut {
+        /// The signal we sent.
+        signal: Signal,
+        /// True if we had to escalate to SIGKILL.
+        killed: bool,
+        /// How it exited, once reaped.
+        status: Option<RawExitStatus>,
+        /// What it used, once reaped.
+        rusage: Option<ResourceUsage>,
+        /// On-timeout hook result if configured.
+        hook: Option<HookResult>,
+        /// What triggered the timeout.
+        reason: TimeoutReason,
+        /// How the child responded to our signal.
+        child_state: ChildState,
+    },
+    /// Went over `--mem-limit` and was sent `signal`.
+    MemoryLimitExceeded {
+        /// The signal we sent.
+        signal: Signal,
+        /// True if we had to escalate to SIGKILL.
+        killed: bool,
+        /// How it exited, once reaped.
+        status: Option<RawExitStatus>,
+        /// What it used, once reaped.
+        rusage: Option<ResourceUsage>,
+        /// The limit that was exceeded.
+        limit_bytes: u64,
+        /// Memory usage when limit was hit.
+        actual_bytes: u64,
+        /// On-limit (or on-timeout) hook result if configured.
+        hook: Option<HookResult>,
+        /// How the child responded to our signal.
+        child_state: ChildState,
+    },
+    /// Killed by the kernel at its `--cpu-time` limit.
+    CpuLimitExceeded {
+        /// SIGXCPU at the soft limit, SIGKILL at the hard one - the
+        /// kernel's, not ours.
+        signal: Signal,
+        /// How it exited.
+        status: RawExitStatus,
+        /// What it used.
+        rusage: ResourceUsage,
+        /// The limit it died at.
+        limit_ms: u64,
+        /// User + system time it had used.
+        actual_ms: u64,
+    },
+    /// We got SIGTERM, SIGINT or SIGHUP and passed it on.
+    SignalForwarded {
+        /// The signal passed on.
+        signal: Signal,
+        /// How it exited, once reaped.
+        status: Option<RawExitStatus>,
+        /// What it used, once reaped.
+        rusage: Option<ResourceUsage>,
+    },
+}
+
+/// Reason for timeout (wall clock, stdin idle, system sleep or the time
+/// window closing)
+///
+/// ```
+/// use procguard::{Outcome, TimeoutReason};
+///
+/// let outcome = Outcome::TimedOut { reason: TimeoutReason::StdinIdle, killed: false };
+/// assert_eq!(outcome.as_str(), "timeout");
+/// assert!(TimeoutReason::default() == TimeoutReason::WallClock);
+/// ```
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum TimeoutReason {
+    /// The timeout ran out.
+    #[default]
+    WallClock,
+    /// Stdin saw no input for `--stdin-timeout`.
+    StdinIdle,
+    /// The machine slept during the run and `on_sleep` is
+    /// [`SleepPolicy::Fail`].
+    SystemSleep,
+    /// The [`window_stop`](RunConfig::window_stop) window closed.
+    WindowClosed,
+    /// The command stopped itself and `on_child_stop` is
+    /// [`ChildStopPolicy::Fail`].
+    ChildStopped,
+}
+
+/// How the child responded to our timeout signal.
+///
+/// `Signaled`/`Exited` are the normal outcomes. The rest explain why
+/// `--kill-after` had to escalate, or why the signal never mattered.
+///
+/// ```
+/// use procguard::ChildState;
+///
+/// assert_eq!(ChildState::AlreadyExited.as_str(), "already_exited");
+/// assert_eq!(ChildState::default(), ChildState::Unknown);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum ChildState {
+    /// Couldn't tell (proc_pidinfo failed).
+    #[default]
+    Unknown,
+    /// Terminated by a signal after we sent ours.
+    Signaled,
+    /// Exited normally after our signal (it handled the signal and quit).
+    Exited,
+    /// Was already dead before our signal landed - we just reaped it.
+    AlreadyExited,
+    /// Still running when the grace period ran out (ignored or blocked it).
+    Running,
+    /// Stopped (SIGSTOP/SIGTSTP) when the grace period ran out, so it
+    /// couldn't act on the signal.
+    Stopped,
+    /// Dead but not yet reaped when the grace period ran out.
+    Zombie,
+    /// Exited on the `--stdin-inject-on-timeout` input; never signaled.
+    ExitedOnInput,
+}
+
+impl ChildState {
+    /// Name used as `"child_state"` in JSON.
+    #[must_use]
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Unknown => "unknown",
+            Self::Signaled => "signaled",
+            Self::Exited => "exited",
+            Self::AlreadyExited => "already_exited",
+            Self::Running => "running",
+            Self::Stopped => "stopped",
+            Self::Zombie => "zombie",
+            Self::ExitedOnInput => "exited_on_input",
+        }
+    }
+
+    /* state right now, for the checks before signaling and at escalation */
+    fn probe(pid: i32) -> Self {
+        match get_process_status(pid) {
+            Some(ProcStatus::Zombie) => Self::Zombie,
+            Some(ProcStatus::Stopped) => Self::Stopped,
+            Some(_) => Self::Running,
+            None => Self::Unknown,
+        }
+    }
+
+    /* how it went down, given what probe() said before we signaled */
+    fn from_exit(status: &RawExitStatus, before: Self) -> Self {
+        if before == Self::Zombie {
+            Self::AlreadyExited
+        } else if status.signal().is_some() {
+            Self::Signaled
+        } else {
+            Self::Exited
+        }
+    }
+}
+
+impl RunResult {
+    /// The exit code the CLI reports for this result, per GNU timeout:
+    /// `timeout_exit_code` (124) on a timeout or limit unless `preserve_status`,
+    /// the command's own code otherwise, 128 + N for a death by signal N.
+    #[must_use]
+    pub fn exit_code(&self, preserve_status: bool, timeout_exit_code: u8) -> u8 {
+        match Outcome::from(self) {
+            Outcome::Success { code } | Outcome::Failed { code } => code,
+            Outcome::Crashed { signal } => signal_exit_code(signal),
+            /* memory limit is a resource limit timeout - same code */
+            Outcome::TimedOut { .. } | Outcome::LimitExceeded { .. } if !preserve_status => {
+                timeout_exit_code
+            }
+            _ => self.child_exit_code(),
+        }
+    }
+
+    /* what the child itself ended with, or the signal that (presumably) ended it */
+    fn child_exit_code(&self) -> u8 {
+        match self {
+            Self::Completed { status, .. } => status_to_exit_code(status),
+            Self::TimedOut {
+                signal,
+                killed,
+                status,
+                ..
+            }
+            | Self::MemoryLimitExceeded {
+                signal,
+                killed,
+                status,
+                ..
+            } => status.map_or_else(
+                || {
+                    let sig = if *killed { Signal::SIGKILL } else { *signal };
+                    signal_exit_code(sig)
+                },
+                |s| status_to_exit_code(&s),
+            ),
+            Self::SignalForwarded { signal, status, .. } => {
+                /* We got killed by a signal - return 128 + signum like the child would */
+                status.map_or_else(|| signal_exit_code(*signal), |s| status_to_exit_code(&s))
+            }
+            Self::CpuLimitExceeded { status, .. } => status_to_exit_code(status),
+        }
+    }
+
+    /// The signal a command that ran to completion crashed with (SIGSEGV,
+    /// SIGABRT, ... - see [`Signal::is_crash`]). None if it exited, or was
+    /// killed by us or by a signal that isn't a crash.
+    #[must_use]
+    pub fn crash_signal(&self) -> Option<Signal> {
+        match Outcome::from(self) {
+            Outcome::Crashed { signal } => Some(signal),
+            _ => None,
+        }
+    }
+
+    /// The signal the command died of when it wasn't one we sent: jetsam's
+    /// SIGKILL, an admin's SIGTERM. None if it exited, crashed (see
+    /// [`crash_signal`](Self::crash_signal)), died of our signal or the
+    /// kernel's at `--cpu-time`, or an on-timeout or on-limit hook ran
+    /// that may have sent it.
+    #[must_use]
+    pub fn external_kill(&self) -> Option<Signal> {
+        let (status, sent) = match self {
+            Self::Completed { status, .. } => (Some(status), None),
+            Self::TimedOut { hook: Some(h), .. }
+            | Self::MemoryLimitExceeded { hook: Some(h), .. }
+                if h.ran =>
+            {
+                return None;
+            }
+            Self::TimedOut {
+                signal,
+                killed,
+                status,
+                child_state,
+                ..
+            }
+            | Self::MemoryLimitExceeded {
+                signal,
+                killed,
+                status,
+                child_state,
+                ..
+            } => match child_state {
+                /* dead before ours landed, or never signaled */
+                ChildState::AlreadyExited | ChildState::ExitedOnInput => (status.as_ref(), None),
+                _ => (status.as_ref(), Some((*signal, *killed))),
+            },
+            Self::SignalForwarded { signal, status, .. } => {
+                (status.as_ref(), Some((*signal, false)))
+            }
+            Self::CpuLimitExceeded { .. } => return None,
+        };
+        let sig = Signal::try_from_raw(status?.signal()?)?;
+        let ours = sent
+            .is_some_and(|(signal, killed)| sig == signal || (killed && sig == Signal::SIGKILL));
+        (!ours && !sig.is_crash()).then_some(sig)
+    }
+
+    /// The kernel's reason for the command's end: jetsam, a signal, a
+    /// codesigning kill... See [`RawExitStatus::exit_reason`]; None if it
+    /// wasn't reaped, or just exited.
+    #[must_use]
+    pub fn exit_reason(&self) -> Option<ExitReason> {
+        match self {
+            Self::Completed { status, .. } | Self::CpuLimitExceeded { status, .. } => {
+                status.exit_reason()
+            }
+            Self::TimedOut { status, .. }
+            | Self::MemoryLimitExceeded { status, .. }
+            | Self::SignalForwarded { status, .. } => status.and_then(|s| s.exit_reason()),
+        }
+    }
+
+    /// What the command used, if it was reaped.
+    #[must_use]
+    pub fn resource_usage(&self) -> Option<&ResourceUsage> {
+        match self {
+            Self::Completed { rusage, .. } => Some(rusage),
+            Self::TimedOut { rusage, .. } => rusage.as_ref(),
+            Self::MemoryLimitExceeded { rusage, .. } => rusage.as_ref(),
+            Self::SignalForwarded { rusage, .. } => rusage.as_ref(),
+            Self::CpuLimitExceeded { rusage, .. } => Some(rusage),
+        }
+    }
+}
+
+/* POSIX: exit_code = 128 + signum */
+#[inline]
+#[allow(clippy::cast_sign_loss)]
+const fn signal_exit_code(signal: Signal) -> u8 {
+    ((128i32 + signal_number(signal)) & 0xFF) as u8
+}
+
+/* exit status to 8-bit code, POSIX style */
+#[allow(clippy::cast_sign_loss)]
+pub(crate) fn status_to_exit_code(status: &RawExitStatus) -> u8 {
+    if let Some(sig) = status.signal() {
+        return ((128i32 + sig) & 0xFF) as u8;
+    }
+
+    (status.code().unwrap_or(1) & 0xFF) as u8
+}
+
+/// How often the polling watchers look at the command: `--poll-interval`
+/// and its per-watcher overrides. Each only matters while its watcher is
+/// on.
+///
+/// ```
+/// use core::time::Duration;
+/// use procguard::{PollIntervals, ResourceLimits, RunConfig};
+///
+/// let config = RunConfig {
+///     limits: ResourceLimits {
+///         mem_bytes: Some(512 * 1024 * 1024),
+///         ..ResourceLimits::default()
+///     },
+///     poll: PollIntervals {
+///         memory: Duration::from_millis(20),
+///         ..PollIntervals::default()
+///     },
+///     ..RunConfig::default()
+/// };
+/// assert_eq!(config.poll.cpu, PollIntervals::default().cpu);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PollIntervals {
+    /// `--mem-limit` (default: 100ms).
+    pub memory: Duration,
+    /// `--cpu-percent` (default: 100ms).
+    pub cpu: Duration,
+    /// `--stdin-mode watch`. None (the default) looks only when the idle
+    /// deadline comes, and whenever something else wakes us.
+    pub stdin: Option<Duration>,
+    /// `--on-child-stop` (default: 100ms); only `--poll-interval` sets it.
+    pub child_stop: Duration,
+}
+
+impl Default for PollIntervals {
+    fn default() -> Self {
+        Self {
+            memory: DEFAULT_POLL_INTERVAL,
+            cpu: DEFAULT_POLL_INTERVAL,
+            stdin: None,
+            child_stop: DEFAULT_POLL_INTERVAL,
+        }
+    }
+}
+
+/* --poll-interval for every watcher, each watcher's own flag over it */
+fn parse_poll_intervals(args: &OwnedArgs) -> Result<PollIntervals> {
+    let parse = |flag: &str, value: &Option<String>| -> Result<Option<Duration>> {
+        let Some(value) = value else {
+            return Ok(None);
+        };
+        let interval = parse_duration(value)?;
+        if !(MIN_POLL_INTERVAL..=MAX_POLL_INTERVAL).contains(&interval) {
+            return Err(TimeoutError::InvalidOptions(format!(
+                "{} must be between {} and {}, not '{}'",
+                flag,
+                format_duration(MIN_POLL_INTERVAL),
+                format_duration(MAX_POLL_INTERVAL),
+                value
+            )));
+        }
+        Ok(Some(interval))
+    };
+    let all = parse("--poll-interval", &args.poll_interval)?;
+    Ok(PollIntervals {
+        memory: parse("--mem-poll-interval", &args.mem_poll_interval)?
+            .or(all)
+            .unwrap_or(DEFAULT_POLL_INTERVAL),
+        cpu: parse("--cpu-poll-interval", &args.cpu_poll_interval)?
+            .or(all)
+            .unwrap_or(DEFAULT_POLL_INTERVAL),
+        stdin: parse("--stdin-poll-interval", &args.stdin_poll_interval)?.or(all),
+        child_stop: all.unwrap_or(DEFAULT_POLL_INTERVAL),
+    })
+}
+
+/// Configuration for running a command with timeout.
+///
+/// Construct via struct literal or use [`RunConfig::default()`] as a base.
+/// All durations use wall-clock time by default (survives system sleep).
+///
+/// # Example
+///
+/// ```
+/// use procguard::{RunConfig, Signal, parse_duration, parse_signal};
+/// use std::time::Duration;
+///
+/// let config = RunConfig {
+///     timeout: parse_duration("30s").unwrap(),
+///     signal: parse_signal("INT").unwrap(),
+///     kill_after: Some(Duration::from_secs(5)),
+///     ..RunConfig::default()
+/// };
+/// assert_eq!(config.signal, Signal::SIGINT);
+/// ```
+///
+/// # Stability Note
+///
+/// New fields may be added in minor versions. Use `..RunConfig::default()`
+/// to ensure forward compatibility.
+pub struct RunConfig {
+    /// Maximum time before sending the timeout signal.
+    pub timeout: Duration,
+    /// Signal to send on timeout (default: SIGTERM).
+    pub signal: Signal,
+    /// Grace period before escalating to SIGKILL. `None` = no escalation.
+    /// `Some(Duration::ZERO)` skips `signal` entirely and sends SIGKILL at
+    /// the deadline.
+    pub kill_after: Option<Duration>,
+    /// If `true`, don't create a process group (child inherits parent's group).
+    pub foreground: bool,
+    /// Process group for the child. `None` derives it from `foreground`;
+    /// see [`RunConfig::process_group`].
+    pub pgroup: Option<ProcessGroup>,
+    /// Slice of a universal binary to run (`--arch`). `None` leaves the
+    /// choice to the system: ours when there is one.
+    pub arch: Option<Arch>,
+    /// Make the child's process group the terminal's foreground group while
+    /// it runs. Needs the child in its own group, so implies
+    /// [`ProcessGroup::New`] unless `pgroup` says otherwise.
+    pub tty_foreground: bool,
+    /// Save the terminal's settings before spawning and restore them (plus
+    /// an ANSI reset) once the child is gone, however it went.
+    pub restore_tty: bool,
+    /// QoS class for our own thread, set once the child is spawned. The
+    /// child keeps its default.
+    pub monitor_qos: Option<QosClass>,
+    /// Print signal diagnostics to stderr.
+    pub verbose: bool,
+    /// Suppress timeout's own warnings and diagnostics. Errors are still
+    /// printed, unless `quiet_errors`.
+    pub quiet: bool,
+    /// Suppress timeout's own errors too (`-qq`). The result still reports
+    /// them.
+    pub quiet_errors: bool,
+    /// What our stderr lines start with (`--tag`); `None` is "timeout".
+    pub tag: Option<String>,
+    /// Word the `verbose` diagnostics exactly as GNU timeout does, and
+    /// leave out the ones it doesn't print (gtimeout).
+    pub gnu_messages: bool,
+    /// Exit code when command times out (default: 124).
+    pub timeout_exit_code: u8,
+    /// On timeout, die of this signal instead of exiting with
+    /// `timeout_exit_code`, for a parent that only reads a signal death as
+    /// a timeout. Acted on by the binary once the result is out; for a
+    /// library caller it's only checked. Must end the process and not look
+    /// like a crash (see [`Signal::terminates`], [`Signal::is_crash`]).
+    pub kill_self_on_timeout: Option<Signal>,
+    /// Shell command to run before killing on timeout. `%p` is replaced with child PID.
+    pub on_timeout: Option<String>,
+    /// Shell command to run before killing for exceeding `limits.mem_bytes`,
+    /// `%p` replaced the same way. `None` runs `on_timeout` there too.
+    pub on_limit: Option<String>,
+    /// Time limit for the `on_timeout` hook and [`run_crash_hook`] (default: 5s).
+    pub on_timeout_limit: Duration,
+    /// Whether the `on_timeout` hook runs on top of the `kill_after` grace
+    /// or out of it; see [`RunConfig::hook_limit`].
+    pub hook_budget: HookBudget,
+    /// Start the `on_timeout` hook and signal the command without waiting
+    /// for it: the hook runs through the `kill_after` grace, which it
+    /// needs. The result still waits for the hook, up to its limit.
+    pub hook_async: bool,
+    /// Check after the signal that the command can act on it, and what to
+    /// do about one that's stopped; `None` doesn't check. Needs the
+    /// `proc-info` feature.
+    pub verify_signal_delivery: Option<StoppedPolicy>,
+    /// When the timeout comes due with a debugger attached, hold off for
+    /// up to this long while it stays attached; the command exiting
+    /// meanwhile completes the run. Needs the `proc-info` feature.
+    pub debugger_grace: Option<Duration>,
+    /// Time mode: `Wall` (includes sleep), `Active` (excludes sleep) or
+    /// `Hybrid` (wall deadline, plus [`active_min`](Self::active_min) awake).
+    pub confine: Confine,
+    /// With `Confine::Hybrid`, the awake time that must also pass before the
+    /// timeout fires.
+    pub active_min: Option<Duration>,
+    /// What the machine sleeping mid-run does to the timeout. However it's
+    /// set, the time asleep is recorded in [`EventLog::slept_ns`].
+    pub on_sleep: SleepPolicy,
+    /// Watch for the command stopping itself (SIGSTOP, SIGTSTP), every
+    /// `poll.child_stop`, and what that does to the timeout; `None`
+    /// doesn't look. The time stopped is recorded in
+    /// [`EventLog::child_stopped_ns`]. Needs the `proc-info` feature.
+    pub on_child_stop: Option<ChildStopPolicy>,
+    /// Stop the command when this local-time window closes, as a timeout
+    /// with reason [`TimeoutReason::WindowClosed`], and don't retry once
+    /// it's closed. The close is worked out from the local clock when each
+    /// attempt starts.
+    pub window_stop: Option<Window>,
+    /// Number of retries on timeout (0 = no retry).
+    pub retry_count: u32,
+    /// Delay between retries.
+    pub retry_delay: Duration,
+    /// Multiplier for delay each retry (1 = no backoff, 2 = exponential).
+    pub retry_backoff: u32,
+    /// Tries again at a spawn that failed with a transient errno (see
+    /// [`Errno::is_transient`](crate::errno::Errno::is_transient)), this
+    /// many times per attempt.
+    pub spawn_retry: u32,
+    /// Wait before the first spawn retry, doubled for each one after.
+    pub spawn_retry_delay: Duration,
+    /// Print heartbeat status to stderr at this interval.
+    pub heartbeat: Option<Duration>,
+    /// Timeout if stdin has no activity for this duration.
+    pub stdin_timeout: Option<Duration>,
+    /// How `stdin_timeout` watches stdin: read and discard it, look without
+    /// reading, or relay it through a pty (see [`StdinMode`]).
+    pub stdin_mode: StdinMode,
+    /// What the command reads instead of our stdin: `/dev/null`, nothing,
+    /// or a file (reopened for each attempt). `stdin_timeout` still
+    /// watches our own.
+    pub child_stdin: ChildStdin,
+    /// On timeout, write these bytes to the command's stdin and give it
+    /// `stdin_inject_wait` to exit before it's signaled. Its stdin then
+    /// comes from us: through the pty with `StdinMode::Pty`, else a pipe
+    /// we copy our stdin into.
+    pub stdin_inject: Option<Vec<u8>>,
+    /// How long the command gets to exit on `stdin_inject` (default: 1s).
+    pub stdin_inject_wait: Duration,
+    /// Resource limits (memory, CPU time).
+    pub limits: ResourceLimits,
+    /// CPU throttling configuration.
+    pub cpu_throttle: Option<CpuThrottleConfig>,
+    /// How often `limits.mem_bytes`, `cpu_throttle` and a watch-mode
+    /// `stdin_timeout` look at the command. `cpu_throttle` carries its own
+    /// copy of `cpu`, which is what it uses.
+    pub poll: PollIntervals,
+    /// After each spawn, write the child's PID and ours (one per line) to
+    /// this path, atomically. Removed once the child has been reaped.
+    pub pidfile: Option<String>,
+    /// Set this environment variable, for the command, to the timeout in
+    /// seconds (`"0"` with no timeout), so it can size its own.
+    pub export_remaining: Option<String>,
+    /// Keep this file at the seconds left before the timeout, rewritten
+    /// atomically every second while the command runs. Removed once it
+    /// has been reaped; not written with no timeout.
+    pub export_remaining_file: Option<String>,
+    /// Re-read this file every second while the command runs; its
+    /// `TIMEOUT_EXTEND` pushes the deadline back (see [`crate::extend`]).
+    pub extend_env_file: Option<String>,
+    /// The most `extend_env_file` can add to each attempt's timeout.
+    pub extend_max: Duration,
+    /// How `pidfile` and `export_remaining_file` are created: exact mode,
+    /// fsync before rename.
+    pub file_options: FileOptions,
+    /// Give each attempt a fresh directory under this one, as `TMPDIR`,
+    /// and remove it with its contents once the command is reaped.
+    pub temp_dir: Option<String>,
+    /// Keep the `temp_dir` of an attempt that didn't exit 0, and say where.
+    pub keep_temp_on_failure: bool,
+    /// After each spawn, write a `"status":"started"` JSON line with the
+    /// child's PID to this descriptor, ahead of the result.
+    pub pid_json_fd: Option<i32>,
+    /// Record the command's process tree as it forks and execs, into
+    /// [`EventLog::tree`]. macOS only, and only while a timeout is being
+    /// enforced; needs the `proc-info` feature.
+    pub proc_tree: bool,
+    /// Once the command is reaped, SIGKILL the processes in its tree that
+    /// escaped its process group (see [`crate::proctree::Proc::escaped`]).
+    /// Tracks the tree as `proc_tree` does, with the same requirements.
+    pub kill_escaped: bool,
+    /// Once the command is reaped, SIGKILL and reap what is left in its
+    /// process group (see [`crate::reaper`]), counted in
+    /// [`EventLog::orphans`]. Needs the command in a group of its own, and
+    /// the `proc-info` feature.
+    pub reap_orphans: bool,
+    /// More processes to signal at a timeout, alongside the command: the
+    /// same signal, and SIGKILL after the same grace period. What each
+    /// was sent is in [`EventLog::also_killed`].
+    pub also_kill: Option<AlsoKill>,
+    /// Put this in front of every line the command writes to stdout and
+    /// stderr. The command's output then goes through a pipe to us.
+    pub prefix_output: Option<String>,
+    /// Put the time in front of every line the command writes, per
+    /// stream, until that stream turns out not to be UTF-8.
+    pub timestamps: Option<Timestamps>,
+    /// Never rewrite the command's output: `prefix_output` and
+    /// `timestamps` are ignored.
+    pub raw_output: bool,
+    /// Give the command one pipe for both stdout and stderr, copied to our
+    /// stdout in the order it was written - `2>&1`, done by us.
+    pub merge_output: bool,
+    /// Let at most this many bytes per second of the command's output
+    /// through, stdout and stderr together; see `output_rate_policy`.
+    pub output_rate_limit: Option<u64>,
+    /// What happens to output above `output_rate_limit`.
+    pub output_rate_policy: RatePolicy,
+}
+
+impl Default for RunConfig {
+    /// Returns a default configuration with 30-second timeout and SIGTERM.
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            signal: Signal::SIGTERM,
+            kill_after: None,
+            foreground: false,
+            pgroup: None,
+            arch: None,
+            tty_foreground: false,
+            restore_tty: false,
+            monitor_qos: None,
+            verbose: false,
+            quiet: false,
+            quiet_errors: false,
+            tag: None,
+            gnu_messages: false,
+            timeout_exit_code: crate::error::exit_codes::TIMEOUT,
+            kill_self_on_timeout: None,
+            on_timeout: None,
+            on_limit: None,
+            on_timeout_limit: Duration::from_secs(5),
+            hook_budget: HookBudget::Separate,
+            hook_async: false,
+            verify_signal_delivery: None,
+            debugger_grace: None,
+            confine: Confine::Wall,
+            active_min: None,
+            on_sleep: SleepPolicy::Continue,
+            on_child_stop: None,
+            window_stop: None,
+            retry_count: 0,
+            retry_delay: Duration::ZERO,
+            retry_backoff: 1,
+            spawn_retry: 0,
+            spawn_retry_delay: DEFAULT_SPAWN_RETRY_DELAY,
+            heartbeat: None,
+            stdin_timeout: None,
+            stdin_mode: StdinMode::Consume,
+            child_stdin: ChildStdin::Inherit,
+            stdin_inject: None,
+            stdin_inject_wait: Duration::from_secs(1),
+            limits: ResourceLimits::default(),
+            cpu_throttle: None,
+            poll: PollIntervals::default(),
+            pidfile: None,
+            export_remaining: None,
+            export_remaining_file: None,
+            extend_env_file: None,
+            extend_max: Duration::ZERO,
+            file_options: FileOptions::default(),
+            temp_dir: None,
+            keep_temp_on_failure: false,
+            pid_json_fd: None,
+            proc_tree: false,
+            kill_escaped: false,
+            reap_orphans: false,
+            also_kill: None,
+            prefix_output: None,
+            timestamps: None,
+            raw_output: false,
+            merge_output: false,
+            output_rate_limit: None,
+            output_rate_policy: RatePolicy::Drop,
+        }
+    }
+}
+
+impl RunConfig {
+    /// Process group the child goes into.
+    ///
+    /// An explicit `pgroup` wins. Otherwise `foreground` means
+    /// [`ProcessGroup::Inherit`] - unless we're a session leader with no
+    /// terminal (a launchd job), where there's no TTY to share and
+    /// inheriting would only stop killpg() from reaching grandchildren.
+    #[must_use]
+    pub fn process_group(&self) -> ProcessGroup {
+        if let Some(pgroup) = self.pgroup {
+            return pgroup;
+        }
+        if !self.foreground || self.tty_foreground {
+            return ProcessGroup::New;
+        }
+        let ctx = ProcessContext::detect();
+        if ctx.session_leader && !ctx.has_tty {
+            ProcessGroup::New
+        } else {
+            ProcessGroup::Inherit
+        }
+    }
+
+    /// Build a config from parsed CLI args, the way the binary does. Fails
+    /// if the duration or an option value is bogus.
+    pub fn from_args(args: &OwnedArgs, duration_str: &str) -> Result<Self> {
+        let timeout = parse_duration(duration_str)?;
+        let signal = parse_signal(&args.signal)?;
+        let kill_after = args
+            .kill_after
+            .as_ref()
+            .map(|s| parse_duration(s))
+            .transpose()?;
+        let on_timeout_limit = parse_duration(&args.on_timeout_limit)?;
+        let kill_self_on_timeout = args
+            .kill_self_on_timeout
+            .as_ref()
+            .map(|s| parse_signal(s))
+            .transpose()?;
+        let active_min = args
+            .active_min
+            .as_ref()
+            .map(|s| parse_duration(s))
+            .transpose()?;
+
+        /* parse retry options */
+        let retry_count = args
+            .retry
+            .as_ref()
+            .map(|s| {
+                s.parse::<u32>()
+                    .map_err(|_| TimeoutError::Internal(format!("invalid retry count: '{}'", s)))
+            })
+            .transpose()?
+            .unwrap_or(0);
+
+        let retry_delay = args
+            .retry_delay
+            .as_ref()
+            .map(|s| parse_duration(s))
+            .transpose()?
+            .unwrap_or(Duration::ZERO);
+
+        /* parse backoff multiplier - strip 'x' suffix if present */
+        /* ensure >= 1 to prevent 0^n = 0 zeroing all delays */
+        let retry_backoff = args
+            .retry_backoff
+            .as_ref()
+            .map(|s| {
+                let s = s.trim_end_matches('x').trim_end_matches('X');
+                s.parse::<u32>().map_err(|_| {
+                    TimeoutError::Internal(format!(
+                        "invalid retry backoff: '{}' (use e.g., 2x)",
+                        args.retry_backoff.as_ref().unwrap()
+                    ))
+                })
+            })
+            .transpose()?
+            .unwrap_or(1)
+            .max(1);
+
+        let spawn_retry_delay = args
+            .spawn_retry_delay
+            .as_ref()
+            .map(|s| parse_duration(s))
+            .transpose()?
+            .unwrap_or(DEFAULT_SPAWN_RETRY_DELAY);
+
+        /* parse heartbeat interval */
+        let heartbeat = args
+            .heartbeat
+            .as_ref()
+            .map(|s| parse_duration(s))
+            .transpose()?;
+
+        /* parse stdin idle timeout */
+        let stdin_timeout = args
+            .stdin_timeout
+            .as_ref()
+            .map(|s| parse_duration(s))
+            .transpose()?;
+        let stdin_inject_wait = args
+            .stdin_inject_wait
+            .as_ref()
+            .map(|s| parse_duration(s))
+            .transpose()?
+            .unwrap_or(Duration::from_secs(1));
+        let debugger_grace = args
+            .debugger_grace
+            .as_ref()
+            .map(|s| parse_duration(s))
+            .transpose()?;
+
+        /* parse resource limits */
+        let mem_limit = args
+            .mem_limit
+            .as_ref()
+            .map(|s| parse_mem_limit(s))
+            .transpose()?;
+
+        let (cpu_time, cpu_time_hard) = args
+            .cpu_time
+            .as_ref()
+            .map(|s| parse_cpu_time_pair(s))
+            .transpose()?
+            .unzip();
+
+        let limits = ResourceLimits {
+            mem_bytes: mem_limit,
+            cpu_time,
+            cpu_time_hard: cpu_time_hard.flatten(),
+        };
+
+        /* parse CPU throttle percent */
+        let cpu_throttle = args
+            .cpu_percent
+            .as_ref()
+            .map(|s| parse_cpu_percent(s))
+            .transpose()?;
+
+        let poll = parse_poll_intervals(args)?;
+        let stdin_watched = stdin_timeout.is_some() && args.stdin_mode == StdinMode::Watch;
+        for (flag, given, watched, watcher) in [
+            (
+                "--mem-poll-interval",
+                &args.mem_poll_interval,
+                limits.mem_bytes.is_some(),
+                "--mem-limit",
+            ),
+            (
+                "--cpu-poll-interval",
+                &args.cpu_poll_interval,
+                cpu_throttle.is_some(),
+                "--cpu-percent",
+            ),
+            (
+                "--cpu-throttle-window",
+                &args.cpu_throttle_window,
+                cpu_throttle.is_some(),
+                "--cpu-percent",
+            ),
+            (
+                "--cpu-throttle-gain",
+                &args.cpu_throttle_gain,
+                cpu_throttle.is_some(),
+                "--cpu-percent",
+            ),
+            (
+                "--cpu-throttle-quantum",
+                &args.cpu_throttle_quantum,
+                cpu_throttle.is_some(),
+                "--cpu-percent",
+            ),
+            (
+                "--stdin-poll-interval",
+                &args.stdin_poll_interval,
+                stdin_watched,
+                "--stdin-timeout in watch mode",
+            ),
+        ] {
+            if given.is_some() && !watched {
+                return Err(TimeoutError::InvalidOptions(format!(
+                    "{} has nothing to poll without {}",
+                    flag, watcher
+                )));
+            }
+        }
+        if args.cpu_throttle_notify.is_some() && cpu_throttle.is_none() {
+            return Err(TimeoutError::InvalidOptions(
+                "--cpu-throttle-notify has nothing to tell without --cpu-percent".to_string(),
+            ));
+        }
+
+        /* --extend-env-file: at most the timeout again, unless told */
+        if args.extend_env_file.is_some() && is_no_timeout(&timeout) {
+            return Err(TimeoutError::InvalidOptions(
+                "--extend-env-file has no timeout to extend".to_string(),
+            ));
+        }
+        let extend_max = args
+            .extend_max
+            .as_ref()
+            .map(|s| parse_duration(s))
+            .transpose()?
+            .unwrap_or(timeout);
+
+        let window_ns = args
+            .cpu_throttle_window
+            .as_ref()
+            .map(|s| parse_duration(s))
+            .transpose()?
+            .map_or(throttle::DEFAULT_WINDOW_NS, duration_to_ns);
+        let gain_tenths = args
+            .cpu_throttle_gain
+            .as_ref()
+            .map(|s| throttle::parse_gain(s))
+            .transpose()?
+            .unwrap_or(throttle::DEFAULT_GAIN_TENTHS);
+        let quantum_ns = args
+            .cpu_throttle_quantum
+            .as_ref()
+            .map(|s| throttle::parse_quantum(s))
+            .transpose()?
+            .unwrap_or(throttle::DEFAULT_QUANTUM_NS);
+        let cpu_throttle = cpu_throttle.map(|percent| CpuThrottleConfig {
+            percent,
+            interval_ns: duration_to_ns(poll.cpu),
+            quantum_ns,
+            window_ns,
+            gain_tenths,
+            notify: args.cpu_throttle_notify.unwrap_or_default(),
+            verbose: args.verbose,
+        });
+
+        let config = Self {
+            timeout,
+            signal,
+            kill_after,
+            foreground: args.foreground,
+            pgroup: args.pgroup,
+            arch: args.arch,
+            tty_foreground: args.tty_foreground,
+            restore_tty: args.restore_tty,
+            monitor_qos: args.monitor_qos,
+            verbose: args.verbose,
+            quiet: args.quiet >= log::QUIET_WARNINGS,
+            quiet_errors: args.quiet >= log::QUIET_ALL,
+            tag: Some(args.log().tag().into()),
+            gnu_messages: args.personality == Personality::Gnu,
+            timeout_exit_code: args.timeout_exit_code.unwrap_or(exit_codes::TIMEOUT),
+            kill_self_on_timeout,
+            on_timeout: args.on_timeout.clone(),
+            on_limit: args.on_limit.clone(),
+            on_timeout_limit,
+            hook_budget: args.hook_budget,
+            verify_signal_delivery: args
+                .verify_signal_delivery
+                .then(|| args.on_stopped.unwrap_or_default()),
+            debugger_grace,
+            hook_async: args.hook_async,
+            confine: args.confine,
+            active_min,
+            on_sleep: args.on_sleep,
+            on_child_stop: args.on_child_stop,
+            window_stop: args.only_between.filter(|_| args.window_stop),
+            retry_count,
+            retry_delay,
+            retry_backoff,
+            spawn_retry: args.spawn_retry.unwrap_or(0),
+            spawn_retry_delay,
+            heartbeat,
+            stdin_timeout,
+            stdin_mode: args.stdin_mode,
+            child_stdin: args.child_stdin.clone(),
+            stdin_inject: args.stdin_inject.clone(),
+            stdin_inject_wait,
+            limits,
+            cpu_throttle,
+            poll,
+            pidfile: args.pidfile.clone(),
+            export_remaining: args.export_remaining.clone(),
+            export_remaining_file: args.export_remaining_file.clone(),
+            extend_env_file: args.extend_env_file.clone(),
+            extend_max,
+            file_options: FileOptions {
+                mode: args.file_mode,
+                fsync: !args.no_fsync,
+            },
+            /* bare --temp-dir: under $TMPDIR, as the command would have
+             * made its own */
+            temp_dir: args.temp_dir.as_ref().map(|parent| match parent.as_str() {
+                "" => crate::args::get_env(b"TMPDIR\0")
+                    .filter(|dir| !dir.is_empty())
+                    .unwrap_or_else(|| "/tmp".to_string()),
+                _ => parent.clone(),
+            }),
+            keep_temp_on_failure: args.keep_temp_on_failure,
+            pid_json_fd: args.print_pid.then(|| args.json_fd.unwrap_or(1)),
+            proc_tree: args.proc_tree,
+            kill_escaped: args.kill_escaped,
+            reap_orphans: args.reap_orphans,
+            also_kill: args.also_kill.clone(),
+            prefix_output: args.prefix_output.clone(),
+            timestamps: args.timestamps,
+            raw_output: args.raw_output,
+            merge_output: args.merge_output,
+            output_rate_limit: args.output_rate_limit,
+            output_rate_policy: args.output_rate_policy,
+        };
+        config.check()?;
+        Ok(config)
+    }
+
+    /// Sanity-check the options against each other: an error for
+    /// combinations that can't work, else a [`Warning`] for each that
+    /// works but likely not as meant ([`warnings`](Self::warnings)), for
+    /// the caller to report. [`from_args`](Self::from_args) and
+    /// [`run_command`] check only the error half.
+    ///
+    /// ```
+    /// use procguard::{RunConfig, Warning};
+    /// use std::time::Duration;
+    ///
+    /// let config = RunConfig {
+    ///     timeout: Duration::from_secs(60),
+    ///     heartbeat: Some(Duration::from_secs(600)),
+    ///     ..RunConfig::default()
+    /// };
+    /// let warnings = config.validate().unwrap();
+    /// assert!(matches!(warnings[..], [Warning::IntervalExceedsTimeout { .. }]));
+    /// ```
+    ///
+    /// # Errors
+    /// [`TimeoutError::InvalidOptions`] naming the first conflict found.
+    pub fn validate(&self) -> Result<alloc::vec::Vec<Warning>> {
+        self.check()?;
+        Ok(self.warnings())
+    }
+
+    /* the hard half of validate(), cheap enough to run before every attempt */
+    fn check(&self) -> Result<()> {
+        self.check_features()?;
+        /* tcsetpgrp needs a group of its own, in our session */
+        if self.tty_foreground && self.pgroup.is_some_and(|p| p != ProcessGroup::New) {
+            return Err(TimeoutError::InvalidOptions(
+                "--tty-foreground requires --pgroup new".to_string(),
+            ));
+        }
+        /* the sweep kills the whole group: ours, without a group of its own */
+        if self.reap_orphans && self.process_group() == ProcessGroup::Inherit {
+            return Err(TimeoutError::InvalidOptions(
+                "--reap-orphans needs the command in a process group of its own \
+                 (not --foreground or --pgroup inherit)"
+                    .to_string(),
+            ));
+        }
+        if self.stdin_mode != StdinMode::Consume && self.stdin_timeout.is_none() {
+            let mode = if self.stdin_mode == StdinMode::Pty {
+                "pty"
+            } else {
+                "watch"
+            };
+            return Err(TimeoutError::InvalidOptions(format!(
+                "--stdin-mode {mode} requires --stdin-timeout"
+            )));
+        }
+        /* the relay reads stdin, which watch promises not to */
+        if self.stdin_inject.is_some() && self.stdin_mode == StdinMode::Watch {
+            return Err(TimeoutError::InvalidOptions(
+                "--stdin-inject-on-timeout can't be used with --stdin-mode watch".to_string(),
+            ));
+        }
+        if let Some(sig) = self.kill_self_on_timeout
+            && (!sig.terminates() || sig.is_crash())
+        {
+            let why = if sig.is_crash() {
+                "would look like procguard crashed"
+            } else {
+                "doesn't end a process"
+            };
+            return Err(TimeoutError::InvalidOptions(format!(
+                "--kill-self-on-timeout: {} {why}",
+                signal_name(sig)
+            )));
+        }
+        /* both would be the command's fd 0 */
+        if self.child_stdin != ChildStdin::Inherit {
+            let relay = if self.stdin_inject.is_some() {
+                Some("--stdin-inject-on-timeout")
+            } else if self.stdin_mode == StdinMode::Pty {
+                Some("--stdin-mode pty")
+            } else {
+                None
+            };
+            if let Some(relay) = relay {
+                return Err(TimeoutError::InvalidOptions(format!(
+                    "--stdin can't be used with {relay}"
+                )));
+            }
+        }
+        /* setenv() would take "A=B" as A, or fail on "" */
+        if let Some(var) = &self.export_remaining
+            && (var.is_empty() || var.contains(['=', '\0']))
+        {
+            return Err(TimeoutError::InvalidOptions(format!(
+                "--export-remaining: invalid variable name '{var}'"
+            )));
+        }
+        /* nothing to share without a grace period */
+        if self.hook_budget == HookBudget::Shared && self.kill_after.is_none_or(|k| k.is_zero()) {
+            return Err(TimeoutError::InvalidOptions(
+                "--hook-budget shared requires a --kill-after grace period".to_string(),
+            ));
+        }
+        if self.on_limit.is_some() && self.limits.mem_bytes.is_none() {
+            return Err(TimeoutError::InvalidOptions(
+                "--on-limit requires --mem-limit".to_string(),
+            ));
+        }
+        if self.hook_async && self.kill_after.is_none_or(|k| k.is_zero()) {
+            return Err(TimeoutError::InvalidOptions(
+                "--hook-async requires a --kill-after grace period".to_string(),
+            ));
+        }
+        if self.active_min.is_some() != (self.confine == Confine::Hybrid) {
+            return Err(TimeoutError::InvalidOptions(
+                "--active-min goes with --confine hybrid, and only with it".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// How this run says things on stderr: `tag`, and `quiet` and
+    /// `quiet_errors` as a quiet level.
+    #[must_use]
+    pub fn log(&self) -> Logger<'_> {
+        let quiet = if self.quiet_errors {
+            log::QUIET_ALL
+        } else {
+            u8::from(self.quiet)
+        };
+        Logger::new(self.tag.as_deref().unwrap_or(log::DEFAULT_TAG), quiet)
+    }
+
+    /// The combinations that run but probably don't do what was meant, as
+    /// [`validate`](Self::validate) returns them. Intervals are weighed
+    /// against the longest the run can take: the timeout, or with
+    /// `--confine hybrid` the active minimum if that's longer.
+    #[must_use]
+    pub fn warnings(&self) -> alloc::vec::Vec<Warning> {
+        let mut found = alloc::vec::Vec::new();
+        if let Some(throttle) = &self.cpu_throttle
+            && throttle.stutters()
+        {
+            found.push(Warning::LowCpuPercent {
+                percent: throttle.percent.get(),
+                quantum_ms: throttle.quantum_ns / 1_000_000,
+            });
+        }
+        if is_no_timeout(&self.timeout) {
+            return found;
+        }
+        let timeout = self.timeout.max(self.active_min.unwrap_or_default());
+        let timeout_ms = duration_ms(timeout);
+
+        if self.on_timeout.is_some() && self.hook_limit() > self.timeout {
+            found.push(Warning::HookLimitExceedsTimeout {
+                limit_ms: duration_ms(self.hook_limit()),
+                timeout_ms: duration_ms(self.timeout),
+            });
+        }
+        let intervals = [
+            (Interval::Heartbeat, self.heartbeat),
+            (Interval::StdinTimeout, self.stdin_timeout),
+            (
+                Interval::MemoryCheck,
+                self.limits
+                    .mem_bytes
+                    .filter(|_| cfg!(feature = "proc-info"))
+                    .map(|_| self.poll.memory),
+            ),
+        ];
+        for (option, interval) in intervals {
+            if let Some(interval) = interval
+                && interval > timeout
+            {
+                found.push(Warning::IntervalExceedsTimeout {
+                    option,
+                    interval_ms: duration_ms(interval),
+                    timeout_ms,
+                });
+            }
+        }
+        found
+    }
+
+    /// The polling watchers this config turns on and how often each looks
+    /// at the command, one per line. Used by `--explain`; writes nothing
+    /// when none is on.
+    pub fn write_polling<W: core::fmt::Write>(&self, out: &mut W) -> core::fmt::Result {
+        let memory = self
+            .limits
+            .mem_bytes
+            .filter(|_| cfg!(feature = "proc-info"))
+            .map(|_| Some(self.poll.memory));
+        let cpu = self
+            .cpu_throttle
+            .map(|t| Some(Duration::from_nanos(t.interval_ns)));
+        let stdin = self
+            .stdin_timeout
+            .filter(|_| self.stdin_mode == StdinMode::Watch)
+            .map(|_| self.poll.stdin);
+        for (watcher, interval) in [
+            ("--mem-limit", memory),
+            ("--cpu-percent", cpu),
+            ("--stdin-timeout", stdin),
+            (
+                "--extend-env-file",
+                self.extend_env_file
+                    .as_ref()
+                    .map(|_| Some(Duration::from_nanos(crate::extend::REREAD_INTERVAL_NS))),
+            ),
+        ] {
+            match interval {
+                Some(Some(every)) => {
+                    writeln!(out, "  {:<18}every {}", watcher, format_duration(every))?;
+                }
+                Some(None) => writeln!(out, "  {:<18}at its deadline", watcher)?,
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// The longest [`run_with_retry`] can legitimately take with this
+    /// config, in awake time: every attempt's timeout, hooks, grace
+    /// periods and the delays between retries. `None` when nothing bounds
+    /// it - no timeout, a signal the command may ignore with no
+    /// `kill_after` to follow up, or [`ChildStopPolicy::Pause`], which
+    /// moves the deadline out for as long as the command stays stopped.
+    #[must_use]
+    pub fn lifetime_bound(&self) -> Option<Duration> {
+        if is_no_timeout(&self.timeout) || self.on_child_stop == Some(ChildStopPolicy::Pause) {
+            return None;
+        }
+        /* --hook-async: the result waits for the hook, which may outlast
+         * the command */
+        let mut stop = self.stop_bound()?;
+        if self.has_stop_hook() && self.hook_async {
+            stop = stop.max(self.hook_limit());
+        }
+        /* --extend-env-file may push each deadline back that far */
+        let extend = self
+            .extend_env_file
+            .as_ref()
+            .map_or(Duration::ZERO, |_| self.extend_max);
+        let attempt = self
+            .timeout
+            .saturating_add(extend)
+            .max(self.active_min.unwrap_or_default())
+            .saturating_add(self.debugger_grace.unwrap_or_default())
+            .saturating_add(stop)
+            .saturating_add(spawn_retry_delays(self));
+        let attempts = max_attempts(self);
+        (0..attempts - 1).try_fold(attempt.checked_mul(attempts)?, |total, n| {
+            total.checked_add(retry_delay(self, n))
+        })
+    }
+
+    /// The longest the command can outlive its timeout or memory limit:
+    /// the `on_timeout` or `on_limit` hook, the `stdin_inject` wait and the
+    /// `kill_after` grace, less whatever of them [`HookBudget::Shared`] or
+    /// `hook_async` overlaps. `None` for a signal the command may ignore
+    /// with no `kill_after` to follow up.
+    #[must_use]
+    pub fn stop_bound(&self) -> Option<Duration> {
+        let mut bound = match self.kill_after {
+            Some(grace) => grace,
+            None if self.signal == Signal::SIGKILL => Duration::ZERO,
+            None => return None,
+        };
+        if self.has_stop_hook() && self.hook_budget == HookBudget::Separate && !self.hook_async {
+            bound = bound.saturating_add(self.on_timeout_limit);
+        }
+        if self.stdin_inject.is_some() {
+            bound = bound.saturating_add(self.stdin_inject_wait);
+        }
+        Some(bound)
+    }
+
+    /* a hook runs before one stop or the other */
+    const fn has_stop_hook(&self) -> bool {
+        self.on_timeout.is_some() || self.on_limit.is_some()
+    }
+
+    /// How long the `on_timeout` or `on_limit` hook may run:
+    /// `on_timeout_limit`, and with [`HookBudget::Shared`] no longer than
+    /// the `kill_after` grace it comes out of.
+    #[must_use]
+    pub fn hook_limit(&self) -> Duration {
+        match (self.hook_budget, self.kill_after) {
+            (HookBudget::Shared, Some(grace)) => self.on_timeout_limit.min(grace),
+            _ => self.on_timeout_limit,
+        }
+    }
+
+    /* settings that need a subsystem this build left out (see the
+     * features in Cargo.toml) - refuse them rather than quietly not
+     * enforcing a limit */
+    fn check_features(&self) -> Result<()> {
+        let missing = if self.limits.cpu_time.is_some() && !cfg!(feature = "rlimit") {
+            Some(("--cpu-time", "rlimit"))
+        } else if self.limits.mem_bytes.is_some() && !cfg!(feature = "proc-info") {
+            Some(("--mem-limit", "proc-info"))
+        } else if self.cpu_throttle.is_some() && !cfg!(feature = "throttle") {
+            Some(("--cpu-percent", "throttle"))
+        } else if self.kill_escaped && !cfg!(feature = "proc-info") {
+            Some(("--kill-escaped", "proc-info"))
+        } else if self.reap_orphans && !cfg!(feature = "proc-info") {
+            Some(("--reap-orphans", "proc-info"))
+        } else if self.proc_tree && !cfg!(feature = "proc-info") {
+            Some(("--proc-tree", "proc-info"))
+        } else if self.verify_signal_delivery.is_some() && !cfg!(feature = "proc-info") {
+            Some(("--verify-signal-delivery", "proc-info"))
+        } else if self.debugger_grace.is_some() && !cfg!(feature = "proc-info") {
+            Some(("--debugger-grace", "proc-info"))
+        } else if self.on_child_stop.is_some() && !cfg!(feature = "proc-info") {
+            Some(("--on-child-stop", "proc-info"))
+        } else {
+            None
+        };
+        /* no NOTE_FORK to build it from - see proctree.rs */
+        if (self.proc_tree || self.kill_escaped) && !cfg!(target_os = "macos") {
+            let option = if self.kill_escaped {
+                "--kill-escaped"
+            } else {
+                "--proc-tree"
+            };
+            return Err(TimeoutError::InvalidOptions(format!(
+                "{option} is only available on macOS"
+            )));
+        }
+        /* another CPU takes Rosetta: elsewhere only our own slice runs */
+        if let Some(arch) = self.arch
+            && Some(arch) != Arch::native()
+            && !cfg!(target_os = "macos")
+        {
+            return Err(TimeoutError::InvalidOptions(format!(
+                "--arch {} is only available on macOS",
+                arch.as_str()
+            )));
+        }
+        match missing {
+            Some((option, feature)) => Err(TimeoutError::InvalidOptions(format!(
+                "{option} is not available: built without the '{feature}' feature"
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Spawn command and enforce timeout.
+///
+/// Errors: command not found, permission denied, spawn failed, signal failed.
+///
+/// ```
+/// use core::time::Duration;
+/// use procguard::{Outcome, RunConfig, RunResult, TimeoutError, run_command};
+///
+/// let config = RunConfig {
+///     timeout: Duration::from_millis(200),
+///     kill_after: Some(Duration::from_millis(100)),
+///     ..RunConfig::default()
+/// };
+/// let sleep = ["-c".to_string(), "sleep 5".to_string()];
+/// let result = run_command("sh", &sleep, &config).unwrap();
+/// assert!(matches!(result, RunResult::TimedOut { .. }));
+/// assert_eq!(result.exit_code(false, 124), 124);
+///
+/// let exit = ["-c".to_string(), "exit 3".to_string()];
+/// let result = run_command("sh", &exit, &config).unwrap();
+/// assert!(matches!(Outcome::from(&result), Outcome::Failed { code: 3 }));
+///
+/// let missing = run_command("no-such-command-here", &[], &config);
+/// assert!(matches!(missing, Err(TimeoutError::NotRunnable(_))));
+/// ```
+pub fn run_command(command: &str, args: &[String], config: &RunConfig) -> Result<RunResult> {
+    let argv = build_argv(command.as_bytes(), args).map_err(TimeoutError::from)?;
+    let mut events = EventLog::new();
+    let subscription = open_route(config.log(), &mut events);
+    let queue = open_kqueue()?;
+    run_command_recorded(&argv, 0, config, &mut events, subscription.as_ref(), &queue)
+}
+
+/* one kqueue per run, shared by every wait in it - see kq.rs */
+fn open_kqueue() -> Result<Kqueue> {
+    Kqueue::new().map_err(|err| TimeoutError::Internal(format!("kqueue failed: errno {err}")))
+}
+
+/* route: this run's subscription to forwarded signals, if any.
+ * queue: the run's kqueue, reused across attempts. attempt: 0-based */
+fn run_command_recorded(
+    argv: &[CString],
+    attempt: u32,
+    config: &RunConfig,
+    events: &mut EventLog,
+    route: Option<&Route>,
+    queue: &Kqueue,
+) -> Result<RunResult> {
+    /* library callers build RunConfig by hand, past from_args */
+    config.check()?;
+
+    /* put child in its own process group unless foreground mode */
+    let pgroup = config.process_group();
+    if config.foreground
+        && config.pgroup.is_none()
+        && pgroup.owns_group()
+        && config.verbose
+        && !config.quiet
+    {
+        crate::log_info!(
+            config.log(),
+            "no controlling terminal (launchd?), using own process group"
+        );
+    }
+
+    /* declared before the child and the foreground guard so it drops after
+     * both: the child is reaped and the terminal is ours again by then */
+    let _termios = if config.restore_tty {
+        TermiosGuard::save()
+    } else {
+        None
+    };
+
+    /* --prefix-output, --timestamps, --merge-output, --output-rate-limit:
+     * the command writes into our pipes */
+    let (mut output, child_ends) = match Interposer::open(config) {
+        Ok(Some((interposer, ends))) => (Some(interposer), Some(ends)),
+        Ok(None) => (None, None),
+        Err(errno) => {
+            return Err(TimeoutError::Internal(format!(
+                "can't create output pipes: errno {errno}"
+            )));
+        }
+    };
+    /* --stdin-mode pty: the command reads a pty we type its input into.
+     * --stdin-inject-on-timeout without one: a pipe we copy stdin into.
+     * after _termios, so our terminal's raw mode is undone first */
+    let relay = if config.stdin_mode == StdinMode::Pty && config.stdin_timeout.is_some() {
+        Some((StdinRelay::pty(), "pty"))
+    } else if config.stdin_inject.is_some() {
+        Some((StdinRelay::pipe(), "pipe"))
+    } else {
+        None
+    };
+    let mut stdin_relay = match relay {
+        Some((Ok(relay), _)) => Some(relay),
+        Some((Err(errno), what)) => {
+            return Err(TimeoutError::Internal(format!(
+                "can't open a {what} for stdin: errno {errno}"
+            )));
+        }
+        None => None,
+    };
+    /* --stdin: what the command reads instead */
+    let stdin_file = match &config.child_stdin {
+        ChildStdin::Null => Some(open_child_stdin("/dev/null")?),
+        ChildStdin::File(path) => Some(open_child_stdin(path)?),
+        ChildStdin::Inherit | ChildStdin::Close => None,
+    };
+    let mut dups = child_ends
+        .as_ref()
+        .map_or(&[][..], ChildEnds::dups)
+        .to_vec();
+    if let Some(relay) = &stdin_relay {
+        dups.push((relay.slave(), 0));
+    }
+    if let Some(fd) = stdin_file {
+        dups.push((fd, 0));
+    } else if config.child_stdin == ChildStdin::Close {
+        /* macOS fails the spawn on closing an fd that isn't open */
+        // SAFETY: fcntl takes no pointers
+        if unsafe { libc::fcntl(0, libc::F_GETFD) } >= 0 {
+            dups.push((-1, 0));
+        }
+    }
+
+    /* all before the spawn, so the command never looks too early */
+    let temp_dir = match &config.temp_dir {
+        Some(parent) => Some(TempDir::create(
+            parent,
+            attempt,
+            config.keep_temp_on_failure,
+            config.log(),
+        )?),
+        None => None,
+    };
+    if let Some(var) = &config.export_remaining {
+        export_remaining(var, config.timeout)?;
+    }
+    if config.cpu_throttle.is_some_and(|t| t.notify.sets_env()) {
+        // SAFETY: both strings are NUL-terminated; single-threaded, so nothing
+        // reads environ while setenv changes it
+        if unsafe { libc::setenv(c"TIMEOUT_THROTTLED".as_ptr(), c"1".as_ptr(), 1) } != 0 {
+            return Err(TimeoutError::Internal(format!(
+                "can't set {}: errno {}",
+                throttle::NOTIFY_ENV,
+                errno()
+            )));
+        }
+    }
+    let remaining_file = config
+        .export_remaining_file
+        .as_deref()
+        .filter(|_| !is_no_timeout(&config.timeout))
+        .and_then(|path| {
+            RemainingFile::write(
+                path,
+                duration_to_ns(config.timeout),
+                config.file_options,
+                config.log(),
+            )
+        });
+
+    /* before the command can orphan anything: it may exit as soon as
+     * it's spawned. held until the sweep below has reaped them */
+    let _subreaper = config.reap_orphans.then(reaper::become_subreaper).flatten();
+
+    /* --spawn-retry: a binary a build is still writing (ETXTBSY), or a
+     * process table that was full (EAGAIN), may do a moment later. a stop
+     * signal while we wait calls it off */
+    let mut spawn_retries = 0;
+    let mut interrupted = None;
+    let spawn_result = loop {
+        let spawned = if config.limits.is_empty() {
+            spawn_argv_dups(argv, pgroup, config.arch, &dups)
+        } else {
+            spawn_argv_with_limits_dups(argv, pgroup, &config.limits, config.arch, &dups)
+        };
+        let errno = match spawned {
+            Err(SpawnError::Spawn(errno))
+                if errno.is_transient() && spawn_retries < config.spawn_retry =>
+            {
+                errno
+            }
+            spawned => break spawned,
+        };
+        let delay = spawn_retry_delay(config, spawn_retries);
+        spawn_retries += 1;
+        if config.verbose && !config.quiet {
+            crate::log_info!(
+                config.log(),
+                "spawn failed: {}, retry {} of {} in {}ms",
+                errno,
+                spawn_retries,
+                config.spawn_retry,
+                duration_ms(delay)
+            );
+        }
+        events.record_spawn_retry(event_now(config.confine), errno);
+        interrupted = sit_out_delay(queue, delay, route, config, events);
+        if interrupted.is_some() {
+            break Err(SpawnError::Spawn(errno));
+        }
+    };
+    /* the command has its copies; a stream ends once those are closed */
+    drop(child_ends);
+    if let Some(relay) = &mut stdin_relay {
+        relay.close_slave();
+    }
+    if let Some(fd) = stdin_file {
+        // SAFETY: fd was opened above and is closed exactly once here
+        unsafe { libc::close(fd) };
+    }
+
+    if let Some(sig) = interrupted {
+        return Err(TimeoutError::Interrupted(sig));
+    }
+    let mut child = spawn_result.map_err(TimeoutError::from)?;
+    if config.also_kill.is_some() {
+        events.also_killed_mut();
+    }
+    events.record(
+        EventKind::Spawned,
+        event_now(config.confine),
+        u64::from(child.id()),
+    );
+
+    /* tell whoever is waiting for the pid, before anything can block */
+    let _pidfile = config
+        .pidfile
+        .as_deref()
+        .and_then(|path| PidFile::write(path, child.id(), config.file_options, config.log()));
+    if let Some(fd) = config.pid_json_fd {
+        write_started(fd, child.id(), attempt + 1, events.run_id(), config.log());
+    }
+
+    #[allow(clippy::cast_possible_wrap)]
+    let tracker = if config.proc_tree || config.kill_escaped {
+        let at = events.offset(event_now(config.confine));
+        Tracker::start(child.id() as i32, events.tree_mut(), at)
+    } else {
+        None
+    };
+
+    /* after spawning, so the child can't pick it up from us */
+    if let Some(class) = config.monitor_qos
+        && !qos::set_self(class)
+        && config.verbose
+        && !config.quiet
+    {
+        crate::log_info!(config.log(), "couldn't set QoS class {}", class.as_str());
+    }
+
+    /* hand over the terminal; the guard takes it back once we return,
+     * by which point the child has been reaped */
+    #[allow(clippy::cast_possible_wrap)]
+    let _tty = if config.tty_foreground {
+        let guard = ForegroundGuard::acquire(child.id() as i32);
+        if guard.is_none() && config.verbose && !config.quiet {
+            crate::log_info!(
+                config.log(),
+                "no terminal to hand over, --tty-foreground ignored"
+            );
+        }
+        guard
+    } else {
+        None
+    };
+
+    /* zero timeout = run forever. output still has to be copied while
+     * it runs, and a stdin relay fed, which takes the monitor */
+    let result = if is_no_timeout(&config.timeout) && output.is_none() && stdin_relay.is_none() {
+        let (status, rusage) = child.wait().map_err(TimeoutError::from)?;
+        completed(status, rusage, &config.limits)
+    } else {
+        monitor_with_timeout(
+            &mut child,
+            &argv[0].to_string_lossy(),
+            config,
+            events,
+            route,
+            queue,
+            tracker.as_ref(),
+            output.as_mut(),
+            stdin_relay.as_ref(),
+            remaining_file.as_ref(),
+        )?
+    };
+
+    /* the rest of the command's output, once it's gone */
+    if let Some(output) = &mut output {
+        output.finish(config.log(), events);
+    }
+
+    /* the --also-kill processes' grace period may outlast the command's */
+    finish_also(config, events);
+
+    /* what it left in its group, before the tree's escapees are looked
+     * for: a member killed here didn't escape */
+    if config.reap_orphans {
+        reap_orphans(child_group(config, child.id()), config, events);
+    }
+
+    /* whatever happened after the last wakeup - the grace period, the exits */
+    if let Some(tracker) = &tracker {
+        let at = events.offset(event_now(config.confine));
+        tracker.drain(events.tree_mut(), at);
+        report_escaped(tracker, child_group(config, child.id()), config, events, at);
+    }
+    record_exit(events, &result, config.confine);
+    if let Some(dir) = temp_dir {
+        dir.finish(&result);
+    }
+    Ok(result)
+}
+
+/* the process group the command was started in */
+fn child_group(config: &RunConfig, child: u32) -> i32 {
+    match config.process_group() {
+        // SAFETY: getpgrp has no preconditions
+        ProcessGroup::Inherit => unsafe { libc::getpgrp() },
+        #[allow(clippy::cast_possible_wrap)]
+        ProcessGroup::New | ProcessGroup::Session => child as i32,
+    }
+}
+
+/* --reap-orphans, after the command is reaped */
+fn reap_orphans(group: i32, config: &RunConfig, events: &mut EventLog) {
+    let orphans = reaper::sweep(group);
+    if orphans.swept > 0 && config.verbose && !config.quiet {
+        crate::log_info!(
+            config.log(),
+            "reaped {} process{} left in the command's group, {} still running and killed",
+            orphans.swept,
+            if orphans.swept == 1 { "" } else { "es" },
+            orphans.killed
+        );
+    }
+    events.record_orphans(orphans);
+}
+
+/* whatever of the tree outlived the command outside its group: the
+ * timeout's group signal never reached it. warn, and with --kill-escaped
+ * clean up */
+fn report_escaped(
+    tracker: &Tracker,
+    group: i32,
+    config: &RunConfig,
+    events: &mut EventLog,
+    at_ns: u64,
+) {
+    let count = events.tree_mut().mark_escaped(group);
+    if count == 0 {
+        return;
+    }
+    let killed = if config.kill_escaped {
+        tracker.kill_escaped(events.tree_mut(), at_ns)
+    } else {
+        0
+    };
+    events.warn(config.log(), Warning::ProcessesEscaped { count, killed });
+}
+
+/* --stdin null|file:PATH, opened read-only for the command. moved clear of
+ * 0-2 and close-on-exec, like our other private fds - the command gets its
+ * copy through the spawn's dup2 */
+fn open_child_stdin(path: &str) -> Result<i32> {
+    let failed = |errno| TimeoutError::StdinFileError(String::from(path), errno);
+    let cpath = CString::new(path).map_err(|_| failed(libc::EINVAL))?;
+    // SAFETY: cpath is NUL-terminated and outlives the call
+    let fd = unsafe { libc::open(cpath.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(failed(errno()));
+    }
+    // SAFETY: fd was just opened; F_DUPFD_CLOEXEC takes no pointers and
+    // returns a new fd >= 3 or -1
+    let moved = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 3) };
+    let err = errno();
+    // SAFETY: fd is the original, closed once here - the copy replaces it
+    unsafe { libc::close(fd) };
+    if moved < 0 {
+        return Err(failed(err));
+    }
+    Ok(moved)
+}
+
+/*
+ * --pidfile: "CHILD\nPROCGUARD\n", written atomically so a reader never
+ * sees half a pid. removed when dropped, after the child is reaped, on
+ * every way out of run_command_recorded - a pidfile naming a dead (and
+ * maybe reused) pid is worse than none. between retries there is no file.
+ */
+struct PidFile<'a>(&'a str);
+
+impl<'a> PidFile<'a> {
+    /* None (after saying why) if it couldn't be written - the command
+     * keeps running either way */
+    fn write(path: &'a str, child: u32, opts: FileOptions, log: Logger<'_>) -> Option<Self> {
+        let mut contents = crate::io::StackBuf::<24>::new();
+        // SAFETY: getpid has no preconditions
+        let ours = unsafe { libc::getpid() };
+        let _ = writeln!(contents, "{child}\n{ours}");
+        match safe_file::write_atomic(path, &[contents.as_bytes()], opts) {
+            Ok(()) => Some(Self(path)),
+            Err(errno) => {
+                crate::log_error!(log, "can't write pidfile '{}': errno {}", path, errno);
+                None
+            }
+        }
+    }
+}
+
+impl Drop for PidFile<'_> {
+    fn drop(&mut self) {
+        let _ = crate::io::remove_file(self.0);
+    }
+}
+
+/* seconds, to the millisecond and rounded down - "90", "2.5", "0.001" */
+fn write_secs(w: &mut impl core::fmt::Write, ns: u64) {
+    let secs = ns / 1_000_000_000;
+    let mut ms = ns % 1_000_000_000 / 1_000_000;
+    if ms == 0 {
+        let _ = write!(w, "{secs}");
+        return;
+    }
+    let mut width = 3;
+    while ms.is_multiple_of(10) {
+        ms /= 10;
+        width -= 1;
+    }
+    let _ = write!(w, "{secs}.{ms:0width$}");
+}
+
+/* --export-remaining: into our own environment, which spawn hands the
+ * command. the same value for every attempt - each gets the full timeout */
+fn export_remaining(var: &str, timeout: Duration) -> Result<()> {
+    let mut value = crate::io::StackBuf::<32>::new();
+    write_secs(&mut value, duration_to_ns(timeout));
+    value.write_bytes(b"\0");
+    /* check() ruled out '=' and NUL in the name */
+    let name = CString::new(var)
+        .map_err(|_| TimeoutError::Internal("invalid --export-remaining".to_string()))?;
+    // SAFETY: both strings are NUL-terminated; single-threaded, so nothing
+    // reads environ while setenv changes it
+    if unsafe { libc::setenv(name.as_ptr(), value.as_bytes().as_ptr().cast(), 1) } != 0 {
+        return Err(TimeoutError::Internal(format!(
+            "can't set {var}: errno {}",
+            errno()
+        )));
+    }
+    Ok(())
+}
+
+/*
+ * --export-remaining-file: the seconds left, as --export-remaining writes
+ * them, replaced atomically on each update so a reader never sees half a
+ * number. written before the spawn, then rewritten by the monitor loop
+ * every REMAINING_FILE_INTERVAL_NS; like the pidfile it's removed when
+ * dropped, after the child is reaped.
+ */
+struct RemainingFile<'a>(&'a str, FileOptions);
+
+impl<'a> RemainingFile<'a> {
+    /* None (after saying why) if it couldn't be written - the command
+     * keeps running either way */
+    fn write(path: &'a str, remaining_ns: u64, opts: FileOptions, log: Logger<'_>) -> Option<Self> {
+        let file = Self(path, opts);
+        match file.update(remaining_ns) {
+            Ok(()) => Some(file),
+            Err(errno) => {
+                crate::log_error!(log, "can't write '{}': errno {}", path, errno);
+                None
+            }
+        }
+    }
+
+    fn update(&self, remaining_ns: u64) -> core::result::Result<(), i32> {
+        let mut contents = crate::io::StackBuf::<32>::new();
+        write_secs(&mut contents, remaining_ns);
+        contents.write_bytes(b"\n");
+        safe_file::write_atomic(self.0, &[contents.as_bytes()], self.1)
+    }
+}
+
+impl Drop for RemainingFile<'_> {
+    fn drop(&mut self) {
+        let _ = crate::io::remove_file(self.0);
+    }
+}
+
+/*
+ * --temp-dir: a fresh directory per attempt, handed to the command as
+ * TMPDIR. named for us and the attempt ("procguard.PID.N.XXXXXX"), so one
+ * left behind says whose it was. removed with everything in it once the
+ * command is reaped, timeout or not; with --keep-temp-on-failure it stays
+ * when the attempt didn't exit 0, and an error return counts as that.
+ */
+struct TempDir<'a> {
+    path: String,
+    keep: bool,
+    log: Logger<'a>,
+}
+
+impl<'a> TempDir<'a> {
+    fn create(parent: &str, attempt: u32, keep_on_failure: bool, log: Logger<'a>) -> Result<Self> {
+        let failed = |errno| TimeoutError::TempDirError(String::from(parent), errno);
+        // SAFETY: getpid has no preconditions
+        let ours = unsafe { libc::getpid() };
+        let template = format!(
+            "{}/procguard.{ours}.{}.XXXXXX",
+            parent.trim_end_matches('/'),
+            attempt + 1
+        );
+        /* removed again (keep is false) if TMPDIR can't be set */
+        let mut dir = Self {
+            path: crate::io::make_temp_dir(&template).map_err(failed)?,
+            keep: false,
+            log,
+        };
+        let value = CString::new(dir.path.as_str()).map_err(|_| failed(libc::EINVAL))?;
+        // SAFETY: both strings are NUL-terminated; single-threaded, so nothing
+        // reads environ while setenv changes it
+        if unsafe { libc::setenv(c"TMPDIR".as_ptr(), value.as_ptr(), 1) } != 0 {
+            return Err(failed(errno()));
+        }
+        dir.keep = keep_on_failure;
+        Ok(dir)
+    }
+
+    /* the attempt is over: only a failed one keeps its directory */
+    fn finish(mut self, result: &RunResult) {
+        if let RunResult::Completed { status, .. } = result
+            && status.code() == Some(0)
+        {
+            self.keep = false;
+        }
+    }
+}
+
+impl Drop for TempDir<'_> {
+    fn drop(&mut self) {
+        if self.keep {
+            crate::log_info!(self.log, "kept temp dir '{}'", self.path);
+        } else if let Err(errno) = crate::io::remove_tree(&self.path) {
+            crate::log_error!(
+                self.log,
+                "can't remove temp dir '{}': errno {}",
+                self.path,
+                errno
+            );
+        }
+    }
+}
+
+/* --print-pid: {"schema_version":N,"run_id":"...","status":"started",
+ * "attempt":1,"pid":CHILD,"procguard_pid":OURS} on its own line, ahead of
+ * the result - one per attempt, numbered from 1 */
+fn write_started(
+    fd: i32,
+    child: u32,
+    attempt: u32,
+    run_id: Option<&crate::run_id::RunId>,
+    log: Logger<'_>,
+) {
+    /* room for the longest --run-id, escaped */
+    let mut w = crate::json::JsonWriter::on(crate::io::StackBuf::<512>::new());
+    // SAFETY: getpid has no preconditions
+    let ours = unsafe { libc::getpid() };
+    crate::run_id::begin_result(&mut w, run_id);
+    w.field_str("status", "started");
+    w.field_u64("attempt", u64::from(attempt));
+    w.field_u64("pid", u64::from(child));
+    w.field_u64("procguard_pid", u64::from(ours.unsigned_abs()));
+    w.end_object();
+    let line = w.finish();
+    if let Err(errno) = crate::io::write_all_vectored(fd, &[line.as_bytes(), b"\n"]) {
+        crate::log_error!(log, "can't write pid to fd {}: errno {}", fd, errno);
+    }
+}
+
+/* the command ended on its own. RLIMIT_CPU sends SIGXCPU at the soft
+ * limit and SIGKILL at the hard one: dying of either there is the
+ * --cpu-time limit, not a crash or someone's kill. short of the limit,
+ * someone else sent it */
+fn completed(status: RawExitStatus, rusage: ResourceUsage, limits: &ResourceLimits) -> RunResult {
+    let Some((soft, hard)) = limits.cpu_secs() else {
+        return RunResult::Completed { status, rusage };
+    };
+    let (signal, secs) = match status.signal().and_then(Signal::try_from_raw) {
+        Some(Signal::SIGXCPU) => (Signal::SIGXCPU, soft),
+        Some(Signal::SIGKILL) => (Signal::SIGKILL, hard),
+        _ => return RunResult::Completed { status, rusage },
+    };
+    let actual_ms = rusage
+        .user_time_ms()
+        .saturating_add(rusage.system_time_ms());
+    if !cpu_limit_reached(actual_ms, secs) {
+        return RunResult::Completed { status, rusage };
+    }
+    RunResult::CpuLimitExceeded {
+        signal,
+        status,
+        rusage,
+        limit_ms: secs.saturating_mul(1000),
+        actual_ms,
+    }
+}
+
+/* every path out of monitor_with_timeout reaps the child - log how it ended */
+fn record_exit(events: &mut EventLog, result: &RunResult, confine: Confine) {
+    let status = match result {
+        RunResult::Completed { status, .. } | RunResult::CpuLimitExceeded { status, .. } => {
+            Some(status)
+        }
+        RunResult::TimedOut { status, .. }
+        | RunResult::MemoryLimitExceeded { status, .. }
+        | RunResult::SignalForwarded { status, .. } => status.as_ref(),
+    };
+    let Some(status) = status else {
+        return;
+    };
+    if let Some(sig) = status.signal() {
+        events.record(EventKind::Killed, event_now(confine), event_value(sig));
+    } else if let Some(code) = status.code() {
+        events.record(EventKind::Exited, event_now(confine), event_value(code));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_result_exit_code_timeout() {
+        let result = RunResult::TimedOut {
+            signal: Signal::SIGTERM,
+            killed: false,
+            status: None,
+            rusage: None,
+            hook: None,
+            reason: TimeoutReason::WallClock,
+            child_state: ChildState::Unknown,
+        };
+
+        assert_eq!(result.exit_code(false, 124), 124);
+        assert_eq!(result.exit_code(true, 124), 143); /* 128 + 15 */
+    }
+
+    #[test]
+    fn test_run_result_exit_code_killed() {
+        let result = RunResult::TimedOut {
+            signal: Signal::SIGTERM,
+            killed: true,
+            status: None,
+            rusage: None,
+            hook: None,
+            reason: TimeoutReason::WallClock,
+            child_state: ChildState::Unknown,
+        };
+
+        assert_eq!(result.exit_code(false, 124), 124);
+        assert_eq!(result.exit_code(true, 124), 137); /* 128 + 9 */
+    }
+
+    #[test]
+    fn test_custom_timeout_exit_code() {
+        let result = RunResult::TimedOut {
+            signal: Signal::SIGTERM,
+            killed: false,
+            status: None,
+            rusage: None,
+            hook: None,
+            reason: TimeoutReason::WallClock,
+            child_state: ChildState::Unknown,
+        };
+
+        assert_eq!(result.exit_code(false, 42), 42);
+        assert_eq!(result.exit_code(false, 0), 0);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] /* spawns a process */
+    fn test_crash_signal() {
+        let config = RunConfig {
+            timeout: Duration::from_secs(10),
+            ..RunConfig::default()
+        };
+        let crash = ["-c".to_string(), "ulimit -c 0; kill -ABRT $$".to_string()];
+        let result = run_command("sh", &crash, &config).unwrap();
+        assert_eq!(result.crash_signal(), Some(Signal::SIGABRT));
+        assert_eq!(result.exit_code(false, 124), 134); /* 128 + 6 */
+
+        /* killed, but not a crash */
+        let term = ["-c".to_string(), "kill -TERM $$".to_string()];
+        let result = run_command("sh", &term, &config).unwrap();
+        assert_eq!(result.crash_signal(), None);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] /* spawns a process */
+    fn test_external_kill() {
+        let config = RunConfig {
+            timeout: Duration::from_secs(10),
+            ..RunConfig::default()
+        };
+        let run = |script: &str, config: &RunConfig| {
+            run_command("sh", &["-c".to_string(), script.to_string()], config).unwrap()
+        };
+        assert_eq!(
+            run("kill -KILL $$", &config).external_kill(),
+            Some(Signal::SIGKILL)
+        );
+        assert_eq!(run("exit 0", &config).external_kill(), None);
+        assert_eq!(
+            run("ulimit -c 0; kill -ABRT $$", &config).external_kill(),
+            None
+        );
+
+        /* our SIGTERM is ours; a SIGKILL we never escalated to isn't */
+        let config = RunConfig {
+            timeout: Duration::from_millis(200),
+            ..config
+        };
+        let result = run("sleep 10", &config);
+        assert!(matches!(result, RunResult::TimedOut { .. }));
+        assert_eq!(result.external_kill(), None);
+        let result = run("trap 'kill -KILL $$' TERM; sleep 10 & wait", &config);
+        assert!(matches!(result, RunResult::TimedOut { killed: false, .. }));
+        assert_eq!(result.external_kill(), Some(Signal::SIGKILL));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] /* spawns a process */
+    fn test_cpu_limit_result() {
+        let config = RunConfig {
+            timeout: Duration::from_secs(30),
+            limits: ResourceLimits {
+                cpu_time: Some(Duration::from_secs(1)),
+                ..ResourceLimits::default()
+            },
+            ..RunConfig::default()
+        };
+        /* SIGXCPU - or SIGKILL, linux's pick when soft and hard coincide */
+        let spin = [
+            "-c".to_string(),
+            "ulimit -c 0; while :; do :; done".to_string(),
+        ];
+        let result = run_command("sh", &spin, &config).unwrap();
+        assert!(
+            matches!(
+                result,
+                RunResult::CpuLimitExceeded {
+                    limit_ms: 1000,
+                    actual_ms: 950..,
+                    ..
+                }
+            ),
+            "{result:?}"
+        );
+        assert_eq!(result.exit_code(false, 124), 124);
+
+        /* a SIGXCPU well short of the limit is someone else's */
+        let config = RunConfig {
+            limits: ResourceLimits {
+                cpu_time: Some(Duration::from_secs(60)),
+                ..ResourceLimits::default()
+            },
+            ..config
+        };
+        let xcpu = ["-c".to_string(), "ulimit -c 0; kill -XCPU $$".to_string()];
+        let result = run_command("sh", &xcpu, &config).unwrap();
+        assert!(matches!(result, RunResult::Completed { .. }));
+        assert_eq!(result.exit_code(false, 124), 152); /* 128 + 24 */
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] /* spawns a process */
+    fn test_proc_tree() {
+        let config = RunConfig {
+            timeout: Duration::from_secs(10),
+            proc_tree: true,
+            ..RunConfig::default()
+        };
+        let args = ["-c".to_string(), "sleep 0.2; true".to_string()];
+        let mut events = EventLog::new();
+        let result = run_with_retry_events("sh", &args, &config, &mut events);
+        if cfg!(not(all(feature = "proc-info", target_os = "macos"))) {
+            assert!(result.is_err(), "--proc-tree accepted without NOTE_FORK");
+            return;
+        }
+        result.unwrap();
+        let tree = events.tree().as_slice();
+        assert_eq!(tree[0].name(), b"sh");
+        assert!(
+            tree.iter()
+                .any(|p| p.ppid == tree[0].pid && p.name() == b"sleep"),
+            "sleep not recorded as a child of sh"
+        );
+        assert!(tree.iter().all(|p| p.exited));
+    }
+
+    #[test]
+    fn test_kill_escaped() {
+        let config = RunConfig {
+            timeout: Duration::from_secs(10),
+            kill_escaped: true,
+            ..RunConfig::default()
+        };
+        /* a daemon in its own session, left behind when sh exits */
+        let script = "perl -MPOSIX -e 'setsid(); sleep 30' & sleep 0.3";
+        let args = ["-c".to_string(), script.to_string()];
+        let mut events = EventLog::new();
+        let result = run_with_retry_events("sh", &args, &config, &mut events);
+        if cfg!(not(all(feature = "proc-info", target_os = "macos"))) {
+            assert!(result.is_err(), "--kill-escaped accepted without NOTE_FORK");
+            return;
+        }
+        result.unwrap();
+        let escaped: alloc::vec::Vec<_> = events.tree().escaped().collect();
+        assert_eq!(escaped.len(), 1, "daemon not found escaped");
+        assert_eq!(escaped[0].name(), b"perl");
+        assert!(escaped[0].killed);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] /* spawns a process */
+    fn test_reap_orphans() {
+        let config = RunConfig {
+            timeout: Duration::from_secs(10),
+            reap_orphans: true,
+            ..RunConfig::default()
+        };
+        /* one job still running when sh exits, one long done */
+        let args = ["-c".to_string(), "sleep 30 & true & sleep 0.2".to_string()];
+        let mut events = EventLog::new();
+        let result = run_with_retry_events("sh", &args, &config, &mut events);
+        if cfg!(not(feature = "proc-info")) {
+            assert!(result.is_err(), "--reap-orphans accepted without proc-info");
+            return;
+        }
+        result.unwrap();
+        let orphans = events.orphans().expect("no sweep recorded");
+        assert_eq!(orphans.killed, 1, "{orphans:?}");
+        assert!(orphans.swept >= 1, "{orphans:?}");
+
+        let config = RunConfig {
+            pgroup: Some(ProcessGroup::Inherit),
+            ..config
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] /* proc_pidinfo is FFI */
+    fn test_child_state_probe_self() {
+        // SAFETY: getpid() always succeeds
+        let pid = unsafe { libc::getpid() };
+        assert_eq!(ChildState::probe(pid), ChildState::Running);
+    }
+
+    #[test]
+    fn test_validate() {
+        let secs = Duration::from_secs;
+        let config = || RunConfig {
+            timeout: secs(60),
+            heartbeat: Some(secs(600)),
+            stdin_timeout: Some(secs(30)),
+            on_timeout: Some("true".to_string()),
+            on_timeout_limit: secs(90),
+            ..RunConfig::default()
+        };
+        assert_eq!(
+            config().warnings(),
+            [
+                Warning::HookLimitExceedsTimeout {
+                    limit_ms: 90_000,
+                    timeout_ms: 60_000,
+                },
+                Warning::IntervalExceedsTimeout {
+                    option: Interval::Heartbeat,
+                    interval_ms: 600_000,
+                    timeout_ms: 60_000,
+                },
+            ]
+        );
+
+        /* the hybrid active minimum stretches the run; no timeout, nothing to exceed */
+        let hybrid = RunConfig {
+            confine: Confine::Hybrid,
+            active_min: Some(secs(900)),
+            ..config()
+        };
+        assert_eq!(hybrid.warnings().len(), 1);
+        let unlimited = RunConfig {
+            timeout: Duration::ZERO,
+            ..config()
+        };
+        assert!(unlimited.warnings().is_empty());
+        /* validate hands them back rather than printing them */
+        assert_eq!(config().validate().unwrap(), config().warnings());
+
+        let passthrough = RunConfig {
+            stdin_mode: StdinMode::Watch,
+            stdin_timeout: None,
+            ..RunConfig::default()
+        };
+        assert!(matches!(
+            passthrough.validate(),
+            Err(TimeoutError::InvalidOptions(_))
+        ));
+        for (sig, ok) in [
+            (Signal::SIGALRM, true),
+            (Signal::SIGSTOP, false),
+            (Signal::SIGSEGV, false),
+        ] {
+            let kill_self = RunConfig {
+                kill_self_on_timeout: Some(sig),
+                ..RunConfig::default()
+            };
+            assert_eq!(kill_self.check().is_ok(), ok, "{sig:?}");
+        }
+        let watched_inject = RunConfig {
+            stdin_mode: StdinMode::Watch,
+            stdin_inject: Some(b"quit\n".to_vec()),
+            ..config()
+        };
+        assert!(matches!(
+            watched_inject.check(),
+            Err(TimeoutError::InvalidOptions(_))
+        ));
+        let loose = RunConfig {
+            active_min: Some(secs(1)),
+            ..RunConfig::default()
+        };
+        assert!(loose.check().is_err());
+        for (var, ok) in [("JOB_TIMEOUT", true), ("", false), ("A=B", false)] {
+            let export = RunConfig {
+                export_remaining: Some(var.to_string()),
+                ..RunConfig::default()
+            };
+            assert_eq!(export.check().is_ok(), ok, "{var:?}");
+        }
+    }
+
+    #[test]
+    fn test_lifetime_bound() {
+        let secs = Duration::from_secs;
+        let config = RunConfig {
+            timeout: secs(10),
+            ..RunConfig::default()
+        };
+        /* TERM may be ignored and nothing follows it up */
+        assert_eq!(config.lifetime_bound(), None);
+        let config = RunConfig {
+            kill_after: Some(secs(2)),
+            ..config
+        };
+        assert_eq!(config.lifetime_bound(), Some(secs(12)));
+        let config = RunConfig {
+            on_timeout: Some("true".to_string()),
+            retry_count: 2,
+            retry_delay: secs(1),
+            retry_backoff: 2,
+            ..config
+        };
+        /* 3 attempts of 10 + 5 + 2, then 1s and 2s between them */
+        assert_eq!(config.lifetime_bound(), Some(secs(54)));
+        let config = RunConfig {
+            timeout: Duration::ZERO,
+            ..config
+        };
+        assert_eq!(config.lifetime_bound(), None);
+        let config = RunConfig {
+            timeout: secs(1),
+            signal: Signal::SIGKILL,
+            ..RunConfig::default()
+        };
+        assert_eq!(config.lifetime_bound(), Some(secs(1)));
+        /* a stop doesn't count against the timeout, and may last forever */
+        let config = RunConfig {
+            on_child_stop: Some(ChildStopPolicy::Fail),
+            ..config
+        };
+        assert_eq!(config.lifetime_bound(), Some(secs(1)));
+        let config = RunConfig {
+            on_child_stop: Some(ChildStopPolicy::Pause),
+            ..config
+        };
+        assert_eq!(config.lifetime_bound(), None);
+    }
+
+    #[test]
+    fn test_hook_budget_shared() {
+        let secs = Duration::from_secs;
+        let config = RunConfig {
+            timeout: secs(10),
+            kill_after: Some(secs(2)),
+            on_timeout: Some("true".to_string()),
+            ..RunConfig::default()
+        };
+        assert_eq!(config.hook_limit(), secs(5));
+        assert_eq!(config.stop_bound(), Some(secs(7)));
+        /* the hook comes out of the grace: cut off at 2s, 2s in all */
+        let config = RunConfig {
+            hook_budget: HookBudget::Shared,
+            ..config
+        };
+        assert_eq!(config.hook_limit(), secs(2));
+        assert_eq!(config.stop_bound(), Some(secs(2)));
+        assert_eq!(config.lifetime_bound(), Some(secs(12)));
+        let config = RunConfig {
+            on_timeout_limit: secs(1),
+            ..config
+        };
+        assert_eq!(config.hook_limit(), secs(1));
+        assert!(config.check().is_ok());
+
+        for kill_after in [None, Some(Duration::ZERO)] {
+            let config = RunConfig {
+                kill_after,
+                ..RunConfig {
+                    hook_budget: HookBudget::Shared,
+                    ..RunConfig::default()
+                }
+            };
+            assert!(matches!(
+                config.check(),
+                Err(TimeoutError::InvalidOptions(msg)) if msg.contains("--kill-after")
+            ));
+        }
+    }
+
+    #[test]
+    fn test_hook_async() {
+        let secs = Duration::from_secs;
+        /* the hook overlaps the grace: the command gets 2s, the result
+         * waits for the hook's 5s */
+        let config = RunConfig {
+            timeout: secs(10),
+            kill_after: Some(secs(2)),
+            on_timeout: Some("true".to_string()),
+            hook_async: true,
+            ..RunConfig::default()
+        };
+        assert!(config.check().is_ok());
+        assert_eq!(config.hook_limit(), secs(5));
+        assert_eq!(config.stop_bound(), Some(secs(2)));
+        assert_eq!(config.lifetime_bound(), Some(secs(15)));
+        let config = RunConfig {
+            hook_budget: HookBudget::Shared,
+            ..config
+        };
+        assert_eq!(config.lifetime_bound(), Some(secs(12)));
+
+        let config = RunConfig {
+            kill_after: None,
+            hook_budget: HookBudget::Separate,
+            ..config
+        };
+        assert!(matches!(
+            config.check(),
+            Err(TimeoutError::InvalidOptions(msg)) if msg.contains("--hook-async")
+        ));
+    }
+
+    #[test]
+    fn test_on_limit() {
+        let secs = Duration::from_secs;
+        let config = RunConfig {
+            timeout: secs(10),
+            kill_after: Some(secs(2)),
+            on_limit: Some("true".to_string()),
+            ..RunConfig::default()
+        };
+        assert!(matches!(
+            config.check(),
+            Err(TimeoutError::InvalidOptions(msg)) if msg.contains("--mem-limit")
+        ));
+        let config = RunConfig {
+            limits: ResourceLimits {
+                mem_bytes: Some(1 << 30),
+                ..ResourceLimits::default()
+            },
+            ..config
+        };
+        assert!(config.check().is_ok() || cfg!(not(feature = "proc-info")));
+        /* the hook runs ahead of the grace, as --on-timeout's does */
+        assert_eq!(config.stop_bound(), Some(secs(7)));
+    }
+
+    #[test]
+    fn test_write_secs() {
+        let secs = |ns| {
+            let mut buf = crate::io::StackBuf::<32>::new();
+            write_secs(&mut buf, ns);
+            String::from_utf8(buf.as_bytes().to_vec()).unwrap()
+        };
+        assert_eq!(secs(90_000_000_000), "90");
+        assert_eq!(secs(2_500_000_000), "2.5");
+        assert_eq!(secs(1_250_000_000), "1.25");
+        assert_eq!(secs(1_000_999), "0.001");
+        assert_eq!(secs(999_999), "0");
+    }
+}
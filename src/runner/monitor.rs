@@ -0,0 +1,1778 @@
+/*
+ * monitor.rs
+ *
+ * one attempt from spawn to reaped: the kqueue wait and everything it
+ * watches on the way - heartbeats, stdin, the memory limit, the throttle,
+ * signals to forward - then what the deadline leads to.
+ */
+
+use alloc::format;
+use core::time::Duration;
+
+use crate::args::{ChildStopPolicy, Confine, HookBudget, SleepPolicy, StdinMode};
+use crate::duration::{format_duration, is_no_timeout};
+use crate::errno::Errno;
+use crate::error::{Result, TimeoutError};
+use crate::events::{EventKind, EventLog};
+use crate::extend::{ExtendFile, REREAD_INTERVAL_NS};
+use crate::forward::Route;
+use crate::kq::{self, Kqueue};
+use crate::log::{Logger, Warning};
+use crate::output::Interposer;
+use crate::proc_info::{ProcStatus, ProcessStats};
+use crate::process::{RawChild, RawExitStatus, ResourceUsage, SpawnError};
+use crate::proctree::Tracker;
+use crate::run_id::RunId;
+use crate::signal::{Signal, signal_name};
+use crate::throttle::{CpuThrottleConfig, CpuThrottleState};
+use crate::time_math::{
+    advance_ns, deadline_reached, elapsed_ns, remaining_ns, time_to_idle_timeout,
+};
+use crate::tty::StdinRelay;
+use crate::window::local_sec_of_day;
+
+use super::clock::{
+    MAX_TIMER_NS, STARVED_LAG_NS, SleepWatch, active_left_ns, active_now_ns, duration_to_ns,
+    event_now, event_value, precise_now_ns, sched_now_ns, sleep_probe_ns,
+};
+use super::escalation::{
+    Escalation, escalation_state, kill_only, log_sending, send_signal_recorded, sigkill_and_wait,
+    signal_also, verify_delivery,
+};
+use super::hooks::{HookResult, HookWaitResult, RunningHook, record_hook_end, start_stop_hook};
+use super::{ChildState, RemainingFile, RunConfig, RunResult, TimeoutReason, completed};
+
+/* a poll due this close to a wake runs on that wake, so the throttle and
+ * the memory limit share one sample instead of waking twice per interval */
+const SAMPLE_COALESCE_NS: u64 = 5_000_000;
+
+/* how often --export-remaining-file is rewritten */
+const REMAINING_FILE_INTERVAL_NS: u64 = 1_000_000_000;
+
+/// `--cpu-percent` duty cycling for one attempt: the settings and where
+/// the cycle is.
+pub struct ThrottleContext {
+    /// The duty cycle asked for.
+    pub cfg: CpuThrottleConfig,
+    /// Whether the command is stopped now, and since when.
+    pub state: CpuThrottleState,
+}
+
+/* memory limit enforcement config */
+struct MemoryLimitConfig {
+    limit_bytes: u64,
+    check_interval_ns: u64,
+}
+
+/* --on-child-stop: where the look for the command stopping itself is */
+struct ChildStopConfig<'a> {
+    policy: ChildStopPolicy,
+    poll_ns: u64,
+    looked_ns: u64,          /* when we last looked */
+    stopped: bool,           /* what we saw then */
+    stopped_ns: u64,         /* how long it's been seen stopped, this stop */
+    log: Option<Logger<'a>>, /* with -v */
+}
+
+impl ChildStopConfig<'_> {
+    /* one look at the command: how long it's been stopped since the last
+     * one, all of it when it's stopped now, since the stop began somewhere
+     * in between. None: it just stopped, and the policy is fail */
+    fn look(&mut self, pid: i32, now_ns: u64, events: &mut EventLog) -> Option<u64> {
+        let stopped = crate::proc_info::get_process_status(pid) == Some(ProcStatus::Stopped);
+        let was_stopped = core::mem::replace(&mut self.stopped, stopped);
+        if !stopped {
+            if was_stopped {
+                let stopped_ms = self.stopped_ns / 1_000_000;
+                events.record(EventKind::ChildContinued, now_ns, stopped_ms);
+                if let Some(log) = self.log {
+                    crate::log_info!(log, "command continued after {}ms stopped", stopped_ms);
+                }
+                self.stopped_ns = 0;
+            }
+            return Some(0);
+        }
+        if !was_stopped {
+            events.record(EventKind::ChildStopped, now_ns, 0);
+            if let Some(log) = self.log {
+                crate::log_info!(log, "command stopped (pid {})", pid);
+            }
+            if self.policy == ChildStopPolicy::Fail {
+                return None;
+            }
+        }
+        let since_ns = now_ns.saturating_sub(self.looked_ns);
+        self.stopped_ns = self.stopped_ns.saturating_add(since_ns);
+        events.add_child_stopped(since_ns);
+        Some(since_ns)
+    }
+}
+
+/* the child's rusage, read at most once per wake and shared by every
+ * check that polls it on that wake */
+struct WakeSample {
+    pid: i32,
+    stats: Option<Option<ProcessStats>>,
+}
+
+impl WakeSample {
+    const fn new(pid: i32) -> Self {
+        Self { pid, stats: None }
+    }
+
+    fn get(&mut self) -> Option<ProcessStats> {
+        *self
+            .stats
+            .get_or_insert_with(|| crate::proc_info::read_process_stats(self.pid))
+    }
+}
+
+/*
+ * main timeout logic using kqueue. kernel wakes us on process exit
+ * or timer expiry - zero CPU while waiting.
+ */
+#[allow(clippy::too_many_arguments)]
+pub(super) fn monitor_with_timeout(
+    child: &mut RawChild,
+    command: &str,
+    config: &RunConfig,
+    events: &mut EventLog,
+    route: Option<&Route>,
+    queue: &Kqueue,
+    tracker: Option<&Tracker>,
+    mut output: Option<&mut Interposer>,
+    stdin_relay: Option<&StdinRelay>,
+    remaining_file: Option<&RemainingFile>,
+) -> Result<RunResult> {
+    #[allow(clippy::cast_possible_wrap)]
+    let pid = child.id() as i32;
+    /* posix_spawn is back once the exec is done; after fork + exec (or
+     * `arch`) it may not be yet, so heartbeats and the exit look again */
+    note_translated(events, pid);
+    let start_ns = precise_now_ns(config.confine)?;
+    /* no timeout: only here to copy output (see run_command_recorded) */
+    let timeout = if is_no_timeout(&config.timeout) {
+        Duration::MAX
+    } else {
+        config.timeout
+    };
+
+    /* set up CPU throttle state if enabled (cfg!: lets the optimizer drop
+     * the throttle entirely from builds without it) */
+    let mut throttle_ctx = if cfg!(feature = "throttle")
+        && let Some(cfg) = config.cpu_throttle
+    {
+        match CpuThrottleState::new(pid, start_ns) {
+            Ok(state) => Some(ThrottleContext { cfg, state }),
+            Err(e) => {
+                if !config.quiet {
+                    crate::log_info!(config.log(), "warning: CPU throttle disabled ({})", e);
+                }
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    /* how far stopping it has got; every wait that reaps it ends here */
+    let mut stage = Escalation::Running;
+
+    /* build heartbeat config if enabled */
+    let heartbeat_config = config.heartbeat.map(|interval| HeartbeatConfig {
+        interval_ns: duration_to_ns(interval),
+        quiet: config.quiet,
+        pid,
+        start_ns,
+    });
+
+    /* build stdin timeout config if enabled */
+    let stdin_timeout_config = config.stdin_timeout.map(|d| StdinTimeoutConfig {
+        timeout_ns: duration_to_ns(d),
+        last_activity_ns: start_ns,
+        mode: config.stdin_mode,
+        poll_ns: config.poll.stdin.map_or(u64::MAX, duration_to_ns),
+        looked_ns: start_ns,
+    });
+
+    /* build memory limit config if enabled */
+    let memory_limit_config = config
+        .limits
+        .mem_bytes
+        .filter(|_| cfg!(feature = "proc-info"))
+        .map(|limit_bytes| MemoryLimitConfig {
+            limit_bytes,
+            check_interval_ns: duration_to_ns(config.poll.memory),
+        });
+
+    /* build the --on-child-stop watch if enabled */
+    let child_stop_config = config
+        .on_child_stop
+        .filter(|_| cfg!(feature = "proc-info"))
+        .map(|policy| ChildStopConfig {
+            policy,
+            poll_ns: duration_to_ns(config.poll.child_stop),
+            looked_ns: start_ns,
+            stopped: false,
+            stopped_ns: 0,
+            log: config.verbose.then(|| config.log()),
+        });
+    if child_stop_config.is_some() {
+        events.add_child_stopped(0);
+    }
+
+    /* --window-stop: when the window closes, by the local clock now */
+    let window_close_ns = config
+        .window_stop
+        .and_then(|w| Some(w.until_close(local_sec_of_day()?)))
+        .map(|secs| advance_ns(event_now(Confine::Wall), u64::from(secs) * 1_000_000_000));
+
+    /* --extend-env-file: each attempt starts from the timeout as given */
+    let mut extend = config
+        .extend_env_file
+        .as_deref()
+        .map(|path| ExtendFile::new(path, config.extend_max, config.log()));
+
+    /* wait for exit or timeout */
+    let exit_result = wait_with_kqueue(
+        config.log(),
+        child,
+        pid,
+        timeout,
+        config.confine,
+        config.on_sleep,
+        config.active_min,
+        window_close_ns,
+        heartbeat_config,
+        stdin_timeout_config,
+        stdin_relay,
+        remaining_file,
+        extend.as_mut(),
+        throttle_ctx.as_mut(),
+        memory_limit_config,
+        child_stop_config,
+        route,
+        queue,
+        tracker,
+        None,
+        output.as_deref_mut(),
+        events,
+    )?;
+
+    /* the throttle is done with this attempt: grace periods run unthrottled */
+    if let Some(ref ctx) = throttle_ctx {
+        let stats = ctx.state.stats();
+        events.record_throttle(&stats);
+        if config.verbose {
+            crate::log_info!(
+                config.log(),
+                "cpu throttle: stopped {} times for {}ms of {}ms, at {}.{}% CPU (limit {}%)",
+                stats.stops,
+                stats.stopped_ns / 1_000_000,
+                stats.wall_ns / 1_000_000,
+                stats.cpu_percent_tenths() / 10,
+                stats.cpu_percent_tenths() % 10,
+                ctx.cfg.percent.get()
+            );
+        }
+    }
+
+    /* a debugger attached at the deadline: --debugger-grace holds off */
+    let mut traced = false;
+    let mut debugger_waited = Duration::ZERO;
+    let exit_result = match exit_result {
+        WaitResult::TimedOut(reason) => {
+            let look = crate::proc_info::is_traced(pid);
+            events.record_traced(look);
+            traced = look == Some(true);
+            match config.debugger_grace.filter(|_| traced) {
+                Some(grace) => {
+                    if let Some(ref mut ctx) = throttle_ctx {
+                        ctx.state.resume();
+                    }
+                    let (result, waited) = wait_out_debugger(
+                        child,
+                        pid,
+                        grace,
+                        config,
+                        route,
+                        queue,
+                        &mut output,
+                        events,
+                    )?;
+                    debugger_waited = waited;
+                    result.unwrap_or(WaitResult::TimedOut(reason))
+                }
+                None => WaitResult::TimedOut(reason),
+            }
+        }
+        result => result,
+    };
+
+    let trigger = match exit_result {
+        WaitResult::Exited(status, rusage) => {
+            /* process already reaped - mark as exited to prevent PID recycling issues */
+            mark_reaped(&mut stage, &mut throttle_ctx);
+            return Ok(completed(status, rusage, &config.limits));
+        }
+        WaitResult::ReceivedSignal(sig) => {
+            /* We received SIGTERM/SIGINT/SIGHUP - forward to child and exit */
+            if config.verbose && !config.quiet {
+                crate::log_info!(
+                    config.log(),
+                    "forwarding signal {} to command",
+                    signal_name(sig)
+                );
+            }
+            /* resume if throttle had it stopped - prevents deadlock */
+            if let Some(ref mut ctx) = throttle_ctx {
+                ctx.state.resume();
+            }
+            events.record(
+                EventKind::SignalReceived,
+                event_now(config.confine),
+                event_value(sig.as_raw()),
+            );
+            send_signal_recorded(pid, sig, config, events)?;
+            /* wait for child - extract rusage even if wait returns error (child exited) */
+            let (status, rusage) = match child.wait() {
+                Ok((s, r)) => (Some(s), Some(r)),
+                Err(_) => (None, None), /* child already reaped or wait failed */
+            };
+            /* mark process exited to prevent PID recycling issues in Drop */
+            mark_reaped(&mut stage, &mut throttle_ctx);
+            return Ok(RunResult::SignalForwarded {
+                signal: sig,
+                status,
+                rusage,
+            });
+        }
+        WaitResult::MemoryLimitExceeded {
+            limit_bytes,
+            actual_bytes,
+        } => {
+            if config.verbose && !config.quiet {
+                crate::log_info!(
+                    config.log(),
+                    "memory limit exceeded ({} bytes > {} bytes limit)",
+                    actual_bytes,
+                    limit_bytes
+                );
+            }
+            events.record(
+                EventKind::MemoryLimit,
+                event_now(config.confine),
+                actual_bytes,
+            );
+            /* before the hook or a signal gets to it */
+            events.record_at_timeout(crate::proc_info::snapshot(pid));
+            Trigger::MemoryLimit {
+                limit_bytes,
+                actual_bytes,
+            }
+        }
+        WaitResult::TimedOut(reason) => {
+            if config.verbose && !config.quiet && !config.gnu_messages {
+                let reason_str = match reason {
+                    TimeoutReason::WallClock => "wall clock",
+                    TimeoutReason::StdinIdle => "stdin idle",
+                    TimeoutReason::SystemSleep => "system sleep",
+                    TimeoutReason::WindowClosed => "time window closing",
+                    TimeoutReason::ChildStopped => "command stopping itself",
+                };
+                crate::log_info!(config.log(), "triggered by {}", reason_str);
+            }
+            let reason_value = match reason {
+                TimeoutReason::WallClock => 0,
+                TimeoutReason::StdinIdle => 1,
+                TimeoutReason::SystemSleep => 2,
+                TimeoutReason::WindowClosed => 3,
+                TimeoutReason::ChildStopped => 4,
+            };
+            events.record(EventKind::TimedOut, event_now(config.confine), reason_value);
+            /* before the hook or a signal gets to it */
+            events.record_at_timeout(crate::proc_info::snapshot(pid));
+            note_translated(events, pid);
+            if events.translated() == Some(true) {
+                events.warn(
+                    config.log(),
+                    Warning::TranslatedTimeout {
+                        timeout_ms: u64::try_from(config.timeout.as_millis()).unwrap_or(u64::MAX),
+                    },
+                );
+            }
+            if traced {
+                events.warn(
+                    config.log(),
+                    Warning::ChildTraced {
+                        waited_ms: u64::try_from(debugger_waited.as_millis()).unwrap_or(u64::MAX),
+                    },
+                );
+            }
+            Trigger::Timeout(reason)
+        }
+    };
+
+    /* from here on the timeout and the memory limit go the same way */
+
+    /* Run the on-timeout or on-limit hook if specified. --hook-async: leave
+     * it running, the waits below watch it along with the command */
+    let mut async_hook = None;
+    let hook_result = match trigger.hook(config) {
+        Some((name, cmd, which)) => {
+            events.record(EventKind::HookStarted, event_now(config.confine), which);
+            match start_stop_hook(name, cmd, pid, config) {
+                Ok(hook) if config.hook_async => {
+                    async_hook = Some(hook);
+                    None
+                }
+                Ok(mut hook) => {
+                    let hook = hook.finish(queue);
+                    record_hook_end(events, &hook, config.confine);
+                    Some(hook)
+                }
+                Err(failed) => {
+                    record_hook_end(events, &failed, config.confine);
+                    Some(failed)
+                }
+            }
+        }
+        None => None,
+    };
+
+    let result = 'stop: {
+        /* --stdin-inject-on-timeout: ask through stdin first, signal if that
+         * doesn't do it. not over a memory limit: it's still growing */
+        if let Some(bytes) = &config.stdin_inject
+            && let Some(relay) = stdin_relay
+            && matches!(trigger, Trigger::Timeout(_))
+        {
+            if relay.inject(bytes) {
+                events.record(
+                    EventKind::StdinInjected,
+                    event_now(config.confine),
+                    bytes.len() as u64,
+                );
+                if config.verbose && !config.quiet {
+                    crate::log_info!(
+                        config.log(),
+                        "wrote {} bytes to command's stdin",
+                        bytes.len()
+                    );
+                }
+                /* a stopped process can't read it */
+                if let Some(ref mut ctx) = throttle_ctx {
+                    ctx.state.resume();
+                }
+                let inject_result = wait_with_kqueue(
+                    config.log(),
+                    child,
+                    pid,
+                    config.stdin_inject_wait,
+                    config.confine,
+                    SleepPolicy::Continue, /* already stopping it */
+                    None,                  /* runs on the wall clock, like the grace period */
+                    None,                  /* already stopping it */
+                    None,
+                    None,
+                    None,
+                    None,
+                    None, /* past extending */
+                    None, /* throttle disabled - process needs to read its input */
+                    None,
+                    None,
+                    route,
+                    queue,
+                    None,
+                    async_hook.as_mut(),
+                    output.as_deref_mut(),
+                    events,
+                )?;
+                match inject_result {
+                    WaitResult::Exited(status, rusage) => {
+                        mark_reaped(&mut stage, &mut throttle_ctx);
+                        break 'stop Ok(trigger.result(
+                            config.signal,
+                            false,
+                            (status, rusage),
+                            hook_result,
+                            ChildState::ExitedOnInput,
+                        ));
+                    }
+                    WaitResult::ReceivedSignal(sig) => {
+                        break 'stop forward_and_wait(
+                            child,
+                            pid,
+                            sig,
+                            config,
+                            events,
+                            &mut stage,
+                            &mut throttle_ctx,
+                        );
+                    }
+                    WaitResult::TimedOut(_) | WaitResult::MemoryLimitExceeded { .. } => {
+                        /* still running - on to the signal */
+                    }
+                }
+            } else if config.verbose && !config.quiet {
+                crate::log_info!(config.log(), "stdin already closed, nothing written to it");
+            }
+        }
+
+        /* zombie already? then it exited on its own right at the deadline */
+        let before = ChildState::probe(pid);
+
+        /* -k 0: skip the soft signal. SIGKILL works on a stopped process,
+         * so no need to resume the throttle first. */
+        if kill_only(config) {
+            stage.advance(Escalation::Killed);
+            signal_also(Signal::SIGKILL, config, events);
+            let (status, rusage) = sigkill_and_wait(child, command, pid, config, events)?;
+            mark_reaped(&mut stage, &mut throttle_ctx);
+            break 'stop Ok(trigger.result(
+                Signal::SIGKILL,
+                true,
+                (status, rusage),
+                hook_result,
+                ChildState::from_exit(&status, before),
+            ));
+        }
+
+        /* time's up, send the signal */
+        log_sending(config, config.signal, command, "");
+
+        /* resume if throttle had it stopped - prevents deadlock.
+         * sending SIGTERM to a SIGSTOP'd process creates deadlock if child
+         * intercepts the signal (can't run handler while stopped). */
+        if let Some(ref mut ctx) = throttle_ctx {
+            ctx.state.resume();
+        }
+
+        send_signal_recorded(pid, config.signal, config, events)?;
+        stage.advance(Escalation::SoftSignaled);
+        signal_also(config.signal, config, events);
+        /* --on-child-stop fail: it's stopped, so the signal waits until
+         * something continues it - without -k, nothing would. unless
+         * --verify-signal-delivery, which does this its own way */
+        if matches!(trigger, Trigger::Timeout(TimeoutReason::ChildStopped))
+            && config.verify_signal_delivery.is_none()
+        {
+            send_signal_recorded(pid, Signal::SIGCONT, config, events)?;
+        }
+        let stuck = verify_delivery(pid, config.signal, config, events)?;
+
+        /* if --kill-after, give it a grace period then escalate to SIGKILL.
+         * --hook-budget shared: the hook already had its part of it, unless
+         * it's --hook-async and still having it. none for a command stuck
+         * stopped */
+        if let Some(kill_after) = config.kill_after.or(stuck.then_some(Duration::ZERO)) {
+            stage.advance(Escalation::Grace);
+            let kill_after = match (config.hook_budget, &hook_result) {
+                _ if stuck => Duration::ZERO,
+                (HookBudget::Shared, Some(hook)) => {
+                    kill_after.saturating_sub(Duration::from_millis(hook.elapsed_ms))
+                }
+                _ => kill_after,
+            };
+            /* no heartbeat, stdin timeout, or throttle during grace period.
+             * throttle disabled because re-SIGSTOP would prevent signal handler. */
+            let grace_result = wait_with_kqueue(
+                config.log(),
+                child,
+                pid,
+                kill_after,
+                config.confine,
+                SleepPolicy::Continue, /* already stopping it */
+                None,                  /* grace period runs on the wall clock */
+                None,                  /* already stopping it */
+                None,
+                None,
+                None,
+                None,
+                None, /* past extending */
+                None, /* throttle disabled - process needs to run signal handler */
+                None, /* no memory limit during grace period */
+                None,
+                route,
+                queue,
+                None,
+                async_hook.as_mut(),
+                output,
+                events,
+            )?;
+
+            match grace_result {
+                WaitResult::Exited(status, rusage) => {
+                    /* mark process exited to prevent PID recycling issues */
+                    mark_reaped(&mut stage, &mut throttle_ctx);
+                    break 'stop Ok(trigger.result(
+                        config.signal,
+                        false,
+                        (status, rusage),
+                        hook_result,
+                        ChildState::from_exit(&status, before),
+                    ));
+                }
+                WaitResult::ReceivedSignal(sig) => {
+                    /* Forward signal during grace period */
+                    break 'stop forward_and_wait(
+                        child,
+                        pid,
+                        sig,
+                        config,
+                        events,
+                        &mut stage,
+                        &mut throttle_ctx,
+                    );
+                }
+                WaitResult::TimedOut(_) | WaitResult::MemoryLimitExceeded { .. } => {
+                    /* Continue to SIGKILL below - shouldn't happen during grace but handle it */
+                }
+            }
+
+            /* still alive? SIGKILL it */
+            let child_state = escalation_state(pid, config);
+            log_sending(config, Signal::SIGKILL, command, "");
+
+            /* resume if throttle had it stopped - prevents deadlock */
+            if let Some(ref mut ctx) = throttle_ctx {
+                ctx.state.resume();
+            }
+
+            stage.advance(Escalation::Killed);
+            send_signal_recorded(pid, Signal::SIGKILL, config, events)?;
+            signal_also(Signal::SIGKILL, config, events);
+
+            let reaped = child.wait().map_err(TimeoutError::from)?;
+
+            /* mark process exited to prevent PID recycling issues */
+            mark_reaped(&mut stage, &mut throttle_ctx);
+
+            Ok(trigger.result(config.signal, true, reaped, hook_result, child_state))
+        } else {
+            /* no kill-after, just wait for it to die */
+            let (status, rusage) = child.wait().map_err(TimeoutError::from)?;
+
+            /* mark process exited to prevent PID recycling issues */
+            mark_reaped(&mut stage, &mut throttle_ctx);
+
+            Ok(trigger.result(
+                config.signal,
+                false,
+                (status, rusage),
+                hook_result,
+                ChildState::from_exit(&status, before),
+            ))
+        }
+    };
+
+    /* --hook-async: the hook may have ended in the grace period, or still
+     * be running. either way its outcome goes with the command's */
+    let Some(mut hook) = async_hook else {
+        return result;
+    };
+    let mut result = result?;
+    let ended = !hook.running();
+    let outcome = hook.finish(queue);
+    if !ended {
+        record_hook_end(events, &outcome, config.confine);
+    }
+    if let RunResult::TimedOut { hook, .. } | RunResult::MemoryLimitExceeded { hook, .. } =
+        &mut result
+    {
+        *hook = Some(outcome);
+    }
+    Ok(result)
+}
+
+/* what set the stop off: the deadline, or --mem-limit. either way the
+ * rest - hook, signal, grace period, SIGKILL - goes the same */
+#[derive(Clone, Copy)]
+enum Trigger {
+    Timeout(TimeoutReason),
+    MemoryLimit { limit_bytes: u64, actual_bytes: u64 },
+}
+
+impl Trigger {
+    /* the hook to run first, as (name, command, HookStarted value):
+     * --on-limit for the memory limit, --on-timeout otherwise or failing it */
+    fn hook(self, config: &RunConfig) -> Option<(&'static str, &str, u64)> {
+        match (self, &config.on_limit) {
+            (Self::MemoryLimit { .. }, Some(cmd)) => Some(("on-limit", cmd, 2)),
+            _ => config
+                .on_timeout
+                .as_deref()
+                .map(|cmd| ("on-timeout", cmd, 0)),
+        }
+    }
+
+    /* the result for a command stopped this way and reaped */
+    fn result(
+        self,
+        signal: Signal,
+        killed: bool,
+        (status, rusage): (RawExitStatus, ResourceUsage),
+        hook: Option<HookResult>,
+        child_state: ChildState,
+    ) -> RunResult {
+        match self {
+            Self::Timeout(reason) => RunResult::TimedOut {
+                signal,
+                killed,
+                status: Some(status),
+                rusage: Some(rusage),
+                hook,
+                reason,
+                child_state,
+            },
+            Self::MemoryLimit {
+                limit_bytes,
+                actual_bytes,
+            } => RunResult::MemoryLimitExceeded {
+                signal,
+                killed,
+                status: Some(status),
+                rusage: Some(rusage),
+                limit_bytes,
+                actual_bytes,
+                hook,
+                child_state,
+            },
+        }
+    }
+}
+
+/* a signal came in while we were stopping it: it goes to the command
+ * instead, and the command's exit is the result */
+fn forward_and_wait(
+    child: &mut RawChild,
+    pid: i32,
+    sig: Signal,
+    config: &RunConfig,
+    events: &mut EventLog,
+    stage: &mut Escalation,
+    throttle_ctx: &mut Option<ThrottleContext>,
+) -> Result<RunResult> {
+    if config.verbose && !config.quiet {
+        crate::log_info!(
+            config.log(),
+            "forwarding signal {} to command",
+            signal_name(sig)
+        );
+    }
+    /* resume if throttle had it stopped - prevents deadlock */
+    if let Some(ctx) = throttle_ctx {
+        ctx.state.resume();
+    }
+    events.record(
+        EventKind::SignalReceived,
+        event_now(config.confine),
+        event_value(sig.as_raw()),
+    );
+    send_signal_recorded(pid, sig, config, events)?;
+    /* wait for child - extract rusage even if wait returns error (child exited) */
+    let (status, rusage) = match child.wait() {
+        Ok((s, r)) => (Some(s), Some(r)),
+        Err(_) => (None, None), /* child already reaped or wait failed */
+    };
+    /* mark process exited to prevent PID recycling issues */
+    mark_reaped(stage, throttle_ctx);
+    Ok(RunResult::SignalForwarded {
+        signal: sig,
+        status,
+        rusage,
+    })
+}
+
+/* the child's been waited for, so its pid may be reused: nothing may
+ * signal it again, the throttle's SIGCONT on drop included */
+fn mark_reaped(stage: &mut Escalation, throttle_ctx: &mut Option<ThrottleContext>) {
+    stage.advance(Escalation::Reaped);
+    if let Some(ctx) = throttle_ctx {
+        ctx.state.mark_process_exited();
+    }
+}
+
+/*
+ * What happened when we waited on the process.
+ */
+enum WaitResult {
+    Exited(RawExitStatus, ResourceUsage),
+    TimedOut(TimeoutReason), /* what triggered: wall clock or stdin idle */
+    MemoryLimitExceeded {
+        limit_bytes: u64,
+        actual_bytes: u64,
+    },
+    /// Received a signal that should be forwarded to the child
+    ReceivedSignal(Signal),
+}
+
+/* stdin timeout config for wait_with_kqueue */
+struct StdinTimeoutConfig {
+    timeout_ns: u64,       /* stdin idle timeout in nanoseconds */
+    last_activity_ns: u64, /* timestamp of last stdin activity */
+    mode: StdinMode,       /* consume, watch or pty */
+    poll_ns: u64,          /* watch: look this often, u64::MAX = at the deadline */
+    looked_ns: u64,        /* watch: when we last looked */
+}
+
+/* heartbeat config for wait_with_kqueue */
+struct HeartbeatConfig {
+    interval_ns: u64, /* heartbeat interval in nanoseconds, 0 = disabled */
+    quiet: bool,      /* suppress output */
+    pid: i32,         /* child pid for message */
+    start_ns: u64,    /* when we started, for elapsed calculation */
+}
+
+/* print heartbeat status message to stderr */
+/* format elapsed time as "Xm Ys" or "Xs" - integer math only, no floats */
+/* feed the lag stats; warn the first time a wakeup crosses STARVED_LAG_NS */
+fn record_wake_lag(log: Logger<'_>, events: &mut EventLog, lag_ns: u64) {
+    let was_starved = events.lag().max_ns >= STARVED_LAG_NS;
+    events.record_lag(lag_ns);
+    if !was_starved && lag_ns >= STARVED_LAG_NS {
+        events.warn(
+            log,
+            Warning::MonitorStarved {
+                lag_ms: lag_ns / 1_000_000,
+            },
+        );
+    }
+}
+
+/* how often --debugger-grace looks whether the debugger is still there */
+const DEBUGGER_POLL: Duration = Duration::from_millis(250);
+
+/* the timeout came due with a debugger attached: wait while it stays,
+ * up to grace. Some(result): the command exited, or we got a signal to
+ * forward, meanwhile. also how long we waited */
+#[allow(clippy::too_many_arguments)]
+fn wait_out_debugger(
+    child: &mut RawChild,
+    pid: i32,
+    grace: Duration,
+    config: &RunConfig,
+    route: Option<&Route>,
+    queue: &Kqueue,
+    output: &mut Option<&mut Interposer>,
+    events: &mut EventLog,
+) -> Result<(Option<WaitResult>, Duration)> {
+    let mut waited = Duration::ZERO;
+    let mut result = None;
+    while waited < grace && crate::proc_info::is_traced(pid) == Some(true) {
+        if waited.is_zero() && config.verbose && !config.quiet {
+            crate::log_info!(
+                config.log(),
+                "debugger attached at the deadline; waiting up to {} for it to detach",
+                format_duration(grace)
+            );
+        }
+        let step = (grace - waited).min(DEBUGGER_POLL);
+        match wait_with_kqueue(
+            config.log(),
+            child,
+            pid,
+            step,
+            config.confine,
+            SleepPolicy::Continue,
+            None, /* runs on the wall clock, like the grace period */
+            None, /* already past the timeout */
+            None,
+            None,
+            None,
+            None,
+            None, /* past extending */
+            None, /* throttle disabled - the debugger has it */
+            None,
+            None,
+            route,
+            queue,
+            None,
+            None,
+            output.as_deref_mut(),
+            events,
+        )? {
+            WaitResult::TimedOut(_) | WaitResult::MemoryLimitExceeded { .. } => waited += step,
+            done => {
+                result = Some(done);
+                break;
+            }
+        }
+    }
+    if !waited.is_zero() || result.is_some() {
+        events.record(
+            EventKind::DebuggerWait,
+            event_now(config.confine),
+            u64::try_from(waited.as_millis()).unwrap_or(u64::MAX),
+        );
+    }
+    Ok((result, waited))
+}
+
+/* one look at whether pid runs under Rosetta, into the run's record */
+fn note_translated(events: &mut EventLog, pid: i32) {
+    events.record_translated(crate::proc_info::is_translated(pid));
+}
+
+fn print_heartbeat(log: Logger<'_>, elapsed_ns: u64, pid: i32, missed: u64, events: &EventLog) {
+    let elapsed_secs = elapsed_ns / 1_000_000_000;
+    let mins = elapsed_secs / 60;
+    let secs = elapsed_secs % 60;
+    /* it's why the run is slow, more often than not */
+    let rosetta = if events.translated() == Some(true) {
+        ", under Rosetta"
+    } else {
+        ""
+    };
+    /* for joining up the stderr of parallel shards */
+    let (run, run_id) = events
+        .run_id()
+        .and_then(RunId::given)
+        .map_or(("", ""), |id| (", run ", id));
+
+    if missed > 0 {
+        crate::log_info!(
+            log,
+            "heartbeat: {}m {}s elapsed, command still running (pid {}{}{}{}), {} missed",
+            mins,
+            secs,
+            pid,
+            rosetta,
+            run,
+            run_id,
+            missed
+        );
+    } else if mins > 0 {
+        crate::log_info!(
+            log,
+            "heartbeat: {}m {}s elapsed, command still running (pid {}{}{}{})",
+            mins,
+            secs,
+            pid,
+            rosetta,
+            run,
+            run_id
+        );
+    } else {
+        crate::log_info!(
+            log,
+            "heartbeat: {}s elapsed, command still running (pid {}{}{}{})",
+            secs,
+            pid,
+            rosetta,
+            run,
+            run_id
+        );
+    }
+}
+
+/* stdin poll result - distinguishes readable from idle from closed */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StdinPollResult {
+    Readable, /* data available */
+    Idle,     /* no data, still open */
+    Eof,      /* pipe closed / hangup */
+}
+
+/* check stdin status without consuming data */
+#[inline]
+fn stdin_poll_status() -> StdinPollResult {
+    let mut pfd = libc::pollfd {
+        fd: 0,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+
+    // SAFETY: poll with nfds=1 and timeout=0 is safe for any fd value.
+    let ret = unsafe { libc::poll(&raw mut pfd, 1, 0) };
+
+    if ret <= 0 {
+        return StdinPollResult::Idle;
+    }
+
+    /* POLLHUP = writer closed the pipe, no more data coming */
+    if (pfd.revents & libc::POLLHUP) != 0 && (pfd.revents & libc::POLLIN) == 0 {
+        return StdinPollResult::Eof;
+    }
+
+    if (pfd.revents & libc::POLLIN) != 0 {
+        return StdinPollResult::Readable;
+    }
+
+    StdinPollResult::Idle
+}
+
+/* --stdin-mode watch on a terminal: how long ago it was last read, from
+ * its access time - what w(1) shows as idle. the child reading what was
+ * typed is the activity; None if stdin isn't a terminal */
+fn stdin_tty_idle_ns() -> Option<u64> {
+    // SAFETY: isatty takes no pointers
+    if unsafe { libc::isatty(0) } != 1 {
+        return None;
+    }
+    // SAFETY: zeroed stat struct is valid for fstat call below
+    let mut stat: libc::stat = unsafe { core::mem::zeroed() };
+    // SAFETY: fstat with valid stat buffer
+    if unsafe { libc::fstat(0, &raw mut stat) } != 0 {
+        return None;
+    }
+    let read_ms = u64::try_from(stat.st_atime)
+        .unwrap_or(0)
+        .saturating_mul(1000)
+        .saturating_add(u64::try_from(stat.st_atime_nsec).unwrap_or(0) / 1_000_000);
+    let idle_ms = crate::events::realtime_ms().saturating_sub(read_ms);
+    Some(idle_ms.saturating_mul(1_000_000))
+}
+
+/* watch mode's look at stdin: input waiting, or on a terminal, read since
+ * we last looked, both count as activity. false once stdin is closed */
+fn watch_stdin(stdin_cfg: &mut StdinTimeoutConfig, now_ns: u64) -> bool {
+    stdin_cfg.looked_ns = now_ns;
+    match stdin_poll_status() {
+        StdinPollResult::Readable => stdin_cfg.last_activity_ns = now_ns,
+        StdinPollResult::Eof => return false,
+        StdinPollResult::Idle => {
+            if let Some(idle_ns) = stdin_tty_idle_ns() {
+                let read_ns = now_ns.saturating_sub(idle_ns);
+                stdin_cfg.last_activity_ns = stdin_cfg.last_activity_ns.max(read_ns);
+            }
+        }
+    }
+    true
+}
+
+/*
+ * wait using kqueue - EVFILT_PROC for exit, EVFILT_TIMER with NOTE_NSECONDS
+ * for nanosecond precision, and optionally EVFILT_READ for signal pipe.
+ * Direct libc because nix kqueue API keeps changing.
+ *
+ * With heartbeat: wakes at min(remaining_timeout, next_heartbeat), prints
+ * status message on heartbeat intervals, continues waiting until timeout.
+ *
+ * With stdin timeout: adds EVFILT_READ on fd 0, resets timer on activity,
+ * triggers if stdin is idle for the specified duration.
+ *
+ * With active_min (--confine hybrid): the timeout waits for that much awake
+ * time too, so it's max(wall remaining, active remaining) away.
+ *
+ * With window_close_ns (--window-stop): a second deadline on the wall clock,
+ * whatever the confine mode, since the window is clock time.
+ *
+ * With remaining_file: rewrites it with the time left every second.
+ *
+ * With extend (--extend-env-file): re-reads it every second, and at the
+ * deadline, moving the deadline by however much its extension changed.
+ *
+ * With child_stop (--on-child-stop): looks at the command's run state
+ * every poll, and at the deadline. a stop the throttle made is ours, not
+ * the command's, so it isn't looked for then.
+ */
+#[allow(clippy::too_many_arguments)]
+fn wait_with_kqueue(
+    log: Logger<'_>,
+    child: &mut RawChild,
+    pid: i32,
+    timeout: Duration,
+    confine: Confine,
+    on_sleep: SleepPolicy,
+    active_min: Option<Duration>,
+    window_close_ns: Option<u64>,
+    heartbeat: Option<HeartbeatConfig>,
+    mut stdin_timeout: Option<StdinTimeoutConfig>,
+    stdin_relay: Option<&StdinRelay>,
+    remaining_file: Option<&RemainingFile>,
+    mut extend: Option<&mut ExtendFile>,
+    mut throttle: Option<&mut ThrottleContext>,
+    memory_limit: Option<MemoryLimitConfig>,
+    mut child_stop: Option<ChildStopConfig<'_>>,
+    route: Option<&Route>,
+    queue: &Kqueue,
+    tracker: Option<&Tracker>,
+    mut hook: Option<&mut RunningHook<'_>>,
+    mut output: Option<&mut Interposer>,
+    events: &mut EventLog,
+) -> Result<WaitResult> {
+    let signal_fd = route.map(Route::fd);
+    let tree_fd = tracker.map(Tracker::fd);
+    let [out_fd, err_fd] = output.as_ref().map_or([None; 2], |o| o.fds());
+    let start_ns = precise_now_ns(confine)?;
+    let timeout_ns = duration_to_ns(timeout);
+
+    /* --output-rate-policy block: the pipes go unwatched until then */
+    let mut output_resume_ns = output
+        .as_deref_mut()
+        .and_then(Interposer::paused_ns)
+        .map_or(u64::MAX, |ns| advance_ns(start_ns, ns));
+    let output_watched = output_resume_ns == u64::MAX;
+
+    /* throttle tracking */
+    let throttle_interval_ns = throttle
+        .as_ref()
+        .map(|t| t.cfg.interval_ns)
+        .unwrap_or(u64::MAX);
+    let mut next_throttle_ns = if throttle_interval_ns < u64::MAX {
+        throttle
+            .as_ref()
+            .map(|t| advance_ns(t.state.last_wall_ns, t.state.next_check_ns(&t.cfg)))
+            .unwrap_or(u64::MAX)
+    } else {
+        u64::MAX
+    };
+
+    /* memory limit tracking */
+    let memory_check_interval_ns = memory_limit
+        .as_ref()
+        .map_or(u64::MAX, |m| m.check_interval_ns);
+    let mut next_memory_check_ns = if memory_check_interval_ns < u64::MAX {
+        advance_ns(start_ns, memory_check_interval_ns)
+    } else {
+        u64::MAX
+    };
+
+    /* heartbeat tracking: tick k fires at start_ns + k * interval, on that
+     * cadence however late the last one ran - ticks that came due while we
+     * were held up are skipped and counted, not bunched up or drifted */
+    let heartbeat_interval_ns = heartbeat.as_ref().map_or(0, |h| h.interval_ns);
+    let mut heartbeat_tick: u64 = 1;
+    let mut next_heartbeat_ns = if heartbeat_interval_ns > 0 {
+        advance_ns(start_ns, heartbeat_interval_ns)
+    } else {
+        u64::MAX /* disabled */
+    };
+
+    /* --export-remaining-file: written at spawn, then every interval */
+    let mut next_remaining_ns = if remaining_file.is_some() {
+        advance_ns(start_ns, REMAINING_FILE_INTERVAL_NS)
+    } else {
+        u64::MAX
+    };
+
+    /* --extend-env-file: read before the first deadline check */
+    let mut next_extend_ns = if extend.is_some() { start_ns } else { u64::MAX };
+
+    /* stdin timeout tracking */
+    /* validate stdin fd before enabling monitoring - fstat returns -1 if fd is invalid */
+    let stdin_valid = if stdin_timeout.is_some() || stdin_relay.is_some() {
+        // SAFETY: zeroed stat struct is valid for fstat call below
+        let mut stat: libc::stat = unsafe { core::mem::zeroed() };
+        // SAFETY: fstat with valid stat buffer, fd 0 may or may not be valid
+        let result = unsafe { libc::fstat(0, &raw mut stat) };
+        if result < 0 {
+            /* stdin fd is invalid (closed, bad fd) - disable stdin timeout,
+             * and the command's stdin ends right away */
+            stdin_timeout = None;
+            if let Some(relay) = stdin_relay {
+                relay.end_input();
+            }
+            false
+        } else {
+            true
+        }
+    } else {
+        false
+    };
+    let stdin_timeout_ns = stdin_timeout.as_ref().map_or(0, |s| s.timeout_ns);
+    /* mutable: updated when stdin EOF/error disables monitoring.
+     * consume mode and a relay read stdin, so register it with kqueue -
+     * watch uses timer-based poll. */
+    let mut stdin_enabled = stdin_valid
+        && (stdin_relay.is_some()
+            || stdin_timeout
+                .as_ref()
+                .is_some_and(|s| s.mode != StdinMode::Watch));
+    /* the pty's echo still needs copying after EOF */
+    let echo_fd = stdin_relay.and_then(StdinRelay::echo_fd);
+
+    /* initial level check for watch mode to set activity timestamp */
+    if let Some(ref mut stdin_cfg) = stdin_timeout
+        && stdin_cfg.mode == StdinMode::Watch
+        && stdin_valid
+    {
+        let now_ns = precise_now_ns(confine)?;
+        if !watch_stdin(stdin_cfg, now_ns) {
+            /* stdin already closed - disable monitoring */
+            stdin_timeout = None;
+        }
+    }
+
+    /* drop whatever the previous wait on this queue left registered or
+     * pending - an earlier child's exit, a timer that never fired */
+    queue.reset();
+
+    /*
+     * kqueue filters:
+     * - EVFILT_PROC + NOTE_EXIT: wake when process dies, no polling needed
+     * - EVFILT_TIMER + NOTE_NSECONDS: nanosecond timer (kernel scheduler adds
+     *   ~15-30ms latency anyway, but we're not the bottleneck)
+     * - EVFILT_READ on signal pipe: self-pipe trick for forwarding signals
+     * - EVFILT_READ on stdin (fd 0): watch for stdin activity
+     * - EVFILT_READ on the --proc-tree tracker's own kqueue: forks and execs
+     *   to record
+     * - EVFILT_READ on the command's stdout and stderr pipes (output.rs)
+     * - EVFILT_READ on the --stdin-mode pty, for its echo (tty.rs)
+     * - EVFILT_PROC on the --hook-async hook, while it runs
+     *
+     * EV_ONESHOT on proc/timer means auto-delete after firing.
+     * Signal pipe and stdin stay registered for multiple events.
+     */
+    /* Use fixed-size array instead of Vec to avoid heap allocation */
+    let mut changes = [
+        /* Watch for process exit */
+        kq::proc_exit(pid),
+        /* High-precision timer - ident 1, just needs to be unique */
+        kq::timer(1, timeout_ns),
+        /* Signal pipe watcher (may be unused if no signal fd) */
+        kq::read(signal_fd.unwrap_or(0), signal_fd.is_some()),
+        /* Stdin watcher - consume mode and the relay, watch uses timer-based poll */
+        kq::read(0, stdin_enabled),
+        /* Process tree tracker (--proc-tree) */
+        kq::read(tree_fd.unwrap_or(0), tree_fd.is_some()),
+        /* The command's output, when it goes through us */
+        kq::read(out_fd.unwrap_or(0), out_fd.is_some() && output_watched),
+        kq::read(err_fd.unwrap_or(0), err_fd.is_some() && output_watched),
+        /* The stdin pty's echo */
+        kq::read(echo_fd.unwrap_or(0), echo_fd.is_some()),
+        /* The hook running alongside (--hook-async) */
+        hook.as_deref()
+            .filter(|h| h.running())
+            .map_or_else(kq::empty, |h| kq::proc_exit(h.pid())),
+    ];
+    /*
+     * only slots with flags set are submitted - kevent doesn't skip a
+     * flags=0 entry, it reports ENOENT for it:
+     * - proc and timer are always active (indices 0, 1)
+     * - signal pipe is active if signal_fd is set (index 2)
+     * - stdin is active if enabled or being deleted (index 3)
+     * - tree tracker is active with --proc-tree (index 4)
+     * - output pipes are active until they reach EOF, except while
+     *   --output-rate-policy block has them paused (indices 5, 6)
+     * - the async hook is active until it's reaped (index 8)
+     */
+
+    /* Buffer for returned events - we only need one */
+    let mut event = kq::empty();
+
+    /*
+     * kevent() atomically registers filters and waits. No race condition.
+     *
+     * EINTR: signal interrupted us, recalculate remaining time and retry.
+     * ESRCH: process already dead, just reap it.
+     *
+     * No timeout arg to kevent, the timer filter handles it.
+     * With heartbeat: timer fires at min(remaining_timeout, time_to_next_heartbeat).
+     * With stdin timeout: timer fires at min(remaining_timeout, stdin_deadline).
+     */
+    let mut deadline_ns = advance_ns(start_ns, timeout_ns);
+    let mut active_deadline_ns = active_min.map(|d| advance_ns(active_now_ns(), duration_to_ns(d)));
+    let mut sleep_watch = SleepWatch::new();
+
+    loop {
+        /* the machine slept since the last wakeup: record it, then apply
+         * --on-sleep before the deadline check sees the jump */
+        let slept_ns = sleep_watch.check();
+        if slept_ns > 0 {
+            events.record_sleep(precise_now_ns(confine)?, slept_ns);
+            match on_sleep {
+                SleepPolicy::Fail => return Ok(WaitResult::TimedOut(TimeoutReason::SystemSleep)),
+                /* active time already leaves the sleep out */
+                SleepPolicy::Pause if confine != Confine::Active => {
+                    deadline_ns = advance_ns(deadline_ns, slept_ns);
+                    if let Some(ref mut stdin_cfg) = stdin_timeout {
+                        stdin_cfg.last_activity_ns =
+                            advance_ns(stdin_cfg.last_activity_ns, slept_ns);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        /* check if we've passed deadline - after a last look at
+         * --extend-env-file, which may have moved it */
+        let now_ns = precise_now_ns(confine)?;
+        if let Some(file) = extend.as_deref_mut()
+            && (deadline_reached(now_ns, next_extend_ns) || deadline_reached(now_ns, deadline_ns))
+        {
+            let before_ns = duration_to_ns(file.applied());
+            if let Some(after) = file.poll() {
+                let after_ns = duration_to_ns(after);
+                deadline_ns = advance_ns(deadline_ns.saturating_sub(before_ns), after_ns);
+                events.record_extension(now_ns, after_ns);
+            }
+            next_extend_ns = advance_ns(now_ns, REREAD_INTERVAL_NS);
+        }
+        /* the command stopping itself: recorded, and with pause the time
+         * it's been stopped moves the deadlines out. while the throttle
+         * has it stopped, that stop is ours: no look then */
+        if let Some(watch) = child_stop.as_mut()
+            && (deadline_reached(now_ns, advance_ns(watch.looked_ns, watch.poll_ns))
+                || deadline_reached(now_ns, deadline_ns))
+        {
+            if !throttle.as_deref().is_some_and(|t| t.state.is_suspended()) {
+                let Some(stopped_ns) = watch.look(pid, now_ns, events) else {
+                    return Ok(WaitResult::TimedOut(TimeoutReason::ChildStopped));
+                };
+                if watch.policy == ChildStopPolicy::Pause {
+                    deadline_ns = advance_ns(deadline_ns, stopped_ns);
+                    active_deadline_ns = active_deadline_ns.map(|d| advance_ns(d, stopped_ns));
+                }
+            }
+            watch.looked_ns = now_ns;
+        }
+
+        let active_left = active_left_ns(active_deadline_ns);
+        if deadline_reached(now_ns, deadline_ns) && active_left == 0 {
+            return Ok(WaitResult::TimedOut(TimeoutReason::WallClock));
+        }
+        let remaining_timeout_ns = remaining_ns(now_ns, deadline_ns).max(active_left);
+
+        /* --hook-async: a hook past its limit is killed, the wait goes on */
+        if let Some(hook) = hook.as_deref_mut()
+            && hook.running()
+            && deadline_reached(now_ns, hook.deadline_ns())
+        {
+            record_hook_end(events, hook.settle(HookWaitResult::TimedOut), confine);
+            changes[8].flags = 0;
+        }
+        let time_to_hook = hook
+            .as_deref()
+            .filter(|h| h.running())
+            .map_or(u64::MAX, |h| remaining_ns(now_ns, h.deadline_ns()));
+
+        let window_left_ns = window_close_ns.map_or(u64::MAX, |close_ns| {
+            remaining_ns(event_now(Confine::Wall), close_ns)
+        });
+        if window_left_ns == 0 {
+            return Ok(WaitResult::TimedOut(TimeoutReason::WindowClosed));
+        }
+
+        /* a failed rewrite leaves the last value; the next one may work */
+        if let Some(file) = remaining_file
+            && deadline_reached(now_ns, next_remaining_ns)
+        {
+            let _ = file.update(remaining_timeout_ns);
+            next_remaining_ns = advance_ns(now_ns, REMAINING_FILE_INTERVAL_NS);
+        }
+
+        /* a signal the pipe had no room for is only in the pending mask,
+         * and kqueue won't wake us for it */
+        if let Some(sig) = route.and_then(Route::take_pending) {
+            return Ok(WaitResult::ReceivedSignal(sig));
+        }
+
+        /* check stdin idle timeout using checked arithmetic for invariant detection */
+        if let Some(ref stdin_cfg) = stdin_timeout {
+            /* elapsed_ns returns None if now < last_activity (clock anomaly) */
+            match elapsed_ns(stdin_cfg.last_activity_ns, now_ns) {
+                Some(idle_ns) if idle_ns >= stdin_timeout_ns => {
+                    return Ok(WaitResult::TimedOut(TimeoutReason::StdinIdle));
+                }
+                None => {
+                    /* clock went backwards - log and continue with 0 idle time */
+                    /* this shouldn't happen but better than silent misbehavior */
+                }
+                Some(_) => { /* still within timeout */ }
+            }
+        }
+
+        /* calculate next wake time: min(remaining timeout, time to next heartbeat, stdin timeout, memory check) */
+        let time_to_heartbeat = remaining_ns(now_ns, next_heartbeat_ns);
+        let time_to_stdin_deadline = if let Some(ref stdin_cfg) = stdin_timeout {
+            /* use checked helper - returns remaining time or 0 if already exceeded */
+            time_to_idle_timeout(stdin_cfg.last_activity_ns, now_ns, stdin_timeout_ns).unwrap_or(0) /* clock anomaly: treat as timed out */
+        } else {
+            u64::MAX
+        };
+        let time_to_stdin_poll = stdin_timeout
+            .as_ref()
+            .filter(|s| s.mode == StdinMode::Watch)
+            .map_or(u64::MAX, |s| {
+                remaining_ns(now_ns, advance_ns(s.looked_ns, s.poll_ns))
+            });
+        let time_to_throttle = remaining_ns(now_ns, next_throttle_ns);
+        let time_to_memory_check = remaining_ns(now_ns, next_memory_check_ns);
+        let time_to_output_resume = remaining_ns(now_ns, output_resume_ns);
+        let time_to_remaining_update = remaining_ns(now_ns, next_remaining_ns);
+        let time_to_extend = remaining_ns(now_ns, next_extend_ns);
+        let time_to_child_stop = child_stop.as_ref().map_or(u64::MAX, |w| {
+            remaining_ns(now_ns, advance_ns(w.looked_ns, w.poll_ns))
+        });
+        let next_wake_ns = remaining_timeout_ns
+            .min(window_left_ns)
+            .min(time_to_heartbeat)
+            .min(time_to_stdin_deadline)
+            .min(time_to_stdin_poll)
+            .min(time_to_throttle)
+            .min(time_to_memory_check)
+            .min(time_to_output_resume)
+            .min(time_to_remaining_update)
+            .min(time_to_extend)
+            .min(time_to_child_stop)
+            .min(time_to_hook)
+            .min(sleep_probe_ns(on_sleep));
+
+        /* update timer to next wake time */
+        let timer_ns = next_wake_ns.min(MAX_TIMER_NS);
+        changes[1] = kq::timer(1, timer_ns);
+        let wake_due_ns = sched_now_ns().saturating_add(timer_ns);
+
+        /* gather the active slots */
+        let mut submit = [kq::empty(); 9];
+        let mut num_changes = 0;
+        for change in changes.iter().filter(|c| c.flags != 0) {
+            submit[num_changes] = *change;
+            num_changes += 1;
+        }
+
+        /* no timeout - the timer event handles it */
+        let waited = queue.wait(&submit[..num_changes], &mut event);
+
+        /* after kevent returns, clear EV_DELETE to avoid re-submitting */
+        for change in &mut changes {
+            if change.flags == kq::EV_DELETE {
+                change.flags = 0;
+            }
+        }
+
+        if let Err(err) = waited {
+            /* EINTR: signal interrupted us, retry with remaining time */
+            if err == libc::EINTR {
+                continue;
+            }
+            /* ESRCH: process already gone, reap it. the hook's, if that's
+             * the one that's gone */
+            if err == libc::ESRCH {
+                if let Some(hook) = hook.as_deref_mut()
+                    && hook.running()
+                    && let Ok(Some((status, _rusage))) = hook.child.try_wait()
+                {
+                    record_hook_end(events, hook.settle(HookWaitResult::Exited(status)), confine);
+                    changes[8].flags = 0;
+                    continue;
+                }
+                /* try non-blocking first, fall back to blocking wait */
+                let Some((status, rusage)) = child.try_wait().ok().flatten() else {
+                    /* ESRCH from kernel but child not reaped yet - use blocking wait.
+                     * ECHILD error means already reaped elsewhere - treat as timeout. */
+                    let wait_result = child.wait();
+                    return match wait_result {
+                        Ok((status, rusage)) => Ok(WaitResult::Exited(status, rusage)),
+                        Err(SpawnError::Wait(Errno::NoChild)) => {
+                            Ok(WaitResult::TimedOut(TimeoutReason::WallClock))
+                        }
+                        Err(e) => Err(TimeoutError::Internal(format!("wait failed: {}", e))),
+                    };
+                };
+
+                return Ok(WaitResult::Exited(status, rusage));
+            }
+            return Err(TimeoutError::Internal(format!(
+                "kevent failed: errno {err}"
+            )));
+        }
+
+        /* --hook-async: the hook exited, or was gone before it could be
+         * watched. an exit that comes in after it was killed is old news */
+        #[allow(clippy::cast_sign_loss)]
+        if let Some(hook) = hook.as_deref_mut()
+            && event.filter == kq::EVFILT_PROC
+            && event.ident == hook.pid() as usize
+        {
+            if hook.running() {
+                record_hook_end(events, hook.reap(), confine);
+            }
+            changes[8].flags = 0;
+            /* re-register proc watcher (oneshot) */
+            changes[0].flags = kq::EV_ADD | kq::EV_ONESHOT;
+            continue;
+        }
+
+        /* --proc-tree: record what the command forked and exec'd */
+        #[allow(clippy::cast_sign_loss)]
+        if let Some(tracker) = tracker
+            && event.filter == kq::EVFILT_READ
+            && tree_fd.is_some_and(|fd| event.ident == fd as usize)
+        {
+            let at = events.offset(precise_now_ns(confine)?);
+            tracker.drain(events.tree_mut(), at);
+            /* re-register proc watcher (oneshot) */
+            changes[0].flags = kq::EV_ADD | kq::EV_ONESHOT;
+            continue;
+        }
+
+        /* the command wrote something: copy it out. EOF: stop watching */
+        if let Some(output) = output.as_deref_mut()
+            && event.filter == kq::EVFILT_READ
+            && let Some(slot) =
+                (5..7).find(|&i| changes[i].flags != 0 && changes[i].ident == event.ident)
+        {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+            if !output.pump(event.ident as i32, log, events) {
+                changes[slot].flags = kq::EV_DELETE;
+            }
+            /* over the rate with 'block': stop reading, the command waits */
+            if let Some(ns) = output.paused_ns() {
+                for change in &mut changes[5..7] {
+                    if change.flags != 0 {
+                        change.flags = kq::EV_DELETE;
+                    }
+                }
+                output_resume_ns = advance_ns(precise_now_ns(confine)?, ns);
+            }
+            /* re-register proc watcher (oneshot) */
+            changes[0].flags = kq::EV_ADD | kq::EV_ONESHOT;
+            continue;
+        }
+
+        /* the stdin pty echoed what was typed: show it. EOF: stop watching */
+        #[allow(clippy::cast_sign_loss)]
+        if let Some(relay) = stdin_relay
+            && event.filter == kq::EVFILT_READ
+            && echo_fd.is_some_and(|fd| event.ident == fd as usize)
+        {
+            if !relay.pump_echo() {
+                changes[7].flags = kq::EV_DELETE;
+            }
+            /* re-register proc watcher (oneshot) */
+            changes[0].flags = kq::EV_ADD | kq::EV_ONESHOT;
+            continue;
+        }
+
+        /* handle stdin activity - reset the idle timer */
+        if event.filter == kq::EVFILT_READ && event.ident == 0 && stdin_enabled {
+            let eof = (event.flags & kq::EV_EOF) != 0;
+            /* EV_EOF means stdin is gone - disable monitoring. a relay
+             * gets what's still buffered first, then the EOF */
+            if eof && stdin_relay.is_none() {
+                stdin_timeout = None;
+                stdin_enabled = false;
+                changes[3].flags = kq::EV_DELETE;
+                changes[0].flags = kq::EV_ADD | kq::EV_ONESHOT;
+                continue;
+            }
+
+            /* consume mode or a relay - watch never registers stdin with
+             * kqueue. drain any available data to prevent busy-loop; consume
+             * only cares about activity, the relay passes it on */
+            let mut buf = [0u8; 1024];
+            // SAFETY: read from stdin (fd 0) with valid buffer
+            let bytes_read = unsafe { libc::read(0, buf.as_mut_ptr().cast(), buf.len()) };
+
+            if bytes_read == 0 || (bytes_read < 0 && eof) {
+                /* EOF on stdin - no more input possible, disable stdin monitoring.
+                 * This prevents busy-loop when stdin is /dev/null or closed pipe. */
+                if let Some(relay) = stdin_relay {
+                    relay.end_input();
+                }
+                stdin_timeout = None;
+                stdin_enabled = false;
+                /* remove stdin filter from kqueue, then clear flags to avoid re-submitting */
+                changes[3].flags = kq::EV_DELETE;
+            } else if let Ok(n) = usize::try_from(bytes_read) {
+                if let Some(relay) = stdin_relay {
+                    relay.feed(&buf[..n]);
+                }
+                /* got actual data - reset the idle timer */
+                let now_ns = precise_now_ns(confine)?;
+                if let Some(ref mut stdin_cfg) = stdin_timeout {
+                    stdin_cfg.last_activity_ns = now_ns;
+                }
+            }
+            /* bytes_read < 0: EAGAIN/EWOULDBLOCK or error - just continue */
+
+            /* re-register proc watcher (oneshot) */
+            changes[0].flags = kq::EV_ADD | kq::EV_ONESHOT;
+            continue;
+        }
+
+        /* got an event - check if it's a heartbeat tick or something else */
+        if event.filter == kq::EVFILT_TIMER {
+            record_wake_lag(log, events, sched_now_ns().saturating_sub(wake_due_ns));
+            let now_ns = precise_now_ns(confine)?;
+            let mut sample = WakeSample::new(pid);
+            let sample_ns = now_ns.saturating_add(SAMPLE_COALESCE_NS);
+
+            /* throttle check */
+            if let Some(ref mut throttle_ctx) = throttle
+                && deadline_reached(sample_ns, next_throttle_ns)
+            {
+                let cpu_ns = sample.get().map(|s| s.cpu_time_ns);
+                throttle_ctx
+                    .state
+                    .update_with(&throttle_ctx.cfg, now_ns, cpu_ns, log)?;
+                next_throttle_ns =
+                    advance_ns(now_ns, throttle_ctx.state.next_check_ns(&throttle_ctx.cfg));
+            }
+
+            /* memory limit check */
+            if let Some(ref mem_cfg) = memory_limit
+                && deadline_reached(sample_ns, next_memory_check_ns)
+            {
+                if let Some(current_bytes) = sample.get().map(|s| s.memory_bytes) {
+                    events.record_peak(EventKind::MemoryPeak, now_ns, current_bytes);
+                    if current_bytes > mem_cfg.limit_bytes {
+                        return Ok(WaitResult::MemoryLimitExceeded {
+                            limit_bytes: mem_cfg.limit_bytes,
+                            actual_bytes: current_bytes,
+                        });
+                    }
+                }
+                next_memory_check_ns = advance_ns(now_ns, memory_check_interval_ns);
+            }
+
+            /* paused output: watch the pipes again */
+            if deadline_reached(now_ns, output_resume_ns)
+                && let Some(output) = output.as_deref()
+            {
+                output_resume_ns = u64::MAX;
+                for (change, fd) in changes[5..7].iter_mut().zip(output.fds()) {
+                    if let Some(fd) = fd {
+                        *change = kq::read(fd, true);
+                    }
+                }
+            }
+
+            /* watch mode: look without consuming data */
+            if let Some(ref mut stdin_cfg) = stdin_timeout
+                && stdin_cfg.mode == StdinMode::Watch
+                && !watch_stdin(stdin_cfg, now_ns)
+            {
+                /* stdin closed - disable monitoring to prevent false idle timeout */
+                stdin_timeout = None;
+            }
+
+            /* check stdin timeout first using checked arithmetic */
+            if let Some(ref stdin_cfg) = stdin_timeout {
+                match elapsed_ns(stdin_cfg.last_activity_ns, now_ns) {
+                    Some(idle_ns) if idle_ns >= stdin_timeout_ns => {
+                        return Ok(WaitResult::TimedOut(TimeoutReason::StdinIdle));
+                    }
+                    _ => { /* within timeout or clock anomaly - continue */ }
+                }
+            }
+
+            /* heartbeat tick: we haven't reached deadline yet, timer fired for heartbeat.
+             * with --extend-env-file the top of the loop decides, after a
+             * last look at the file */
+            let timed_out = extend.is_none()
+                && deadline_reached(now_ns, deadline_ns)
+                && active_left_ns(active_deadline_ns) == 0;
+            if heartbeat_interval_ns > 0
+                && !timed_out
+                && deadline_reached(now_ns, next_heartbeat_ns)
+            {
+                /* the latest tick due by now; the ones before it since
+                 * the last we ran are missed */
+                let due = (elapsed_ns(start_ns, now_ns).unwrap_or(0) / heartbeat_interval_ns)
+                    .max(heartbeat_tick);
+                let missed = due - heartbeat_tick;
+                note_translated(events, pid);
+                /* print heartbeat message */
+                if let Some(ref hb) = heartbeat
+                    && !hb.quiet
+                {
+                    /* elapsed_ns validated: hb.start_ns <= now_ns (start before now) */
+                    let elapsed = elapsed_ns(hb.start_ns, now_ns).unwrap_or(0);
+                    print_heartbeat(log, elapsed, hb.pid, missed, events);
+                }
+                events.record_heartbeat(now_ns, missed);
+                /* schedule next heartbeat */
+                heartbeat_tick = due.saturating_add(1);
+                next_heartbeat_ns = advance_ns(
+                    start_ns,
+                    heartbeat_interval_ns.saturating_mul(heartbeat_tick),
+                );
+                /* re-register the timer for next wake (proc watcher is oneshot, re-add) */
+                changes[0].flags = kq::EV_ADD | kq::EV_ONESHOT;
+                continue;
+            }
+
+            /* wall clock deadline reached (and, if hybrid, the awake minimum) */
+            if timed_out {
+                return Ok(WaitResult::TimedOut(TimeoutReason::WallClock));
+            }
+
+            /* timer fired for stdin timeout check - continue loop to recalculate */
+            changes[0].flags = kq::EV_ADD | kq::EV_ONESHOT;
+            continue;
+        }
+
+        /* check for registration errors inside loop */
+        if (event.flags & kq::EV_ERROR) != 0 {
+            #[allow(clippy::cast_possible_truncation)]
+            let err_code = event.data as i32;
+
+            /* EBADF on stdin (ident 0) - stdin became invalid, disable monitoring */
+            if err_code == libc::EBADF && event.ident == 0 {
+                stdin_timeout = None;
+                stdin_enabled = false;
+                /* remove stdin filter from kqueue */
+                changes[3].flags = kq::EV_DELETE;
+                changes[0].flags = kq::EV_ADD | kq::EV_ONESHOT;
+                continue;
+            }
+
+            /* ENOENT on stdin (ident 0) - filter doesn't exist (already deleted), ignore */
+            if err_code == libc::ENOENT && event.ident == 0 {
+                /* clear EV_DELETE flag since filter is gone */
+                changes[3].flags = 0;
+                changes[0].flags = kq::EV_ADD | kq::EV_ONESHOT;
+                continue;
+            }
+
+            /* ESRCH = process gone - break to handle below */
+            if err_code == libc::ESRCH {
+                break;
+            }
+
+            /* other errors are fatal */
+            return Err(TimeoutError::Internal(format!(
+                "kqueue event registration failed: errno {}",
+                err_code
+            )));
+        }
+
+        /* got a non-heartbeat/non-stdin result, exit loop */
+        break;
+    }
+
+    /* handle ESRCH after loop exit */
+    if (event.flags & kq::EV_ERROR) != 0 {
+        #[allow(clippy::cast_possible_truncation)]
+        let err_code = event.data as i32;
+        /* ESRCH = process gone, that's fine */
+        if err_code == libc::ESRCH {
+            /* try non-blocking first, fall back to blocking wait */
+            match child.try_wait() {
+                Ok(Some((status, rusage))) => return Ok(WaitResult::Exited(status, rusage)),
+                Ok(None) | Err(_) => match child.wait() {
+                    Ok((status, rusage)) => return Ok(WaitResult::Exited(status, rusage)),
+                    Err(SpawnError::Wait(Errno::NoChild)) => {
+                        return Ok(WaitResult::TimedOut(TimeoutReason::WallClock));
+                    }
+                    Err(e) => {
+                        return Err(TimeoutError::Internal(format!("wait failed: {}", e)));
+                    }
+                },
+            }
+        }
+    }
+
+    /* EVFILT_PROC = exited, EVFILT_TIMER = timed out, EVFILT_READ = signal received */
+    if event.filter == kq::EVFILT_PROC {
+        /* still a zombie: its flags can be read one last time */
+        note_translated(events, pid);
+        let (status, rusage) = child.wait().map_err(TimeoutError::from)?;
+        return Ok(WaitResult::Exited(status, rusage));
+    }
+
+    if event.filter == kq::EVFILT_READ {
+        /* signal pipe became readable - a signal was received */
+        let Some(sig) = route.and_then(Route::take_signal) else {
+            /* pipe readable but no signal byte yet - treat as timeout */
+            return Ok(WaitResult::TimedOut(TimeoutReason::WallClock));
+        };
+        return Ok(WaitResult::ReceivedSignal(sig));
+    }
+
+    Ok(WaitResult::TimedOut(TimeoutReason::WallClock))
+}
@@ -0,0 +1,433 @@
+/*
+ * retry.rs
+ *
+ * attempts: --retry after a timeout, --spawn-retry after a spawn that
+ * failed for now, and the delays between them.
+ */
+
+use alloc::string::{String, ToString};
+use core::time::Duration;
+
+use crate::error::{Result, TimeoutError};
+use crate::events::{EventKind, EventLog};
+use crate::forward::{Route, is_stop};
+use crate::kq::Kqueue;
+use crate::log::Warning;
+use crate::outcome::Outcome;
+use crate::process::build_argv;
+use crate::signal::{Signal, signal_name};
+use crate::time_math::elapsed_ns;
+use crate::wait::kqueue_delay_on;
+
+use super::clock::{duration_ms, event_now, event_value, precise_now_ns};
+use super::{RunConfig, RunResult, TimeoutReason, open_kqueue, open_route, run_command_recorded};
+
+/// Result of a single attempt in retry mode.
+///
+/// ```
+/// use core::time::Duration;
+/// use procguard::{RunConfig, run_with_retry};
+///
+/// let config = RunConfig {
+///     timeout: Duration::from_secs(10),
+///     ..RunConfig::default()
+/// };
+/// let args = ["-c".to_string(), "exit 4".to_string()];
+/// let (_, attempts) = run_with_retry("sh", &args, &config).unwrap();
+/// let only = attempts.as_slice()[0];
+/// assert_eq!(only.status, "completed");
+/// assert_eq!(only.exit_code, Some(4));
+/// ```
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, Copy, Default)]
+pub struct AttemptResult {
+    /// `"completed"`, `"crashed"`, `"timeout"` or `"aborted"`.
+    pub status: &'static str,
+    /// Exit code, if it completed.
+    pub exit_code: Option<i32>,
+    /// How long this attempt took.
+    pub elapsed_ms: u64,
+}
+
+/// Most attempts a retrying run makes, and [`Attempts`] holds: a fixed
+/// array, no Vec.
+///
+/// ```
+/// use procguard::{AttemptResult, Attempts, MAX_RETRIES};
+///
+/// let mut attempts = Attempts::new();
+/// for _ in 0..MAX_RETRIES + 1 {
+///     attempts.push(AttemptResult::default());
+/// }
+/// assert_eq!(attempts.len(), MAX_RETRIES);
+/// ```
+pub const MAX_RETRIES: usize = 32;
+
+/// Most `--spawn-retry` takes: with the delay doubling each time, ten
+/// already wait 1023 times `--spawn-retry-delay`.
+pub const MAX_SPAWN_RETRIES: u32 = 10;
+
+/// `--spawn-retry-delay` when not given.
+pub const DEFAULT_SPAWN_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// The attempts of a [`run_with_retry`] run, oldest first.
+///
+/// ```
+/// use core::time::Duration;
+/// use procguard::{RunConfig, RunResult, run_with_retry};
+///
+/// let config = RunConfig {
+///     timeout: Duration::from_millis(100),
+///     retry_count: 1,
+///     ..RunConfig::default()
+/// };
+/// let args = ["-c".to_string(), "sleep 5".to_string()];
+/// let (result, attempts) = run_with_retry("sh", &args, &config).unwrap();
+///
+/// assert!(matches!(result, RunResult::TimedOut { .. }));
+/// assert_eq!(attempts.len(), 2);
+/// assert!(attempts.as_slice().iter().all(|a| a.status == "timeout"));
+/// ```
+pub struct Attempts {
+    data: [AttemptResult; MAX_RETRIES],
+    len: usize,
+}
+
+impl Default for Attempts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Attempts {
+    /// None yet.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            data: [AttemptResult::default(); MAX_RETRIES],
+            len: 0,
+        }
+    }
+
+    /// Record one; past [`MAX_RETRIES`] it's dropped.
+    #[inline]
+    pub fn push(&mut self, item: AttemptResult) {
+        if self.len < MAX_RETRIES {
+            self.data[self.len] = item;
+            self.len += 1;
+        }
+    }
+
+    /// The attempts, oldest first.
+    #[inline]
+    pub fn as_slice(&self) -> &[AttemptResult] {
+        &self.data[..self.len]
+    }
+
+    /// Attempts recorded.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether none were recorded.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/* max_attempts = retry_count + 1 (initial attempt), capped at MAX_RETRIES */
+/* note: --retry=31 gives 32 attempts (max), --retry=32+ also gives 32 */
+pub(super) fn max_attempts(config: &RunConfig) -> u32 {
+    config
+        .retry_count
+        .min(MAX_RETRIES as u32 - 1)
+        .saturating_add(1)
+}
+
+/* wait before spawn retry `retry` (0-based): the delay, doubled each time */
+pub(super) fn spawn_retry_delay(config: &RunConfig, retry: u32) -> Duration {
+    config
+        .spawn_retry_delay
+        .saturating_mul(1u32.checked_shl(retry).unwrap_or(u32::MAX))
+}
+
+/* all the spawn retry delays one attempt can sit out */
+pub(super) fn spawn_retry_delays(config: &RunConfig) -> Duration {
+    (0..config.spawn_retry).fold(Duration::ZERO, |total, n| {
+        total.saturating_add(spawn_retry_delay(config, n))
+    })
+}
+
+/* delay after failed attempt `attempt` (0-based), with exponential backoff:
+ * attempt 0 gets base delay, attempt 1 gets delay*backoff, etc.
+ * capped at 5 minutes to prevent runaway delays with large backoff values */
+pub(super) fn retry_delay(config: &RunConfig, attempt: u32) -> Duration {
+    const MAX_DELAY_MS: u64 = 5 * 60 * 1000; /* 5 minutes */
+    if config.retry_backoff > 1 {
+        let multiplier = (config.retry_backoff as u64).saturating_pow(attempt);
+        let delay_ms = duration_ms(config.retry_delay);
+        let total_ms = delay_ms.saturating_mul(multiplier).min(MAX_DELAY_MS);
+        Duration::from_millis(total_ms)
+    } else {
+        config.retry_delay
+    }
+}
+
+/// Run command with retry on timeout.
+///
+/// Returns the final result and attempt results for JSON output.
+/// Only retries on timeout - other failures (exit code, signal) are returned immediately.
+/// Max retries capped at MAX_RETRIES (32) to avoid unbounded allocation.
+/// A stop signal during the retry delay calls the retry off: the last
+/// result is returned with an `"aborted"` attempt, and the signal is left
+/// for [`take_unforwarded_stop`].
+///
+/// [`take_unforwarded_stop`]: super::take_unforwarded_stop
+///
+/// ```
+/// use core::time::Duration;
+/// use procguard::{Outcome, RunConfig, run_with_retry};
+///
+/// let config = RunConfig {
+///     timeout: Duration::from_secs(10),
+///     retry_count: 3,
+///     ..RunConfig::default()
+/// };
+/// /* a failure isn't a timeout: no retry */
+/// let args = ["-c".to_string(), "exit 1".to_string()];
+/// let (result, attempts) = run_with_retry("sh", &args, &config).unwrap();
+/// assert!(matches!(Outcome::from(&result), Outcome::Failed { code: 1 }));
+/// assert_eq!(attempts.len(), 1);
+/// ```
+pub fn run_with_retry(
+    command: &str,
+    args: &[String],
+    config: &RunConfig,
+) -> Result<(RunResult, Attempts)> {
+    run_with_retry_events(command, args, config, &mut EventLog::new())
+}
+
+/// Same as [`run_with_retry`], also recording a timeline of what happened
+/// (spawns, signals, heartbeats, exits) into `events`.
+///
+/// ```
+/// use procguard::{EventLog, RunConfig, run_with_retry_events};
+///
+/// let mut events = EventLog::new();
+/// let args = ["-c".to_string(), "exit 0".to_string()];
+/// run_with_retry_events("sh", &args, &RunConfig::default(), &mut events).unwrap();
+///
+/// let mut timeline = String::new();
+/// events.write_timeline(&mut timeline).unwrap();
+/// assert!(timeline.contains("spawned pid"));
+/// ```
+pub fn run_with_retry_events(
+    command: &str,
+    args: &[String],
+    config: &RunConfig,
+    events: &mut EventLog,
+) -> Result<(RunResult, Attempts)> {
+    run_with_retry_bytes(command.as_bytes(), args, config, events)
+}
+
+/// Same as [`run_with_retry_events`], with the command and its arguments as
+/// raw bytes. Use this to pass through arguments that aren't valid UTF-8.
+///
+/// ```
+/// use procguard::{EventLog, Outcome, RunConfig, run_with_retry_bytes};
+///
+/// /* $0 is a lone 0xff byte: not UTF-8, passed through as is */
+/// let args: [&[u8]; 3] = [b"-c", b"test \"$0\" = \"$(printf '\\377')\"", b"\xff"];
+/// let (result, _) =
+///     run_with_retry_bytes(b"sh", &args, &RunConfig::default(), &mut EventLog::new()).unwrap();
+/// assert!(Outcome::from(&result).is_success());
+/// ```
+pub fn run_with_retry_bytes<A: AsRef<[u8]>>(
+    command: &[u8],
+    args: &[A],
+    config: &RunConfig,
+    events: &mut EventLog,
+) -> Result<(RunResult, Attempts)> {
+    let argv = build_argv(command, args).map_err(TimeoutError::from)?;
+
+    let max_attempts = max_attempts(config);
+    if config.retry_count >= max_attempts {
+        events.warn(
+            config.log(),
+            Warning::RetryCapped {
+                requested: config.retry_count,
+                max: MAX_RETRIES as u32 - 1,
+            },
+        );
+    }
+    let mut attempts = Attempts::new();
+    /* one route for all attempts, so a signal during the retry delay
+     * is seen too */
+    let subscription = open_route(config.log(), events);
+    let route = subscription.as_ref();
+    /* likewise one kqueue: a retry storm shouldn't pay kqueue() + close()
+     * per attempt, grace period and delay */
+    let queue = open_kqueue()?;
+
+    /* safety counter to prevent infinite loops even if logic has bugs */
+    let mut safety_counter: u32 = 0;
+    const SAFETY_LIMIT: u32 = MAX_RETRIES as u32 + 10;
+
+    for attempt in 0..max_attempts {
+        /* defense-in-depth: abort if we've looped too many times */
+        safety_counter += 1;
+        if safety_counter > SAFETY_LIMIT {
+            return Err(TimeoutError::Internal(
+                "retry loop exceeded safety limit".to_string(),
+            ));
+        }
+
+        let attempt_start = precise_now_ns(config.confine).unwrap_or(0);
+
+        let result = run_command_recorded(&argv, attempt, config, events, route, &queue)?;
+        /* use checked elapsed - fallback to 0 on clock anomaly */
+        let attempt_elapsed_ms = elapsed_ns(
+            attempt_start,
+            precise_now_ns(config.confine).unwrap_or(attempt_start),
+        )
+        .unwrap_or(0)
+            / 1_000_000;
+
+        match &result {
+            RunResult::TimedOut { .. }
+            | RunResult::MemoryLimitExceeded { .. }
+            | RunResult::CpuLimitExceeded { .. } => {
+                attempts.push(AttemptResult {
+                    status: "timeout",
+                    exit_code: None,
+                    elapsed_ms: attempt_elapsed_ms,
+                });
+
+                /* check if we should retry - not once the time window
+                 * has closed */
+                let is_last_attempt = attempt + 1 >= max_attempts;
+                let window_closed = matches!(
+                    result,
+                    RunResult::TimedOut {
+                        reason: TimeoutReason::WindowClosed,
+                        ..
+                    }
+                );
+                if is_last_attempt || window_closed {
+                    return Ok((result, attempts));
+                }
+
+                let delay = retry_delay(config, attempt);
+
+                if config.verbose && !config.quiet {
+                    crate::log_info!(
+                        config.log(),
+                        "attempt {} timed out, retry delay {}ms",
+                        attempt + 1,
+                        duration_ms(delay)
+                    );
+                }
+
+                events.record(
+                    EventKind::RetryScheduled,
+                    event_now(config.confine),
+                    duration_ms(delay),
+                );
+
+                if let Some(sig) = sit_out_delay(&queue, delay, route, config, events) {
+                    /* nobody to forward it to: the retry is off, the last
+                     * attempt's result stands, and the caller dies of the
+                     * signal once that's out (take_unforwarded_stop) */
+                    if config.verbose && !config.quiet {
+                        crate::log_info!(
+                            config.log(),
+                            "{} during the retry delay, not retrying",
+                            signal_name(sig)
+                        );
+                    }
+                    attempts.push(AttemptResult {
+                        status: "aborted",
+                        exit_code: None,
+                        elapsed_ms: 0,
+                    });
+                    crate::forward::park_stop(sig);
+                    return Ok((result, attempts));
+                }
+                /* the window closed during the delay: the last result stands */
+                if config.window_stop.is_some_and(|w| !w.is_open()) {
+                    return Ok((result, attempts));
+                }
+            }
+            RunResult::Completed { status, .. } => {
+                attempts.push(AttemptResult {
+                    status: Outcome::from(&result).as_str(),
+                    exit_code: status.code(),
+                    elapsed_ms: attempt_elapsed_ms,
+                });
+                return Ok((result, attempts));
+            }
+            RunResult::SignalForwarded { .. } => {
+                /* signal forwarded - don't retry */
+                attempts.push(AttemptResult {
+                    status: "signal_forwarded",
+                    exit_code: None,
+                    elapsed_ms: attempt_elapsed_ms,
+                });
+                return Ok((result, attempts));
+            }
+        }
+    }
+
+    /* shouldn't reach here, but just in case */
+    Err(TimeoutError::Internal(
+        "retry loop exited unexpectedly".to_string(),
+    ))
+}
+
+/* wait out the retry delay, watching for signals - including one the pipe
+ * had no room for, which won't wake kqueue. a stop signal ends it and is
+ * returned; USR1/USR2 have no command to go to between attempts, so
+ * they're dropped and the delay goes on */
+pub(super) fn sit_out_delay(
+    queue: &Kqueue,
+    delay: Duration,
+    route: Option<&Route>,
+    config: &RunConfig,
+    events: &mut EventLog,
+) -> Option<Signal> {
+    let start_ns = precise_now_ns(config.confine).unwrap_or(0);
+    loop {
+        let spent_ns =
+            elapsed_ns(start_ns, precise_now_ns(config.confine).unwrap_or(start_ns)).unwrap_or(0);
+        let left = delay.saturating_sub(Duration::from_nanos(spent_ns));
+        let sig = match route.and_then(Route::take_pending) {
+            Some(sig) => sig,
+            /* delay over. anything that came in since goes to the next
+             * attempt's command, forwarded as usual */
+            None if left.is_zero() || kqueue_delay_on(queue, left, route.map(Route::fd)) => {
+                return None;
+            }
+            None => match route.and_then(Route::take_signal) {
+                Some(sig) => sig,
+                None => continue, /* woken with nothing to read */
+            },
+        };
+        events.record(
+            EventKind::SignalReceived,
+            event_now(config.confine),
+            event_value(sig.as_raw()),
+        );
+        if is_stop(sig) {
+            return Some(sig);
+        }
+        if config.verbose && !config.quiet {
+            crate::log_info!(
+                config.log(),
+                "{} during the retry delay, no command to forward it to",
+                signal_name(sig)
+            );
+        }
+    }
+}
@@ -0,0 +1,201 @@
+/*
+ * safe_file.rs
+ *
+ * every file procguard writes - --result-file, --pidfile,
+ * --export-remaining-file, the --flock-queue tickets - is created here,
+ * so they all get the same care:
+ *
+ * - O_EXCL | O_NOFOLLOW on creation. the paths often sit in a shared
+ *   directory like /tmp, where anyone can plant a symlink under the name
+ *   we're about to open; O_EXCL refuses to open anything that's already
+ *   there, symlinks included, so we never write through someone else's
+ *   link. O_NOFOLLOW is belt and braces for the last component.
+ * - an exact mode with --file-mode: fchmod() after open, so the umask
+ *   can't widen or narrow it. without it files are 0644 less the umask,
+ *   like any other tool's.
+ * - fsync before the rename, unless --no-fsync: a crash right after
+ *   could otherwise leave the new name pointing at an empty file.
+ *
+ * replacing a file goes through a temp file next to it and rename(),
+ * which replaces a symlink at the target rather than following it.
+ */
+
+use crate::errno::errno;
+use crate::io::{c_path, format_u32, write_all_vectored};
+
+/// How [`write_atomic`] and [`create_new`] create files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileOptions {
+    /// Exact permission bits (`--file-mode`), whatever the umask.
+    /// `None`: 0644 less the umask.
+    pub mode: Option<u32>,
+    /// fsync() a file before renaming it into place.
+    pub fsync: bool,
+}
+
+impl Default for FileOptions {
+    fn default() -> Self {
+        Self {
+            mode: None,
+            fsync: true,
+        }
+    }
+}
+
+/* create the NUL-terminated `path`, which mustn't exist yet, for writing */
+fn create_c(path: &[u8], opts: FileOptions) -> Result<i32, i32> {
+    // SAFETY: path is NUL-terminated; mode is passed as the variadic arg
+    let fd = unsafe {
+        libc::open(
+            path.as_ptr().cast(),
+            libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+            0o644 as libc::c_uint,
+        )
+    };
+    if fd < 0 {
+        return Err(errno());
+    }
+    if let Some(mode) = opts.mode {
+        // SAFETY: fd was just opened; fchmod takes no pointers
+        if unsafe { libc::fchmod(fd, mode as libc::mode_t) } != 0 {
+            let err = errno();
+            // SAFETY: fd is ours, closed once; path is NUL-terminated
+            #[allow(clippy::multiple_unsafe_ops_per_block)]
+            unsafe {
+                libc::close(fd);
+                libc::unlink(path.as_ptr().cast());
+            }
+            return Err(err);
+        }
+    }
+    Ok(fd)
+}
+
+/// Create `path` for writing, failing with `EEXIST` if anything - a
+/// symlink included - is already there. Returns the descriptor
+/// (close-on-exec), or errno.
+pub fn create_new(path: &str, opts: FileOptions) -> Result<i32, i32> {
+    create_c(&c_path(&[path.as_bytes()])?, opts)
+}
+
+/// Replace `path` with `parts` (concatenated) atomically: readers see the
+/// old file or the complete new one, never a partial write. Returns errno
+/// on failure, and leaves no temp file behind.
+pub fn write_atomic(path: &str, parts: &[&[u8]], opts: FileOptions) -> Result<(), i32> {
+    /* same directory as the target - rename() doesn't cross filesystems.
+     * pid in the name so concurrent runs don't share a temp file */
+    let mut pid = [0u8; 10];
+    // SAFETY: getpid has no preconditions
+    let pid = format_u32(unsafe { libc::getpid() }.unsigned_abs(), &mut pid);
+    let target = c_path(&[path.as_bytes()])?;
+    let tmp = c_path(&[path.as_bytes(), b".tmp.", pid])?;
+
+    /* a temp file already there is left over from an earlier run with our
+     * pid, or planted: either way it goes (the link, not what it points
+     * to), and if it's back by the next try we give up */
+    let fd = match create_c(&tmp, opts) {
+        Err(libc::EEXIST) => {
+            // SAFETY: tmp is NUL-terminated
+            unsafe { libc::unlink(tmp.as_ptr().cast()) };
+            create_c(&tmp, opts)?
+        }
+        created => created?,
+    };
+
+    let written = write_all_vectored(fd, parts).and_then(|()| {
+        // SAFETY: fd is our open temp file
+        if !opts.fsync || unsafe { libc::fsync(fd) } == 0 {
+            Ok(())
+        } else {
+            Err(errno())
+        }
+    });
+    // SAFETY: fd was opened above and is closed exactly once here
+    unsafe { libc::close(fd) };
+
+    let result = written.and_then(|()| {
+        // SAFETY: both paths are NUL-terminated
+        if unsafe { libc::rename(tmp.as_ptr().cast(), target.as_ptr().cast()) } == 0 {
+            Ok(())
+        } else {
+            Err(errno())
+        }
+    });
+    if result.is_err() {
+        // SAFETY: tmp is NUL-terminated; failure just means nothing to remove
+        unsafe { libc::unlink(tmp.as_ptr().cast()) };
+    }
+    result
+}
+
+#[cfg(test)]
+#[cfg(not(miri))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn test_path(name: &str) -> std::string::String {
+        std::format!(
+            "{}/procguard-{}-{}",
+            std::env::temp_dir().display(),
+            name,
+            std::process::id()
+        )
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let path = test_path("safe-file");
+        write_atomic(&path, &[b"first\n"], FileOptions::default()).unwrap();
+        write_atomic(&path, &[b"sec", b"ond\n"], FileOptions::default()).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second\n");
+        /* no temp file left over */
+        let tmp = std::format!("{}.tmp.{}", path, std::process::id());
+        assert!(!std::path::Path::new(&tmp).exists());
+
+        /* exact mode, umask or not */
+        let opts = FileOptions {
+            mode: Some(0o600),
+            fsync: false,
+        };
+        write_atomic(&path, &[b"x"], opts).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        /* a symlink planted at the temp name is replaced, not written through */
+        let victim = test_path("safe-file-victim");
+        std::fs::write(&victim, b"untouched").unwrap();
+        std::os::unix::fs::symlink(&victim, &tmp).unwrap();
+        write_atomic(&path, &[b"third\n"], FileOptions::default()).unwrap();
+        assert_eq!(std::fs::read(&victim).unwrap(), b"untouched");
+        assert_eq!(std::fs::read(&path).unwrap(), b"third\n");
+        let _ = std::fs::remove_file(&victim);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(
+            write_atomic("/nonexistent-dir/x", &[], FileOptions::default()),
+            Err(libc::ENOENT)
+        );
+        assert_eq!(
+            write_atomic("a\0b", &[], FileOptions::default()),
+            Err(libc::EINVAL)
+        );
+    }
+
+    #[test]
+    fn test_create_new() {
+        let path = test_path("safe-file-new");
+        let fd = create_new(&path, FileOptions::default()).unwrap();
+        // SAFETY: fd was just opened
+        unsafe { libc::close(fd) };
+        assert_eq!(create_new(&path, FileOptions::default()), Err(libc::EEXIST));
+        let _ = std::fs::remove_file(&path);
+
+        /* a dangling symlink isn't followed to create its target */
+        let target = test_path("safe-file-target");
+        std::os::unix::fs::symlink(&target, &path).unwrap();
+        assert_eq!(create_new(&path, FileOptions::default()), Err(libc::EEXIST));
+        assert!(!std::path::Path::new(&target).exists());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -8,49 +8,132 @@
  * that's 99% of usage.
  *
  * Local Signal enum with libc constants - no nix dependency.
+ *
+ * parse_signal, signal_name, signal_number and Signal::ALL are public API
+ * other tools use to accept the same signal specs we do: names and
+ * numbers that parse today keep parsing to the same signal. New signals
+ * may be added, which is why Signal is non_exhaustive.
  */
 
 use crate::error::{Result, TimeoutError};
 use alloc::format;
 
-/* POSIX signals as i32 values from libc. Copy/PartialEq for easy comparison. */
+/// The signals procguard can send, forward and report, with their
+/// platform's numbers (SIGBUS is 10 on macOS, 7 on Linux). Non-exhaustive:
+/// more may be added, so a `match` needs a wildcard arm.
+///
+/// ```
+/// use procguard::{RunConfig, Signal, parse_signal};
+///
+/// let config = RunConfig {
+///     signal: Signal::SIGINT,
+///     ..RunConfig::default()
+/// };
+/// assert_eq!(parse_signal("2").unwrap(), config.signal);
+/// assert!(Signal::SIGSEGV.is_crash() && !Signal::SIGTERM.is_crash());
+/// ```
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(i32)]
 #[non_exhaustive]
 pub enum Signal {
+    /// Hangup: the terminal went away.
     SIGHUP = libc::SIGHUP,
+    /// Interrupt: `^C`.
     SIGINT = libc::SIGINT,
+    /// Quit: `^\`, with a core dump.
     SIGQUIT = libc::SIGQUIT,
+    /// Illegal instruction.
     SIGILL = libc::SIGILL,
+    /// Trace or breakpoint trap.
     SIGTRAP = libc::SIGTRAP,
+    /// Abort: `abort()`, a failed assertion. `IOT` is an alias.
     SIGABRT = libc::SIGABRT,
+    /// Bus error.
     SIGBUS = libc::SIGBUS,
+    /// Arithmetic exception.
     SIGFPE = libc::SIGFPE,
+    /// Kill: can't be caught or ignored.
     SIGKILL = libc::SIGKILL,
+    /// User-defined signal 1.
     SIGUSR1 = libc::SIGUSR1,
+    /// Segmentation fault.
     SIGSEGV = libc::SIGSEGV,
+    /// User-defined signal 2.
     SIGUSR2 = libc::SIGUSR2,
+    /// Write to a pipe nobody reads.
     SIGPIPE = libc::SIGPIPE,
+    /// Timer alarm.
     SIGALRM = libc::SIGALRM,
+    /// Terminate: the default timeout signal.
     SIGTERM = libc::SIGTERM,
+    /// A child stopped or exited.
     SIGCHLD = libc::SIGCHLD,
+    /// Continue a stopped process.
     SIGCONT = libc::SIGCONT,
+    /// Stop: can't be caught or ignored.
     SIGSTOP = libc::SIGSTOP,
+    /// Terminal stop: `^Z`.
     SIGTSTP = libc::SIGTSTP,
+    /// Background read from the terminal.
     SIGTTIN = libc::SIGTTIN,
+    /// Background write to the terminal.
     SIGTTOU = libc::SIGTTOU,
+    /// Urgent data on a socket.
     SIGURG = libc::SIGURG,
+    /// CPU time limit exceeded.
     SIGXCPU = libc::SIGXCPU,
+    /// File size limit exceeded.
     SIGXFSZ = libc::SIGXFSZ,
+    /// Virtual timer alarm.
     SIGVTALRM = libc::SIGVTALRM,
+    /// Profiling timer alarm.
     SIGPROF = libc::SIGPROF,
+    /// Terminal window size changed.
     SIGWINCH = libc::SIGWINCH,
+    /// I/O possible on a descriptor.
     SIGIO = libc::SIGIO,
+    /// Bad system call.
     SIGSYS = libc::SIGSYS,
 }
 
 impl Signal {
-    /* convert from raw signal number */
+    /// Every signal in the table - what [`parse_signal`] accepts by name or
+    /// number - in declaration order. Grows when the enum does.
+    pub const ALL: &'static [Self] = &[
+        Self::SIGHUP,
+        Self::SIGINT,
+        Self::SIGQUIT,
+        Self::SIGILL,
+        Self::SIGTRAP,
+        Self::SIGABRT,
+        Self::SIGBUS,
+        Self::SIGFPE,
+        Self::SIGKILL,
+        Self::SIGUSR1,
+        Self::SIGSEGV,
+        Self::SIGUSR2,
+        Self::SIGPIPE,
+        Self::SIGALRM,
+        Self::SIGTERM,
+        Self::SIGCHLD,
+        Self::SIGCONT,
+        Self::SIGSTOP,
+        Self::SIGTSTP,
+        Self::SIGTTIN,
+        Self::SIGTTOU,
+        Self::SIGURG,
+        Self::SIGXCPU,
+        Self::SIGXFSZ,
+        Self::SIGVTALRM,
+        Self::SIGPROF,
+        Self::SIGWINCH,
+        Self::SIGIO,
+        Self::SIGSYS,
+    ];
+
+    /// The signal with this platform number, or `None` if it isn't in the
+    /// table.
+    #[must_use]
     pub fn try_from_raw(num: i32) -> Option<Self> {
         match num {
             libc::SIGHUP => Some(Self::SIGHUP),
@@ -86,15 +169,77 @@ impl Signal {
         }
     }
 
-    /* get raw signal number */
+    /// The platform's number for this signal.
+    #[must_use]
     #[inline]
     pub const fn as_raw(self) -> i32 {
         self as i32
     }
+
+    /// Whether a process dying of this signal crashed, rather than being
+    /// told to stop: the signals the kernel raises for a fault (and
+    /// SIGABRT, which is how abort() and failed asserts end). On macOS these
+    /// are the ones ReportCrash writes a report for.
+    #[must_use]
+    pub const fn is_crash(self) -> bool {
+        matches!(
+            self,
+            Self::SIGSEGV
+                | Self::SIGBUS
+                | Self::SIGILL
+                | Self::SIGFPE
+                | Self::SIGABRT
+                | Self::SIGTRAP
+                | Self::SIGSYS
+        )
+    }
+
+    /// Whether the default action ends the process. Not for the stop
+    /// signals, nor the ones ignored by default (SIGIO is on macOS).
+    #[must_use]
+    pub const fn terminates(self) -> bool {
+        !matches!(
+            self,
+            Self::SIGSTOP
+                | Self::SIGTSTP
+                | Self::SIGTTIN
+                | Self::SIGTTOU
+                | Self::SIGCHLD
+                | Self::SIGCONT
+                | Self::SIGURG
+                | Self::SIGWINCH
+                | Self::SIGIO
+        )
+    }
+}
+
+/// Die of `sig`: its default action back, unblocked, raised against
+/// ourselves - so our parent sees that signal as the cause of death.
+/// Returns only if `sig` doesn't [terminate](Signal::terminates).
+pub fn raise_default(sig: Signal) {
+    // SAFETY: sigaction and sigset_t are plain old data, zeroed is valid
+    // for sigaction/sigemptyset to fill in; every pointer is to a local
+    // that outlives the call. Grouped since they're one way out.
+    #[allow(clippy::multiple_unsafe_ops_per_block)]
+    unsafe {
+        let mut sa: libc::sigaction = core::mem::zeroed();
+        sa.sa_sigaction = libc::SIG_DFL;
+        libc::sigemptyset(&raw mut sa.sa_mask);
+        libc::sigaction(sig.as_raw(), &raw const sa, core::ptr::null_mut());
+        let mut set: libc::sigset_t = core::mem::zeroed();
+        libc::sigemptyset(&raw mut set);
+        libc::sigaddset(&raw mut set, sig.as_raw());
+        libc::pthread_sigmask(libc::SIG_UNBLOCK, &raw const set, core::ptr::null_mut());
+        libc::raise(sig.as_raw());
+    }
 }
 
 /// Parse "TERM", "SIGKILL", "9", "hup" - all the ways to specify a signal.
 ///
+/// Names are case-insensitive with an optional SIG prefix, and "IOT" is
+/// SIGABRT; numbers are the platform's. Every name [`signal_name`] returns
+/// parses back to its signal.
+///
 /// # Examples
 ///
 /// ```
@@ -193,14 +338,29 @@ pub fn parse_signal(input: &str) -> Result<Signal> {
     }
 }
 
-/* signal number for exit code (128 + signum) */
+/// The platform's number for `signal`, as in the 128 + signum exit code.
+///
+/// ```
+/// use procguard::{Signal, signal_number};
+///
+/// assert_eq!(signal_number(Signal::SIGKILL), 9);
+/// assert_eq!(128 + signal_number(Signal::SIGTERM), 143);
+/// ```
 #[must_use]
 #[inline]
 pub const fn signal_number(signal: Signal) -> i32 {
     signal.as_raw()
 }
 
-/* human-readable name for verbose output */
+/// The canonical name of `signal`, with the SIG prefix: "SIGTERM".
+/// [`parse_signal`] reads it back.
+///
+/// ```
+/// use procguard::{Signal, parse_signal, signal_name};
+///
+/// assert_eq!(signal_name(Signal::SIGABRT), "SIGABRT");
+/// assert_eq!(parse_signal("IOT").map(signal_name).unwrap(), "SIGABRT");
+/// ```
 #[must_use]
 pub const fn signal_name(signal: Signal) -> &'static str {
     match signal {
@@ -304,4 +464,42 @@ mod tests {
         assert_eq!(signal_name(Signal::SIGTERM), "SIGTERM");
         assert_eq!(signal_name(Signal::SIGKILL), "SIGKILL");
     }
+
+    #[test]
+    fn test_table_roundtrip() {
+        assert_eq!(Signal::ALL.len(), 29);
+        for (i, &sig) in Signal::ALL.iter().enumerate() {
+            assert_eq!(parse_signal(signal_name(sig)).unwrap(), sig);
+            assert_eq!(Signal::try_from_raw(sig.as_raw()), Some(sig));
+            assert_eq!(
+                parse_signal(&format!("{}", signal_number(sig))).unwrap(),
+                sig
+            );
+            /* no two entries share a name or a number */
+            for &other in &Signal::ALL[..i] {
+                assert_ne!(signal_name(sig), signal_name(other));
+                assert_ne!(sig.as_raw(), other.as_raw());
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_crash() {
+        assert!(Signal::SIGSEGV.is_crash());
+        assert!(Signal::SIGABRT.is_crash());
+        assert!(Signal::SIGBUS.is_crash());
+        assert!(!Signal::SIGTERM.is_crash());
+        assert!(!Signal::SIGKILL.is_crash());
+        assert!(!Signal::SIGPIPE.is_crash());
+    }
+
+    #[test]
+    fn test_terminates() {
+        assert!(Signal::SIGALRM.terminates());
+        assert!(Signal::SIGKILL.terminates());
+        assert!(Signal::SIGQUIT.terminates());
+        assert!(!Signal::SIGSTOP.terminates());
+        assert!(!Signal::SIGCHLD.terminates());
+        assert!(!Signal::SIGWINCH.terminates());
+    }
 }
@@ -0,0 +1,286 @@
+/*
+ * stats.rs
+ *
+ * pass/fail counts and duration percentiles over the runs of a repeat
+ * (--runs, --every) or the attempts of a --retry, for the JSON "aggregate"
+ * object.
+ *
+ * fixed size and allocation-free: durations go into a log-linear
+ * histogram rather than a list. below 32ms every millisecond has its own
+ * bucket; above, each power of two is split in 16, so a percentile comes
+ * out at most 1/16 (6.25%) over the real one - never under, and never past
+ * the slowest run. min, max and mean are exact. about 3KB, whatever the
+ * number of runs.
+ */
+
+/* buckets per power of two, as a shift */
+const SUB_BITS: u32 = 4;
+const SUB: u64 = 1 << SUB_BITS;
+/* below this, one bucket per millisecond */
+const EXACT: u64 = 2 * SUB;
+/* powers of two kept apart; longer runs (over 8900 years) share the last */
+const OCTAVES: usize = 44;
+const BUCKETS: usize = EXACT as usize + OCTAVES * SUB as usize;
+
+/* the bucket a duration in ms goes in */
+const fn bucket(ms: u64) -> usize {
+    if ms < EXACT {
+        return ms as usize;
+    }
+    let exp = 63 - ms.leading_zeros(); /* >= SUB_BITS + 1 */
+    let octave = (exp - SUB_BITS - 1) as usize;
+    let sub = ((ms >> (exp - SUB_BITS)) & (SUB - 1)) as usize;
+    let index = EXACT as usize + octave * SUB as usize + sub;
+    if index < BUCKETS { index } else { BUCKETS - 1 }
+}
+
+/* the longest duration that goes in bucket `index` */
+const fn bucket_high(index: usize) -> u64 {
+    if index < EXACT as usize {
+        return index as u64;
+    }
+    let octave = ((index - EXACT as usize) / SUB as usize) as u32;
+    let sub = ((index - EXACT as usize) % SUB as usize) as u64;
+    let exp = octave + SUB_BITS + 1;
+    let width = 1u64 << (exp - SUB_BITS);
+    (1u64 << exp) + sub * width + (width - 1)
+}
+
+/// Pass/fail counts and durations over a series of runs.
+///
+/// # Example
+///
+/// ```
+/// use procguard::stats::RunStats;
+///
+/// let mut stats = RunStats::new();
+/// for (passed, ms) in [(true, 120), (false, 300), (true, 110), (true, 130)] {
+///     stats.record(passed, ms);
+/// }
+/// assert_eq!(stats.failed(), 1);
+/// assert_eq!(stats.first_failure(), Some(1));
+/// assert_eq!(stats.success_permille(), Some(750));
+/// assert_eq!(stats.percentile_ms(100), Some(300));
+/// ```
+#[derive(Debug, Clone)]
+pub struct RunStats {
+    runs: u32,
+    passed: u32,
+    first_failure: Option<u32>,
+    min_ms: u64,
+    max_ms: u64,
+    total_ms: u64,
+    buckets: [u32; BUCKETS],
+}
+
+impl Default for RunStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RunStats {
+    /// No runs yet.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            runs: 0,
+            passed: 0,
+            first_failure: None,
+            min_ms: u64::MAX,
+            max_ms: 0,
+            total_ms: 0,
+            buckets: [0; BUCKETS],
+        }
+    }
+
+    /// Count one more run: whether it passed, and how long it took.
+    pub fn record(&mut self, passed: bool, elapsed_ms: u64) {
+        if passed {
+            self.passed = self.passed.saturating_add(1);
+        } else if self.first_failure.is_none() {
+            self.first_failure = Some(self.runs);
+        }
+        self.runs = self.runs.saturating_add(1);
+        self.min_ms = self.min_ms.min(elapsed_ms);
+        self.max_ms = self.max_ms.max(elapsed_ms);
+        self.total_ms = self.total_ms.saturating_add(elapsed_ms);
+        let b = &mut self.buckets[bucket(elapsed_ms)];
+        *b = b.saturating_add(1);
+    }
+
+    /// Runs counted.
+    #[must_use]
+    pub const fn runs(&self) -> u32 {
+        self.runs
+    }
+
+    /// Runs that passed.
+    #[must_use]
+    pub const fn passed(&self) -> u32 {
+        self.passed
+    }
+
+    /// Runs that didn't.
+    #[must_use]
+    pub const fn failed(&self) -> u32 {
+        self.runs - self.passed
+    }
+
+    /// Index of the first run that failed (0 = the first run).
+    #[must_use]
+    pub const fn first_failure(&self) -> Option<u32> {
+        self.first_failure
+    }
+
+    /// Share of runs that passed, in tenths of a percent, rounded down.
+    /// `None` before any run.
+    #[must_use]
+    pub const fn success_permille(&self) -> Option<u32> {
+        if self.runs == 0 {
+            return None;
+        }
+        Some((self.passed as u64 * 1000 / self.runs as u64) as u32)
+    }
+
+    /// Whether more than `permille` tenths of a percent of the runs failed.
+    #[must_use]
+    pub const fn failures_exceed(&self, permille: u32) -> bool {
+        self.failed() as u64 * 1000 > permille as u64 * self.runs as u64
+    }
+
+    /// The fastest run, in ms.
+    #[must_use]
+    pub const fn min_ms(&self) -> Option<u64> {
+        if self.runs == 0 {
+            None
+        } else {
+            Some(self.min_ms)
+        }
+    }
+
+    /// The slowest run, in ms.
+    #[must_use]
+    pub const fn max_ms(&self) -> Option<u64> {
+        if self.runs == 0 {
+            None
+        } else {
+            Some(self.max_ms)
+        }
+    }
+
+    /// The mean duration, in ms, rounded down.
+    #[must_use]
+    pub const fn mean_ms(&self) -> Option<u64> {
+        if self.runs == 0 {
+            None
+        } else {
+            Some(self.total_ms / self.runs as u64)
+        }
+    }
+
+    /// The `pct`th percentile duration in ms (nearest rank; `pct` over 100
+    /// counts as 100). Exact under 32ms, otherwise at most 1/16 over.
+    #[must_use]
+    pub fn percentile_ms(&self, pct: u32) -> Option<u64> {
+        if self.runs == 0 {
+            return None;
+        }
+        let pct = u64::from(pct.min(100));
+        let rank = (pct * u64::from(self.runs)).div_ceil(100).max(1);
+        let mut seen = 0u64;
+        for (index, &count) in self.buckets.iter().enumerate() {
+            seen += u64::from(count);
+            if seen >= rank {
+                return Some(bucket_high(index).clamp(self.min_ms, self.max_ms));
+            }
+        }
+        Some(self.max_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets() {
+        /* every duration lands in a bucket whose top is at or above it,
+         * by at most 1/16 */
+        for ms in (0..5000).chain([u64::from(u32::MAX), 1 << 40]) {
+            let high = bucket_high(bucket(ms));
+            assert!(high >= ms, "{ms}");
+            assert!(high - ms <= ms / SUB, "{ms}: {high}");
+        }
+        for ms in 0..EXACT {
+            assert_eq!(bucket_high(bucket(ms)), ms);
+        }
+        /* buckets are in order */
+        for index in 1..BUCKETS {
+            assert!(bucket_high(index) > bucket_high(index - 1), "{index}");
+        }
+        assert_eq!(bucket(u64::MAX), BUCKETS - 1);
+    }
+
+    #[test]
+    fn test_counts() {
+        let mut stats = RunStats::new();
+        assert_eq!(stats.success_permille(), None);
+        assert_eq!(stats.percentile_ms(50), None);
+        assert_eq!(stats.mean_ms(), None);
+
+        for (passed, ms) in [(true, 10), (true, 20), (false, 30), (false, 40)] {
+            stats.record(passed, ms);
+        }
+        assert_eq!(stats.runs(), 4);
+        assert_eq!(stats.passed(), 2);
+        assert_eq!(stats.failed(), 2);
+        assert_eq!(stats.first_failure(), Some(2));
+        assert_eq!(stats.success_permille(), Some(500));
+        assert_eq!(stats.min_ms(), Some(10));
+        assert_eq!(stats.max_ms(), Some(40));
+        assert_eq!(stats.mean_ms(), Some(25));
+
+        /* 2 of 4 failed: over 49.9%, not over 50% */
+        assert!(stats.failures_exceed(499));
+        assert!(!stats.failures_exceed(500));
+        assert!(stats.failures_exceed(0));
+
+        let mut clean = RunStats::new();
+        clean.record(true, 5);
+        assert!(!clean.failures_exceed(0));
+        assert_eq!(clean.first_failure(), None);
+        assert_eq!(clean.success_permille(), Some(1000));
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let mut stats = RunStats::new();
+        for ms in 1..=20 {
+            stats.record(true, ms);
+        }
+        /* exact below 32ms: nearest rank */
+        assert_eq!(stats.percentile_ms(50), Some(10));
+        assert_eq!(stats.percentile_ms(95), Some(19));
+        assert_eq!(stats.percentile_ms(100), Some(20));
+        assert_eq!(stats.percentile_ms(0), Some(1));
+        assert_eq!(stats.percentile_ms(250), Some(20));
+
+        /* larger: never under the real value, nor past the slowest */
+        let mut stats = RunStats::new();
+        for ms in (1..=100).map(|n| n * 1000) {
+            stats.record(true, ms);
+        }
+        let p50 = stats.percentile_ms(50).unwrap();
+        assert!((50_000..=50_000 + 50_000 / 16).contains(&p50), "{p50}");
+        let p95 = stats.percentile_ms(95).unwrap();
+        assert!((95_000..=100_000).contains(&p95), "{p95}");
+        assert_eq!(stats.percentile_ms(100), Some(100_000));
+
+        /* one run is every percentile */
+        let mut one = RunStats::new();
+        one.record(false, 1234);
+        assert_eq!(one.percentile_ms(50), Some(1234));
+        assert_eq!(one.percentile_ms(95), Some(1234));
+    }
+}
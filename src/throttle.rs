@@ -12,35 +12,274 @@
  * NOTE: Uses SIGSTOP (not catchable by child) and SIGCONT (catchable).
  * Child process may observe SIGCONT signals when throttling is active.
  * Only throttles the main process, not entire process group.
+ *
+ * Without the throttle feature, CpuThrottleState::new always fails, so the
+ * sampling and signaling below never run and drop out of release builds.
+ *
+ * CONTROL: PI on top of the integral "debt" (see update()). integral alone
+ * stops a bursty process only once it's already over, then holds it until
+ * the budget catches up - long stops, visible stutter. two things shorten
+ * them:
+ *
+ * - the measured rate is smoothed (EWMA over --cpu-throttle-window) and a
+ *   proportional term, --cpu-throttle-gain times how far that is from the
+ *   limit over one window, is added to the debt: a process running hot is
+ *   stopped a little early, one that's been stopped a while resumes a
+ *   little early.
+ * - credit is capped at one window's budget, so an idle stretch can't bank
+ *   the minutes of full speed that the next burst would spend at once.
+ *
+ * the debt itself is unchanged, so the long-run average still converges
+ * to the limit. --cpu-throttle-window 0 turns both off: integral only.
+ * with -v, the share of each second the process was let run is logged.
+ *
+ * TIMING: the next look isn't a fixed interval. a stop lasts as long as
+ * the budget takes to catch up with the overshoot (see next_check_ns),
+ * capped so each --cpu-throttle-quantum still leaves the process its
+ * share; a run lasts until its credit could be spent. at 5% that's a
+ * 5ms run and a 95ms stop per 100ms quantum, not a second-long freeze.
+ *
+ * STATS: the state counts its stops and the time they took; stats()
+ * hands them, with the CPU used, to the run's result so the target can
+ * be checked against what was achieved.
+ *
+ * NOTIFY: some commands misbehave when stopped unannounced - a watchdog
+ * thread that sees a gap, a heartbeat connection that drops. with
+ * --cpu-throttle-notify they can be told: TIMEOUT_THROTTLED=1 in their
+ * environment from the start (the runner sets it), and/or SIGUSR1 when
+ * throttling starts and again when it ends. nothing is sent in the first
+ * poll interval, while it may not have a handler yet, and the first stop
+ * is held back a poll interval after the signal so the handler gets to
+ * run; a second without a stop counts as the end. only the command
+ * itself is told.
  */
 
 use core::num::NonZeroU32;
+use core::time::Duration;
 
+use crate::duration::{format_duration, parse_duration};
 use crate::error::{Result, TimeoutError};
+use crate::log::Logger;
 use crate::proc_info;
 
+/// `--cpu-throttle-window` when not given.
+pub const DEFAULT_WINDOW_NS: u64 = 1_000_000_000;
+/// `--cpu-throttle-gain` when not given, in tenths: 0.5.
+pub const DEFAULT_GAIN_TENTHS: u32 = 5;
+/// Largest `--cpu-throttle-gain`, in tenths: 10.0.
+pub const MAX_GAIN_TENTHS: u32 = 100;
+
+/// `--cpu-throttle-quantum` when not given.
+pub const DEFAULT_QUANTUM_NS: u64 = 100_000_000;
+/// Bounds on `--cpu-throttle-quantum`: below 10ms the signals cost more
+/// than they smooth, past a second a stop is a visible freeze.
+pub const MIN_QUANTUM_NS: u64 = 10_000_000;
+pub const MAX_QUANTUM_NS: u64 = 1_000_000_000;
+/// The shortest run or stop worth a wake; a limit that leaves less than
+/// this of a quantum will stutter.
+pub const MIN_SLICE_NS: u64 = 1_000_000;
+
+/// Set to 1 in the command's environment by `--cpu-throttle-notify env`.
+pub const NOTIFY_ENV: &str = "TIMEOUT_THROTTLED";
+
+/* how often -v logs the duty cycle */
+const LOG_INTERVAL_NS: u64 = 1_000_000_000;
+
+/* this long running without a stop and throttling has ended, for
+ * --cpu-throttle-notify signal */
+const DISENGAGE_NS: u64 = 1_000_000_000;
+
+/// `--cpu-throttle-notify`: how the command learns it's being throttled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum ThrottleNotify {
+    /// It isn't told: stops come unannounced
+    #[default]
+    Off,
+    /// `TIMEOUT_THROTTLED=1` in its environment from the start
+    Env,
+    /// SIGUSR1 when throttling starts, and again when it ends
+    Signal,
+    /// Both
+    Both,
+}
+
+impl ThrottleNotify {
+    pub(crate) fn from_str(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "env" => Some(Self::Env),
+            "signal" => Some(Self::Signal),
+            "both" => Some(Self::Both),
+            _ => None,
+        }
+    }
+
+    /// The name `--cpu-throttle-notify` takes, as the JSON output gives it.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Env => "env",
+            Self::Signal => "signal",
+            Self::Both => "both",
+        }
+    }
+
+    /// Whether the command gets [`NOTIFY_ENV`].
+    #[inline]
+    #[must_use]
+    pub const fn sets_env(self) -> bool {
+        matches!(self, Self::Env | Self::Both)
+    }
+
+    /// Whether the command gets SIGUSR1 as throttling starts and ends.
+    #[inline]
+    #[must_use]
+    pub const fn signals(self) -> bool {
+        matches!(self, Self::Signal | Self::Both)
+    }
+}
+
 #[cfg_attr(test, derive(Debug))]
 #[derive(Clone, Copy)]
 pub struct CpuThrottleConfig {
-    pub percent: NonZeroU32, /* 1-100 */
-    pub interval_ns: u64,    /* sampling window */
-    pub sleep_ns: u64,       /* suspension duration when over limit */
+    pub percent: NonZeroU32,    /* 1-100 */
+    pub interval_ns: u64,       /* sampling window */
+    pub quantum_ns: u64,        /* duty-cycle period, bounds each stop */
+    pub window_ns: u64,         /* EWMA window and credit cap, 0 = integral only */
+    pub gain_tenths: u32,       /* proportional gain, 10 = 1.0 */
+    pub notify: ThrottleNotify, /* how the command is told */
+    pub verbose: bool,          /* log the duty cycle every second */
+}
+
+impl CpuThrottleConfig {
+    /// The longest one stop lasts: the quantum less the limit's share of
+    /// it, and never more than half of it for limits of 50% and up.
+    #[must_use]
+    pub fn max_sleep_ns(&self) -> u64 {
+        let share = u64::from(self.percent.get().min(50));
+        self.quantum_ns - self.quantum_ns * share / 100
+    }
+
+    /// Whether the limit's share of a quantum is under [`MIN_SLICE_NS`].
+    #[must_use]
+    pub fn stutters(&self) -> bool {
+        u128::from(self.quantum_ns) * u128::from(self.percent.get()) / 100
+            < u128::from(MIN_SLICE_NS)
+    }
+}
+
+/// What the throttle did: for one attempt, or summed over all of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThrottleStats {
+    /// Time the command was held stopped.
+    pub stopped_ns: u64,
+    /// SIGSTOPs that took: stop/cont cycles.
+    pub stops: u32,
+    /// CPU the command used while throttled.
+    pub cpu_ns: u64,
+    /// Wall time the throttle watched it.
+    pub wall_ns: u64,
+}
+
+impl ThrottleStats {
+    /// Fold in another attempt's.
+    pub fn add(&mut self, other: &Self) {
+        self.stopped_ns = self.stopped_ns.saturating_add(other.stopped_ns);
+        self.stops = self.stops.saturating_add(other.stops);
+        self.cpu_ns = self.cpu_ns.saturating_add(other.cpu_ns);
+        self.wall_ns = self.wall_ns.saturating_add(other.wall_ns);
+    }
+
+    /// The CPU percentage achieved, in tenths (505 is 50.5%).
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn cpu_percent_tenths(&self) -> u64 {
+        if self.wall_ns == 0 {
+            return 0;
+        }
+        (u128::from(self.cpu_ns) * 1000 / u128::from(self.wall_ns)).min(u128::from(u64::MAX)) as u64
+    }
+}
+
+/// Parse `--cpu-throttle-quantum`: a duration from 10ms to 1s, in ns.
+pub fn parse_quantum(input: &str) -> Result<u64> {
+    let quantum = parse_duration(input)?;
+    let bounds = Duration::from_nanos(MIN_QUANTUM_NS)..=Duration::from_nanos(MAX_QUANTUM_NS);
+    if !bounds.contains(&quantum) {
+        return Err(TimeoutError::InvalidOptions(alloc::format!(
+            "--cpu-throttle-quantum must be between {} and {}, not '{}'",
+            format_duration(*bounds.start()),
+            format_duration(*bounds.end()),
+            input
+        )));
+    }
+    Ok(u64::try_from(quantum.as_nanos()).unwrap_or(MAX_QUANTUM_NS))
+}
+
+/// Parse `--cpu-throttle-gain`: a number from 0 to 10 with at most one
+/// decimal, returned in tenths ("0.5" is 5).
+pub fn parse_gain(input: &str) -> Result<u32> {
+    let invalid = || {
+        TimeoutError::InvalidOptions(alloc::format!(
+            "invalid --cpu-throttle-gain: '{input}' (a number from 0 to 10, like 0.5)"
+        ))
+    };
+    let s = input.trim();
+    let (whole, tenth) = s.split_once('.').unwrap_or((s, "0"));
+    if whole.is_empty() || tenth.len() != 1 || !whole.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let whole: u32 = whole.parse().map_err(|_| invalid())?;
+    let tenth: u32 = tenth.parse().map_err(|_| invalid())?;
+    let tenths = whole
+        .checked_mul(10)
+        .and_then(|w| w.checked_add(tenth))
+        .ok_or_else(invalid)?;
+    if tenths > MAX_GAIN_TENTHS {
+        return Err(invalid());
+    }
+    Ok(tenths)
+}
+
+/// The control signal in ns of CPU: stop while positive. `debt_ns` is the
+/// CPU used past the budget so far (negative: under it); `smoothed` the
+/// smoothed rate in thousandths of a percent. The proportional term is
+/// the gain times what that rate would overrun the limit by in one window.
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn control_ns(debt_ns: i64, smoothed: u64, cfg: &CpuThrottleConfig) -> i64 {
+    let over = i128::from(smoothed) - i128::from(cfg.percent.get()) * 1000;
+    let proportional =
+        over * i128::from(cfg.gain_tenths) * i128::from(cfg.window_ns) / (10 * 1000 * 100);
+    (i128::from(debt_ns) + proportional).clamp(i128::from(i64::MIN), i128::from(i64::MAX)) as i64
 }
 
 #[cfg_attr(test, derive(Debug))]
 #[derive(Clone)]
 pub struct CpuThrottleState {
     pid: i32,
-    /* integral control: track cumulative time from start, not per-interval deltas.
-     * compares total_cpu / total_wall against target to create "debt" mechanism
-     * that converges to exact target percentage over process lifetime.
-     * TODO: consider PI control if integral-only shows initial oscillation. */
+    /* integral control: debt is the CPU used past the budget since attach,
+     * summed from per-interval deltas. it's what makes the average converge
+     * to the target; the window only caps how far below zero it may go */
     start_cpu_ns: u64,     /* CPU time at throttle attach */
     start_wall_ns: u64,    /* wall time at throttle attach */
     pub last_cpu_ns: u64,  /* most recent CPU time reading */
     pub last_wall_ns: u64, /* most recent wall time reading */
+    debt_ns: i64,          /* CPU past the budget; negative = credit */
+    smoothed: Option<u64>, /* EWMA of the rate, thousandths of a percent */
     suspended: bool,       /* track if we've sent SIGSTOP */
-    process_exited: bool,  /* set when process is known dead - prevents PID recycling issues */
+    stops: u32,            /* SIGSTOPs that took */
+    stopped_ns: u64,       /* time held stopped, up to the last update */
+    /* --cpu-throttle-notify signal: told throttling started, and when it
+     * was last held back (stopped, or told) */
+    engaged: bool,
+    held_ns: u64,
+    process_exited: bool, /* set when process is known dead - prevents PID recycling issues */
+    /* -v duty-cycle log: since log_start_ns, CPU used and time stopped */
+    log_start_ns: u64,
+    log_cpu_ns: u64,
+    log_stopped_ns: u64,
 }
 
 impl CpuThrottleState {
@@ -49,6 +288,9 @@ impl CpuThrottleState {
     }
 
     pub fn new(pid: i32, now_ns: u64) -> Result<Self> {
+        if !cfg!(feature = "throttle") {
+            return Err(TimeoutError::ThrottleAttachError(libc::ENOTSUP));
+        }
         /* get initial CPU time via proc_pid_rusage - no entitlements needed */
         let initial_cpu_ns = proc_info::get_process_cpu_time(pid)
             .ok_or(TimeoutError::ThrottleAttachError(libc::ESRCH))?;
@@ -59,8 +301,17 @@ impl CpuThrottleState {
             start_wall_ns: now_ns,
             last_cpu_ns: initial_cpu_ns,
             last_wall_ns: now_ns,
+            debt_ns: 0,
+            smoothed: None,
             suspended: false,
+            stops: 0,
+            stopped_ns: 0,
+            engaged: false,
+            held_ns: now_ns,
             process_exited: false,
+            log_start_ns: now_ns,
+            log_cpu_ns: 0,
+            log_stopped_ns: 0,
         })
     }
 
@@ -79,6 +330,11 @@ impl CpuThrottleState {
         }
     }
 
+    /* whether we have it stopped now - a stop that isn't the command's */
+    pub fn is_suspended(&self) -> bool {
+        self.suspended
+    }
+
     /* mark process as exited - prevents PID recycling issues where Drop
      * could SIGCONT an unrelated process that got the recycled PID. */
     pub fn mark_process_exited(&mut self) {
@@ -92,12 +348,32 @@ impl CpuThrottleState {
      * If total_cpu > total_wall * target%, process is "in debt" and gets stopped.
      * If total_cpu <= total_wall * target%, process is "under budget" and runs.
      * This mathematically converges to exact target percentage over process lifetime.
+     * The proportional term (see control_ns) moves each stop and resume a
+     * little earlier; it doesn't change the debt.
      *
      * Previous delta-based approach compared interval-local usage, which aliased with
      * the scheduler and converged to ~50% duty cycle regardless of target. */
-    pub fn update(&mut self, cfg: &CpuThrottleConfig, now_ns: u64) -> Result<bool> {
-        /* get current CPU time */
-        let current_cpu_ns = match proc_info::get_process_cpu_time(self.pid) {
+    pub fn update(
+        &mut self,
+        cfg: &CpuThrottleConfig,
+        now_ns: u64,
+        log: Logger<'_>,
+    ) -> Result<bool> {
+        self.update_with(cfg, now_ns, proc_info::get_process_cpu_time(self.pid), log)
+    }
+
+    /* update() with the CPU time already read - the wait loop samples the
+     * child once per wake and hands the same reading to every check.
+     * None means the read failed: the process is gone. the duty cycle
+     * goes to log with cfg.verbose */
+    pub fn update_with(
+        &mut self,
+        cfg: &CpuThrottleConfig,
+        now_ns: u64,
+        cpu_ns: Option<u64>,
+        log: Logger<'_>,
+    ) -> Result<bool> {
+        let current_cpu_ns = match cpu_ns {
             Some(t) => t,
             None => {
                 /* process gone - mark as exited and not suspended */
@@ -108,45 +384,173 @@ impl CpuThrottleState {
             }
         };
 
-        /* integral control: compare cumulative totals from start */
-        let total_wall_ns = now_ns.saturating_sub(self.start_wall_ns);
-        let total_cpu_ns = current_cpu_ns.saturating_sub(self.start_cpu_ns);
+        /* integral control: this interval's use against its budget */
+        let wall_ns = now_ns.saturating_sub(self.last_wall_ns);
+        let cpu_ns = current_cpu_ns.saturating_sub(self.last_cpu_ns);
 
         /* avoid division by zero on first call */
-        if total_wall_ns == 0 {
+        if wall_ns == 0 {
             self.last_cpu_ns = current_cpu_ns;
             self.last_wall_ns = now_ns;
             return Ok(self.suspended);
         }
-
-        /* cpu_budget_ns = total_wall_ns * (percent / 100)
-         * use u128 intermediate to prevent overflow.
-         * note: percent can exceed 100 for multi-core systems. */
-        let limit = u64::from(cfg.percent.get());
-        #[allow(clippy::cast_possible_truncation)]
-        let cpu_budget_ns = ((total_wall_ns as u128 * limit as u128) / 100) as u64;
-
-        /* if over budget and not already suspended, SIGSTOP */
-        if total_cpu_ns > cpu_budget_ns && !self.suspended {
+        let stopped_ns = if self.suspended { wall_ns } else { 0 };
+        self.stopped_ns = self.stopped_ns.saturating_add(stopped_ns);
+        self.advance(cfg, cpu_ns, wall_ns);
+
+        /* over (or about to be) and not already suspended: SIGSTOP */
+        let over = control_ns(self.debt_ns, self.smoothed(cfg), cfg) > 0;
+        if over && !self.suspended && cfg.notify.signals() && !self.engaged {
+            /* told first, and not in its first poll interval - starting up
+             * is a burst, and it may not have a handler yet. the stop waits
+             * for the next look */
+            if now_ns.saturating_sub(self.start_wall_ns) >= cfg.interval_ns {
+                self.engaged = true;
+                self.notify();
+            }
+        } else if over && !self.suspended {
             // SAFETY: kill with SIGSTOP is safe
             if unsafe { libc::kill(self.pid, libc::SIGSTOP) } == 0 {
                 self.suspended = true;
+                self.stops = self.stops.saturating_add(1);
             }
         }
         /* if under/at budget and suspended, SIGCONT */
-        else if total_cpu_ns <= cpu_budget_ns && self.suspended {
+        else if !over && self.suspended {
             // SAFETY: kill with SIGCONT is safe
             if unsafe { libc::kill(self.pid, libc::SIGCONT) } == 0 {
                 self.suspended = false;
             }
         }
 
+        /* held back now, or a second of running since: throttling ended */
+        if over || self.suspended {
+            self.held_ns = now_ns;
+        } else if self.engaged && now_ns.saturating_sub(self.held_ns) >= DISENGAGE_NS {
+            self.engaged = false;
+            self.notify();
+        }
+
         /* update state for next interval */
         self.last_cpu_ns = current_cpu_ns;
         self.last_wall_ns = now_ns;
 
+        if cfg.verbose {
+            self.log_duty_cycle(cfg, now_ns, cpu_ns, stopped_ns, log);
+        }
         Ok(self.suspended)
     }
+
+    /* --cpu-throttle-notify signal: throttling started or ended */
+    fn notify(&self) {
+        // SAFETY: kill with SIGUSR1 is safe; ESRCH (process gone) is fine
+        unsafe { libc::kill(self.pid, libc::SIGUSR1) };
+    }
+
+    /// What the throttle has done so far, up to the last update.
+    #[must_use]
+    pub fn stats(&self) -> ThrottleStats {
+        ThrottleStats {
+            stopped_ns: self.stopped_ns,
+            stops: self.stops,
+            cpu_ns: self.last_cpu_ns.saturating_sub(self.start_cpu_ns),
+            wall_ns: self.last_wall_ns.saturating_sub(self.start_wall_ns),
+        }
+    }
+
+    /* how long until the next look. stopped: until the budget, growing
+     * percent/100 per ns of wall, has caught up with the overshoot - at
+     * most one stop (max_sleep_ns). running: until it could spend its
+     * credit flat out on one core - at most the poll interval, which is
+     * also all there is to go on above 100%. never under MIN_SLICE_NS */
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn next_check_ns(&self, cfg: &CpuThrottleConfig) -> u64 {
+        let control = control_ns(self.debt_ns, self.smoothed(cfg), cfg);
+        let percent = u128::from(cfg.percent.get());
+        let ns = if self.process_exited || (!self.suspended && (control > 0 || percent >= 100)) {
+            /* gone, a SIGSTOP that didn't take, or no single-core bound */
+            u128::from(cfg.interval_ns)
+        } else if self.suspended {
+            (u128::from(control.unsigned_abs()) * 100 / percent).min(u128::from(cfg.max_sleep_ns()))
+        } else {
+            (u128::from(control.unsigned_abs()) * 100 / (100 - percent))
+                .min(u128::from(cfg.interval_ns))
+        };
+        (ns as u64).max(MIN_SLICE_NS)
+    }
+
+    /* fold one interval (cpu_ns used in wall_ns, wall_ns > 0) into the debt
+     * and the smoothed rate */
+    #[allow(clippy::cast_possible_truncation)]
+    fn advance(&mut self, cfg: &CpuThrottleConfig, cpu_ns: u64, wall_ns: u64) {
+        /* budget = wall * (percent / 100), u128 so it can't overflow.
+         * note: percent can exceed 100 for multi-core systems. */
+        let limit = u128::from(cfg.percent.get());
+        let budget_ns = (u128::from(wall_ns) * limit / 100) as u64;
+        let delta = i128::from(cpu_ns) - i128::from(budget_ns);
+        let mut debt = (i128::from(self.debt_ns) + delta).min(i128::from(i64::MAX));
+        /* anti-windup: no more credit than one window's budget */
+        if cfg.window_ns > 0 {
+            let cap = (u128::from(cfg.window_ns) * limit / 100).min(i64::MAX as u128) as i128;
+            debt = debt.max(-cap);
+        }
+        self.debt_ns = debt.max(i128::from(i64::MIN)) as i64;
+
+        /* EWMA: each interval moves it wall/window of the way (all of it
+         * for an interval as long as the window) */
+        let rate = (u128::from(cpu_ns) * 100_000 / u128::from(wall_ns)).min(u128::from(u64::MAX));
+        let rate = rate as u64;
+        let prev = self.smoothed(cfg);
+        self.smoothed = Some(if wall_ns < cfg.window_ns {
+            let step = (i128::from(rate) - i128::from(prev)) * i128::from(wall_ns)
+                / i128::from(cfg.window_ns);
+            (i128::from(prev) + step) as u64
+        } else {
+            rate
+        });
+    }
+
+    /* the smoothed rate, starting from the limit: a first reading taken
+     * as is - one core flat out for the first few ms - would hold a low
+     * limit stopped for seconds */
+    fn smoothed(&self, cfg: &CpuThrottleConfig) -> u64 {
+        self.smoothed.unwrap_or(u64::from(cfg.percent.get()) * 1000)
+    }
+
+    /* -v: every LOG_INTERVAL_NS, how much of it the process was let run */
+    fn log_duty_cycle(
+        &mut self,
+        cfg: &CpuThrottleConfig,
+        now_ns: u64,
+        cpu_ns: u64,
+        stopped_ns: u64,
+        log: Logger<'_>,
+    ) {
+        self.log_cpu_ns = self.log_cpu_ns.saturating_add(cpu_ns);
+        self.log_stopped_ns = self.log_stopped_ns.saturating_add(stopped_ns);
+        let period_ns = now_ns.saturating_sub(self.log_start_ns);
+        if period_ns < LOG_INTERVAL_NS {
+            return;
+        }
+        let ran_ns = period_ns.saturating_sub(self.log_stopped_ns);
+        crate::log_info!(
+            log,
+            "cpu throttle: ran {}% of the last {}ms at {}% CPU (smoothed {}%, {}% overall, limit {}%)",
+            calculate_cpu_percent(ran_ns, period_ns),
+            period_ns / 1_000_000,
+            calculate_cpu_percent(self.log_cpu_ns, period_ns),
+            self.smoothed(cfg) / 1000,
+            calculate_cpu_percent(
+                self.last_cpu_ns.saturating_sub(self.start_cpu_ns),
+                now_ns.saturating_sub(self.start_wall_ns)
+            ),
+            cfg.percent.get()
+        );
+        self.log_start_ns = now_ns;
+        self.log_cpu_ns = 0;
+        self.log_stopped_ns = 0;
+    }
 }
 
 impl Drop for CpuThrottleState {
@@ -157,7 +561,7 @@ impl Drop for CpuThrottleState {
 }
 
 /* get number of logical CPU cores via sysctl */
-#[cfg(test)]
+#[cfg(all(test, target_os = "macos"))]
 fn get_cpu_core_count() -> Option<u32> {
     let mut count: i32 = 0;
     let mut size = core::mem::size_of::<i32>();
@@ -181,6 +585,14 @@ fn get_cpu_core_count() -> Option<u32> {
     }
 }
 
+/* linux: online CPUs via sysconf */
+#[cfg(all(test, target_os = "linux"))]
+fn get_cpu_core_count() -> Option<u32> {
+    // SAFETY: sysconf takes no pointers
+    let count = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+    u32::try_from(count).ok().filter(|&n| n > 0)
+}
+
 /* helper to calculate cpu percent - exposed for testing */
 #[inline]
 #[allow(clippy::cast_possible_truncation)]
@@ -288,13 +700,18 @@ mod tests {
         let cfg = CpuThrottleConfig {
             percent: NonZeroU32::new(50).unwrap(),
             interval_ns: 100_000_000,
-            sleep_ns: 50_000_000,
+            quantum_ns: DEFAULT_QUANTUM_NS,
+            window_ns: DEFAULT_WINDOW_NS,
+            gain_tenths: DEFAULT_GAIN_TENTHS,
+            notify: ThrottleNotify::Off,
+            verbose: false,
         };
         assert_eq!(cfg.percent.get(), 50);
         assert_eq!(cfg.interval_ns, 100_000_000);
     }
 
     #[test]
+    #[cfg(feature = "throttle")]
     #[cfg_attr(miri, ignore)]
     fn test_throttle_state_new_self() {
         /* we should be able to create throttle state for ourselves */
@@ -343,6 +760,7 @@ mod tests {
 
     #[test]
     #[cfg_attr(miri, ignore)]
+    #[cfg(feature = "throttle")]
     fn test_throttle_state_has_start_fields() {
         /* verify integral control fields are initialized */
         // SAFETY: getpid() always succeeds
@@ -369,6 +787,7 @@ mod tests {
 
     #[test]
     #[cfg_attr(miri, ignore)]
+    #[cfg(feature = "throttle")]
     fn test_resume_when_not_suspended() {
         /* resume on non-suspended state should be safe no-op */
         // SAFETY: getpid() always succeeds
@@ -383,6 +802,191 @@ mod tests {
         assert!(!state.suspended);
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    #[cfg(feature = "throttle")]
+    fn test_update_with_reading() {
+        /* a shared reading is used as is; no reading means the process is gone */
+        // SAFETY: getpid() always succeeds
+        let pid = unsafe { libc::getpid() };
+        let cfg = CpuThrottleConfig {
+            percent: NonZeroU32::new(50).unwrap(),
+            interval_ns: 100_000_000,
+            quantum_ns: DEFAULT_QUANTUM_NS,
+            window_ns: DEFAULT_WINDOW_NS,
+            gain_tenths: DEFAULT_GAIN_TENTHS,
+            notify: ThrottleNotify::Off,
+            verbose: false,
+        };
+        let mut state = CpuThrottleState::new(pid, 1_000_000_000).unwrap();
+        let cpu_ns = state.start_cpu_ns + 100_000_000;
+
+        /* 100ms of CPU over 1s of wall is under 50% */
+        assert!(
+            !state
+                .update_with(&cfg, 2_000_000_000, Some(cpu_ns), Logger::default())
+                .unwrap()
+        );
+        assert_eq!(state.last_cpu_ns, cpu_ns);
+        assert_eq!(state.last_wall_ns, 2_000_000_000);
+
+        assert!(
+            !state
+                .update_with(&cfg, 3_000_000_000, None, Logger::default())
+                .unwrap()
+        );
+        assert!(state.process_exited);
+        assert_eq!(state.last_cpu_ns, cpu_ns);
+    }
+
+    /* a state for the control math alone: no process behind it */
+    fn detached_state() -> CpuThrottleState {
+        CpuThrottleState {
+            pid: 0,
+            start_cpu_ns: 0,
+            start_wall_ns: 0,
+            last_cpu_ns: 0,
+            last_wall_ns: 0,
+            debt_ns: 0,
+            smoothed: None,
+            suspended: false,
+            stops: 0,
+            stopped_ns: 0,
+            engaged: false,
+            held_ns: 0,
+            process_exited: true,
+            log_start_ns: 0,
+            log_cpu_ns: 0,
+            log_stopped_ns: 0,
+        }
+    }
+
+    fn pi_config(window_ns: u64, gain_tenths: u32) -> CpuThrottleConfig {
+        CpuThrottleConfig {
+            percent: NonZeroU32::new(50).unwrap(),
+            interval_ns: 100_000_000,
+            quantum_ns: DEFAULT_QUANTUM_NS,
+            window_ns,
+            gain_tenths,
+            notify: ThrottleNotify::Off,
+            verbose: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_gain() {
+        assert_eq!(parse_gain("0.5").unwrap(), 5);
+        assert_eq!(parse_gain("1").unwrap(), 10);
+        assert_eq!(parse_gain(" 2.5 ").unwrap(), 25);
+        assert_eq!(parse_gain("0").unwrap(), 0);
+        assert_eq!(parse_gain("10").unwrap(), MAX_GAIN_TENTHS);
+        for bad in ["", ".5", "0.25", "1.", "-1", "10.1", "abc", "1e2"] {
+            assert!(parse_gain(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn test_parse_quantum() {
+        assert_eq!(parse_quantum("100ms").unwrap(), DEFAULT_QUANTUM_NS);
+        assert_eq!(parse_quantum("10ms").unwrap(), MIN_QUANTUM_NS);
+        assert_eq!(parse_quantum("1s").unwrap(), MAX_QUANTUM_NS);
+        for bad in ["5ms", "2s", "0", "soon"] {
+            assert!(parse_quantum(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn test_max_sleep_and_stutter() {
+        let mut cfg = pi_config(DEFAULT_WINDOW_NS, DEFAULT_GAIN_TENTHS);
+        cfg.percent = NonZeroU32::new(5).unwrap();
+        assert_eq!(cfg.max_sleep_ns(), 95_000_000);
+        assert!(!cfg.stutters());
+        /* half a quantum at most, however generous the limit */
+        cfg.percent = NonZeroU32::new(400).unwrap();
+        assert_eq!(cfg.max_sleep_ns(), 50_000_000);
+        /* 5% of 10ms is half a millisecond */
+        cfg.percent = NonZeroU32::new(5).unwrap();
+        cfg.quantum_ns = MIN_QUANTUM_NS;
+        assert!(cfg.stutters());
+    }
+
+    #[test]
+    fn test_next_check_sized_to_overshoot() {
+        let mut cfg = pi_config(0, 0);
+        cfg.percent = NonZeroU32::new(5).unwrap();
+        let mut state = detached_state();
+        state.process_exited = false;
+
+        /* 1ms over at 5%: the budget takes 20ms to catch up */
+        state.suspended = true;
+        state.debt_ns = 1_000_000;
+        assert_eq!(state.next_check_ns(&cfg), 20_000_000);
+        /* far over: one stop, then a look */
+        state.debt_ns = 50_000_000;
+        assert_eq!(state.next_check_ns(&cfg), cfg.max_sleep_ns());
+
+        /* 4.75ms of credit at 5% lasts 5ms flat out */
+        state.suspended = false;
+        state.debt_ns = -4_750_000;
+        assert_eq!(state.next_check_ns(&cfg), 5_000_000);
+        state.debt_ns = -1_000_000_000;
+        assert_eq!(state.next_check_ns(&cfg), cfg.interval_ns);
+        state.debt_ns = 0;
+        assert_eq!(state.next_check_ns(&cfg), MIN_SLICE_NS);
+
+        /* above one core there's no bound but the poll interval */
+        cfg.percent = NonZeroU32::new(200).unwrap();
+        state.debt_ns = -1_000_000;
+        assert_eq!(state.next_check_ns(&cfg), cfg.interval_ns);
+    }
+
+    #[test]
+    fn test_control_ns_proportional_term() {
+        /* at the limit, only the debt counts */
+        let cfg = pi_config(1_000_000_000, 10);
+        assert_eq!(control_ns(-7, 50_000, &cfg), -7);
+        /* 10 points over for a 1s window at gain 1.0: 100ms early */
+        assert_eq!(control_ns(0, 60_000, &cfg), 100_000_000);
+        assert_eq!(control_ns(-50_000_000, 60_000, &cfg), 50_000_000);
+        /* stopped, the rate falls and it resumes before the debt is paid */
+        assert!(control_ns(50_000_000, 0, &cfg) < 0);
+        /* window 0 or gain 0: integral only */
+        assert_eq!(control_ns(3, 400_000, &pi_config(0, 10)), 3);
+        assert_eq!(control_ns(3, 400_000, &pi_config(1_000_000_000, 0)), 3);
+    }
+
+    #[test]
+    fn test_credit_capped_at_one_window() {
+        /* idle for 10s at a 50% limit: 5s of credit without a cap, 500ms
+         * (one 1s window's budget) with one */
+        let mut state = detached_state();
+        state.advance(&pi_config(1_000_000_000, 5), 0, 10_000_000_000);
+        assert_eq!(state.debt_ns, -500_000_000);
+        let mut state = detached_state();
+        state.advance(&pi_config(0, 5), 0, 10_000_000_000);
+        assert_eq!(state.debt_ns, -5_000_000_000);
+        /* debt isn't capped: overuse is always paid back */
+        state.advance(&pi_config(1_000_000_000, 5), 20_000_000_000, 10_000_000_000);
+        assert_eq!(state.debt_ns, 10_000_000_000);
+    }
+
+    #[test]
+    fn test_smoothing_follows_window() {
+        let cfg = pi_config(1_000_000_000, 5);
+        let mut state = detached_state();
+        /* it starts at the limit: 100ms flat out moves it a tenth of the
+         * way to 100 */
+        assert_eq!(state.smoothed(&cfg), 50_000);
+        state.advance(&cfg, 100_000_000, 100_000_000);
+        assert_eq!(state.smoothed, Some(55_000));
+        /* 100ms idle moves it a tenth of the way to 0 */
+        state.advance(&cfg, 0, 100_000_000);
+        assert_eq!(state.smoothed, Some(49_500));
+        /* an interval as long as the window replaces it */
+        state.advance(&cfg, 250_000_000, 1_000_000_000);
+        assert_eq!(state.smoothed, Some(25_000));
+    }
+
     /* helper to calculate budget for integral control testing */
     fn calculate_cpu_budget_ns(total_wall_ns: u64, percent: u32) -> u64 {
         ((total_wall_ns as u128 * percent as u128) / 100) as u64
@@ -16,79 +16,67 @@
  * - advance_deadline(base, offset_ns): safely add signed offset to u64
  */
 
-/*
- * Calculate elapsed time in nanoseconds: now - start
- *
- * Returns None if now < start (invariant violation - clock went backwards
- * or arguments swapped). Callers should handle this as a bug, not silently
- * clamp to 0 like saturating_sub would.
- *
- * Uses u64::checked_sub which returns None only when now < start,
- * correctly handling all valid u64 time differences.
- */
+/// Calculate elapsed time in nanoseconds: now - start
+///
+/// Returns None if now < start (invariant violation - clock went backwards
+/// or arguments swapped). Callers should handle this as a bug, not silently
+/// clamp to 0 like saturating_sub would.
+///
+/// Uses u64::checked_sub which returns None only when now < start,
+/// correctly handling all valid u64 time differences.
 #[inline]
 pub const fn elapsed_ns(start_ns: u64, now_ns: u64) -> Option<u64> {
     now_ns.checked_sub(start_ns)
 }
 
-/*
- * Calculate remaining time until deadline, clamped to 0 on overshoot.
- *
- * Unlike elapsed_ns, overshooting a deadline is expected (not a bug).
- * Returns 0 when now >= deadline, otherwise returns deadline - now.
- *
- * Uses saturating_sub intentionally - deadline overshoot is normal.
- */
+/// Calculate remaining time until deadline, clamped to 0 on overshoot.
+///
+/// Unlike elapsed_ns, overshooting a deadline is expected (not a bug).
+/// Returns 0 when now >= deadline, otherwise returns deadline - now.
+///
+/// Uses saturating_sub intentionally - deadline overshoot is normal.
 #[inline]
 pub const fn remaining_ns(now_ns: u64, deadline_ns: u64) -> u64 {
     deadline_ns.saturating_sub(now_ns)
 }
 
-/*
- * Check if deadline has been reached: now >= deadline
- *
- * Clearer intent than `remaining_ns(now, deadline) == 0`
- */
+/// Check if deadline has been reached: now >= deadline
+///
+/// Clearer intent than `remaining_ns(now, deadline) == 0`
 #[inline]
 pub const fn deadline_reached(now_ns: u64, deadline_ns: u64) -> bool {
     now_ns >= deadline_ns
 }
 
-/*
- * Advance a timestamp by a nanosecond offset.
- *
- * Uses saturating_add - overflow to u64::MAX is acceptable for deadlines
- * (effectively "never timeout" rather than wrap to small value).
- */
+/// Advance a timestamp by a nanosecond offset.
+///
+/// Uses saturating_add - overflow to u64::MAX is acceptable for deadlines
+/// (effectively "never timeout" rather than wrap to small value).
 #[inline]
 pub const fn advance_ns(base_ns: u64, offset_ns: u64) -> u64 {
     base_ns.saturating_add(offset_ns)
 }
 
-/*
- * Adjust a timestamp by a signed offset (can be negative).
- *
- * Uses u64::checked_add_signed (stabilized 1.66.0) for safety.
- * Returns None if result would be negative or overflow u64.
- *
- * Useful for clock skew adjustments or relative time calculations.
- */
+/// Adjust a timestamp by a signed offset (can be negative).
+///
+/// Uses u64::checked_add_signed (stabilized 1.66.0) for safety.
+/// Returns None if result would be negative or overflow u64.
+///
+/// Useful for clock skew adjustments or relative time calculations.
 #[inline]
 pub const fn adjust_ns(base_ns: u64, offset_ns: i64) -> Option<u64> {
     base_ns.checked_add_signed(offset_ns)
 }
 
-/*
- * Check if idle timeout exceeded: (now - last_activity) >= timeout
- *
- * Returns:
- * - Some(true) if idle timeout exceeded
- * - Some(false) if still within timeout
- * - None if now < last_activity (invariant violation)
- *
- * Using checked_signed_diff ensures we detect clock anomalies rather than
- * silently returning "not idle" due to saturating_sub clamping to 0.
- */
+/// Check if idle timeout exceeded: (now - last_activity) >= timeout
+///
+/// Returns:
+/// - Some(true) if idle timeout exceeded
+/// - Some(false) if still within timeout
+/// - None if now < last_activity (invariant violation)
+///
+/// Using checked_signed_diff ensures we detect clock anomalies rather than
+/// silently returning "not idle" due to saturating_sub clamping to 0.
 #[inline]
 pub const fn idle_timeout_exceeded(
     last_activity_ns: u64,
@@ -101,14 +89,12 @@ pub const fn idle_timeout_exceeded(
     }
 }
 
-/*
- * Calculate time remaining until idle timeout.
- *
- * Returns:
- * - Some(remaining) if still within timeout (remaining > 0)
- * - Some(0) if timeout already exceeded
- * - None if now < last_activity (invariant violation)
- */
+/// Calculate time remaining until idle timeout.
+///
+/// Returns:
+/// - Some(remaining) if still within timeout (remaining > 0)
+/// - Some(0) if timeout already exceeded
+/// - None if now < last_activity (invariant violation)
 #[inline]
 pub const fn time_to_idle_timeout(
     last_activity_ns: u64,
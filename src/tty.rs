@@ -0,0 +1,437 @@
+/*
+ * tty.rs
+ *
+ * controlling terminal handling for interactive children.
+ *
+ * --tty-foreground hands the terminal to the child's process group, so
+ * Ctrl-C/Ctrl-Z and terminal reads go to the child instead of us. once we
+ * give it away we're a background process, and tcsetpgrp() from the
+ * background raises SIGTTOU - so SIGTTOU is blocked around every call.
+ *
+ * --restore-tty saves the terminal settings before the child starts and
+ * puts them back afterwards. a full-screen app killed mid-flight leaves the
+ * line discipline raw and the screen in alternate mode with the cursor
+ * hidden; TermiosGuard undoes both.
+ *
+ * both guards undo their work on drop, which covers every way out of the
+ * runner: normal exit, timeout, SIGKILL escalation, errors. the runner
+ * creates TermiosGuard first so it drops last - after the child is reaped
+ * and after the foreground group is ours again.
+ *
+ * --stdin-mode pty gives the child a pseudo-terminal for stdin and types
+ * into it whatever arrives on ours, so --stdin-timeout sees each keystroke
+ * without taking it from the child. the pty gets our terminal's settings
+ * and size, and does the line editing and echo itself (its echo is copied
+ * back to our terminal); ours goes non-canonical with echo off, so keys
+ * reach the pty one at a time and a full-screen child that switches the
+ * pty to raw mode gets them as it expects. ISIG stays on: Ctrl-C is still
+ * our terminal's, and signals the foreground group as before. the pty
+ * isn't the child's controlling terminal - /dev/tty is still ours, and
+ * window size changes after the start aren't passed on. with stdin not a
+ * terminal, the pty just carries it, EOF included.
+ *
+ * --stdin-inject-on-timeout needs a way into the child's stdin, so its
+ * stdin goes through us there too: the pty if there is one, else a pipe.
+ * the bytes go in on timeout, after whatever we'd passed on - unless our
+ * stdin had already ended and closed the pipe.
+ */
+
+use core::cell::Cell;
+
+use crate::errno::errno;
+
+/* open our controlling terminal without ever acquiring one */
+fn open_tty() -> Option<i32> {
+    // SAFETY: path is a NUL-terminated literal; O_NOCTTY keeps open() from
+    // making it our controlling terminal if we don't have one.
+    let fd = unsafe {
+        libc::open(
+            c"/dev/tty".as_ptr(),
+            libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC,
+        )
+    };
+    (fd >= 0).then_some(fd)
+}
+
+/* run f with SIGTTOU blocked - terminal writes/ioctls from a background
+ * group would otherwise stop us */
+fn without_sigttou<T>(f: impl FnOnce() -> T) -> T {
+    with_blocked(libc::SIGTTOU, f)
+}
+
+/* run f with SIGPIPE blocked, and drop one it raised - writing to a pipe
+ * the child closed would otherwise kill us. setting SIG_IGN discards a
+ * pending SIGPIPE; the old disposition goes straight back */
+fn without_sigpipe<T>(f: impl FnOnce() -> T) -> T {
+    with_blocked(libc::SIGPIPE, || {
+        let ret = f();
+        if errno() == libc::EPIPE {
+            // SAFETY: signal() takes no pointers; the handler we put back is
+            // the one it returned
+            #[allow(clippy::multiple_unsafe_ops_per_block)]
+            unsafe {
+                let old = libc::signal(libc::SIGPIPE, libc::SIG_IGN);
+                libc::signal(libc::SIGPIPE, old);
+            }
+        }
+        ret
+    })
+}
+
+fn with_blocked<T>(sig: i32, f: impl FnOnce() -> T) -> T {
+    // SAFETY: sigset_t is a plain bitmask (an array of them on linux),
+    // zeroed is a valid empty set.
+    let mut old: libc::sigset_t = unsafe { core::mem::zeroed() };
+    // SAFETY: zeroed is a valid empty set, as above.
+    // sigemptyset/sigaddset/pthread_sigmask get pointers to locals that
+    // outlive the calls. Grouped since they build one mask change.
+    #[allow(clippy::multiple_unsafe_ops_per_block)]
+    unsafe {
+        let mut block: libc::sigset_t = core::mem::zeroed();
+        libc::sigemptyset(&raw mut block);
+        libc::sigaddset(&raw mut block, sig);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &raw const block, &raw mut old);
+    }
+
+    let ret = f();
+
+    // SAFETY: old was filled in by pthread_sigmask above
+    unsafe { libc::pthread_sigmask(libc::SIG_SETMASK, &raw const old, core::ptr::null_mut()) };
+    ret
+}
+
+fn set_foreground(fd: i32, pgid: i32) -> bool {
+    // SAFETY: tcsetpgrp takes no pointers; fd is our open tty
+    without_sigttou(|| unsafe { libc::tcsetpgrp(fd, pgid) } == 0)
+}
+
+/// Holds the terminal for a child's process group; gives it back on drop.
+pub struct ForegroundGuard {
+    fd: i32,
+    original: i32,
+}
+
+impl ForegroundGuard {
+    /// Make `pgid` the terminal's foreground group.
+    ///
+    /// Returns `None` (and changes nothing) when there's no controlling
+    /// terminal, or when we aren't the foreground job ourselves - a
+    /// background job has no business taking the terminal.
+    pub fn acquire(pgid: i32) -> Option<Self> {
+        let fd = open_tty()?;
+
+        // SAFETY: tcgetpgrp/getpgrp take no pointers; fd is our open tty.
+        // Grouped since they share that invariant.
+        #[allow(clippy::multiple_unsafe_ops_per_block)]
+        let (original, ours) = unsafe { (libc::tcgetpgrp(fd), libc::getpgrp()) };
+
+        if original < 0 || original != ours || !set_foreground(fd, pgid) {
+            // SAFETY: fd was opened above and isn't shared
+            unsafe { libc::close(fd) };
+            return None;
+        }
+
+        /* the child may have touched the terminal before it owned it and
+         * been stopped by SIGTTIN/SIGTTOU - wake it up */
+        // SAFETY: killpg takes no pointers; pgid is the child's group
+        unsafe { libc::killpg(pgid, libc::SIGCONT) };
+
+        Some(Self { fd, original })
+    }
+}
+
+impl Drop for ForegroundGuard {
+    fn drop(&mut self) {
+        /* best effort - nothing useful to do if the terminal went away */
+        let _ = set_foreground(self.fd, self.original);
+        // SAFETY: fd was opened in acquire() and is closed exactly once here
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+/* a pty or pipe fd, moved to >= 3 and close-on-exec like our other
+ * private fds */
+fn private_fd(fd: i32) -> i32 {
+    // SAFETY: fd was just returned by openpty() or pipe(); F_DUPFD_CLOEXEC
+    // takes no pointers and returns a new fd >= 3 or -1
+    let moved = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 3) };
+    // SAFETY: fd is the original, closed once here - the copy (or nothing,
+    // on error) replaces it
+    unsafe { libc::close(fd) };
+    moved
+}
+
+fn set_nonblocking(fd: i32) -> bool {
+    // SAFETY: fd is -1 or an fd we own; fcntl takes no pointers
+    #[allow(clippy::multiple_unsafe_ops_per_block)]
+    let ok = fd >= 0
+        && unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            flags >= 0 && libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) >= 0
+        };
+    ok
+}
+
+/// The child's stdin when it goes through us - a pty (`--stdin-mode pty`)
+/// or a pipe (`--stdin-inject-on-timeout`) - and our end of it. Gives our
+/// terminal its settings back on drop.
+pub(crate) struct StdinRelay {
+    master: Cell<i32>, /* our end; a pipe's is closed at EOF */
+    slave: i32,        /* -1 once the child has its copy */
+    pty: bool,
+    terminal: i32, /* where the pty's echo goes: our tty, or -1 */
+    saved: Option<libc::termios>,
+    line_start: Cell<bool>, /* nothing typed since the last newline */
+}
+
+impl StdinRelay {
+    /// Open a pty and, if stdin is a terminal, take its settings and size
+    /// and make it non-canonical (see the header). `Err(errno)` if no pty
+    /// could be had.
+    pub(crate) fn pty() -> Result<Self, i32> {
+        // SAFETY: termios and winsize are plain old data, zeroed is a valid
+        // value to have tcgetattr/ioctl overwrite
+        #[allow(clippy::multiple_unsafe_ops_per_block)]
+        let (mut term, mut size): (libc::termios, libc::winsize) =
+            unsafe { (core::mem::zeroed(), core::mem::zeroed()) };
+        // SAFETY: term is a valid termios to write into
+        let is_tty = unsafe { libc::tcgetattr(0, &raw mut term) } == 0;
+        // SAFETY: TIOCGWINSZ writes one winsize into size
+        let sized = is_tty && unsafe { libc::ioctl(0, libc::TIOCGWINSZ, &raw mut size) } == 0;
+
+        /* the pty's echo goes through our terminal's output processing
+         * on its way out, once is enough */
+        let mut pty_term = term;
+        pty_term.c_oflag &= !libc::OPOST;
+
+        let (mut master, mut slave) = (-1, -1);
+        let null = core::ptr::null_mut();
+        let term_ptr = if is_tty { &raw mut pty_term } else { null };
+        let size_ptr = if sized { &raw mut size } else { null.cast() };
+        // SAFETY: master and slave are locals for openpty to fill in; the
+        // name buffer may be null; term_ptr and size_ptr are null or point
+        // to locals that outlive the call
+        if unsafe {
+            libc::openpty(
+                &raw mut master,
+                &raw mut slave,
+                core::ptr::null_mut(),
+                term_ptr,
+                size_ptr,
+            )
+        } != 0
+        {
+            return Err(errno());
+        }
+        let (master, slave) = (private_fd(master), private_fd(slave));
+        let mut pty = Self::new(master, slave, true);
+        if slave < 0 || !set_nonblocking(master) {
+            return Err(errno());
+        }
+
+        if is_tty {
+            let mut raw = term;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ECHONL | libc::IEXTEN);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            // SAFETY: raw is a valid termios, alive for the call
+            if without_sigttou(|| unsafe { libc::tcsetattr(0, libc::TCSANOW, &raw const raw) }) == 0
+            {
+                pty.saved = Some(term);
+            }
+            pty.terminal = open_tty().unwrap_or(-1);
+        }
+        Ok(pty)
+    }
+
+    /// A plain pipe: what arrives on our stdin, passed on as is.
+    /// `Err(errno)` if it couldn't be created.
+    pub(crate) fn pipe() -> Result<Self, i32> {
+        let mut fds = [0i32; 2];
+        // SAFETY: fds is a valid 2-element array, pipe() writes exactly 2 fds
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(errno());
+        }
+        let [read, write] = fds.map(private_fd);
+        let pipe = Self::new(write, read, false);
+        if read < 0 || !set_nonblocking(write) {
+            return Err(errno());
+        }
+        Ok(pipe)
+    }
+
+    const fn new(master: i32, slave: i32, pty: bool) -> Self {
+        Self {
+            master: Cell::new(master),
+            slave,
+            pty,
+            terminal: -1,
+            saved: None,
+            line_start: Cell::new(true),
+        }
+    }
+
+    /// The child's end, to dup onto its fd 0.
+    pub(crate) const fn slave(&self) -> i32 {
+        self.slave
+    }
+
+    /// Close our copy of the child's end, once it has its own.
+    pub(crate) fn close_slave(&mut self) {
+        if self.slave >= 0 {
+            // SAFETY: slave is ours and closed exactly once here
+            unsafe { libc::close(self.slave) };
+            self.slave = -1;
+        }
+    }
+
+    /// Our end of a pty: readable when it echoes something. None for a
+    /// pipe, which doesn't.
+    pub(crate) fn echo_fd(&self) -> Option<i32> {
+        self.pty.then(|| self.master.get())
+    }
+
+    /// Type `bytes` into the relay. What doesn't fit is lost, as it would
+    /// be typing ahead of a child that isn't reading its terminal.
+    pub(crate) fn feed(&self, mut bytes: &[u8]) {
+        let fd = self.master.get();
+        if fd < 0 {
+            return;
+        }
+        if let Some(&last) = bytes.last() {
+            self.line_start.set(last == b'\n');
+        }
+        without_sigpipe(|| {
+            while !bytes.is_empty() {
+                // SAFETY: bytes is a valid buffer of the given length
+                let n = unsafe { libc::write(fd, bytes.as_ptr().cast(), bytes.len()) };
+                match usize::try_from(n) {
+                    Ok(n) => bytes = &bytes[n..],
+                    Err(_) if errno() == libc::EINTR => {}
+                    Err(_) => return,
+                }
+            }
+        });
+    }
+
+    /// Write `bytes` to the child after whatever was passed on before.
+    /// False if its input has already ended - a pipe is closed by then.
+    pub(crate) fn inject(&self, bytes: &[u8]) -> bool {
+        if self.master.get() < 0 {
+            return false;
+        }
+        self.feed(bytes);
+        true
+    }
+
+    /// Our stdin ended: pass the EOF on. A pipe just closes; a canonical
+    /// pty needs a second VEOF when a line is half typed, the first only
+    /// flushes it.
+    pub(crate) fn end_input(&self) {
+        let fd = self.master.get();
+        if !self.pty {
+            if fd >= 0 {
+                // SAFETY: fd is our write end, closed exactly once - it's
+                // -1 from here on
+                unsafe { libc::close(fd) };
+                self.master.set(-1);
+            }
+            return;
+        }
+        // SAFETY: termios is plain old data, zeroed is a valid value to have
+        // tcgetattr overwrite
+        let mut term: libc::termios = unsafe { core::mem::zeroed() };
+        // SAFETY: fd is our open pty, term a valid termios to write into
+        let eof = if unsafe { libc::tcgetattr(fd, &raw mut term) } == 0 {
+            term.c_cc[libc::VEOF]
+        } else {
+            4 /* ^D */
+        };
+        if !self.line_start.get() {
+            self.feed(&[eof]);
+        }
+        self.feed(&[eof]);
+    }
+
+    /// The pty has output (its echo): copy it to our terminal. False once
+    /// the child's end is closed everywhere - stop waiting on it.
+    pub(crate) fn pump_echo(&self) -> bool {
+        let mut buf = [0u8; 1024];
+        // SAFETY: buf is a valid buffer of the given length
+        let n = unsafe { libc::read(self.master.get(), buf.as_mut_ptr().cast(), buf.len()) };
+        let Ok(n) = usize::try_from(n) else {
+            /* EIO is linux's EOF here */
+            return matches!(errno(), libc::EAGAIN | libc::EINTR);
+        };
+        if n > 0 && self.terminal >= 0 {
+            // SAFETY: buf holds n bytes read above
+            without_sigttou(|| unsafe { libc::write(self.terminal, buf.as_ptr().cast(), n) });
+        }
+        n > 0
+    }
+}
+
+impl Drop for StdinRelay {
+    fn drop(&mut self) {
+        if let Some(saved) = &self.saved {
+            let saved = &raw const *saved;
+            // SAFETY: saved points into self, alive for the call
+            without_sigttou(|| unsafe { libc::tcsetattr(0, libc::TCSANOW, saved) });
+        }
+        self.close_slave();
+        for fd in [self.master.get(), self.terminal] {
+            if fd >= 0 {
+                // SAFETY: fd is ours and closed exactly once here
+                unsafe { libc::close(fd) };
+            }
+        }
+    }
+}
+
+/* leave alternate screen, show cursor, reset colors/attributes */
+const ANSI_RESET: &[u8] = b"\x1b[?1049l\x1b[?25h\x1b[0m";
+
+/// Terminal settings captured before the child ran; restored on drop.
+pub struct TermiosGuard {
+    fd: i32,
+    saved: libc::termios,
+}
+
+impl TermiosGuard {
+    /// Snapshot the controlling terminal's settings.
+    ///
+    /// Returns `None` when there's no controlling terminal to save.
+    pub fn save() -> Option<Self> {
+        let fd = open_tty()?;
+        // SAFETY: termios is plain old data, zeroed is a valid value to
+        // have tcgetattr overwrite
+        let mut saved: libc::termios = unsafe { core::mem::zeroed() };
+        // SAFETY: fd is our open tty, saved is a valid termios to write into
+        if unsafe { libc::tcgetattr(fd, &raw mut saved) } != 0 {
+            // SAFETY: fd was opened above and isn't shared
+            unsafe { libc::close(fd) };
+            return None;
+        }
+        Some(Self { fd, saved })
+    }
+}
+
+impl Drop for TermiosGuard {
+    fn drop(&mut self) {
+        let (fd, saved) = (self.fd, &raw const self.saved);
+        /* best effort on all three - the terminal may be gone by now */
+        without_sigttou(|| {
+            // SAFETY: fd is our open tty; saved points into self, alive for
+            // the call; ANSI_RESET is a static buffer of the given length.
+            // Grouped since they share the fd invariant.
+            #[allow(clippy::multiple_unsafe_ops_per_block)]
+            unsafe {
+                /* TCSAFLUSH: drop keystrokes typed at the dead app */
+                libc::tcsetattr(fd, libc::TCSAFLUSH, saved);
+                libc::write(fd, ANSI_RESET.as_ptr().cast(), ANSI_RESET.len());
+            }
+        });
+        // SAFETY: fd was opened in save() and is closed exactly once here
+        unsafe { libc::close(self.fd) };
+    }
+}
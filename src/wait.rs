@@ -6,33 +6,80 @@
  * Currently supports: --wait-for-file <path>
  * Waits for a file to exist before proceeding. Useful for orchestration
  * scenarios where one process signals readiness by creating a file.
+ * --wait-for-file-gone waits for it to go away instead (lockfiles), and
+ * --wait-for-file-contains until it has a marker string in it.
+ *
+ * --flock <path> takes an exclusive flock() on the path instead, so only
+ * one instance of a job runs at a time. flock() can't time out, so a held
+ * lock is retried with the same backoff, capped lower since the holder
+ * gives no notice when it lets go. --flock-queue makes that fair: each
+ * waiter drops a ticket file named by its arrival time in <path>.queue/
+ * and only tries the lock once no live ticket is ahead of its own.
+ *
+ * --only-between waits for a time-of-day window to open instead, by the
+ * local clock (see window.rs). the wait is long and the clock can move
+ * under it (set by hand, a DST change), so it sleeps at most a minute at a
+ * time and asks the clock again.
+ *
+ * the last path component may be a glob (`*`, `?`, `[...]`); the first
+ * match in name order wins. wildcards in directories aren't supported.
  *
  * Uses stat-based polling with exponential backoff (10ms → 1s) to minimize
- * CPU usage while maintaining reasonable responsiveness.
+ * CPU usage while maintaining reasonable responsiveness. on macOS an
+ * EVFILT_VNODE watch on the parent directory (and on the file itself, for
+ * a content match) wakes the loop as soon as something changes, so the
+ * backoff is only the fallback.
+ *
+ * --sleep is delay_confined() and nothing else: the delay on the wall
+ * clock, so time asleep counts. --wait-pid is wait_for_pid(): the same
+ * timer next to an EVFILT_PROC watch on someone else's process, which
+ * nothing signals.
+ *
+ * the same loops are public for library use: delay() and wait_for_path()
+ * take a Cancel - a descriptor to watch or a flag to check - so a caller
+ * can cut them short from a signal handler or another thread.
  */
 
-use alloc::string::String;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
 use core::time::Duration;
 
 use crate::args::Confine;
+use crate::errno::errno;
 use crate::error::{Result, TimeoutError};
+use crate::kq::{self, Kqueue};
+use crate::safe_file::{self, FileOptions};
 use crate::sync::AtomicOnce;
 use crate::time_math::{advance_ns, deadline_reached, remaining_ns};
+use crate::window::{Window, local_sec_of_day};
 
 /* Timing helpers - reimplemented here to avoid circular deps with runner */
+#[cfg(target_os = "macos")]
 #[repr(C)]
 struct MachTimebaseInfo {
     numer: u32,
     denom: u32,
 }
 
+#[cfg(target_os = "macos")]
 unsafe extern "C" {
     fn mach_continuous_time() -> u64;
     fn mach_timebase_info(info: *mut MachTimebaseInfo) -> i32;
     fn clock_gettime_nsec_np(clock_id: libc::clockid_t) -> u64;
+}
+
+#[cfg(target_os = "linux")]
+use crate::linux::{
+    MachTimebaseInfo, clock_gettime_nsec_np, mach_continuous_time, mach_timebase_info,
+};
+
+unsafe extern "C" {
     fn nanosleep(rqtp: *const libc::timespec, rmtp: *mut libc::timespec) -> i32;
 }
 
+/* same id on linux */
 const CLOCK_MONOTONIC_RAW: libc::clockid_t = 4;
 
 /* Cached timebase info for mach_continuous_time conversion */
@@ -60,7 +107,7 @@ fn get_timebase_info() -> Option<(u64, u64)> {
 #[inline]
 fn now_ns(confine: Confine) -> u64 {
     match confine {
-        Confine::Wall => wall_now_ns(),
+        Confine::Wall | Confine::Hybrid => wall_now_ns(),
         Confine::Active => active_now_ns(),
     }
 }
@@ -92,7 +139,7 @@ fn active_now_ns() -> u64 {
 }
 
 /* Sleep for given milliseconds, handling EINTR by continuing with remaining time */
-fn sleep_ms(ms: u64) {
+pub(crate) fn sleep_ms(ms: u64) {
     let mut ts = libc::timespec {
         tv_sec: (ms / 1000) as libc::time_t,
         tv_nsec: ((ms % 1000) * 1_000_000) as libc::c_long,
@@ -114,124 +161,280 @@ fn sleep_ms(ms: u64) {
     }
 }
 
-/* Duration to milliseconds as u64 (avoids u128 from as_millis()) */
-#[inline]
-fn duration_ms(d: Duration) -> u64 {
-    d.as_secs()
-        .saturating_mul(1000)
-        .saturating_add(u64::from(d.subsec_millis()))
+/// What can cut a [`delay`] or [`wait_for_path`] short.
+///
+/// # Example
+///
+/// ```no_run
+/// use core::sync::atomic::{AtomicBool, Ordering};
+/// use core::time::Duration;
+/// use procguard::wait::{Cancel, delay};
+///
+/// static STOP: AtomicBool = AtomicBool::new(false);
+///
+/// std::thread::spawn(|| STOP.store(true, Ordering::Relaxed));
+/// if !delay(Duration::from_secs(60), Cancel::Flag(&STOP)) {
+///     println!("cancelled");
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+#[non_exhaustive]
+pub enum Cancel<'a> {
+    /// Nothing: the wait runs until it completes or times out.
+    #[default]
+    Never,
+    /// Stop once this descriptor is readable: the read end of a pipe a
+    /// signal handler writes to, an eventfd, a socket. Nothing is read
+    /// from it, so it stays readable for the caller to drain.
+    Fd(i32),
+    /// Stop once this flag is true. A store can't wake a sleeping thread,
+    /// so the flag is checked every 10ms; use [`Cancel::Fd`] where that
+    /// latency matters.
+    Flag(&'a AtomicBool),
 }
 
-/*
- * kqueue-based delay - zero CPU while waiting, handles EINTR.
- * used for retry delays between command attempts.
- *
- * returns true if delay completed normally, false if interrupted by signal.
- * caller should check signal pipe after false return.
- */
-pub fn kqueue_delay(d: Duration, signal_fd: Option<i32>) -> bool {
-    if d.is_zero() {
-        return true;
+/* how often Cancel::Flag is looked at */
+const FLAG_POLL_NS: u64 = 10_000_000;
+
+/* longest a wall-clock delay sleeps on an awake-time timer before it
+ * looks at the clock again */
+const WALL_PROBE_NS: u64 = 1_000_000_000;
+
+impl Cancel<'_> {
+    fn flagged(&self) -> bool {
+        matches!(self, Self::Flag(flag) if flag.load(Ordering::Acquire))
     }
 
-    /* track deadline for EINTR recalculation */
-    let start_ns = active_now_ns();
-    let deadline_ns = advance_ns(start_ns, duration_to_ns(d));
+    fn fd(&self) -> Option<i32> {
+        match *self {
+            Self::Fd(fd) => Some(fd),
+            _ => None,
+        }
+    }
+
+    /* cap a sleep so a flag gets looked at in time */
+    fn cap_ns(&self, ns: u64) -> u64 {
+        match self {
+            Self::Flag(_) => ns.min(FLAG_POLL_NS),
+            _ => ns,
+        }
+    }
+}
+
+/// Sleep for `d` on a kqueue timer, zero CPU, unless `cancel` fires first.
+/// Returns true if the whole delay elapsed, false if it was cancelled.
+///
+/// EINTR is retried with the time that's left. The delay is measured on
+/// `CLOCK_MONOTONIC_RAW`, so time the machine spends asleep doesn't count
+/// towards it (as with `--confine active`). If no kqueue can be created
+/// (fd limit) it falls back to nanosleep, which only [`Cancel::Flag`] can
+/// interrupt.
+#[must_use]
+pub fn delay(d: Duration, cancel: Cancel<'_>) -> bool {
+    delay_confined(d, Confine::Active, cancel)
+}
 
-    /* create kqueue fd */
-    // SAFETY: kqueue() has no preconditions, returns -1 on error
-    let kq = unsafe { libc::kqueue() };
-    if kq < 0 {
-        /* fallback to nanosleep on kqueue failure */
-        sleep_ms(duration_ms(d));
-        return true;
+/// [`delay`] on the `confine` clock: with [`Confine::Wall`] (or
+/// `Hybrid`) time the machine spends asleep counts, so a 10 minute delay
+/// that a closed lid interrupts for an hour is over when the lid opens.
+///
+/// The kernel's timers stop while the machine sleeps, so a wall-clock
+/// delay wakes at least once a second to look at the clock, and ends
+/// within a second of the machine waking past its deadline.
+#[must_use]
+pub fn delay_confined(d: Duration, confine: Confine, cancel: Cancel<'_>) -> bool {
+    if d.is_zero() {
+        return !cancel.flagged();
+    }
+    match Kqueue::new() {
+        Ok(queue) => delay_on(&queue, d, confine, cancel),
+        Err(_) if confine == Confine::Active => sleep_cancellable(d, cancel),
+        /* nanosleep stops during sleep too: in probe-sized steps */
+        Err(_) => {
+            let deadline_ns = advance_ns(now_ns(confine), duration_to_ns(d));
+            loop {
+                let current_ns = now_ns(confine);
+                if deadline_reached(current_ns, deadline_ns) {
+                    return !cancel.flagged();
+                }
+                let step_ns = remaining_ns(current_ns, deadline_ns).min(WALL_PROBE_NS);
+                if !sleep_cancellable(Duration::from_nanos(step_ns), cancel) {
+                    return false;
+                }
+            }
+        }
     }
+}
+
+/// [`delay`] cancelled by a descriptor becoming readable - the signal
+/// pipe between retry attempts. True if the delay ran out, false if it
+/// was interrupted; the caller should check the pipe then.
+pub fn kqueue_delay(d: Duration, signal_fd: Option<i32>) -> bool {
+    delay(d, signal_fd.map_or(Cancel::Never, Cancel::Fd))
+}
 
-    /* max ns that fits in isize (~292 years on 64-bit) */
-    const MAX_TIMER_NS: u64 = isize::MAX as u64;
+/* same, on a kqueue the caller already has (the retry loop's) */
+pub(crate) fn kqueue_delay_on(queue: &Kqueue, d: Duration, signal_fd: Option<i32>) -> bool {
+    delay_on(
+        queue,
+        d,
+        Confine::Active,
+        signal_fd.map_or(Cancel::Never, Cancel::Fd),
+    )
+}
 
-    let num_changes: i32 = if signal_fd.is_some() { 2 } else { 1 };
+fn delay_on(queue: &Kqueue, d: Duration, confine: Confine, cancel: Cancel<'_>) -> bool {
+    if d.is_zero() {
+        return !cancel.flagged();
+    }
 
-    let mut event = libc::kevent {
-        ident: 0,
-        filter: 0,
-        flags: 0,
-        fflags: 0,
-        data: 0,
-        udata: core::ptr::null_mut(),
+    /* track deadline for EINTR recalculation */
+    let start_ns = now_ns(confine);
+    let deadline_ns = advance_ns(start_ns, duration_to_ns(d));
+    /* the timer runs on awake time: on the wall clock, look again often
+     * enough to notice a sleep that has used up the rest */
+    let probe_ns = match confine {
+        Confine::Active => u64::MAX,
+        Confine::Wall | Confine::Hybrid => WALL_PROBE_NS,
     };
 
+    queue.reset();
+
+    let cancel_fd = cancel.fd();
+    let mut event = kq::empty();
+
     loop {
-        /* recalculate remaining time after each EINTR */
-        let current_ns = active_now_ns();
+        if cancel.flagged() {
+            return false;
+        }
+        /* recalculate remaining time after each wakeup */
+        let current_ns = now_ns(confine);
         if deadline_reached(current_ns, deadline_ns) {
-            // SAFETY: kq is valid fd
-            unsafe { libc::close(kq) };
             return true; /* deadline reached */
         }
-        let remaining_timeout_ns = remaining_ns(current_ns, deadline_ns);
+        let remaining_timeout_ns =
+            cancel.cap_ns(remaining_ns(current_ns, deadline_ns).min(probe_ns));
 
-        /* set up timer event, optionally watch signal pipe */
-        #[allow(clippy::cast_possible_wrap)]
+        /* set up timer event, optionally watch the cancel fd */
         let changes = [
-            libc::kevent {
-                ident: 1,
-                filter: libc::EVFILT_TIMER,
-                flags: libc::EV_ADD | libc::EV_ONESHOT,
-                fflags: libc::NOTE_NSECONDS,
-                data: remaining_timeout_ns.min(MAX_TIMER_NS) as isize,
-                udata: core::ptr::null_mut(),
-            },
-            libc::kevent {
-                ident: signal_fd.unwrap_or(0) as usize,
-                filter: libc::EVFILT_READ,
-                flags: if signal_fd.is_some() { libc::EV_ADD } else { 0 },
-                fflags: 0,
-                data: 0,
-                udata: core::ptr::null_mut(),
-            },
+            kq::timer(1, remaining_timeout_ns),
+            kq::read(cancel_fd.unwrap_or(0), cancel_fd.is_some()),
         ];
 
-        // SAFETY: kq is valid, changes/event are valid kevent structs
-        #[allow(clippy::cast_possible_wrap)]
-        let n = unsafe {
-            libc::kevent(
-                kq,
-                changes.as_ptr(),
-                num_changes,
-                &raw mut event,
-                1,
-                core::ptr::null(),
-            )
-        };
-
-        if n < 0 {
-            let err = errno();
+        if let Err(err) = queue.wait(&changes, &mut event) {
             if err == libc::EINTR {
                 continue; /* retry with recalculated timer */
             }
-            /* other error - close and fallback with REMAINING time */
-            // SAFETY: kq is valid fd
-            unsafe { libc::close(kq) };
-            let remaining_ms = remaining_ns(active_now_ns(), deadline_ns) / 1_000_000;
-            if remaining_ms > 0 {
-                sleep_ms(remaining_ms);
-            }
+            /* other error - fallback with REMAINING time */
+            let remaining = remaining_ns(now_ns(confine), deadline_ns);
+            return sleep_cancellable(Duration::from_nanos(remaining), cancel);
+        }
+
+        /* cancel fd readable - e.g. a signal received during delay */
+        if event.filter == kq::EVFILT_READ {
+            return false;
+        }
+        /* timer: done, or time to look at the flag again */
+    }
+}
+
+/* nanosleep fallback, in flag-sized steps when there's a flag */
+fn sleep_cancellable(d: Duration, cancel: Cancel<'_>) -> bool {
+    let deadline_ns = advance_ns(active_now_ns(), duration_to_ns(d));
+    loop {
+        if cancel.flagged() {
+            return false;
+        }
+        let current_ns = active_now_ns();
+        if deadline_reached(current_ns, deadline_ns) {
             return true;
         }
-        break;
+        let step_ns = cancel.cap_ns(remaining_ns(current_ns, deadline_ns));
+        sleep_ms(step_ns.div_ceil(1_000_000));
     }
+}
 
-    // SAFETY: kq is valid fd
-    unsafe { libc::close(kq) };
+/// How [`wait_for_pid`] ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PidWait {
+    /// The process exited during the wait.
+    Exited,
+    /// There was no such process to wait for: it had already exited and
+    /// been reaped, or the pid was never in use.
+    NotRunning,
+    /// The deadline came with the process still running.
+    TimedOut,
+    /// `cancel` fired first.
+    Cancelled,
+}
 
-    /* check what woke us up */
-    if event.filter == libc::EVFILT_READ {
-        /* signal pipe readable - signal received during delay */
-        return false;
-    }
+/// Wait for `pid` to exit, up to `timeout` on the `confine` clock (`None`:
+/// for as long as it takes). It needn't be our child: the kernel says when
+/// it exits (EVFILT_PROC, a pidfd on Linux). Nothing is sent to it, and it
+/// isn't reaped - that's its parent's job.
+///
+/// # Errors
+///
+/// `WaitPidError` with the errno if the process can't be watched (EPERM
+/// for one we may not look at) or no kqueue can be created.
+pub fn wait_for_pid(
+    pid: i32,
+    timeout: Option<Duration>,
+    confine: Confine,
+    cancel: Cancel<'_>,
+) -> Result<PidWait> {
+    let failed = |e| TimeoutError::WaitPidError(pid, e);
+    let queue = Kqueue::new().map_err(failed)?;
+
+    let deadline_ns = timeout.map(|d| advance_ns(now_ns(confine), duration_to_ns(d)));
+    /* as in delay_on: the timer stops while the machine sleeps */
+    let probe_ns = match confine {
+        Confine::Active => u64::MAX,
+        Confine::Wall | Confine::Hybrid => WALL_PROBE_NS,
+    };
+    let cancel_fd = cancel.fd();
+    let mut event = kq::empty();
+
+    loop {
+        if cancel.flagged() {
+            return Ok(PidWait::Cancelled);
+        }
+        let current_ns = now_ns(confine);
+        let left_ns = match deadline_ns {
+            Some(deadline_ns) if deadline_reached(current_ns, deadline_ns) => {
+                return Ok(PidWait::TimedOut);
+            }
+            Some(deadline_ns) => remaining_ns(current_ns, deadline_ns),
+            None => u64::MAX,
+        };
+        /* re-adding the exit watch each time round leaves it as it is */
+        let changes = [
+            kq::proc_exit(pid),
+            kq::timer(1, cancel.cap_ns(left_ns.min(probe_ns))),
+            kq::read(cancel_fd.unwrap_or(0), cancel_fd.is_some()),
+        ];
+        match queue.wait(&changes, &mut event) {
+            Ok(()) => {}
+            Err(libc::EINTR) => continue,
+            Err(err) => return Err(failed(err)),
+        }
 
-    true /* timer expired normally */
+        if (event.flags & kq::EV_ERROR) != 0 {
+            #[allow(clippy::cast_possible_truncation)]
+            let err = event.data as i32;
+            return match err {
+                libc::ESRCH => Ok(PidWait::NotRunning),
+                err => Err(failed(err)),
+            };
+        }
+        match event.filter {
+            kq::EVFILT_PROC => return Ok(PidWait::Exited),
+            kq::EVFILT_READ => return Ok(PidWait::Cancelled),
+            /* timer: the deadline, a clock probe or a flag check */
+            _ => {}
+        }
+    }
 }
 
 /* Check if file exists using stat */
@@ -263,19 +466,6 @@ fn file_exists(path: &str) -> core::result::Result<bool, i32> {
     }
 }
 
-/* Get errno - on macOS this is a thread-local via __error() */
-#[inline]
-fn errno() -> i32 {
-    unsafe extern "C" {
-        fn __error() -> *mut i32;
-    }
-    // SAFETY: __error always returns valid pointer on macOS
-    #[allow(clippy::multiple_unsafe_ops_per_block)]
-    unsafe {
-        *__error()
-    }
-}
-
 /* Duration to nanoseconds */
 #[inline]
 fn duration_to_ns(d: Duration) -> u64 {
@@ -284,10 +474,375 @@ fn duration_to_ns(d: Duration) -> u64 {
         .saturating_add(u64::from(d.subsec_nanos()))
 }
 
+/* does the path have wildcards in it */
+fn is_pattern(path: &str) -> bool {
+    path.bytes().any(|b| matches!(b, b'*' | b'?' | b'['))
+}
+
+/* (directory, last component) of a path, "." for a bare name */
+fn split_path(path: &str) -> (&str, &str) {
+    match path.rsplit_once('/') {
+        Some(("", name)) => ("/", name),
+        Some((dir, name)) => (dir, name),
+        None => (".", path),
+    }
+}
+
+/* (directory, name pattern) for a path with wildcards, None for a literal
+ * path. Err(directory) if the wildcards aren't all in the last component */
+pub(crate) fn split_pattern(path: &str) -> core::result::Result<Option<(&str, &str)>, &str> {
+    if !is_pattern(path) {
+        return Ok(None);
+    }
+    let (dir, name) = split_path(path);
+    if is_pattern(dir) {
+        return Err(dir);
+    }
+    Ok(Some((dir, name)))
+}
+
+/*
+ * shell-style match of one file name: `*`, `?`, `[abc]`, `[a-z]`, `[!..]`
+ * or `[^..]`, and `\` to take the next byte literally. like the shell,
+ * wildcards don't match a leading dot. bytes, not characters: `?` is one
+ * byte of a multibyte name.
+ */
+fn glob_match(pat: &[u8], name: &[u8]) -> bool {
+    if name.first() == Some(&b'.') && pat.first() != Some(&b'.') {
+        return false;
+    }
+    let (mut p, mut n) = (0, 0);
+    /* where to resume if the last * has to take one more byte */
+    let mut backtrack = None;
+    while n < name.len() {
+        if pat.get(p) == Some(&b'*') {
+            p += 1;
+            backtrack = Some((p, n));
+            continue;
+        }
+        if p < pat.len()
+            && let Some(next) = glob_step(pat, p, name[n])
+        {
+            p = next;
+            n += 1;
+            continue;
+        }
+        let Some((bp, bn)) = backtrack else {
+            return false;
+        };
+        backtrack = Some((bp, bn + 1));
+        p = bp;
+        n = bn + 1;
+    }
+    pat[p..].iter().all(|&b| b == b'*')
+}
+
+/* if the pattern element at pat[p] matches `c`, the index after it */
+fn glob_step(pat: &[u8], p: usize, c: u8) -> Option<usize> {
+    match pat[p] {
+        b'?' => Some(p + 1),
+        b'\\' if p + 1 < pat.len() => (pat[p + 1] == c).then_some(p + 2),
+        b'[' => match glob_class(&pat[p + 1..], c) {
+            Some((hit, len)) => hit.then_some(p + 1 + len),
+            /* no closing ], a plain [ */
+            None => (c == b'[').then_some(p + 1),
+        },
+        b => (b == c).then_some(p + 1),
+    }
+}
+
+/* `body` follows a '['. (c is in the class, length through the ']'), or
+ * None if it's never closed. a ']' right after the '[' is a member */
+fn glob_class(body: &[u8], c: u8) -> Option<(bool, usize)> {
+    let negate = matches!(body.first(), Some(b'!' | b'^'));
+    let mut i = usize::from(negate);
+    let start = i;
+    let mut hit = false;
+    while let Some(&lo) = body.get(i) {
+        if lo == b']' && i > start {
+            return Some((hit != negate, i + 1));
+        }
+        match (body.get(i + 1), body.get(i + 2)) {
+            (Some(b'-'), Some(&hi)) if hi != b']' => {
+                hit |= (lo..=hi).contains(&c);
+                i += 3;
+            }
+            _ => {
+                hit |= lo == c;
+                i += 1;
+            }
+        }
+    }
+    None
+}
+
+/* names in `dir` matching `pattern`, as full paths in name order. a
+ * directory that isn't there (yet) has no matches */
+fn glob_paths(dir: &str, pattern: &str) -> core::result::Result<Vec<String>, i32> {
+    let mut c_dir = String::with_capacity(dir.len() + 1);
+    c_dir.push_str(dir);
+    c_dir.push('\0');
+    // SAFETY: c_dir is null-terminated
+    let handle = unsafe { libc::opendir(c_dir.as_ptr().cast()) };
+    if handle.is_null() {
+        let err = errno();
+        return if err == libc::ENOENT || err == libc::ENOTDIR {
+            Ok(Vec::new())
+        } else {
+            Err(err)
+        };
+    }
+
+    let mut found = Vec::new();
+    loop {
+        // SAFETY: handle is an open DIR, only used by this loop
+        let entry = unsafe { libc::readdir(handle) };
+        if entry.is_null() {
+            break;
+        }
+        // SAFETY: readdir returned a valid dirent; d_name is null-terminated
+        // and stays valid until the next readdir on this handle.
+        #[allow(clippy::multiple_unsafe_ops_per_block)]
+        let name = unsafe { core::ffi::CStr::from_ptr((*entry).d_name.as_ptr()) }.to_bytes();
+        if name == b"." || name == b".." || !glob_match(pattern.as_bytes(), name) {
+            continue;
+        }
+        /* not UTF-8: can't be handed back as a path string, skip */
+        let Ok(name) = core::str::from_utf8(name) else {
+            continue;
+        };
+        let mut path = String::with_capacity(dir.len() + name.len() + 1);
+        path.push_str(dir);
+        if !dir.ends_with('/') {
+            path.push('/');
+        }
+        path.push_str(name);
+        found.push(path);
+    }
+    // SAFETY: handle is open and closed once here
+    unsafe { libc::closedir(handle) };
+    found.sort_unstable();
+    Ok(found)
+}
+
+/* bytes read from the file per read() while looking for the marker */
+const CONTAINS_CHUNK: usize = 64 * 1024;
+
+/* does the file have `marker` in it. a file that isn't there doesn't */
+fn file_contains(path: &str, marker: &[u8]) -> core::result::Result<bool, i32> {
+    if marker.is_empty() {
+        return file_exists(path);
+    }
+    let mut c_path = String::with_capacity(path.len() + 1);
+    c_path.push_str(path);
+    c_path.push('\0');
+    // SAFETY: c_path is null-terminated
+    let fd = unsafe { libc::open(c_path.as_ptr().cast(), libc::O_RDONLY | libc::O_CLOEXEC) };
+    if fd < 0 {
+        let err = errno();
+        return if err == libc::ENOENT || err == libc::ENOTDIR {
+            Ok(false)
+        } else {
+            Err(err)
+        };
+    }
+
+    /* the tail of each read is carried over, for a marker split across two */
+    let mut buf = vec![0u8; CONTAINS_CHUNK + marker.len()];
+    let mut kept = 0;
+    let found = loop {
+        // SAFETY: fd is open; buf[kept..] is valid for buf.len() - kept bytes
+        let n = unsafe { libc::read(fd, buf[kept..].as_mut_ptr().cast(), buf.len() - kept) };
+        let Ok(n) = usize::try_from(n) else {
+            let err = errno();
+            if err == libc::EINTR {
+                continue;
+            }
+            break Err(err);
+        };
+        if n == 0 {
+            break Ok(false);
+        }
+        let end = kept + n;
+        if buf[..end].windows(marker.len()).any(|w| w == marker) {
+            break Ok(true);
+        }
+        kept = (marker.len() - 1).min(end);
+        buf.copy_within(end - kept..end, 0);
+    };
+    // SAFETY: fd is open and closed once here
+    unsafe { libc::close(fd) };
+    found
+}
+
+/// What [`wait_for_file_condition`] waits for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FileCondition<'a> {
+    /// The file exists (`--wait-for-file`).
+    Exists,
+    /// Nothing is at the path any more (`--wait-for-file-gone`).
+    Gone,
+    /// The file exists and has this string in it
+    /// (`--wait-for-file-contains`).
+    Contains(&'a str),
+}
+
+/* Some(path that satisfies it) if `condition` holds right now. for a
+ * pattern, the first match in name order; for Gone, `path` itself */
+fn satisfied(
+    path: &str,
+    pattern: Option<(&str, &str)>,
+    condition: FileCondition<'_>,
+) -> core::result::Result<Option<String>, i32> {
+    let Some((dir, name)) = pattern else {
+        let there = match condition {
+            FileCondition::Contains(marker) => file_contains(path, marker.as_bytes())?,
+            FileCondition::Exists | FileCondition::Gone => file_exists(path)?,
+        };
+        let hit = there != (condition == FileCondition::Gone);
+        return Ok(hit.then(|| path.to_string()));
+    };
+
+    let matches = glob_paths(dir, name)?;
+    match condition {
+        FileCondition::Exists => Ok(matches.into_iter().next()),
+        FileCondition::Gone => Ok(matches.is_empty().then(|| path.to_string())),
+        FileCondition::Contains(marker) => {
+            for candidate in matches {
+                if file_contains(&candidate, marker.as_bytes())? {
+                    return Ok(Some(candidate));
+                }
+            }
+            Ok(None)
+        }
+    }
+}
+
+/*
+ * vnode watches that wake the wait loop early: the directory the path
+ * lives in (entries added, removed, renamed) and, for a content match on
+ * a literal path, the file itself (written to, extended). opened with
+ * O_EVTONLY so they don't keep a volume from unmounting; closing the fds
+ * on drop takes the filters and their pending events with them.
+ *
+ * best effort: anything that can't be opened is left to the backoff.
+ */
+#[cfg(target_os = "macos")]
+struct Watch {
+    fds: [i32; 2],
+}
+
+#[cfg(target_os = "macos")]
+impl Watch {
+    fn arm(queue: &Kqueue, dir: &str, file: Option<&str>) -> Self {
+        Self {
+            fds: [
+                Self::open(queue, dir),
+                file.map_or(-1, |f| Self::open(queue, f)),
+            ],
+        }
+    }
+
+    fn open(queue: &Kqueue, path: &str) -> i32 {
+        let mut c_path = String::with_capacity(path.len() + 1);
+        c_path.push_str(path);
+        c_path.push('\0');
+        // SAFETY: c_path is null-terminated
+        let fd = unsafe { libc::open(c_path.as_ptr().cast(), libc::O_EVTONLY | libc::O_CLOEXEC) };
+        if fd >= 0 && queue.register(&kq::vnode(fd)).is_err() {
+            // SAFETY: fd was just opened and isn't used again
+            unsafe { libc::close(fd) };
+            return -1;
+        }
+        fd
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl Drop for Watch {
+    fn drop(&mut self) {
+        for &fd in self.fds.iter().filter(|&&fd| fd >= 0) {
+            // SAFETY: fd came from open() in arm() and is closed once here
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
+/* no vnode filter on epoll; linux waits on the backoff alone */
+#[cfg(target_os = "linux")]
+struct Watch;
+
+#[cfg(target_os = "linux")]
+impl Watch {
+    fn arm(_queue: &Kqueue, _dir: &str, _file: Option<&str>) -> Self {
+        Self
+    }
+}
+
 /// Wait for a file to exist.
 ///
-/// Uses exponential backoff polling: starts at 10ms, caps at 1s.
-/// If timeout is None, waits indefinitely.
+/// Same as [`wait_for_path`] with [`FileCondition::Exists`] and no way to
+/// cancel.
+///
+/// # Errors
+///
+/// See [`wait_for_file_condition`].
+pub fn wait_for_file(path: &str, timeout: Option<Duration>, confine: Confine) -> Result<()> {
+    wait_for_file_condition(path, FileCondition::Exists, timeout, confine).map(drop)
+}
+
+/// Wait until `condition` holds for `path`. Returns the path that
+/// satisfied it.
+///
+/// [`wait_for_path`] without a way to cancel.
+///
+/// # Errors
+///
+/// See [`wait_for_path`].
+pub fn wait_for_file_condition(
+    path: &str,
+    condition: FileCondition<'_>,
+    timeout: Option<Duration>,
+    confine: Confine,
+) -> Result<String> {
+    /* None only comes from a cancel, and Never doesn't */
+    wait_for_path(path, condition, timeout, confine, Cancel::Never).map(Option::unwrap_or_default)
+}
+
+/// Wait until `condition` holds for `path`, or `cancel` fires. Returns the
+/// path that satisfied it - the first match in name order if the last
+/// component of `path` is a glob (`*`, `?`, `[...]`), otherwise `path`
+/// itself - or `None` if the wait was cancelled.
+///
+/// Uses exponential backoff polling: starts at 10ms, caps at 1s. On macOS
+/// a kqueue vnode watch on the parent directory (and on the file, for
+/// [`FileCondition::Contains`]) cuts the wait short as soon as it changes.
+/// If timeout is None, waits indefinitely. `confine` picks the clock the
+/// timeout runs on: [`Confine::Wall`] keeps counting while the machine
+/// sleeps.
+///
+/// # Example
+///
+/// ```no_run
+/// use core::time::Duration;
+/// use procguard::args::Confine;
+/// use procguard::wait::{Cancel, FileCondition, wait_for_path};
+///
+/// // e.g. the read end of a self-pipe written from a SIGINT handler
+/// let stop_fd = 3;
+/// match wait_for_path(
+///     "/var/run/app.lock",
+///     FileCondition::Gone,
+///     Some(Duration::from_secs(30)),
+///     Confine::Wall,
+///     Cancel::Fd(stop_fd),
+/// ) {
+///     Ok(Some(_)) => println!("lock released"),
+///     Ok(None) => println!("interrupted"),
+///     Err(e) => eprintln!("{e}"),
+/// }
+/// ```
 ///
 /// # Race Condition (TOCTOU)
 ///
@@ -298,15 +853,27 @@ fn duration_to_ns(d: Duration) -> u64 {
 ///
 /// # Errors
 ///
-/// - `WaitForFileTimeout` if timeout expires before file appears
-/// - `WaitForFileError` if stat() fails with an error other than ENOENT
-pub fn wait_for_file(path: &str, timeout: Option<Duration>, confine: Confine) -> Result<()> {
-    /* Check immediately first - avoid sleeping if file already exists */
-    match file_exists(path) {
-        Ok(true) => return Ok(()),
-        Ok(false) => { /* Continue to wait loop */ }
-        Err(e) => return Err(TimeoutError::WaitForFileError(String::from(path), e)),
-    }
+/// - `WaitForFileTimeout` if timeout expires before the condition holds
+/// - `WaitForFileError` if stat(), opendir() or read() fails with an error
+///   other than ENOENT, or (EINVAL) if a directory in `path` has wildcards
+pub fn wait_for_path(
+    path: &str,
+    condition: FileCondition<'_>,
+    timeout: Option<Duration>,
+    confine: Confine,
+    cancel: Cancel<'_>,
+) -> Result<Option<String>> {
+    let failed = |e| TimeoutError::WaitForFileError(String::from(path), e);
+    let pattern = split_pattern(path).map_err(|_| failed(libc::EINVAL))?;
+    /* the file itself only matters for a content match on a literal path */
+    let watch_file = match (pattern, condition) {
+        (None, FileCondition::Contains(_)) => Some(path),
+        _ => None,
+    };
+    let dir = pattern.map_or_else(|| split_path(path).0, |(dir, _)| dir);
+    /* no kqueue (fd limit): plain sleeps, as before */
+    let queue = Kqueue::new().ok();
+    let cancel_fd = cancel.fd();
 
     let deadline_ns = timeout.map(|d| advance_ns(now_ns(confine), duration_to_ns(d)));
 
@@ -316,6 +883,15 @@ pub fn wait_for_file(path: &str, timeout: Option<Duration>, confine: Confine) ->
     let mut poll_interval_ms = INITIAL_POLL_MS;
 
     loop {
+        /* watch first, check second: a change in between still wakes us */
+        if cancel.flagged() {
+            return Ok(None);
+        }
+        let _watch = queue.as_ref().map(|q| Watch::arm(q, dir, watch_file));
+        if let Some(found) = satisfied(path, pattern, condition).map_err(failed)? {
+            return Ok(Some(found));
+        }
+
         /* Check timeout BEFORE sleeping to avoid overshoot */
         let sleep_time = if let Some(dl) = deadline_ns {
             let current = now_ns(confine);
@@ -329,13 +905,27 @@ pub fn wait_for_file(path: &str, timeout: Option<Duration>, confine: Confine) ->
             poll_interval_ms
         };
 
-        sleep_ms(sleep_time);
-
-        /* Check if file exists */
-        match file_exists(path) {
-            Ok(true) => return Ok(()),
-            Ok(false) => { /* Continue waiting */ }
-            Err(e) => return Err(TimeoutError::WaitForFileError(String::from(path), e)),
+        let sleep_ns = cancel.cap_ns(sleep_time * 1_000_000);
+        let slept = match &queue {
+            Some(q) => {
+                q.reset();
+                let mut event = kq::empty();
+                /* woken by the timer or a vnode event, rechecked either way;
+                 * EINTR too */
+                let changes = [
+                    kq::timer(1, sleep_ns),
+                    kq::read(cancel_fd.unwrap_or(0), cancel_fd.is_some()),
+                ];
+                match q.wait(&changes, &mut event) {
+                    Ok(()) => event.filter != kq::EVFILT_READ,
+                    Err(libc::EINTR) => true,
+                    Err(_) => sleep_cancellable(Duration::from_nanos(sleep_ns), cancel),
+                }
+            }
+            None => sleep_cancellable(Duration::from_nanos(sleep_ns), cancel),
+        };
+        if !slept {
+            return Ok(None);
         }
 
         /* Increase poll interval with exponential backoff */
@@ -343,6 +933,287 @@ pub fn wait_for_file(path: &str, timeout: Option<Duration>, confine: Confine) ->
     }
 }
 
+/// An exclusive `flock()` on a file, held until dropped - or until the
+/// process exits, however it exits. The descriptor is close-on-exec, so
+/// the command doesn't inherit the lock and can't keep it past us.
+#[derive(Debug)]
+pub struct FileLock {
+    fd: i32,
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // SAFETY: fd is ours and closed exactly once; closing releases the lock
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+impl FileLock {
+    /* open the lock file, creating it, without locking it yet */
+    fn open(path: &str) -> Result<Self> {
+        let failed = |e| TimeoutError::LockError(String::from(path), e);
+        let cpath = alloc::ffi::CString::new(path).map_err(|_| failed(libc::EINVAL))?;
+        // SAFETY: cpath is NUL-terminated; mode is passed as the variadic arg
+        let fd = unsafe {
+            libc::open(
+                cpath.as_ptr(),
+                libc::O_RDONLY | libc::O_CREAT | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+                0o644 as libc::c_uint,
+            )
+        };
+        if fd < 0 {
+            return Err(failed(errno()));
+        }
+        /* from here the descriptor closes on every way out */
+        Ok(Self { fd })
+    }
+
+    /* one try without blocking; false while someone else holds it */
+    fn try_lock(&self, path: &str) -> Result<bool> {
+        // SAFETY: fd is open; flock takes no pointers
+        if unsafe { libc::flock(self.fd, libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+            return Ok(true);
+        }
+        match errno() {
+            libc::EWOULDBLOCK | libc::EINTR => Ok(false),
+            err => Err(TimeoutError::LockError(String::from(path), err)),
+        }
+    }
+}
+
+/* lock polling backoff, shared by lock_file and lock_file_queued */
+const LOCK_INITIAL_POLL_MS: u64 = 10;
+const LOCK_MAX_POLL_MS: u64 = 250;
+
+/// Take an exclusive lock on `path`, created (mode 0644) if it doesn't
+/// exist and left in place afterwards - removing a lock file races with
+/// the next taker. While someone else holds it, retry with backoff for up
+/// to `timeout` (`None`: indefinitely, `Some(ZERO)`: one try). Returns
+/// `None` if `cancel` fired first.
+///
+/// # Errors
+///
+/// - `LockBusy` if the lock is still held when `timeout` expires
+/// - `LockError` if the file can't be opened or `flock()` fails otherwise
+pub fn lock_file(
+    path: &str,
+    timeout: Option<Duration>,
+    confine: Confine,
+    cancel: Cancel<'_>,
+) -> Result<Option<FileLock>> {
+    let lock = FileLock::open(path)?;
+    let deadline_ns = timeout.map(|d| advance_ns(now_ns(confine), duration_to_ns(d)));
+    let mut poll_interval_ms = LOCK_INITIAL_POLL_MS;
+    loop {
+        if lock.try_lock(path)? {
+            return Ok(Some(lock));
+        }
+        let sleep_ms = match deadline_ns {
+            Some(dl) => {
+                let current = now_ns(confine);
+                if deadline_reached(current, dl) {
+                    return Err(TimeoutError::LockBusy(String::from(path)));
+                }
+                poll_interval_ms.min((remaining_ns(current, dl) / 1_000_000).max(1))
+            }
+            None => poll_interval_ms,
+        };
+        if !sleep_cancellable(Duration::from_millis(sleep_ms), cancel) {
+            return Ok(None);
+        }
+        poll_interval_ms = (poll_interval_ms * 2).min(LOCK_MAX_POLL_MS);
+    }
+}
+
+/// How a [`lock_file_queued`] wait went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueWait {
+    /// Live tickets ahead of ours when we joined the queue.
+    pub ahead: u32,
+    /// How long until ours was first in line. Any wait after that was
+    /// for a holder that never queued, or for the last one to let go.
+    pub queued: Duration,
+}
+
+/* our place in a --flock-queue line: an empty file in <lock>.queue/
+ * named "<wall clock ns, 20 digits>.<pid>", so name order is arrival
+ * order. removed when dropped, holding the lock or giving up */
+struct Ticket {
+    dir: String,
+    name: String,
+}
+
+impl Ticket {
+    fn take(dir: &str) -> core::result::Result<Self, i32> {
+        let cdir = alloc::ffi::CString::new(dir).map_err(|_| libc::EINVAL)?;
+        // SAFETY: cdir is NUL-terminated
+        if unsafe { libc::mkdir(cdir.as_ptr(), 0o755) } != 0 && errno() != libc::EEXIST {
+            return Err(errno());
+        }
+        // SAFETY: getpid has no preconditions
+        let pid = unsafe { libc::getpid() };
+        loop {
+            let name = alloc::format!("{:020}.{}", wall_now_ns(), pid);
+            match safe_file::create_new(&alloc::format!("{dir}/{name}"), FileOptions::default()) {
+                Ok(fd) => {
+                    // SAFETY: fd was just opened and is closed once
+                    unsafe { libc::close(fd) };
+                    return Ok(Self {
+                        dir: String::from(dir),
+                        name,
+                    });
+                }
+                /* same nanosecond as another ticket: take the next */
+                Err(libc::EEXIST) => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /* live tickets named ahead of ours. tickets left by processes that are
+     * gone (SIGKILLed before they could clean up) are removed on the way */
+    fn ahead(&self) -> core::result::Result<u32, i32> {
+        let mut ahead = 0;
+        for path in glob_paths(&self.dir, "*")? {
+            let name = path.rsplit('/').next().unwrap_or("");
+            if name >= self.name.as_str() {
+                break;
+            }
+            let Some(pid) = name
+                .split_once('.')
+                .and_then(|(_, pid)| pid.parse::<libc::pid_t>().ok())
+            else {
+                continue; /* not a ticket */
+            };
+            // SAFETY: signal 0 only checks that pid exists
+            if unsafe { libc::kill(pid, 0) } == 0 || errno() == libc::EPERM {
+                ahead += 1;
+            } else if let Ok(cpath) = alloc::ffi::CString::new(path) {
+                // SAFETY: cpath is NUL-terminated
+                unsafe { libc::unlink(cpath.as_ptr()) };
+            }
+        }
+        Ok(ahead)
+    }
+}
+
+fn ticket_path(dir: &str, name: &str) -> core::result::Result<alloc::ffi::CString, i32> {
+    alloc::ffi::CString::new(alloc::format!("{dir}/{name}")).map_err(|_| libc::EINVAL)
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        if let Ok(cpath) = ticket_path(&self.dir, &self.name) {
+            // SAFETY: cpath is NUL-terminated
+            unsafe { libc::unlink(cpath.as_ptr()) };
+        }
+    }
+}
+
+/// [`lock_file`], but fair: join a queue in `<path>.queue/` (created if
+/// need be, and left in place like the lock file) and only try the lock
+/// once every live instance that queued earlier has had it, for up to
+/// `max_queue` in all (`None`: indefinitely). Instances that don't queue
+/// can still take the lock whenever it's free. Returns `None` if `cancel`
+/// fired first.
+///
+/// # Errors
+///
+/// - `LockQueueExpired` if `max_queue` runs out first
+/// - `LockError` if the lock file or a ticket can't be created, or
+///   `flock()` fails otherwise
+pub fn lock_file_queued(
+    path: &str,
+    max_queue: Option<Duration>,
+    confine: Confine,
+    cancel: Cancel<'_>,
+) -> Result<Option<(FileLock, QueueWait)>> {
+    let failed = |e| TimeoutError::LockError(String::from(path), e);
+    let lock = FileLock::open(path)?;
+    let mut dir = String::from(path);
+    dir.push_str(".queue");
+    let ticket = Ticket::take(&dir).map_err(failed)?;
+
+    let start_ns = now_ns(confine);
+    let deadline_ns = max_queue.map(|d| advance_ns(start_ns, duration_to_ns(d)));
+    let mut joined_ahead = None;
+    let mut queued_ns = None;
+    let mut poll_interval_ms = LOCK_INITIAL_POLL_MS;
+    loop {
+        let ahead = ticket.ahead().map_err(failed)?;
+        let joined_ahead = *joined_ahead.get_or_insert(ahead);
+        if ahead == 0 {
+            let queued_ns =
+                *queued_ns.get_or_insert_with(|| now_ns(confine).saturating_sub(start_ns));
+            if lock.try_lock(path)? {
+                drop(ticket);
+                let wait = QueueWait {
+                    ahead: joined_ahead,
+                    queued: Duration::from_nanos(queued_ns),
+                };
+                return Ok(Some((lock, wait)));
+            }
+        }
+        let sleep_ms = match deadline_ns {
+            Some(dl) => {
+                let current = now_ns(confine);
+                if deadline_reached(current, dl) {
+                    return Err(TimeoutError::LockQueueExpired(String::from(path), ahead));
+                }
+                poll_interval_ms.min((remaining_ns(current, dl) / 1_000_000).max(1))
+            }
+            None => poll_interval_ms,
+        };
+        if !sleep_cancellable(Duration::from_millis(sleep_ms), cancel) {
+            return Ok(None);
+        }
+        poll_interval_ms = (poll_interval_ms * 2).min(LOCK_MAX_POLL_MS);
+    }
+}
+
+/* longest a window wait sleeps before it reads the local clock again */
+const WINDOW_RECHECK: Duration = Duration::from_secs(60);
+
+/// Wait until `window` is open by the local clock, for at most
+/// `max_wait`. Returns how long it waited (zero if the window was already
+/// open), or `None` if `cancel` fired first.
+///
+/// When the window won't open within `max_wait` it gives up straight away
+/// rather than waiting `max_wait` out first.
+///
+/// # Errors
+///
+/// - `OutsideWindow` if the window isn't open and won't be within `max_wait`
+/// - `Internal` if the local time can't be read
+pub fn wait_for_window(
+    window: Window,
+    max_wait: Duration,
+    cancel: Cancel<'_>,
+) -> Result<Option<Duration>> {
+    let start_ns = wall_now_ns();
+    let deadline_ns = advance_ns(start_ns, duration_to_ns(max_wait));
+    loop {
+        if cancel.flagged() {
+            return Ok(None);
+        }
+        let sec_of_day = local_sec_of_day()
+            .ok_or_else(|| TimeoutError::Internal("can't read the local time".to_string()))?;
+        let current = wall_now_ns();
+        let until_open = window.until_open(sec_of_day);
+        if until_open == 0 {
+            return Ok(Some(Duration::from_nanos(current.saturating_sub(start_ns))));
+        }
+        let opens_ns = advance_ns(current, u64::from(until_open) * 1_000_000_000);
+        if opens_ns > deadline_ns {
+            return Err(TimeoutError::OutsideWindow(window.to_string()));
+        }
+        let nap = Duration::from_secs(u64::from(until_open)).min(WINDOW_RECHECK);
+        if !delay(nap, cancel) {
+            return Ok(None);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -418,4 +1289,362 @@ mod tests {
         // Should have completed in a reasonable time
         assert!(elapsed < Duration::from_millis(500));
     }
+
+    #[test]
+    fn test_glob_match() {
+        let m = |p: &str, n: &str| glob_match(p.as_bytes(), n.as_bytes());
+        assert!(m("ready.*", "ready.42"));
+        assert!(m("*.sock", "a.sock"));
+        assert!(!m("*.sock", "a.sock.tmp"));
+        assert!(m("job-??", "job-07"));
+        assert!(!m("job-??", "job-7"));
+        assert!(m("[abc]*", "beta"));
+        assert!(!m("[!abc]*", "beta"));
+        assert!(m("v[0-9].txt", "v3.txt"));
+        assert!(m("[]x]", "]"));
+        assert!(m("a[b", "a[b"), "unclosed [ is literal");
+        assert!(m("lit\\*", "lit*"));
+        assert!(!m("lit\\*", "little"));
+        assert!(m("*a*b*c", "xxaxxbxxbxc"));
+        /* wildcards skip dotfiles, an explicit dot doesn't */
+        assert!(!m("*", ".hidden"));
+        assert!(m(".h*", ".hidden"));
+    }
+
+    #[test]
+    fn test_split_pattern() {
+        assert_eq!(split_pattern("/tmp/ready"), Ok(None));
+        assert_eq!(split_pattern("/tmp/r*"), Ok(Some(("/tmp", "r*"))));
+        assert_eq!(split_pattern("/r*"), Ok(Some(("/", "r*"))));
+        assert_eq!(split_pattern("r?"), Ok(Some((".", "r?"))));
+        assert_eq!(split_pattern("/tmp/*/ready"), Err("/tmp/*"));
+    }
+
+    #[test]
+    fn test_file_contains_across_reads() {
+        let path = &std::format!(
+            "{}/procguard-contains-test-{}",
+            std::env::temp_dir().display(),
+            std::process::id()
+        );
+        let mut data = vec![b'x'; CONTAINS_CHUNK - 3];
+        data.extend_from_slice(b"MARKER");
+        fs::write(path, &data).unwrap();
+        let split = file_contains(path, b"MARKER");
+        let missing = file_contains(path, b"MARKERS");
+        let _ = fs::remove_file(path);
+
+        assert_eq!(split, Ok(true), "marker straddling two reads");
+        assert_eq!(missing, Ok(false));
+        assert_eq!(file_contains(path, b"x"), Ok(false), "no file, no marker");
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Miri doesn't support mach_continuous_time
+    fn test_wait_for_file_conditions() {
+        let dir = &std::format!(
+            "{}/procguard-wait-dir-test-{}",
+            std::env::temp_dir().display(),
+            std::process::id()
+        );
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir(dir).unwrap();
+        let in_dir = |name: &str| format!("{dir}/{name}");
+
+        /* the first match in name order; dotfiles and non-matches ignored */
+        fs::write(in_dir("ready.b"), "").unwrap();
+        fs::write(in_dir("ready.a"), "").unwrap();
+        fs::write(in_dir(".ready.0"), "").unwrap();
+        let pattern = in_dir("ready.*");
+        let first = wait_for_file_condition(
+            &pattern,
+            FileCondition::Exists,
+            Some(Duration::from_millis(100)),
+            Confine::Wall,
+        );
+
+        /* a lock removed partway through */
+        let lock = in_dir("lock");
+        fs::write(&lock, "").unwrap();
+        let remove = lock.clone();
+        let remover = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(30));
+            fs::remove_file(remove).unwrap();
+        });
+        let gone = wait_for_file_condition(
+            &lock,
+            FileCondition::Gone,
+            Some(Duration::from_secs(2)),
+            Confine::Wall,
+        );
+        remover.join().unwrap();
+
+        /* a log that says it's ready after a while */
+        let log = in_dir("app.log");
+        fs::write(&log, "starting\n").unwrap();
+        let append = log.clone();
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(30));
+            fs::write(append, "starting\nlistening on :8080\n").unwrap();
+        });
+        let contains = wait_for_file_condition(
+            &in_dir("*.log"),
+            FileCondition::Contains("listening on"),
+            Some(Duration::from_secs(2)),
+            Confine::Wall,
+        );
+        writer.join().unwrap();
+
+        let never = wait_for_file_condition(
+            &log,
+            FileCondition::Contains("never"),
+            Some(Duration::from_millis(50)),
+            Confine::Wall,
+        );
+        let bad =
+            wait_for_file_condition("/tmp/*/ready", FileCondition::Exists, None, Confine::Wall);
+        let _ = fs::remove_dir_all(dir);
+
+        assert_eq!(first.unwrap(), in_dir("ready.a"));
+        assert_eq!(gone.unwrap(), lock);
+        assert_eq!(contains.unwrap(), log);
+        assert!(matches!(never, Err(TimeoutError::WaitForFileTimeout(_))));
+        assert!(matches!(
+            bad,
+            Err(TimeoutError::WaitForFileError(_, libc::EINVAL))
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Miri doesn't support kqueue
+    fn test_delay_runs_out() {
+        let start = std::time::Instant::now();
+        assert!(delay(Duration::from_millis(30), Cancel::Never));
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert!(delay(Duration::ZERO, Cancel::Never));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Miri doesn't support kqueue
+    fn test_delay_confined_wall() {
+        /* past the one-second probe, so the wall clock gets looked at midway */
+        let start = std::time::Instant::now();
+        assert!(delay_confined(
+            Duration::from_millis(1100),
+            Confine::Wall,
+            Cancel::Never
+        ));
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(1100), "{elapsed:?}");
+        assert!(elapsed < Duration::from_secs(3), "{elapsed:?}");
+
+        let stop = AtomicBool::new(true);
+        assert!(!delay_confined(
+            Duration::from_secs(10),
+            Confine::Wall,
+            Cancel::Flag(&stop)
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Miri doesn't support kqueue
+    fn test_wait_for_pid() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("0.2")
+            .spawn()
+            .expect("spawn");
+        #[allow(clippy::cast_possible_wrap)]
+        let pid = child.id() as i32;
+        let wait = |ms| {
+            wait_for_pid(
+                pid,
+                Some(Duration::from_millis(ms)),
+                Confine::Wall,
+                Cancel::Never,
+            )
+            .expect("wait_for_pid")
+        };
+        assert_eq!(wait(20), PidWait::TimedOut);
+        /* nothing was sent: it's still running */
+        assert!(child.try_wait().expect("try_wait").is_none());
+        assert_eq!(wait(5_000), PidWait::Exited);
+
+        child.wait().expect("wait");
+        assert_eq!(wait(5_000), PidWait::NotRunning);
+
+        let stop = AtomicBool::new(true);
+        assert_eq!(
+            wait_for_pid(
+                std::process::id().cast_signed(),
+                None,
+                Confine::Active,
+                Cancel::Flag(&stop)
+            )
+            .expect("wait_for_pid"),
+            PidWait::Cancelled
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Miri doesn't support kqueue
+    fn test_delay_cancelled() {
+        use std::io::Write;
+        use std::os::fd::AsRawFd;
+        use std::os::unix::net::UnixStream;
+        use std::sync::Arc;
+
+        let flag = Arc::new(AtomicBool::new(false));
+        let setter = Arc::clone(&flag);
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(30));
+            setter.store(true, Ordering::Release);
+        });
+        let start = std::time::Instant::now();
+        assert!(!delay(Duration::from_secs(10), Cancel::Flag(&flag)));
+        assert!(start.elapsed() < Duration::from_secs(2));
+        /* already set: not even a zero delay completes */
+        assert!(!delay(Duration::ZERO, Cancel::Flag(&flag)));
+
+        let (mut tx, rx) = UnixStream::pair().unwrap();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(30));
+            tx.write_all(b"x").unwrap();
+        });
+        let start = std::time::Instant::now();
+        assert!(!delay(Duration::from_secs(10), Cancel::Fd(rx.as_raw_fd())));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Miri doesn't support mach_continuous_time
+    fn test_wait_for_path_cancelled() {
+        let stop = AtomicBool::new(true);
+        let result = wait_for_path(
+            "/tmp/this_file_will_never_exist_98765",
+            FileCondition::Exists,
+            None,
+            Confine::Wall,
+            Cancel::Flag(&stop),
+        );
+        assert!(matches!(result, Ok(None)));
+
+        stop.store(false, Ordering::Relaxed);
+        let result = wait_for_path(
+            "Cargo.toml",
+            FileCondition::Exists,
+            None,
+            Confine::Wall,
+            Cancel::Flag(&stop),
+        );
+        assert_eq!(result.unwrap().as_deref(), Some("Cargo.toml"));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Miri doesn't support flock
+    fn test_lock_file() {
+        let path = std::format!(
+            "{}/procguard-lock-test-{}",
+            std::env::temp_dir().display(),
+            std::process::id()
+        );
+        let lock = lock_file(&path, None, Confine::Wall, Cancel::Never)
+            .unwrap()
+            .unwrap();
+        /* flock is per open file, so a second open conflicts even here */
+        let start = std::time::Instant::now();
+        let busy = lock_file(
+            &path,
+            Some(Duration::from_millis(100)),
+            Confine::Wall,
+            Cancel::Never,
+        );
+        assert!(matches!(busy, Err(TimeoutError::LockBusy(_))));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        let stop = AtomicBool::new(true);
+        let cancelled = lock_file(&path, None, Confine::Wall, Cancel::Flag(&stop));
+        assert!(matches!(cancelled, Ok(None)));
+
+        drop(lock);
+        assert!(
+            lock_file(&path, Some(Duration::ZERO), Confine::Wall, Cancel::Never)
+                .unwrap()
+                .is_some()
+        );
+        let _ = fs::remove_file(&path);
+
+        assert!(matches!(
+            lock_file("/nonexistent-dir/x", None, Confine::Wall, Cancel::Never),
+            Err(TimeoutError::LockError(_, libc::ENOENT))
+        ));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Miri doesn't support localtime_r
+    fn test_wait_for_window() {
+        let minute = local_sec_of_day().unwrap() / 60;
+        let hhmm = |m: u32| std::format!("{:02}:{:02}", (m / 60) % 24, m % 60);
+        let window = |from: u32, to: u32| {
+            Window::parse(&std::format!("{}-{}", hhmm(from), hhmm(to))).unwrap()
+        };
+
+        /* open now, and for the next two hours: no wait */
+        let waited = wait_for_window(window(minute, minute + 120), Duration::ZERO, Cancel::Never);
+        assert!(waited.unwrap().is_some_and(|d| d < Duration::from_secs(1)));
+
+        /* opens in an hour: refused at once, not after max_wait */
+        let later = window(minute + 60, minute + 120);
+        let start = std::time::Instant::now();
+        assert!(matches!(
+            wait_for_window(later, Duration::from_secs(5), Cancel::Never),
+            Err(TimeoutError::OutsideWindow(_))
+        ));
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        /* cancelled while waiting */
+        let stop = AtomicBool::new(true);
+        let waited = wait_for_window(later, Duration::from_secs(3 * 3600), Cancel::Flag(&stop));
+        assert!(matches!(waited, Ok(None)));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Miri doesn't support flock
+    fn test_lock_file_queued() {
+        let path = std::format!(
+            "{}/procguard-queue-test-{}",
+            std::env::temp_dir().display(),
+            std::process::id()
+        );
+        let dir = std::format!("{path}.queue");
+        let short = Some(Duration::from_millis(50));
+
+        /* first in line, but the lock is held by someone who didn't queue */
+        let lock = lock_file(&path, None, Confine::Wall, Cancel::Never)
+            .unwrap()
+            .unwrap();
+        let held = lock_file_queued(&path, short, Confine::Wall, Cancel::Never);
+        assert!(matches!(held, Err(TimeoutError::LockQueueExpired(_, 0))));
+        drop(lock);
+
+        /* an earlier live ticket (ours) holds us back; a dead one doesn't */
+        let live = std::format!("{dir}/00000000000000000001.{}", std::process::id());
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+        let dead = std::format!("{dir}/00000000000000000002.{}", child.id());
+        fs::write(&live, "").unwrap();
+        fs::write(&dead, "").unwrap();
+        let behind = lock_file_queued(&path, short, Confine::Wall, Cancel::Never);
+        assert!(matches!(behind, Err(TimeoutError::LockQueueExpired(_, 1))));
+        assert!(!std::path::Path::new(&dead).exists());
+
+        fs::remove_file(&live).unwrap();
+        let (lock, wait) = lock_file_queued(&path, short, Confine::Wall, Cancel::Never)
+            .unwrap()
+            .unwrap();
+        assert_eq!(wait.ahead, 0);
+        /* our own ticket is gone once the lock is ours */
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        drop(lock);
+        let _ = fs::remove_dir(&dir);
+        let _ = fs::remove_file(&path);
+    }
 }
@@ -0,0 +1,226 @@
+/*
+ * window.rs
+ *
+ * --only-between 22:00-06:00: a time-of-day window, in local time, that
+ * the command may only run in. a window whose end comes before its start
+ * runs past midnight. minutes only - a maintenance window has no use for
+ * seconds, and "22:00" can't be misread.
+ *
+ * local time is whatever localtime_r says, so TZ applies. the distances
+ * below are in seconds of the local day: on the night the clocks change,
+ * a window spanning the change runs an hour long or short.
+ */
+
+use core::fmt;
+
+const DAY_SECS: u32 = 86_400;
+
+/// A daily window of local time: `start` inclusive, `end` exclusive,
+/// wrapping past midnight when `end` is earlier than `start`.
+///
+/// ```
+/// use procguard::Window;
+///
+/// let night = Window::parse("22:00-06:00").unwrap();
+/// let hour = 3600;
+/// assert!(night.contains(23 * hour) && night.contains(5 * hour));
+/// assert!(!night.contains(12 * hour));
+/// assert_eq!(night.until_open(21 * hour), hour);
+/// assert_eq!(night.to_string(), "22:00-06:00");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    /* minutes since local midnight */
+    start: u16,
+    end: u16,
+}
+
+/* "HH:MM" as minutes since midnight; "24:00" only where `end` allows it */
+fn parse_hhmm(s: &str, end: bool) -> Option<u16> {
+    let (h, m) = s.split_once(':')?;
+    if h.is_empty() || h.len() > 2 || m.len() != 2 {
+        return None;
+    }
+    if !h.bytes().chain(m.bytes()).all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let h: u16 = h.parse().ok()?;
+    let m: u16 = m.parse().ok()?;
+    match (h, m) {
+        (24, 0) if end => Some(24 * 60),
+        (0..=23, 0..=59) => Some(h * 60 + m),
+        _ => None,
+    }
+}
+
+impl Window {
+    /// Parse "HH:MM-HH:MM" ("22:00-06:00", "9:30-17:00"). The end may be
+    /// "24:00". `None` for anything else, including an empty window
+    /// (start = end).
+    #[must_use]
+    pub fn parse(s: &str) -> Option<Self> {
+        let (start, end) = s.trim().split_once('-')?;
+        let start = parse_hhmm(start, false)?;
+        /* 24:00 is midnight, as an end */
+        let end = parse_hhmm(end, true)? % (24 * 60);
+        (start != end).then_some(Self { start, end })
+    }
+
+    const fn start_secs(self) -> u32 {
+        self.start as u32 * 60
+    }
+
+    const fn end_secs(self) -> u32 {
+        self.end as u32 * 60
+    }
+
+    /// Whether `sec_of_day` (seconds since local midnight) is inside.
+    #[must_use]
+    pub const fn contains(self, sec_of_day: u32) -> bool {
+        let (start, end) = (self.start_secs(), self.end_secs());
+        if start < end {
+            start <= sec_of_day && sec_of_day < end
+        } else {
+            sec_of_day >= start || sec_of_day < end
+        }
+    }
+
+    /// Seconds from `sec_of_day` until the window next opens; 0 inside it.
+    #[must_use]
+    pub const fn until_open(self, sec_of_day: u32) -> u32 {
+        if self.contains(sec_of_day) {
+            0
+        } else {
+            (self.start_secs() + DAY_SECS - sec_of_day % DAY_SECS) % DAY_SECS
+        }
+    }
+
+    /// Seconds from `sec_of_day` until the window closes; 0 outside it.
+    #[must_use]
+    pub const fn until_close(self, sec_of_day: u32) -> u32 {
+        if self.contains(sec_of_day) {
+            match (self.end_secs() + DAY_SECS - sec_of_day % DAY_SECS) % DAY_SECS {
+                /* only at the end itself, which isn't inside */
+                0 => DAY_SECS,
+                left => left,
+            }
+        } else {
+            0
+        }
+    }
+
+    /// Whether the window is open now, by the local clock. A clock that
+    /// can't be read counts as closed.
+    #[must_use]
+    pub fn is_open(self) -> bool {
+        local_sec_of_day().is_some_and(|s| self.contains(s))
+    }
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.start / 60,
+            self.start % 60,
+            self.end / 60,
+            self.end % 60
+        )
+    }
+}
+
+/// Seconds since local midnight, `None` if the clock or the time zone
+/// can't be read.
+#[must_use]
+pub fn local_sec_of_day() -> Option<u32> {
+    // SAFETY: time accepts a null pointer and only returns the time
+    let now = unsafe { libc::time(core::ptr::null_mut()) };
+    // SAFETY: tm is plain data; all zeroes is a valid value to overwrite
+    let mut tm: libc::tm = unsafe { core::mem::zeroed() };
+    // SAFETY: both pointers are valid for the call; localtime_r is the
+    // thread-safe variant and keeps no reference to either
+    if unsafe { libc::localtime_r(&raw const now, &raw mut tm) }.is_null() {
+        return None;
+    }
+    /* tm_sec can be 60 on a leap second */
+    let secs = tm.tm_hour * 3600 + tm.tm_min * 60 + tm.tm_sec.min(59);
+    u32::try_from(secs).ok().filter(|&s| s < DAY_SECS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const H: u32 = 3600;
+
+    #[test]
+    fn test_parse() {
+        let w = Window::parse("22:00-06:00").unwrap();
+        assert_eq!(alloc::format!("{w}"), "22:00-06:00");
+        let w = Window::parse(" 9:30-17:05 ").unwrap();
+        assert_eq!(alloc::format!("{w}"), "09:30-17:05");
+        let w = Window::parse("18:00-24:00").unwrap();
+        assert_eq!(alloc::format!("{w}"), "18:00-00:00");
+
+        for bad in [
+            "",
+            "22:00",
+            "22:00-",
+            "-06:00",
+            "22-06",
+            "22:0-06:00",
+            "25:00-06:00",
+            "22:60-06:00",
+            "24:00-06:00",
+            "22:00-24:01",
+            "+1:00-02:00",
+            "10:00-10:00",
+            "00:00-24:00",
+            "22:00-06:00-07:00",
+        ] {
+            assert_eq!(Window::parse(bad), None, "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn test_contains() {
+        let day = Window::parse("09:00-17:00").unwrap();
+        assert!(!day.contains(9 * H - 1));
+        assert!(day.contains(9 * H));
+        assert!(day.contains(17 * H - 1));
+        assert!(!day.contains(17 * H));
+
+        let night = Window::parse("22:00-06:00").unwrap();
+        assert!(night.contains(23 * H));
+        assert!(night.contains(0));
+        assert!(night.contains(6 * H - 1));
+        assert!(!night.contains(6 * H));
+        assert!(!night.contains(12 * H));
+        assert!(night.contains(22 * H));
+    }
+
+    #[test]
+    fn test_until_open_close() {
+        let night = Window::parse("22:00-06:00").unwrap();
+        assert_eq!(night.until_open(21 * H), H);
+        assert_eq!(night.until_open(6 * H), 16 * H);
+        assert_eq!(night.until_open(23 * H), 0);
+        assert_eq!(night.until_close(23 * H), 7 * H);
+        assert_eq!(night.until_close(H), 5 * H);
+        assert_eq!(night.until_close(12 * H), 0);
+
+        let day = Window::parse("09:00-17:00").unwrap();
+        /* after it closes, the next one is tomorrow's */
+        assert_eq!(day.until_open(18 * H), 15 * H);
+        assert_eq!(day.until_close(17 * H - 1), 1);
+        let evening = Window::parse("18:00-24:00").unwrap();
+        assert_eq!(evening.until_close(23 * H), H);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Miri doesn't support localtime_r
+    fn test_local_sec_of_day() {
+        assert!(local_sec_of_day().is_some_and(|s| s < DAY_SECS));
+    }
+}
@@ -665,6 +665,28 @@ fn bench_json_with_timeout() {
     );
 }
 
+/* result JSON is built in a stack buffer - completed and plain timeouts
+ * shouldn't allocate for it. only measurable with the counting allocator:
+ * cargo test --release --features alloc-stats --test benchmarks */
+#[cfg(feature = "alloc-stats")]
+#[test]
+fn bench_json_result_allocations() {
+    for args in [
+        &["--json", "60s", "true"][..],
+        &["--json", "50ms", "sleep", "60"],
+    ] {
+        let out = timeout_cmd().args(args).output().unwrap();
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        println!("{:?}: {}", args, stderr.trim());
+        assert!(
+            stderr.contains("allocations (building the result): 0 "),
+            "result JSON allocated for {:?}: {}",
+            args,
+            stderr
+        );
+    }
+}
+
 /* =========================================================================
  * VERBOSE MODE - Overhead of -v flag
  * ========================================================================= */
@@ -923,3 +945,55 @@ fn bench_stdin_timeout_triggers() {
         elapsed
     );
 }
+
+/* =========================================================================
+ * MONITORING OVERHEAD - throttle and memory limit polling at 100ms
+ * ========================================================================= */
+
+/* our own CPU time as `times` reports it for children of a shell. clock
+ * ticks, so coarse - a ceiling, not a measurement (see RESULTS.md) */
+fn children_cpu(times_output: &str) -> Duration {
+    /* second line: "0m0.004s 0m0.012s" - user, then system */
+    let line = times_output.lines().nth(1).unwrap_or_default();
+    line.split_whitespace()
+        .filter_map(|t| {
+            let (m, s) = t.strip_suffix('s')?.split_once('m')?;
+            Some(m.parse::<f64>().ok()? * 60.0 + s.parse::<f64>().ok()?)
+        })
+        .map(Duration::from_secs_f64)
+        .sum()
+}
+
+#[test]
+fn bench_monitoring_overhead_100ms() {
+    /*
+     * --cpu-percent and --mem-limit both sample the child every 100ms.
+     * They share one rusage read per wake, so a long run costs us a few
+     * ms of CPU per second, not a steady slice of a core. The child
+     * sleeps, so the CPU measured is ours.
+     */
+    let run = Duration::from_secs(5);
+    let script = format!(
+        "{} --cpu-percent 400 --mem-limit 1G {}s sleep 60; times",
+        timeout_bin_path(),
+        run.as_secs()
+    );
+    let output = std::process::Command::new("sh")
+        .args(["-c", &script])
+        .output()
+        .expect("failed to run sh");
+    let cpu = children_cpu(&String::from_utf8_lossy(&output.stdout));
+    let per_sec = cpu / run.as_secs() as u32;
+
+    println!(
+        "Monitoring at 100ms for {:?}: {:?} CPU ({:?}/s)",
+        run, cpu, per_sec
+    );
+
+    /* 50 wakes a second would be ~10ms/s even on a slow runner */
+    assert!(
+        per_sec < Duration::from_millis(20),
+        "monitoring overhead too high: {:?}/s",
+        per_sec
+    );
+}
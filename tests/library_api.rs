@@ -9,12 +9,14 @@
 use std::time::Duration;
 
 use procguard::error::exit_codes;
+use procguard::run_id::RunId;
 use procguard::runner::{
-    RunConfig, RunResult, cleanup_signal_forwarding, run_command, run_with_retry,
-    setup_signal_forwarding,
+    RunConfig, RunResult, SignalForwardGuard, run_command, run_with_retry, setup_signal_forwarding,
 };
 use procguard::signal::Signal;
-use procguard::{TimeoutReason, parse_duration, parse_signal};
+use procguard::{
+    EventLog, Outcome, TimeoutReason, Warning, parse_duration, parse_signal, run_with_retry_events,
+};
 
 fn basic_config(timeout: Duration) -> RunConfig {
     RunConfig {
@@ -32,7 +34,7 @@ fn basic_config(timeout: Duration) -> RunConfig {
 
 #[test]
 fn library_run_command_completes() {
-    let _ = setup_signal_forwarding();
+    let _forwarding = setup_signal_forwarding();
 
     let config = basic_config(Duration::from_secs(2));
     let args = ["-c".to_string(), "exit 0".to_string()];
@@ -45,13 +47,11 @@ fn library_run_command_completes() {
         }
         _ => panic!("expected Completed, got other variant"),
     }
-
-    cleanup_signal_forwarding();
 }
 
 #[test]
 fn library_run_command_nonzero_exit() {
-    let _ = setup_signal_forwarding();
+    let _forwarding = setup_signal_forwarding();
 
     let config = basic_config(Duration::from_secs(2));
     let args = ["-c".to_string(), "exit 42".to_string()];
@@ -66,13 +66,11 @@ fn library_run_command_nonzero_exit() {
         }
         _ => panic!("expected Completed, got other variant"),
     }
-
-    cleanup_signal_forwarding();
 }
 
 #[test]
 fn library_run_command_times_out() {
-    let _ = setup_signal_forwarding();
+    let _forwarding = setup_signal_forwarding();
 
     let config = basic_config(Duration::from_millis(150));
     let args = ["10".to_string()];
@@ -86,8 +84,51 @@ fn library_run_command_times_out() {
         }
         _ => panic!("expected TimedOut, got other variant"),
     }
+}
 
-    cleanup_signal_forwarding();
+#[test]
+fn library_outcome_classifies_results() {
+    let _forwarding = setup_signal_forwarding();
+
+    let config = basic_config(Duration::from_secs(2));
+    let run = |script: &str| {
+        let args = ["-c".to_string(), script.to_string()];
+        run_command("sh", &args, &config).expect("run_command should succeed")
+    };
+
+    let ok = run("exit 0");
+    assert!(matches!(Outcome::from(&ok), Outcome::Success { code: 0 }));
+    assert_eq!(ok.exit_code(false, 124), 0);
+
+    let failed = run("exit 7");
+    assert!(matches!(
+        Outcome::from(&failed),
+        Outcome::Failed { code: 7 }
+    ));
+    assert_eq!(Outcome::from(&failed).as_str(), "completed");
+
+    let crashed = run("ulimit -c 0; kill -ABRT $$");
+    assert!(matches!(
+        Outcome::from(&crashed),
+        Outcome::Crashed {
+            signal: Signal::SIGABRT
+        }
+    ));
+    assert_eq!(crashed.exit_code(false, 124), 134);
+
+    let timed_out = run_command(
+        "sleep",
+        &["10".to_string()],
+        &basic_config(Duration::from_millis(150)),
+    )
+    .expect("run_command should succeed");
+    assert!(matches!(
+        Outcome::from(timed_out),
+        Outcome::TimedOut {
+            reason: TimeoutReason::WallClock,
+            ..
+        }
+    ));
 }
 
 /* =========================================================================
@@ -96,7 +137,7 @@ fn library_run_command_times_out() {
 
 #[test]
 fn library_run_command_not_found() {
-    let _ = setup_signal_forwarding();
+    let _forwarding = setup_signal_forwarding();
 
     let config = basic_config(Duration::from_secs(5));
     let args: [String; 0] = [];
@@ -109,8 +150,6 @@ fn library_run_command_not_found() {
         }
         Ok(_) => panic!("expected error for nonexistent command"),
     }
-
-    cleanup_signal_forwarding();
 }
 
 /* =========================================================================
@@ -119,7 +158,7 @@ fn library_run_command_not_found() {
 
 #[test]
 fn library_run_with_retry_succeeds_first_try() {
-    let _ = setup_signal_forwarding();
+    let _forwarding = setup_signal_forwarding();
 
     let config = RunConfig {
         timeout: Duration::from_secs(5),
@@ -142,8 +181,6 @@ fn library_run_with_retry_succeeds_first_try() {
     /* should have exactly one attempt when command succeeds */
     assert_eq!(attempts.len(), 1);
     assert_eq!(attempts.as_slice()[0].status, "completed");
-
-    cleanup_signal_forwarding();
 }
 
 #[test]
@@ -152,7 +189,7 @@ fn library_run_with_retry_retries_on_timeout() {
      * interference - SignalForwarded can occur if another test's signal
      * reaches this process. Accept both TimedOut and SignalForwarded as
      * valid "didn't complete successfully" outcomes. */
-    let _ = setup_signal_forwarding();
+    let _forwarding = setup_signal_forwarding();
 
     let config = RunConfig {
         timeout: Duration::from_millis(100),
@@ -192,8 +229,94 @@ fn library_run_with_retry_retries_on_timeout() {
             attempt.status
         );
     }
+}
+
+#[test]
+fn library_warnings_stay_with_their_run() {
+    /* one run warns; the next, in the same process, has nothing to report */
+    let capped = RunConfig {
+        retry_count: u32::MAX,
+        ..basic_config(Duration::from_secs(2))
+    };
+    let args = ["-c".to_string(), "exit 0".to_string()];
+    let mut first = EventLog::new();
+    run_with_retry_events("sh", &args, &capped, &mut first).expect("first run");
+    assert!(
+        first
+            .warnings()
+            .iter()
+            .any(|w| matches!(w, Warning::RetryCapped { .. }))
+    );
+
+    let mut second = EventLog::new();
+    let config = basic_config(Duration::from_secs(2));
+    run_with_retry_events("sh", &args, &config, &mut second).expect("second run");
+    assert!(second.warnings().is_empty());
+}
+
+#[test]
+fn library_run_ids_stay_with_their_run() {
+    /* --print-pid's line carries the id of the run it's about */
+    use std::os::fd::AsRawFd;
 
-    cleanup_signal_forwarding();
+    let args = ["-c".to_string(), "exit 0".to_string()];
+    let started = |id: Option<&str>| {
+        let path = std::env::temp_dir().join(format!(
+            "procguard-lib-run-id-{}-{}",
+            std::process::id(),
+            id.unwrap_or("none")
+        ));
+        let file = std::fs::File::create(&path).expect("create");
+        let config = RunConfig {
+            pid_json_fd: Some(file.as_raw_fd()),
+            ..basic_config(Duration::from_secs(2))
+        };
+        let mut events = EventLog::new();
+        if let Some(id) = id {
+            events.set_run_id(RunId::new(Some(id.to_string())));
+        }
+        run_with_retry_events("sh", &args, &config, &mut events).expect("run");
+        let line = std::fs::read_to_string(&path).expect("read");
+        let _ = std::fs::remove_file(&path);
+        line
+    };
+    assert!(started(Some("shard-1")).contains(r#""run_id":"shard-1","status":"started""#));
+    assert!(started(Some("shard-2")).contains(r#""run_id":"shard-2","status":"started""#));
+    /* a run given no id has none */
+    assert!(!started(None).contains("run_id"));
+}
+
+#[test]
+fn library_loggers_stay_with_their_run() {
+    /* the tag and quiet level are each RunConfig's own, not the process's */
+    let ci = RunConfig {
+        tag: Some("ci".to_string()),
+        quiet: true,
+        ..basic_config(Duration::from_secs(2))
+    };
+    let plain = RunConfig {
+        quiet: false,
+        ..basic_config(Duration::from_secs(2))
+    };
+    assert_eq!(ci.log().tag(), "ci");
+    assert!(ci.log().is_quiet());
+    assert_eq!(plain.log().tag(), "timeout");
+    assert!(!plain.log().is_quiet());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn library_reap_orphans_leaves_no_subreaper() {
+    /* PR_SET_CHILD_SUBREAPER is the whole process's: an embedder isn't
+     * left one once the run is over */
+    let config = RunConfig {
+        reap_orphans: true,
+        ..basic_config(Duration::from_secs(2))
+    };
+    let args = ["-c".to_string(), "sleep 10 & exit 0".to_string()];
+    let result = run_command("sh", &args, &config).expect("run_command should succeed");
+    assert!(Outcome::from(&result).is_success());
+    assert!(!procguard::reaper::is_subreaper());
 }
 
 /* =========================================================================
@@ -223,17 +346,50 @@ fn library_parse_signal_works() {
  * ========================================================================= */
 
 #[test]
-fn library_cleanup_signal_forwarding_is_idempotent() {
-    cleanup_signal_forwarding();
-    cleanup_signal_forwarding();
+fn library_setup_cleanup_cycle() {
+    /* verify forwarding can be set up again after the guard is dropped */
+    for _ in 0..3 {
+        let guard = setup_signal_forwarding();
+        assert!(guard.is_ok(), "setup should install handlers");
+        drop(guard);
+    }
 }
 
 #[test]
-fn library_setup_cleanup_cycle() {
-    /* verify setup/cleanup can be called multiple times */
-    for _ in 0..3 {
-        let fd = setup_signal_forwarding();
-        assert!(fd.is_some(), "setup should return valid fd");
-        cleanup_signal_forwarding();
+fn library_nested_guards() {
+    /* guards nest: dropping the inner one leaves forwarding to the outer */
+    let outer: SignalForwardGuard = setup_signal_forwarding().expect("outer guard");
+    {
+        let _inner = setup_signal_forwarding().expect("inner guard");
+    }
+    let config = basic_config(Duration::from_secs(2));
+    let args = ["-c".to_string(), "exit 0".to_string()];
+    let result = run_command("sh", &args, &config).expect("run_command should succeed");
+    assert!(matches!(result, RunResult::Completed { .. }));
+    drop(outer);
+}
+
+#[test]
+fn library_concurrent_runs_with_forwarding() {
+    /* several callers, each with its own guard, overlapping in time:
+     * one caller dropping its guard must not break another's run */
+    let workers: Vec<_> = (0..4)
+        .map(|i| {
+            std::thread::spawn(move || {
+                let forwarding = setup_signal_forwarding();
+                assert!(forwarding.is_ok());
+                let config = basic_config(Duration::from_secs(5));
+                let args = ["-c".to_string(), format!("sleep 0.{i}; exit {i}")];
+                let result = run_command("sh", &args, &config);
+                drop(forwarding);
+                match result.expect("run_command should succeed") {
+                    RunResult::Completed { status, .. } => assert_eq!(status.code(), Some(i)),
+                    _ => panic!("expected Completed, got other variant"),
+                }
+            })
+        })
+        .collect();
+    for w in workers {
+        w.join().expect("worker panicked");
     }
 }
@@ -8,8 +8,9 @@
 use proptest::prelude::*;
 use std::time::Duration;
 
-use procguard::duration::parse_duration;
-use procguard::rlimit::parse_mem_limit;
+use procguard::args::parse_from_slice;
+use procguard::duration::{format_duration, parse_duration};
+use procguard::rlimit::{parse_cpu_percent, parse_mem_limit};
 use procguard::signal::{Signal, parse_signal, signal_name};
 
 /* ============================================================================
@@ -63,6 +64,30 @@ proptest! {
     }
 }
 
+/* format_duration output parses back to the same duration, and is
+ * canonical: formatting that again gives the same string */
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(1000))]
+
+    #[test]
+    fn duration_format_roundtrip(nanos in any::<u64>()) {
+        let d = Duration::from_nanos(nanos);
+        let s = format_duration(d).to_string();
+        let parsed = parse_duration(&s).expect("format_duration output should parse");
+        prop_assert_eq!(parsed, d);
+        prop_assert_eq!(format_duration(parsed).to_string(), s);
+    }
+
+    #[test]
+    fn duration_format_parse_stable(n in 0u64..100_000, unit in prop::sample::select(vec![
+        "", "s", "ms", "us", "m", "h", "d",
+    ])) {
+        let d = parse_duration(&format!("{}{}", n, unit)).unwrap();
+        let s = format_duration(d).to_string();
+        prop_assert_eq!(parse_duration(&s).unwrap(), d);
+    }
+}
+
 /* duration ordering: if a > b numerically, then parse(a) >= parse(b) */
 proptest! {
     #![proptest_config(ProptestConfig::with_cases(500))]
@@ -170,37 +195,37 @@ proptest! {
 
     #[test]
     fn signal_valid_numbers_parse(num in prop::sample::select(vec![
-        /* only signals defined in the Signal enum (darwin/POSIX subset) */
-        1,  /* SIGHUP */
-        2,  /* SIGINT */
-        3,  /* SIGQUIT */
-        4,  /* SIGILL */
-        5,  /* SIGTRAP */
-        6,  /* SIGABRT */
-        8,  /* SIGFPE */
-        9,  /* SIGKILL */
-        10, /* SIGBUS */
-        11, /* SIGSEGV */
-        12, /* SIGSYS */
-        13, /* SIGPIPE */
-        14, /* SIGALRM */
-        15, /* SIGTERM */
-        /* skipping 16: SIGURG defined in enum but let's check */
-        16, /* SIGURG */
-        17, /* SIGSTOP */
-        18, /* SIGTSTP */
-        19, /* SIGCONT */
-        20, /* SIGCHLD */
-        21, /* SIGTTIN */
-        22, /* SIGTTOU */
-        23, /* SIGIO */
-        24, /* SIGXCPU */
-        25, /* SIGXFSZ */
-        26, /* SIGVTALRM */
-        27, /* SIGPROF */
-        28, /* SIGWINCH */
-        30, /* SIGUSR1 */
-        31, /* SIGUSR2 */
+        /* only signals defined in the Signal enum (darwin/POSIX subset),
+         * by the platform's numbers */
+        libc::SIGHUP,
+        libc::SIGINT,
+        libc::SIGQUIT,
+        libc::SIGILL,
+        libc::SIGTRAP,
+        libc::SIGABRT,
+        libc::SIGFPE,
+        libc::SIGKILL,
+        libc::SIGBUS,
+        libc::SIGSEGV,
+        libc::SIGSYS,
+        libc::SIGPIPE,
+        libc::SIGALRM,
+        libc::SIGTERM,
+        libc::SIGURG,
+        libc::SIGSTOP,
+        libc::SIGTSTP,
+        libc::SIGCONT,
+        libc::SIGCHLD,
+        libc::SIGTTIN,
+        libc::SIGTTOU,
+        libc::SIGIO,
+        libc::SIGXCPU,
+        libc::SIGXFSZ,
+        libc::SIGVTALRM,
+        libc::SIGPROF,
+        libc::SIGWINCH,
+        libc::SIGUSR1,
+        libc::SIGUSR2,
     ])) {
         let sig = parse_signal(&num.to_string()).expect("valid signal number");
         prop_assert_eq!(sig.as_raw(), num);
@@ -333,6 +358,13 @@ proptest! {
         let m = parse_mem_limit(&s).expect("gigabytes should parse");
         prop_assert_eq!(m, gb * 1024 * 1024 * 1024);
     }
+
+    #[test]
+    fn mem_limit_kib_units_parse(val in 0u64..1_000_000) {
+        prop_assert_eq!(parse_mem_limit(&format!("{}KiB", val)).unwrap(), val * 1024);
+        prop_assert_eq!(parse_mem_limit(&format!("{}MiB", val)).unwrap(), val * 1024 * 1024);
+        prop_assert_eq!(parse_mem_limit(&format!("{}KB", val)).unwrap(), val * 1024);
+    }
 }
 
 /* case insensitivity for memory suffixes */
@@ -342,7 +374,7 @@ proptest! {
     #[test]
     fn mem_limit_case_insensitive(
         val in 1u64..1000,
-        suffix in prop::sample::select(vec!["k", "K", "kb", "KB", "Kb", "m", "M", "mb", "MB", "g", "G", "gb", "GB"])
+        suffix in prop::sample::select(vec!["k", "K", "kb", "KB", "Kb", "KiB", "kib", "m", "M", "mb", "MB", "MiB", "g", "G", "gb", "GB", "GiB"])
     ) {
         let s = format!("{}{}", val, suffix);
         prop_assert!(parse_mem_limit(&s).is_ok());
@@ -420,3 +452,125 @@ proptest! {
         prop_assert!(parse_mem_limit(&s).is_err());
     }
 }
+
+/* ============================================================================
+ * CPU Percent Parsing Properties
+ * ============================================================================ */
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(500))]
+
+    /* any positive u32 parses to itself, surrounding whitespace ignored */
+    #[test]
+    fn cpu_percent_positive_parses(pct in 1u32.., spaces in 0usize..3) {
+        let pad = " ".repeat(spaces);
+        let input = format!("{pad}{pct}{pad}");
+        prop_assert_eq!(parse_cpu_percent(&input).unwrap().get(), pct);
+    }
+
+    /* past u32 is an error, not a wrap */
+    #[test]
+    fn cpu_percent_overflow_errors(pct in (u64::from(u32::MAX) + 1)..u64::MAX) {
+        prop_assert!(parse_cpu_percent(&pct.to_string()).is_err());
+    }
+
+    #[test]
+    fn cpu_percent_suffix_errors(pct in 1u32..1000, suffix in "[%a-zA-Z.]{1,3}") {
+        let input = format!("{pct}{suffix}");
+        prop_assert!(parse_cpu_percent(&input).is_err());
+    }
+}
+
+#[test]
+fn cpu_percent_zero_and_negative_error() {
+    assert!(parse_cpu_percent("0").is_err());
+    assert!(parse_cpu_percent("-0").is_err());
+    assert!(parse_cpu_percent("-50").is_err());
+    assert!(parse_cpu_percent("").is_err());
+}
+
+/* ============================================================================
+ * Robustness: arbitrary input returns Ok or Err, never panics
+ *
+ * same contract as the cargo-fuzz targets in fuzz/, run on every cargo test
+ * ============================================================================ */
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(2000))]
+
+    #[test]
+    fn parsers_never_panic(s in any::<String>()) {
+        let _ = parse_duration(&s);
+        let _ = parse_signal(&s);
+        let _ = parse_mem_limit(&s);
+        let _ = parse_cpu_percent(&s);
+    }
+
+    /* near-miss inputs: digits, dots, signs and suffix letters mixed up */
+    #[test]
+    fn parsers_never_panic_numeric_like(s in "[-+ .0-9a-zA-Zµ]{0,24}") {
+        let _ = parse_duration(&s);
+        let _ = parse_signal(&s);
+        let _ = parse_mem_limit(&s);
+        let _ = parse_cpu_percent(&s);
+    }
+
+    /* a successful parse never rounds garbage away: what's left after the
+     * number must be a known suffix */
+    #[test]
+    fn duration_ok_means_known_suffix(s in "[0-9.]{1,12}[a-zµ]{0,2}") {
+        if parse_duration(&s).is_ok() {
+            let suffix = s.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+            prop_assert!(matches!(suffix, "" | "s" | "ms" | "us" | "µs" | "m" | "h" | "d"));
+        }
+    }
+}
+
+/* ============================================================================
+ * Argument Parsing Properties
+ * ============================================================================ */
+
+/* flags that print and exit(0) - the fuzz target skips them too */
+fn exits_early(arg: &str) -> bool {
+    matches!(arg, "-h" | "--help" | "-V" | "--version")
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(1000))]
+
+    #[test]
+    fn args_never_panic(args in prop::collection::vec(any::<String>(), 0..8)) {
+        prop_assume!(!args.iter().any(|a| exits_early(a)));
+        let _ = parse_from_slice(&args);
+    }
+
+    /* option-shaped noise: clusters, =values, missing values */
+    #[test]
+    fn args_never_panic_option_like(
+        args in prop::collection::vec("-{1,2}[a-zA-Z-]{0,12}(=[a-z0-9]{0,4})?|[0-9]{1,3}[smhd]?", 0..8)
+    ) {
+        prop_assume!(!args.iter().any(|a| exits_early(a)));
+        let _ = parse_from_slice(&args);
+    }
+
+    /* GNU form: timeout DURATION COMMAND [ARG]... */
+    #[test]
+    fn args_gnu_positional_form(
+        secs in 0u64..100_000,
+        cmd in "[a-z][a-z0-9_]{0,8}",
+        rest in prop::collection::vec("[a-zA-Z0-9_./-]{1,8}", 0..4),
+    ) {
+        /* a "--" after the command is consumed as the separator, by design */
+        prop_assume!(!rest.iter().any(|a| a == "--"));
+        let secs = secs.to_string();
+        let mut args = vec![secs.clone(), cmd.clone()];
+        args.extend(rest.iter().cloned());
+        let parsed = parse_from_slice(&args).unwrap();
+        prop_assert_eq!(parsed.duration.as_ref().map(|d| d.as_str()), Some(secs.as_str()));
+        prop_assert_eq!(parsed.command.as_ref().map(|c| c.as_str()), Some(cmd.as_str()));
+        /* everything after the command belongs to it, options included */
+        let got: Vec<&str> = parsed.args.iter().map(|a| a.as_str()).collect();
+        let want: Vec<&str> = rest.iter().map(String::as_str).collect();
+        prop_assert_eq!(got, want);
+    }
+}
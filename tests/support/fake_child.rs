@@ -0,0 +1,207 @@
+/*
+ * fake_child.rs
+ *
+ * procguard-fake-child: the command the integration tests run, so what
+ * they supervise does exactly what the test says and nothing else. sh -c
+ * snippets made every test depend on the shell, on sleep(1), perl and
+ * python being there, and on how fast sh forks.
+ *
+ * arguments are steps, run in order:
+ *
+ *   ready          print "ready" on stdout (flushed), for tests that wait
+ *                  for the child instead of sleeping
+ *   ignore SIG     ignore SIG (TERM, INT, HUP, ...) from here on
+ *   sleep SECS     sleep (fractions ok)
+ *   spin SECS      burn CPU for SECS
+ *   alloc MB       allocate MB megabytes and touch every page
+ *   print N TEXT   print TEXT on its own line N times
+ *   flood BYTES    write BYTES 'x's to stdout, then a newline
+ *   stderr TEXT    print TEXT on stderr
+ *   drain          read stdin to EOF; print how many bytes came
+ *   daemon SECS    fork a child into a session of its own, stdio on
+ *                  /dev/null, that sleeps SECS; print its pid
+ *   stop           SIGSTOP ourselves
+ *   crash SIG      die of SIG (no core dump)
+ *   exit CODE      exit with CODE
+ *
+ * with no exit step the exit status is 0. a bad step exits 99, which no
+ * test expects.
+ *
+ * not part of the tool: it's a [[bin]] only because cargo builds bins
+ * for integration tests and gives them CARGO_BIN_EXE_<name>.
+ */
+
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+fn usage(msg: &str) -> ! {
+    eprintln!("procguard-fake-child: {msg}");
+    std::process::exit(99);
+}
+
+fn signal(name: &str) -> i32 {
+    let name = name.trim_start_matches("SIG");
+    match name {
+        "HUP" => libc::SIGHUP,
+        "INT" => libc::SIGINT,
+        "QUIT" => libc::SIGQUIT,
+        "ABRT" => libc::SIGABRT,
+        "KILL" => libc::SIGKILL,
+        "SEGV" => libc::SIGSEGV,
+        "BUS" => libc::SIGBUS,
+        "PIPE" => libc::SIGPIPE,
+        "ALRM" => libc::SIGALRM,
+        "TERM" => libc::SIGTERM,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        _ => name
+            .parse()
+            .unwrap_or_else(|_| usage(&format!("unknown signal '{name}'"))),
+    }
+}
+
+fn secs(s: &str) -> Duration {
+    s.parse::<f64>()
+        .ok()
+        .and_then(|s| Duration::try_from_secs_f64(s).ok())
+        .unwrap_or_else(|| usage(&format!("bad seconds '{s}'")))
+}
+
+fn number(s: &str) -> u64 {
+    s.parse()
+        .unwrap_or_else(|_| usage(&format!("bad number '{s}'")))
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut out = std::io::stdout().lock();
+    /* kept alive so `alloc` stays resident */
+    let mut held: Vec<Vec<u8>> = Vec::new();
+
+    while let Some(step) = args.next() {
+        let step = step.as_str();
+        /* the step's next argument */
+        let mut next = |step: &str| {
+            args.next()
+                .unwrap_or_else(|| usage(&format!("{step} needs an argument")))
+        };
+        match step {
+            "ready" => {
+                let _ = writeln!(out, "ready");
+                let _ = out.flush();
+            }
+            "ignore" => {
+                let sig = signal(&next(step));
+                // SAFETY: SIG_IGN for a catchable signal, no handler to run
+                unsafe { libc::signal(sig, libc::SIG_IGN) };
+            }
+            "sleep" => std::thread::sleep(secs(&next(step))),
+            "spin" => {
+                let until = Instant::now() + secs(&next(step));
+                let mut n = 0u64;
+                while Instant::now() < until {
+                    n = std::hint::black_box(n.wrapping_add(1));
+                }
+            }
+            "alloc" => {
+                let mb = number(&next(step));
+                let mut block = vec![0u8; (mb as usize) << 20];
+                /* touch every page, or it may never be resident */
+                for page in block.chunks_mut(4096) {
+                    page[0] = 1;
+                }
+                held.push(std::hint::black_box(block));
+            }
+            "print" => {
+                let n = number(&next(step));
+                let text = next(step);
+                for _ in 0..n {
+                    let _ = writeln!(out, "{text}");
+                }
+                let _ = out.flush();
+            }
+            "flood" => {
+                let mut left = number(&next(step)) as usize;
+                let chunk = [b'x'; 8192];
+                while left > 0 {
+                    let n = left.min(chunk.len());
+                    if out.write_all(&chunk[..n]).is_err() {
+                        break;
+                    }
+                    left -= n;
+                }
+                let _ = writeln!(out);
+                let _ = out.flush();
+            }
+            "stderr" => eprintln!("{}", next(step)),
+            "drain" => {
+                let mut buf = Vec::new();
+                let n = std::io::stdin().read_to_end(&mut buf).unwrap_or(0);
+                let _ = writeln!(out, "{n}");
+                let _ = out.flush();
+            }
+            "daemon" => {
+                let nap = secs(&next(step));
+                let _ = out.flush();
+                // SAFETY: single-threaded here; the child only detaches,
+                // sleeps and _exits
+                match unsafe { libc::fork() } {
+                    0 => {
+                        /* a daemon doesn't hold on to the pipes it came with */
+                        let null = std::fs::File::options()
+                            .read(true)
+                            .write(true)
+                            .open("/dev/null")
+                            .map(std::os::fd::IntoRawFd::into_raw_fd)
+                            .unwrap_or(-1);
+                        // SAFETY: a fresh child, not a group leader; dup2
+                        // onto stdio is fine with any null fd, even -1
+                        #[allow(clippy::multiple_unsafe_ops_per_block)]
+                        unsafe {
+                            libc::setsid();
+                            for fd in 0..3 {
+                                libc::dup2(null, fd);
+                            }
+                        }
+                        std::thread::sleep(nap);
+                        // SAFETY: _exit skips the parent's atexit handlers
+                        unsafe { libc::_exit(0) };
+                    }
+                    -1 => usage("fork failed"),
+                    pid => {
+                        let _ = writeln!(out, "{pid}");
+                        let _ = out.flush();
+                    }
+                }
+            }
+            "stop" => {
+                let _ = out.flush();
+                // SAFETY: raise has no preconditions
+                unsafe { libc::raise(libc::SIGSTOP) };
+            }
+            "crash" => {
+                let sig = signal(&next(step));
+                let _ = out.flush();
+                let none = libc::rlimit {
+                    rlim_cur: 0,
+                    rlim_max: 0,
+                };
+                // SAFETY: none outlives the call; SIG_DFL and raise take no
+                // pointers. grouped: one way out
+                #[allow(clippy::multiple_unsafe_ops_per_block)]
+                unsafe {
+                    libc::setrlimit(libc::RLIMIT_CORE, &raw const none);
+                    libc::signal(sig, libc::SIG_DFL);
+                    libc::raise(sig);
+                }
+                usage("still alive after crash");
+            }
+            "exit" => {
+                let code = number(&next(step));
+                let _ = out.flush();
+                std::process::exit(code as i32);
+            }
+            _ => usage(&format!("unknown step '{step}'")),
+        }
+    }
+}